@@ -52,6 +52,7 @@ pub mod either;
 pub mod muxing;
 pub mod peer_record;
 pub mod signed_envelope;
+pub mod subscribers;
 pub mod transport;
 pub mod upgrade;
 
@@ -61,6 +62,7 @@ pub use multihash;
 pub use muxing::StreamMuxer;
 pub use peer_record::PeerRecord;
 pub use signed_envelope::SignedEnvelope;
+pub use subscribers::Subscribers;
 pub use translation::address_translation;
 pub use transport::Transport;
 pub use upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
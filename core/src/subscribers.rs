@@ -0,0 +1,123 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use futures::channel::mpsc;
+use parking_lot::Mutex;
+use std::{fmt, sync::Arc};
+
+/// The number of events buffered for each subscriber.
+const BUFFER_SIZE: usize = 32;
+
+/// The subscribers to the events of a transport, upgrade or muxer, shared by all its clones.
+///
+/// Each subscriber receives the events through a bounded channel of its own. Events are dropped
+/// for subscribers not keeping up, and subscribers are forgotten once their receiver is dropped,
+/// so emitting never blocks.
+pub struct Subscribers<T> {
+    senders: Arc<Mutex<Vec<mpsc::Sender<T>>>>,
+}
+
+impl<T: Clone> Subscribers<T> {
+    /// Returns a receiver of all events emitted from now on.
+    pub fn subscribe(&self) -> mpsc::Receiver<T> {
+        let (tx, rx) = mpsc::channel(BUFFER_SIZE);
+        self.senders.lock().push(tx);
+        rx
+    }
+
+    /// Sends `event` to all subscribers.
+    pub fn emit(&self, event: T) {
+        self.senders
+            .lock()
+            .retain_mut(|tx| match tx.try_send(event.clone()) {
+                Ok(()) => true,
+                Err(e) => !e.is_disconnected(),
+            });
+    }
+}
+
+impl<T> Default for Subscribers<T> {
+    fn default() -> Self {
+        Self {
+            senders: Default::default(),
+        }
+    }
+}
+
+impl<T> Clone for Subscribers<T> {
+    fn clone(&self) -> Self {
+        Self {
+            senders: self.senders.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Subscribers<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Subscribers")
+            .field("len", &self.senders.lock().len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[test]
+    fn emits_to_all_subscribers() {
+        let subscribers = Subscribers::default();
+        let mut first = subscribers.subscribe();
+        let mut second = subscribers.clone().subscribe();
+
+        subscribers.emit(1);
+
+        assert_eq!(first.try_next().unwrap(), Some(1));
+        assert_eq!(second.try_next().unwrap(), Some(1));
+    }
+
+    #[test]
+    fn drops_events_for_slow_subscribers() {
+        let subscribers = Subscribers::default();
+        let slow = subscribers.subscribe();
+
+        for i in 0..2 * BUFFER_SIZE {
+            subscribers.emit(i);
+        }
+
+        let received = futures::executor::block_on(async {
+            drop(subscribers);
+            slow.collect::<Vec<_>>().await
+        });
+        // Each sender has a guaranteed slot in addition to the buffer.
+        assert_eq!(received, (0..BUFFER_SIZE + 1).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn forgets_dropped_subscribers() {
+        let subscribers = Subscribers::default();
+        drop(subscribers.subscribe());
+
+        subscribers.emit(());
+
+        assert_eq!(subscribers.senders.lock().len(), 0);
+    }
+}
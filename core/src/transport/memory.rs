@@ -24,18 +24,23 @@ use futures::{
     channel::mpsc,
     future::{self, Ready},
     prelude::*,
+    ready,
     task::Context,
     task::Poll,
 };
+use futures_timer::Delay;
+use instant::Instant;
 use multiaddr::{Multiaddr, Protocol};
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use rw_stream_sink::RwStreamSink;
 use std::{
     collections::{hash_map::Entry, VecDeque},
     error, fmt, io,
     num::NonZeroU64,
     pin::Pin,
+    time::Duration,
 };
 
 static HUB: Lazy<Hub> = Lazy::new(|| Hub(Mutex::new(FnvHashMap::default())));
@@ -94,12 +99,153 @@ impl Hub {
 #[derive(Default)]
 pub struct MemoryTransport {
     listeners: VecDeque<Pin<Box<Listener>>>,
+    /// The simulated network conditions of dialed connections.
+    link_conditions: Option<LinkConditions>,
+    /// The simulated network conditions of connections dialed to specific ports.
+    port_link_conditions: FnvHashMap<u64, LinkConditions>,
+    /// The number of dials with simulated network conditions, to seed their randomness.
+    simulated_dials: u64,
 }
 
 impl MemoryTransport {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Simulates the given network conditions on all connections dialed by this transport,
+    /// in both directions.
+    pub fn with_link_conditions(mut self, conditions: LinkConditions) -> Self {
+        self.link_conditions = Some(conditions);
+        self
+    }
+
+    /// Simulates the given network conditions on connections dialed by this transport to
+    /// `/memory/<port>`, in both directions, instead of those of
+    /// [`MemoryTransport::with_link_conditions`].
+    pub fn with_link_conditions_to(mut self, port: u64, conditions: LinkConditions) -> Self {
+        self.port_link_conditions.insert(port, conditions);
+        self
+    }
+}
+
+/// Simulated network conditions of the connections of a [`MemoryTransport`].
+///
+/// All randomness is derived from a seed, so that given the same order of dials and
+/// messages, the simulation behaves the same in every run.
+#[derive(Debug, Clone, Default)]
+pub struct LinkConditions {
+    latency: Duration,
+    jitter: Duration,
+    bandwidth: Option<NonZeroU64>,
+    drop_probability: f64,
+    reset_probability: f64,
+    seed: u64,
+}
+
+impl LinkConditions {
+    /// Sets the time it takes for a message to reach the other side.
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Sets the maximum random delay added to the latency of each message.
+    ///
+    /// Messages are delivered in order regardless.
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets the number of bytes per second that can be sent in each direction.
+    pub fn with_bandwidth(mut self, bytes_per_second: NonZeroU64) -> Self {
+        self.bandwidth = Some(bytes_per_second);
+        self
+    }
+
+    /// Sets the probability that a dial is lost, i.e. never completes.
+    ///
+    /// # Panics
+    ///
+    /// If `probability` is not within `0.0..=1.0`.
+    pub fn with_drop_probability(mut self, probability: f64) -> Self {
+        assert!((0.0..=1.0).contains(&probability));
+        self.drop_probability = probability;
+        self
+    }
+
+    /// Sets the probability that sending a message resets the connection instead.
+    ///
+    /// After a reset, reading from and writing to the connection fail with
+    /// [`io::ErrorKind::ConnectionReset`] and the remote sees the connection closed.
+    ///
+    /// # Panics
+    ///
+    /// If `probability` is not within `0.0..=1.0`.
+    pub fn with_reset_probability(mut self, probability: f64) -> Self {
+        assert!((0.0..=1.0).contains(&probability));
+        self.reset_probability = probability;
+        self
+    }
+
+    /// Sets the seed of the randomness of jitter, dropped dials and resets.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+/// The simulated network conditions of one direction of a connection carrying messages of
+/// type `T`.
+struct Link<T> {
+    conditions: LinkConditions,
+    /// Returns the number of bytes of a message, to account for the bandwidth.
+    size: fn(&T) -> usize,
+    rng: StdRng,
+    /// Until when previously sent messages occupy the bandwidth.
+    busy_until: Instant,
+    /// When the last sent message is delivered.
+    last_delivery: Instant,
+}
+
+impl<T> Link<T> {
+    fn new(conditions: LinkConditions, size: fn(&T) -> usize, seed: u64) -> Self {
+        let now = Instant::now();
+        Link {
+            conditions,
+            size,
+            rng: StdRng::seed_from_u64(seed),
+            busy_until: now,
+            last_delivery: now,
+        }
+    }
+
+    /// Returns when the given message sent now is delivered.
+    fn delivery(&mut self, item: &T) -> Instant {
+        let now = Instant::now();
+        if let Some(bandwidth) = self.conditions.bandwidth {
+            let len = (self.size)(item);
+            let transmission = Duration::from_secs_f64(len as f64 / bandwidth.get() as f64);
+            self.busy_until = self.busy_until.max(now) + transmission;
+        } else {
+            self.busy_until = now;
+        }
+        let jitter = if self.conditions.jitter.is_zero() {
+            Duration::ZERO
+        } else {
+            self.rng.gen_range(Duration::ZERO..=self.conditions.jitter)
+        };
+        let delivery = self.busy_until + self.conditions.latency + jitter;
+        // Messages are not reordered.
+        self.last_delivery = self.last_delivery.max(delivery);
+        self.last_delivery
+    }
+
+    /// Decides whether sending a message resets the connection.
+    fn reset(&mut self) -> bool {
+        self.conditions.reset_probability > 0.0
+            && self.rng.gen_bool(self.conditions.reset_probability)
+    }
 }
 
 /// Connection to a `MemoryTransport` currently being opened.
@@ -114,31 +260,50 @@ pub struct DialFuture {
     sender: ChannelSender,
     channel_to_send: Option<Channel<Vec<u8>>>,
     channel_to_return: Option<Channel<Vec<u8>>>,
+    /// Whether the dial is lost due to simulated network conditions.
+    dropped: bool,
 }
 
 impl DialFuture {
-    fn new(port: NonZeroU64) -> Option<Self> {
+    fn new(port: NonZeroU64, link: Option<(LinkConditions, u64)>) -> Option<Self> {
         let sender = HUB.get(&port)?;
 
         let (_dial_port_channel, dial_port) = HUB
             .register_port(0)
             .expect("there to be some random unoccupied port.");
 
+        let mut dropped = false;
+        let (listener_link, dialer_link) = match link {
+            Some((conditions, seed)) => {
+                let mut rng = StdRng::seed_from_u64(seed);
+                dropped =
+                    conditions.drop_probability > 0.0 && rng.gen_bool(conditions.drop_probability);
+                (
+                    Some(Link::new(conditions.clone(), Vec::len, rng.gen())),
+                    Some(Link::new(conditions, Vec::len, rng.gen())),
+                )
+            }
+            None => (None, None),
+        };
+
         let (a_tx, a_rx) = mpsc::channel(4096);
         let (b_tx, b_rx) = mpsc::channel(4096);
         Some(DialFuture {
             dial_port,
             sender,
-            channel_to_send: Some(RwStreamSink::new(Chan {
-                incoming: a_rx,
-                outgoing: b_tx,
-                dial_port: None,
-            })),
-            channel_to_return: Some(RwStreamSink::new(Chan {
-                incoming: b_rx,
-                outgoing: a_tx,
-                dial_port: Some(dial_port),
-            })),
+            channel_to_send: Some(RwStreamSink::new(Chan::new(
+                a_rx,
+                b_tx,
+                None,
+                listener_link,
+            ))),
+            channel_to_return: Some(RwStreamSink::new(Chan::new(
+                b_rx,
+                a_tx,
+                Some(dial_port),
+                dialer_link,
+            ))),
+            dropped,
         })
     }
 }
@@ -147,6 +312,10 @@ impl Future for DialFuture {
     type Output = Result<Channel<Vec<u8>>, MemoryTransportError>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.dropped {
+            return Poll::Pending;
+        }
+
         match self.sender.poll_ready(cx) {
             Poll::Pending => return Poll::Pending,
             Poll::Ready(Ok(())) => {}
@@ -230,7 +399,18 @@ impl Transport for MemoryTransport {
             return Err(TransportError::MultiaddrNotSupported(addr));
         };
 
-        DialFuture::new(port).ok_or(TransportError::Other(MemoryTransportError::Unreachable))
+        let link = self
+            .port_link_conditions
+            .get(&port.get())
+            .or(self.link_conditions.as_ref())
+            .cloned()
+            .map(|conditions| {
+                let seed = conditions.seed.wrapping_add(self.simulated_dials);
+                self.simulated_dials += 1;
+                (conditions, seed)
+            });
+
+        DialFuture::new(port, link).ok_or(TransportError::Other(MemoryTransportError::Unreachable))
     }
 
     fn dial_as_listener(
@@ -349,8 +529,8 @@ pub type Channel<T> = RwStreamSink<Chan<T>>;
 ///
 /// Implements `Sink` and `Stream`.
 pub struct Chan<T = Vec<u8>> {
-    incoming: mpsc::Receiver<T>,
-    outgoing: mpsc::Sender<T>,
+    incoming: mpsc::Receiver<Packet<T>>,
+    outgoing: mpsc::Sender<Packet<T>>,
 
     // Needed in [`Drop`] implementation of [`Chan`] to unregister the dialing
     // port with the global [`HUB`]. Is [`Some`] when [`Chan`] of dialer and
@@ -359,6 +539,38 @@ pub struct Chan<T = Vec<u8>> {
     // Note: Listening port is unregistered in [`Drop`] implementation of
     // [`Listener`].
     dial_port: Option<NonZeroU64>,
+
+    /// The simulated network conditions of outgoing messages, if any.
+    link: Option<Link<T>>,
+    /// A received message and the delay until its simulated delivery.
+    delayed: Option<(T, Delay)>,
+    /// Whether the connection was reset due to simulated network conditions.
+    reset: bool,
+}
+
+/// A message sent over a [`Chan`].
+struct Packet<T> {
+    item: T,
+    /// When the message is delivered, if it is subject to simulated network conditions.
+    delivery: Option<Instant>,
+}
+
+impl<T> Chan<T> {
+    fn new(
+        incoming: mpsc::Receiver<Packet<T>>,
+        outgoing: mpsc::Sender<Packet<T>>,
+        dial_port: Option<NonZeroU64>,
+        link: Option<Link<T>>,
+    ) -> Self {
+        Chan {
+            incoming,
+            outgoing,
+            dial_port,
+            link,
+            delayed: None,
+            reset: false,
+        }
+    }
 }
 
 impl<T> Unpin for Chan<T> {}
@@ -367,10 +579,31 @@ impl<T> Stream for Chan<T> {
     type Item = Result<T, io::Error>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        match Stream::poll_next(Pin::new(&mut self.incoming), cx) {
-            Poll::Pending => Poll::Pending,
-            Poll::Ready(None) => Poll::Ready(None),
-            Poll::Ready(Some(v)) => Poll::Ready(Some(Ok(v))),
+        if self.reset {
+            return Poll::Ready(Some(Err(io::ErrorKind::ConnectionReset.into())));
+        }
+
+        loop {
+            if let Some((_, delay)) = self.delayed.as_mut() {
+                ready!(delay.poll_unpin(cx));
+                let (item, _) = self.delayed.take().expect("to be some");
+                return Poll::Ready(Some(Ok(item)));
+            }
+
+            match ready!(Stream::poll_next(Pin::new(&mut self.incoming), cx)) {
+                None => return Poll::Ready(None),
+                Some(Packet {
+                    item,
+                    delivery: None,
+                }) => return Poll::Ready(Some(Ok(item))),
+                Some(Packet {
+                    item,
+                    delivery: Some(delivery),
+                }) => {
+                    let delay = delivery.saturating_duration_since(Instant::now());
+                    self.delayed = Some((item, Delay::new(delay)));
+                }
+            }
         }
     }
 }
@@ -379,14 +612,31 @@ impl<T> Sink<T> for Chan<T> {
     type Error = io::Error;
 
     fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.reset {
+            return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()));
+        }
+
         self.outgoing
             .poll_ready(cx)
             .map(|v| v.map_err(|_| io::ErrorKind::BrokenPipe.into()))
     }
 
     fn start_send(mut self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        if self.reset {
+            return Err(io::ErrorKind::ConnectionReset.into());
+        }
+
+        let reset = self.link.as_mut().map_or(false, Link::reset);
+        if reset {
+            self.reset = true;
+            self.outgoing.close_channel();
+            self.incoming.close();
+            return Err(io::ErrorKind::ConnectionReset.into());
+        }
+        let delivery = self.link.as_mut().map(|link| link.delivery(&item));
+
         self.outgoing
-            .start_send(item)
+            .start_send(Packet { item, delivery })
             .map_err(|_| io::ErrorKind::BrokenPipe.into())
     }
 
@@ -675,4 +925,91 @@ mod tests {
 
         futures::executor::block_on(futures::future::join(listener, dialer));
     }
+
+    #[test]
+    fn simulated_latency_delays_messages() {
+        let latency = Duration::from_millis(50);
+        let listener_addr: Multiaddr =
+            Protocol::Memory(rand::random::<u64>().saturating_add(1)).into();
+        let mut listener_transport = MemoryTransport::default().boxed();
+        listener_transport
+            .listen_on(ListenerId::next(), listener_addr.clone())
+            .unwrap();
+
+        let listener = async move {
+            let upgrade = loop {
+                if let Some(upgrade) = listener_transport.select_next_some().await.into_incoming() {
+                    break upgrade;
+                }
+            };
+            let mut socket = upgrade.0.await.unwrap();
+            let mut buf = [0; 3];
+            socket.read_exact(&mut buf).await.unwrap();
+            socket.write_all(&buf).await.unwrap();
+            // Keep the connection open until the echo was read.
+            socket.read_exact(&mut [0]).await.unwrap_err();
+        };
+
+        let mut dialer_transport = MemoryTransport::default()
+            .with_link_conditions(LinkConditions::default().with_latency(latency));
+        let dialer = async move {
+            let mut socket = dialer_transport.dial(listener_addr).unwrap().await.unwrap();
+            let start = Instant::now();
+            socket.write_all(&[1, 2, 3]).await.unwrap();
+            let mut buf = [0; 3];
+            socket.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, [1, 2, 3]);
+            assert!(start.elapsed() >= 2 * latency);
+        };
+
+        futures::executor::block_on(futures::future::join(listener, dialer));
+    }
+
+    #[test]
+    fn simulated_drops_and_resets_are_deterministic() {
+        let port = rand::random::<u64>().saturating_add(1);
+        let listener_addr: Multiaddr = Protocol::Memory(port).into();
+        let mut listener_transport = MemoryTransport::default();
+        listener_transport
+            .listen_on(ListenerId::next(), listener_addr.clone())
+            .unwrap();
+
+        // The dial to `port` is lost, while the others use the default conditions.
+        let mut transport = MemoryTransport::default()
+            .with_link_conditions_to(port, LinkConditions::default().with_drop_probability(1.0));
+        assert!(transport
+            .dial(listener_addr.clone())
+            .unwrap()
+            .now_or_never()
+            .is_none());
+
+        let sends_until_reset = |seed| {
+            let mut transport = MemoryTransport::default().with_link_conditions(
+                LinkConditions::default()
+                    .with_reset_probability(0.1)
+                    .with_seed(seed),
+            );
+            futures::executor::block_on(async {
+                let mut socket = transport
+                    .dial(listener_addr.clone())
+                    .unwrap()
+                    .await
+                    .unwrap();
+                let mut sends = 0;
+                loop {
+                    match socket.write_all(&[0]).await {
+                        Ok(()) => sends += 1,
+                        Err(e) => {
+                            assert_eq!(e.kind(), io::ErrorKind::ConnectionReset);
+                            break sends;
+                        }
+                    }
+                }
+            })
+        };
+        let sends = (0..8).map(sends_until_reset).collect::<Vec<_>>();
+        assert_eq!(sends, (0..8).map(sends_until_reset).collect::<Vec<_>>());
+        // Different seeds lead to different outcomes.
+        assert!(sends.iter().any(|n| *n != sends[0]));
+    }
 }
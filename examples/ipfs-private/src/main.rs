@@ -141,6 +141,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
         ping: ping::Behaviour,
     }
 
+    #[allow(clippy::large_enum_variant)]
     enum MyBehaviourEvent {
         Gossipsub(gossipsub::Event),
         Identify(identify::Event),
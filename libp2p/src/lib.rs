@@ -98,6 +98,14 @@ pub use libp2p_plaintext as plaintext;
 #[cfg(feature = "pnet")]
 #[doc(inline)]
 pub use libp2p_pnet as pnet;
+#[cfg(feature = "proxy")]
+#[cfg_attr(docsrs, doc(cfg(feature = "proxy")))]
+#[cfg(not(target_arch = "wasm32"))]
+#[doc(inline)]
+pub use libp2p_proxy as proxy;
+#[cfg(feature = "qmux")]
+#[doc(inline)]
+pub use libp2p_qmux as qmux;
 #[cfg(feature = "quic")]
 #[cfg(not(target_arch = "wasm32"))]
 pub use libp2p_quic as quic;
@@ -134,6 +142,11 @@ pub use libp2p_wasm_ext as wasm_ext;
 #[cfg(not(target_arch = "wasm32"))]
 #[doc(inline)]
 pub use libp2p_websocket as websocket;
+#[cfg(feature = "webtransport")]
+#[cfg_attr(docsrs, doc(cfg(feature = "webtransport")))]
+#[cfg(not(target_arch = "wasm32"))]
+#[doc(inline)]
+pub use libp2p_webtransport as webtransport;
 #[cfg(feature = "webtransport-websys")]
 #[cfg_attr(docsrs, doc(cfg(feature = "webtransport-websys")))]
 #[doc(inline)]
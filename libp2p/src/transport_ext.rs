@@ -89,6 +89,56 @@ pub trait TransportExt: Transport {
         .boxed();
         (transport, sinks)
     }
+
+    /// Protects all connections of the `Transport` with a pre-shared key, such that only
+    /// nodes configured with the same key can communicate, see [`crate::pnet`].
+    ///
+    /// This is to be applied to the raw transport, before upgrading it with a security
+    /// protocol and a multiplexer.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use libp2p_yamux as yamux;
+    /// use libp2p_noise as noise;
+    /// use libp2p_tcp as tcp;
+    /// use libp2p::{
+    ///     core::upgrade,
+    ///     identity,
+    ///     pnet::PreSharedKey,
+    ///     TransportExt,
+    ///     Transport,
+    /// };
+    ///
+    /// let id_keys = identity::Keypair::generate_ed25519();
+    /// let psk = PreSharedKey::new([0; 32]);
+    ///
+    /// let transport = tcp::tokio::Transport::new(tcp::Config::default())
+    ///     .with_pnet(psk)
+    ///     .upgrade(upgrade::Version::V1)
+    ///     .authenticate(
+    ///         noise::Config::new(&id_keys)
+    ///             .expect("Signing libp2p-noise static DH keypair failed."),
+    ///     )
+    ///     .multiplex(yamux::Config::default())
+    ///     .boxed();
+    /// ```
+    #[cfg(feature = "pnet")]
+    fn with_pnet(
+        self,
+        psk: crate::pnet::PreSharedKey,
+    ) -> Boxed<crate::pnet::PnetOutput<Self::Output>>
+    where
+        Self: Sized + Send + Unpin + 'static,
+        Self::Dial: Send + 'static,
+        Self::ListenerUpgrade: Send + 'static,
+        Self::Error: Send + Sync,
+        Self::Output: futures::AsyncRead + futures::AsyncWrite + Send + Unpin,
+    {
+        let config = crate::pnet::PnetConfig::new(psk);
+        self.and_then(move |socket, _| config.handshake(socket))
+            .boxed()
+    }
 }
 
 impl<TTransport> TransportExt for TTransport where TTransport: Transport {}
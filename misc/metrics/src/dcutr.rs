@@ -18,6 +18,7 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
+use libp2p_swarm::StreamUpgradeError;
 use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
 use prometheus_client::metrics::counter::Counter;
 use prometheus_client::metrics::family::Family;
@@ -25,6 +26,8 @@ use prometheus_client::registry::Registry;
 
 pub(crate) struct Metrics {
     events: Family<EventLabels, Counter>,
+    upgrades_succeeded: Counter,
+    upgrades_failed: Family<FailureLabels, Counter>,
 }
 
 impl Metrics {
@@ -38,7 +41,55 @@ impl Metrics {
             events.clone(),
         );
 
-        Self { events }
+        let upgrades_succeeded = Counter::default();
+        sub_registry.register(
+            "upgrades_succeeded",
+            "Number of relayed connections successfully upgraded to a direct connection",
+            upgrades_succeeded.clone(),
+        );
+
+        let upgrades_failed = Family::default();
+        sub_registry.register(
+            "upgrades_failed",
+            "Number of failed direct connection upgrades, by reason",
+            upgrades_failed.clone(),
+        );
+
+        Self {
+            events,
+            upgrades_succeeded,
+            upgrades_failed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct FailureLabels {
+    reason: FailureReason,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelValue)]
+enum FailureReason {
+    /// All attempts to dial the remote directly failed.
+    Dial,
+    /// The remote did not respond in time.
+    Timeout,
+    /// The remote does not support the protocol.
+    Unsupported,
+    Io,
+}
+
+impl From<&libp2p_dcutr::Error> for FailureReason {
+    fn from(error: &libp2p_dcutr::Error) -> Self {
+        match error {
+            libp2p_dcutr::Error::Dial => FailureReason::Dial,
+            libp2p_dcutr::Error::Handler(StreamUpgradeError::Timeout) => FailureReason::Timeout,
+            libp2p_dcutr::Error::Handler(StreamUpgradeError::NegotiationFailed) => {
+                FailureReason::Unsupported
+            }
+            libp2p_dcutr::Error::Handler(StreamUpgradeError::Io(_)) => FailureReason::Io,
+            libp2p_dcutr::Error::Handler(StreamUpgradeError::Apply(never)) => match *never {},
+        }
     }
 }
 
@@ -53,6 +104,8 @@ enum EventType {
     RemoteInitiatedDirectConnectionUpgrade,
     DirectConnectionUpgradeSucceeded,
     DirectConnectionUpgradeFailed,
+    HolePunchAttempt,
+    FellBackToRelay,
 }
 
 impl From<&libp2p_dcutr::Event> for EventType {
@@ -73,6 +126,8 @@ impl From<&libp2p_dcutr::Event> for EventType {
                 remote_peer_id: _,
                 error: _,
             } => EventType::DirectConnectionUpgradeFailed,
+            libp2p_dcutr::Event::HolePunchAttempt { .. } => EventType::HolePunchAttempt,
+            libp2p_dcutr::Event::FellBackToRelay { .. } => EventType::FellBackToRelay,
         }
     }
 }
@@ -84,5 +139,19 @@ impl super::Recorder<libp2p_dcutr::Event> for Metrics {
                 event: event.into(),
             })
             .inc();
+
+        match event {
+            libp2p_dcutr::Event::DirectConnectionUpgradeSucceeded { .. } => {
+                self.upgrades_succeeded.inc();
+            }
+            libp2p_dcutr::Event::DirectConnectionUpgradeFailed { error, .. } => {
+                self.upgrades_failed
+                    .get_or_create(&FailureLabels {
+                        reason: error.into(),
+                    })
+                    .inc();
+            }
+            _ => {}
+        }
     }
 }
@@ -18,11 +18,19 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
+use instant::Instant;
 use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
 use prometheus_client::metrics::counter::Counter;
 use prometheus_client::metrics::family::Family;
-use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
+use prometheus_client::metrics::histogram::{exponential_buckets, linear_buckets, Histogram};
 use prometheus_client::registry::{Registry, Unit};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How long the number of results returned so far by a query is kept after its last progress step,
+/// in case the final step of the query is never recorded.
+const PENDING_RESULT_SIZE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
 
 pub(crate) struct Metrics {
     query_result_get_record_ok: Counter,
@@ -39,6 +47,13 @@ pub(crate) struct Metrics {
     query_result_num_failure: Family<QueryResult, Histogram>,
     query_result_duration: Family<QueryResult, Histogram>,
 
+    completed_query_duration: Family<QueryOutcome, Histogram>,
+    completed_query_hops: Family<QueryOutcome, Histogram>,
+    completed_query_result_size: Family<QueryOutcome, Histogram>,
+    /// Number of results returned so far by the queries still in progress, along with the time of
+    /// their last progress step.
+    pending_result_sizes: Mutex<HashMap<libp2p_kad::QueryId, (u64, Instant)>>,
+
     routing_updated: Family<RoutingUpdated, Counter>,
 
     inbound_requests: Family<InboundRequest, Counter>,
@@ -123,6 +138,31 @@ impl Metrics {
             query_result_duration.clone(),
         );
 
+        let completed_query_duration: Family<_, _> =
+            Family::new_with_constructor(|| Histogram::new(exponential_buckets(0.1, 2.0, 10)));
+        sub_registry.register_with_unit(
+            "completed_query_duration",
+            "Duration of a completed Kademlia query",
+            Unit::Seconds,
+            completed_query_duration.clone(),
+        );
+
+        let completed_query_hops: Family<_, _> =
+            Family::new_with_constructor(|| Histogram::new(linear_buckets(1.0, 1.0, 10)));
+        sub_registry.register(
+            "completed_query_hops",
+            "Number of hops of a completed Kademlia query",
+            completed_query_hops.clone(),
+        );
+
+        let completed_query_result_size: Family<_, _> =
+            Family::new_with_constructor(|| Histogram::new(exponential_buckets(1.0, 2.0, 10)));
+        sub_registry.register(
+            "completed_query_result_size",
+            "Number of peers, providers or records returned by a completed Kademlia query",
+            completed_query_result_size.clone(),
+        );
+
         let routing_updated = Family::default();
         sub_registry.register(
             "routing_updated",
@@ -152,6 +192,11 @@ impl Metrics {
             query_result_num_failure,
             query_result_duration,
 
+            completed_query_duration,
+            completed_query_hops,
+            completed_query_result_size,
+            pending_result_sizes: Default::default(),
+
             routing_updated,
 
             inbound_requests,
@@ -162,7 +207,12 @@ impl Metrics {
 impl super::Recorder<libp2p_kad::KademliaEvent> for Metrics {
     fn record(&self, event: &libp2p_kad::KademliaEvent) {
         match event {
-            libp2p_kad::KademliaEvent::OutboundQueryProgressed { result, stats, .. } => {
+            libp2p_kad::KademliaEvent::OutboundQueryProgressed {
+                id,
+                result,
+                stats,
+                step,
+            } => {
                 self.query_result_num_requests
                     .get_or_create(&result.into())
                     .observe(stats.num_requests().into());
@@ -178,6 +228,8 @@ impl super::Recorder<libp2p_kad::KademliaEvent> for Metrics {
                         .observe(duration.as_secs_f64());
                 }
 
+                self.record_query_progress(*id, result, stats, step);
+
                 match result {
                     libp2p_kad::QueryResult::GetRecord(result) => match result {
                         Ok(libp2p_kad::GetRecordOk::FoundRecord(_)) => {
@@ -258,6 +310,74 @@ impl super::Recorder<libp2p_kad::KademliaEvent> for Metrics {
     }
 }
 
+impl Metrics {
+    fn record_query_progress(
+        &self,
+        id: libp2p_kad::QueryId,
+        result: &libp2p_kad::QueryResult,
+        stats: &libp2p_kad::QueryStats,
+        step: &libp2p_kad::ProgressStep,
+    ) {
+        let mut pending_result_sizes = self.pending_result_sizes.lock().unwrap();
+        let result_size = result_size(result);
+
+        if !step.last {
+            let now = Instant::now();
+            pending_result_sizes.retain(|_, (_, updated)| {
+                now.duration_since(*updated) < PENDING_RESULT_SIZE_TIMEOUT
+            });
+            if let Some(size) = result_size {
+                let (pending_size, updated) = pending_result_sizes.entry(id).or_insert((0, now));
+                *pending_size += size;
+                *updated = now;
+            }
+            return;
+        }
+
+        let previous_size = pending_result_sizes.remove(&id).map(|(size, _)| size);
+        drop(pending_result_sizes);
+
+        let outcome = QueryOutcome::from(result);
+        if let Some(duration) = stats.duration() {
+            self.completed_query_duration
+                .get_or_create(&outcome)
+                .observe(duration.as_secs_f64());
+        }
+        self.completed_query_hops
+            .get_or_create(&outcome)
+            .observe(stats.num_hops().into());
+        if let Some(size) = result_size {
+            self.completed_query_result_size
+                .get_or_create(&outcome)
+                .observe((previous_size.unwrap_or_default() + size) as f64);
+        }
+    }
+}
+
+/// Returns the number of peers, providers or records returned with a progress step of a query,
+/// or `None` for queries not returning any of these.
+fn result_size(result: &libp2p_kad::QueryResult) -> Option<u64> {
+    let size = match result {
+        libp2p_kad::QueryResult::GetClosestPeers(Ok(libp2p_kad::GetClosestPeersOk {
+            peers,
+            ..
+        }))
+        | libp2p_kad::QueryResult::GetClosestPeers(Err(
+            libp2p_kad::GetClosestPeersError::Timeout { peers, .. },
+        )) => peers.len(),
+        libp2p_kad::QueryResult::GetProviders(Ok(libp2p_kad::GetProvidersOk::FoundProviders {
+            providers,
+            ..
+        })) => providers.len(),
+        libp2p_kad::QueryResult::GetProviders(_) => 0,
+        libp2p_kad::QueryResult::GetRecord(Ok(libp2p_kad::GetRecordOk::FoundRecord(_))) => 1,
+        libp2p_kad::QueryResult::GetRecord(_) => 0,
+        _ => return None,
+    };
+
+    Some(size as u64)
+}
+
 #[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
 struct QueryResult {
     r#type: QueryType,
@@ -306,6 +426,60 @@ impl From<&libp2p_kad::QueryResult> for QueryResult {
     }
 }
 
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+struct QueryOutcome {
+    r#type: QueryType,
+    outcome: Outcome,
+}
+
+#[derive(EncodeLabelValue, Hash, Clone, Eq, PartialEq, Debug)]
+enum Outcome {
+    Ok,
+    NotFound,
+    QuorumFailed,
+    Timeout,
+}
+
+impl From<&libp2p_kad::QueryResult> for QueryOutcome {
+    fn from(result: &libp2p_kad::QueryResult) -> Self {
+        let outcome = match result {
+            libp2p_kad::QueryResult::Bootstrap(Ok(_))
+            | libp2p_kad::QueryResult::GetClosestPeers(Ok(_))
+            | libp2p_kad::QueryResult::GetProviders(Ok(_))
+            | libp2p_kad::QueryResult::StartProviding(Ok(_))
+            | libp2p_kad::QueryResult::RepublishProvider(Ok(_))
+            | libp2p_kad::QueryResult::GetRecord(Ok(_))
+            | libp2p_kad::QueryResult::PutRecord(Ok(_))
+            | libp2p_kad::QueryResult::RepublishRecord(Ok(_)) => Outcome::Ok,
+            libp2p_kad::QueryResult::GetRecord(Err(libp2p_kad::GetRecordError::NotFound {
+                ..
+            })) => Outcome::NotFound,
+            libp2p_kad::QueryResult::GetRecord(Err(libp2p_kad::GetRecordError::QuorumFailed {
+                ..
+            }))
+            | libp2p_kad::QueryResult::PutRecord(Err(libp2p_kad::PutRecordError::QuorumFailed {
+                ..
+            }))
+            | libp2p_kad::QueryResult::RepublishRecord(Err(
+                libp2p_kad::PutRecordError::QuorumFailed { .. },
+            )) => Outcome::QuorumFailed,
+            libp2p_kad::QueryResult::Bootstrap(Err(_))
+            | libp2p_kad::QueryResult::GetClosestPeers(Err(_))
+            | libp2p_kad::QueryResult::GetProviders(Err(_))
+            | libp2p_kad::QueryResult::StartProviding(Err(_))
+            | libp2p_kad::QueryResult::RepublishProvider(Err(_))
+            | libp2p_kad::QueryResult::GetRecord(Err(_))
+            | libp2p_kad::QueryResult::PutRecord(Err(_))
+            | libp2p_kad::QueryResult::RepublishRecord(Err(_)) => Outcome::Timeout,
+        };
+
+        QueryOutcome {
+            r#type: QueryResult::from(result).r#type,
+            outcome,
+        }
+    }
+}
+
 #[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
 struct GetRecordResult {
     error: GetRecordError,
@@ -414,3 +588,63 @@ enum Request {
     GetRecord,
     PutRecord,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Recorder;
+    use libp2p_identity::PeerId;
+    use libp2p_kad::record::store::MemoryStore;
+    use prometheus_client::encoding::text::encode;
+    use std::num::NonZeroUsize;
+
+    #[test]
+    fn records_completed_queries() {
+        let mut registry = Registry::default();
+        let metrics = Metrics::new(&mut registry);
+        let local_peer_id = PeerId::random();
+        let mut kademlia =
+            libp2p_kad::Kademlia::new(local_peer_id, MemoryStore::new(local_peer_id));
+        let id = kademlia.get_closest_peers(PeerId::random());
+        let abandoned = kademlia.get_closest_peers(PeerId::random());
+
+        let progressed = |id, peers: usize, count: usize, last| {
+            libp2p_kad::KademliaEvent::OutboundQueryProgressed {
+                id,
+                result: libp2p_kad::QueryResult::GetClosestPeers(Ok(
+                    libp2p_kad::GetClosestPeersOk {
+                        key: Vec::new(),
+                        peers: (0..peers).map(|_| PeerId::random()).collect(),
+                    },
+                )),
+                stats: libp2p_kad::QueryStats::empty(),
+                step: libp2p_kad::ProgressStep {
+                    count: NonZeroUsize::new(count).unwrap(),
+                    last,
+                },
+            }
+        };
+
+        metrics.record(&progressed(abandoned, 1, 1, false));
+        metrics.record(&progressed(id, 2, 1, false));
+        metrics.record(&progressed(id, 3, 2, true));
+
+        let mut encoded = String::new();
+        encode(&mut encoded, &registry).unwrap();
+        assert!(encoded.contains(
+            "kad_completed_query_result_size_sum{type=\"GetClosestPeers\",outcome=\"Ok\"} 5.0"
+        ));
+        assert!(encoded
+            .contains("kad_completed_query_hops_count{type=\"GetClosestPeers\",outcome=\"Ok\"} 1"));
+
+        // The result sizes of queries whose last step is never recorded are eventually dropped.
+        let mut pending_result_sizes = metrics.pending_result_sizes.lock().unwrap();
+        assert_eq!(pending_result_sizes.len(), 1);
+        pending_result_sizes.get_mut(&abandoned).unwrap().1 =
+            Instant::now() - PENDING_RESULT_SIZE_TIMEOUT;
+        drop(pending_result_sizes);
+        metrics.record(&progressed(id, 1, 1, false));
+        let pending_result_sizes = metrics.pending_result_sizes.lock().unwrap();
+        assert!(!pending_result_sizes.contains_key(&abandoned));
+    }
+}
@@ -35,12 +35,16 @@ mod gossipsub;
 mod identify;
 #[cfg(feature = "kad")]
 mod kad;
+#[cfg(feature = "otlp")]
+pub mod otlp;
 #[cfg(feature = "ping")]
 mod ping;
 mod protocol_stack;
 #[cfg(feature = "relay")]
 mod relay;
 mod swarm;
+#[cfg(feature = "yamux")]
+mod yamux;
 
 use prometheus_client::registry::Registry;
 
@@ -90,6 +94,24 @@ impl Metrics {
     }
 }
 
+/// Registers metrics exposing the flow control statistics of yamux connections, i.e. of each
+/// connection created with the [`libp2p_yamux::Config`] the statistics were obtained from,
+/// labeled by the ID of the connection.
+///
+/// ```
+/// use prometheus_client::registry::Registry;
+/// let mut registry = Registry::default();
+/// let yamux_config = libp2p_yamux::Config::default();
+/// libp2p_metrics::register_yamux_stats(&mut registry, yamux_config.stats());
+/// ```
+#[cfg(feature = "yamux")]
+pub fn register_yamux_stats(registry: &mut Registry, stats: libp2p_yamux::Stats) {
+    registry
+        .sub_registry_with_prefix("libp2p")
+        .sub_registry_with_prefix("yamux")
+        .register_collector(Box::new(yamux::Stats(stats)));
+}
+
 /// Recorder that can record Swarm and protocol events.
 pub trait Recorder<Event> {
     /// Record the given event.
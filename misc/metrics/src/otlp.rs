@@ -0,0 +1,548 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Export of the metrics in a [`Registry`] via the [OpenTelemetry protocol (OTLP)][otlp].
+//!
+//! The [`Exporter`] pushes the current state of a registry as an OTLP/JSON
+//! `ExportMetricsServiceRequest` to the `/v1/metrics` endpoint of an OTLP/HTTP receiver, e.g. an
+//! OpenTelemetry collector. The metrics are read from the OpenMetrics text encoding of
+//! `prometheus-client`.
+//!
+//! All metrics are exported with cumulative temporality: counters as monotonic sums, gauges and
+//! info metrics as gauges and histograms as explicit bucket histograms.
+//!
+//! libp2p does not emit spans, thus only metrics are exported.
+//!
+//! ```no_run
+//! use libp2p_metrics::otlp;
+//! use prometheus_client::registry::Registry;
+//! use std::time::Duration;
+//!
+//! # async fn push() {
+//! let mut registry = Registry::default();
+//! let metrics = libp2p_metrics::Metrics::new(&mut registry);
+//! let exporter = otlp::Exporter::new(
+//!     otlp::Config::new("my-node")
+//!         .with_endpoint("http://collector:4318/v1/metrics".parse().unwrap()),
+//! );
+//!
+//! loop {
+//!     if let Err(e) = exporter.push(&registry).await {
+//!         eprintln!("Failed to export metrics: {e}");
+//!     }
+//!     tokio::time::sleep(Duration::from_secs(10)).await;
+//! }
+//! # }
+//! ```
+//!
+//! [otlp]: https://opentelemetry.io/docs/specs/otlp/
+
+use hyper::client::HttpConnector;
+use hyper::{header, Body, Client, Method, Request, StatusCode, Uri};
+use instant::SystemTime;
+use prometheus_client::encoding::text;
+use prometheus_client::registry::Registry;
+use serde_json::{json, Value};
+use std::{error, fmt};
+
+/// The content type of the requests pushed by an [`Exporter`].
+pub const CONTENT_TYPE: &str = "application/json";
+
+/// The default endpoint metrics are pushed to, i.e. that of an OpenTelemetry collector on the
+/// local host.
+pub const DEFAULT_ENDPOINT: &str = "http://localhost:4318/v1/metrics";
+
+/// The `AggregationTemporality` of all exported sums and histograms, i.e. cumulative.
+const AGGREGATION_TEMPORALITY_CUMULATIVE: u8 = 2;
+
+/// Configuration of an [`Exporter`].
+#[derive(Debug, Clone)]
+pub struct Config {
+    endpoint: Uri,
+    service_name: String,
+    resource_attributes: Vec<(String, String)>,
+}
+
+impl Config {
+    /// Creates a configuration exporting metrics with the given `service.name` resource attribute
+    /// to [`DEFAULT_ENDPOINT`].
+    pub fn new(service_name: impl Into<String>) -> Self {
+        Self {
+            endpoint: Uri::from_static(DEFAULT_ENDPOINT),
+            service_name: service_name.into(),
+            resource_attributes: Vec::new(),
+        }
+    }
+
+    /// Sets the OTLP/HTTP endpoint metrics are pushed to.
+    pub fn with_endpoint(mut self, endpoint: Uri) -> Self {
+        self.endpoint = endpoint;
+        self
+    }
+
+    /// Adds an attribute to the resource the metrics are exported for, e.g.
+    /// `service.instance.id`.
+    pub fn with_resource_attribute(
+        mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.resource_attributes.push((key.into(), value.into()));
+        self
+    }
+}
+
+/// Pushes the metrics of a [`Registry`] to an OTLP/HTTP receiver.
+///
+/// The point in time the exporter is created at is exported as the start time of all cumulative
+/// metrics. Thus the exporter should be created along with the registry and reused for all
+/// exports.
+#[derive(Debug)]
+pub struct Exporter {
+    config: Config,
+    start_time: SystemTime,
+    client: Client<HttpConnector>,
+}
+
+impl Exporter {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            start_time: SystemTime::now(),
+            client: Client::new(),
+        }
+    }
+
+    /// Pushes the current values of all metrics in the registry to the configured endpoint.
+    ///
+    /// Must be called within a tokio runtime.
+    pub async fn push(&self, registry: &Registry) -> Result<(), Error> {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(self.config.endpoint.clone())
+            .header(header::CONTENT_TYPE, CONTENT_TYPE)
+            .body(Body::from(self.encode(registry)?))
+            .expect("method, URI and header to be valid");
+
+        let response = self.client.request(request).await.map_err(Error::Http)?;
+        if !response.status().is_success() {
+            return Err(Error::Status(response.status()));
+        }
+
+        Ok(())
+    }
+
+    /// Encodes the current values of all metrics in the registry as an OTLP/JSON
+    /// `ExportMetricsServiceRequest`, as pushed by [`Exporter::push`].
+    pub fn encode(&self, registry: &Registry) -> Result<String, Error> {
+        let mut encoded = String::new();
+        text::encode(&mut encoded, registry).map_err(Error::Encode)?;
+
+        let timestamps = Timestamps {
+            start: unix_nanos(self.start_time),
+            now: unix_nanos(SystemTime::now()),
+        };
+        let metrics = parse_families(&encoded)
+            .iter()
+            .filter_map(|family| to_otlp(family, &timestamps))
+            .collect::<Vec<_>>();
+
+        let mut resource_attributes = vec![attribute("service.name", &self.config.service_name)];
+        resource_attributes.extend(
+            self.config
+                .resource_attributes
+                .iter()
+                .map(|(key, value)| attribute(key, value)),
+        );
+
+        let request = json!({
+            "resourceMetrics": [{
+                "resource": { "attributes": resource_attributes },
+                "scopeMetrics": [{
+                    "scope": {
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                    },
+                    "metrics": metrics,
+                }],
+            }],
+        });
+
+        Ok(request.to_string())
+    }
+}
+
+/// An error exporting metrics.
+#[derive(Debug)]
+pub enum Error {
+    /// The metrics of the registry could not be encoded.
+    Encode(fmt::Error),
+    /// The request could not be sent.
+    Http(hyper::Error),
+    /// The receiver rejected the request.
+    Status(StatusCode),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Encode(_) => write!(f, "Failed to encode metrics"),
+            Error::Http(e) => write!(f, "Failed to send metrics: {e}"),
+            Error::Status(status) => write!(f, "Receiver rejected metrics: {status}"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Encode(e) => Some(e),
+            Error::Http(e) => Some(e),
+            Error::Status(_) => None,
+        }
+    }
+}
+
+struct Timestamps {
+    start: String,
+    now: String,
+}
+
+/// A metric family as read from the OpenMetrics text encoding.
+#[derive(Debug, Default)]
+struct Family {
+    /// The name of the family, including the unit suffix.
+    name: String,
+    help: String,
+    unit: String,
+    r#type: String,
+    samples: Vec<Sample>,
+}
+
+/// A sample of a metric family, e.g. `libp2p_dials_total{transport="tcp"} 3`.
+#[derive(Debug)]
+struct Sample {
+    /// The suffix of the sample name after the family name, e.g. `_total`.
+    suffix: String,
+    labels: Vec<(String, String)>,
+    value: String,
+}
+
+/// Reads the metric families from the OpenMetrics text encoding of `prometheus-client`.
+///
+/// Lines that are not understood are skipped.
+fn parse_families(text: &str) -> Vec<Family> {
+    let mut families: Vec<Family> = Vec::new();
+    for line in text.lines() {
+        if let Some(comment) = line.strip_prefix("# ") {
+            let Some((keyword, rest)) = comment.split_once(' ') else {
+                continue;
+            };
+            let (name, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+            if families.last().map_or(true, |family| family.name != name) {
+                families.push(Family {
+                    name: name.to_string(),
+                    ..Default::default()
+                });
+            }
+            let family = families.last_mut().expect("family to be pushed");
+            match keyword {
+                "HELP" => family.help = rest.to_string(),
+                "TYPE" => family.r#type = rest.to_string(),
+                "UNIT" => family.unit = rest.to_string(),
+                _ => {}
+            }
+            continue;
+        }
+
+        let Some(family) = families.last_mut() else {
+            continue;
+        };
+        if let Some(sample) = parse_sample(line, &family.name) {
+            family.samples.push(sample);
+        }
+    }
+
+    families
+}
+
+/// Reads a sample of the family with the given name, ignoring exemplars.
+fn parse_sample(line: &str, family_name: &str) -> Option<Sample> {
+    let name_end = line.find(['{', ' '])?;
+    let (name, mut rest) = line.split_at(name_end);
+    let suffix = name.strip_prefix(family_name)?;
+
+    let mut labels = Vec::new();
+    if let Some(mut label_set) = rest.strip_prefix('{') {
+        while let Some((key, value)) = label_set.split_once("=\"") {
+            let value_end = value.find('"')?;
+            labels.push((key.to_string(), value[..value_end].to_string()));
+            label_set = &value[value_end + 1..];
+            label_set = label_set.strip_prefix(',').unwrap_or(label_set);
+        }
+        rest = label_set.strip_prefix('}')?;
+    }
+
+    let value = rest.trim_start().split(' ').next()?;
+    Some(Sample {
+        suffix: suffix.to_string(),
+        labels,
+        value: value.to_string(),
+    })
+}
+
+/// Converts a metric family to an OTLP metric, or `None` for types libp2p does not use, like
+/// summaries.
+fn to_otlp(family: &Family, timestamps: &Timestamps) -> Option<Value> {
+    let data_point = |labels: &[(String, String)]| {
+        json!({
+            "attributes": attributes(labels),
+            "startTimeUnixNano": timestamps.start,
+            "timeUnixNano": timestamps.now,
+        })
+    };
+    let samples = |suffix: &'static str| {
+        family
+            .samples
+            .iter()
+            .filter(move |sample| sample.suffix == suffix)
+    };
+
+    let mut data_points = Vec::new();
+    match family.r#type.as_str() {
+        "counter" | "gauge" => {
+            let suffix = if family.r#type == "counter" {
+                "_total"
+            } else {
+                ""
+            };
+            for sample in samples(suffix) {
+                let mut point = data_point(&sample.labels);
+                // OTLP/JSON encodes 64 bit integers as strings.
+                if sample.value.parse::<i64>().is_ok() {
+                    point["asInt"] = json!(sample.value);
+                } else if let Ok(v) = sample.value.parse::<f64>() {
+                    point["asDouble"] = json!(v);
+                } else {
+                    continue;
+                }
+                data_points.push(point);
+            }
+        }
+        "info" => {
+            // Like in OpenMetrics, info metrics have the value 1 and carry the information as
+            // labels.
+            for sample in samples("_info") {
+                let mut point = data_point(&sample.labels);
+                point["asInt"] = json!("1");
+                data_points.push(point);
+            }
+        }
+        "histogram" => {
+            // The samples of a histogram are its sum, its count and its cumulative buckets, in
+            // that order.
+            let mut cumulative = 0;
+            for sample in &family.samples {
+                match sample.suffix.as_str() {
+                    "_sum" => {
+                        let mut point = data_point(&sample.labels);
+                        point["sum"] = json!(sample.value.parse::<f64>().unwrap_or_default());
+                        point["bucketCounts"] = json!([]);
+                        point["explicitBounds"] = json!([]);
+                        data_points.push(point);
+                        cumulative = 0;
+                    }
+                    "_count" => {
+                        let Some(point) = data_points.last_mut() else {
+                            continue;
+                        };
+                        point["count"] = json!(sample.value);
+                    }
+                    "_bucket" => {
+                        let Some(point) = data_points.last_mut() else {
+                            continue;
+                        };
+                        // Unlike in OpenMetrics, buckets are not cumulative in OTLP and the last
+                        // bucket has no upper bound.
+                        let count = sample.value.parse::<u64>().unwrap_or_default();
+                        point["bucketCounts"]
+                            .as_array_mut()
+                            .expect("bucket counts to be an array")
+                            .push(json!(count.saturating_sub(cumulative).to_string()));
+                        cumulative = count;
+
+                        let le = sample.labels.iter().find(|(key, _)| key == "le");
+                        if let Some(Ok(bound)) = le.map(|(_, le)| le.parse::<f64>()) {
+                            if bound.is_finite() {
+                                point["explicitBounds"]
+                                    .as_array_mut()
+                                    .expect("explicit bounds to be an array")
+                                    .push(json!(bound));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        _ => return None,
+    }
+
+    let name = match family.unit.as_str() {
+        "" => family.name.as_str(),
+        unit => family
+            .name
+            .strip_suffix(unit)
+            .and_then(|name| name.strip_suffix('_'))
+            .unwrap_or(&family.name),
+    };
+    let mut metric = json!({
+        "name": name,
+        "description": family.help,
+        "unit": family.unit,
+    });
+    match family.r#type.as_str() {
+        "counter" => {
+            metric["sum"] = json!({
+                "dataPoints": data_points,
+                "aggregationTemporality": AGGREGATION_TEMPORALITY_CUMULATIVE,
+                "isMonotonic": true,
+            });
+        }
+        "histogram" => {
+            metric["histogram"] = json!({
+                "dataPoints": data_points,
+                "aggregationTemporality": AGGREGATION_TEMPORALITY_CUMULATIVE,
+            });
+        }
+        _ => metric["gauge"] = json!({ "dataPoints": data_points }),
+    }
+
+    Some(metric)
+}
+
+fn attributes(labels: &[(String, String)]) -> Vec<Value> {
+    labels
+        .iter()
+        .map(|(key, value)| attribute(key, value))
+        .collect()
+}
+
+fn attribute(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+/// OTLP/JSON encodes 64 bit integers, like timestamps, as strings.
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Response, Server};
+    use prometheus_client::metrics::counter::Counter;
+    use prometheus_client::metrics::family::Family;
+    use prometheus_client::metrics::gauge::Gauge;
+    use prometheus_client::metrics::histogram::Histogram;
+    use prometheus_client::registry::Unit;
+    use std::convert::Infallible;
+    use std::sync::atomic::AtomicU64;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn pushes_counters_and_histograms() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let make_service = make_service_fn(move |_| {
+            let tx = tx.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let tx = tx.clone();
+                    async move {
+                        let content_type = request.headers()[header::CONTENT_TYPE].clone();
+                        let body = hyper::body::to_bytes(request.into_body()).await?;
+                        tx.send((content_type, body)).unwrap();
+                        Ok::<_, hyper::Error>(Response::new(Body::empty()))
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let endpoint = format!("http://{}/v1/metrics", server.local_addr())
+            .parse()
+            .unwrap();
+        tokio::spawn(server);
+
+        let mut registry = Registry::default();
+        let sub_registry = registry.sub_registry_with_prefix("libp2p");
+
+        let dials = Family::<Vec<(String, String)>, Counter>::default();
+        sub_registry.register("dials", "Number of dials", dials.clone());
+        dials
+            .get_or_create(&vec![("transport".to_string(), "tcp".to_string())])
+            .inc_by(3);
+
+        let latency = Histogram::new([0.1, 1.0].into_iter());
+        sub_registry.register_with_unit("latency", "Latency", Unit::Seconds, latency.clone());
+        latency.observe(0.05);
+        latency.observe(0.5);
+        latency.observe(5.0);
+
+        let ratio = Gauge::<f64, AtomicU64>::default();
+        sub_registry.register("ratio", "Ratio", ratio.clone());
+        ratio.set(0.5);
+
+        let exporter = Exporter::new(
+            Config::new("test")
+                .with_endpoint(endpoint)
+                .with_resource_attribute("a", "b"),
+        );
+        exporter.push(&registry).await.unwrap();
+
+        let (content_type, body) = rx.recv().await.unwrap();
+        assert_eq!(content_type, CONTENT_TYPE);
+        let request: Value = serde_json::from_slice(&body).unwrap();
+
+        let resource = &request["resourceMetrics"][0];
+        assert_eq!(resource["resource"]["attributes"][1]["key"], "a");
+        let metrics = &resource["scopeMetrics"][0]["metrics"];
+
+        assert_eq!(metrics[0]["name"], "libp2p_dials");
+        let point = &metrics[0]["sum"]["dataPoints"][0];
+        assert_eq!(point["asInt"], "3");
+        assert_eq!(point["attributes"][0]["key"], "transport");
+        assert_eq!(point["attributes"][0]["value"]["stringValue"], "tcp");
+
+        assert_eq!(metrics[1]["name"], "libp2p_latency");
+        assert_eq!(metrics[1]["unit"], "seconds");
+        let point = &metrics[1]["histogram"]["dataPoints"][0];
+        assert_eq!(point["count"], "3");
+        assert_eq!(point["bucketCounts"], json!(["1", "1", "1"]));
+        assert_eq!(point["explicitBounds"], json!([0.1, 1.0]));
+        assert_eq!(point["sum"], json!(5.55));
+
+        assert_eq!(metrics[2]["name"], "libp2p_ratio");
+        assert_eq!(metrics[2]["gauge"]["dataPoints"][0]["asDouble"], json!(0.5));
+    }
+}
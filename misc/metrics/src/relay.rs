@@ -21,10 +21,16 @@
 use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
 use prometheus_client::metrics::counter::Counter;
 use prometheus_client::metrics::family::Family;
-use prometheus_client::registry::Registry;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
+use prometheus_client::registry::{Registry, Unit};
 
 pub(crate) struct Metrics {
     events: Family<EventLabels, Counter>,
+    reservations: Gauge,
+    circuits: Gauge,
+    circuit_bytes_relayed: Histogram,
+    denials: Family<DenialLabels, Counter>,
 }
 
 impl Metrics {
@@ -38,7 +44,42 @@ impl Metrics {
             events.clone(),
         );
 
-        Self { events }
+        let reservations = Gauge::default();
+        sub_registry.register(
+            "reservations",
+            "Number of active reservations with the relay server",
+            reservations.clone(),
+        );
+
+        let circuits = Gauge::default();
+        sub_registry.register(
+            "circuits",
+            "Number of active circuits relayed by the relay server",
+            circuits.clone(),
+        );
+
+        let circuit_bytes_relayed = Histogram::new(exponential_buckets(1024.0, 4.0, 12));
+        sub_registry.register_with_unit(
+            "circuit_bytes_relayed",
+            "Number of bytes relayed in both directions over a closed circuit",
+            Unit::Bytes,
+            circuit_bytes_relayed.clone(),
+        );
+
+        let denials = Family::default();
+        sub_registry.register(
+            "denials",
+            "Number of reservation and circuit requests denied by the relay server",
+            denials.clone(),
+        );
+
+        Self {
+            events,
+            reservations,
+            circuits,
+            circuit_bytes_relayed,
+            denials,
+        }
     }
 }
 
@@ -54,11 +95,13 @@ enum EventType {
     ReservationReqDenied,
     ReservationReqDenyFailed,
     ReservationTimedOut,
+    ReservationClosed,
     CircuitReqDenied,
     CircuitReqDenyFailed,
     CircuitReqOutboundConnectFailed,
     CircuitReqAccepted,
     CircuitReqAcceptFailed,
+    CircuitLimitApproaching,
     CircuitClosed,
 }
 
@@ -74,6 +117,7 @@ impl From<&libp2p_relay::Event> for EventType {
                 EventType::ReservationReqDenyFailed
             }
             libp2p_relay::Event::ReservationTimedOut { .. } => EventType::ReservationTimedOut,
+            libp2p_relay::Event::ReservationClosed { .. } => EventType::ReservationClosed,
             libp2p_relay::Event::CircuitReqDenied { .. } => EventType::CircuitReqDenied,
             libp2p_relay::Event::CircuitReqOutboundConnectFailed { .. } => {
                 EventType::CircuitReqOutboundConnectFailed
@@ -81,11 +125,47 @@ impl From<&libp2p_relay::Event> for EventType {
             libp2p_relay::Event::CircuitReqDenyFailed { .. } => EventType::CircuitReqDenyFailed,
             libp2p_relay::Event::CircuitReqAccepted { .. } => EventType::CircuitReqAccepted,
             libp2p_relay::Event::CircuitReqAcceptFailed { .. } => EventType::CircuitReqAcceptFailed,
+            libp2p_relay::Event::CircuitLimitApproaching { .. } => {
+                EventType::CircuitLimitApproaching
+            }
             libp2p_relay::Event::CircuitClosed { .. } => EventType::CircuitClosed,
         }
     }
 }
 
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct DenialLabels {
+    request: Request,
+    reason: DenialReason,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelValue)]
+enum Request {
+    Reservation,
+    Circuit,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelValue)]
+enum DenialReason {
+    ResourceLimitExceeded,
+    PermissionDenied,
+    NoReservation,
+    ConnectionFailed,
+}
+
+impl From<&libp2p_relay::DenialReason> for DenialReason {
+    fn from(reason: &libp2p_relay::DenialReason) -> Self {
+        match reason {
+            libp2p_relay::DenialReason::ResourceLimitExceeded => {
+                DenialReason::ResourceLimitExceeded
+            }
+            libp2p_relay::DenialReason::PermissionDenied => DenialReason::PermissionDenied,
+            libp2p_relay::DenialReason::NoReservation => DenialReason::NoReservation,
+            libp2p_relay::DenialReason::ConnectionFailed => DenialReason::ConnectionFailed,
+        }
+    }
+}
+
 impl super::Recorder<libp2p_relay::Event> for Metrics {
     fn record(&self, event: &libp2p_relay::Event) {
         self.events
@@ -93,5 +173,39 @@ impl super::Recorder<libp2p_relay::Event> for Metrics {
                 event: event.into(),
             })
             .inc();
+
+        match event {
+            libp2p_relay::Event::ReservationReqAccepted { renewed: false, .. } => {
+                self.reservations.inc();
+            }
+            libp2p_relay::Event::ReservationTimedOut { .. }
+            | libp2p_relay::Event::ReservationClosed { .. } => {
+                self.reservations.dec();
+            }
+            libp2p_relay::Event::ReservationReqDenied { reason, .. } => {
+                self.denials
+                    .get_or_create(&DenialLabels {
+                        request: Request::Reservation,
+                        reason: reason.into(),
+                    })
+                    .inc();
+            }
+            libp2p_relay::Event::CircuitReqDenied { reason, .. } => {
+                self.denials
+                    .get_or_create(&DenialLabels {
+                        request: Request::Circuit,
+                        reason: reason.into(),
+                    })
+                    .inc();
+            }
+            libp2p_relay::Event::CircuitReqAccepted { .. } => {
+                self.circuits.inc();
+            }
+            libp2p_relay::Event::CircuitClosed { bytes_relayed, .. } => {
+                self.circuits.dec();
+                self.circuit_bytes_relayed.observe(*bytes_relayed as f64);
+            }
+            _ => {}
+        }
     }
 }
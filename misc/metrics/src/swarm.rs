@@ -23,6 +23,7 @@ use std::sync::{Arc, Mutex};
 
 use crate::protocol_stack;
 use instant::Instant;
+use libp2p_core::multiaddr::{Multiaddr, Protocol};
 use libp2p_swarm::ConnectionId;
 use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
 use prometheus_client::metrics::counter::Counter;
@@ -47,6 +48,11 @@ pub(crate) struct Metrics {
     dial_attempt: Counter,
     outgoing_connection_error: Family<OutgoingConnectionErrorLabels, Counter>,
 
+    transport_dial_attempts: Family<TransportLabels, Counter>,
+    transport_dial_successes: Family<TransportLabels, Counter>,
+    transport_dial_failures: Family<TransportDialFailureLabels, Counter>,
+    transport_dial_duration: Family<TransportLabels, Histogram>,
+
     connections: Arc<Mutex<HashMap<ConnectionId, Instant>>>,
 }
 
@@ -110,6 +116,39 @@ impl Metrics {
             outgoing_connection_error.clone(),
         );
 
+        let transport_dial_attempts = Family::default();
+        sub_registry.register(
+            "transport_dial_attempts",
+            "Number of addresses dialed per transport",
+            transport_dial_attempts.clone(),
+        );
+
+        let transport_dial_successes = Family::default();
+        sub_registry.register(
+            "transport_dial_successes",
+            "Number of dials per transport that resulted in an established connection",
+            transport_dial_successes.clone(),
+        );
+
+        let transport_dial_failures = Family::default();
+        sub_registry.register(
+            "transport_dial_failures",
+            "Number of failed dials per transport and error class",
+            transport_dial_failures.clone(),
+        );
+
+        let transport_dial_duration = {
+            let constructor: fn() -> Histogram =
+                || Histogram::new(exponential_buckets(0.01, 1.5, 20));
+            Family::new_with_constructor(constructor)
+        };
+        sub_registry.register_with_unit(
+            "transport_dial_duration",
+            "Time it took to establish a connection per transport when dialing",
+            Unit::Seconds,
+            transport_dial_duration.clone(),
+        );
+
         let connections_established = Family::default();
         sub_registry.register(
             "connections_established",
@@ -150,6 +189,10 @@ impl Metrics {
             listener_error,
             dial_attempt,
             outgoing_connection_error,
+            transport_dial_attempts,
+            transport_dial_successes,
+            transport_dial_failures,
+            transport_dial_duration,
             connections_establishment_duration,
             connections_duration,
             connections: Default::default(),
@@ -179,6 +222,17 @@ impl<TBvEv, THandleErr> super::Recorder<libp2p_swarm::SwarmEvent<TBvEv, THandleE
                     .lock()
                     .expect("lock not to be poisoned")
                     .insert(*connection_id, Instant::now());
+
+                if let libp2p_core::ConnectedPoint::Dialer { address, .. } = endpoint {
+                    let labels = TransportLabels {
+                        transport: address.into(),
+                    };
+                    self.transport_dial_attempts.get_or_create(&labels).inc();
+                    self.transport_dial_successes.get_or_create(&labels).inc();
+                    self.transport_dial_duration
+                        .get_or_create(&labels)
+                        .observe(time_taken.as_secs_f64());
+                }
             }
             libp2p_swarm::SwarmEvent::ConnectionClosed {
                 endpoint,
@@ -234,9 +288,21 @@ impl<TBvEv, THandleErr> super::Recorder<libp2p_swarm::SwarmEvent<TBvEv, THandleE
                         .inc();
                 };
 
+                let record_transport = |address: &Multiaddr, error| {
+                    let transport = Transport::from(address);
+                    self.transport_dial_attempts
+                        .get_or_create(&TransportLabels { transport })
+                        .inc();
+                    self.transport_dial_failures
+                        .get_or_create(&TransportDialFailureLabels { transport, error })
+                        .inc();
+                };
+
                 match error {
                     libp2p_swarm::DialError::Transport(errors) => {
-                        for (_multiaddr, error) in errors {
+                        for (multiaddr, error) in errors {
+                            record_transport(multiaddr, error.into());
+
                             match error {
                                 libp2p_core::transport::TransportError::MultiaddrNotSupported(
                                     _,
@@ -259,7 +325,10 @@ impl<TBvEv, THandleErr> super::Recorder<libp2p_swarm::SwarmEvent<TBvEv, THandleE
                         record(OutgoingConnectionError::DialPeerConditionFalse)
                     }
                     libp2p_swarm::DialError::Aborted => record(OutgoingConnectionError::Aborted),
-                    libp2p_swarm::DialError::WrongPeerId { .. } => {
+                    libp2p_swarm::DialError::WrongPeerId { endpoint, .. } => {
+                        if let libp2p_core::ConnectedPoint::Dialer { address, .. } = endpoint {
+                            record_transport(address, TransportDialFailure::WrongPeerId);
+                        }
                         record(OutgoingConnectionError::WrongPeerId)
                     }
                     libp2p_swarm::DialError::Denied { .. } => {
@@ -374,6 +443,78 @@ enum OutgoingConnectionError {
     Denied,
 }
 
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+struct TransportLabels {
+    transport: Transport,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+struct TransportDialFailureLabels {
+    transport: Transport,
+    error: TransportDialFailure,
+}
+
+#[derive(EncodeLabelValue, Hash, Clone, Copy, Eq, PartialEq, Debug)]
+enum Transport {
+    Tcp,
+    Quic,
+    Webrtc,
+    Webtransport,
+    Websocket,
+    Relay,
+    Memory,
+    Other,
+}
+
+impl From<&Multiaddr> for Transport {
+    fn from(address: &Multiaddr) -> Self {
+        let has = |f: fn(&Protocol) -> bool| address.iter().any(|p| f(&p));
+
+        // Check the outermost transport first, e.g. a relayed address might be reached via TCP.
+        if has(|p| matches!(p, Protocol::P2pCircuit)) {
+            Transport::Relay
+        } else if has(|p| matches!(p, Protocol::WebRTCDirect)) {
+            Transport::Webrtc
+        } else if has(|p| matches!(p, Protocol::WebTransport)) {
+            Transport::Webtransport
+        } else if has(|p| matches!(p, Protocol::Ws(_) | Protocol::Wss(_))) {
+            Transport::Websocket
+        } else if has(|p| matches!(p, Protocol::Quic | Protocol::QuicV1)) {
+            Transport::Quic
+        } else if has(|p| matches!(p, Protocol::Tcp(_))) {
+            Transport::Tcp
+        } else if has(|p| matches!(p, Protocol::Memory(_))) {
+            Transport::Memory
+        } else {
+            Transport::Other
+        }
+    }
+}
+
+#[derive(EncodeLabelValue, Hash, Clone, Eq, PartialEq, Debug)]
+enum TransportDialFailure {
+    MultiaddrNotSupported,
+    ConnectionRefused,
+    Timeout,
+    WrongPeerId,
+    Other,
+}
+
+impl From<&libp2p_core::transport::TransportError<std::io::Error>> for TransportDialFailure {
+    fn from(error: &libp2p_core::transport::TransportError<std::io::Error>) -> Self {
+        match error {
+            libp2p_core::transport::TransportError::MultiaddrNotSupported(_) => {
+                TransportDialFailure::MultiaddrNotSupported
+            }
+            libp2p_core::transport::TransportError::Other(e) => match e.kind() {
+                std::io::ErrorKind::ConnectionRefused => TransportDialFailure::ConnectionRefused,
+                std::io::ErrorKind::TimedOut => TransportDialFailure::Timeout,
+                _ => TransportDialFailure::Other,
+            },
+        }
+    }
+}
+
 #[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
 struct IncomingConnectionErrorLabels {
     error: IncomingConnectionError,
@@ -406,3 +547,32 @@ impl From<&libp2p_swarm::ListenError> for IncomingConnectionError {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transport_of_address() {
+        let transport = |address: &str| Transport::from(&address.parse::<Multiaddr>().unwrap());
+
+        assert_eq!(transport("/ip4/127.0.0.1/tcp/1234"), Transport::Tcp);
+        assert_eq!(
+            transport("/ip4/127.0.0.1/udp/1234/quic-v1"),
+            Transport::Quic
+        );
+        assert_eq!(
+            transport("/ip4/127.0.0.1/tcp/1234/wss"),
+            Transport::Websocket
+        );
+        assert_eq!(
+            transport("/ip4/127.0.0.1/udp/1234/webrtc-direct"),
+            Transport::Webrtc
+        );
+        assert_eq!(
+            transport("/ip4/127.0.0.1/tcp/1234/p2p/12D3KooWGQmdpzHXCqLno4mMxWXKNFQHASBeF99gTm2JR8Vu5Bdc/p2p-circuit"),
+            Transport::Relay
+        );
+        assert_eq!(transport("/memory/1234"), Transport::Memory);
+    }
+}
@@ -0,0 +1,139 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use prometheus_client::collector::Collector;
+use prometheus_client::metrics::counter::ConstCounter;
+use prometheus_client::metrics::family::ConstFamily;
+use prometheus_client::metrics::gauge::ConstGauge;
+use prometheus_client::registry::{Descriptor, LocalMetric};
+use prometheus_client::MaybeOwned;
+use std::borrow::Cow;
+
+/// Exposes the flow control statistics of yamux connections, labeled by connection.
+#[derive(Debug)]
+pub(crate) struct Stats(pub(crate) libp2p_yamux::Stats);
+
+impl Collector for Stats {
+    fn collect<'a>(
+        &'a self,
+    ) -> Box<dyn Iterator<Item = (Cow<'a, Descriptor>, MaybeOwned<'a, Box<dyn LocalMetric>>)> + 'a>
+    {
+        let connections = self.0.connections();
+
+        let gauge = |name: &str, help: &str, value: fn(&libp2p_yamux::ConnectionStats) -> usize| {
+            let metric: Box<dyn LocalMetric> = Box::new(ConstFamily::new(
+                connections
+                    .iter()
+                    .map(|c| {
+                        (
+                            [("connection", c.id().to_string())],
+                            ConstGauge::new(value(c) as i64),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            ));
+            (
+                Cow::Owned(Descriptor::new(name, help, None, None, vec![])),
+                MaybeOwned::Owned(metric),
+            )
+        };
+        let counter = |name: &str, help: &str, value: fn(&libp2p_yamux::ConnectionStats) -> u64| {
+            let metric: Box<dyn LocalMetric> = Box::new(ConstFamily::new(
+                connections
+                    .iter()
+                    .map(|c| {
+                        (
+                            [("connection", c.id().to_string())],
+                            ConstCounter::new(value(c)),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            ));
+            (
+                Cow::Owned(Descriptor::new(name, help, None, None, vec![])),
+                MaybeOwned::Owned(metric),
+            )
+        };
+
+        let metrics = vec![
+            gauge("open_streams", "Number of currently open substreams", |c| {
+                c.open_streams()
+            }),
+            counter(
+                "send_stalls",
+                "Number of times writing to a substream was blocked by flow control",
+                |c| c.send_stalls(),
+            ),
+            gauge(
+                "streams_blocked_on_send",
+                "Number of substreams currently blocked on writing",
+                |c| c.streams_blocked_on_send(),
+            ),
+            gauge(
+                "buffered_send_bytes",
+                "Number of bytes written to substreams not yet written to the connection",
+                |c| c.buffered_send_bytes(),
+            ),
+            gauge(
+                "buffered_inbound_streams",
+                "Number of inbound substreams buffered because they have not yet been accepted",
+                |c| c.buffered_inbound_streams(),
+            ),
+            counter(
+                "dropped_inbound_streams",
+                "Number of inbound substreams dropped because too many were buffered",
+                |c| c.dropped_inbound_streams(),
+            ),
+        ];
+
+        Box::new(metrics.into_iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+    use libp2p_core::upgrade::InboundUpgrade;
+    use prometheus_client::encoding::text::encode;
+    use prometheus_client::registry::Registry;
+
+    #[test]
+    fn encodes_stats_per_connection() {
+        let mut registry = Registry::default();
+        let config = libp2p_yamux::Config::default();
+        crate::register_yamux_stats(&mut registry, config.stats());
+
+        let _muxer = config
+            .upgrade_inbound(futures::io::Cursor::new(Vec::new()), "/yamux/1.0.0")
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+
+        let mut encoded = String::new();
+        encode(&mut encoded, &registry).unwrap();
+
+        assert!(encoded.contains(r#"libp2p_yamux_open_streams{connection="0"} 0"#));
+        assert!(encoded.contains(r#"libp2p_yamux_send_stalls_total{connection="0"} 0"#));
+        assert!(encoded.contains(r#"libp2p_yamux_buffered_send_bytes{connection="0"} 0"#));
+        assert!(encoded.contains(r#"libp2p_yamux_dropped_inbound_streams_total{connection="0"} 0"#));
+    }
+}
@@ -26,7 +26,7 @@ use std::{
     hash::{Hash, Hasher},
     io, mem,
 };
-use unsigned_varint::{codec, encode};
+use unsigned_varint::{codec, decode, encode};
 
 // Maximum size for a packet: 1MB as per the spec.
 // Since data is entirely buffered before being dispatched, we need a limit or remotes could just
@@ -159,10 +159,27 @@ impl RemoteStreamId {
 /// An Mplex protocol frame.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) enum Frame<T> {
-    Open { stream_id: T },
-    Data { stream_id: T, data: Bytes },
-    Close { stream_id: T },
-    Reset { stream_id: T },
+    Open {
+        stream_id: T,
+    },
+    Data {
+        stream_id: T,
+        data: Bytes,
+    },
+    Close {
+        stream_id: T,
+    },
+    Reset {
+        stream_id: T,
+    },
+    /// Grants the remote `credit` more bytes to send on a substream.
+    ///
+    /// Only valid with credit-based flow control, see
+    /// [`MplexConfig::set_receive_window`](crate::MplexConfig::set_receive_window).
+    WindowUpdate {
+        stream_id: T,
+        credit: u32,
+    },
 }
 
 impl Frame<RemoteStreamId> {
@@ -172,6 +189,7 @@ impl Frame<RemoteStreamId> {
             Frame::Data { stream_id, .. } => stream_id,
             Frame::Close { stream_id, .. } => stream_id,
             Frame::Reset { stream_id, .. } => stream_id,
+            Frame::WindowUpdate { stream_id, .. } => stream_id,
         }
     }
 }
@@ -179,6 +197,8 @@ impl Frame<RemoteStreamId> {
 pub(crate) struct Codec {
     varint_decoder: codec::Uvi<u64>,
     decoder_state: CodecDecodeState,
+    /// Whether `WindowUpdate` frames are permitted.
+    credit: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -194,6 +214,15 @@ impl Codec {
         Codec {
             varint_decoder: codec::Uvi::default(),
             decoder_state: CodecDecodeState::Begin,
+            credit: false,
+        }
+    }
+
+    /// Permits `WindowUpdate` frames, used by credit-based flow control.
+    pub(crate) fn with_credit() -> Codec {
+        Codec {
+            credit: true,
+            ..Codec::new()
         }
     }
 }
@@ -263,6 +292,7 @@ impl Decoder for Codec {
                         6 => Frame::Reset {
                             stream_id: RemoteStreamId::dialer(num),
                         },
+                        7 if self.credit => decode_window_update(num, &buf)?,
                         _ => {
                             let msg = format!("Invalid mplex header value 0x{header:x}");
                             return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
@@ -284,6 +314,23 @@ impl Decoder for Codec {
     }
 }
 
+/// Decodes the payload of a `WindowUpdate` frame, i.e. a flag identifying the role of the
+/// sender followed by the credit as an unsigned varint.
+fn decode_window_update(num: u64, payload: &[u8]) -> io::Result<Frame<RemoteStreamId>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Invalid mplex window update");
+    let (flag, credit) = payload.split_first().ok_or_else(invalid)?;
+    let stream_id = match flag {
+        1 => RemoteStreamId::listener(num),
+        2 => RemoteStreamId::dialer(num),
+        _ => return Err(invalid()),
+    };
+    let (credit, rest) = decode::u32(credit).map_err(|_| invalid())?;
+    if !rest.is_empty() {
+        return Err(invalid());
+    }
+    Ok(Frame::WindowUpdate { stream_id, credit })
+}
+
 impl Encoder for Codec {
     type Item = Frame<LocalStreamId>;
     type Error = io::Error;
@@ -335,6 +382,17 @@ impl Encoder for Codec {
                         role: Endpoint::Dialer,
                     },
             } => (num << 3 | 6, Bytes::new()),
+            Frame::WindowUpdate { stream_id, credit } => {
+                let flag = match stream_id.role {
+                    Endpoint::Listener => 1,
+                    Endpoint::Dialer => 2,
+                };
+                let mut credit_buf = encode::u32_buffer();
+                let mut payload = BytesMut::new();
+                payload.put_u8(flag);
+                payload.put(encode::u32(credit, &mut credit_buf));
+                (stream_id.num << 3 | 7, payload.freeze())
+            }
         };
 
         let mut header_buf = encode::u64_buffer();
@@ -415,4 +473,30 @@ mod tests {
 
         assert_eq!(dec_string_id.num, stream_id.num);
     }
+
+    #[test]
+    fn window_update_requires_credit() {
+        let stream_id = LocalStreamId::dialer(42);
+        let frame = Frame::WindowUpdate {
+            stream_id,
+            credit: 1024 * 1024,
+        };
+
+        let mut buf = BytesMut::new();
+        Codec::with_credit()
+            .encode(frame, &mut buf)
+            .expect("Encoding to succeed.");
+        assert!(Codec::new().decode(&mut buf.clone()).is_err());
+
+        let decoded = Codec::with_credit()
+            .decode(&mut buf)
+            .expect("Decoding to succeed.");
+        assert_eq!(
+            decoded,
+            Some(Frame::WindowUpdate {
+                stream_id: RemoteStreamId::dialer(42),
+                credit: 1024 * 1024,
+            })
+        );
+    }
 }
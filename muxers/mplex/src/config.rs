@@ -19,10 +19,20 @@
 // DEALINGS IN THE SOFTWARE.
 
 use crate::codec::MAX_FRAME_SIZE;
-use std::cmp;
+use futures::channel::mpsc;
+use libp2p_core::Subscribers;
+use libp2p_identity::PeerId;
+use std::{borrow::Cow, cmp};
 
 pub(crate) const DEFAULT_MPLEX_PROTOCOL_NAME: &str = "/mplex/6.7.0";
 
+/// Protocol name of mplex with credit-based flow control, see [`MplexConfig::set_receive_window`].
+pub(crate) const CREDIT_MPLEX_PROTOCOL_NAME: &str = "/mplex/6.7.0+credit";
+
+/// The credit, in bytes, of a new substream in each direction with credit-based flow control.
+/// Larger receive windows are granted with a `WindowUpdate` frame when the substream is opened.
+pub(crate) const INITIAL_CREDIT: usize = 256 * 1024;
+
 /// Configuration for the multiplexer.
 #[derive(Debug, Clone)]
 pub struct MplexConfig {
@@ -35,8 +45,19 @@ pub struct MplexConfig {
     /// When sending data, split it into frames whose maximum size is this value
     /// (max 1MByte, as per the Mplex spec).
     pub(crate) split_send_size: usize,
+    /// The maximum frame size when adapting the frame size to the observed throughput,
+    /// if enabled.
+    pub(crate) max_split_send_size: Option<usize>,
     /// Protocol name, defaults to b"/mplex/6.7.0"
     pub(crate) protocol_name: &'static str,
+    /// Protocol name with credit-based flow control, derived from `protocol_name`.
+    pub(crate) credit_protocol_name: Cow<'static, str>,
+    /// Receive window per substream with credit-based flow control, if enabled.
+    pub(crate) receive_window: Option<usize>,
+    /// The remote peer reported in [`ProtocolViolation`]s, if known.
+    pub(crate) peer_id: Option<PeerId>,
+    /// The receivers of [`ProtocolViolation`]s, shared by all clones.
+    pub(crate) subscribers: Subscribers<ProtocolViolation>,
 }
 
 impl MplexConfig {
@@ -83,12 +104,96 @@ impl MplexConfig {
 
     /// Sets the frame size used when sending data. Capped at 1Mbyte as per the
     /// Mplex spec.
+    ///
+    /// With [`MplexConfig::set_adaptive_split_send_size`], this is the minimum frame size.
     pub fn set_split_send_size(&mut self, size: usize) -> &mut Self {
         let size = cmp::min(size, MAX_FRAME_SIZE);
         self.split_send_size = size;
         self
     }
 
+    /// The frame size used when sending data, or the minimum frame size if the frame size
+    /// adapts to the throughput of the connection.
+    pub fn split_send_size(&self) -> usize {
+        self.split_send_size
+    }
+
+    /// Enables adapting the frame size used when sending data to the throughput of each
+    /// connection, up to the given maximum frame size, or disables it with `None`.
+    /// Capped at 1Mbyte as per the Mplex spec. Disabled by default.
+    ///
+    /// The frame size is periodically set to the number of bytes sent within about a
+    /// millisecond at the observed throughput, but no less than the frame size set via
+    /// [`MplexConfig::set_split_send_size`]. Larger frames reduce the framing overhead on
+    /// high-bandwidth links, whereas the time a substream waits for the frame of another
+    /// substream to be sent stays about the same.
+    pub fn set_adaptive_split_send_size(&mut self, max: Option<usize>) -> &mut Self {
+        self.max_split_send_size = max.map(|max| cmp::min(max, MAX_FRAME_SIZE));
+        self
+    }
+
+    /// Enables credit-based flow control per substream with the given receive window (in
+    /// bytes), or disables it with `None`. Disabled by default. The receive window is at
+    /// least 256 KiB.
+    ///
+    /// With credit-based flow control, the remote may only send as many bytes on a substream
+    /// as have not yet been read locally, up to the receive window, bounding the memory used
+    /// for buffered data regardless of how fast the remote sends. Sending on a substream is
+    /// paused while the remote has not granted more credit. Credit is granted while reading from
+    /// any substream or accepting inbound substreams of the connection.
+    ///
+    /// Credit-based flow control is an extension of mplex, negotiated as the protocol name
+    /// suffixed with `+credit`, i.e. `/mplex/6.7.0+credit` by default, in preference to the plain
+    /// protocol name, so that peers not supporting it fall back to plain mplex.
+    pub fn set_receive_window(&mut self, window: Option<usize>) -> &mut Self {
+        self.receive_window = window.map(|window| window.clamp(INITIAL_CREDIT, u32::MAX as usize));
+        self
+    }
+
+    /// Sets the remote peer reported in [`ProtocolViolation`]s.
+    ///
+    /// The remote peer is known once the connection is authenticated, i.e. when the
+    /// multiplexer is created via `multiplex_ext`:
+    ///
+    /// ```rust
+    /// use libp2p_core::{transport::MemoryTransport, upgrade, Transport};
+    /// use libp2p_identity as identity;
+    /// use libp2p_mplex::MplexConfig;
+    /// use libp2p_plaintext::PlainText2Config;
+    ///
+    /// let config = MplexConfig::new();
+    /// let violations = config.subscribe();
+    /// let transport = MemoryTransport::default()
+    ///     .upgrade(upgrade::Version::V1)
+    ///     .authenticate(PlainText2Config {
+    ///         local_public_key: identity::Keypair::generate_ed25519().public(),
+    ///     })
+    ///     .multiplex_ext(move |peer_id, _| {
+    ///         let mut config = config.clone();
+    ///         config.set_peer_id(*peer_id);
+    ///         config
+    ///     });
+    /// ```
+    pub fn set_peer_id(&mut self, peer_id: PeerId) -> &mut Self {
+        self.peer_id = Some(peer_id);
+        self
+    }
+
+    /// Returns a receiver of the [`ProtocolViolation`]s of the remotes of all connections
+    /// created with this configuration or its clones, e.g. to block repeated offenders, see
+    /// [`Subscribers`].
+    pub fn subscribe(&self) -> mpsc::Receiver<ProtocolViolation> {
+        self.subscribers.subscribe()
+    }
+
+    /// Notifies the subscribers of a protocol violation of the remote.
+    pub(crate) fn emit(&self, kind: ViolationKind) {
+        self.subscribers.emit(ProtocolViolation {
+            peer_id: self.peer_id,
+            kind,
+        });
+    }
+
     /// Set the protocol name.
     ///
     /// ```rust
@@ -98,6 +203,11 @@ impl MplexConfig {
     /// ```
     pub fn set_protocol_name(&mut self, protocol_name: &'static str) -> &mut Self {
         self.protocol_name = protocol_name;
+        self.credit_protocol_name = if protocol_name == DEFAULT_MPLEX_PROTOCOL_NAME {
+            Cow::Borrowed(CREDIT_MPLEX_PROTOCOL_NAME)
+        } else {
+            Cow::Owned(format!("{protocol_name}+credit"))
+        };
         self
     }
 }
@@ -129,6 +239,35 @@ pub enum MaxBufferBehaviour {
     Block,
 }
 
+/// A violation of the mplex protocol or the configured limits by the remote,
+/// see [`MplexConfig::subscribe`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolViolation {
+    /// The remote peer, if set via [`MplexConfig::set_peer_id`].
+    pub peer_id: Option<PeerId>,
+    /// The kind of violation.
+    pub kind: ViolationKind,
+}
+
+/// The kind of a [`ProtocolViolation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ViolationKind {
+    /// The remote sent a frame that could not be decoded, closing the connection.
+    InvalidFrame,
+    /// The remote opened a substream with the ID of an open substream, closing the connection.
+    DuplicateSubstream,
+    /// The remote opened more substreams than permitted by
+    /// [`MplexConfig::set_max_num_streams`]. The substream is reset.
+    TooManySubstreams,
+    /// Too many frames, e.g. resets of substreams opened in excess, are pending to be sent
+    /// to the remote, closing the connection.
+    TooManyPendingFrames,
+    /// The remote sent more data on a substream than it was granted credit for with
+    /// credit-based flow control, closing the connection.
+    CreditExceeded,
+}
+
 impl Default for MplexConfig {
     fn default() -> MplexConfig {
         MplexConfig {
@@ -136,7 +275,12 @@ impl Default for MplexConfig {
             max_buffer_len: 32,
             max_buffer_behaviour: MaxBufferBehaviour::Block,
             split_send_size: 8 * 1024,
+            max_split_send_size: None,
             protocol_name: DEFAULT_MPLEX_PROTOCOL_NAME,
+            credit_protocol_name: Cow::Borrowed(CREDIT_MPLEX_PROTOCOL_NAME),
+            receive_window: None,
+            peer_id: None,
+            subscribers: Default::default(),
         }
     }
 }
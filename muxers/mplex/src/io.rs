@@ -19,7 +19,8 @@
 // DEALINGS IN THE SOFTWARE.
 
 use crate::codec::{Codec, Frame, LocalStreamId, RemoteStreamId};
-use crate::{MaxBufferBehaviour, MplexConfig};
+use crate::config::INITIAL_CREDIT;
+use crate::{MaxBufferBehaviour, MplexConfig, ViolationKind};
 use asynchronous_codec::Framed;
 use bytes::Bytes;
 use futures::task::{waker_ref, ArcWake, AtomicWaker, WakerRef};
@@ -33,6 +34,7 @@ use std::{
     cmp, fmt, io, mem,
     sync::Arc,
     task::{Context, Poll, Waker},
+    time::{Duration, Instant},
 };
 
 pub(crate) use std::io::{Error, Result};
@@ -85,8 +87,13 @@ pub(crate) struct Multiplexed<C> {
     pending_frames: VecDeque<Frame<LocalStreamId>>,
     /// The managed substreams.
     substreams: IntMap<LocalStreamId, SubstreamState>,
+    /// The credit of the managed substreams, if credit-based
+    /// flow control has been negotiated.
+    credits: IntMap<LocalStreamId, Credit>,
     /// The ID for the next outbound substream.
     next_outbound_stream_id: LocalStreamId,
+    /// The size of data frames, if adapted to the throughput.
+    split_send_size: Option<AdaptiveSplitSendSize>,
     /// Registry of wakers for pending tasks interested in reading.
     notifier_read: Arc<NotifierRead>,
     /// Registry of wakers for pending tasks interested in writing.
@@ -118,17 +125,27 @@ where
     pub(crate) fn new(io: C, config: MplexConfig) -> Self {
         let id = ConnectionId(rand::random());
         debug!("New multiplexed connection: {}", id);
+        let codec = if config.receive_window.is_some() {
+            Codec::with_credit()
+        } else {
+            Codec::new()
+        };
+        let split_send_size = config
+            .max_split_send_size
+            .map(|max| AdaptiveSplitSendSize::new(config.split_send_size, max, Instant::now()));
         Multiplexed {
             id,
             config,
             status: Status::Open,
-            io: Framed::new(io, Codec::new()).fuse(),
+            io: Framed::new(io, codec).fuse(),
             open_buffer: Default::default(),
             substreams: Default::default(),
+            credits: Default::default(),
             pending_flush_open: Default::default(),
             pending_frames: Default::default(),
             blocking_stream: None,
             next_outbound_stream_id: LocalStreamId::dialer(0),
+            split_send_size,
             notifier_read: Arc::new(NotifierRead {
                 read_stream: Mutex::new(Default::default()),
                 next_stream: AtomicWaker::new(),
@@ -189,6 +206,7 @@ where
                 // I/O stream, hence clearing the buffer and substreams.
                 self.open_buffer = Default::default();
                 self.substreams = Default::default();
+                self.credits = Default::default();
                 self.status = Status::Closed;
                 Poll::Ready(Ok(()))
             }
@@ -244,6 +262,9 @@ where
                     self.on_close(stream_id.into_local());
                 }
                 Frame::Reset { stream_id } => self.on_reset(stream_id.into_local()),
+                Frame::WindowUpdate { stream_id, credit } => {
+                    self.on_window_update(stream_id.into_local(), credit)
+                }
             }
         }
     }
@@ -282,6 +303,7 @@ where
                             stream_id,
                             self.substreams.len()
                         );
+                        self.init_credit(stream_id)?;
                         // The flush is delayed and the `Open` frame may be sent
                         // together with other frames in the same transport packet.
                         self.pending_flush_open.insert(stream_id);
@@ -328,6 +350,7 @@ where
         // the stream is gone. In contrast, wakers for write operations
         // are all woken on every new write opportunity.
         self.notifier_read.wake_read_stream(id);
+        self.credits.remove(&id);
 
         // Remove the substream, scheduling pending frames as necessary.
         match self.substreams.remove(&id) {
@@ -388,7 +411,21 @@ where
         }
 
         // Determine the size of the frame to send.
-        let frame_len = cmp::min(buf.len(), self.config.split_send_size);
+        let split_send_size = match &self.split_send_size {
+            Some(adaptive) => adaptive.current,
+            None => self.config.split_send_size,
+        };
+        let mut frame_len = cmp::min(buf.len(), split_send_size);
+
+        // Limit the frame to the credit granted by the remote, if any.
+        if let Some(credit) = self.credits.get_mut(&id) {
+            if credit.send == 0 && !buf.is_empty() {
+                trace!("{}: Substream {} is out of credit", self.id, id);
+                credit.send_waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            frame_len = cmp::min(frame_len, credit.send);
+        }
 
         // Send the data frame.
         ready!(self.poll_send_frame(cx, || {
@@ -399,6 +436,13 @@ where
             }
         }))?;
 
+        if let Some(credit) = self.credits.get_mut(&id) {
+            credit.send -= frame_len;
+        }
+        if let Some(adaptive) = &mut self.split_send_size {
+            adaptive.on_sent(frame_len, Instant::now());
+        }
+
         Poll::Ready(Ok(frame_len))
     }
 
@@ -433,6 +477,7 @@ where
                     ArcWake::wake_by_ref(&self.notifier_read);
                 }
                 let data = buf.remove(0);
+                self.on_consumed(id, data.len())?;
                 return Poll::Ready(Ok(Some(data)));
             }
             // If the stream buffer "spilled" onto the heap, free that memory.
@@ -464,7 +509,9 @@ where
             // Read the next frame.
             match ready!(self.poll_read_frame(cx, Some(id)))? {
                 Frame::Data { data, stream_id } if stream_id.into_local() == id => {
-                    return Poll::Ready(Ok(Some(data)))
+                    self.on_data(id, data.len())?;
+                    self.on_consumed(id, data.len())?;
+                    return Poll::Ready(Ok(Some(data)));
                 }
                 Frame::Data { stream_id, data } => {
                     // The data frame is for a different stream than the one
@@ -499,6 +546,9 @@ where
                         return Poll::Ready(Ok(None));
                     }
                 }
+                Frame::WindowUpdate { stream_id, credit } => {
+                    self.on_window_update(stream_id.into_local(), credit)
+                }
             }
         }
     }
@@ -667,7 +717,13 @@ where
                 trace!("{}: Received {:?}", self.id, frame);
                 Poll::Ready(Ok(frame))
             }
-            Some(Err(e)) => Poll::Ready(self.on_error(e)),
+            Some(Err(e)) => {
+                // The codec fails with `InvalidData` on frames that cannot be decoded.
+                if e.kind() == io::ErrorKind::InvalidData {
+                    self.config.emit(ViolationKind::InvalidFrame);
+                }
+                Poll::Ready(self.on_error(e))
+            }
             None => Poll::Ready(self.on_error(io::ErrorKind::UnexpectedEof.into())),
         }
     }
@@ -681,6 +737,7 @@ where
                 "{}: Received unexpected `Open` frame for open substream {}",
                 self.id, id
             );
+            self.config.emit(ViolationKind::DuplicateSubstream);
             return self.on_error(io::Error::new(
                 io::ErrorKind::Other,
                 "Protocol error: Received `Open` frame for open substream.",
@@ -692,6 +749,7 @@ where
                 "{}: Maximum number of substreams exceeded: {}",
                 self.id, self.config.max_substreams
             );
+            self.config.emit(ViolationKind::TooManySubstreams);
             self.check_max_pending_frames()?;
             debug!("{}: Pending reset for new stream {}", self.id, id);
             self.pending_frames
@@ -712,6 +770,7 @@ where
             id,
             self.substreams.len()
         );
+        self.init_credit(id)?;
 
         Ok(Some(id))
     }
@@ -800,6 +859,97 @@ where
         }
     }
 
+    /// Initialises the credit of a new substream if credit-based flow control
+    /// has been negotiated, granting the remote the configured receive window.
+    fn init_credit(&mut self, id: LocalStreamId) -> io::Result<()> {
+        let window = match self.config.receive_window {
+            Some(window) => window,
+            None => return Ok(()),
+        };
+        self.credits.insert(
+            id,
+            Credit {
+                send: INITIAL_CREDIT,
+                recv: window,
+                consumed: 0,
+                send_waker: None,
+            },
+        );
+        if window > INITIAL_CREDIT {
+            self.check_max_pending_frames()?;
+            self.pending_frames.push_front(Frame::WindowUpdate {
+                stream_id: id,
+                credit: (window - INITIAL_CREDIT) as u32,
+            });
+        }
+        Ok(())
+    }
+
+    /// Accounts for data received on a substream, failing the multiplexed
+    /// stream if the remote exceeded the credit of the substream.
+    fn on_data(&mut self, id: LocalStreamId, len: usize) -> io::Result<()> {
+        let credit = match self.credits.get_mut(&id) {
+            Some(credit) => credit,
+            None => return Ok(()),
+        };
+        if len > credit.recv {
+            debug!(
+                "{}: Remote exceeded the credit of substream {}",
+                self.id, id
+            );
+            self.config.emit(ViolationKind::CreditExceeded);
+            return self.on_error(io::Error::new(
+                io::ErrorKind::Other,
+                "Protocol error: Remote exceeded the credit of a substream.",
+            ));
+        }
+        credit.recv -= len;
+        Ok(())
+    }
+
+    /// Accounts for data of a substream read by the application, granting
+    /// the remote more credit once half of the receive window has been read.
+    fn on_consumed(&mut self, id: LocalStreamId, len: usize) -> io::Result<()> {
+        let window = match self.config.receive_window {
+            Some(window) => window,
+            None => return Ok(()),
+        };
+        let can_read = self.can_read(&id);
+        let credit = match self.credits.get_mut(&id) {
+            Some(credit) => credit,
+            None => return Ok(()),
+        };
+        credit.consumed += len;
+        if credit.consumed < window / 2 || !can_read {
+            return Ok(());
+        }
+        let grant = mem::take(&mut credit.consumed);
+        credit.recv += grant;
+        self.check_max_pending_frames()?;
+        trace!("{}: Granting {} bytes for stream {}", self.id, grant, id);
+        self.pending_frames.push_front(Frame::WindowUpdate {
+            stream_id: id,
+            credit: grant as u32,
+        });
+        Ok(())
+    }
+
+    /// Processes an inbound `WindowUpdate` frame.
+    fn on_window_update(&mut self, id: LocalStreamId, grant: u32) {
+        if let Some(credit) = self.credits.get_mut(&id) {
+            credit.send = credit.send.saturating_add(grant as usize);
+            if let Some(waker) = credit.send_waker.take() {
+                waker.wake();
+            }
+        } else {
+            trace!(
+                "{}: Ignoring `WindowUpdate` for unknown substream {}. Possibly dropped earlier.",
+                self.id,
+                id
+            );
+        }
+    }
+
     /// Generates the next outbound stream ID.
     fn next_outbound_stream_id(&mut self) -> LocalStreamId {
         let id = self.next_outbound_stream_id;
@@ -833,6 +983,7 @@ where
         self.status = Status::Err(io::Error::new(e.kind(), e.to_string()));
         self.pending_frames = Default::default();
         self.substreams = Default::default();
+        self.credits = Default::default();
         self.open_buffer = Default::default();
         Err(e)
     }
@@ -851,6 +1002,7 @@ where
     /// has not been reached.
     fn check_max_pending_frames(&mut self) -> io::Result<()> {
         if self.pending_frames.len() >= self.config.max_substreams + EXTRA_PENDING_FRAMES {
+            self.config.emit(ViolationKind::TooManyPendingFrames);
             return self.on_error(io::Error::new(
                 io::ErrorKind::Other,
                 "Too many pending frames.",
@@ -869,6 +1021,8 @@ where
     /// Fails the entire multiplexed stream if too many pending `Reset`
     /// frames accumulate when using [`MaxBufferBehaviour::ResetStream`].
     fn buffer(&mut self, id: LocalStreamId, data: Bytes) -> io::Result<()> {
+        self.on_data(id, data.len())?;
+
         let state = if let Some(state) = self.substreams.get_mut(&id) {
             state
         } else {
@@ -926,6 +1080,66 @@ where
 
 type RecvBuf = SmallVec<[Bytes; 10]>;
 
+/// The interval at which the size of data frames is adapted to the throughput.
+const ADAPT_SPLIT_SEND_SIZE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The time it should take to send a data frame at the observed throughput.
+const TARGET_FRAME_DURATION: Duration = Duration::from_millis(1);
+
+/// The size of data frames, adapted to the observed throughput.
+#[derive(Debug)]
+struct AdaptiveSplitSendSize {
+    min: usize,
+    max: usize,
+    /// The current size of data frames.
+    current: usize,
+    /// The number of bytes sent since `since`.
+    sent: usize,
+    since: Instant,
+}
+
+impl AdaptiveSplitSendSize {
+    fn new(min: usize, max: usize, now: Instant) -> Self {
+        let min = cmp::min(min, max);
+        Self {
+            min,
+            max,
+            current: min,
+            sent: 0,
+            since: now,
+        }
+    }
+
+    /// Records data sent, adapting the frame size once per interval to the
+    /// number of bytes sent within [`TARGET_FRAME_DURATION`] at the observed throughput.
+    fn on_sent(&mut self, len: usize, now: Instant) {
+        self.sent += len;
+        let elapsed = now.duration_since(self.since);
+        if elapsed < ADAPT_SPLIT_SEND_SIZE_INTERVAL {
+            return;
+        }
+        let target = self.sent as f64 * TARGET_FRAME_DURATION.as_secs_f64() / elapsed.as_secs_f64();
+        self.current = (target as usize).clamp(self.min, self.max);
+        trace!("Adapted split send size to {} bytes", self.current);
+        self.sent = 0;
+        self.since = now;
+    }
+}
+
+/// The credit of a substream with credit-based flow control.
+#[derive(Debug)]
+struct Credit {
+    /// The number of bytes the remote permits to be sent.
+    send: usize,
+    /// The number of bytes the remote may still send.
+    recv: usize,
+    /// The number of bytes read by the application but not yet granted
+    /// to the remote again.
+    consumed: usize,
+    /// The task waiting for the remote to grant more credit, if any.
+    send_waker: Option<Waker>,
+}
+
 /// The operating states of a substream.
 #[derive(Clone, Debug)]
 enum SubstreamState {
@@ -1123,7 +1337,12 @@ mod tests {
                 max_buffer_len: g.gen_range(1..1000),
                 max_buffer_behaviour: MaxBufferBehaviour::arbitrary(g),
                 split_send_size: g.gen_range(1..10000),
+                max_split_send_size: None,
                 protocol_name: crate::config::DEFAULT_MPLEX_PROTOCOL_NAME,
+                credit_protocol_name: crate::config::CREDIT_MPLEX_PROTOCOL_NAME.into(),
+                receive_window: None,
+                peer_id: None,
+                subscribers: Default::default(),
             }
         }
     }
@@ -1355,4 +1574,138 @@ mod tests {
 
         quickcheck(prop as fn(_, _))
     }
+
+    #[test]
+    fn reports_protocol_violations() {
+        let peer_id = libp2p_identity::PeerId::random();
+        let mut cfg = MplexConfig::new();
+        cfg.set_max_num_streams(1).set_peer_id(peer_id);
+        let mut violations = cfg.subscribe();
+
+        let mut r_buf = BytesMut::new();
+        let mut codec = Codec::new();
+        for stream_id in [0, 1, 0].map(LocalStreamId::dialer) {
+            codec.encode(Frame::Open { stream_id }, &mut r_buf).unwrap();
+        }
+        let conn = Connection {
+            r_buf,
+            w_buf: BytesMut::new(),
+            eof: false,
+        };
+        let mut m = Multiplexed::new(conn, cfg);
+
+        task::block_on(future::poll_fn(move |cx| {
+            assert!(matches!(m.poll_next_stream(cx), Poll::Ready(Ok(_))));
+            assert!(matches!(m.poll_next_stream(cx), Poll::Ready(Err(_))));
+            Poll::Ready(())
+        }));
+
+        let kinds = [
+            ViolationKind::TooManySubstreams,
+            ViolationKind::DuplicateSubstream,
+        ];
+        for kind in kinds {
+            assert_eq!(
+                violations.try_next().unwrap(),
+                Some(crate::ProtocolViolation {
+                    peer_id: Some(peer_id),
+                    kind
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn adaptive_split_send_size() {
+        let start = Instant::now();
+        let mut size = AdaptiveSplitSendSize::new(8 * 1024, 256 * 1024, start);
+
+        // 100 MB/s, i.e. 100 KB per millisecond.
+        size.on_sent(5_000_000, start + Duration::from_millis(50));
+        assert_eq!(size.current, 8 * 1024);
+        size.on_sent(5_000_000, start + Duration::from_millis(100));
+        assert_eq!(size.current, 100_000);
+
+        // 1 GB/s exceeds the maximum.
+        size.on_sent(100_000_000, start + Duration::from_millis(200));
+        assert_eq!(size.current, 256 * 1024);
+
+        // Idle, i.e. little throughput.
+        size.on_sent(1024, start + Duration::from_secs(10));
+        assert_eq!(size.current, 8 * 1024);
+    }
+
+    #[test]
+    fn credit_flow_control() {
+        let mut cfg = MplexConfig::new();
+        cfg.set_receive_window(Some(INITIAL_CREDIT))
+            .set_split_send_size(64 * 1024);
+        let conn = Connection {
+            r_buf: BytesMut::new(),
+            w_buf: BytesMut::new(),
+            eof: false,
+        };
+        let mut m = Multiplexed::new(conn, cfg);
+        let mut codec = Codec::with_credit();
+
+        task::block_on(future::poll_fn(move |cx| {
+            let id = ready!(m.poll_open_stream(cx)).unwrap();
+            let remote_id = LocalStreamId::listener(0);
+
+            // Writing is paused once the initial credit is used up.
+            let data = vec![0; 64 * 1024];
+            let mut sent = 0;
+            while let Poll::Ready(n) = m.poll_write_stream(cx, id, &data) {
+                sent += n.unwrap();
+            }
+            assert_eq!(sent, INITIAL_CREDIT);
+
+            // Reading a `WindowUpdate` frame resumes writing.
+            codec
+                .encode(
+                    Frame::WindowUpdate {
+                        stream_id: remote_id,
+                        credit: 1024,
+                    },
+                    &mut m.io.get_mut().deref_mut().r_buf,
+                )
+                .unwrap();
+            assert!(m.poll_read_stream(cx, id).is_pending());
+            assert!(matches!(
+                m.poll_write_stream(cx, id, &data),
+                Poll::Ready(Ok(1024))
+            ));
+
+            // Sending data in excess of the credit is a protocol violation.
+            let r_buf = &mut m.io.get_mut().deref_mut().r_buf;
+            for _ in 0..=INITIAL_CREDIT / data.len() {
+                let frame = Frame::Data {
+                    stream_id: remote_id,
+                    data: Bytes::from(data.clone()),
+                };
+                codec.encode(frame, r_buf).unwrap();
+            }
+            assert!(matches!(m.poll_next_stream(cx), Poll::Ready(Err(_))));
+
+            Poll::Ready(())
+        }));
+    }
+
+    #[test]
+    fn credit_protocol_name_follows_protocol_name() {
+        use libp2p_core::upgrade::UpgradeInfo;
+
+        let mut cfg = MplexConfig::new();
+        cfg.set_receive_window(Some(INITIAL_CREDIT));
+        assert_eq!(
+            cfg.protocol_info().collect::<Vec<_>>(),
+            ["/mplex/6.7.0+credit", "/mplex/6.7.0"]
+        );
+
+        cfg.set_protocol_name("/custom-mplex/1.0.0");
+        assert_eq!(
+            cfg.protocol_info().collect::<Vec<_>>(),
+            ["/custom-mplex/1.0.0+credit", "/custom-mplex/1.0.0"]
+        );
+    }
 }
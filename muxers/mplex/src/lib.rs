@@ -26,7 +26,7 @@ mod codec;
 mod config;
 mod io;
 
-pub use config::{MaxBufferBehaviour, MplexConfig};
+pub use config::{MaxBufferBehaviour, MplexConfig, ProtocolViolation, ViolationKind};
 
 use bytes::Bytes;
 use codec::LocalStreamId;
@@ -34,14 +34,17 @@ use futures::{future, prelude::*, ready};
 use libp2p_core::muxing::{StreamMuxer, StreamMuxerEvent};
 use libp2p_core::upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
 use parking_lot::Mutex;
-use std::{cmp, iter, pin::Pin, sync::Arc, task::Context, task::Poll};
+use std::{borrow::Cow, cmp, iter, option, pin::Pin, sync::Arc, task::Context, task::Poll};
 
 impl UpgradeInfo for MplexConfig {
-    type Info = &'static str;
-    type InfoIter = iter::Once<Self::Info>;
+    type Info = Cow<'static, str>;
+    type InfoIter = iter::Chain<option::IntoIter<Self::Info>, iter::Once<Self::Info>>;
 
     fn protocol_info(&self) -> Self::InfoIter {
-        iter::once(self.protocol_name)
+        self.receive_window
+            .map(|_| self.credit_protocol_name.clone())
+            .into_iter()
+            .chain(iter::once(Cow::Borrowed(self.protocol_name)))
     }
 }
 
@@ -53,7 +56,10 @@ where
     type Error = io::Error;
     type Future = future::Ready<Result<Self::Output, io::Error>>;
 
-    fn upgrade_inbound(self, socket: C, _: Self::Info) -> Self::Future {
+    fn upgrade_inbound(mut self, socket: C, protocol: Self::Info) -> Self::Future {
+        if protocol != self.credit_protocol_name {
+            self.receive_window = None;
+        }
         future::ready(Ok(Multiplex {
             #[allow(unknown_lints, clippy::arc_with_non_send_sync)] // `T` is not enforced to be `Send` but we don't want to constrain it either.
             io: Arc::new(Mutex::new(io::Multiplexed::new(socket, self))),
@@ -69,7 +75,10 @@ where
     type Error = io::Error;
     type Future = future::Ready<Result<Self::Output, io::Error>>;
 
-    fn upgrade_outbound(self, socket: C, _: Self::Info) -> Self::Future {
+    fn upgrade_outbound(mut self, socket: C, protocol: Self::Info) -> Self::Future {
+        if protocol != self.credit_protocol_name {
+            self.receive_window = None;
+        }
         future::ready(Ok(Multiplex {
             #[allow(unknown_lints, clippy::arc_with_non_send_sync)] // `T` is not enforced to be `Send` but we don't want to constrain it either.
             io: Arc::new(Mutex::new(io::Multiplexed::new(socket, self))),
@@ -0,0 +1,367 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Encoding and decoding of QUIC frames on an ordered byte stream.
+//!
+//! Frames use the wire format of [RFC 9000, section 19](https://www.rfc-editor.org/rfc/rfc9000#section-19).
+//! As there are no packets, `STREAM` frames always carry an explicit offset and length.
+
+use asynchronous_codec::{Decoder, Encoder};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::io;
+
+/// Largest value representable as a QUIC variable-length integer.
+pub(crate) const MAX_VARINT: u64 = (1 << 62) - 1;
+/// Maximum number of bytes in the payload of a single `STREAM` frame.
+pub(crate) const MAX_FRAME_PAYLOAD: usize = 1024 * 1024;
+
+const PADDING: u64 = 0x00;
+const PING: u64 = 0x01;
+const RESET_STREAM: u64 = 0x04;
+const STOP_SENDING: u64 = 0x05;
+const STREAM: u64 = 0x08;
+const STREAM_FIN: u64 = 0x01;
+const STREAM_LEN: u64 = 0x02;
+const STREAM_OFF: u64 = 0x04;
+const MAX_DATA: u64 = 0x10;
+const MAX_STREAM_DATA: u64 = 0x11;
+const MAX_STREAMS_BIDI: u64 = 0x12;
+const CONNECTION_CLOSE_APPLICATION: u64 = 0x1d;
+
+/// A QUIC frame as exchanged by qmux.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Frame {
+    Stream {
+        stream_id: u64,
+        offset: u64,
+        data: Bytes,
+        fin: bool,
+    },
+    ResetStream {
+        stream_id: u64,
+        error_code: u64,
+        final_size: u64,
+    },
+    StopSending {
+        stream_id: u64,
+        error_code: u64,
+    },
+    MaxData(u64),
+    MaxStreamData {
+        stream_id: u64,
+        max: u64,
+    },
+    MaxStreams(u64),
+    ConnectionClose {
+        error_code: u64,
+        reason: Bytes,
+    },
+}
+
+impl Frame {
+    /// Number of payload bytes of the frame that count towards flow control.
+    pub(crate) fn data_len(&self) -> usize {
+        match self {
+            Frame::Stream { data, .. } => data.len(),
+            _ => 0,
+        }
+    }
+}
+
+/// Encoder and decoder of [`Frame`]s.
+#[derive(Debug, Default)]
+pub(crate) struct Codec;
+
+impl Decoder for Codec {
+    type Item = Frame;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        loop {
+            let mut buf = &src[..];
+            let frame = match read_varint(&mut buf)? {
+                None => return Ok(None),
+                Some(PADDING) | Some(PING) => {
+                    let consumed = src.len() - buf.len();
+                    src.advance(consumed);
+                    continue;
+                }
+                Some(ty) => match decode_frame(ty, &mut buf)? {
+                    Some(frame) => frame,
+                    None => return Ok(None),
+                },
+            };
+            let consumed = src.len() - buf.len();
+            src.advance(consumed);
+            return Ok(Some(frame));
+        }
+    }
+}
+
+/// Decodes the body of a frame of the given type.
+///
+/// Returns `None` if `buf` does not yet contain the entire frame.
+fn decode_frame(ty: u64, buf: &mut &[u8]) -> io::Result<Option<Frame>> {
+    macro_rules! varint {
+        () => {
+            match read_varint(buf)? {
+                Some(v) => v,
+                None => return Ok(None),
+            }
+        };
+    }
+
+    let frame = match ty {
+        RESET_STREAM => Frame::ResetStream {
+            stream_id: varint!(),
+            error_code: varint!(),
+            final_size: varint!(),
+        },
+        STOP_SENDING => Frame::StopSending {
+            stream_id: varint!(),
+            error_code: varint!(),
+        },
+        ty if ty & !(STREAM_FIN | STREAM_LEN | STREAM_OFF) == STREAM => {
+            if ty & STREAM_LEN == 0 {
+                return Err(invalid_data("STREAM frame without length"));
+            }
+            let stream_id = varint!();
+            let offset = if ty & STREAM_OFF != 0 { varint!() } else { 0 };
+            let len = varint!();
+            if len > MAX_FRAME_PAYLOAD as u64 {
+                return Err(invalid_data("STREAM frame too large"));
+            }
+            if offset + len > MAX_VARINT {
+                return Err(invalid_data("STREAM frame exceeds maximum offset"));
+            }
+            if (buf.len() as u64) < len {
+                return Ok(None);
+            }
+            let data = Bytes::copy_from_slice(&buf[..len as usize]);
+            buf.advance(len as usize);
+            Frame::Stream {
+                stream_id,
+                offset,
+                data,
+                fin: ty & STREAM_FIN != 0,
+            }
+        }
+        MAX_DATA => Frame::MaxData(varint!()),
+        MAX_STREAM_DATA => Frame::MaxStreamData {
+            stream_id: varint!(),
+            max: varint!(),
+        },
+        MAX_STREAMS_BIDI => Frame::MaxStreams(varint!()),
+        CONNECTION_CLOSE_APPLICATION => {
+            let error_code = varint!();
+            let len = varint!();
+            if len > MAX_FRAME_PAYLOAD as u64 {
+                return Err(invalid_data("CONNECTION_CLOSE reason too large"));
+            }
+            if (buf.len() as u64) < len {
+                return Ok(None);
+            }
+            let reason = Bytes::copy_from_slice(&buf[..len as usize]);
+            buf.advance(len as usize);
+            Frame::ConnectionClose { error_code, reason }
+        }
+        ty => {
+            return Err(invalid_data(format!("unsupported frame type {ty:#x}")));
+        }
+    };
+
+    Ok(Some(frame))
+}
+
+impl Encoder for Codec {
+    type Item = Frame;
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        match item {
+            Frame::Stream {
+                stream_id,
+                offset,
+                data,
+                fin,
+            } => {
+                let mut ty = STREAM | STREAM_LEN | STREAM_OFF;
+                if fin {
+                    ty |= STREAM_FIN;
+                }
+                write_varint(dst, ty);
+                write_varint(dst, stream_id);
+                write_varint(dst, offset);
+                write_varint(dst, data.len() as u64);
+                dst.extend_from_slice(&data);
+            }
+            Frame::ResetStream {
+                stream_id,
+                error_code,
+                final_size,
+            } => {
+                write_varint(dst, RESET_STREAM);
+                write_varint(dst, stream_id);
+                write_varint(dst, error_code);
+                write_varint(dst, final_size);
+            }
+            Frame::StopSending {
+                stream_id,
+                error_code,
+            } => {
+                write_varint(dst, STOP_SENDING);
+                write_varint(dst, stream_id);
+                write_varint(dst, error_code);
+            }
+            Frame::MaxData(max) => {
+                write_varint(dst, MAX_DATA);
+                write_varint(dst, max);
+            }
+            Frame::MaxStreamData { stream_id, max } => {
+                write_varint(dst, MAX_STREAM_DATA);
+                write_varint(dst, stream_id);
+                write_varint(dst, max);
+            }
+            Frame::MaxStreams(max) => {
+                write_varint(dst, MAX_STREAMS_BIDI);
+                write_varint(dst, max);
+            }
+            Frame::ConnectionClose { error_code, reason } => {
+                write_varint(dst, CONNECTION_CLOSE_APPLICATION);
+                write_varint(dst, error_code);
+                write_varint(dst, reason.len() as u64);
+                dst.extend_from_slice(&reason);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads a QUIC variable-length integer, returning `None` if `buf` is too short.
+fn read_varint(buf: &mut &[u8]) -> io::Result<Option<u64>> {
+    let first = match buf.first() {
+        Some(b) => *b,
+        None => return Ok(None),
+    };
+    let len = 1 << (first >> 6);
+    if buf.len() < len {
+        return Ok(None);
+    }
+    let mut value = u64::from(first & 0x3f);
+    for b in &buf[1..len] {
+        value = (value << 8) | u64::from(*b);
+    }
+    buf.advance(len);
+    Ok(Some(value))
+}
+
+/// Writes `value` as a QUIC variable-length integer.
+///
+/// # Panics
+///
+/// Panics if `value` exceeds [`MAX_VARINT`].
+fn write_varint(dst: &mut BytesMut, value: u64) {
+    assert!(value <= MAX_VARINT, "varint out of range");
+    if value < 1 << 6 {
+        dst.put_u8(value as u8);
+    } else if value < 1 << 14 {
+        dst.put_u16(0x4000 | value as u16);
+    } else if value < 1 << 30 {
+        dst.put_u32(0x8000_0000 | value as u32);
+    } else {
+        dst.put_u64(0xc000_0000_0000_0000 | value);
+    }
+}
+
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varint_roundtrip() {
+        for value in [0, 63, 64, 16383, 16384, (1 << 30) - 1, 1 << 30, MAX_VARINT] {
+            let mut buf = BytesMut::new();
+            write_varint(&mut buf, value);
+            let mut slice = &buf[..];
+            assert_eq!(read_varint(&mut slice).unwrap(), Some(value));
+            assert!(slice.is_empty());
+        }
+    }
+
+    #[test]
+    fn frame_roundtrip_and_partial_input() {
+        let frames = vec![
+            Frame::Stream {
+                stream_id: 4,
+                offset: 1000,
+                data: Bytes::from_static(b"hello"),
+                fin: true,
+            },
+            Frame::ResetStream {
+                stream_id: 1,
+                error_code: 7,
+                final_size: 42,
+            },
+            Frame::StopSending {
+                stream_id: 5,
+                error_code: 0,
+            },
+            Frame::MaxData(1 << 40),
+            Frame::MaxStreamData {
+                stream_id: 8,
+                max: 300_000,
+            },
+            Frame::MaxStreams(512),
+            Frame::ConnectionClose {
+                error_code: 0,
+                reason: Bytes::from_static(b"bye"),
+            },
+        ];
+
+        let mut encoded = BytesMut::new();
+        for frame in frames.clone() {
+            Codec.encode(frame, &mut encoded).unwrap();
+        }
+
+        // Feed the encoding byte by byte to exercise partial decoding.
+        let mut src = BytesMut::new();
+        let mut decoded = Vec::new();
+        for b in encoded {
+            src.put_u8(b);
+            if let Some(frame) = Codec.decode(&mut src).unwrap() {
+                decoded.push(frame);
+            }
+        }
+
+        assert_eq!(decoded, frames);
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_frame_type() {
+        let mut src = BytesMut::from(&[0x1e][..]);
+        assert!(Codec.decode(&mut src).is_err());
+    }
+}
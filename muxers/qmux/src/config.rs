@@ -0,0 +1,92 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+/// The protocol name negotiated via multistream-select.
+pub(crate) const PROTOCOL_NAME: &str = "/qmux/1.0.0";
+
+/// Number of concurrent substreams each peer may open before receiving a `MAX_STREAMS` frame.
+pub(crate) const INITIAL_MAX_STREAMS: u64 = 128;
+/// Number of bytes each peer may send on a substream before receiving a `MAX_STREAM_DATA` frame.
+pub(crate) const INITIAL_STREAM_WINDOW: u64 = 256 * 1024;
+/// Number of bytes each peer may send on the connection before receiving a `MAX_DATA` frame.
+pub(crate) const INITIAL_CONNECTION_WINDOW: u64 = 1024 * 1024;
+
+/// Configuration for the qmux multiplexer.
+///
+/// Both peers start out with the fixed limits of the protocol. Configured limits larger than
+/// those are announced to the remote when the connection or substream is created.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub(crate) max_concurrent_streams: u64,
+    pub(crate) stream_receive_window: u64,
+    pub(crate) connection_receive_window: u64,
+    pub(crate) split_send_size: usize,
+}
+
+impl Config {
+    /// Builds the default configuration.
+    pub fn new() -> Config {
+        Default::default()
+    }
+
+    /// Sets the maximum number of substreams the remote may have open concurrently.
+    ///
+    /// Values below the initial limit of the protocol (128) are raised to it.
+    pub fn set_max_concurrent_streams(&mut self, max: usize) -> &mut Self {
+        self.max_concurrent_streams = (max as u64).max(INITIAL_MAX_STREAMS);
+        self
+    }
+
+    /// Sets the number of bytes the remote may send on a substream without it being read.
+    ///
+    /// Values below the initial window of the protocol (256 KiB) are raised to it.
+    pub fn set_stream_receive_window(&mut self, window: usize) -> &mut Self {
+        self.stream_receive_window = (window as u64).max(INITIAL_STREAM_WINDOW);
+        self
+    }
+
+    /// Sets the number of bytes the remote may send across all substreams without them being read.
+    ///
+    /// Values below the initial window of the protocol (1 MiB) are raised to it.
+    pub fn set_connection_receive_window(&mut self, window: usize) -> &mut Self {
+        self.connection_receive_window = (window as u64).max(INITIAL_CONNECTION_WINDOW);
+        self
+    }
+
+    /// Sets the maximum number of bytes sent in a single `STREAM` frame.
+    ///
+    /// Smaller frames reduce the time other substreams wait for a large write to hit the wire.
+    /// The size is capped at 1 MiB.
+    pub fn set_split_send_size(&mut self, size: usize) -> &mut Self {
+        self.split_send_size = size.clamp(1, crate::codec::MAX_FRAME_PAYLOAD);
+        self
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            max_concurrent_streams: 256,
+            stream_receive_window: 1024 * 1024,
+            connection_receive_window: 16 * 1024 * 1024,
+            split_send_size: 16 * 1024,
+        }
+    }
+}
@@ -0,0 +1,749 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Connection and substream state shared between the [`Muxer`](crate::Muxer) and its substreams.
+//!
+//! Substreams only ever touch this state; reading from and writing to the socket is done by
+//! whoever polls the muxer.
+
+use crate::codec::Frame;
+use crate::config::{
+    Config, INITIAL_CONNECTION_WINDOW, INITIAL_MAX_STREAMS, INITIAL_STREAM_WINDOW,
+};
+use bytes::Bytes;
+use libp2p_core::Endpoint;
+use log::{debug, trace};
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::task::{Context, Poll, Waker};
+
+/// Maximum number of payload bytes waiting to be written to the socket before writes to
+/// substreams are held back.
+const MAX_QUEUED_BYTES: usize = 256 * 1024;
+
+/// State of a single substream.
+#[derive(Debug)]
+struct StreamState {
+    /// Received data not yet read.
+    recv_buf: VecDeque<Bytes>,
+    /// Offset up to which data has been received.
+    recv_offset: u64,
+    /// Offset up to which the remote may send.
+    recv_max: u64,
+    /// Number of bytes read by the local substream.
+    consumed: u64,
+    /// Whether the remote finished sending, i.e. sent a `STREAM` frame with FIN.
+    recv_fin: bool,
+    /// Whether the remote abandoned sending via `RESET_STREAM`.
+    recv_reset: bool,
+
+    /// Offset up to which data has been sent.
+    send_offset: u64,
+    /// Offset up to which the remote allows us to send.
+    send_max: u64,
+    /// Whether we finished sending.
+    send_fin: bool,
+    /// Whether we abandoned sending via `RESET_STREAM`.
+    send_reset: bool,
+    /// Sequence number of the last frame queued for this substream.
+    last_seq: u64,
+
+    /// Whether the local [`Stream`](crate::Stream) has been dropped.
+    dropped: bool,
+    read_waker: Option<Waker>,
+}
+
+impl StreamState {
+    fn new(recv_max: u64) -> Self {
+        StreamState {
+            recv_buf: VecDeque::new(),
+            recv_offset: 0,
+            recv_max,
+            consumed: 0,
+            recv_fin: false,
+            recv_reset: false,
+            send_offset: 0,
+            send_max: INITIAL_STREAM_WINDOW,
+            send_fin: false,
+            send_reset: false,
+            last_seq: 0,
+            dropped: false,
+            read_waker: None,
+        }
+    }
+
+    fn buffered(&self) -> u64 {
+        self.recv_buf.iter().map(|b| b.len() as u64).sum()
+    }
+
+    fn wake_reader(&mut self) {
+        if let Some(waker) = self.read_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// State of a qmux connection.
+pub(crate) struct Shared {
+    config: Config,
+    /// Value of the initiator bit of the ids of the substreams we open.
+    local_initiator: u64,
+
+    streams: HashMap<u64, StreamState>,
+    /// Inbound substreams not yet returned by [`Shared::poll_accept`].
+    pending_inbound: VecDeque<u64>,
+    /// Number of substreams we opened.
+    opened_outbound: u64,
+    /// Number of substreams the remote opened.
+    opened_inbound: u64,
+    /// Number of substreams the remote allows us to open.
+    remote_max_streams: u64,
+    /// Number of substreams we allow the remote to open.
+    local_max_streams: u64,
+
+    /// Number of bytes sent across all substreams.
+    sent: u64,
+    /// Number of bytes the remote allows us to send across all substreams.
+    send_max: u64,
+    /// Number of bytes received across all substreams.
+    received: u64,
+    /// Number of received bytes read or discarded.
+    consumed: u64,
+    /// Number of bytes we allow the remote to send across all substreams.
+    recv_max: u64,
+
+    /// Frames waiting to be written to the socket.
+    queue: VecDeque<Frame>,
+    /// Number of payload bytes in `queue`.
+    queued_bytes: usize,
+    /// Sequence number of the last frame queued.
+    queued_seq: u64,
+    /// Sequence number of the last frame written to the socket.
+    written_seq: u64,
+    /// Sequence number of the last frame flushed to the socket.
+    flushed_seq: u64,
+
+    /// Set once the connection failed or was closed.
+    error: Option<io::ErrorKind>,
+
+    io_waker: Option<Waker>,
+    inbound_waker: Option<Waker>,
+    outbound_waker: Option<Waker>,
+    write_wakers: Vec<Waker>,
+    flush_wakers: Vec<Waker>,
+}
+
+impl Shared {
+    pub(crate) fn new(config: Config, endpoint: Endpoint) -> Self {
+        let mut shared = Shared {
+            local_initiator: match endpoint {
+                Endpoint::Dialer => 0,
+                Endpoint::Listener => 1,
+            },
+            streams: HashMap::new(),
+            pending_inbound: VecDeque::new(),
+            opened_outbound: 0,
+            opened_inbound: 0,
+            remote_max_streams: INITIAL_MAX_STREAMS,
+            local_max_streams: config.max_concurrent_streams,
+            sent: 0,
+            send_max: INITIAL_CONNECTION_WINDOW,
+            received: 0,
+            consumed: 0,
+            recv_max: config.connection_receive_window,
+            queue: VecDeque::new(),
+            queued_bytes: 0,
+            queued_seq: 0,
+            written_seq: 0,
+            flushed_seq: 0,
+            error: None,
+            io_waker: None,
+            inbound_waker: None,
+            outbound_waker: None,
+            write_wakers: Vec::new(),
+            flush_wakers: Vec::new(),
+            config,
+        };
+
+        if shared.local_max_streams > INITIAL_MAX_STREAMS {
+            shared.enqueue(Frame::MaxStreams(shared.local_max_streams));
+        }
+        if shared.recv_max > INITIAL_CONNECTION_WINDOW {
+            shared.enqueue(Frame::MaxData(shared.recv_max));
+        }
+
+        shared
+    }
+
+    fn is_local(&self, stream_id: u64) -> bool {
+        stream_id & 1 == self.local_initiator
+    }
+
+    fn check_error(&self) -> io::Result<()> {
+        match self.error {
+            Some(kind) => Err(io::Error::new(kind, "qmux connection closed")),
+            None => Ok(()),
+        }
+    }
+
+    /// Queues a frame for sending and returns its sequence number.
+    fn enqueue(&mut self, frame: Frame) -> u64 {
+        self.queued_bytes += frame.data_len();
+        self.queued_seq += 1;
+        self.queue.push_back(frame);
+        if let Some(waker) = self.io_waker.take() {
+            waker.wake();
+        }
+        self.queued_seq
+    }
+
+    /// Queues a `CONNECTION_CLOSE` frame, gracefully closing the connection.
+    pub(crate) fn queue_close(&mut self) {
+        self.enqueue(Frame::ConnectionClose {
+            error_code: 0,
+            reason: Bytes::new(),
+        });
+    }
+
+    /// Registers the task driving the socket, to be woken when new frames are queued.
+    pub(crate) fn register_io(&mut self, cx: &Context<'_>) {
+        self.io_waker = Some(cx.waker().clone());
+    }
+
+    pub(crate) fn has_queued(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    /// Takes the next frame to be written to the socket.
+    pub(crate) fn next_frame(&mut self) -> Option<Frame> {
+        let frame = self.queue.pop_front()?;
+        let was_full = self.queued_bytes >= MAX_QUEUED_BYTES;
+        self.queued_bytes -= frame.data_len();
+        self.written_seq += 1;
+        if was_full && self.queued_bytes < MAX_QUEUED_BYTES {
+            self.wake_writers();
+        }
+        Some(frame)
+    }
+
+    /// Marks all frames written so far as flushed.
+    pub(crate) fn on_flushed(&mut self) {
+        self.flushed_seq = self.written_seq;
+        for waker in self.flush_wakers.drain(..) {
+            waker.wake();
+        }
+    }
+
+    /// Fails the connection, waking all tasks waiting on it.
+    pub(crate) fn fail(&mut self, kind: io::ErrorKind) {
+        if self.error.is_some() {
+            return;
+        }
+        debug!("qmux connection closed: {:?}", kind);
+        self.error = Some(kind);
+        for stream in self.streams.values_mut() {
+            stream.wake_reader();
+        }
+        self.wake_writers();
+        for waker in self.flush_wakers.drain(..) {
+            waker.wake();
+        }
+        if let Some(waker) = self.inbound_waker.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.outbound_waker.take() {
+            waker.wake();
+        }
+    }
+
+    pub(crate) fn error(&self) -> Option<io::ErrorKind> {
+        self.error
+    }
+
+    fn wake_writers(&mut self) {
+        for waker in self.write_wakers.drain(..) {
+            waker.wake();
+        }
+    }
+
+    /// Creates the state of a new substream.
+    fn insert_stream(&mut self, stream_id: u64) {
+        let window = self.config.stream_receive_window;
+        self.streams.insert(stream_id, StreamState::new(window));
+        if window > INITIAL_STREAM_WINDOW {
+            self.enqueue(Frame::MaxStreamData {
+                stream_id,
+                max: window,
+            });
+        }
+    }
+
+    pub(crate) fn poll_open(&mut self, cx: &Context<'_>) -> Poll<io::Result<u64>> {
+        self.check_error()?;
+        if self.opened_outbound >= self.remote_max_streams {
+            trace!("remote does not allow opening more substreams");
+            self.outbound_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let stream_id = self.opened_outbound << 2 | self.local_initiator;
+        self.opened_outbound += 1;
+        self.insert_stream(stream_id);
+        Poll::Ready(Ok(stream_id))
+    }
+
+    pub(crate) fn poll_accept(&mut self, cx: &Context<'_>) -> Poll<io::Result<u64>> {
+        if let Some(stream_id) = self.pending_inbound.pop_front() {
+            return Poll::Ready(Ok(stream_id));
+        }
+        self.check_error()?;
+        self.inbound_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    pub(crate) fn poll_read(
+        &mut self,
+        stream_id: u64,
+        cx: &Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let window = self.config.stream_receive_window;
+        let error = self.error;
+        let stream = self
+            .streams
+            .get_mut(&stream_id)
+            .expect("state of a live substream to exist");
+
+        if stream.recv_reset {
+            return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()));
+        }
+
+        let mut n = 0;
+        while n < buf.len() {
+            let Some(chunk) = stream.recv_buf.front_mut() else {
+                break;
+            };
+            let len = chunk.len().min(buf.len() - n);
+            buf[n..n + len].copy_from_slice(&chunk.split_to(len));
+            if chunk.is_empty() {
+                stream.recv_buf.pop_front();
+            }
+            n += len;
+        }
+
+        if n == 0 {
+            if stream.recv_fin || buf.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+            if let Some(kind) = error {
+                return Poll::Ready(Err(io::Error::new(kind, "qmux connection closed")));
+            }
+            stream.read_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        stream.consumed += n as u64;
+        let update =
+            (!stream.recv_fin && stream.recv_max - stream.consumed < window / 2).then(|| {
+                stream.recv_max = stream.consumed + window;
+                stream.recv_max
+            });
+        if let Some(max) = update {
+            self.enqueue(Frame::MaxStreamData { stream_id, max });
+        }
+        self.release(n as u64);
+
+        Poll::Ready(Ok(n))
+    }
+
+    /// Returns connection-level credit for `n` received bytes that have been read or discarded.
+    fn release(&mut self, n: u64) {
+        self.consumed += n;
+        let window = self.config.connection_receive_window;
+        if self.recv_max - self.consumed < window / 2 {
+            self.recv_max = self.consumed + window;
+            self.enqueue(Frame::MaxData(self.recv_max));
+        }
+    }
+
+    pub(crate) fn poll_write(
+        &mut self,
+        stream_id: u64,
+        cx: &Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.check_error()?;
+        let conn_credit = self.send_max - self.sent;
+        let queue_full = self.queued_bytes >= MAX_QUEUED_BYTES;
+        let split_send_size = self.config.split_send_size;
+        let stream = self
+            .streams
+            .get_mut(&stream_id)
+            .expect("state of a live substream to exist");
+
+        if stream.send_reset {
+            return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()));
+        }
+        if stream.send_fin {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "write after close",
+            )));
+        }
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let credit = (stream.send_max - stream.send_offset).min(conn_credit);
+        if credit == 0 || queue_full {
+            trace!("writing to substream {} is blocked", stream_id);
+            self.write_wakers.push(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        let len = buf.len().min(split_send_size).min(credit as usize);
+        let frame = Frame::Stream {
+            stream_id,
+            offset: stream.send_offset,
+            data: Bytes::copy_from_slice(&buf[..len]),
+            fin: false,
+        };
+        stream.send_offset += len as u64;
+        self.sent += len as u64;
+        let seq = self.enqueue(frame);
+        self.stream_mut(stream_id).last_seq = seq;
+
+        Poll::Ready(Ok(len))
+    }
+
+    pub(crate) fn poll_flush(&mut self, stream_id: u64, cx: &Context<'_>) -> Poll<io::Result<()>> {
+        if self.streams[&stream_id].last_seq <= self.flushed_seq {
+            return Poll::Ready(Ok(()));
+        }
+        self.check_error()?;
+        self.flush_wakers.push(cx.waker().clone());
+        Poll::Pending
+    }
+
+    pub(crate) fn poll_close(&mut self, stream_id: u64, cx: &Context<'_>) -> Poll<io::Result<()>> {
+        let stream = self.stream_mut(stream_id);
+        if !stream.send_fin && !stream.send_reset {
+            stream.send_fin = true;
+            let frame = Frame::Stream {
+                stream_id,
+                offset: stream.send_offset,
+                data: Bytes::new(),
+                fin: true,
+            };
+            let seq = self.enqueue(frame);
+            self.stream_mut(stream_id).last_seq = seq;
+        }
+        self.poll_flush(stream_id, cx)
+    }
+
+    /// Handles the local substream being dropped, resetting whatever direction is still open.
+    pub(crate) fn drop_stream(&mut self, stream_id: u64) {
+        let stream = self.stream_mut(stream_id);
+        stream.dropped = true;
+        let reset = (!stream.send_fin && !stream.send_reset).then(|| {
+            stream.send_reset = true;
+            Frame::ResetStream {
+                stream_id,
+                error_code: 0,
+                final_size: stream.send_offset,
+            }
+        });
+        let stop = (!stream.recv_fin && !stream.recv_reset).then_some(Frame::StopSending {
+            stream_id,
+            error_code: 0,
+        });
+        let discarded = stream.buffered();
+        stream.recv_buf.clear();
+
+        if self.error.is_none() {
+            if let Some(frame) = reset {
+                self.enqueue(frame);
+            }
+            if let Some(frame) = stop {
+                self.enqueue(frame);
+            }
+        }
+        self.release(discarded);
+        self.maybe_remove(stream_id);
+    }
+
+    fn stream_mut(&mut self, stream_id: u64) -> &mut StreamState {
+        self.streams
+            .get_mut(&stream_id)
+            .expect("state of a live substream to exist")
+    }
+
+    /// Removes the state of a substream once both directions are done and it has been dropped.
+    fn maybe_remove(&mut self, stream_id: u64) {
+        let Some(stream) = self.streams.get(&stream_id) else {
+            return;
+        };
+        if !stream.dropped
+            || !(stream.recv_fin || stream.recv_reset)
+            || !(stream.send_fin || stream.send_reset)
+        {
+            return;
+        }
+        self.streams.remove(&stream_id);
+        trace!("substream {} closed", stream_id);
+
+        if !self.is_local(stream_id) && self.error.is_none() {
+            self.local_max_streams += 1;
+            self.enqueue(Frame::MaxStreams(self.local_max_streams));
+        }
+    }
+
+    /// Looks up the state of the substream a received frame refers to, opening remote substreams
+    /// as needed.
+    ///
+    /// Returns `None` if the substream has already been closed.
+    fn remote_stream(&mut self, stream_id: u64) -> io::Result<Option<&mut StreamState>> {
+        if stream_id & 2 != 0 {
+            return Err(protocol_violation(
+                "unidirectional substreams are not supported",
+            ));
+        }
+        let index = stream_id >> 2;
+        if self.is_local(stream_id) {
+            if index >= self.opened_outbound {
+                return Err(protocol_violation("frame for substream not yet opened"));
+            }
+        } else if index >= self.opened_inbound {
+            if index >= self.local_max_streams {
+                return Err(protocol_violation("substream limit exceeded"));
+            }
+            // Remote substreams are opened implicitly, including those with a lower id.
+            for index in self.opened_inbound..=index {
+                let id = index << 2 | (self.local_initiator ^ 1);
+                self.insert_stream(id);
+                self.pending_inbound.push_back(id);
+            }
+            self.opened_inbound = index + 1;
+            if let Some(waker) = self.inbound_waker.take() {
+                waker.wake();
+            }
+        }
+        Ok(self.streams.get_mut(&stream_id))
+    }
+
+    /// Handles a frame received from the remote.
+    pub(crate) fn on_frame(&mut self, frame: Frame) -> io::Result<()> {
+        trace!("received {:?}", frame);
+        match frame {
+            Frame::Stream {
+                stream_id,
+                offset,
+                data,
+                fin,
+            } => {
+                let len = data.len() as u64;
+                self.received += len;
+                if self.received > self.recv_max {
+                    return Err(protocol_violation("connection flow control exceeded"));
+                }
+                let Some(stream) = self.remote_stream(stream_id)? else {
+                    self.release(len);
+                    return Ok(());
+                };
+                if stream.recv_fin || stream.recv_reset {
+                    return Err(protocol_violation("data after end of substream"));
+                }
+                if offset != stream.recv_offset {
+                    return Err(protocol_violation("non-contiguous substream data"));
+                }
+                if offset + len > stream.recv_max {
+                    return Err(protocol_violation("substream flow control exceeded"));
+                }
+                stream.recv_offset += len;
+                stream.recv_fin = fin;
+                let discard = stream.dropped;
+                if !discard && !data.is_empty() {
+                    stream.recv_buf.push_back(data);
+                }
+                stream.wake_reader();
+                if discard {
+                    self.release(len);
+                }
+                if fin {
+                    self.maybe_remove(stream_id);
+                }
+            }
+            Frame::ResetStream {
+                stream_id,
+                final_size,
+                ..
+            } => {
+                let Some(stream) = self.remote_stream(stream_id)? else {
+                    return Ok(());
+                };
+                if stream.recv_fin || stream.recv_reset {
+                    return Ok(());
+                }
+                if final_size < stream.recv_offset || final_size > stream.recv_max {
+                    return Err(protocol_violation("invalid final size"));
+                }
+                let unsent = final_size - stream.recv_offset;
+                let discarded = stream.buffered();
+                stream.recv_buf.clear();
+                stream.recv_offset = final_size;
+                stream.recv_reset = true;
+                stream.wake_reader();
+                self.received += unsent;
+                if self.received > self.recv_max {
+                    return Err(protocol_violation("connection flow control exceeded"));
+                }
+                self.release(unsent + discarded);
+                self.maybe_remove(stream_id);
+            }
+            Frame::StopSending {
+                stream_id,
+                error_code,
+            } => {
+                let Some(stream) = self.remote_stream(stream_id)? else {
+                    return Ok(());
+                };
+                if !stream.send_fin && !stream.send_reset {
+                    stream.send_reset = true;
+                    let frame = Frame::ResetStream {
+                        stream_id,
+                        error_code,
+                        final_size: stream.send_offset,
+                    };
+                    self.enqueue(frame);
+                    self.wake_writers();
+                    self.maybe_remove(stream_id);
+                }
+            }
+            Frame::MaxData(max) => {
+                if max > self.send_max {
+                    self.send_max = max;
+                    self.wake_writers();
+                }
+            }
+            Frame::MaxStreamData { stream_id, max } => {
+                let Some(stream) = self.remote_stream(stream_id)? else {
+                    return Ok(());
+                };
+                if max > stream.send_max {
+                    stream.send_max = max;
+                    self.wake_writers();
+                }
+            }
+            Frame::MaxStreams(max) => {
+                if max > self.remote_max_streams {
+                    self.remote_max_streams = max;
+                    if let Some(waker) = self.outbound_waker.take() {
+                        waker.wake();
+                    }
+                }
+            }
+            Frame::ConnectionClose { error_code, reason } => {
+                debug!(
+                    "remote closed connection with code {}: {}",
+                    error_code,
+                    String::from_utf8_lossy(&reason)
+                );
+                self.fail(io::ErrorKind::ConnectionAborted);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn protocol_violation(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::task::noop_waker_ref;
+
+    /// Delivers all frames queued by `from` to `to`.
+    fn transfer(from: &mut Shared, to: &mut Shared) {
+        while let Some(frame) = from.next_frame() {
+            to.on_frame(frame).unwrap();
+        }
+        from.on_flushed();
+    }
+
+    #[test]
+    fn flow_control_and_reset() {
+        let mut config = Config::default();
+        config.set_stream_receive_window(0);
+        let mut dialer = Shared::new(config.clone(), Endpoint::Dialer);
+        let mut listener = Shared::new(config, Endpoint::Listener);
+        let cx = Context::from_waker(noop_waker_ref());
+        transfer(&mut dialer, &mut listener);
+        transfer(&mut listener, &mut dialer);
+
+        let Poll::Ready(Ok(id)) = dialer.poll_open(&cx) else {
+            panic!("expected to open a substream");
+        };
+
+        // Fill the initial window of the substream.
+        let data = vec![1; INITIAL_STREAM_WINDOW as usize];
+        let mut written = 0;
+        while written < data.len() {
+            match dialer.poll_write(id, &cx, &data[written..]) {
+                Poll::Ready(Ok(n)) => written += n,
+                other => panic!("unexpected {other:?}"),
+            }
+            transfer(&mut dialer, &mut listener);
+        }
+        assert!(dialer.poll_write(id, &cx, &[1]).is_pending());
+
+        // Reading returns credit to the dialer.
+        let Poll::Ready(Ok(remote_id)) = listener.poll_accept(&cx) else {
+            panic!("expected an inbound substream");
+        };
+        assert_eq!(remote_id, id);
+        let mut buf = vec![0; data.len()];
+        let mut read = 0;
+        while read < buf.len() {
+            match listener.poll_read(id, &cx, &mut buf[read..]) {
+                Poll::Ready(Ok(n)) => read += n,
+                other => panic!("unexpected {other:?}"),
+            }
+        }
+        transfer(&mut listener, &mut dialer);
+        assert!(matches!(
+            dialer.poll_write(id, &cx, &[1]),
+            Poll::Ready(Ok(1))
+        ));
+
+        // Dropping the substream on one side resets it on the other.
+        listener.drop_stream(id);
+        transfer(&mut listener, &mut dialer);
+        assert!(matches!(
+            dialer.poll_write(id, &cx, &[1]),
+            Poll::Ready(Err(e)) if e.kind() == io::ErrorKind::ConnectionReset
+        ));
+        dialer.drop_stream(id);
+        transfer(&mut dialer, &mut listener);
+        assert!(dialer.streams.is_empty());
+        assert!(listener.streams.is_empty());
+    }
+}
@@ -0,0 +1,307 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Implementation of qmux, a stream multiplexer running the stream layer of
+//! [QUIC](https://www.rfc-editor.org/rfc/rfc9000) over an ordered byte stream such as TCP.
+//!
+//! Substreams are bidirectional QUIC streams, exchanged using QUIC's `STREAM`, `RESET_STREAM`,
+//! `STOP_SENDING`, `MAX_DATA`, `MAX_STREAM_DATA`, `MAX_STREAMS` and `CONNECTION_CLOSE` frames.
+//! This gives every substream its own flow control window next to the one of the connection and
+//! makes resetting a substream a single frame in each direction.
+//!
+//! As all frames share one ordered byte stream, a lost packet of the underlying transport still
+//! delays all substreams, i.e. qmux does not avoid head-of-line blocking like QUIC does.
+
+#![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
+
+mod codec;
+mod config;
+mod io;
+
+pub use config::Config;
+
+use asynchronous_codec::Framed;
+use codec::Codec;
+use config::PROTOCOL_NAME;
+use futures::{future, prelude::*, ready};
+use io::Shared;
+use libp2p_core::muxing::{StreamMuxer, StreamMuxerEvent};
+use libp2p_core::upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
+use libp2p_core::Endpoint;
+use parking_lot::Mutex;
+use std::{fmt, iter, pin::Pin, sync::Arc, task::Context, task::Poll};
+
+/// A qmux connection.
+///
+/// All reading from and writing to the underlying socket happens while polling the muxer, so it
+/// has to be polled continuously for its substreams to make progress.
+pub struct Muxer<C> {
+    io: Framed<C, Codec>,
+    shared: Arc<Mutex<Shared>>,
+    /// Whether frames have been written to `io` since it was last flushed.
+    needs_flush: bool,
+    /// Whether a `CONNECTION_CLOSE` frame has been queued.
+    close_sent: bool,
+}
+
+impl<C> fmt::Debug for Muxer<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Muxer").finish_non_exhaustive()
+    }
+}
+
+impl<C> Muxer<C>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+{
+    fn new(io: C, config: Config, endpoint: Endpoint) -> Self {
+        Muxer {
+            io: Framed::new(io, Codec),
+            shared: Arc::new(Mutex::new(Shared::new(config, endpoint))),
+            needs_flush: false,
+            close_sent: false,
+        }
+    }
+
+    /// Writes queued frames to and reads frames from the socket until neither makes progress.
+    fn poll_io(&mut self, cx: &mut Context<'_>) -> Result<(), std::io::Error> {
+        self.shared.lock().register_io(cx);
+
+        loop {
+            let mut progress = false;
+
+            while self.shared.lock().has_queued() {
+                match self.io.poll_ready_unpin(cx) {
+                    Poll::Ready(Ok(())) => {}
+                    Poll::Ready(Err(e)) => return Err(self.fail(e)),
+                    Poll::Pending => break,
+                }
+                let frame = self
+                    .shared
+                    .lock()
+                    .next_frame()
+                    .expect("queue to be non-empty");
+                if let Err(e) = self.io.start_send_unpin(frame) {
+                    return Err(self.fail(e));
+                }
+                self.needs_flush = true;
+                progress = true;
+            }
+
+            if self.needs_flush {
+                match self.io.poll_flush_unpin(cx) {
+                    Poll::Ready(Ok(())) => {
+                        self.needs_flush = false;
+                        self.shared.lock().on_flushed();
+                    }
+                    Poll::Ready(Err(e)) => return Err(self.fail(e)),
+                    Poll::Pending => {}
+                }
+            }
+
+            match self.io.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(frame))) => {
+                    let result = self.shared.lock().on_frame(frame);
+                    if let Err(e) = result {
+                        return Err(self.fail(e));
+                    }
+                    progress = true;
+                }
+                Poll::Ready(Some(Err(e))) => return Err(self.fail(e)),
+                Poll::Ready(None) => {
+                    return Err(self.fail(std::io::ErrorKind::UnexpectedEof.into()));
+                }
+                Poll::Pending => {}
+            }
+
+            if let Some(kind) = self.shared.lock().error() {
+                return Err(kind.into());
+            }
+
+            if !progress {
+                return Ok(());
+            }
+        }
+    }
+
+    fn fail(&mut self, error: std::io::Error) -> std::io::Error {
+        log::debug!("qmux connection failed: {}", error);
+        self.shared.lock().fail(error.kind());
+        error
+    }
+}
+
+impl<C> StreamMuxer for Muxer<C>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+{
+    type Substream = Stream;
+    type Error = std::io::Error;
+
+    fn poll_inbound(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::Substream, Self::Error>> {
+        let this = self.get_mut();
+        this.poll_io(cx)?;
+        this.shared
+            .lock()
+            .poll_accept(cx)
+            .map_ok(|id| Stream::new(id, this.shared.clone()))
+    }
+
+    fn poll_outbound(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::Substream, Self::Error>> {
+        let this = self.get_mut();
+        this.poll_io(cx)?;
+        this.shared
+            .lock()
+            .poll_open(cx)
+            .map_ok(|id| Stream::new(id, this.shared.clone()))
+    }
+
+    fn poll(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<StreamMuxerEvent, Self::Error>> {
+        self.get_mut().poll_io(cx)?;
+        Poll::Pending
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+
+        if !this.close_sent {
+            if this.shared.lock().error().is_some() {
+                return Poll::Ready(Ok(()));
+            }
+            this.shared.lock().queue_close();
+            this.close_sent = true;
+        }
+
+        while this.shared.lock().has_queued() {
+            ready!(this.io.poll_ready_unpin(cx))?;
+            let frame = this
+                .shared
+                .lock()
+                .next_frame()
+                .expect("queue to be non-empty");
+            this.io.start_send_unpin(frame)?;
+        }
+        ready!(this.io.poll_close_unpin(cx))?;
+
+        let mut shared = this.shared.lock();
+        shared.on_flushed();
+        shared.fail(std::io::ErrorKind::ConnectionAborted);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// A substream of a qmux connection.
+///
+/// Dropping the substream resets it unless it has been closed and read to its end.
+pub struct Stream {
+    id: u64,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Stream {
+    fn new(id: u64, shared: Arc<Mutex<Shared>>) -> Self {
+        Stream { id, shared }
+    }
+}
+
+impl fmt::Debug for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Stream").field("id", &self.id).finish()
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        self.shared.lock().poll_read(self.id, cx, buf)
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        self.shared.lock().poll_write(self.id, cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.shared.lock().poll_flush(self.id, cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.shared.lock().poll_close(self.id, cx)
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        self.shared.lock().drop_stream(self.id)
+    }
+}
+
+impl UpgradeInfo for Config {
+    type Info = &'static str;
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(PROTOCOL_NAME)
+    }
+}
+
+impl<C> InboundUpgrade<C> for Config
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+{
+    type Output = Muxer<C>;
+    type Error = std::io::Error;
+    type Future = future::Ready<Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, io: C, _: Self::Info) -> Self::Future {
+        future::ready(Ok(Muxer::new(io, self, Endpoint::Listener)))
+    }
+}
+
+impl<C> OutboundUpgrade<C> for Config
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+{
+    type Output = Muxer<C>;
+    type Error = std::io::Error;
+    type Future = future::Ready<Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, io: C, _: Self::Info) -> Self::Future {
+        future::ready(Ok(Muxer::new(io, self, Endpoint::Dialer)))
+    }
+}
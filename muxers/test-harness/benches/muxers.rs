@@ -0,0 +1,225 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Compares the throughput of the stream multiplexers.
+//!
+//! Every combination of muxer, stream count, message size and latency is run `--samples` times
+//! and reported as one JSON object per line on stdout, e.g.
+//!
+//! ```sh
+//! cargo bench -p libp2p-muxer-test-harness --bench muxers -- \
+//!     --muxers yamux,mplex --streams 1,64 --message-sizes 1024,65536 --latencies-ms 0,20
+//! ```
+//!
+//! Yamux, mplex and qmux run over a `MemoryTransport` connection delaying all data by the given
+//! latency.
+//! QUIC runs over UDP on the loopback interface, where no latency can be simulated, and is thus
+//! only benchmarked with a latency of zero.
+//!
+//! Without `--bench`, i.e. when run by `cargo test`, a single small workload is run per muxer.
+
+use clap::{Parser, ValueEnum};
+use futures::channel::oneshot;
+use futures::StreamExt;
+use libp2p_core::transport::ListenerId;
+use libp2p_core::Transport;
+use libp2p_muxer_test_harness::bench::{self, Workload};
+use std::time::Duration;
+
+#[derive(Debug, Parser)]
+#[clap(name = "muxer benchmarks")]
+struct Opts {
+    /// The muxers to benchmark.
+    #[arg(long, value_delimiter = ',', default_values = ["yamux", "mplex", "qmux", "quic"])]
+    muxers: Vec<Muxer>,
+    /// Numbers of concurrent substreams.
+    #[arg(long, value_delimiter = ',', default_values_t = [1, 16, 128])]
+    streams: Vec<usize>,
+    /// Sizes of the messages written to each substream, in bytes.
+    #[arg(long, value_delimiter = ',', default_values_t = [1024, 64 * 1024])]
+    message_sizes: Vec<usize>,
+    /// Total number of bytes written to each substream.
+    #[arg(long, default_value_t = 1024 * 1024)]
+    bytes_per_stream: usize,
+    /// One-way latencies of the link, in milliseconds.
+    #[arg(long, value_delimiter = ',', default_values_t = [0, 10])]
+    latencies_ms: Vec<u64>,
+    /// Number of times each combination is run.
+    #[arg(long, default_value_t = 5)]
+    samples: usize,
+    /// Passed by `cargo bench`.
+    #[arg(long, hide = true)]
+    bench: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Muxer {
+    Yamux,
+    Mplex,
+    Qmux,
+    Quic,
+}
+
+impl Muxer {
+    fn name(&self) -> &'static str {
+        match self {
+            Muxer::Yamux => "yamux",
+            Muxer::Mplex => "mplex",
+            Muxer::Qmux => "qmux",
+            Muxer::Quic => "quic",
+        }
+    }
+}
+
+#[async_std::main]
+async fn main() {
+    let _ = env_logger::try_init();
+
+    let mut opts = Opts::parse();
+    if !opts.bench {
+        opts.streams = vec![4];
+        opts.message_sizes = vec![1024];
+        opts.bytes_per_stream = 16 * 1024;
+        opts.latencies_ms = vec![0, 1];
+        opts.samples = 1;
+    }
+
+    for &muxer in &opts.muxers {
+        for &latency_ms in &opts.latencies_ms {
+            if muxer == Muxer::Quic && latency_ms > 0 {
+                eprintln!("skipping quic with a latency of {latency_ms}ms: not supported");
+                continue;
+            }
+            let latency = Duration::from_millis(latency_ms);
+
+            for &streams in &opts.streams {
+                for &message_size in &opts.message_sizes {
+                    let workload = Workload {
+                        streams,
+                        message_size,
+                        messages: (opts.bytes_per_stream / message_size).max(1),
+                    };
+
+                    let mut samples = Vec::with_capacity(opts.samples);
+                    for _ in 0..opts.samples {
+                        samples.push(run(muxer, latency, workload).await);
+                    }
+                    report(muxer, latency, workload, samples);
+                }
+            }
+        }
+    }
+}
+
+async fn run(muxer: Muxer, latency: Duration, workload: Workload) -> Duration {
+    let streams = workload.streams.max(1);
+
+    match muxer {
+        Muxer::Yamux => {
+            let mut config = libp2p_yamux::Config::default();
+            config.set_max_num_streams(streams);
+            let (mut dialer, mut listener) =
+                bench::connected_muxers_with_latency(config.clone(), config, latency).await;
+            bench::run(&mut dialer, &mut listener, workload).await
+        }
+        Muxer::Mplex => {
+            let mut config = libp2p_mplex::MplexConfig::default();
+            config.set_max_num_streams(streams);
+            let (mut dialer, mut listener) =
+                bench::connected_muxers_with_latency(config.clone(), config, latency).await;
+            bench::run(&mut dialer, &mut listener, workload).await
+        }
+        Muxer::Qmux => {
+            let mut config = libp2p_qmux::Config::default();
+            config.set_max_concurrent_streams(streams);
+            let (mut dialer, mut listener) =
+                bench::connected_muxers_with_latency(config.clone(), config, latency).await;
+            bench::run(&mut dialer, &mut listener, workload).await
+        }
+        Muxer::Quic => {
+            let (mut dialer, mut listener) = connected_quic_peers().await;
+            bench::run(&mut dialer, &mut listener, workload).await
+        }
+    }
+}
+
+/// Prints the results of all samples of a workload as a line of JSON.
+fn report(muxer: Muxer, latency: Duration, workload: Workload, mut samples: Vec<Duration>) {
+    samples.sort();
+    let median = samples[samples.len() / 2];
+    let throughput = workload.total_bytes() as f64 / median.as_secs_f64();
+
+    println!(
+        "{{\"muxer\":\"{}\",\"latency_ms\":{},\"streams\":{},\"message_size\":{},\"messages\":{},\"bytes\":{},\"samples\":{},\"min_ms\":{:.3},\"median_ms\":{:.3},\"max_ms\":{:.3},\"throughput_bytes_per_s\":{:.0}}}",
+        muxer.name(),
+        latency.as_millis(),
+        workload.streams,
+        workload.message_size,
+        workload.messages,
+        workload.total_bytes(),
+        samples.len(),
+        samples[0].as_secs_f64() * 1000.0,
+        median.as_secs_f64() * 1000.0,
+        samples[samples.len() - 1].as_secs_f64() * 1000.0,
+        throughput,
+    );
+}
+
+async fn connected_quic_peers() -> (libp2p_quic::Connection, libp2p_quic::Connection) {
+    let mut dialer = new_quic_transport().boxed();
+    let mut listener = new_quic_transport().boxed();
+
+    listener
+        .listen_on(
+            ListenerId::next(),
+            "/ip4/127.0.0.1/udp/0/quic-v1".parse().unwrap(),
+        )
+        .unwrap();
+    let listen_address = listener.next().await.unwrap().into_new_address().unwrap();
+
+    let (listener_conn_sender, listener_conn_receiver) = oneshot::channel();
+    async_std::task::spawn(async move {
+        let (upgrade, _) = listener.next().await.unwrap().into_incoming().unwrap();
+        async_std::task::spawn(async move {
+            let (_, connection) = upgrade.await.unwrap();
+            let _ = listener_conn_sender.send(connection);
+        });
+        loop {
+            listener.next().await;
+        }
+    });
+
+    let dial = dialer.dial(listen_address).unwrap();
+    async_std::task::spawn(async move {
+        loop {
+            dialer.next().await;
+        }
+    });
+
+    let (dialer_conn, listener_conn) = futures::future::join(dial, listener_conn_receiver).await;
+
+    (dialer_conn.unwrap().1, listener_conn.unwrap())
+}
+
+fn new_quic_transport() -> libp2p_quic::async_std::Transport {
+    let keypair = libp2p_identity::Keypair::generate_ed25519();
+
+    libp2p_quic::async_std::Transport::new(libp2p_quic::Config::new(&keypair))
+}
@@ -0,0 +1,175 @@
+//! Building blocks for benchmarking [`StreamMuxer`] implementations against each other.
+//!
+//! [`connected_muxers_with_latency`] connects two muxers over a [`MemoryTransport`] connection
+//! delaying all data by a fixed latency, [`run`] measures the time it takes to transfer data over
+//! a number of concurrent substreams.
+
+use futures::future::{poll_fn, BoxFuture};
+use futures::stream::FuturesUnordered;
+use futures::{AsyncRead, AsyncWrite, AsyncWriteExt, FutureExt, StreamExt};
+use libp2p_core::muxing::StreamMuxerExt;
+use libp2p_core::transport::memory::{Channel, LinkConditions};
+use libp2p_core::transport::{ListenerId, MemoryTransport};
+use libp2p_core::{InboundUpgrade, OutboundUpgrade, StreamMuxer, Transport};
+use std::fmt;
+use std::task::Poll;
+use std::time::{Duration, Instant};
+
+/// The workload of a single benchmark run.
+#[derive(Debug, Clone, Copy)]
+pub struct Workload {
+    /// Number of substreams opened concurrently by the dialer.
+    pub streams: usize,
+    /// Size of each message written to a substream.
+    pub message_size: usize,
+    /// Number of messages written to each substream.
+    pub messages: usize,
+}
+
+impl Workload {
+    /// Total number of bytes transferred by the workload.
+    pub fn total_bytes(&self) -> usize {
+        self.streams * self.message_size * self.messages
+    }
+}
+
+/// Connects two muxers over a [`MemoryTransport`] connection with the given one-way `latency`.
+pub async fn connected_muxers_with_latency<MC, M, E>(
+    dialer_config: MC,
+    listener_config: MC,
+    latency: Duration,
+) -> (M, M)
+where
+    MC: InboundUpgrade<Channel<Vec<u8>>, Error = E, Output = M>
+        + OutboundUpgrade<Channel<Vec<u8>>, Error = E, Output = M>,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let mut listener_transport = MemoryTransport::default().boxed();
+    listener_transport
+        .listen_on(ListenerId::next(), "/memory/0".parse().unwrap())
+        .unwrap();
+    let listen_addr = listener_transport
+        .select_next_some()
+        .await
+        .into_new_address()
+        .expect("listen address");
+
+    let mut dialer_transport = MemoryTransport::default();
+    if !latency.is_zero() {
+        dialer_transport =
+            dialer_transport.with_link_conditions(LinkConditions::default().with_latency(latency));
+    }
+    let (dialer, listener) =
+        futures::future::join(dialer_transport.dial(listen_addr).unwrap(), async {
+            loop {
+                if let Some((upgrade, _)) =
+                    listener_transport.select_next_some().await.into_incoming()
+                {
+                    break upgrade.await;
+                }
+            }
+        })
+        .await;
+    let (dialer, listener) = (dialer.unwrap(), listener.unwrap());
+
+    let dialer_info = dialer_config.protocol_info().into_iter().next().unwrap();
+    let listener_info = listener_config.protocol_info().into_iter().next().unwrap();
+
+    futures::future::try_join(
+        dialer_config.upgrade_outbound(dialer, dialer_info),
+        listener_config.upgrade_inbound(listener, listener_info),
+    )
+    .await
+    .unwrap()
+}
+
+/// Runs the given [`Workload`], returning the time it took the listener to receive all data.
+///
+/// The dialer opens [`Workload::streams`] substreams, writes the messages to each of them and
+/// closes them. The listener reads every substream to its end. Both muxers are polled from the
+/// calling task throughout.
+pub async fn run<A, B, E>(dialer: &mut A, listener: &mut B, workload: Workload) -> Duration
+where
+    A: StreamMuxer<Error = E> + Unpin,
+    B: StreamMuxer<Error = E> + Unpin,
+    A::Substream: Send + Unpin + 'static,
+    B::Substream: Send + Unpin + 'static,
+    E: fmt::Debug,
+{
+    let message = vec![0u8; workload.message_size];
+    let expected = (workload.message_size * workload.messages) as u64;
+
+    let mut senders = FuturesUnordered::<BoxFuture<'static, ()>>::new();
+    let mut receivers = FuturesUnordered::<BoxFuture<'static, ()>>::new();
+    let mut opened = 0;
+    let mut accepted = 0;
+    let mut received = 0;
+
+    let start = Instant::now();
+
+    poll_fn(|cx| loop {
+        let mut progress = false;
+
+        while let Poll::Ready(event) = dialer.poll_unpin(cx) {
+            event.expect("dialer muxer to not fail");
+        }
+        while let Poll::Ready(event) = listener.poll_unpin(cx) {
+            event.expect("listener muxer to not fail");
+        }
+
+        while opened < workload.streams {
+            let Poll::Ready(stream) = dialer.poll_outbound_unpin(cx) else {
+                break;
+            };
+            senders.push(send(stream.unwrap(), message.clone(), workload.messages).boxed());
+            opened += 1;
+            progress = true;
+        }
+        while accepted < workload.streams {
+            let Poll::Ready(stream) = listener.poll_inbound_unpin(cx) else {
+                break;
+            };
+            receivers.push(receive(stream.unwrap(), expected).boxed());
+            accepted += 1;
+            progress = true;
+        }
+
+        while let Poll::Ready(Some(())) = senders.poll_next_unpin(cx) {
+            progress = true;
+        }
+        while let Poll::Ready(Some(())) = receivers.poll_next_unpin(cx) {
+            received += 1;
+            progress = true;
+        }
+
+        if received == workload.streams {
+            return Poll::Ready(());
+        }
+        if !progress {
+            return Poll::Pending;
+        }
+    })
+    .await;
+
+    start.elapsed()
+}
+
+async fn send<S>(mut stream: S, message: Vec<u8>, messages: usize)
+where
+    S: AsyncWrite + Unpin,
+{
+    for _ in 0..messages {
+        stream.write_all(&message).await.unwrap();
+    }
+    stream.close().await.unwrap();
+}
+
+async fn receive<S>(mut stream: S, expected: u64)
+where
+    S: AsyncRead + Unpin,
+{
+    let received = futures::io::copy(&mut stream, &mut futures::io::sink())
+        .await
+        .unwrap();
+    assert_eq!(received, expected);
+}
@@ -10,6 +10,8 @@ use std::task::{Context, Poll};
 use std::time::Duration;
 use std::{fmt, mem};
 
+pub mod bench;
+
 pub async fn connected_muxers_on_memory_ring_buffer<MC, M, E>() -> (M, M)
 where
     MC: InboundUpgrade<futures_ringbuf::Endpoint, Error = E, Output = M>
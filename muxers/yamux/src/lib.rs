@@ -22,9 +22,11 @@
 
 #![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
 
-use futures::{future, prelude::*, ready};
+use either::Either;
+use futures::{channel::mpsc, future, prelude::*, ready};
 use libp2p_core::muxing::{StreamMuxer, StreamMuxerEvent};
 use libp2p_core::upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
+use libp2p_core::Subscribers;
 use std::collections::VecDeque;
 use std::io::{IoSlice, IoSliceMut};
 use std::task::Waker;
@@ -32,29 +34,55 @@ use std::{
     io, iter,
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
 use thiserror::Error;
-use yamux::ConnectionError;
+
+mod priority;
+mod stats;
+mod timeout;
+
+pub use priority::Priority;
+use priority::Scheduler;
+use stats::CountSent;
+pub use stats::{ConnectionStats, Stats};
+use timeout::WriteTimeout;
+
+type Connection<C> = Either<
+    yamux012::Connection<CountSent<WriteTimeout<C>>>,
+    yamux013::Connection<CountSent<WriteTimeout<C>>>,
+>;
 
 /// A Yamux connection.
 #[derive(Debug)]
 pub struct Muxer<C> {
-    connection: yamux::Connection<C>,
+    connection: Connection<C>,
     /// Temporarily buffers inbound streams in case our node is performing backpressure on the remote.
     ///
-    /// The only way how yamux can make progress is by calling [`yamux::Connection::poll_next_inbound`]. However, the
+    /// The only way how yamux can make progress is by calling `yamux::Connection::poll_next_inbound`. However, the
     /// [`StreamMuxer`] interface is designed to allow a caller to selectively make progress via
     /// [`StreamMuxer::poll_inbound`] and [`StreamMuxer::poll_outbound`] whilst the more general
     /// [`StreamMuxer::poll`] is designed to make progress on existing streams etc.
     ///
     /// This buffer stores inbound streams that are created whilst [`StreamMuxer::poll`] is called.
-    /// Once the buffer is full, new inbound streams are dropped.
+    /// Once the buffer is full, streams are reset according to the [`InboundStreamBufferPolicy`].
     inbound_stream_buffer: VecDeque<Stream>,
+    /// The maximum number of streams in `inbound_stream_buffer`.
+    max_buffered_inbound_streams: usize,
+    inbound_stream_buffer_policy: InboundStreamBufferPolicy,
     /// Waker to be called when new inbound streams are available.
     inbound_stream_waker: Option<Waker>,
+    /// Flow control statistics shared with the [`Config`].
+    stats: Stats,
+    /// Flow control statistics of this connection.
+    connection_stats: ConnectionStats,
+    /// Schedules writes to the streams of this connection by their [`Priority`].
+    scheduler: Scheduler,
+    /// The receivers of [`Event`]s, shared with the [`Config`].
+    subscribers: Subscribers<Event>,
 }
 
-/// How many streams to buffer before we start resetting them.
+/// How many streams to buffer before we start resetting them, by default.
 ///
 /// This is equal to the ACK BACKLOG in `rust-yamux`.
 /// Thus, for peers running on a recent version of `rust-libp2p`, we should never need to reset streams because they'll voluntarily stop opening them once they hit the ACK backlog.
@@ -65,15 +93,29 @@ where
     C: AsyncRead + AsyncWrite + Send + Unpin + 'static,
 {
     /// Create a new Yamux connection.
-    fn new(io: C, cfg: yamux::Config, mode: yamux::Mode) -> Self {
+    fn new(connection: Connection<C>, config: Shared, connection_stats: ConnectionStats) -> Self {
         Muxer {
-            connection: yamux::Connection::new(io, cfg, mode),
+            connection,
             inbound_stream_buffer: VecDeque::default(),
+            max_buffered_inbound_streams: config.max_buffered_inbound_streams,
+            inbound_stream_buffer_policy: config.inbound_stream_buffer_policy,
             inbound_stream_waker: None,
+            stats: config.stats,
+            connection_stats,
+            scheduler: Scheduler::default(),
+            subscribers: config.subscribers,
         }
     }
 }
 
+impl<C> Drop for Muxer<C> {
+    fn drop(&mut self) {
+        self.connection_stats
+            .inbound_streams_unbuffered(self.inbound_stream_buffer.len());
+        self.stats.connection_closed(&self.connection_stats);
+    }
+}
+
 impl<C> StreamMuxer for Muxer<C>
 where
     C: AsyncRead + AsyncWrite + Unpin + 'static,
@@ -86,6 +128,7 @@ where
         cx: &mut Context<'_>,
     ) -> Poll<Result<Self::Substream, Self::Error>> {
         if let Some(stream) = self.inbound_stream_buffer.pop_front() {
+            self.connection_stats.inbound_streams_unbuffered(1);
             return Poll::Ready(Ok(stream));
         }
 
@@ -101,15 +144,25 @@ where
         mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Result<Self::Substream, Self::Error>> {
-        let stream = ready!(self.connection.poll_new_outbound(cx).map_err(Error)?);
+        let stats = self.connection_stats.clone();
+        let scheduler = self.scheduler.clone();
+        let stream = match self.connection.as_mut() {
+            Either::Left(c) => ready!(c.poll_new_outbound(cx))
+                .map_err(|e| Error(Either::Left(e)))
+                .map(|s| Stream::new(Either::Left(s), stats, scheduler)),
+            Either::Right(c) => ready!(c.poll_new_outbound(cx))
+                .map_err(|e| Error(Either::Right(e)))
+                .map(|s| Stream::new(Either::Right(s), stats, scheduler)),
+        }?;
 
-        Poll::Ready(Ok(Stream(stream)))
+        Poll::Ready(Ok(stream))
     }
 
     fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        ready!(self.connection.poll_close(cx).map_err(Error)?);
-
-        Poll::Ready(Ok(()))
+        match self.connection.as_mut() {
+            Either::Left(c) => c.poll_close(cx).map_err(|e| Error(Either::Left(e))),
+            Either::Right(c) => c.poll_close(cx).map_err(|e| Error(Either::Right(e))),
+        }
     }
 
     fn poll(
@@ -120,16 +173,7 @@ where
 
         let inbound_stream = ready!(this.poll_inner(cx))?;
 
-        if this.inbound_stream_buffer.len() >= MAX_BUFFERED_INBOUND_STREAMS {
-            log::warn!("dropping {} because buffer is full", inbound_stream.0);
-            drop(inbound_stream);
-        } else {
-            this.inbound_stream_buffer.push_back(inbound_stream);
-
-            if let Some(waker) = this.inbound_stream_waker.take() {
-                waker.wake()
-            }
-        }
+        this.buffer_inbound_stream(inbound_stream);
 
         // Schedule an immediate wake-up, allowing other code to run.
         cx.waker().wake_by_ref();
@@ -139,7 +183,96 @@ where
 
 /// A stream produced by the yamux multiplexer.
 #[derive(Debug)]
-pub struct Stream(yamux::Stream);
+pub struct Stream {
+    inner: Either<yamux012::Stream, yamux013::Stream>,
+    stats: ConnectionStats,
+    scheduler: Scheduler,
+    priority: Priority,
+    /// Whether the last attempt to write to the stream was blocked.
+    blocked_on_send: bool,
+    /// Whether the last attempt to write to the stream was blocked because the connection could
+    /// not take more data, as opposed to the send window of the stream being exhausted.
+    blocked_on_connection: bool,
+}
+
+impl Stream {
+    fn new(
+        inner: Either<yamux012::Stream, yamux013::Stream>,
+        stats: ConnectionStats,
+        scheduler: Scheduler,
+    ) -> Self {
+        stats.stream_opened();
+        Self {
+            inner,
+            stats,
+            scheduler,
+            priority: Priority::default(),
+            blocked_on_send: false,
+            blocked_on_connection: false,
+        }
+    }
+
+    /// The priority of the stream when sending data.
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    /// Sets the priority of the stream when sending data. Defaults to [`Priority::Normal`].
+    ///
+    /// Writing to the stream is held back while a stream of the same connection with a higher
+    /// priority is blocked on writing because the connection cannot take more data. A stream
+    /// that merely exhausted its own send window does not hold back other streams.
+    pub fn set_priority(&mut self, priority: Priority) {
+        if self.blocked_on_connection {
+            self.scheduler.send_blocked(priority);
+            self.scheduler.send_unblocked(self.priority);
+        }
+        self.priority = priority;
+    }
+
+    /// Tracks whether writing to the stream is blocked and the bytes buffered by the
+    /// connection, given the result of a write attempt.
+    fn track_send(
+        &mut self,
+        cx: &mut Context<'_>,
+        poll: Poll<io::Result<usize>>,
+    ) -> Poll<io::Result<usize>> {
+        if let Poll::Ready(Ok(n)) = poll {
+            self.stats.send_bytes_buffered(n);
+        }
+        match (poll.is_pending(), self.blocked_on_send) {
+            (true, false) => self.stats.send_blocked(),
+            (false, true) => self.stats.send_unblocked(),
+            _ => {}
+        }
+        self.blocked_on_send = poll.is_pending();
+
+        // Flushing a stream only waits for the connection to take more commands, thus it is
+        // pending iff the write was blocked by the connection rather than the send window.
+        let blocked_on_connection = poll.is_pending()
+            && either::for_both!(self.inner.as_mut(), s => Pin::new(s).poll_flush(cx)).is_pending();
+        match (blocked_on_connection, self.blocked_on_connection) {
+            (true, false) => self.scheduler.send_blocked(self.priority),
+            (false, true) => self.scheduler.send_unblocked(self.priority),
+            _ => {}
+        }
+        self.blocked_on_connection = blocked_on_connection;
+
+        poll
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        if self.blocked_on_send {
+            self.stats.send_unblocked();
+        }
+        if self.blocked_on_connection {
+            self.scheduler.send_unblocked(self.priority);
+        }
+        self.stats.stream_closed();
+    }
+}
 
 impl AsyncRead for Stream {
     fn poll_read(
@@ -147,7 +280,7 @@ impl AsyncRead for Stream {
         cx: &mut Context<'_>,
         buf: &mut [u8],
     ) -> Poll<io::Result<usize>> {
-        Pin::new(&mut self.0).poll_read(cx, buf)
+        either::for_both!(self.inner.as_mut(), s => Pin::new(s).poll_read(cx, buf))
     }
 
     fn poll_read_vectored(
@@ -155,7 +288,7 @@ impl AsyncRead for Stream {
         cx: &mut Context<'_>,
         bufs: &mut [IoSliceMut<'_>],
     ) -> Poll<io::Result<usize>> {
-        Pin::new(&mut self.0).poll_read_vectored(cx, bufs)
+        either::for_both!(self.inner.as_mut(), s => Pin::new(s).poll_read_vectored(cx, bufs))
     }
 }
 
@@ -165,7 +298,9 @@ impl AsyncWrite for Stream {
         cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
-        Pin::new(&mut self.0).poll_write(cx, buf)
+        ready!(self.scheduler.poll_ready(self.priority, cx));
+        let poll = either::for_both!(self.inner.as_mut(), s => Pin::new(s).poll_write(cx, buf));
+        self.track_send(cx, poll)
     }
 
     fn poll_write_vectored(
@@ -173,15 +308,18 @@ impl AsyncWrite for Stream {
         cx: &mut Context<'_>,
         bufs: &[IoSlice<'_>],
     ) -> Poll<io::Result<usize>> {
-        Pin::new(&mut self.0).poll_write_vectored(cx, bufs)
+        ready!(self.scheduler.poll_ready(self.priority, cx));
+        let poll =
+            either::for_both!(self.inner.as_mut(), s => Pin::new(s).poll_write_vectored(cx, bufs));
+        self.track_send(cx, poll)
     }
 
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        Pin::new(&mut self.0).poll_flush(cx)
+        either::for_both!(self.inner.as_mut(), s => Pin::new(s).poll_flush(cx))
     }
 
     fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        Pin::new(&mut self.0).poll_close(cx)
+        either::for_both!(self.inner.as_mut(), s => Pin::new(s).poll_close(cx))
     }
 }
 
@@ -189,27 +327,163 @@ impl<C> Muxer<C>
 where
     C: AsyncRead + AsyncWrite + Unpin + 'static,
 {
+    /// Buffers an inbound stream until it is accepted via [`StreamMuxer::poll_inbound`],
+    /// resetting a stream according to the [`InboundStreamBufferPolicy`] if the buffer is full.
+    fn buffer_inbound_stream(&mut self, inbound_stream: Stream) {
+        let mut inbound_stream = Some(inbound_stream);
+        if self.inbound_stream_buffer.len() >= self.max_buffered_inbound_streams {
+            let policy = self.inbound_stream_buffer_policy;
+            let dropped = match policy {
+                InboundStreamBufferPolicy::ResetOldest
+                    if !self.inbound_stream_buffer.is_empty() =>
+                {
+                    self.connection_stats.inbound_streams_unbuffered(1);
+                    self.inbound_stream_buffer.pop_front()
+                }
+                _ => inbound_stream.take(),
+            };
+            if let Some(stream) = dropped {
+                log::warn!("dropping {} because buffer is full", stream.inner);
+                self.connection_stats.inbound_stream_dropped();
+            }
+            self.subscribers
+                .emit(Event::InboundStreamBufferFull { policy });
+        }
+        if let Some(inbound_stream) = inbound_stream {
+            self.inbound_stream_buffer.push_back(inbound_stream);
+            self.connection_stats.inbound_stream_buffered();
+
+            if let Some(waker) = self.inbound_stream_waker.take() {
+                waker.wake()
+            }
+        }
+    }
+
     fn poll_inner(&mut self, cx: &mut Context<'_>) -> Poll<Result<Stream, Error>> {
-        let stream = ready!(self.connection.poll_next_inbound(cx))
-            .transpose()
-            .map_err(Error)?
-            .map(Stream)
-            .ok_or(Error(ConnectionError::Closed))?;
+        let stats = self.connection_stats.clone();
+        let scheduler = self.scheduler.clone();
+        let stream = match self.connection.as_mut() {
+            Either::Left(c) => ready!(c.poll_next_inbound(cx))
+                .ok_or(Error(Either::Left(yamux012::ConnectionError::Closed)))?
+                .map_err(|e| Error(Either::Left(e)))
+                .map(|s| Stream::new(Either::Left(s), stats, scheduler))?,
+            Either::Right(c) => ready!(c.poll_next_inbound(cx))
+                .ok_or(Error(Either::Right(yamux013::ConnectionError::Closed)))?
+                .map_err(|e| Error(Either::Right(e)))
+                .map(|s| Stream::new(Either::Right(s), stats, scheduler))?,
+        };
 
         Poll::Ready(Ok(stream))
     }
 }
 
 /// The yamux configuration.
+///
+/// By default, the receive window of each substream is auto-tuned based on the round-trip
+/// time of the connection and the bandwidth of the substream, allowing a single substream to
+/// exhaust the available bandwidth on high-latency and/or high-bandwidth links. Configuring a
+/// static receive window via [`Config::set_receive_window_size`],
+/// [`Config::set_max_buffer_size`] or [`Config::set_window_update_mode`], or a fixed mode via
+/// [`Config::client`] or [`Config::server`], disables auto-tuning.
 #[derive(Debug, Clone)]
 pub struct Config {
-    inner: yamux::Config,
-    mode: Option<yamux::Mode>,
+    inner: Either<Config012, Config013>,
+    /// The maximum number of substreams, if set, retained when switching between a static and
+    /// an auto-tuned receive window.
+    max_num_streams: Option<usize>,
+    /// See [`Config::set_write_timeout`].
+    write_timeout: Option<Duration>,
+    /// The configuration shared with the connections created with this configuration.
+    shared: Shared,
+}
+
+/// The configuration of a [`Config`] applying to all of its connections.
+#[derive(Debug, Clone)]
+struct Shared {
+    max_buffered_inbound_streams: usize,
+    inbound_stream_buffer_policy: InboundStreamBufferPolicy,
+    stats: Stats,
+    subscribers: Subscribers<Event>,
+}
+
+impl Default for Shared {
+    fn default() -> Self {
+        Self {
+            max_buffered_inbound_streams: MAX_BUFFERED_INBOUND_STREAMS,
+            inbound_stream_buffer_policy: InboundStreamBufferPolicy::RefuseNew,
+            stats: Stats::default(),
+            subscribers: Default::default(),
+        }
+    }
+}
+
+/// What to do with inbound substreams once the buffer of inbound substreams not yet accepted
+/// is full, see [`Config::set_max_buffered_inbound_streams`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InboundStreamBufferPolicy {
+    /// Reset the oldest buffered substream in favor of the new one.
+    ResetOldest,
+    /// Reset the new substream. The default.
+    RefuseNew,
+}
+
+/// An event of the connections created with a [`Config`], see [`Config::subscribe`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum Event {
+    /// An inbound substream was reset because the buffer of inbound substreams not yet accepted
+    /// was full, i.e. the local node is not accepting substreams as fast as the remote opens them.
+    InboundStreamBufferFull {
+        /// The policy determining which substream was reset.
+        policy: InboundStreamBufferPolicy,
+    },
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            inner: Either::Right(Config013::default()),
+            max_num_streams: None,
+            write_timeout: None,
+            shared: Shared::default(),
+        }
+    }
+}
+
+/// Configuration of a static receive window.
+#[derive(Debug, Clone)]
+struct Config012 {
+    inner: yamux012::Config,
+    mode: Option<yamux012::Mode>,
+}
+
+impl Default for Config012 {
+    fn default() -> Self {
+        let mut inner = yamux012::Config::default();
+        // For conformity with mplex, read-after-close on a multiplexed
+        // connection is never permitted and not configurable.
+        inner.set_read_after_close(false);
+        Self { inner, mode: None }
+    }
+}
+
+/// Configuration of an auto-tuned receive window.
+#[derive(Debug, Clone)]
+struct Config013(yamux013::Config);
+
+impl Default for Config013 {
+    fn default() -> Self {
+        let mut cfg = yamux013::Config::default();
+        // For conformity with mplex, read-after-close on a multiplexed
+        // connection is never permitted and not configurable.
+        cfg.set_read_after_close(false);
+        Self(cfg)
+    }
 }
 
 /// The window update mode determines when window updates are
 /// sent to the remote, giving it new credit to send more data.
-pub struct WindowUpdateMode(yamux::WindowUpdateMode);
+pub struct WindowUpdateMode(yamux012::WindowUpdateMode);
 
 impl WindowUpdateMode {
     /// The window update mode whereby the remote is given
@@ -225,7 +499,7 @@ impl WindowUpdateMode {
     /// > throughput and level of tolerance for (temporarily)
     /// > slow receivers.
     pub fn on_receive() -> Self {
-        WindowUpdateMode(yamux::WindowUpdateMode::OnReceive)
+        WindowUpdateMode(yamux012::WindowUpdateMode::OnReceive)
     }
 
     /// The window update mode whereby the remote is given new
@@ -243,62 +517,174 @@ impl WindowUpdateMode {
     /// > **Note**: With this strategy, there is usually no point in the
     /// > receive buffer being larger than the window size.
     pub fn on_read() -> Self {
-        WindowUpdateMode(yamux::WindowUpdateMode::OnRead)
+        WindowUpdateMode(yamux012::WindowUpdateMode::OnRead)
     }
 }
 
 impl Config {
     /// Creates a new `YamuxConfig` in client mode, regardless of whether
     /// it will be used for an inbound or outbound upgrade.
+    ///
+    /// Disables receive window auto-tuning.
     pub fn client() -> Self {
         Self {
-            mode: Some(yamux::Mode::Client),
-            ..Default::default()
+            inner: Either::Left(Config012 {
+                mode: Some(yamux012::Mode::Client),
+                ..Default::default()
+            }),
+            max_num_streams: None,
+            write_timeout: None,
+            shared: Shared::default(),
         }
     }
 
     /// Creates a new `YamuxConfig` in server mode, regardless of whether
     /// it will be used for an inbound or outbound upgrade.
+    ///
+    /// Disables receive window auto-tuning.
     pub fn server() -> Self {
         Self {
-            mode: Some(yamux::Mode::Server),
-            ..Default::default()
+            inner: Either::Left(Config012 {
+                mode: Some(yamux012::Mode::Server),
+                ..Default::default()
+            }),
+            max_num_streams: None,
+            write_timeout: None,
+            shared: Shared::default(),
         }
     }
 
     /// Sets the size (in bytes) of the receive window per substream.
+    ///
+    /// Disables receive window auto-tuning.
     pub fn set_receive_window_size(&mut self, num_bytes: u32) -> &mut Self {
-        self.inner.set_receive_window(num_bytes);
+        self.static_window().set_receive_window(num_bytes);
         self
     }
 
     /// Sets the maximum size (in bytes) of the receive buffer per substream.
+    ///
+    /// Disables receive window auto-tuning.
     pub fn set_max_buffer_size(&mut self, num_bytes: usize) -> &mut Self {
-        self.inner.set_max_buffer_size(num_bytes);
+        self.static_window().set_max_buffer_size(num_bytes);
         self
     }
 
     /// Sets the maximum number of concurrent substreams.
     pub fn set_max_num_streams(&mut self, num_streams: usize) -> &mut Self {
-        self.inner.set_max_num_streams(num_streams);
+        self.max_num_streams = Some(num_streams);
+        match self.inner.as_mut() {
+            Either::Left(c) => {
+                c.inner.set_max_num_streams(num_streams);
+            }
+            Either::Right(c) => {
+                c.0.set_max_num_streams(num_streams);
+            }
+        }
         self
     }
 
     /// Sets the window update mode that determines when the remote
     /// is given new credit for sending more data.
+    ///
+    /// Disables receive window auto-tuning.
     pub fn set_window_update_mode(&mut self, mode: WindowUpdateMode) -> &mut Self {
-        self.inner.set_window_update_mode(mode.0);
+        self.static_window().set_window_update_mode(mode.0);
         self
     }
-}
 
-impl Default for Config {
-    fn default() -> Self {
-        let mut inner = yamux::Config::default();
-        // For conformity with mplex, read-after-close on a multiplexed
-        // connection is never permitted and not configurable.
-        inner.set_read_after_close(false);
-        Config { inner, mode: None }
+    /// Sets the upper limit (in bytes) of the total receive window across all substreams of a
+    /// connection, or `None` for no limit, and enables receive window auto-tuning.
+    ///
+    /// The receive window of a substream starts at 256 KiB and grows up to twice the
+    /// bandwidth-delay-product of the substream, as long as the limit permits.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the limit is smaller than 256 KiB times the maximum number of substreams.
+    pub fn set_max_connection_receive_window(&mut self, num_bytes: Option<usize>) -> &mut Self {
+        self.auto_tuned_window()
+            .set_max_connection_receive_window(num_bytes);
+        self
+    }
+
+    /// Returns the flow control statistics of all connections created with this configuration
+    /// or its clones.
+    pub fn stats(&self) -> Stats {
+        self.shared.stats.clone()
+    }
+
+    /// Sets the maximum number of inbound substreams buffered while they are not accepted,
+    /// e.g. because the local node is busy. Defaults to 256.
+    pub fn set_max_buffered_inbound_streams(&mut self, num_streams: usize) -> &mut Self {
+        self.shared.max_buffered_inbound_streams = num_streams;
+        self
+    }
+
+    /// Sets the time after which the connection is closed if writing to it makes no progress,
+    /// e.g. because the remote stopped reading or vanished without closing the connection.
+    /// Disabled by default.
+    ///
+    /// This detects the loss of a connection while sending independently of a libp2p-level
+    /// ping. Note that the underlying `yamux` implementations do not allow configuring
+    /// their ping interval or the number of pending frames, e.g. `yamux` `v0.13` pings every
+    /// 10 seconds and both versions buffer up to 10 frames per substream.
+    ///
+    /// Returns [`ConfigError::ZeroWriteTimeout`] if the timeout is zero.
+    pub fn set_write_timeout(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Result<&mut Self, ConfigError> {
+        if timeout == Some(Duration::ZERO) {
+            return Err(ConfigError::ZeroWriteTimeout);
+        }
+        self.write_timeout = timeout;
+        Ok(self)
+    }
+
+    /// Sets which inbound substream is reset once the buffer of inbound substreams not yet
+    /// accepted is full. Defaults to [`InboundStreamBufferPolicy::RefuseNew`].
+    pub fn set_inbound_stream_buffer_policy(
+        &mut self,
+        policy: InboundStreamBufferPolicy,
+    ) -> &mut Self {
+        self.shared.inbound_stream_buffer_policy = policy;
+        self
+    }
+
+    /// Returns a receiver of the [`Event`]s of all connections created with this configuration
+    /// or its clones, see [`Subscribers`].
+    pub fn subscribe(&self) -> mpsc::Receiver<Event> {
+        self.shared.subscribers.subscribe()
+    }
+
+    /// Whether the receive window of substreams is auto-tuned.
+    pub fn is_receive_window_auto_tuned(&self) -> bool {
+        self.inner.is_right()
+    }
+
+    /// Returns the configuration of a static receive window, switching to one if necessary.
+    fn static_window(&mut self) -> &mut yamux012::Config {
+        if self.inner.is_right() {
+            let mut static_window = Config012::default();
+            if let Some(num_streams) = self.max_num_streams {
+                static_window.inner.set_max_num_streams(num_streams);
+            }
+            self.inner = Either::Left(static_window);
+        }
+        &mut self.inner.as_mut().unwrap_left().inner
+    }
+
+    /// Returns the configuration of an auto-tuned receive window, switching to one if necessary.
+    fn auto_tuned_window(&mut self) -> &mut yamux013::Config {
+        if self.inner.is_left() {
+            let mut auto_tuned = Config013::default();
+            if let Some(num_streams) = self.max_num_streams {
+                auto_tuned.0.set_max_num_streams(num_streams);
+            }
+            self.inner = Either::Right(auto_tuned);
+        }
+        &mut self.inner.as_mut().unwrap_right().0
     }
 }
 
@@ -320,8 +706,23 @@ where
     type Future = future::Ready<Result<Self::Output, Self::Error>>;
 
     fn upgrade_inbound(self, io: C, _: Self::Info) -> Self::Future {
-        let mode = self.mode.unwrap_or(yamux::Mode::Server);
-        future::ready(Ok(Muxer::new(io, self.inner, mode)))
+        let connection_stats = self.shared.stats.connection_opened();
+        let io = CountSent::new(
+            WriteTimeout::new(io, self.write_timeout),
+            connection_stats.clone(),
+        );
+        let connection = match self.inner {
+            Either::Left(Config012 { inner, mode }) => Either::Left(yamux012::Connection::new(
+                io,
+                inner,
+                mode.unwrap_or(yamux012::Mode::Server),
+            )),
+            Either::Right(Config013(cfg)) => {
+                Either::Right(yamux013::Connection::new(io, cfg, yamux013::Mode::Server))
+            }
+        };
+
+        future::ready(Ok(Muxer::new(connection, self.shared, connection_stats)))
     }
 }
 
@@ -334,21 +735,290 @@ where
     type Future = future::Ready<Result<Self::Output, Self::Error>>;
 
     fn upgrade_outbound(self, io: C, _: Self::Info) -> Self::Future {
-        let mode = self.mode.unwrap_or(yamux::Mode::Client);
-        future::ready(Ok(Muxer::new(io, self.inner, mode)))
+        let connection_stats = self.shared.stats.connection_opened();
+        let io = CountSent::new(
+            WriteTimeout::new(io, self.write_timeout),
+            connection_stats.clone(),
+        );
+        let connection = match self.inner {
+            Either::Left(Config012 { inner, mode }) => Either::Left(yamux012::Connection::new(
+                io,
+                inner,
+                mode.unwrap_or(yamux012::Mode::Client),
+            )),
+            Either::Right(Config013(cfg)) => {
+                Either::Right(yamux013::Connection::new(io, cfg, yamux013::Mode::Client))
+            }
+        };
+
+        future::ready(Ok(Muxer::new(connection, self.shared, connection_stats)))
     }
 }
 
+/// Error of an invalid [`Config`] setting.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ConfigError {
+    /// The write timeout is zero.
+    #[error("write timeout must be non-zero")]
+    ZeroWriteTimeout,
+}
+
 /// The Yamux [`StreamMuxer`] error type.
 #[derive(Debug, Error)]
 #[error(transparent)]
-pub struct Error(yamux::ConnectionError);
+pub struct Error(Either<yamux012::ConnectionError, yamux013::ConnectionError>);
 
 impl From<Error> for io::Error {
     fn from(err: Error) -> Self {
         match err.0 {
-            yamux::ConnectionError::Io(e) => e,
-            e => io::Error::new(io::ErrorKind::Other, e),
+            Either::Left(err) => match err {
+                yamux012::ConnectionError::Io(e) => e,
+                e => io::Error::new(io::ErrorKind::Other, e),
+            },
+            Either::Right(err) => match err {
+                yamux013::ConnectionError::Io(e) => e,
+                e => io::Error::new(io::ErrorKind::Other, e),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn receive_window_is_auto_tuned_by_default() {
+        let mut cfg = Config::default();
+        assert!(cfg.is_receive_window_auto_tuned());
+
+        cfg.set_max_num_streams(42);
+        assert!(cfg.is_receive_window_auto_tuned());
+
+        cfg.set_receive_window_size(1024 * 1024);
+        assert!(!cfg.is_receive_window_auto_tuned());
+
+        cfg.set_max_connection_receive_window(None);
+        assert!(cfg.is_receive_window_auto_tuned());
+
+        assert!(!Config::client().is_receive_window_auto_tuned());
+    }
+
+    #[test]
+    fn rejects_zero_write_timeout() {
+        let mut cfg = Config::default();
+        assert!(matches!(
+            cfg.set_write_timeout(Some(Duration::ZERO)),
+            Err(ConfigError::ZeroWriteTimeout)
+        ));
+        assert!(cfg.set_write_timeout(Some(Duration::from_secs(10))).is_ok());
+        assert_eq!(cfg.write_timeout, Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn tracks_streams_blocked_on_send() {
+        let config = Config::default();
+        let stats = config.stats();
+        let mut muxer = config
+            .upgrade_outbound(futures::io::Cursor::new(Vec::new()), "")
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+
+        let mut stream = match Pin::new(&mut muxer).poll_outbound(&mut cx) {
+            Poll::Ready(Ok(stream)) => stream,
+            _ => panic!("Failed to open stream"),
+        };
+        assert_eq!(stats.open_streams(), 1);
+
+        // The connection is never polled, thus writing eventually blocks.
+        let data = [0; 1024];
+        let mut written = 0;
+        for _ in 0..1024 {
+            match Pin::new(&mut stream).poll_write(&mut cx, &data) {
+                Poll::Ready(Ok(n)) => written += n,
+                _ => break,
+            }
+        }
+        assert_eq!(stats.send_stalls(), 1);
+        assert_eq!(stats.streams_blocked_on_send(), 1);
+        assert_eq!(stats.buffered_send_bytes(), written);
+
+        let connections = stats.connections();
+        assert_eq!(connections.len(), 1);
+        assert_eq!(connections[0].streams_blocked_on_send(), 1);
+
+        drop(stream);
+        assert_eq!(stats.open_streams(), 0);
+        assert_eq!(stats.streams_blocked_on_send(), 0);
+
+        drop(muxer);
+        assert!(stats.connections().is_empty());
+        assert_eq!(stats.send_stalls(), 1);
+    }
+
+    #[test]
+    fn holds_back_streams_of_lower_priority() {
+        let mut muxer = Config::default()
+            .upgrade_outbound(futures::io::Cursor::new(Vec::new()), "")
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        let mut open_stream = || match Pin::new(&mut muxer).poll_outbound(&mut cx) {
+            Poll::Ready(Ok(stream)) => stream,
+            _ => panic!("Failed to open stream"),
+        };
+        let mut high = open_stream();
+        let mut low = open_stream();
+        high.set_priority(Priority::High);
+        low.set_priority(Priority::Low);
+        let scheduler = high.scheduler.clone();
+
+        // The connection is never polled, thus writing eventually blocks.
+        let data = [0; 1024];
+        for _ in 0..1024 {
+            if Pin::new(&mut high).poll_write(&mut cx, &data).is_pending() {
+                break;
+            }
+        }
+        assert!(high.blocked_on_connection);
+        assert!(Pin::new(&mut low).poll_write(&mut cx, &data).is_pending());
+        assert!(!low.blocked_on_send);
+        assert!(scheduler.poll_ready(Priority::Normal, &mut cx).is_pending());
+
+        high.set_priority(Priority::Normal);
+        assert!(scheduler.poll_ready(Priority::Normal, &mut cx).is_ready());
+        assert!(scheduler.poll_ready(Priority::Low, &mut cx).is_pending());
+
+        drop(high);
+        assert!(scheduler.poll_ready(Priority::Low, &mut cx).is_ready());
+    }
+
+    #[test]
+    fn does_not_hold_back_streams_for_exhausted_send_window() {
+        let mut muxer = Config::default()
+            .upgrade_outbound(DiscardingIo, "")
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        let mut open_stream = || match Pin::new(&mut muxer).poll_outbound(&mut cx) {
+            Poll::Ready(Ok(stream)) => stream,
+            _ => panic!("Failed to open stream"),
+        };
+        let mut high = open_stream();
+        let mut low = open_stream();
+        high.set_priority(Priority::High);
+        low.set_priority(Priority::Low);
+
+        // The connection is polled after every write but the remote never grants more credit,
+        // thus writing eventually blocks on the send window of the stream.
+        let data = [0; 1024];
+        for _ in 0..1024 {
+            if Pin::new(&mut high).poll_write(&mut cx, &data).is_pending() {
+                break;
+            }
+            assert!(Pin::new(&mut muxer).poll(&mut cx).is_pending());
         }
+        assert!(high.blocked_on_send);
+        assert!(!high.blocked_on_connection);
+
+        assert!(Pin::new(&mut low).poll_write(&mut cx, &data).is_ready());
+    }
+
+    /// Connection that discards all written data and never receives any.
+    struct DiscardingIo;
+
+    impl AsyncRead for DiscardingIo {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            _: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            Poll::Pending
+        }
+    }
+
+    impl AsyncWrite for DiscardingIo {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn outbound_streams(num_streams: usize) -> Vec<Stream> {
+        let mut muxer = Config::default()
+            .upgrade_outbound(futures::io::Cursor::new(Vec::new()), "")
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        (0..num_streams)
+            .map(|_| match Pin::new(&mut muxer).poll_outbound(&mut cx) {
+                Poll::Ready(Ok(stream)) => stream,
+                _ => panic!("Failed to open stream"),
+            })
+            .collect()
+    }
+
+    fn buffered_stream_ids(policy: InboundStreamBufferPolicy) -> (Vec<String>, Vec<String>) {
+        let mut config = Config::default();
+        config
+            .set_max_buffered_inbound_streams(2)
+            .set_inbound_stream_buffer_policy(policy);
+        let stats = config.stats();
+        let mut events = config.subscribe();
+        let mut muxer = config
+            .upgrade_inbound(futures::io::Cursor::new(Vec::new()), "")
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+
+        let streams = outbound_streams(3);
+        let ids = streams.iter().map(|s| s.inner.to_string()).collect();
+        for stream in streams {
+            muxer.buffer_inbound_stream(stream);
+        }
+
+        assert_eq!(stats.buffered_inbound_streams(), 2);
+        assert_eq!(stats.dropped_inbound_streams(), 1);
+        assert!(matches!(
+            events.try_next(),
+            Ok(Some(Event::InboundStreamBufferFull { policy: p })) if p == policy
+        ));
+        assert!(events.try_next().is_err());
+
+        let buffered = muxer
+            .inbound_stream_buffer
+            .iter()
+            .map(|s| s.inner.to_string())
+            .collect();
+        (ids, buffered)
+    }
+
+    #[test]
+    fn refuses_new_inbound_streams_when_buffer_is_full() {
+        let (ids, buffered) = buffered_stream_ids(InboundStreamBufferPolicy::RefuseNew);
+        assert_eq!(buffered, ids[..2]);
+    }
+
+    #[test]
+    fn resets_oldest_inbound_stream_when_buffer_is_full() {
+        let (ids, buffered) = buffered_stream_ids(InboundStreamBufferPolicy::ResetOldest);
+        assert_eq!(buffered, ids[1..]);
     }
 }
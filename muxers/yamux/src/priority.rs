@@ -0,0 +1,94 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use std::{
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+/// The priority of a substream when sending data, see
+/// [`Stream::set_priority`](crate::Stream::set_priority).
+///
+/// `yamux` sends the frames of all substreams of a connection in the order they are written.
+/// Thus, while a substream is blocked on writing because the connection cannot take more data,
+/// substreams of a lower priority are held back, giving the blocked substream the first share
+/// of the capacity once the connection frees up. A substream blocked on its own send window is
+/// skipped, letting substreams of lower priority use the connection in the meantime. This allows
+/// control-plane substreams, e.g. identify or ping, to make progress ahead of bulk transfers
+/// sharing the connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Priority {
+    /// Held back while a substream of higher priority is blocked on writing.
+    Low,
+    /// The priority of new substreams.
+    #[default]
+    Normal,
+    /// Never held back by other substreams. Meant for substreams sending little data.
+    High,
+}
+
+impl Priority {
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Holds back writes to substreams of a connection while a substream of a higher
+/// [`Priority`] is blocked on writing because the connection cannot take more data.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Scheduler(Arc<Mutex<Inner>>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// Number of substreams blocked on writing to the connection, by priority.
+    blocked: [usize; 3],
+    /// Substreams held back until a substream of a higher priority is no longer blocked.
+    waiting: Vec<Waker>,
+}
+
+impl Scheduler {
+    /// Returns [`Poll::Pending`] if a substream with a priority higher than `priority` is blocked
+    /// on writing, waking the task once it is unblocked.
+    pub(crate) fn poll_ready(&self, priority: Priority, cx: &mut Context<'_>) -> Poll<()> {
+        let mut inner = self.0.lock().expect("lock not to be poisoned");
+        if inner.blocked[priority.index() + 1..]
+            .iter()
+            .all(|n| *n == 0)
+        {
+            return Poll::Ready(());
+        }
+        if !inner.waiting.iter().any(|w| w.will_wake(cx.waker())) {
+            inner.waiting.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+
+    pub(crate) fn send_blocked(&self, priority: Priority) {
+        self.0.lock().expect("lock not to be poisoned").blocked[priority.index()] += 1;
+    }
+
+    pub(crate) fn send_unblocked(&self, priority: Priority) {
+        let mut inner = self.0.lock().expect("lock not to be poisoned");
+        inner.blocked[priority.index()] -= 1;
+        if inner.blocked[priority.index()] == 0 {
+            inner.waiting.drain(..).for_each(Waker::wake);
+        }
+    }
+}
@@ -0,0 +1,403 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use futures::{AsyncRead, AsyncWrite};
+use std::{
+    collections::BTreeMap,
+    io::{self, IoSlice, IoSliceMut},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+};
+
+/// The size of a yamux frame header.
+const HEADER_SIZE: usize = 12;
+/// The frame type of yamux data frames, the only frames with a body.
+const TYPE_DATA: u8 = 0;
+
+/// Flow control statistics of all connections created with a [`Config`](crate::Config),
+/// allowing throughput issues to be attributed to the flow control of the muxer rather than
+/// the application.
+///
+/// Obtained via [`Config::stats`](crate::Config::stats). Cloning is cheap and all clones
+/// share the same statistics. The statistics of the individual connections are available
+/// via [`Stats::connections`].
+#[derive(Debug, Clone, Default)]
+pub struct Stats(Arc<Shared>);
+
+#[derive(Debug, Default)]
+struct Shared {
+    next_connection_id: AtomicU64,
+    /// The statistics of the open connections, by their ID.
+    connections: Mutex<BTreeMap<u64, ConnectionStats>>,
+    /// The counters of the closed connections.
+    closed: Counters,
+}
+
+/// Flow control statistics of a single connection, see [`Stats::connections`].
+#[derive(Debug, Clone)]
+pub struct ConnectionStats {
+    id: u64,
+    counters: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    open_streams: AtomicUsize,
+    send_stalls: AtomicU64,
+    streams_blocked_on_send: AtomicUsize,
+    buffered_inbound_streams: AtomicUsize,
+    dropped_inbound_streams: AtomicU64,
+    buffered_send_bytes: AtomicUsize,
+}
+
+impl Stats {
+    /// Number of currently open substreams.
+    pub fn open_streams(&self) -> usize {
+        self.sum(|c| c.open_streams())
+    }
+
+    /// Number of times writing to a substream was blocked, either because the send window of
+    /// the substream was exhausted or because the connection could not take more data.
+    pub fn send_stalls(&self) -> u64 {
+        self.0.closed.send_stalls.load(Ordering::Relaxed) + self.sum(|c| c.send_stalls())
+    }
+
+    /// Number of substreams currently blocked on writing.
+    pub fn streams_blocked_on_send(&self) -> usize {
+        self.sum(|c| c.streams_blocked_on_send())
+    }
+
+    /// Number of inbound substreams currently buffered because they have not yet been
+    /// accepted.
+    pub fn buffered_inbound_streams(&self) -> usize {
+        self.sum(|c| c.buffered_inbound_streams())
+    }
+
+    /// Number of inbound substreams dropped because too many were buffered.
+    pub fn dropped_inbound_streams(&self) -> u64 {
+        self.0
+            .closed
+            .dropped_inbound_streams
+            .load(Ordering::Relaxed)
+            + self.sum(|c| c.dropped_inbound_streams())
+    }
+
+    /// Number of bytes written to substreams that are buffered because they have not yet
+    /// been written to the connection.
+    pub fn buffered_send_bytes(&self) -> usize {
+        self.sum(|c| c.buffered_send_bytes())
+    }
+
+    /// The statistics of the currently open connections, ordered by their ID.
+    pub fn connections(&self) -> Vec<ConnectionStats> {
+        self.0
+            .connections
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    fn sum<T: std::iter::Sum<T>>(&self, f: impl Fn(&ConnectionStats) -> T) -> T {
+        self.0.connections.lock().unwrap().values().map(f).sum()
+    }
+
+    /// Registers the statistics of a new connection.
+    pub(crate) fn connection_opened(&self) -> ConnectionStats {
+        let stats = ConnectionStats {
+            id: self.0.next_connection_id.fetch_add(1, Ordering::Relaxed),
+            counters: Default::default(),
+        };
+        self.0
+            .connections
+            .lock()
+            .unwrap()
+            .insert(stats.id, stats.clone());
+
+        stats
+    }
+
+    /// Removes the statistics of a closed connection, retaining its counters.
+    pub(crate) fn connection_closed(&self, stats: &ConnectionStats) {
+        self.0.connections.lock().unwrap().remove(&stats.id);
+        self.0
+            .closed
+            .send_stalls
+            .fetch_add(stats.send_stalls(), Ordering::Relaxed);
+        self.0
+            .closed
+            .dropped_inbound_streams
+            .fetch_add(stats.dropped_inbound_streams(), Ordering::Relaxed);
+    }
+}
+
+impl ConnectionStats {
+    /// The ID of the connection, unique among the connections created with a
+    /// [`Config`](crate::Config).
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Number of currently open substreams.
+    pub fn open_streams(&self) -> usize {
+        self.counters.open_streams.load(Ordering::Relaxed)
+    }
+
+    /// Number of times writing to a substream was blocked, either because the send window of
+    /// the substream was exhausted or because the connection could not take more data.
+    pub fn send_stalls(&self) -> u64 {
+        self.counters.send_stalls.load(Ordering::Relaxed)
+    }
+
+    /// Number of substreams currently blocked on writing.
+    pub fn streams_blocked_on_send(&self) -> usize {
+        self.counters
+            .streams_blocked_on_send
+            .load(Ordering::Relaxed)
+    }
+
+    /// Number of inbound substreams currently buffered because they have not yet been
+    /// accepted.
+    pub fn buffered_inbound_streams(&self) -> usize {
+        self.counters
+            .buffered_inbound_streams
+            .load(Ordering::Relaxed)
+    }
+
+    /// Number of inbound substreams dropped because too many were buffered.
+    pub fn dropped_inbound_streams(&self) -> u64 {
+        self.counters
+            .dropped_inbound_streams
+            .load(Ordering::Relaxed)
+    }
+
+    /// Number of bytes written to substreams that are buffered because they have not yet
+    /// been written to the connection.
+    pub fn buffered_send_bytes(&self) -> usize {
+        self.counters.buffered_send_bytes.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn stream_opened(&self) {
+        self.counters.open_streams.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stream_closed(&self) {
+        self.counters.open_streams.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn send_blocked(&self) {
+        self.counters.send_stalls.fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .streams_blocked_on_send
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn send_unblocked(&self) {
+        self.counters
+            .streams_blocked_on_send
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn inbound_stream_buffered(&self) {
+        self.counters
+            .buffered_inbound_streams
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn inbound_streams_unbuffered(&self, n: usize) {
+        self.counters
+            .buffered_inbound_streams
+            .fetch_sub(n, Ordering::Relaxed);
+    }
+
+    pub(crate) fn inbound_stream_dropped(&self) {
+        self.counters
+            .dropped_inbound_streams
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn send_bytes_buffered(&self, n: usize) {
+        self.counters
+            .buffered_send_bytes
+            .fetch_add(n, Ordering::Relaxed);
+    }
+
+    fn send_bytes_written(&self, n: usize) {
+        self.counters
+            .buffered_send_bytes
+            .fetch_sub(n, Ordering::Relaxed);
+    }
+}
+
+/// Wraps the socket of a connection, tracking the data written to the substreams that is
+/// buffered by the connection by parsing the frames written to the socket.
+#[derive(Debug)]
+pub(crate) struct CountSent<C> {
+    inner: C,
+    stats: ConnectionStats,
+    /// The header of the frame currently written.
+    header: [u8; HEADER_SIZE],
+    /// The number of bytes of `header` written so far.
+    header_len: usize,
+    /// The number of data bytes of the current frame not yet written.
+    remaining_data: usize,
+}
+
+impl<C> CountSent<C> {
+    pub(crate) fn new(inner: C, stats: ConnectionStats) -> Self {
+        Self {
+            inner,
+            stats,
+            header: [0; HEADER_SIZE],
+            header_len: 0,
+            remaining_data: 0,
+        }
+    }
+
+    /// Accounts for the bytes written to the socket.
+    fn written(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            if self.remaining_data > 0 {
+                let n = self.remaining_data.min(bytes.len());
+                self.stats.send_bytes_written(n);
+                self.remaining_data -= n;
+                bytes = &bytes[n..];
+                continue;
+            }
+
+            let n = (HEADER_SIZE - self.header_len).min(bytes.len());
+            self.header[self.header_len..][..n].copy_from_slice(&bytes[..n]);
+            self.header_len += n;
+            bytes = &bytes[n..];
+
+            if self.header_len == HEADER_SIZE {
+                self.header_len = 0;
+                if self.header[1] == TYPE_DATA {
+                    let len = u32::from_be_bytes(self.header[8..].try_into().expect("4 bytes"));
+                    self.remaining_data = len as usize;
+                }
+            }
+        }
+    }
+}
+
+impl<C: AsyncRead + Unpin> AsyncRead for CountSent<C> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+
+    fn poll_read_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &mut [IoSliceMut<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_read_vectored(cx, bufs)
+    }
+}
+
+impl<C: AsyncWrite + Unpin> AsyncWrite for CountSent<C> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll {
+            self.written(&buf[..n]);
+        }
+        poll
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write_vectored(cx, bufs);
+        if let Poll::Ready(Ok(mut n)) = poll {
+            for buf in bufs {
+                let k = n.min(buf.len());
+                self.written(&buf[..k]);
+                n -= k;
+            }
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_data_frames_written_in_pieces() {
+        let stats = Stats::default();
+        let connection = stats.connection_opened();
+        connection.send_bytes_buffered(5);
+
+        let mut frames = Vec::new();
+        // A window update frame, whose length is not followed by a body.
+        frames.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 1, 0, 0, 1, 0]);
+        // A data frame with a body of 5 bytes.
+        frames.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 5]);
+        frames.extend_from_slice(b"hello");
+
+        let mut io = CountSent::new((), connection.clone());
+        for chunk in frames.chunks(7) {
+            io.written(chunk);
+        }
+
+        assert_eq!(connection.buffered_send_bytes(), 0);
+        assert_eq!(io.header_len, 0);
+        assert_eq!(io.remaining_data, 0);
+    }
+
+    #[test]
+    fn retains_counters_of_closed_connections() {
+        let stats = Stats::default();
+        let connection = stats.connection_opened();
+        connection.stream_opened();
+        connection.send_blocked();
+        assert_eq!(stats.connections().len(), 1);
+
+        stats.connection_closed(&connection);
+
+        assert!(stats.connections().is_empty());
+        assert_eq!(stats.open_streams(), 0);
+        assert_eq!(stats.send_stalls(), 1);
+    }
+}
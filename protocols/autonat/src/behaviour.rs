@@ -85,6 +85,8 @@ pub struct Config {
     /// private ip address. Note that this does not apply for servers that are added via
     /// [`Behaviour::add_server`].
     pub only_global_ips: bool,
+    /// Whether dial-back requests of other peers are served.
+    pub server_mode: ServerMode,
 }
 
 impl Default for Config {
@@ -102,10 +104,24 @@ impl Default for Config {
             throttle_clients_peer_max: 3,
             throttle_clients_period: Duration::from_secs(1),
             only_global_ips: true,
+            server_mode: ServerMode::Enabled,
         }
     }
 }
 
+/// Whether the [`Behaviour`] serves dial-back requests of other peers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerMode {
+    /// Serve dial-back requests only while we are assumed to be publicly reachable, i.e. while
+    /// the [`NatStatus`] is [`NatStatus::Public`]. Peers behind a NAT can't dial back addresses
+    /// that a peer on the public internet could not reach, thus their results would be useless.
+    Auto,
+    /// Always serve dial-back requests. This is the default.
+    Enabled,
+    /// Never serve dial-back requests.
+    Disabled,
+}
+
 /// Assumed NAT status.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum NatStatus {
@@ -120,6 +136,29 @@ impl NatStatus {
     }
 }
 
+/// Reachability of a single address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reachability {
+    /// A server dialed us at the address.
+    Public,
+    /// A server failed to dial us at the address.
+    Private,
+}
+
+/// Assumed reachability of a single address, see [`Behaviour::address_status`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressStatus {
+    /// Assumed reachability of the address.
+    pub reachability: Reachability,
+    /// Confidence in the assumed reachability, up to [`Config::confidence_max`].
+    /// Like for the [`NatStatus`], it increases with each probe confirming the reachability and
+    /// decreases with each probe reporting a different one, which flips the reachability once the
+    /// confidence is 0.
+    pub confidence: usize,
+    /// Time of the last probe that tested the address.
+    pub last_probe: Instant,
+}
+
 /// Unique identifier for a probe.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ProbeId(usize);
@@ -132,6 +171,19 @@ impl ProbeId {
     }
 }
 
+/// NAT status reported by the response to an outbound probe.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusReport {
+    /// Id of the outbound probe the response belongs to.
+    pub probe_id: ProbeId,
+    /// Id of the peer that sent the response.
+    pub peer: PeerId,
+    /// The address at which the remote succeeded to dial us, or the reason why it did not.
+    pub result: Result<Multiaddr, ResponseError>,
+    /// Confidence in the assumed NAT status after the response was taken into account.
+    pub confidence: usize,
+}
+
 /// Event produced by [`Behaviour`].
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Event {
@@ -145,6 +197,9 @@ pub enum Event {
         old: NatStatus,
         /// New status.
         new: NatStatus,
+        /// The responses that were taken into account since the former status was assumed, oldest
+        /// first and limited to the 16 most recent ones. The last one flipped the status.
+        reports: Vec<StatusReport>,
     },
 }
 
@@ -161,6 +216,8 @@ pub enum Event {
 /// enough confidence in the assumed NAT status was reached or not.
 /// The confidence increases each time a probe confirms the assumed status, and decreases if a different status is reported.
 /// If the confidence is 0, the status is flipped and the Behaviour will report the new status in an `OutEvent`.
+/// Whether dial-back requests of other peers are served, e.g. only while the local peer is assumed to be public, is
+/// configured via [`Config::server_mode`].
 pub struct Behaviour {
     // Local peer id
     local_peer_id: PeerId,
@@ -179,6 +236,9 @@ pub struct Behaviour {
     // Confidence in the assumed NAT status.
     confidence: usize,
 
+    // Responses taken into account since the assumed NAT status last changed.
+    status_reports: VecDeque<StatusReport>,
+
     // Timer for the next probe.
     schedule_probe: Delay,
 
@@ -193,8 +253,12 @@ pub struct Behaviour {
         ),
     >,
 
-    // Ongoing outbound probes and mapped to the inner request id.
-    ongoing_outbound: HashMap<RequestId, ProbeId>,
+    // Ongoing outbound probes with the addresses sent for dialing back, mapped to the inner
+    // request id.
+    ongoing_outbound: HashMap<RequestId, (ProbeId, Vec<Multiaddr>)>,
+
+    // Assumed reachability of each address that was tested by a probe.
+    address_statuses: HashMap<Multiaddr, AddressStatus>,
 
     // Connected peers with the observed address of each connection.
     // If the endpoint of a connection is relayed or not global (in case of Config::only_global_ips),
@@ -231,9 +295,11 @@ impl Behaviour {
             servers: HashSet::new(),
             ongoing_inbound: HashMap::default(),
             ongoing_outbound: HashMap::default(),
+            address_statuses: HashMap::default(),
             connected: HashMap::default(),
             nat_status: NatStatus::Unknown,
             confidence: 0,
+            status_reports: VecDeque::new(),
             throttled_servers: Vec::new(),
             throttled_clients: Vec::new(),
             last_probe: None,
@@ -263,6 +329,28 @@ impl Behaviour {
         self.confidence
     }
 
+    /// Whether dial-back requests of other peers are currently served.
+    /// See [`Config::server_mode`].
+    pub fn is_server_enabled(&self) -> bool {
+        is_server_enabled(self.config.server_mode, &self.nat_status)
+    }
+
+    /// Assumed reachability of an address.
+    /// Returns `None` if the address was not tested by any probe yet.
+    ///
+    /// Other than the [`NatStatus`], which is based on the outcome of the probes as a whole, this
+    /// tracks each address on its own: The address a server dialed us at is assumed public, all
+    /// addresses sent in a probe are assumed private if the server could not dial any of them.
+    pub fn address_status(&self, address: &Multiaddr) -> Option<&AddressStatus> {
+        self.address_statuses.get(address)
+    }
+
+    /// Assumed reachability of all addresses that were tested by a probe.
+    /// See [`Behaviour::address_status`] for more info.
+    pub fn address_statuses(&self) -> impl Iterator<Item = (&Multiaddr, &AddressStatus)> {
+        self.address_statuses.iter()
+    }
+
     /// Add a peer to the list over servers that may be used for probes.
     /// These peers are used for dial-request even if they are currently not connection, in which case a connection will be
     /// establish before sending the dial-request.
@@ -296,7 +384,9 @@ impl Behaviour {
             throttled_servers: &mut self.throttled_servers,
             nat_status: &mut self.nat_status,
             confidence: &mut self.confidence,
+            status_reports: &mut self.status_reports,
             ongoing_outbound: &mut self.ongoing_outbound,
+            address_statuses: &mut self.address_statuses,
             last_probe: &mut self.last_probe,
             schedule_probe: &mut self.schedule_probe,
             listen_addresses: &self.listen_addresses,
@@ -312,6 +402,7 @@ impl Behaviour {
             probe_id: &mut self.probe_id,
             throttled_clients: &mut self.throttled_clients,
             ongoing_inbound: &mut self.ongoing_inbound,
+            nat_status: &self.nat_status,
         }
     }
 
@@ -614,7 +705,7 @@ trait HandleInnerEvent {
     ) -> VecDeque<Action>;
 }
 
-trait GlobalIp {
+pub(crate) trait GlobalIp {
     fn is_global_ip(&self) -> bool;
 }
 
@@ -729,3 +820,11 @@ impl GlobalIp for std::net::Ipv6Addr {
         }
     }
 }
+
+fn is_server_enabled(mode: ServerMode, nat_status: &NatStatus) -> bool {
+    match mode {
+        ServerMode::Auto => nat_status.is_public(),
+        ServerMode::Enabled => true,
+        ServerMode::Disabled => false,
+    }
+}
@@ -21,8 +21,8 @@
 use crate::ResponseError;
 
 use super::{
-    Action, AutoNatCodec, Config, DialRequest, DialResponse, Event, HandleInnerEvent, NatStatus,
-    ProbeId,
+    Action, AddressStatus, AutoNatCodec, Config, DialRequest, DialResponse, Event,
+    HandleInnerEvent, NatStatus, ProbeId, Reachability, StatusReport,
 };
 use futures::FutureExt;
 use futures_timer::Delay;
@@ -38,6 +38,9 @@ use std::{
     time::Duration,
 };
 
+/// Max number of responses reported in [`Event::StatusChanged`].
+const MAX_STATUS_REPORTS: usize = 16;
+
 /// Outbound probe failed or was aborted.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum OutboundProbeError {
@@ -91,7 +94,9 @@ pub(crate) struct AsClient<'a> {
     pub(crate) throttled_servers: &'a mut Vec<(PeerId, Instant)>,
     pub(crate) nat_status: &'a mut NatStatus,
     pub(crate) confidence: &'a mut usize,
-    pub(crate) ongoing_outbound: &'a mut HashMap<RequestId, ProbeId>,
+    pub(crate) status_reports: &'a mut VecDeque<StatusReport>,
+    pub(crate) ongoing_outbound: &'a mut HashMap<RequestId, (ProbeId, Vec<Multiaddr>)>,
+    pub(crate) address_statuses: &'a mut HashMap<Multiaddr, AddressStatus>,
     pub(crate) last_probe: &'a mut Option<Instant>,
     pub(crate) schedule_probe: &'a mut Delay,
     pub(crate) listen_addresses: &'a ListenAddresses,
@@ -115,11 +120,21 @@ impl<'a> HandleInnerEvent for AsClient<'a> {
             } => {
                 log::debug!("Outbound dial-back request returned {:?}.", response);
 
-                let probe_id = self
+                let (probe_id, addresses) = self
                     .ongoing_outbound
                     .remove(&request_id)
                     .expect("RequestId exists.");
 
+                match &response.result {
+                    Ok(address) => self.handle_reported_reachability(address, Reachability::Public),
+                    Err(ResponseError::DialError) => {
+                        for address in &addresses {
+                            self.handle_reported_reachability(address, Reachability::Private);
+                        }
+                    }
+                    Err(_) => {}
+                }
+
                 let event = match response.result.clone() {
                     Ok(address) => OutboundProbeEvent::Response {
                         probe_id,
@@ -137,10 +152,25 @@ impl<'a> HandleInnerEvent for AsClient<'a> {
 
                 actions.push_back(ToSwarm::GenerateEvent(Event::OutboundProbe(event)));
 
-                if let Some(old) = self.handle_reported_status(response.result.clone().into()) {
+                let reported_status: NatStatus = response.result.clone().into();
+                let is_known = !matches!(reported_status, NatStatus::Unknown);
+                let flipped = self.handle_reported_status(reported_status);
+                if is_known {
+                    if self.status_reports.len() == MAX_STATUS_REPORTS {
+                        self.status_reports.pop_front();
+                    }
+                    self.status_reports.push_back(StatusReport {
+                        probe_id,
+                        peer,
+                        result: response.result.clone(),
+                        confidence: *self.confidence,
+                    });
+                }
+                if let Some(old) = flipped {
                     actions.push_back(ToSwarm::GenerateEvent(Event::StatusChanged {
                         old,
                         new: self.nat_status.clone(),
+                        reports: self.status_reports.drain(..).collect(),
                     }));
                 }
 
@@ -163,6 +193,7 @@ impl<'a> HandleInnerEvent for AsClient<'a> {
                 let probe_id = self
                     .ongoing_outbound
                     .remove(&request_id)
+                    .map(|(probe_id, _)| probe_id)
                     .unwrap_or_else(|| self.probe_id.next());
 
                 self.schedule_probe.reset(Duration::ZERO);
@@ -233,10 +264,12 @@ impl<'a> AsClient<'a> {
     }
 
     pub(crate) fn on_expired_address(&mut self, addr: &Multiaddr) {
+        self.address_statuses.remove(addr);
         if let NatStatus::Public(public_address) = self.nat_status {
             if public_address == addr {
                 *self.confidence = 0;
                 *self.nat_status = NatStatus::Unknown;
+                self.status_reports.clear();
                 self.schedule_next_probe(Duration::ZERO);
             }
         }
@@ -290,12 +323,13 @@ impl<'a> AsClient<'a> {
             &server,
             DialRequest {
                 peer_id: self.local_peer_id,
-                addresses,
+                addresses: addresses.clone(),
             },
         );
         self.throttled_servers.push((server, Instant::now()));
         log::debug!("Send dial-back request to peer {}.", server);
-        self.ongoing_outbound.insert(request_id, probe_id);
+        self.ongoing_outbound
+            .insert(request_id, (probe_id, addresses));
         Ok(server)
     }
 
@@ -356,6 +390,40 @@ impl<'a> AsClient<'a> {
 
         Some(old_status)
     }
+
+    // Adapt the confidence and reachability of a single address to the reachability reported by
+    // the latest probe, in the same way as for the NAT status.
+    fn handle_reported_reachability(&mut self, address: &Multiaddr, reported: Reachability) {
+        let now = Instant::now();
+        let Some(status) = self.address_statuses.get_mut(address) else {
+            self.address_statuses.insert(
+                address.clone(),
+                AddressStatus {
+                    reachability: reported,
+                    confidence: 0,
+                    last_probe: now,
+                },
+            );
+            return;
+        };
+        status.last_probe = now;
+
+        if status.reachability == reported {
+            if status.confidence < self.config.confidence_max {
+                status.confidence += 1;
+            }
+        } else if status.confidence > 0 {
+            status.confidence -= 1;
+        } else {
+            log::debug!(
+                "Flipped assumed reachability of {} from {:?} to {:?}",
+                address,
+                status.reachability,
+                reported
+            );
+            status.reachability = reported;
+        }
+    }
 }
 
 impl From<Result<Multiaddr, ResponseError>> for NatStatus {
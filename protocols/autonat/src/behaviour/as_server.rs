@@ -19,8 +19,8 @@
 // DEALINGS IN THE SOFTWARE.
 
 use super::{
-    Action, AutoNatCodec, Config, DialRequest, DialResponse, Event, HandleInnerEvent, ProbeId,
-    ResponseError,
+    Action, AutoNatCodec, Config, DialRequest, DialResponse, Event, HandleInnerEvent, NatStatus,
+    ProbeId, ResponseError,
 };
 use instant::Instant;
 use libp2p_core::{multiaddr::Protocol, Multiaddr};
@@ -90,6 +90,7 @@ pub(crate) struct AsServer<'a> {
             ResponseChannel<DialResponse>,
         ),
     >,
+    pub(crate) nat_status: &'a NatStatus,
 }
 
 impl<'a> HandleInnerEvent for AsServer<'a> {
@@ -264,6 +265,11 @@ impl<'a> AsServer<'a> {
         });
         self.throttled_clients.drain(..i);
 
+        if !super::is_server_enabled(self.config.server_mode, self.nat_status) {
+            let status_text = "server mode disabled".to_string();
+            return Err((status_text, ResponseError::DialRefused));
+        }
+
         if request.peer_id != sender {
             let status_text = "peer id mismatch".to_string();
             return Err((status_text, ResponseError::BadRequest));
@@ -24,11 +24,12 @@
 
 mod behaviour;
 mod protocol;
+pub mod v2;
 
 pub use self::{
     behaviour::{
-        Behaviour, Config, Event, InboundProbeError, InboundProbeEvent, NatStatus,
-        OutboundProbeError, OutboundProbeEvent, ProbeId,
+        AddressStatus, Behaviour, Config, Event, InboundProbeError, InboundProbeEvent, NatStatus,
+        OutboundProbeError, OutboundProbeEvent, ProbeId, Reachability, ServerMode, StatusReport,
     },
     protocol::{ResponseError, DEFAULT_PROTOCOL_NAME},
 };
@@ -0,0 +1,61 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Implementation of the [AutoNAT v2](https://github.com/libp2p/specs/blob/master/autonat/autonat-v2.md) protocol.
+//!
+//! Contrary to v1, v2 determines the reachability of each address on its own. A
+//! [`client::Behaviour`] asks a server to dial back a single address and includes a random nonce
+//! in its request. The server dials the address on a new connection and sends the nonce back,
+//! so that the client can attribute the dial-back to its request. The client only verifies the
+//! nonce and trusts the server to have dialed the requested address: the local address of the
+//! dial-back connection generally differs from the tested address, as NATs translate it.
+//!
+//! To prevent servers from being abused for amplification attacks, a server requires the client
+//! to send it between 30 and 100 kB of data before it dials an address whose IP differs from the
+//! one it observes the client at.
+//!
+//! The v2 behaviours are independent of the v1 [`Behaviour`](crate::Behaviour) and may be used
+//! alongside it.
+
+pub mod client;
+mod protocol;
+pub mod server;
+
+use libp2p_swarm::StreamProtocol;
+
+/// The protocol name of the stream on which a client requests a dial-back.
+pub const DIAL_REQUEST_PROTOCOL: StreamProtocol =
+    StreamProtocol::new("/libp2p/autonat/2/dial-request");
+
+/// The protocol name of the stream on which a server sends the nonce back to the client.
+pub const DIAL_BACK_PROTOCOL: StreamProtocol = StreamProtocol::new("/libp2p/autonat/2/dial-back");
+
+mod proto {
+    #![allow(unreachable_pub)]
+    include!("v2/generated/mod.rs");
+    pub(crate) use self::autonatv2::{
+        mod_DialBackResponse::DialBackStatus, mod_DialResponse::ResponseStatus, DialBack,
+        DialBackResponse, DialDataRequest, DialDataResponse, DialRequest, DialResponse, DialStatus,
+        Message,
+    };
+}
+
+/// Random value included in a dial request that the server has to send back on the dial-back.
+type Nonce = u64;
@@ -0,0 +1,490 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The client side of AutoNAT v2, testing the reachability of our own addresses.
+
+mod handler;
+
+use super::{protocol::DialRequest, Nonce};
+use futures::FutureExt;
+use futures_timer::Delay;
+use handler::Handler;
+use libp2p_core::{Endpoint, Multiaddr};
+use libp2p_identity::PeerId;
+use libp2p_swarm::{
+    behaviour::{
+        ConnectionClosed, ConnectionEstablished, ExpiredListenAddr, ExternalAddrExpired, FromSwarm,
+        NewExternalAddrCandidate, NewListenAddr,
+    },
+    ConnectionDenied, ConnectionId, NetworkBehaviour, NotifyHandler, PollParameters, THandler,
+    THandlerInEvent, THandlerOutEvent, ToSwarm,
+};
+use rand::seq::SliceRandom;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// Config for the [`Behaviour`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// Interval in which untested address candidates are sent to servers for testing.
+    pub probe_interval: Duration,
+    /// Max number of untested address candidates. If more candidates are reported, the ones
+    /// reported least often are dropped.
+    pub max_candidates: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            probe_interval: Duration::from_secs(5),
+            max_candidates: 10,
+        }
+    }
+}
+
+/// Identifier of a test started through [`Behaviour::probe_address`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProbeId(u64);
+
+impl ProbeId {
+    fn next(&mut self) -> ProbeId {
+        let current = *self;
+        self.0 += 1;
+        current
+    }
+}
+
+/// The result of testing one of our addresses.
+#[derive(Debug)]
+pub struct Event {
+    /// The address that was tested.
+    pub tested_addr: Multiaddr,
+    /// The id returned by [`Behaviour::probe_address`] if the test was requested explicitly,
+    /// `None` for tests of address candidates.
+    pub probe_id: Option<ProbeId>,
+    /// The server that was asked to test the address.
+    pub server: PeerId,
+    /// Amount of data that was sent to the server as part of the amplification protection.
+    pub bytes_sent: usize,
+    /// `Ok` if the server reached us at the tested address.
+    pub result: Result<(), Error>,
+}
+
+/// Testing an address failed.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The server could not dial the address.
+    #[error("server failed to dial the address")]
+    Unreachable,
+    /// The server reached the address, but failed to send the dial-back.
+    #[error("server failed to send the dial-back")]
+    DialBackFailed,
+    /// The server reported a successful dial-back, but we never received the nonce.
+    #[error("server reported a dial-back that was never received")]
+    MissingDialBack,
+    /// The server does not support AutoNAT v2.
+    #[error("server does not support autonat v2")]
+    UnsupportedProtocol,
+    /// The server rejected the request.
+    #[error("server rejected the dial request")]
+    Rejected,
+    /// The server refused to dial the address.
+    #[error("server refused to dial the address")]
+    DialRefused,
+    /// The server failed with an internal error.
+    #[error("server reported an internal error")]
+    InternalServerError,
+    /// The server requested more data than allowed by the protocol, in total over all its data
+    /// requests for the dial request.
+    #[error("server requested {0} bytes of dial data")]
+    DataRequestTooLarge(usize),
+    /// The server sent a malformed response.
+    #[error("invalid response from server")]
+    InvalidResponse,
+    /// The stream to the server failed or timed out.
+    #[error("dial request stream failed: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// [`Behaviour::probe_address`] failed because none of the connected peers supports the server
+/// side of the protocol.
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("no connected peer supports the server side of autonat v2")]
+pub struct NoServer;
+
+impl Error {
+    // Whether the error tells us something about the reachability of the tested address, or only
+    // about the server.
+    fn is_conclusive(&self) -> bool {
+        matches!(
+            self,
+            Error::Unreachable | Error::DialBackFailed | Error::MissingDialBack
+        )
+    }
+}
+
+/// [`NetworkBehaviour`] for the client side of AutoNAT v2.
+///
+/// Our listen addresses and the external address candidates reported by other behaviours are
+/// tested in a frequency of [`Config::probe_interval`]. Each address is sent to a randomly selected
+/// connected peer that supports the server side of the protocol. An address that the server
+/// reached us at is reported to the swarm as confirmed external address.
+///
+/// Addresses for which no conclusive result could be obtained, e.g. because the server refused to
+/// dial them, are retried with another server.
+///
+/// Independently of the candidates, a specific address can be tested with
+/// [`Behaviour::probe_address`].
+pub struct Behaviour {
+    config: Config,
+
+    // Timer for the next probe.
+    schedule_probe: Delay,
+
+    // Untested addresses with the number of times they were reported.
+    address_candidates: HashMap<Multiaddr, usize>,
+
+    // Connected peers with their connections, oldest first.
+    connected: HashMap<PeerId, Vec<ConnectionId>>,
+
+    // Connected peers that don't support the server side of the protocol.
+    unsupported: HashSet<PeerId>,
+
+    // Ongoing tests by the nonce included in the dial request.
+    ongoing: HashMap<Nonce, Probe>,
+
+    next_probe_id: ProbeId,
+
+    pending_events: VecDeque<ToSwarm<Event, THandlerInEvent<Self>>>,
+}
+
+struct Probe {
+    addr: Multiaddr,
+    // Number of times the address was reported as candidate.
+    score: usize,
+    server: PeerId,
+    connection: ConnectionId,
+    dial_back_received: bool,
+    // Set if the test was requested through `Behaviour::probe_address`.
+    probe_id: Option<ProbeId>,
+}
+
+impl Behaviour {
+    pub fn new(config: Config) -> Self {
+        Self {
+            schedule_probe: Delay::new(config.probe_interval),
+            config,
+            address_candidates: HashMap::new(),
+            connected: HashMap::new(),
+            unsupported: HashSet::new(),
+            ongoing: HashMap::new(),
+            next_probe_id: ProbeId(0),
+            pending_events: VecDeque::new(),
+        }
+    }
+
+    /// Tests whether we are reachable at `addr`.
+    ///
+    /// The address is sent on its own to a randomly selected server, without waiting for
+    /// [`Config::probe_interval`]. The result is reported as [`Event`] with the returned
+    /// [`ProbeId`]. Contrary to address candidates, a reachable address is not reported to the
+    /// swarm as confirmed external address, and an inconclusive test is not retried. This allows
+    /// verifying an address before advertising it.
+    ///
+    /// Fails if none of the connected peers supports the server side of the protocol.
+    pub fn probe_address(&mut self, addr: Multiaddr) -> Result<ProbeId, NoServer> {
+        let (server, connection) = *self
+            .servers()
+            .choose(&mut rand::thread_rng())
+            .ok_or(NoServer)?;
+        let probe_id = self.next_probe_id.next();
+        self.send_dial_request(addr, 0, server, connection, Some(probe_id));
+
+        Ok(probe_id)
+    }
+
+    // Connected peers that may support the server side of the protocol, each with its oldest
+    // connection. Newer connections may be dial-backs that are closed by the server soon.
+    fn servers(&self) -> Vec<(PeerId, ConnectionId)> {
+        self.connected
+            .iter()
+            .filter(|(peer, _)| !self.unsupported.contains(peer))
+            .map(|(peer, connections)| {
+                let connection = *connections
+                    .first()
+                    .expect("connected peer to have a connection");
+                (*peer, connection)
+            })
+            .collect()
+    }
+
+    fn add_candidate(&mut self, addr: Multiaddr, score: usize) {
+        if self
+            .ongoing
+            .values()
+            .any(|probe| probe.probe_id.is_none() && probe.addr == addr)
+        {
+            return;
+        }
+        *self.address_candidates.entry(addr).or_default() += score;
+
+        while self.address_candidates.len() > self.config.max_candidates {
+            let least_reported = self
+                .address_candidates
+                .iter()
+                .min_by_key(|(_, score)| **score)
+                .map(|(addr, _)| addr.clone())
+                .expect("candidates not to be empty");
+            self.address_candidates.remove(&least_reported);
+        }
+    }
+
+    // Send each untested candidate to a randomly selected server.
+    fn probe_candidates(&mut self) {
+        let servers = self.servers();
+        if servers.is_empty() {
+            if !self.address_candidates.is_empty() {
+                log::debug!("Not testing address candidates: No qualified server.");
+            }
+            return;
+        }
+
+        let mut rng = rand::thread_rng();
+        let candidates: Vec<_> = self.address_candidates.drain().collect();
+        for (addr, score) in candidates {
+            let (server, connection) = *servers.choose(&mut rng).expect("servers not to be empty");
+            self.send_dial_request(addr, score, server, connection, None);
+        }
+    }
+
+    fn send_dial_request(
+        &mut self,
+        addr: Multiaddr,
+        score: usize,
+        server: PeerId,
+        connection: ConnectionId,
+        probe_id: Option<ProbeId>,
+    ) {
+        let nonce = rand::random();
+
+        log::debug!("Send dial request for {} to peer {}.", addr, server);
+        self.pending_events.push_back(ToSwarm::NotifyHandler {
+            peer_id: server,
+            handler: NotifyHandler::One(connection),
+            event: DialRequest {
+                addrs: vec![addr.clone()],
+                nonce,
+            },
+        });
+        self.ongoing.insert(
+            nonce,
+            Probe {
+                addr,
+                score,
+                server,
+                connection,
+                dial_back_received: false,
+                probe_id,
+            },
+        );
+    }
+
+    fn on_test_outcome(&mut self, nonce: Nonce, bytes_sent: usize, result: Result<(), Error>) {
+        let Some(probe) = self.ongoing.remove(&nonce) else {
+            return;
+        };
+
+        let result = match result {
+            Ok(()) if !probe.dial_back_received => Err(Error::MissingDialBack),
+            result => result,
+        };
+        match &result {
+            Ok(()) => {
+                log::debug!("Address {} was confirmed by {}.", probe.addr, probe.server);
+                if probe.probe_id.is_none() {
+                    self.pending_events
+                        .push_back(ToSwarm::ExternalAddrConfirmed(probe.addr.clone()));
+                }
+            }
+            Err(error) => {
+                log::debug!(
+                    "Testing address {} with {} failed: {}.",
+                    probe.addr,
+                    probe.server,
+                    error
+                );
+                if matches!(error, Error::UnsupportedProtocol) {
+                    self.unsupported.insert(probe.server);
+                }
+                if !error.is_conclusive() && probe.probe_id.is_none() {
+                    self.add_candidate(probe.addr.clone(), probe.score);
+                }
+            }
+        }
+
+        self.pending_events.push_back(ToSwarm::GenerateEvent(Event {
+            tested_addr: probe.addr,
+            probe_id: probe.probe_id,
+            server: probe.server,
+            bytes_sent,
+            result,
+        }));
+    }
+
+    fn on_connection_closed(
+        &mut self,
+        ConnectionClosed {
+            peer_id,
+            connection_id,
+            remaining_established,
+            ..
+        }: ConnectionClosed<<Self as NetworkBehaviour>::ConnectionHandler>,
+    ) {
+        if remaining_established == 0 {
+            self.connected.remove(&peer_id);
+            self.unsupported.remove(&peer_id);
+        } else if let Some(connections) = self.connected.get_mut(&peer_id) {
+            connections.retain(|c| *c != connection_id);
+        }
+
+        let aborted: Vec<Nonce> = self
+            .ongoing
+            .iter()
+            .filter(|(_, probe)| probe.connection == connection_id)
+            .map(|(nonce, _)| *nonce)
+            .collect();
+        for nonce in aborted {
+            self.on_test_outcome(
+                nonce,
+                0,
+                Err(Error::Io(io::ErrorKind::ConnectionAborted.into())),
+            );
+        }
+    }
+}
+
+impl Default for Behaviour {
+    fn default() -> Self {
+        Self::new(Config::default())
+    }
+}
+
+impl NetworkBehaviour for Behaviour {
+    type ConnectionHandler = Handler;
+    type ToSwarm = Event;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: PeerId,
+        _: &Multiaddr,
+        _: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(Handler::new())
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: PeerId,
+        _: &Multiaddr,
+        _: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(Handler::new())
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm<Self::ConnectionHandler>) {
+        match event {
+            FromSwarm::ConnectionEstablished(ConnectionEstablished {
+                peer_id,
+                connection_id,
+                ..
+            }) => {
+                self.connected
+                    .entry(peer_id)
+                    .or_default()
+                    .push(connection_id);
+            }
+            FromSwarm::ConnectionClosed(connection_closed) => {
+                self.on_connection_closed(connection_closed)
+            }
+            FromSwarm::NewListenAddr(NewListenAddr { addr, .. }) => {
+                self.add_candidate(addr.clone(), 1);
+            }
+            FromSwarm::NewExternalAddrCandidate(NewExternalAddrCandidate { addr }) => {
+                self.add_candidate(addr.clone(), 1);
+            }
+            FromSwarm::ExpiredListenAddr(ExpiredListenAddr { addr, .. })
+            | FromSwarm::ExternalAddrExpired(ExternalAddrExpired { addr }) => {
+                self.address_candidates.remove(addr);
+            }
+            FromSwarm::AddressChange(_)
+            | FromSwarm::DialFailure(_)
+            | FromSwarm::ListenFailure(_)
+            | FromSwarm::NewListener(_)
+            | FromSwarm::ListenerError(_)
+            | FromSwarm::ListenerClosed(_)
+            | FromSwarm::ExternalAddrConfirmed(_) => {}
+        }
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        _: PeerId,
+        _: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {
+            handler::ToBehaviour::TestOutcome {
+                nonce,
+                bytes_sent,
+                result,
+            } => self.on_test_outcome(nonce, bytes_sent, result),
+            handler::ToBehaviour::DialBack(nonce) => match self.ongoing.get_mut(&nonce) {
+                Some(probe) => probe.dial_back_received = true,
+                None => log::debug!("Received dial-back with unknown nonce."),
+            },
+        }
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+        _: &mut impl PollParameters,
+    ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        loop {
+            if let Some(event) = self.pending_events.pop_front() {
+                return Poll::Ready(event);
+            }
+
+            if self.schedule_probe.poll_unpin(cx).is_ready() {
+                self.schedule_probe.reset(self.config.probe_interval);
+                self.probe_candidates();
+                continue;
+            }
+
+            return Poll::Pending;
+        }
+    }
+}
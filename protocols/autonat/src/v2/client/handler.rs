@@ -0,0 +1,314 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use super::Error;
+use crate::v2::{
+    proto,
+    protocol::{
+        self, DialDataRequest, DialDataResponse, DialRequest, DialResponse, Request, Response,
+        DATA_FIELD_LEN_UPPER_BOUND, DATA_LEN_UPPER_BOUND,
+    },
+    Nonce, DIAL_BACK_PROTOCOL, DIAL_REQUEST_PROTOCOL,
+};
+use futures::{
+    future::{self, BoxFuture, Either},
+    stream::FuturesUnordered,
+    FutureExt, StreamExt,
+};
+use futures_timer::Delay;
+use instant::Instant;
+use libp2p_core::upgrade::ReadyUpgrade;
+use libp2p_swarm::{
+    handler::{
+        ConnectionEvent, DialUpgradeError, FullyNegotiatedInbound, FullyNegotiatedOutbound,
+        ListenUpgradeError,
+    },
+    ConnectionHandler, ConnectionHandlerEvent, KeepAlive, Stream, StreamProtocol,
+    StreamUpgradeError, SubstreamProtocol,
+};
+use std::{
+    collections::VecDeque,
+    io,
+    task::{Context, Poll},
+    time::Duration,
+};
+use void::Void;
+
+/// Timeout for a dial request, including the dial-back of the server.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Timeout for receiving the nonce on an inbound dial-back stream.
+const DIAL_BACK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time an idle connection is kept alive.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum ToBehaviour {
+    /// A dial request sent on this connection completed.
+    TestOutcome {
+        nonce: Nonce,
+        bytes_sent: usize,
+        result: Result<(), Error>,
+    },
+    /// A server sent a nonce back on this connection.
+    DialBack(Nonce),
+}
+
+pub struct Handler {
+    queued_events:
+        VecDeque<ConnectionHandlerEvent<ReadyUpgrade<StreamProtocol>, (), ToBehaviour, Void>>,
+
+    // Dial requests waiting for an outbound stream.
+    pending_requests: VecDeque<DialRequest>,
+
+    outbound: FuturesUnordered<BoxFuture<'static, ToBehaviour>>,
+
+    inbound: FuturesUnordered<BoxFuture<'static, io::Result<Nonce>>>,
+
+    keep_alive: KeepAlive,
+}
+
+impl Handler {
+    pub(crate) fn new() -> Self {
+        Self {
+            queued_events: VecDeque::new(),
+            pending_requests: VecDeque::new(),
+            outbound: FuturesUnordered::new(),
+            inbound: FuturesUnordered::new(),
+            keep_alive: KeepAlive::Yes,
+        }
+    }
+}
+
+impl ConnectionHandler for Handler {
+    type FromBehaviour = DialRequest;
+    type ToBehaviour = ToBehaviour;
+    type Error = Void;
+    type InboundProtocol = ReadyUpgrade<StreamProtocol>;
+    type OutboundProtocol = ReadyUpgrade<StreamProtocol>;
+    type InboundOpenInfo = ();
+    type OutboundOpenInfo = ();
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
+        SubstreamProtocol::new(ReadyUpgrade::new(DIAL_BACK_PROTOCOL), ())
+    }
+
+    fn on_behaviour_event(&mut self, request: Self::FromBehaviour) {
+        self.pending_requests.push_back(request);
+        self.queued_events
+            .push_back(ConnectionHandlerEvent::OutboundSubstreamRequest {
+                protocol: SubstreamProtocol::new(ReadyUpgrade::new(DIAL_REQUEST_PROTOCOL), ()),
+            });
+    }
+
+    fn on_connection_event(
+        &mut self,
+        event: ConnectionEvent<
+            Self::InboundProtocol,
+            Self::OutboundProtocol,
+            Self::InboundOpenInfo,
+            Self::OutboundOpenInfo,
+        >,
+    ) {
+        match event {
+            ConnectionEvent::FullyNegotiatedInbound(FullyNegotiatedInbound {
+                protocol: stream,
+                info: (),
+            }) => {
+                self.inbound.push(
+                    async move {
+                        match future::select(
+                            protocol::recv_dial_back(stream).boxed(),
+                            Delay::new(DIAL_BACK_TIMEOUT),
+                        )
+                        .await
+                        {
+                            Either::Left((result, _)) => result,
+                            Either::Right(((), _)) => Err(io::ErrorKind::TimedOut.into()),
+                        }
+                    }
+                    .boxed(),
+                );
+            }
+            ConnectionEvent::FullyNegotiatedOutbound(FullyNegotiatedOutbound {
+                protocol: stream,
+                info: (),
+            }) => {
+                let request = self
+                    .pending_requests
+                    .pop_front()
+                    .expect("opened a stream without a pending request");
+                self.outbound
+                    .push(send_dial_request(stream, request).boxed());
+            }
+            ConnectionEvent::DialUpgradeError(DialUpgradeError { info: (), error }) => {
+                let request = self
+                    .pending_requests
+                    .pop_front()
+                    .expect("requested a stream without a pending request");
+                let error = match error {
+                    StreamUpgradeError::NegotiationFailed => Error::UnsupportedProtocol,
+                    StreamUpgradeError::Timeout => Error::Io(io::ErrorKind::TimedOut.into()),
+                    StreamUpgradeError::Apply(v) => void::unreachable(v),
+                    StreamUpgradeError::Io(e) => Error::Io(e),
+                };
+                self.queued_events
+                    .push_back(ConnectionHandlerEvent::NotifyBehaviour(
+                        ToBehaviour::TestOutcome {
+                            nonce: request.nonce,
+                            bytes_sent: 0,
+                            result: Err(error),
+                        },
+                    ));
+            }
+            ConnectionEvent::ListenUpgradeError(ListenUpgradeError { info: (), error }) => {
+                void::unreachable(error)
+            }
+            ConnectionEvent::AddressChange(_)
+            | ConnectionEvent::LocalProtocolsChange(_)
+            | ConnectionEvent::RemoteProtocolsChange(_) => {}
+        }
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        self.keep_alive
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<
+        ConnectionHandlerEvent<
+            Self::OutboundProtocol,
+            Self::OutboundOpenInfo,
+            Self::ToBehaviour,
+            Self::Error,
+        >,
+    > {
+        if let Some(event) = self.queued_events.pop_front() {
+            return Poll::Ready(event);
+        }
+
+        if let Poll::Ready(Some(event)) = self.outbound.poll_next_unpin(cx) {
+            return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(event));
+        }
+
+        while let Poll::Ready(Some(result)) = self.inbound.poll_next_unpin(cx) {
+            match result {
+                Ok(nonce) => {
+                    return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(
+                        ToBehaviour::DialBack(nonce),
+                    ))
+                }
+                Err(e) => log::debug!("Inbound dial-back failed: {}", e),
+            }
+        }
+
+        if self.pending_requests.is_empty() && self.outbound.is_empty() && self.inbound.is_empty() {
+            if let KeepAlive::Yes = self.keep_alive {
+                self.keep_alive = KeepAlive::Until(Instant::now() + IDLE_TIMEOUT);
+            }
+        } else {
+            self.keep_alive = KeepAlive::Yes;
+        }
+
+        Poll::Pending
+    }
+}
+
+async fn send_dial_request(stream: Stream, request: DialRequest) -> ToBehaviour {
+    let nonce = request.nonce;
+    let mut bytes_sent = 0;
+    let result = {
+        let dial_request = dial_request(stream, request, &mut bytes_sent);
+        futures::pin_mut!(dial_request);
+        match future::select(dial_request, Delay::new(REQUEST_TIMEOUT)).await {
+            Either::Left((result, _)) => result,
+            Either::Right(((), _)) => Err(Error::Io(io::ErrorKind::TimedOut.into())),
+        }
+    };
+    ToBehaviour::TestOutcome {
+        nonce,
+        bytes_sent,
+        result,
+    }
+}
+
+async fn dial_request(
+    stream: Stream,
+    request: DialRequest,
+    bytes_sent: &mut usize,
+) -> Result<(), Error> {
+    let mut stream = protocol::dial_request_stream(stream);
+    let num_addrs = request.addrs.len();
+    Request::Dial(request).write_into(&mut stream).await?;
+
+    loop {
+        match Response::read_from(&mut stream).await? {
+            Response::Data(DialDataRequest {
+                addr_idx,
+                num_bytes,
+            }) => {
+                if addr_idx >= num_addrs {
+                    return Err(Error::InvalidResponse);
+                }
+                // Servers may split their request, but never get more data in total than the
+                // client agrees to send for a single dial request.
+                let total_bytes = *bytes_sent + num_bytes;
+                if total_bytes > DATA_LEN_UPPER_BOUND {
+                    return Err(Error::DataRequestTooLarge(total_bytes));
+                }
+                log::trace!("Sending {} bytes of dial data.", num_bytes);
+                let mut remaining = num_bytes;
+                while remaining > 0 {
+                    let data_count = remaining.min(DATA_FIELD_LEN_UPPER_BOUND);
+                    Request::Data(DialDataResponse { data_count })
+                        .write_into(&mut stream)
+                        .await?;
+                    remaining -= data_count;
+                    *bytes_sent += data_count;
+                }
+            }
+            Response::Dial(DialResponse {
+                status,
+                addr_idx,
+                dial_status,
+            }) => {
+                let _ = futures::SinkExt::close(&mut stream).await;
+                match status {
+                    proto::ResponseStatus::OK => {}
+                    proto::ResponseStatus::E_REQUEST_REJECTED => return Err(Error::Rejected),
+                    proto::ResponseStatus::E_DIAL_REFUSED => return Err(Error::DialRefused),
+                    proto::ResponseStatus::E_INTERNAL_ERROR => {
+                        return Err(Error::InternalServerError)
+                    }
+                }
+                if addr_idx >= num_addrs {
+                    return Err(Error::InvalidResponse);
+                }
+                return match dial_status {
+                    proto::DialStatus::OK => Ok(()),
+                    proto::DialStatus::E_DIAL_ERROR => Err(Error::Unreachable),
+                    proto::DialStatus::E_DIAL_BACK_ERROR => Err(Error::DialBackFailed),
+                    proto::DialStatus::UNUSED => Err(Error::InvalidResponse),
+                };
+            }
+        }
+    }
+}
@@ -0,0 +1,358 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use super::{proto, Nonce};
+use asynchronous_codec::Framed;
+use futures::{SinkExt, StreamExt};
+use libp2p_core::Multiaddr;
+use libp2p_swarm::Stream;
+use std::{convert::TryFrom, io};
+
+/// Minimum amount of data a server requests before dialing an address with an unobserved IP.
+pub(crate) const DATA_LEN_LOWER_BOUND: usize = 30_000;
+/// Maximum amount of data a server requests before dialing an address with an unobserved IP.
+pub(crate) const DATA_LEN_UPPER_BOUND: usize = 100_000;
+/// Maximum amount of data sent in a single [`proto::DialDataResponse`].
+pub(crate) const DATA_FIELD_LEN_UPPER_BOUND: usize = 4096;
+
+const MAX_MESSAGE_SIZE_BYTES: usize = 8192;
+
+/// A stream of the [`DIAL_REQUEST_PROTOCOL`](super::DIAL_REQUEST_PROTOCOL).
+pub(crate) type DialRequestStream = Framed<Stream, quick_protobuf_codec::Codec<proto::Message>>;
+
+pub(crate) fn dial_request_stream(stream: Stream) -> DialRequestStream {
+    Framed::new(
+        stream,
+        quick_protobuf_codec::Codec::new(MAX_MESSAGE_SIZE_BYTES),
+    )
+}
+
+/// Message sent by the client on a dial-request stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Request {
+    Dial(DialRequest),
+    Data(DialDataResponse),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DialRequest {
+    pub(crate) addrs: Vec<Multiaddr>,
+    pub(crate) nonce: Nonce,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DialDataResponse {
+    pub(crate) data_count: usize,
+}
+
+/// Message sent by the server on a dial-request stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Response {
+    Dial(DialResponse),
+    Data(DialDataRequest),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DialDataRequest {
+    pub(crate) addr_idx: usize,
+    pub(crate) num_bytes: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DialResponse {
+    pub(crate) status: proto::ResponseStatus,
+    pub(crate) addr_idx: usize,
+    pub(crate) dial_status: proto::DialStatus,
+}
+
+impl Request {
+    pub(crate) async fn read_from(stream: &mut DialRequestStream) -> io::Result<Self> {
+        let msg = stream
+            .next()
+            .await
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))??;
+        Self::try_from(msg)
+    }
+
+    pub(crate) async fn write_into(self, stream: &mut DialRequestStream) -> io::Result<()> {
+        match self {
+            Request::Dial(request) => stream.send(request.into()).await?,
+            Request::Data(DialDataResponse { data_count }) => {
+                debug_assert!(data_count <= DATA_FIELD_LEN_UPPER_BOUND);
+                let msg = proto::Message {
+                    dialDataResponse: Some(proto::DialDataResponse {
+                        data: Some(vec![0; data_count]),
+                    }),
+                    ..Default::default()
+                };
+                stream.send(msg).await?
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<proto::Message> for Request {
+    type Error = io::Error;
+
+    fn try_from(msg: proto::Message) -> Result<Self, Self::Error> {
+        match msg {
+            proto::Message {
+                dialRequest: Some(proto::DialRequest { addrs, nonce }),
+                dialResponse: None,
+                dialDataRequest: None,
+                dialDataResponse: None,
+            } => {
+                let addrs = addrs
+                    .into_iter()
+                    .map(Multiaddr::try_from)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                Ok(Request::Dial(DialRequest {
+                    addrs,
+                    nonce: nonce.unwrap_or_default(),
+                }))
+            }
+            proto::Message {
+                dialRequest: None,
+                dialResponse: None,
+                dialDataRequest: None,
+                dialDataResponse: Some(proto::DialDataResponse { data }),
+            } => {
+                let data_count = data.map(|d| d.len()).unwrap_or_default();
+                if data_count > DATA_FIELD_LEN_UPPER_BOUND {
+                    return Err(invalid_data("dial data exceeds the maximum field length"));
+                }
+                Ok(Request::Data(DialDataResponse { data_count }))
+            }
+            _ => Err(invalid_data(
+                "expected a dial request or dial data response",
+            )),
+        }
+    }
+}
+
+impl From<DialRequest> for proto::Message {
+    fn from(DialRequest { addrs, nonce }: DialRequest) -> Self {
+        proto::Message {
+            dialRequest: Some(proto::DialRequest {
+                addrs: addrs.into_iter().map(|addr| addr.to_vec()).collect(),
+                nonce: Some(nonce),
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+impl Response {
+    pub(crate) async fn read_from(stream: &mut DialRequestStream) -> io::Result<Self> {
+        let msg = stream
+            .next()
+            .await
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))??;
+        Self::try_from(msg)
+    }
+
+    pub(crate) async fn write_into(self, stream: &mut DialRequestStream) -> io::Result<()> {
+        stream.send(self.into()).await?;
+        Ok(())
+    }
+}
+
+impl TryFrom<proto::Message> for Response {
+    type Error = io::Error;
+
+    fn try_from(msg: proto::Message) -> Result<Self, Self::Error> {
+        match msg {
+            proto::Message {
+                dialRequest: None,
+                dialResponse:
+                    Some(proto::DialResponse {
+                        status,
+                        addrIdx,
+                        dialStatus,
+                    }),
+                dialDataRequest: None,
+                dialDataResponse: None,
+            } => Ok(Response::Dial(DialResponse {
+                status: status.unwrap_or_default(),
+                addr_idx: addrIdx.unwrap_or_default() as usize,
+                dial_status: dialStatus.unwrap_or_default(),
+            })),
+            proto::Message {
+                dialRequest: None,
+                dialResponse: None,
+                dialDataRequest: Some(proto::DialDataRequest { addrIdx, numBytes }),
+                dialDataResponse: None,
+            } => Ok(Response::Data(DialDataRequest {
+                addr_idx: addrIdx.unwrap_or_default() as usize,
+                num_bytes: usize::try_from(numBytes.unwrap_or_default())
+                    .map_err(|_| invalid_data("requested amount of dial data is too large"))?,
+            })),
+            _ => Err(invalid_data(
+                "expected a dial response or dial data request",
+            )),
+        }
+    }
+}
+
+impl From<Response> for proto::Message {
+    fn from(response: Response) -> Self {
+        match response {
+            Response::Dial(DialResponse {
+                status,
+                addr_idx,
+                dial_status,
+            }) => proto::Message {
+                dialResponse: Some(proto::DialResponse {
+                    status: Some(status),
+                    addrIdx: Some(addr_idx as u32),
+                    dialStatus: Some(dial_status),
+                }),
+                ..Default::default()
+            },
+            Response::Data(DialDataRequest {
+                addr_idx,
+                num_bytes,
+            }) => proto::Message {
+                dialDataRequest: Some(proto::DialDataRequest {
+                    addrIdx: Some(addr_idx as u32),
+                    numBytes: Some(num_bytes as u64),
+                }),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Sends the nonce on a stream of the [`DIAL_BACK_PROTOCOL`](super::DIAL_BACK_PROTOCOL) and waits
+/// for the client to acknowledge it.
+pub(crate) async fn dial_back(stream: Stream, nonce: Nonce) -> io::Result<()> {
+    let mut framed = Framed::new(
+        stream,
+        quick_protobuf_codec::Codec::<proto::DialBack, proto::DialBackResponse>::new(
+            MAX_MESSAGE_SIZE_BYTES,
+        ),
+    );
+    framed.send(proto::DialBack { nonce: Some(nonce) }).await?;
+    let proto::DialBackResponse { status } = framed
+        .next()
+        .await
+        .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))??;
+    match status.unwrap_or_default() {
+        proto::DialBackStatus::OK => {}
+    }
+    framed.close().await?;
+    Ok(())
+}
+
+/// Receives the nonce on an inbound stream of the
+/// [`DIAL_BACK_PROTOCOL`](super::DIAL_BACK_PROTOCOL) and acknowledges it.
+pub(crate) async fn recv_dial_back(stream: Stream) -> io::Result<Nonce> {
+    let mut framed = Framed::new(
+        stream,
+        quick_protobuf_codec::Codec::<proto::DialBackResponse, proto::DialBack>::new(
+            MAX_MESSAGE_SIZE_BYTES,
+        ),
+    );
+    let proto::DialBack { nonce } = framed
+        .next()
+        .await
+        .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))??;
+    let nonce = nonce.ok_or_else(|| invalid_data("dial back without nonce"))?;
+    framed
+        .send(proto::DialBackResponse {
+            status: Some(proto::DialBackStatus::OK),
+        })
+        .await?;
+    framed.close().await?;
+    Ok(nonce)
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quick_protobuf::{BytesReader, MessageRead, MessageWrite, Writer};
+
+    fn roundtrip(msg: proto::Message) -> proto::Message {
+        let mut buf = Vec::with_capacity(msg.get_size());
+        let mut writer = Writer::new(&mut buf);
+        msg.write_message(&mut writer).expect("Encoding to succeed");
+        let mut reader = BytesReader::from_bytes(&buf);
+        proto::Message::from_reader(&mut reader, &buf).expect("Decoding to succeed")
+    }
+
+    #[test]
+    fn dial_request_encode_decode() {
+        let request = DialRequest {
+            addrs: vec![
+                "/ip4/8.8.8.8/tcp/30333".parse().unwrap(),
+                "/ip6/2001:db8::/udp/1234/quic-v1".parse().unwrap(),
+            ],
+            nonce: rand::random(),
+        };
+        let decoded = Request::try_from(roundtrip(request.clone().into())).unwrap();
+        assert_eq!(decoded, Request::Dial(request));
+    }
+
+    #[test]
+    fn responses_encode_decode() {
+        let responses = [
+            Response::Data(DialDataRequest {
+                addr_idx: 2,
+                num_bytes: DATA_LEN_UPPER_BOUND,
+            }),
+            Response::Dial(DialResponse {
+                status: proto::ResponseStatus::OK,
+                addr_idx: 1,
+                dial_status: proto::DialStatus::E_DIAL_BACK_ERROR,
+            }),
+        ];
+        for response in responses {
+            let decoded = Response::try_from(roundtrip(response.clone().into())).unwrap();
+            assert_eq!(decoded, response);
+        }
+    }
+
+    #[test]
+    fn reject_oversized_dial_data() {
+        let msg = proto::Message {
+            dialDataResponse: Some(proto::DialDataResponse {
+                data: Some(vec![0; DATA_FIELD_LEN_UPPER_BOUND + 1]),
+            }),
+            ..Default::default()
+        };
+        assert!(Request::try_from(msg).is_err());
+    }
+
+    #[test]
+    fn reject_unexpected_message() {
+        let response: proto::Message = Response::Data(DialDataRequest {
+            addr_idx: 0,
+            num_bytes: DATA_LEN_LOWER_BOUND,
+        })
+        .into();
+        assert!(Request::try_from(response).is_err());
+    }
+}
@@ -0,0 +1,346 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The server side of AutoNAT v2, dialing back addresses on request of clients.
+
+mod handler;
+
+use handler::{DialBackCommand, DialBackStatus, Handler};
+use instant::Instant;
+use libp2p_core::{Endpoint, Multiaddr};
+use libp2p_identity::PeerId;
+use libp2p_swarm::{
+    behaviour::{DialFailure, FromSwarm},
+    dial_opts::{DialOpts, PeerCondition},
+    ConnectionDenied, ConnectionId, NetworkBehaviour, PollParameters, THandler, THandlerInEvent,
+    THandlerOutEvent, ToSwarm,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+type DialBackFilter = Box<dyn Fn(&PeerId, &Multiaddr) -> bool + Send>;
+
+/// Config for the [`Behaviour`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// Max dial-backs done in [`Config::throttle_clients_period`] on request of a single client.
+    pub throttle_clients_peer_max: usize,
+    /// Max dial-backs done in [`Config::throttle_clients_period`] to addresses in the same IP range,
+    /// i.e. the same `/24` for IPv4 and the same `/48` for IPv6.
+    pub throttle_ip_range_max: usize,
+    /// Period in which the dial-backs are limited.
+    pub throttle_clients_period: Duration,
+    /// Only dial back addresses with a global IP. Otherwise clients could make the server dial
+    /// hosts in its own private network.
+    pub only_global_ips: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            throttle_clients_peer_max: 12,
+            throttle_ip_range_max: 30,
+            throttle_clients_period: Duration::from_secs(60),
+            only_global_ips: true,
+        }
+    }
+}
+
+/// A dial request of a client was handled.
+#[derive(Debug)]
+pub struct Event {
+    /// All addresses the client requested to be tested.
+    pub all_addrs: Vec<Multiaddr>,
+    /// The address that was selected for testing.
+    /// `None` if none of the requested addresses can be dialed.
+    pub tested_addr: Option<Multiaddr>,
+    /// The client that sent the request.
+    pub client: PeerId,
+    /// Amount of data that was requested from the client as part of the amplification protection.
+    pub data_amount: usize,
+    /// `Ok` if the tested address was dialed and the dial-back was sent.
+    pub result: Result<(), Error>,
+}
+
+/// Handling a dial request failed.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// None of the requested addresses can be dialed.
+    #[error("no dialable address in request")]
+    NoDialableAddress,
+    /// The dial-back was rejected, either by the filter or because of the throttling limits.
+    #[error("dial-back was rejected")]
+    Rejected,
+    /// Dialing the tested address failed.
+    #[error("failed to dial the address")]
+    DialFailed,
+    /// The tested address was dialed, but sending the dial-back failed.
+    #[error("failed to send the dial-back")]
+    DialBackFailed,
+    /// The dial request stream failed or timed out.
+    #[error("dial request stream failed: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// [`NetworkBehaviour`] for the server side of AutoNAT v2.
+///
+/// For each dial request, the first requested address that can be dialed is tested on a new
+/// connection. By default, only addresses with a global IP can be dialed, see
+/// [`Config::only_global_ips`]. Before dialing an address whose IP differs from the one the client is observed at,
+/// the client has to send between 30 and 100 kB of data to make the dial costly for it.
+///
+/// To prevent the server from being abused for dialing arbitrary hosts, the number of dial-backs
+/// is limited per client and per IP range of the tested address, see [`Config`]. Additionally,
+/// dial-backs can be restricted with [`Behaviour::set_dial_back_filter`]. Dial requests exceeding
+/// the limits or denied by the filter are rejected.
+///
+/// Unlike with the v1 [`ServerMode`](crate::ServerMode), dial requests are served regardless of
+/// our own reachability, as the outcome of a dial-back does not depend on it. To stop serving
+/// them, disable the behaviour, e.g. through a
+/// [`Toggle`](libp2p_swarm::behaviour::toggle::Toggle).
+pub struct Behaviour {
+    config: Config,
+
+    // Dial-backs for which a connection is being established, by the id of that connection.
+    dialing_dial_back: HashMap<ConnectionId, DialBackCommand>,
+
+    // Recent dial-backs, by the client that requested them and by the IP range they targeted.
+    throttled_clients: Vec<(PeerId, Instant)>,
+    throttled_ip_ranges: Vec<(IpAddr, Instant)>,
+
+    dial_back_filter: Option<DialBackFilter>,
+
+    pending_events: VecDeque<ToSwarm<Event, THandlerInEvent<Self>>>,
+}
+
+impl Behaviour {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            dialing_dial_back: HashMap::new(),
+            throttled_clients: Vec::new(),
+            throttled_ip_ranges: Vec::new(),
+            dial_back_filter: None,
+            pending_events: VecDeque::new(),
+        }
+    }
+
+    /// Sets a predicate that decides whether the address of a client may be dialed back.
+    ///
+    /// The predicate is called with the client and the address selected for testing. Dial
+    /// requests for which it returns `false` are rejected.
+    pub fn set_dial_back_filter<F>(&mut self, filter: F)
+    where
+        F: Fn(&PeerId, &Multiaddr) -> bool + Send + 'static,
+    {
+        self.dial_back_filter = Some(Box::new(filter));
+    }
+
+    // Whether a dial-back to `addr` may be done on request of `client`, recording it if so.
+    fn admit_dial_back(&mut self, client: PeerId, addr: &Multiaddr) -> bool {
+        if let Some(filter) = self.dial_back_filter.as_ref() {
+            if !filter(&client, addr) {
+                log::debug!("Dial-back to {} for {} denied by filter.", addr, client);
+                return false;
+            }
+        }
+
+        let period = self.config.throttle_clients_period;
+        self.throttled_clients
+            .retain(|(_, time)| time.elapsed() < period);
+        self.throttled_ip_ranges
+            .retain(|(_, time)| time.elapsed() < period);
+
+        let client_count = self
+            .throttled_clients
+            .iter()
+            .filter(|(peer, _)| *peer == client)
+            .count();
+        if client_count >= self.config.throttle_clients_peer_max {
+            log::debug!(
+                "Dial-back to {} for {} throttled: Client limit.",
+                addr,
+                client
+            );
+            return false;
+        }
+        let ip_range = handler::ip(addr).map(ip_range);
+        if let Some(ip_range) = ip_range {
+            let ip_range_count = self
+                .throttled_ip_ranges
+                .iter()
+                .filter(|(range, _)| *range == ip_range)
+                .count();
+            if ip_range_count >= self.config.throttle_ip_range_max {
+                log::debug!(
+                    "Dial-back to {} for {} throttled: IP range limit.",
+                    addr,
+                    client
+                );
+                return false;
+            }
+        }
+
+        let now = Instant::now();
+        self.throttled_clients.push((client, now));
+        if let Some(ip_range) = ip_range {
+            self.throttled_ip_ranges.push((ip_range, now));
+        }
+        true
+    }
+}
+
+impl Default for Behaviour {
+    fn default() -> Self {
+        Self::new(Config::default())
+    }
+}
+
+impl NetworkBehaviour for Behaviour {
+    type ConnectionHandler = Handler;
+    type ToSwarm = Event;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _: ConnectionId,
+        peer: PeerId,
+        _: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(Handler::new(
+            peer,
+            remote_addr.clone(),
+            self.config.only_global_ips,
+        ))
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        addr: &Multiaddr,
+        _: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        let only_global_ips = self.config.only_global_ips;
+        match self.dialing_dial_back.remove(&connection_id) {
+            Some(command) => Ok(Handler::dial_back(
+                peer,
+                addr.clone(),
+                only_global_ips,
+                command,
+            )),
+            None => Ok(Handler::new(peer, addr.clone(), only_global_ips)),
+        }
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm<Self::ConnectionHandler>) {
+        match event {
+            FromSwarm::DialFailure(DialFailure {
+                connection_id,
+                error,
+                ..
+            }) => {
+                if let Some(command) = self.dialing_dial_back.remove(&connection_id) {
+                    log::debug!("Dial-back to {} failed: {}", command.addr, error);
+                    let _ = command.back_channel.send(DialBackStatus::DialErr);
+                }
+            }
+            FromSwarm::ConnectionEstablished(_)
+            | FromSwarm::ConnectionClosed(_)
+            | FromSwarm::AddressChange(_)
+            | FromSwarm::ListenFailure(_)
+            | FromSwarm::NewListener(_)
+            | FromSwarm::NewListenAddr(_)
+            | FromSwarm::ExpiredListenAddr(_)
+            | FromSwarm::ListenerError(_)
+            | FromSwarm::ListenerClosed(_)
+            | FromSwarm::NewExternalAddrCandidate(_)
+            | FromSwarm::ExternalAddrConfirmed(_)
+            | FromSwarm::ExternalAddrExpired(_) => {}
+        }
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        peer_id: PeerId,
+        _: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {
+            handler::ToBehaviour::DialBack(command) => {
+                if !self.admit_dial_back(peer_id, &command.addr) {
+                    let _ = command.back_channel.send(DialBackStatus::Rejected);
+                    return;
+                }
+                let opts = DialOpts::peer_id(peer_id)
+                    .addresses(vec![command.addr.clone()])
+                    .condition(PeerCondition::Always)
+                    .build();
+                self.dialing_dial_back.insert(opts.connection_id(), command);
+                self.pending_events.push_back(ToSwarm::Dial { opts });
+            }
+            handler::ToBehaviour::TestEnd(handler::TestEnd {
+                all_addrs,
+                tested_addr,
+                data_amount,
+                result,
+            }) => {
+                self.pending_events.push_back(ToSwarm::GenerateEvent(Event {
+                    all_addrs,
+                    tested_addr,
+                    client: peer_id,
+                    data_amount,
+                    result,
+                }));
+            }
+        }
+    }
+
+    fn poll(
+        &mut self,
+        _: &mut Context<'_>,
+        _: &mut impl PollParameters,
+    ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        if let Some(event) = self.pending_events.pop_front() {
+            return Poll::Ready(event);
+        }
+
+        Poll::Pending
+    }
+}
+
+// The range an IP belongs to, i.e. its `/24` network for IPv4 and its `/48` network for IPv6.
+fn ip_range(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            Ipv4Addr::new(a, b, c, 0).into()
+        }
+        IpAddr::V6(ip) => {
+            let [a, b, c, ..] = ip.segments();
+            Ipv6Addr::new(a, b, c, 0, 0, 0, 0, 0).into()
+        }
+    }
+}
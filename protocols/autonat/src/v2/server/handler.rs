@@ -0,0 +1,503 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use super::Error;
+use crate::behaviour::GlobalIp;
+use crate::v2::{
+    proto,
+    protocol::{
+        self, DialDataRequest, DialDataResponse, DialRequest, DialRequestStream, DialResponse,
+        Request, Response, DATA_LEN_LOWER_BOUND, DATA_LEN_UPPER_BOUND,
+    },
+    Nonce, DIAL_BACK_PROTOCOL, DIAL_REQUEST_PROTOCOL,
+};
+use futures::{
+    channel::{mpsc, oneshot},
+    future::{self, BoxFuture, Either},
+    stream::FuturesUnordered,
+    FutureExt, SinkExt, StreamExt,
+};
+use futures_timer::Delay;
+use instant::Instant;
+use libp2p_core::{multiaddr::Protocol, upgrade::ReadyUpgrade, Multiaddr};
+use libp2p_identity::PeerId;
+use libp2p_swarm::{
+    handler::{
+        ConnectionEvent, DialUpgradeError, FullyNegotiatedInbound, FullyNegotiatedOutbound,
+        ListenUpgradeError,
+    },
+    ConnectionHandler, ConnectionHandlerEvent, KeepAlive, Stream, StreamProtocol,
+    SubstreamProtocol,
+};
+use rand::Rng;
+use std::{
+    collections::VecDeque,
+    io,
+    net::IpAddr,
+    task::{Context, Poll},
+    time::Duration,
+};
+use void::Void;
+
+/// Timeout for handling a dial request, including the dial-back.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Timeout for the client to acknowledge the nonce on the dial-back stream.
+const DIAL_BACK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time an idle connection is kept alive.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Request to dial back an address of the client.
+#[derive(Debug)]
+pub struct DialBackCommand {
+    pub(crate) addr: Multiaddr,
+    pub(crate) nonce: Nonce,
+    pub(crate) back_channel: oneshot::Sender<DialBackStatus>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DialBackStatus {
+    Ok,
+    /// The dial-back was not admitted by the behaviour.
+    Rejected,
+    /// Dialing the address failed.
+    DialErr,
+    /// Sending the nonce on the new connection failed.
+    DialBackErr,
+}
+
+/// A dial request received on this connection was handled.
+#[derive(Debug)]
+pub struct TestEnd {
+    pub(crate) all_addrs: Vec<Multiaddr>,
+    pub(crate) tested_addr: Option<Multiaddr>,
+    pub(crate) data_amount: usize,
+    pub(crate) result: Result<(), Error>,
+}
+
+#[derive(Debug)]
+pub enum ToBehaviour {
+    /// The client's address should be dialed on a new connection.
+    DialBack(DialBackCommand),
+    TestEnd(TestEnd),
+}
+
+pub struct Handler {
+    // The client on the other end of the connection.
+    client: PeerId,
+
+    // The address the client is observed at on this connection.
+    observed_addr: Multiaddr,
+
+    // Whether only addresses with a global IP are dialed back.
+    only_global_ips: bool,
+
+    queued_events:
+        VecDeque<ConnectionHandlerEvent<ReadyUpgrade<StreamProtocol>, (), ToBehaviour, Void>>,
+
+    // Ongoing dial requests of the client.
+    inbound: FuturesUnordered<BoxFuture<'static, TestEnd>>,
+
+    // Channel on which the dial requests hand over the dial-backs to the handler.
+    dial_back_sender: mpsc::Sender<DialBackCommand>,
+    dial_back_receiver: mpsc::Receiver<DialBackCommand>,
+
+    // Whether the connection was established for a dial-back, in which case it is closed once the
+    // dial-back completed.
+    is_dial_back: bool,
+
+    // Dial-back waiting for the outbound stream.
+    pending_dial_back: Option<DialBackCommand>,
+
+    // Ongoing dial-back on this connection.
+    outbound: Option<BoxFuture<'static, ()>>,
+
+    keep_alive: KeepAlive,
+}
+
+impl Handler {
+    pub(crate) fn new(client: PeerId, observed_addr: Multiaddr, only_global_ips: bool) -> Self {
+        let (dial_back_sender, dial_back_receiver) = mpsc::channel(0);
+        Self {
+            client,
+            observed_addr,
+            only_global_ips,
+            queued_events: VecDeque::new(),
+            inbound: FuturesUnordered::new(),
+            dial_back_sender,
+            dial_back_receiver,
+            is_dial_back: false,
+            pending_dial_back: None,
+            outbound: None,
+            keep_alive: KeepAlive::Yes,
+        }
+    }
+
+    /// Creates a handler that sends the dial-back on the connection.
+    pub(crate) fn dial_back(
+        client: PeerId,
+        addr: Multiaddr,
+        only_global_ips: bool,
+        command: DialBackCommand,
+    ) -> Self {
+        let mut handler = Self::new(client, addr, only_global_ips);
+        handler.is_dial_back = true;
+        handler.pending_dial_back = Some(command);
+        handler
+            .queued_events
+            .push_back(ConnectionHandlerEvent::OutboundSubstreamRequest {
+                protocol: SubstreamProtocol::new(ReadyUpgrade::new(DIAL_BACK_PROTOCOL), ()),
+            });
+        handler
+    }
+}
+
+impl ConnectionHandler for Handler {
+    type FromBehaviour = Void;
+    type ToBehaviour = ToBehaviour;
+    type Error = Void;
+    type InboundProtocol = ReadyUpgrade<StreamProtocol>;
+    type OutboundProtocol = ReadyUpgrade<StreamProtocol>;
+    type InboundOpenInfo = ();
+    type OutboundOpenInfo = ();
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
+        SubstreamProtocol::new(ReadyUpgrade::new(DIAL_REQUEST_PROTOCOL), ())
+    }
+
+    fn on_behaviour_event(&mut self, event: Self::FromBehaviour) {
+        void::unreachable(event)
+    }
+
+    fn on_connection_event(
+        &mut self,
+        event: ConnectionEvent<
+            Self::InboundProtocol,
+            Self::OutboundProtocol,
+            Self::InboundOpenInfo,
+            Self::OutboundOpenInfo,
+        >,
+    ) {
+        match event {
+            ConnectionEvent::FullyNegotiatedInbound(FullyNegotiatedInbound {
+                protocol: stream,
+                info: (),
+            }) => {
+                self.inbound.push(
+                    handle_dial_request(
+                        stream,
+                        self.client,
+                        self.observed_addr.clone(),
+                        self.only_global_ips,
+                        self.dial_back_sender.clone(),
+                    )
+                    .boxed(),
+                );
+            }
+            ConnectionEvent::FullyNegotiatedOutbound(FullyNegotiatedOutbound {
+                protocol: stream,
+                info: (),
+            }) => {
+                let DialBackCommand {
+                    addr,
+                    nonce,
+                    back_channel,
+                } = self
+                    .pending_dial_back
+                    .take()
+                    .expect("opened a stream without a pending dial-back");
+                self.outbound = Some(
+                    async move {
+                        let status = match future::select(
+                            protocol::dial_back(stream, nonce).boxed(),
+                            Delay::new(DIAL_BACK_TIMEOUT),
+                        )
+                        .await
+                        {
+                            Either::Left((Ok(()), _)) => DialBackStatus::Ok,
+                            Either::Left((Err(e), _)) => {
+                                log::debug!("Dial-back to {} failed: {}", addr, e);
+                                DialBackStatus::DialBackErr
+                            }
+                            Either::Right(((), _)) => {
+                                log::debug!("Dial-back to {} timed out", addr);
+                                DialBackStatus::DialBackErr
+                            }
+                        };
+                        let _ = back_channel.send(status);
+                    }
+                    .boxed(),
+                );
+            }
+            ConnectionEvent::DialUpgradeError(DialUpgradeError { info: (), error }) => {
+                let command = self
+                    .pending_dial_back
+                    .take()
+                    .expect("requested a stream without a pending dial-back");
+                log::debug!(
+                    "Failed to open dial-back stream to {}: {}",
+                    command.addr,
+                    error
+                );
+                let _ = command.back_channel.send(DialBackStatus::DialBackErr);
+            }
+            ConnectionEvent::ListenUpgradeError(ListenUpgradeError { info: (), error }) => {
+                void::unreachable(error)
+            }
+            ConnectionEvent::AddressChange(_)
+            | ConnectionEvent::LocalProtocolsChange(_)
+            | ConnectionEvent::RemoteProtocolsChange(_) => {}
+        }
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        self.keep_alive
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<
+        ConnectionHandlerEvent<
+            Self::OutboundProtocol,
+            Self::OutboundOpenInfo,
+            Self::ToBehaviour,
+            Self::Error,
+        >,
+    > {
+        if let Some(event) = self.queued_events.pop_front() {
+            return Poll::Ready(event);
+        }
+
+        if let Poll::Ready(Some(end)) = self.inbound.poll_next_unpin(cx) {
+            return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(
+                ToBehaviour::TestEnd(end),
+            ));
+        }
+
+        if let Poll::Ready(Some(command)) = self.dial_back_receiver.poll_next_unpin(cx) {
+            return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(
+                ToBehaviour::DialBack(command),
+            ));
+        }
+
+        if let Some(outbound) = self.outbound.as_mut() {
+            if outbound.poll_unpin(cx).is_ready() {
+                self.outbound = None;
+            }
+        }
+
+        if self.inbound.is_empty() && self.pending_dial_back.is_none() && self.outbound.is_none() {
+            if self.is_dial_back {
+                self.keep_alive = KeepAlive::No;
+            } else if let KeepAlive::Yes = self.keep_alive {
+                self.keep_alive = KeepAlive::Until(Instant::now() + IDLE_TIMEOUT);
+            }
+        } else {
+            self.keep_alive = KeepAlive::Yes;
+        }
+
+        Poll::Pending
+    }
+}
+
+async fn handle_dial_request(
+    stream: Stream,
+    client: PeerId,
+    observed_addr: Multiaddr,
+    only_global_ips: bool,
+    dial_back_sender: mpsc::Sender<DialBackCommand>,
+) -> TestEnd {
+    let mut stream = protocol::dial_request_stream(stream);
+    let mut end = TestEnd {
+        all_addrs: Vec::new(),
+        tested_addr: None,
+        data_amount: 0,
+        result: Ok(()),
+    };
+    end.result = {
+        let handle = handle_request_inner(
+            &mut stream,
+            client,
+            &observed_addr,
+            only_global_ips,
+            dial_back_sender,
+            &mut end,
+        );
+        futures::pin_mut!(handle);
+        match future::select(handle, Delay::new(REQUEST_TIMEOUT)).await {
+            Either::Left((result, _)) => result,
+            Either::Right(((), _)) => Err(Error::Io(io::ErrorKind::TimedOut.into())),
+        }
+    };
+    let _ = stream.close().await;
+    end
+}
+
+async fn handle_request_inner(
+    stream: &mut DialRequestStream,
+    client: PeerId,
+    observed_addr: &Multiaddr,
+    only_global_ips: bool,
+    mut dial_back_sender: mpsc::Sender<DialBackCommand>,
+    end: &mut TestEnd,
+) -> Result<(), Error> {
+    let DialRequest { addrs, nonce } = match Request::read_from(stream).await? {
+        Request::Dial(request) => request,
+        Request::Data(_) => {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "expected a dial request",
+            )))
+        }
+    };
+    end.all_addrs = addrs.clone();
+
+    let Some((addr_idx, addr)) = addrs
+        .into_iter()
+        .enumerate()
+        .find(|(_, addr)| is_dialable(addr, client, only_global_ips))
+    else {
+        Response::Dial(DialResponse {
+            status: proto::ResponseStatus::E_DIAL_REFUSED,
+            addr_idx: 0,
+            dial_status: proto::DialStatus::UNUSED,
+        })
+        .write_into(stream)
+        .await?;
+        return Err(Error::NoDialableAddress);
+    };
+    end.tested_addr = Some(addr.clone());
+
+    // Require the client to send data if the dial could target a host other than the client.
+    if ip(&addr) != ip(observed_addr) {
+        let num_bytes = rand::thread_rng().gen_range(DATA_LEN_LOWER_BOUND..=DATA_LEN_UPPER_BOUND);
+        Response::Data(DialDataRequest {
+            addr_idx,
+            num_bytes,
+        })
+        .write_into(stream)
+        .await?;
+        while end.data_amount < num_bytes {
+            match Request::read_from(stream).await? {
+                Request::Data(DialDataResponse { data_count }) => end.data_amount += data_count,
+                Request::Dial(_) => {
+                    return Err(Error::Io(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "expected dial data",
+                    )))
+                }
+            }
+        }
+    }
+
+    let (back_channel, status) = oneshot::channel();
+    let _ = dial_back_sender
+        .send(DialBackCommand {
+            addr,
+            nonce,
+            back_channel,
+        })
+        .await;
+    let (dial_status, result) = match status.await {
+        Ok(DialBackStatus::Ok) => (proto::DialStatus::OK, Ok(())),
+        Ok(DialBackStatus::Rejected) => {
+            Response::Dial(DialResponse {
+                status: proto::ResponseStatus::E_REQUEST_REJECTED,
+                addr_idx,
+                dial_status: proto::DialStatus::UNUSED,
+            })
+            .write_into(stream)
+            .await?;
+            return Err(Error::Rejected);
+        }
+        Ok(DialBackStatus::DialErr) => (proto::DialStatus::E_DIAL_ERROR, Err(Error::DialFailed)),
+        Ok(DialBackStatus::DialBackErr) => (
+            proto::DialStatus::E_DIAL_BACK_ERROR,
+            Err(Error::DialBackFailed),
+        ),
+        Err(oneshot::Canceled) => {
+            Response::Dial(DialResponse {
+                status: proto::ResponseStatus::E_INTERNAL_ERROR,
+                addr_idx,
+                dial_status: proto::DialStatus::UNUSED,
+            })
+            .write_into(stream)
+            .await?;
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::Other,
+                "dial-back was dropped",
+            )));
+        }
+    };
+
+    Response::Dial(DialResponse {
+        status: proto::ResponseStatus::OK,
+        addr_idx,
+        dial_status,
+    })
+    .write_into(stream)
+    .await?;
+
+    result
+}
+
+// Whether the address can be dialed to reach the client.
+fn is_dialable(addr: &Multiaddr, client: PeerId, only_global_ips: bool) -> bool {
+    if ip(addr).is_none() || (only_global_ips && !addr.is_global_ip()) {
+        return false;
+    }
+    addr.iter().all(|protocol| match protocol {
+        Protocol::P2pCircuit => false,
+        Protocol::P2p(peer_id) => peer_id == client,
+        _ => true,
+    })
+}
+
+pub(super) fn ip(addr: &Multiaddr) -> Option<IpAddr> {
+    match addr.iter().next()? {
+        Protocol::Ip4(ip) => Some(ip.into()),
+        Protocol::Ip6(ip) => Some(ip.into()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dialable_addresses() {
+        let client = PeerId::random();
+        let dialable: Multiaddr = "/ip4/8.8.8.8/tcp/30333".parse().unwrap();
+        let with_peer_id = dialable.clone().with(Protocol::P2p(client));
+        let other_peer_id = dialable.clone().with(Protocol::P2p(PeerId::random()));
+        let relayed = with_peer_id.clone().with(Protocol::P2pCircuit);
+        let dns: Multiaddr = "/dns4/example.com/tcp/30333".parse().unwrap();
+        let local: Multiaddr = "/ip4/192.168.1.1/tcp/30333".parse().unwrap();
+
+        assert!(is_dialable(&dialable, client, true));
+        assert!(is_dialable(&with_peer_id, client, true));
+        assert!(!is_dialable(&other_peer_id, client, true));
+        assert!(!is_dialable(&relayed, client, true));
+        assert!(!is_dialable(&dns, client, true));
+        assert!(!is_dialable(&local, client, true));
+        assert!(is_dialable(&local, client, false));
+    }
+}
@@ -0,0 +1,306 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use futures::future::{select, Either};
+use libp2p_autonat::v2::{client, server};
+use libp2p_core::{multiaddr::Protocol, Multiaddr};
+use libp2p_swarm::{Swarm, SwarmEvent};
+use libp2p_swarm_test::SwarmExt as _;
+use std::time::Duration;
+
+#[async_std::test]
+async fn confirms_reachable_address() {
+    let _ = env_logger::try_init();
+
+    let mut server = new_server();
+    server.listen().await;
+    let mut client = new_client();
+    let (_, client_tcp_addr) = client.listen().await;
+    client.connect(&mut server).await;
+    let client_id = *client.local_peer_id();
+    let server_id = *server.local_peer_id();
+
+    let mut client_event = None;
+    let mut server_event = None;
+    while client_event.is_none() || server_event.is_none() {
+        match select(client.next_swarm_event(), server.next_swarm_event()).await {
+            Either::Left((SwarmEvent::Behaviour(event), _))
+                if event.tested_addr == client_tcp_addr =>
+            {
+                client_event = Some(event);
+            }
+            Either::Right((SwarmEvent::Behaviour(event), _))
+                if event.tested_addr.as_ref() == Some(&client_tcp_addr) =>
+            {
+                server_event = Some(event);
+            }
+            _ => {}
+        }
+    }
+    let client_event = client_event.unwrap();
+    let server_event = server_event.unwrap();
+
+    assert_eq!(client_event.server, server_id);
+    assert!(client_event.result.is_ok(), "{:?}", client_event.result);
+    assert_eq!(server_event.client, client_id);
+    assert!(server_event.result.is_ok(), "{:?}", server_event.result);
+    assert_eq!(server_event.all_addrs, vec![client_tcp_addr.clone()]);
+
+    // The client is connected through the memory transport, thus the server can't observe its IP
+    // and has to request data before dialing.
+    assert!(client_event.bytes_sent >= 30_000);
+    assert_eq!(client_event.bytes_sent, server_event.data_amount);
+
+    assert!(client.external_addresses().any(|a| a == &client_tcp_addr));
+}
+
+#[async_std::test]
+async fn refuses_address_without_ip() {
+    let _ = env_logger::try_init();
+
+    let mut server = new_server();
+    server.listen().await;
+    let mut client = new_client();
+    let (client_memory_addr, _) = client.listen().await;
+    client.connect(&mut server).await;
+    async_std::task::spawn(server.loop_on_next());
+
+    let event = client
+        .wait(|e| match e {
+            SwarmEvent::Behaviour(event) if event.tested_addr == client_memory_addr => Some(event),
+            _ => None,
+        })
+        .await;
+    assert!(
+        matches!(event.result, Err(client::Error::DialRefused)),
+        "{:?}",
+        event.result
+    );
+    assert_eq!(event.bytes_sent, 0);
+}
+
+#[async_std::test]
+async fn peer_without_server_support() {
+    let _ = env_logger::try_init();
+
+    let mut other = new_client();
+    other.listen().await;
+    let mut client = new_client();
+    let (_, client_tcp_addr) = client.listen().await;
+    client.connect(&mut other).await;
+    let other_id = *other.local_peer_id();
+    async_std::task::spawn(other.loop_on_next());
+
+    let event = client
+        .wait(|e| match e {
+            SwarmEvent::Behaviour(event) if event.tested_addr == client_tcp_addr => Some(event),
+            _ => None,
+        })
+        .await;
+    assert_eq!(event.server, other_id);
+    assert!(
+        matches!(event.result, Err(client::Error::UnsupportedProtocol)),
+        "{:?}",
+        event.result
+    );
+}
+
+#[async_std::test]
+async fn probe_address_without_confirming() {
+    let _ = env_logger::try_init();
+
+    let mut server = new_server();
+    server.listen().await;
+    let mut client = new_probing_client();
+    let (_, client_tcp_addr) = client.listen().await;
+    assert!(client
+        .behaviour_mut()
+        .probe_address(client_tcp_addr.clone())
+        .is_err());
+
+    client.connect(&mut server).await;
+    async_std::task::spawn(server.loop_on_next());
+
+    let unreachable_addr: Multiaddr = "/ip4/127.0.0.1/tcp/1".parse().unwrap();
+    let reachable_id = client
+        .behaviour_mut()
+        .probe_address(client_tcp_addr.clone())
+        .unwrap();
+    let unreachable_id = client
+        .behaviour_mut()
+        .probe_address(unreachable_addr.clone())
+        .unwrap();
+
+    let mut events = Vec::new();
+    while events.len() < 2 {
+        if let SwarmEvent::Behaviour(event) = client.next_swarm_event().await {
+            events.push(event);
+        }
+    }
+    events.sort_by_key(|event| event.probe_id != Some(reachable_id));
+
+    assert_eq!(events[0].probe_id, Some(reachable_id));
+    assert_eq!(events[0].tested_addr, client_tcp_addr);
+    assert!(events[0].result.is_ok(), "{:?}", events[0].result);
+    assert_eq!(events[1].probe_id, Some(unreachable_id));
+    assert_eq!(events[1].tested_addr, unreachable_addr);
+    assert!(
+        matches!(events[1].result, Err(client::Error::Unreachable)),
+        "{:?}",
+        events[1].result
+    );
+
+    // Explicitly tested addresses are left to the application to advertise.
+    assert!(!client.external_addresses().any(|a| a == &client_tcp_addr));
+}
+
+#[async_std::test]
+async fn throttles_dial_backs_per_client() {
+    let _ = env_logger::try_init();
+
+    let mut server = Swarm::new_ephemeral(|_| {
+        server::Behaviour::new(server::Config {
+            throttle_clients_peer_max: 1,
+            only_global_ips: false,
+            ..Default::default()
+        })
+    });
+    server.listen().await;
+    let mut client = new_probing_client();
+    let (_, client_tcp_addr) = client.listen().await;
+    client.connect(&mut server).await;
+    async_std::task::spawn(server.loop_on_next());
+
+    let mut results = Vec::new();
+    for _ in 0..2 {
+        client
+            .behaviour_mut()
+            .probe_address(client_tcp_addr.clone())
+            .unwrap();
+        let event = client
+            .wait(|e| match e {
+                SwarmEvent::Behaviour(event) => Some(event),
+                _ => None,
+            })
+            .await;
+        results.push(event.result);
+    }
+    assert!(results[0].is_ok(), "{:?}", results[0]);
+    assert!(
+        matches!(results[1], Err(client::Error::Rejected)),
+        "{:?}",
+        results[1]
+    );
+}
+
+#[async_std::test]
+async fn dial_back_filter_rejects_request() {
+    let _ = env_logger::try_init();
+
+    let mut server = Swarm::new_ephemeral(|_| {
+        let mut behaviour = server::Behaviour::new(local_server_config());
+        behaviour.set_dial_back_filter(|_, addr| {
+            !addr
+                .iter()
+                .any(|p| matches!(p, Protocol::Ip4(ip) if ip.is_loopback()))
+        });
+        behaviour
+    });
+    server.listen().await;
+    let mut client = new_probing_client();
+    let (_, client_tcp_addr) = client.listen().await;
+    client.connect(&mut server).await;
+    async_std::task::spawn(server.loop_on_next());
+
+    client
+        .behaviour_mut()
+        .probe_address(client_tcp_addr)
+        .unwrap();
+    let event = client
+        .wait(|e| match e {
+            SwarmEvent::Behaviour(event) => Some(event),
+            _ => None,
+        })
+        .await;
+    assert!(
+        matches!(event.result, Err(client::Error::Rejected)),
+        "{:?}",
+        event.result
+    );
+}
+
+#[async_std::test]
+async fn refuses_non_global_address_by_default() {
+    let _ = env_logger::try_init();
+
+    let mut server = Swarm::new_ephemeral(|_| server::Behaviour::default());
+    server.listen().await;
+    let mut client = new_probing_client();
+    let (_, client_tcp_addr) = client.listen().await;
+    client.connect(&mut server).await;
+    async_std::task::spawn(server.loop_on_next());
+
+    client
+        .behaviour_mut()
+        .probe_address(client_tcp_addr)
+        .unwrap();
+    let event = client
+        .wait(|e| match e {
+            SwarmEvent::Behaviour(event) => Some(event),
+            _ => None,
+        })
+        .await;
+    assert!(
+        matches!(event.result, Err(client::Error::DialRefused)),
+        "{:?}",
+        event.result
+    );
+}
+
+// Server that also dials back the loopback addresses the tests listen on.
+fn new_server() -> Swarm<server::Behaviour> {
+    Swarm::new_ephemeral(|_| server::Behaviour::new(local_server_config()))
+}
+
+fn local_server_config() -> server::Config {
+    server::Config {
+        only_global_ips: false,
+        ..Default::default()
+    }
+}
+
+// Client that only tests addresses passed to `probe_address`.
+fn new_probing_client() -> Swarm<client::Behaviour> {
+    Swarm::new_ephemeral(|_| {
+        client::Behaviour::new(client::Config {
+            probe_interval: Duration::from_secs(3600),
+            ..Default::default()
+        })
+    })
+}
+
+fn new_client() -> Swarm<client::Behaviour> {
+    Swarm::new_ephemeral(|_| {
+        client::Behaviour::new(client::Config {
+            probe_interval: Duration::from_millis(100),
+            ..Default::default()
+        })
+    })
+}
@@ -20,7 +20,8 @@
 
 use async_std::task::JoinHandle;
 use libp2p_autonat::{
-    Behaviour, Config, Event, NatStatus, OutboundProbeError, OutboundProbeEvent, ResponseError,
+    Behaviour, Config, Event, NatStatus, OutboundProbeError, OutboundProbeEvent, Reachability,
+    ResponseError,
 };
 use libp2p_core::Multiaddr;
 use libp2p_identity::PeerId;
@@ -98,7 +99,7 @@ async fn test_auto_probe() {
                 assert_eq!(peer, server_id);
                 assert_eq!(probe_id, id);
             }
-            SwarmEvent::Behaviour(Event::StatusChanged { old, new }) => {
+            SwarmEvent::Behaviour(Event::StatusChanged { old, new, .. }) => {
                 // Expect to flip status to public
                 assert_eq!(old, NatStatus::Unknown);
                 assert!(matches!(new, NatStatus::Public(_)));
@@ -154,11 +155,13 @@ async fn test_confidence() {
 
     // Randomly test either for public or for private status the confidence.
     let test_public = rand::random::<bool>();
+    let unreachable_addr: Multiaddr = "/ip4/127.0.0.1/tcp/42".parse().unwrap();
     if test_public {
         client.listen().await;
     } else {
-        let unreachable_addr = "/ip4/127.0.0.1/tcp/42".parse().unwrap();
-        client.behaviour_mut().probe_address(unreachable_addr);
+        client
+            .behaviour_mut()
+            .probe_address(unreachable_addr.clone());
     }
 
     for i in 0..MAX_CONFIDENCE + 1 {
@@ -170,12 +173,14 @@ async fn test_confidence() {
             other => panic!("Unexpected behaviour event: {other:?}."),
         };
 
-        match client.next_behaviour_event().await {
+        let tested_addr = match client.next_behaviour_event().await {
             Event::OutboundProbe(event) => {
-                let (peer, probe_id) = match event {
-                    OutboundProbeEvent::Response { probe_id, peer, .. } if test_public => {
-                        (peer, probe_id)
-                    }
+                let (peer, probe_id, tested_addr) = match event {
+                    OutboundProbeEvent::Response {
+                        probe_id,
+                        peer,
+                        address,
+                    } if test_public => (peer, probe_id, address),
                     OutboundProbeEvent::Error {
                         probe_id,
                         peer,
@@ -185,15 +190,16 @@ async fn test_confidence() {
                             error,
                             OutboundProbeError::Response(ResponseError::DialError)
                         );
-                        (peer.unwrap(), probe_id)
+                        (peer.unwrap(), probe_id, unreachable_addr.clone())
                     }
                     other => panic!("Unexpected Outbound Event: {other:?}"),
                 };
                 assert_eq!(peer, server_id);
                 assert_eq!(probe_id, id);
+                tested_addr
             }
             other => panic!("Unexpected behaviour event: {other:?}."),
-        }
+        };
 
         // Confidence should increase each iteration up to MAX_CONFIDENCE
         let expect_confidence = if i <= MAX_CONFIDENCE {
@@ -204,12 +210,28 @@ async fn test_confidence() {
         assert_eq!(client.behaviour().confidence(), expect_confidence);
         assert_eq!(client.behaviour().nat_status().is_public(), test_public);
 
+        // The tested address is tracked with the same confidence.
+        let status = client.behaviour().address_status(&tested_addr).unwrap();
+        let expect_reachability = if test_public {
+            Reachability::Public
+        } else {
+            Reachability::Private
+        };
+        assert_eq!(status.reachability, expect_reachability);
+        assert_eq!(status.confidence, expect_confidence);
+
         // Expect status to flip after first probe
         if i == 0 {
             match client.next_behaviour_event().await {
-                Event::StatusChanged { old, new } => {
+                Event::StatusChanged { old, new, reports } => {
                     assert_eq!(old, NatStatus::Unknown);
                     assert_eq!(new.is_public(), test_public);
+                    // The flip was caused by the response to this probe.
+                    assert_eq!(reports.len(), 1);
+                    assert_eq!(reports[0].probe_id, id);
+                    assert_eq!(reports[0].peer, server_id);
+                    assert_eq!(reports[0].result.is_ok(), test_public);
+                    assert_eq!(reports[0].confidence, 0);
                 }
                 other => panic!("Unexpected behaviour event: {other:?}."),
             }
@@ -243,7 +265,7 @@ async fn test_throttle_server_period() {
     // First probe should be successful and flip status to public.
     loop {
         match client.next_behaviour_event().await {
-            Event::StatusChanged { old, new } => {
+            Event::StatusChanged { old, new, .. } => {
                 assert_eq!(old, NatStatus::Unknown);
                 assert!(new.is_public());
                 break;
@@ -336,7 +358,7 @@ async fn test_outbound_failure() {
     // First probe should be successful and flip status to public.
     loop {
         match client.next_behaviour_event().await {
-            Event::StatusChanged { old, new } => {
+            Event::StatusChanged { old, new, .. } => {
                 assert_eq!(old, NatStatus::Unknown);
                 assert!(new.is_public());
                 break;
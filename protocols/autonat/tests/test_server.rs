@@ -19,7 +19,7 @@
 // DEALINGS IN THE SOFTWARE.
 
 use libp2p_autonat::{
-    Behaviour, Config, Event, InboundProbeError, InboundProbeEvent, ResponseError,
+    Behaviour, Config, Event, InboundProbeError, InboundProbeEvent, ResponseError, ServerMode,
 };
 use libp2p_core::{multiaddr::Protocol, ConnectedPoint, Endpoint, Multiaddr};
 use libp2p_identity::PeerId;
@@ -345,6 +345,30 @@ async fn test_global_ips_config() {
     };
 }
 
+#[async_std::test]
+async fn test_server_mode_auto() {
+    let (mut server, server_id, server_addr) = new_server_swarm(Some(Config {
+        only_global_ips: false,
+        server_mode: ServerMode::Auto,
+        ..Default::default()
+    }))
+    .await;
+    assert!(!server.behaviour().is_server_enabled());
+
+    let (mut client, _) = new_client_swarm(server_id, server_addr).await;
+    client.listen().await;
+    async_std::task::spawn(client.loop_on_next());
+
+    // Expect the probe to be refused as the server's own reachability is still unknown.
+    match server.next_behaviour_event().await {
+        Event::InboundProbe(InboundProbeEvent::Error { error, .. }) => assert!(matches!(
+            error,
+            InboundProbeError::Response(ResponseError::DialRefused)
+        )),
+        other => panic!("Unexpected behaviour event: {other:?}."),
+    };
+}
+
 async fn new_server_swarm(config: Option<Config>) -> (Swarm<Behaviour>, PeerId, Multiaddr) {
     let mut config = config.unwrap_or_else(|| Config {
         only_global_ips: false,
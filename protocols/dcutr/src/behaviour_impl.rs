@@ -21,7 +21,12 @@
 //! [`NetworkBehaviour`] to act as a direct connection upgrade through relay node.
 
 use crate::handler;
+use crate::protocol::TransportProtocol;
 use either::Either;
+use futures::future::{BoxFuture, FutureExt};
+use futures::stream::{FuturesUnordered, StreamExt};
+use futures_timer::Delay;
+use instant::Instant;
 use libp2p_core::connection::ConnectedPoint;
 use libp2p_core::multiaddr::Protocol;
 use libp2p_core::{Endpoint, Multiaddr};
@@ -37,10 +42,48 @@ use libp2p_swarm::{
 };
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::task::{Context, Poll};
+use std::time::Duration;
 use thiserror::Error;
 use void::Void;
 
-pub(crate) const MAX_NUMBER_OF_UPGRADE_ATTEMPTS: u8 = 3;
+/// Configuration for the [`Behaviour`].
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The number of hole punch attempts made by the listening side of a relayed connection
+    /// before giving up.
+    pub max_attempts: u8,
+    /// The delay between a failed hole punch attempt and the next one.
+    pub retry_delay: Duration,
+    /// The transports to hole punch, in order of preference.
+    ///
+    /// Addresses are exchanged and dialed in this order, followed by the addresses of transports
+    /// not listed. When initiating an upgrade, the first of these transports the remote has
+    /// addresses of as well is the one hole punched through by both peers, and only its
+    /// addresses are dialed.
+    ///
+    /// Note that the swarm dials the addresses of an attempt concurrently, up to its dial
+    /// concurrency factor. When the peers have no transport in common, the order thus does not
+    /// hold back the dials of less preferred transports until the preferred ones failed.
+    pub transport_preference: Vec<TransportProtocol>,
+    /// The period after all attempts with a peer failed during which no upgrades of further
+    /// relayed connections to the peer are attempted.
+    pub fallback_backoff: Duration,
+    /// The interval at which the upgrade of a relayed connection fallen back to is retried, if
+    /// at all.
+    pub fallback_retry_interval: Option<Duration>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            retry_delay: Duration::ZERO,
+            transport_preference: vec![TransportProtocol::Quic, TransportProtocol::Tcp],
+            fallback_backoff: Duration::from_secs(5 * 60),
+            fallback_retry_interval: None,
+        }
+    }
+}
 
 /// The events produced by the [`Behaviour`].
 #[derive(Debug)]
@@ -60,6 +103,47 @@ pub enum Event {
         remote_peer_id: PeerId,
         error: Error,
     },
+    /// The relayed connection to a peer is kept as a fallback, as all attempts to upgrade it
+    /// failed or upgrades with the peer are backed off after earlier failures.
+    ///
+    /// Only reported by the initiator of upgrades, i.e. the listening side of the relayed
+    /// connection. See [`Config::fallback_backoff`] and [`Config::fallback_retry_interval`].
+    FellBackToRelay {
+        remote_peer_id: PeerId,
+        relayed_connection_id: ConnectionId,
+    },
+    /// A single hole punch attempt finished, successfully or not.
+    ///
+    /// Reported by both peers for each of their attempts, ahead of
+    /// [`Event::DirectConnectionUpgradeSucceeded`] or [`Event::DirectConnectionUpgradeFailed`].
+    HolePunchAttempt {
+        remote_peer_id: PeerId,
+        /// The round-trip time over the relayed connection the dials were synchronized with.
+        ///
+        /// Only measured by the initiator of the upgrade, i.e. the listening side of the relayed
+        /// connection.
+        rtt: Option<Duration>,
+        /// The transport negotiated to hole punch through, if any.
+        transport: Option<TransportProtocol>,
+        /// The addresses of the remote that were dialed.
+        remote_addrs: Vec<Multiaddr>,
+        /// The time from starting to dial until the attempt succeeded or failed.
+        elapsed: Duration,
+        result: Result<DirectConnection, Error>,
+    },
+}
+
+/// The direct connection established by a successful hole punch attempt.
+#[derive(Debug, Clone)]
+pub struct DirectConnection {
+    /// The address of the remote the connection was established to.
+    pub address: Multiaddr,
+    /// The role of the local peer on the connection.
+    ///
+    /// [`Endpoint::Dialer`] if the connection was established through the local dial,
+    /// [`Endpoint::Listener`] if through the dial of the remote. As both peers dial each other,
+    /// either can be the case for both of them.
+    pub role: Endpoint,
 }
 
 #[derive(Debug, Error)]
@@ -71,6 +155,8 @@ pub enum Error {
 }
 
 pub struct Behaviour {
+    config: Config,
+
     /// Queue of actions to return when polled.
     queued_events: VecDeque<ToSwarm<Event, Either<handler::relayed::Command, Void>>>,
 
@@ -86,27 +172,190 @@ pub struct Behaviour {
     /// Indexed by the [`ConnectionId`] of the relayed connection and
     /// the [`PeerId`] we are trying to establish a direct connection to.
     outgoing_direct_connection_attempts: HashMap<(ConnectionId, PeerId), u8>,
+
+    /// Ongoing hole punch attempts, indexed by the [`ConnectionId`] of their direct connection.
+    hole_punch_attempts: HashMap<ConnectionId, Attempt>,
+
+    /// Hole punch attempts to be retried once their [`Config::retry_delay`] elapsed.
+    pending_retries: FuturesUnordered<BoxFuture<'static, (ConnectionId, PeerId)>>,
+
+    /// Relayed connections fallen back to, with the peer on the other end.
+    fallback_connections: HashMap<ConnectionId, PeerId>,
+    /// Until when no upgrades with a peer are attempted, after all attempts with it failed.
+    backoffs: HashMap<PeerId, Instant>,
+    /// Upgrades of connections fallen back to, to be retried once
+    /// [`Config::fallback_retry_interval`] elapsed.
+    scheduled_retries: FuturesUnordered<BoxFuture<'static, (ConnectionId, PeerId)>>,
+}
+
+struct Attempt {
+    remote_peer_id: PeerId,
+    rtt: Option<Duration>,
+    transport: Option<TransportProtocol>,
+    remote_addrs: Vec<Multiaddr>,
+    started: Instant,
 }
 
 impl Behaviour {
     pub fn new(local_peer_id: PeerId) -> Self {
+        Self::with_config(local_peer_id, Config::default())
+    }
+
+    pub fn with_config(local_peer_id: PeerId, config: Config) -> Self {
         Behaviour {
+            config,
             queued_events: Default::default(),
             direct_connections: Default::default(),
             external_addresses: Default::default(),
             local_peer_id,
             direct_to_relayed_connections: Default::default(),
             outgoing_direct_connection_attempts: Default::default(),
+            hole_punch_attempts: Default::default(),
+            pending_retries: Default::default(),
+            fallback_connections: Default::default(),
+            backoffs: Default::default(),
+            scheduled_retries: Default::default(),
+        }
+    }
+
+    /// Returns whether the given relayed connection is kept as a fallback, see
+    /// [`Event::FellBackToRelay`].
+    pub fn is_fallback(&self, connection_id: &ConnectionId) -> bool {
+        self.fallback_connections.contains_key(connection_id)
+    }
+
+    /// Returns whether upgrades with the given peer are backed off.
+    fn is_backed_off(&mut self, peer_id: &PeerId) -> bool {
+        match self.backoffs.get(peer_id) {
+            Some(until) if *until > Instant::now() => true,
+            Some(_) => {
+                self.backoffs.remove(peer_id);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Tags the given relayed connection as fallback, scheduling a retry if configured.
+    fn fall_back(&mut self, peer_id: PeerId, relayed_connection_id: ConnectionId) {
+        self.fallback_connections
+            .insert(relayed_connection_id, peer_id);
+        self.queued_events
+            .push_back(ToSwarm::GenerateEvent(Event::FellBackToRelay {
+                remote_peer_id: peer_id,
+                relayed_connection_id,
+            }));
+
+        if let Some(interval) = self.config.fallback_retry_interval {
+            self.scheduled_retries.push(
+                async move {
+                    Delay::new(interval).await;
+                    (relayed_connection_id, peer_id)
+                }
+                .boxed(),
+            );
         }
     }
 
     fn observed_addresses(&self) -> Vec<Multiaddr> {
-        self.external_addresses
+        let mut addresses: Vec<_> = self
+            .external_addresses
             .iter()
             .cloned()
             .filter(|a| !a.iter().any(|p| p == Protocol::P2pCircuit))
             .map(|a| a.with(Protocol::P2p(self.local_peer_id)))
-            .collect()
+            .collect();
+        self.sort_by_preference(&mut addresses);
+        addresses
+    }
+
+    /// Orders the given addresses by [`Config::transport_preference`].
+    fn sort_by_preference(&self, addresses: &mut [Multiaddr]) {
+        let preference = &self.config.transport_preference;
+        addresses.sort_by_key(|a| {
+            TransportProtocol::of(a)
+                .and_then(|t| preference.iter().position(|p| *p == t))
+                .unwrap_or(preference.len())
+        });
+    }
+
+    /// Dials the remote, tracking the dial as a hole punch attempt.
+    fn dial_attempt(
+        &mut self,
+        opts: DialOpts,
+        relayed_connection_id: ConnectionId,
+        attempt: Attempt,
+    ) {
+        let direct_connection_id = opts.connection_id();
+
+        self.direct_to_relayed_connections
+            .insert(direct_connection_id, relayed_connection_id);
+        self.hole_punch_attempts
+            .insert(direct_connection_id, attempt);
+        self.queued_events.push_back(ToSwarm::Dial { opts });
+    }
+
+    /// Reports the hole punch attempt of the given direct connection as finished.
+    fn finish_attempt(
+        &mut self,
+        remote_peer_id: PeerId,
+        direct_connection_id: ConnectionId,
+        result: Result<DirectConnection, Error>,
+    ) {
+        let Some(Attempt {
+            rtt,
+            remote_peer_id: _,
+            transport,
+            remote_addrs,
+            started,
+        }) = self.hole_punch_attempts.remove(&direct_connection_id)
+        else {
+            return;
+        };
+
+        self.queued_events
+            .push_back(ToSwarm::GenerateEvent(Event::HolePunchAttempt {
+                remote_peer_id,
+                rtt,
+                transport,
+                remote_addrs,
+                elapsed: started.elapsed(),
+                result,
+            }));
+    }
+
+    /// Reports the upgrade of the given relayed connection as succeeded, through the given direct
+    /// connection established by a hole punch attempt.
+    fn on_upgraded(
+        &mut self,
+        peer_id: PeerId,
+        relayed_connection_id: ConnectionId,
+        direct_connection_id: ConnectionId,
+        endpoint: &ConnectedPoint,
+    ) {
+        self.fallback_connections.remove(&relayed_connection_id);
+        self.backoffs.remove(&peer_id);
+        self.finish_attempt(
+            peer_id,
+            direct_connection_id,
+            Ok(DirectConnection {
+                address: endpoint.get_remote_address().clone(),
+                role: endpoint.to_endpoint(),
+            }),
+        );
+        self.queued_events.extend([ToSwarm::GenerateEvent(
+            Event::DirectConnectionUpgradeSucceeded {
+                remote_peer_id: peer_id,
+            },
+        )]);
+    }
+
+    fn new_handler(&self, endpoint: ConnectedPoint) -> handler::relayed::Handler {
+        handler::relayed::Handler::new(
+            endpoint,
+            self.observed_addresses(),
+            self.config.max_attempts,
+        )
     }
 
     fn on_dial_failure(
@@ -132,6 +381,8 @@ impl Behaviour {
             return;
         };
 
+        self.finish_attempt(peer_id, failed_direct_connection, Err(Error::Dial));
+
         let attempt = if let Some(attempt) = self
             .outgoing_direct_connection_attempts
             .get(&(relayed_connection_id, peer_id))
@@ -141,12 +392,15 @@ impl Behaviour {
             return;
         };
 
-        if attempt < MAX_NUMBER_OF_UPGRADE_ATTEMPTS {
-            self.queued_events.push_back(ToSwarm::NotifyHandler {
-                handler: NotifyHandler::One(relayed_connection_id),
-                peer_id,
-                event: Either::Left(handler::relayed::Command::Connect),
-            })
+        if attempt < self.config.max_attempts {
+            let retry_delay = self.config.retry_delay;
+            self.pending_retries.push(
+                async move {
+                    Delay::new(retry_delay).await;
+                    (relayed_connection_id, peer_id)
+                }
+                .boxed(),
+            );
         } else {
             self.queued_events.extend([ToSwarm::GenerateEvent(
                 Event::DirectConnectionUpgradeFailed {
@@ -154,9 +408,24 @@ impl Behaviour {
                     error: Error::Dial,
                 },
             )]);
+
+            self.give_up(peer_id, relayed_connection_id);
         }
     }
 
+    /// Keeps the given relayed connection as a fallback and backs off further upgrades with
+    /// the peer, after the upgrade of the connection failed for good.
+    fn give_up(&mut self, peer_id: PeerId, relayed_connection_id: ConnectionId) {
+        self.backoffs
+            .insert(peer_id, Instant::now() + self.config.fallback_backoff);
+        self.queued_events.push_back(ToSwarm::NotifyHandler {
+            handler: NotifyHandler::One(relayed_connection_id),
+            peer_id,
+            event: Either::Left(handler::relayed::Command::Fallback),
+        });
+        self.fall_back(peer_id, relayed_connection_id);
+    }
+
     fn on_connection_closed(
         &mut self,
         ConnectionClosed {
@@ -178,6 +447,8 @@ impl Behaviour {
             if connections.is_empty() {
                 self.direct_connections.remove(&peer_id);
             }
+        } else {
+            self.fallback_connections.remove(&connection_id);
         }
     }
 }
@@ -198,8 +469,15 @@ impl NetworkBehaviour for Behaviour {
                 local_addr: local_addr.clone(),
                 send_back_addr: remote_addr.clone(),
             };
-            let mut handler =
-                handler::relayed::Handler::new(connected_point, self.observed_addresses());
+            let mut handler = self.new_handler(connected_point);
+
+            if self.is_backed_off(&peer) {
+                handler.on_behaviour_event(handler::relayed::Command::Fallback);
+                self.fall_back(peer, connection_id);
+
+                return Ok(Either::Left(handler));
+            }
+
             handler.on_behaviour_event(handler::relayed::Command::Connect);
 
             self.queued_events.extend([ToSwarm::GenerateEvent(
@@ -223,6 +501,31 @@ impl NetworkBehaviour for Behaviour {
             "state mismatch"
         );
 
+        // The dial of the remote may succeed before the local one of an ongoing hole punch
+        // attempt, which then no longer counts towards the upgrade.
+        let attempt = self
+            .hole_punch_attempts
+            .iter()
+            .find(|(_, attempt)| attempt.remote_peer_id == peer)
+            .map(|(id, _)| *id);
+        if let Some(attempted_connection_id) = attempt {
+            let relayed_connection_id = self
+                .direct_to_relayed_connections
+                .remove(&attempted_connection_id)
+                .expect("Hole punch attempt to be tracked.");
+            self.outgoing_direct_connection_attempts
+                .remove(&(relayed_connection_id, peer));
+            self.on_upgraded(
+                peer,
+                relayed_connection_id,
+                attempted_connection_id,
+                &ConnectedPoint::Listener {
+                    local_addr: local_addr.clone(),
+                    send_back_addr: remote_addr.clone(),
+                },
+            );
+        }
+
         Ok(Either::Right(dummy::ConnectionHandler))
     }
 
@@ -234,13 +537,10 @@ impl NetworkBehaviour for Behaviour {
         role_override: Endpoint,
     ) -> Result<THandler<Self>, ConnectionDenied> {
         if is_relayed(addr) {
-            return Ok(Either::Left(handler::relayed::Handler::new(
-                ConnectedPoint::Dialer {
-                    address: addr.clone(),
-                    role_override,
-                },
-                self.observed_addresses(),
-            ))); // TODO: We could make two `handler::relayed::Handler` here, one inbound one outbound.
+            return Ok(Either::Left(self.new_handler(ConnectedPoint::Dialer {
+                address: addr.clone(),
+                role_override,
+            }))); // TODO: We could make two `handler::relayed::Handler` here, one inbound one outbound.
         }
 
         self.direct_connections
@@ -260,11 +560,15 @@ impl NetworkBehaviour for Behaviour {
                 );
             }
 
-            self.queued_events.extend([ToSwarm::GenerateEvent(
-                Event::DirectConnectionUpgradeSucceeded {
-                    remote_peer_id: peer,
+            self.on_upgraded(
+                peer,
+                relayed_connection_id,
+                connection_id,
+                &ConnectedPoint::Dialer {
+                    address: addr.clone(),
+                    role_override,
                 },
-            )]);
+            );
         }
 
         Ok(Either::Right(dummy::ConnectionHandler))
@@ -304,17 +608,27 @@ impl NetworkBehaviour for Behaviour {
                     },
                 ));
             }
-            Either::Left(handler::relayed::Event::InboundConnectNegotiated(remote_addrs)) => {
+            Either::Left(handler::relayed::Event::InboundConnectNegotiated {
+                mut remote_addrs,
+                transport,
+            }) => {
+                self.sort_by_preference(&mut remote_addrs);
                 let opts = DialOpts::peer_id(event_source)
-                    .addresses(remote_addrs)
+                    .addresses(remote_addrs.clone())
                     .condition(dial_opts::PeerCondition::Always)
                     .build();
 
-                let maybe_direct_connection_id = opts.connection_id();
-
-                self.direct_to_relayed_connections
-                    .insert(maybe_direct_connection_id, relayed_connection_id);
-                self.queued_events.push_back(ToSwarm::Dial { opts });
+                self.dial_attempt(
+                    opts,
+                    relayed_connection_id,
+                    Attempt {
+                        remote_peer_id: event_source,
+                        rtt: None,
+                        transport,
+                        remote_addrs,
+                        started: Instant::now(),
+                    },
+                );
             }
             Either::Left(handler::relayed::Event::OutboundNegotiationFailed { error }) => {
                 self.queued_events.push_back(ToSwarm::GenerateEvent(
@@ -323,23 +637,38 @@ impl NetworkBehaviour for Behaviour {
                         error: Error::Handler(error),
                     },
                 ));
+                // Without negotiating the protocol, there is nothing left to retry.
+                self.outgoing_direct_connection_attempts
+                    .remove(&(relayed_connection_id, event_source));
+                self.give_up(event_source, relayed_connection_id);
             }
-            Either::Left(handler::relayed::Event::OutboundConnectNegotiated { remote_addrs }) => {
+            Either::Left(handler::relayed::Event::OutboundConnectNegotiated {
+                mut remote_addrs,
+                transport,
+                rtt,
+            }) => {
+                self.sort_by_preference(&mut remote_addrs);
                 let opts = DialOpts::peer_id(event_source)
                     .condition(dial_opts::PeerCondition::Always)
-                    .addresses(remote_addrs)
+                    .addresses(remote_addrs.clone())
                     .override_role()
                     .build();
 
-                let maybe_direct_connection_id = opts.connection_id();
-
-                self.direct_to_relayed_connections
-                    .insert(maybe_direct_connection_id, relayed_connection_id);
+                self.dial_attempt(
+                    opts,
+                    relayed_connection_id,
+                    Attempt {
+                        remote_peer_id: event_source,
+                        rtt: Some(rtt),
+                        transport,
+                        remote_addrs,
+                        started: Instant::now(),
+                    },
+                );
                 *self
                     .outgoing_direct_connection_attempts
                     .entry((relayed_connection_id, event_source))
                     .or_default() += 1;
-                self.queued_events.push_back(ToSwarm::Dial { opts });
             }
             Either::Right(never) => void::unreachable(never),
         };
@@ -347,13 +676,46 @@ impl NetworkBehaviour for Behaviour {
 
     fn poll(
         &mut self,
-        _cx: &mut Context<'_>,
+        cx: &mut Context<'_>,
         _: &mut impl PollParameters,
     ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
         if let Some(event) = self.queued_events.pop_front() {
             return Poll::Ready(event);
         }
 
+        let now = Instant::now();
+        self.backoffs.retain(|_, until| *until > now);
+
+        if let Poll::Ready(Some((relayed_connection_id, peer_id))) =
+            self.pending_retries.poll_next_unpin(cx)
+        {
+            return Poll::Ready(ToSwarm::NotifyHandler {
+                handler: NotifyHandler::One(relayed_connection_id),
+                peer_id,
+                event: Either::Left(handler::relayed::Command::Connect),
+            });
+        }
+
+        while let Poll::Ready(Some((relayed_connection_id, peer_id))) =
+            self.scheduled_retries.poll_next_unpin(cx)
+        {
+            if !self
+                .fallback_connections
+                .contains_key(&relayed_connection_id)
+            {
+                // The connection closed or has been upgraded in the meantime.
+                continue;
+            }
+
+            self.outgoing_direct_connection_attempts
+                .remove(&(relayed_connection_id, peer_id));
+            return Poll::Ready(ToSwarm::NotifyHandler {
+                handler: NotifyHandler::One(relayed_connection_id),
+                peer_id,
+                event: Either::Left(handler::relayed::Command::Retry),
+            });
+        }
+
         Poll::Pending
     }
 
@@ -20,7 +20,6 @@
 
 //! [`ConnectionHandler`] handling relayed connection potentially upgraded to a direct connection.
 
-use crate::behaviour_impl::MAX_NUMBER_OF_UPGRADE_ATTEMPTS;
 use crate::protocol;
 use either::Either;
 use futures::future;
@@ -37,10 +36,16 @@ use libp2p_swarm::{
 };
 use std::collections::VecDeque;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 #[derive(Debug)]
 pub enum Command {
     Connect,
+    /// Start a new round of attempts on a connection fallen back to.
+    Retry,
+    /// Stop keeping the connection alive for hole punching, as the connection fell back to being
+    /// relayed.
+    Fallback,
 }
 
 #[derive(Debug)]
@@ -51,12 +56,17 @@ pub enum Event {
     InboundNegotiationFailed {
         error: StreamUpgradeError<void::Void>,
     },
-    InboundConnectNegotiated(Vec<Multiaddr>),
+    InboundConnectNegotiated {
+        remote_addrs: Vec<Multiaddr>,
+        transport: Option<protocol::TransportProtocol>,
+    },
     OutboundNegotiationFailed {
         error: StreamUpgradeError<void::Void>,
     },
     OutboundConnectNegotiated {
         remote_addrs: Vec<Multiaddr>,
+        transport: Option<protocol::TransportProtocol>,
+        rtt: Duration,
     },
 }
 
@@ -78,17 +88,27 @@ pub struct Handler {
         >,
     >,
     /// Inbound connect, accepted by the behaviour, pending completion.
-    inbound_connect:
-        Option<BoxFuture<'static, Result<Vec<Multiaddr>, protocol::inbound::UpgradeError>>>,
+    inbound_connect: Option<
+        BoxFuture<'static, Result<protocol::inbound::Connect, protocol::inbound::UpgradeError>>,
+    >,
 
     /// The addresses we will send to the other party for hole-punching attempts.
     holepunch_candidates: Vec<Multiaddr>,
 
     attempts: u8,
+    /// The number of attempts after which the connection is no longer kept alive for
+    /// hole punching.
+    max_attempts: u8,
+    /// Whether the connection fell back to being relayed.
+    fallback: bool,
 }
 
 impl Handler {
-    pub fn new(endpoint: ConnectedPoint, holepunch_candidates: Vec<Multiaddr>) -> Self {
+    pub fn new(
+        endpoint: ConnectedPoint,
+        holepunch_candidates: Vec<Multiaddr>,
+        max_attempts: u8,
+    ) -> Self {
         Self {
             endpoint,
             pending_error: Default::default(),
@@ -96,6 +116,8 @@ impl Handler {
             inbound_connect: Default::default(),
             holepunch_candidates,
             attempts: 0,
+            max_attempts,
+            fallback: false,
         }
     }
 
@@ -142,7 +164,12 @@ impl Handler {
     fn on_fully_negotiated_outbound(
         &mut self,
         FullyNegotiatedOutbound {
-            protocol: protocol::outbound::Connect { obs_addrs },
+            protocol:
+                protocol::outbound::Connect {
+                    obs_addrs,
+                    transport,
+                    rtt,
+                },
             ..
         }: FullyNegotiatedOutbound<
             <Self as ConnectionHandler>::OutboundProtocol,
@@ -157,6 +184,8 @@ impl Handler {
             .push_back(ConnectionHandlerEvent::NotifyBehaviour(
                 Event::OutboundConnectNegotiated {
                     remote_addrs: obs_addrs,
+                    transport,
+                    rtt,
                 },
             ));
     }
@@ -174,6 +203,17 @@ impl Handler {
         }));
     }
 
+    fn connect(&mut self) {
+        self.queued_events
+            .push_back(ConnectionHandlerEvent::OutboundSubstreamRequest {
+                protocol: SubstreamProtocol::new(
+                    protocol::outbound::Upgrade::new(self.holepunch_candidates.clone()),
+                    (),
+                ),
+            });
+        self.attempts += 1;
+    }
+
     fn on_dial_upgrade_error(
         &mut self,
         DialUpgradeError { error, .. }: DialUpgradeError<
@@ -239,16 +279,13 @@ impl ConnectionHandler for Handler {
 
     fn on_behaviour_event(&mut self, event: Self::FromBehaviour) {
         match event {
-            Command::Connect => {
-                self.queued_events
-                    .push_back(ConnectionHandlerEvent::OutboundSubstreamRequest {
-                        protocol: SubstreamProtocol::new(
-                            protocol::outbound::Upgrade::new(self.holepunch_candidates.clone()),
-                            (),
-                        ),
-                    });
-                self.attempts += 1;
+            Command::Connect => self.connect(),
+            Command::Retry => {
+                self.fallback = false;
+                self.attempts = 0;
+                self.connect();
             }
+            Command::Fallback => self.fallback = true,
         }
     }
 
@@ -261,7 +298,11 @@ impl ConnectionHandler for Handler {
             return KeepAlive::Yes;
         }
 
-        if self.attempts < MAX_NUMBER_OF_UPGRADE_ATTEMPTS {
+        if self.fallback {
+            return KeepAlive::No;
+        }
+
+        if self.attempts < self.max_attempts {
             return KeepAlive::Yes;
         }
 
@@ -293,9 +334,15 @@ impl ConnectionHandler for Handler {
         if let Some(Poll::Ready(result)) = self.inbound_connect.as_mut().map(|f| f.poll_unpin(cx)) {
             self.inbound_connect = None;
             match result {
-                Ok(addresses) => {
+                Ok(protocol::inbound::Connect {
+                    remote_obs_addrs,
+                    transport,
+                }) => {
                     return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(
-                        Event::InboundConnectNegotiated(addresses),
+                        Event::InboundConnectNegotiated {
+                            remote_addrs: remote_obs_addrs,
+                            transport,
+                        },
                     ));
                 }
                 Err(e) => {
@@ -34,8 +34,11 @@ mod proto {
 }
 
 pub use behaviour_impl::Behaviour;
+pub use behaviour_impl::Config;
+pub use behaviour_impl::DirectConnection;
 pub use behaviour_impl::Error;
 pub use behaviour_impl::Event;
+pub use protocol::TransportProtocol;
 pub use protocol::PROTOCOL_NAME;
 pub mod inbound {
     pub use crate::protocol::inbound::UpgradeError;
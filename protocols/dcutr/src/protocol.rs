@@ -18,6 +18,8 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
+use libp2p_core::multiaddr::Protocol;
+use libp2p_core::Multiaddr;
 use libp2p_swarm::StreamProtocol;
 
 pub(crate) mod inbound;
@@ -25,3 +27,88 @@ pub(crate) mod outbound;
 pub const PROTOCOL_NAME: StreamProtocol = StreamProtocol::new("/libp2p/dcutr");
 
 const MAX_MESSAGE_SIZE_BYTES: usize = 4096;
+
+/// A transport protocol hole punching can be attempted through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TransportProtocol {
+    Tcp,
+    Quic,
+}
+
+impl TransportProtocol {
+    /// Returns the transport protocol of the given address, if hole punching through it is
+    /// supported.
+    pub(crate) fn of(addr: &Multiaddr) -> Option<Self> {
+        let mut transport = None;
+        for protocol in addr.iter() {
+            match protocol {
+                Protocol::Tcp(_) => transport = Some(TransportProtocol::Tcp),
+                Protocol::Quic | Protocol::QuicV1 => transport = Some(TransportProtocol::Quic),
+                Protocol::Ws(_) | Protocol::Wss(_) | Protocol::WebTransport => return None,
+                _ => {}
+            }
+        }
+        transport
+    }
+}
+
+/// Negotiates the transport to hole punch through from the addresses exchanged by both peers.
+///
+/// This is the transport of the first address sent by the initiator of the upgrade, i.e. the
+/// listening side of the relayed connection, that the responder has an address of as well.
+/// As both peers know both lists of addresses, they agree on the transport without further
+/// messages. Returns [`None`] if the peers have no such transport in common.
+pub(crate) fn negotiate_transport(
+    initiator_addrs: &[Multiaddr],
+    responder_addrs: &[Multiaddr],
+) -> Option<TransportProtocol> {
+    initiator_addrs
+        .iter()
+        .filter_map(TransportProtocol::of)
+        .find(|transport| {
+            responder_addrs
+                .iter()
+                .any(|a| TransportProtocol::of(a) == Some(*transport))
+        })
+}
+
+/// Restricts the remote addresses to dial to those of the negotiated transport.
+///
+/// Without a common transport, all addresses are dialed.
+pub(crate) fn retain_negotiated(
+    remote_addrs: &mut Vec<Multiaddr>,
+    transport: Option<TransportProtocol>,
+) {
+    let Some(transport) = transport else {
+        return;
+    };
+
+    log::debug!("Hole punching through {transport:?}");
+    remote_addrs.retain(|a| TransportProtocol::of(a) == Some(transport));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiates_first_common_transport_of_initiator() {
+        let tcp: Multiaddr = "/ip4/1.2.3.4/tcp/4001".parse().unwrap();
+        let quic: Multiaddr = "/ip4/1.2.3.4/udp/4001/quic-v1".parse().unwrap();
+        let ws: Multiaddr = "/ip4/1.2.3.4/tcp/4002/ws".parse().unwrap();
+
+        assert_eq!(
+            negotiate_transport(&[quic.clone(), tcp.clone()], &[tcp.clone(), quic.clone()]),
+            Some(TransportProtocol::Quic)
+        );
+        assert_eq!(
+            negotiate_transport(&[tcp.clone(), quic.clone()], &[quic.clone(), tcp.clone()]),
+            Some(TransportProtocol::Tcp)
+        );
+        assert_eq!(
+            negotiate_transport(&[quic.clone(), tcp.clone()], std::slice::from_ref(&tcp)),
+            Some(TransportProtocol::Tcp)
+        );
+        assert_eq!(negotiate_transport(&[quic], &[tcp, ws]), None);
+    }
+}
@@ -19,6 +19,7 @@
 // DEALINGS IN THE SOFTWARE.
 
 use crate::proto;
+use crate::protocol::TransportProtocol;
 use asynchronous_codec::Framed;
 use futures::{future::BoxFuture, prelude::*};
 use libp2p_core::{multiaddr::Protocol, upgrade, Multiaddr};
@@ -100,7 +101,9 @@ impl PendingConnect {
     pub async fn accept(
         mut self,
         local_obs_addrs: Vec<Multiaddr>,
-    ) -> Result<Vec<Multiaddr>, UpgradeError> {
+    ) -> Result<Connect, UpgradeError> {
+        let transport = super::negotiate_transport(&self.remote_obs_addrs, &local_obs_addrs);
+
         let msg = proto::HolePunch {
             type_pb: proto::Type::CONNECT,
             ObsAddrs: local_obs_addrs.into_iter().map(|a| a.to_vec()).collect(),
@@ -118,10 +121,22 @@ impl PendingConnect {
             proto::Type::SYNC => {}
         }
 
-        Ok(self.remote_obs_addrs)
+        let mut remote_obs_addrs = self.remote_obs_addrs;
+        super::retain_negotiated(&mut remote_obs_addrs, transport);
+
+        Ok(Connect {
+            remote_obs_addrs,
+            transport,
+        })
     }
 }
 
+pub struct Connect {
+    pub remote_obs_addrs: Vec<Multiaddr>,
+    /// The transport negotiated to hole punch through, if any.
+    pub transport: Option<TransportProtocol>,
+}
+
 #[derive(Debug, Error)]
 pub enum UpgradeError {
     #[error(transparent)]
@@ -19,6 +19,7 @@
 // DEALINGS IN THE SOFTWARE.
 
 use crate::proto;
+use crate::protocol::TransportProtocol;
 use asynchronous_codec::Framed;
 use futures::{future::BoxFuture, prelude::*};
 use futures_timer::Delay;
@@ -27,6 +28,7 @@ use libp2p_core::{multiaddr::Protocol, upgrade, Multiaddr};
 use libp2p_swarm::{Stream, StreamProtocol};
 use std::convert::TryFrom;
 use std::iter;
+use std::time::Duration;
 use thiserror::Error;
 
 pub struct Upgrade {
@@ -61,8 +63,9 @@ impl upgrade::OutboundUpgrade<Stream> for Upgrade {
 
         let msg = proto::HolePunch {
             type_pb: proto::Type::CONNECT,
-            ObsAddrs: self.obs_addrs.into_iter().map(|a| a.to_vec()).collect(),
+            ObsAddrs: self.obs_addrs.iter().map(|a| a.to_vec()).collect(),
         };
+        let local_obs_addrs = self.obs_addrs;
 
         async move {
             substream.send(msg).await?;
@@ -79,7 +82,7 @@ impl upgrade::OutboundUpgrade<Stream> for Upgrade {
                 proto::Type::SYNC => return Err(UpgradeError::UnexpectedTypeSync),
             }
 
-            let obs_addrs = if ObsAddrs.is_empty() {
+            let mut obs_addrs = if ObsAddrs.is_empty() {
                 return Err(UpgradeError::NoAddresses);
             } else {
                 ObsAddrs
@@ -102,6 +105,8 @@ impl upgrade::OutboundUpgrade<Stream> for Upgrade {
                     })
                     .collect::<Vec<Multiaddr>>()
             };
+            let transport = super::negotiate_transport(&local_obs_addrs, &obs_addrs);
+            super::retain_negotiated(&mut obs_addrs, transport);
 
             let msg = proto::HolePunch {
                 type_pb: proto::Type::SYNC,
@@ -112,7 +117,11 @@ impl upgrade::OutboundUpgrade<Stream> for Upgrade {
 
             Delay::new(rtt / 2).await;
 
-            Ok(Connect { obs_addrs })
+            Ok(Connect {
+                obs_addrs,
+                transport,
+                rtt,
+            })
         }
         .boxed()
     }
@@ -120,6 +129,10 @@ impl upgrade::OutboundUpgrade<Stream> for Upgrade {
 
 pub struct Connect {
     pub obs_addrs: Vec<Multiaddr>,
+    /// The transport negotiated to hole punch through, if any.
+    pub transport: Option<TransportProtocol>,
+    /// The round-trip time the dials are synchronized with.
+    pub rtt: Duration,
 }
 
 #[derive(Debug, Error)]
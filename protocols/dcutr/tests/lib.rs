@@ -18,9 +18,13 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
+use futures::channel::oneshot;
+use futures::future::{self, Either};
+use futures::StreamExt;
 use libp2p_core::multiaddr::{Multiaddr, Protocol};
 use libp2p_core::transport::upgrade::Version;
 use libp2p_core::transport::{MemoryTransport, Transport};
+use libp2p_core::Endpoint;
 use libp2p_dcutr as dcutr;
 use libp2p_identity as identity;
 use libp2p_identity::PeerId;
@@ -86,13 +90,110 @@ async fn connect() {
 
     let dst_addr = dst_addr.with(Protocol::P2p(dst_peer_id));
 
-    src.wait(move |e| match e {
-        SwarmEvent::ConnectionEstablished { endpoint, .. } => {
-            (*endpoint.get_remote_address() == dst_addr).then_some(())
+    src.wait({
+        let dst_addr = dst_addr.clone();
+        move |e| match e {
+            SwarmEvent::ConnectionEstablished { endpoint, .. } => {
+                (*endpoint.get_remote_address() == dst_addr).then_some(())
+            }
+            _ => None,
         }
-        _ => None,
     })
     .await;
+
+    let (rtt, remote_addrs, direct_connection) = src
+        .wait(|e| match e {
+            SwarmEvent::Behaviour(ClientEvent::Dcutr(dcutr::Event::HolePunchAttempt {
+                rtt,
+                remote_addrs,
+                result,
+                ..
+            })) => Some((rtt, remote_addrs, result)),
+            _ => None,
+        })
+        .await;
+    // The dialing side of the relayed connection responds to the upgrade, thus has no RTT.
+    assert_eq!(rtt, None);
+    assert!(remote_addrs.contains(&dst_addr));
+    let direct_connection = direct_connection.unwrap();
+    assert_eq!(direct_connection.address, dst_addr);
+    assert_eq!(direct_connection.role, Endpoint::Dialer);
+}
+
+#[async_std::test]
+async fn falls_back_to_relay_after_failed_attempts() {
+    let _ = env_logger::try_init();
+
+    let mut relay = build_relay();
+    let mut dst = build_client_with_config(dcutr::Config {
+        max_attempts: 1,
+        ..Default::default()
+    });
+    let mut src = build_client();
+
+    let (relay_addr, _) = relay.listen().await;
+    let relay_peer_id = *relay.local_peer_id();
+    let dst_peer_id = *dst.local_peer_id();
+    let src_peer_id = *src.local_peer_id();
+
+    // Neither peer is reachable at its external address, thus hole punching fails.
+    dst.add_external_address(Multiaddr::empty().with(Protocol::Memory(rand::random::<u64>())));
+    src.add_external_address(Multiaddr::empty().with(Protocol::Memory(rand::random::<u64>())));
+
+    async_std::task::spawn(relay.loop_on_next());
+
+    let dst_relayed_addr = relay_addr
+        .with(Protocol::P2p(relay_peer_id))
+        .with(Protocol::P2pCircuit)
+        .with(Protocol::P2p(dst_peer_id));
+    dst.listen_on(dst_relayed_addr.clone()).unwrap();
+
+    wait_for_reservation(
+        &mut dst,
+        dst_relayed_addr.clone(),
+        relay_peer_id,
+        false, // No renewal.
+    )
+    .await;
+
+    // Dial through the relay a second time once the first relayed connection fell back.
+    let (redial_tx, mut redial_rx) = oneshot::channel();
+    async_std::task::spawn(async move {
+        src.dial(dst_relayed_addr.clone()).unwrap();
+        while let Either::Right(_) = future::select(&mut redial_rx, src.select_next_some()).await {}
+        src.dial(dst_relayed_addr).unwrap();
+        src.loop_on_next().await;
+    });
+    let mut redial_tx = Some(redial_tx);
+
+    let mut initiated = 0;
+    let mut fallbacks = Vec::new();
+    while fallbacks.len() < 2 {
+        match dst.next_behaviour_event().await {
+            ClientEvent::Dcutr(dcutr::Event::InitiatedDirectConnectionUpgrade {
+                remote_peer_id,
+                ..
+            }) => {
+                assert_eq!(remote_peer_id, src_peer_id);
+                initiated += 1;
+            }
+            ClientEvent::Dcutr(dcutr::Event::FellBackToRelay {
+                remote_peer_id,
+                relayed_connection_id,
+            }) => {
+                assert_eq!(remote_peer_id, src_peer_id);
+                fallbacks.push(relayed_connection_id);
+                if let Some(redial_tx) = redial_tx.take() {
+                    redial_tx.send(()).unwrap();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Only the first relayed connection is attempted to be upgraded, the second one is backed off.
+    assert_eq!(initiated, 1);
+    assert!(dst.behaviour().dcutr.is_fallback(&fallbacks[1]));
 }
 
 fn build_relay() -> Swarm<relay::Behaviour> {
@@ -110,6 +211,10 @@ fn build_relay() -> Swarm<relay::Behaviour> {
 }
 
 fn build_client() -> Swarm<Client> {
+    build_client_with_config(Default::default())
+}
+
+fn build_client_with_config(config: dcutr::Config) -> Swarm<Client> {
     let local_key = identity::Keypair::generate_ed25519();
     let local_public_key = local_key.public();
     let local_peer_id = local_public_key.to_peer_id();
@@ -128,7 +233,7 @@ fn build_client() -> Swarm<Client> {
         transport,
         Client {
             relay: behaviour,
-            dcutr: dcutr::Behaviour::new(local_peer_id),
+            dcutr: dcutr::Behaviour::with_config(local_peer_id, config),
         },
         local_peer_id,
     )
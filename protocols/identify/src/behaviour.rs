@@ -20,19 +20,24 @@
 
 use crate::handler::{self, Handler, InEvent};
 use crate::protocol::{Info, UpgradeError};
+use futures::FutureExt;
+use futures_timer::Delay;
+use instant::Instant;
 use libp2p_core::{multiaddr, ConnectedPoint, Endpoint, Multiaddr};
 use libp2p_identity::PeerId;
-use libp2p_identity::PublicKey;
+use libp2p_identity::{Keypair, PublicKey};
 use libp2p_swarm::behaviour::{ConnectionClosed, ConnectionEstablished, DialFailure, FromSwarm};
 use libp2p_swarm::{
     ConnectionDenied, DialError, ExternalAddresses, ListenAddresses, NetworkBehaviour,
-    NotifyHandler, PollParameters, StreamUpgradeError, THandlerInEvent, ToSwarm,
+    NotifyHandler, PollParameters, StreamProtocol, StreamUpgradeError, THandlerInEvent, ToSwarm,
 };
 use libp2p_swarm::{ConnectionId, THandler, THandlerOutEvent};
 use lru::LruCache;
 use std::num::NonZeroUsize;
+use std::sync::Arc;
 use std::{
     collections::{HashMap, HashSet, VecDeque},
+    fmt,
     iter::FromIterator,
     task::Context,
     task::Poll,
@@ -43,7 +48,9 @@ use std::{
 /// about them, and answers identify queries from other nodes.
 ///
 /// All external addresses of the local node supposedly observed by remotes
-/// are reported via [`ToSwarm::NewExternalAddrCandidate`].
+/// are reported via [`ToSwarm::NewExternalAddrCandidate`], once enough peers
+/// reported them, see [`Config::observed_addr_confirmations`]. An address is reported
+/// again only after its confirmations dropped below the threshold in the meantime.
 pub struct Behaviour {
     config: Config,
     /// For each peer we're connected to, the observed address to send back to it.
@@ -55,6 +62,24 @@ pub struct Behaviour {
 
     listen_addresses: ListenAddresses,
     external_addresses: ExternalAddresses,
+
+    /// When we last pushed to each connected peer.
+    last_pushed: HashMap<PeerId, Instant>,
+    /// Pushes held back by [`Config::min_push_interval`] and when they may be sent.
+    deferred_pushes: HashMap<PeerId, Instant>,
+    /// Fires when the earliest deferred push may be sent.
+    deferred_push_timer: Option<Delay>,
+
+    /// Protocols advertised in addition to the ones supported by the connection handlers.
+    protocol_hints: HashSet<StreamProtocol>,
+
+    /// The last [`Info`] received from each connected peer.
+    infos: HashMap<PeerId, Info>,
+    /// The last [`Info`] received from recently disconnected peers, if enabled.
+    disconnected_infos: Option<LruCache<PeerId, Info>>,
+
+    /// Recent reports of our address observed by remotes.
+    observed_addrs: ObservedAddrs,
 }
 
 /// Configuration for the [`identify::Behaviour`](Behaviour).
@@ -101,6 +126,53 @@ pub struct Config {
     ///
     /// Disabled by default.
     pub cache_size: usize,
+
+    /// How many [`Info`]s of disconnected peers to keep for [`Behaviour::info`]
+    /// before we discard the least-recently received one.
+    ///
+    /// The last received [`Info`] of a connected peer is always kept.
+    ///
+    /// Defaults to 0, i.e. [`Info`]s are discarded once we disconnect from the peer.
+    pub info_cache_size: usize,
+
+    /// The minimum time between two pushes to the same peer.
+    ///
+    /// Pushes triggered within this time after the previous push to a peer are
+    /// deferred until it has passed, and multiple deferred pushes to a peer are
+    /// sent as one. [`Behaviour::push_now`] is not subject to this limit.
+    ///
+    /// Defaults to 0, i.e. no limit.
+    pub min_push_interval: Duration,
+
+    /// How many distinct peers have to report the same observed address within
+    /// [`Config::observed_addr_window`] before it is reported as an external address candidate.
+    ///
+    /// Defaults to 1, i.e. every observed address is reported immediately.
+    pub observed_addr_confirmations: NonZeroUsize,
+
+    /// The time within which observations of an address count towards
+    /// [`Config::observed_addr_confirmations`].
+    ///
+    /// Defaults to 10 minutes.
+    pub observed_addr_window: Duration,
+
+    /// Decides which of our listen and external addresses are advertised to a peer.
+    address_filter: Option<AddressFilter>,
+
+    /// The keypair of the local node, used to sign our addresses as a signed peer record.
+    local_keypair: Option<Keypair>,
+}
+
+/// Predicate deciding whether to advertise an address to a peer, see [`Config::with_address_filter`].
+#[derive(Clone)]
+struct AddressFilter(Arc<AddressFilterFn>);
+
+type AddressFilterFn = dyn Fn(&PeerId, &Multiaddr, &Multiaddr) -> bool + Send + Sync;
+
+impl fmt::Debug for AddressFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AddressFilter").finish()
+    }
 }
 
 impl Config {
@@ -116,6 +188,25 @@ impl Config {
             interval: Duration::from_secs(5 * 60),
             push_listen_addr_updates: false,
             cache_size: 100,
+            info_cache_size: 0,
+            min_push_interval: Duration::ZERO,
+            observed_addr_confirmations: NonZeroUsize::new(1).expect("1 > 0"),
+            observed_addr_window: Duration::from_secs(10 * 60),
+            address_filter: None,
+            local_keypair: None,
+        }
+    }
+
+    /// Creates a new configuration for the identify [`Behaviour`] that
+    /// advertises the given protocol version and the public key of the given keypair.
+    ///
+    /// In addition to the plain listen addresses, the advertised addresses are sent as a
+    /// [`PeerRecord`](libp2p_core::PeerRecord) signed with `local_keypair`, allowing remotes
+    /// to verify that they originate from us.
+    pub fn new_with_signed_peer_record(protocol_version: String, local_keypair: &Keypair) -> Self {
+        Self {
+            local_keypair: Some(local_keypair.clone()),
+            ..Self::new(protocol_version, local_keypair.public())
         }
     }
 
@@ -156,6 +247,48 @@ impl Config {
         self.cache_size = cache_size;
         self
     }
+
+    /// Configures how many [`Info`]s of disconnected peers to keep for [`Behaviour::info`].
+    pub fn with_info_cache_size(mut self, info_cache_size: usize) -> Self {
+        self.info_cache_size = info_cache_size;
+        self
+    }
+
+    /// Configures the minimum time between two pushes to the same peer.
+    pub fn with_min_push_interval(mut self, d: Duration) -> Self {
+        self.min_push_interval = d;
+        self
+    }
+
+    /// Configures how many distinct peers have to report the same observed address within
+    /// `window` before it is reported as an external address candidate.
+    ///
+    /// Requiring multiple confirmations avoids reporting short-lived addresses, e.g. ports
+    /// assigned by a NAT to a single connection.
+    pub fn with_observed_addr_confirmations(
+        mut self,
+        confirmations: NonZeroUsize,
+        window: Duration,
+    ) -> Self {
+        self.observed_addr_confirmations = confirmations;
+        self.observed_addr_window = window;
+        self
+    }
+
+    /// Configures which of our listen and external addresses are advertised to a peer.
+    ///
+    /// The filter is called with the remote peer, the address of the remote on the
+    /// connection and the candidate address, and returns whether to include the
+    /// candidate, e.g. to not advertise private addresses to peers on public addresses.
+    ///
+    /// By default, all addresses are advertised.
+    pub fn with_address_filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&PeerId, &Multiaddr, &Multiaddr) -> bool + Send + Sync + 'static,
+    {
+        self.address_filter = Some(AddressFilter(Arc::new(filter)));
+        self
+    }
 }
 
 impl Behaviour {
@@ -166,6 +299,8 @@ impl Behaviour {
             Some(size) => PeerCache::enabled(size),
         };
 
+        let disconnected_infos = NonZeroUsize::new(config.info_cache_size).map(LruCache::new);
+
         Self {
             config,
             connected: HashMap::new(),
@@ -173,10 +308,64 @@ impl Behaviour {
             discovered_peers,
             listen_addresses: Default::default(),
             external_addresses: Default::default(),
+            last_pushed: HashMap::new(),
+            deferred_pushes: HashMap::new(),
+            deferred_push_timer: None,
+            protocol_hints: HashSet::new(),
+            infos: HashMap::new(),
+            disconnected_infos,
+            observed_addrs: ObservedAddrs::default(),
         }
     }
 
+    /// Returns the last [`Info`] received from the given peer.
+    ///
+    /// The [`Info`] of a peer is kept while we are connected to it. Afterwards, it is kept
+    /// as long as it is among the [`Config::info_cache_size`] most recently disconnected peers.
+    pub fn info(&self, peer_id: &PeerId) -> Option<&Info> {
+        self.infos.get(peer_id).or_else(|| {
+            self.disconnected_infos
+                .as_ref()
+                .and_then(|cache| cache.peek(peer_id))
+        })
+    }
+
+    /// Changes the agent version sent to peers and pushes it to all connected peers.
+    ///
+    /// The pushes are subject to [`Config::min_push_interval`].
+    pub fn set_agent_version(&mut self, agent_version: String) {
+        if self.config.agent_version == agent_version {
+            return;
+        }
+
+        self.config.agent_version = agent_version.clone();
+        self.notify_all_handlers(|| InEvent::AgentVersionChanged(agent_version.clone()));
+        self.push_to_all_connected();
+    }
+
+    /// Sets protocols to advertise to peers in addition to the ones supported by the local
+    /// connection handlers and pushes them to all connected peers.
+    ///
+    /// This allows hinting at protocols that are not served on every connection, e.g. ones that
+    /// are only available after some setup has finished. The pushes are subject to
+    /// [`Config::min_push_interval`].
+    pub fn set_protocol_hints<I>(&mut self, protocols: I)
+    where
+        I: IntoIterator<Item = StreamProtocol>,
+    {
+        let protocols = protocols.into_iter().collect::<HashSet<_>>();
+        if self.protocol_hints == protocols {
+            return;
+        }
+
+        self.protocol_hints = protocols.clone();
+        self.notify_all_handlers(|| InEvent::ProtocolHintsChanged(protocols.clone()));
+        self.push_to_all_connected();
+    }
+
     /// Initiates an active push of the local peer information to the given peers.
+    ///
+    /// Pushes to peers we pushed to less than [`Config::min_push_interval`] ago are deferred.
     pub fn push<I>(&mut self, peers: I)
     where
         I: IntoIterator<Item = PeerId>,
@@ -187,11 +376,107 @@ impl Behaviour {
                 continue;
             }
 
-            self.events.push_back(ToSwarm::NotifyHandler {
-                peer_id: p,
-                handler: NotifyHandler::Any,
-                event: InEvent::Push,
-            });
+            self.push_rate_limited(p);
+        }
+    }
+
+    /// Immediately pushes the local peer information to the given peers,
+    /// regardless of [`Config::min_push_interval`].
+    pub fn push_now<I>(&mut self, peers: I)
+    where
+        I: IntoIterator<Item = PeerId>,
+    {
+        for p in peers {
+            if !self.connected.contains_key(&p) {
+                log::debug!("Not pushing to {p} because we are not connected");
+                continue;
+            }
+
+            self.push_immediately(p);
+        }
+    }
+
+    fn notify_all_handlers(&mut self, event: impl Fn() -> InEvent) {
+        let events = self
+            .connected
+            .iter()
+            .flat_map(|(peer, map)| map.keys().map(move |id| (*peer, *id)))
+            .map(|(peer_id, connection_id)| ToSwarm::NotifyHandler {
+                peer_id,
+                handler: NotifyHandler::One(connection_id),
+                event: event(),
+            })
+            .collect::<Vec<_>>();
+        self.events.extend(events);
+    }
+
+    fn push_to_all_connected(&mut self) {
+        let peers = self.connected.keys().copied().collect::<Vec<_>>();
+        for peer in peers {
+            self.push_rate_limited(peer);
+        }
+    }
+
+    fn push_rate_limited(&mut self, peer_id: PeerId) {
+        let earliest = match self.last_pushed.get(&peer_id) {
+            Some(last) => *last + self.config.min_push_interval,
+            None => return self.push_immediately(peer_id),
+        };
+
+        if earliest <= Instant::now() {
+            return self.push_immediately(peer_id);
+        }
+
+        if self.deferred_pushes.insert(peer_id, earliest).is_none() {
+            log::debug!("Deferring push to {peer_id} due to the minimum push interval");
+        }
+
+        // The new push may be due before the one the timer is scheduled for.
+        if let Some(timer) = self.deferred_push_timer.as_mut() {
+            let next = self
+                .deferred_pushes
+                .values()
+                .min()
+                .expect("a push to be deferred");
+            timer.reset(next.saturating_duration_since(Instant::now()));
+        }
+    }
+
+    fn push_immediately(&mut self, peer_id: PeerId) {
+        self.deferred_pushes.remove(&peer_id);
+        self.last_pushed.insert(peer_id, Instant::now());
+        self.events.push_back(ToSwarm::NotifyHandler {
+            peer_id,
+            handler: NotifyHandler::Any,
+            event: InEvent::Push,
+        });
+    }
+
+    /// Sends all deferred pushes that are due and schedules the timer for the next one.
+    fn poll_deferred_pushes(&mut self, cx: &mut Context<'_>) {
+        loop {
+            if let Some(timer) = self.deferred_push_timer.as_mut() {
+                if timer.poll_unpin(cx).is_pending() {
+                    return;
+                }
+                self.deferred_push_timer = None;
+            }
+
+            let now = Instant::now();
+            let due = self
+                .deferred_pushes
+                .iter()
+                .filter(|(_, at)| **at <= now)
+                .map(|(peer, _)| *peer)
+                .collect::<Vec<_>>();
+            for peer in due {
+                self.push_immediately(peer);
+            }
+
+            match self.deferred_pushes.values().min() {
+                Some(next) => self.deferred_push_timer = Some(Delay::new(*next - now)),
+                None => return,
+            }
         }
     }
 
@@ -222,10 +507,15 @@ impl Behaviour {
         }
     }
 
-    fn all_addresses(&self) -> HashSet<Multiaddr> {
+    /// Our listen and external addresses to advertise to `peer` on a connection to `remote_addr`.
+    fn addresses_for(&self, peer: &PeerId, remote_addr: &Multiaddr) -> HashSet<Multiaddr> {
         self.listen_addresses
             .iter()
             .chain(self.external_addresses.iter())
+            .filter(|addr| match &self.config.address_filter {
+                Some(AddressFilter(filter)) => filter(peer, remote_addr, addr),
+                None => true,
+            })
             .cloned()
             .collect()
     }
@@ -248,10 +538,12 @@ impl NetworkBehaviour for Behaviour {
             self.config.interval,
             peer,
             self.config.local_public_key.clone(),
+            self.config.local_keypair.clone(),
             self.config.protocol_version.clone(),
             self.config.agent_version.clone(),
+            self.protocol_hints.clone(),
             remote_addr.clone(),
-            self.all_addresses(),
+            self.addresses_for(&peer, remote_addr),
         ))
     }
 
@@ -268,10 +560,12 @@ impl NetworkBehaviour for Behaviour {
             self.config.interval,
             peer,
             self.config.local_public_key.clone(),
+            self.config.local_keypair.clone(),
             self.config.protocol_version.clone(),
             self.config.agent_version.clone(),
+            self.protocol_hints.clone(),
             addr.clone(), // TODO: This is weird? That is the public address we dialed, shouldn't need to tell the other party?
-            self.all_addresses(),
+            self.addresses_for(&peer, addr),
         ))
     }
 
@@ -291,11 +585,24 @@ impl NetworkBehaviour for Behaviour {
                 self.discovered_peers
                     .put(peer_id, info.listen_addrs.iter().cloned());
 
+                if let Some(cache) = self.disconnected_infos.as_mut() {
+                    cache.pop(&peer_id);
+                }
+                self.infos.insert(peer_id, info.clone());
+
                 let observed = info.observed_addr.clone();
                 self.events
                     .push_back(ToSwarm::GenerateEvent(Event::Received { peer_id, info }));
-                self.events
-                    .push_back(ToSwarm::NewExternalAddrCandidate(observed));
+                if self.observed_addrs.observe(
+                    peer_id,
+                    observed.clone(),
+                    Instant::now(),
+                    self.config.observed_addr_window,
+                    self.config.observed_addr_confirmations,
+                ) {
+                    self.events
+                        .push_back(ToSwarm::NewExternalAddrCandidate(observed));
+                }
             }
             handler::Event::Identification => {
                 self.events
@@ -314,9 +621,11 @@ impl NetworkBehaviour for Behaviour {
 
     fn poll(
         &mut self,
-        _cx: &mut Context<'_>,
+        cx: &mut Context<'_>,
         _: &mut impl PollParameters,
     ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        self.poll_deferred_pushes(cx);
+
         if let Some(event) = self.events.pop_front() {
             return Poll::Ready(event);
         }
@@ -348,11 +657,11 @@ impl NetworkBehaviour for Behaviour {
             let change_events = self
                 .connected
                 .iter()
-                .flat_map(|(peer, map)| map.keys().map(|id| (*peer, id)))
-                .map(|(peer_id, connection_id)| ToSwarm::NotifyHandler {
+                .flat_map(|(peer, map)| map.iter().map(move |(id, addr)| (*peer, id, addr)))
+                .map(|(peer_id, connection_id, addr)| ToSwarm::NotifyHandler {
                     peer_id,
                     handler: NotifyHandler::One(*connection_id),
-                    event: InEvent::AddressesChanged(self.all_addresses()),
+                    event: InEvent::AddressesChanged(self.addresses_for(&peer_id, addr)),
                 })
                 .collect::<Vec<_>>();
 
@@ -361,13 +670,7 @@ impl NetworkBehaviour for Behaviour {
 
         if listen_addr_changed && self.config.push_listen_addr_updates {
             // trigger an identify push for all connected peers
-            let push_events = self.connected.keys().map(|peer| ToSwarm::NotifyHandler {
-                peer_id: *peer,
-                handler: NotifyHandler::Any,
-                event: InEvent::Push,
-            });
-
-            self.events.extend(push_events);
+            self.push_to_all_connected();
         }
 
         match event {
@@ -382,6 +685,13 @@ impl NetworkBehaviour for Behaviour {
             }) => {
                 if remaining_established == 0 {
                     self.connected.remove(&peer_id);
+                    self.last_pushed.remove(&peer_id);
+                    self.deferred_pushes.remove(&peer_id);
+                    if let Some(info) = self.infos.remove(&peer_id) {
+                        if let Some(cache) = self.disconnected_infos.as_mut() {
+                            cache.put(peer_id, info);
+                        }
+                    }
                 } else if let Some(addrs) = self.connected.get_mut(&peer_id) {
                     addrs.remove(&connection_id);
                 }
@@ -451,6 +761,45 @@ fn multiaddr_matches_peer_id(addr: &Multiaddr, peer_id: &PeerId) -> bool {
     true
 }
 
+/// Reports of our address observed by remotes, keeping the latest report of each peer.
+#[derive(Debug, Default)]
+struct ObservedAddrs {
+    reports: HashMap<PeerId, (Multiaddr, Instant)>,
+    /// Addresses reported by at least the required number of peers.
+    confirmed: HashSet<Multiaddr>,
+}
+
+impl ObservedAddrs {
+    /// Records `peer` observing `addr`, discarding reports older than `window`.
+    ///
+    /// Returns `true` if `addr` was just reported by `confirmations` distinct peers within
+    /// `window`, i.e. if it was not already confirmed.
+    fn observe(
+        &mut self,
+        peer: PeerId,
+        addr: Multiaddr,
+        now: Instant,
+        window: Duration,
+        confirmations: NonZeroUsize,
+    ) -> bool {
+        self.reports
+            .retain(|_, (_, at)| now.saturating_duration_since(*at) < window);
+        self.reports.insert(peer, (addr.clone(), now));
+
+        let reports = &self.reports;
+        let count = |addr: &Multiaddr| {
+            reports
+                .values()
+                .filter(|(observed, _)| observed == addr)
+                .count()
+        };
+        self.confirmed
+            .retain(|confirmed| count(confirmed) >= confirmations.get());
+
+        count(&addr) >= confirmations.get() && self.confirmed.insert(addr)
+    }
+}
+
 struct PeerCache(Option<LruCache<PeerId, HashSet<Multiaddr>>>);
 
 impl PeerCache {
@@ -493,6 +842,34 @@ impl PeerCache {
 mod tests {
     use super::*;
 
+    #[test]
+    fn observed_addr_requires_distinct_peers_within_window() {
+        let window = Duration::from_secs(60);
+        let confirmations = NonZeroUsize::new(2).unwrap();
+        let start = Instant::now();
+        let addr: Multiaddr = "/ip4/147.75.69.143/tcp/4001".parse().unwrap();
+        let other_addr: Multiaddr = "/ip4/147.75.69.143/tcp/4002".parse().unwrap();
+        let (peer_a, peer_b, peer_c) = (PeerId::random(), PeerId::random(), PeerId::random());
+
+        let mut observed = ObservedAddrs::default();
+        let mut observe = |peer, addr: &Multiaddr, now| {
+            observed.observe(peer, addr.clone(), now, window, confirmations)
+        };
+        assert!(!observe(peer_a, &addr, start));
+        // Repeated reports of the same peer count once.
+        assert!(!observe(peer_a, &addr, start));
+        assert!(observe(peer_b, &addr, start));
+        // Confirmed addresses are reported once.
+        assert!(!observe(peer_c, &addr, start));
+        // A peer's latest report replaces its previous one.
+        assert!(!observe(peer_b, &other_addr, start));
+        assert!(observe(peer_c, &other_addr, start + window / 2));
+        // The address is reported again once it dropped below the threshold in between.
+        assert!(observe(peer_b, &addr, start + window / 2));
+        // Reports older than the window are discarded.
+        assert!(!observe(peer_a, &addr, start + window * 2));
+    }
+
     #[test]
     fn check_multiaddr_matches_peer_id() {
         let peer_id = PeerId::random();
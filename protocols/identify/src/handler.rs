@@ -25,9 +25,9 @@ use futures::prelude::*;
 use futures::stream::FuturesUnordered;
 use futures_timer::Delay;
 use libp2p_core::upgrade::SelectUpgrade;
-use libp2p_core::Multiaddr;
+use libp2p_core::{Multiaddr, PeerRecord, SignedEnvelope};
 use libp2p_identity::PeerId;
-use libp2p_identity::PublicKey;
+use libp2p_identity::{Keypair, PublicKey};
 use libp2p_swarm::handler::{
     ConnectionEvent, DialUpgradeError, FullyNegotiatedInbound, FullyNegotiatedOutbound,
     ProtocolSupport,
@@ -36,7 +36,7 @@ use libp2p_swarm::{
     ConnectionHandler, ConnectionHandlerEvent, KeepAlive, StreamProtocol, StreamUpgradeError,
     SubstreamProtocol, SupportedProtocols,
 };
-use log::{warn, Level};
+use log::{debug, warn, Level};
 use smallvec::SmallVec;
 use std::collections::HashSet;
 use std::{io, task::Context, task::Poll, time::Duration};
@@ -69,6 +69,12 @@ pub struct Handler {
     /// The public key of the local peer.
     public_key: PublicKey,
 
+    /// The keypair of the local peer, if our addresses are to be sent as a signed peer record.
+    local_keypair: Option<Keypair>,
+
+    /// Our addresses signed with `local_keypair`, cached until they change.
+    signed_peer_record: Option<SignedEnvelope>,
+
     /// Application-specific version of the protocol family used by the peer,
     /// e.g. `ipfs/1.0.0` or `polkadot/1.0.0`.
     protocol_version: String,
@@ -81,6 +87,8 @@ pub struct Handler {
     observed_addr: Multiaddr,
 
     local_supported_protocols: SupportedProtocols,
+    /// Protocols advertised in addition to `local_supported_protocols`.
+    protocol_hints: HashSet<StreamProtocol>,
     remote_supported_protocols: HashSet<StreamProtocol>,
     external_addresses: HashSet<Multiaddr>,
 }
@@ -89,6 +97,8 @@ pub struct Handler {
 #[derive(Debug)]
 pub enum InEvent {
     AddressesChanged(HashSet<Multiaddr>),
+    AgentVersionChanged(String),
+    ProtocolHintsChanged(HashSet<StreamProtocol>),
     Push,
 }
 
@@ -114,8 +124,10 @@ impl Handler {
         interval: Duration,
         remote_peer_id: PeerId,
         public_key: PublicKey,
+        local_keypair: Option<Keypair>,
         protocol_version: String,
         agent_version: String,
+        protocol_hints: HashSet<StreamProtocol>,
         observed_addr: Multiaddr,
         external_addresses: HashSet<Multiaddr>,
     ) -> Self {
@@ -128,10 +140,13 @@ impl Handler {
             exchanged_one_periodic_identify: false,
             interval,
             public_key,
+            local_keypair,
+            signed_peer_record: None,
             protocol_version,
             agent_version,
             observed_addr,
             local_supported_protocols: SupportedProtocols::default(),
+            protocol_hints,
             remote_supported_protocols: HashSet::default(),
             external_addresses,
         }
@@ -208,11 +223,34 @@ impl Handler {
             protocol_version: self.protocol_version.clone(),
             agent_version: self.agent_version.clone(),
             listen_addrs: Vec::from_iter(self.external_addresses.iter().cloned()),
-            protocols: Vec::from_iter(self.local_supported_protocols.iter().cloned()),
+            protocols: Vec::from_iter(
+                self.local_supported_protocols
+                    .iter()
+                    .chain(self.protocol_hints.iter())
+                    .cloned()
+                    .collect::<HashSet<_>>(),
+            ),
             observed_addr: self.observed_addr.clone(),
+            signed_peer_record: self.signed_peer_record(),
         }
     }
 
+    /// Returns our addresses signed as a [`PeerRecord`], if we have a keypair to sign them with.
+    fn signed_peer_record(&mut self) -> Option<SignedEnvelope> {
+        if self.signed_peer_record.is_none() {
+            let keypair = self.local_keypair.as_ref()?;
+            match PeerRecord::new(
+                keypair,
+                Vec::from_iter(self.external_addresses.iter().cloned()),
+            ) {
+                Ok(record) => self.signed_peer_record = Some(record.into_signed_envelope()),
+                Err(e) => debug!("Failed to sign peer record: {e}"),
+            }
+        }
+
+        self.signed_peer_record.clone()
+    }
+
     fn update_supported_protocols_for_remote(&mut self, remote_info: &Info) {
         let new_remote_protocols = HashSet::from_iter(remote_info.protocols.clone());
 
@@ -269,6 +307,13 @@ impl ConnectionHandler for Handler {
         match event {
             InEvent::AddressesChanged(addresses) => {
                 self.external_addresses = addresses;
+                self.signed_peer_record = None;
+            }
+            InEvent::AgentVersionChanged(agent_version) => {
+                self.agent_version = agent_version;
+            }
+            InEvent::ProtocolHintsChanged(protocols) => {
+                self.protocol_hints = protocols;
             }
             InEvent::Push => {
                 let info = self.build_info();
@@ -24,7 +24,7 @@ use futures::{future::BoxFuture, prelude::*};
 use libp2p_core::{
     multiaddr,
     upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo},
-    Multiaddr,
+    Multiaddr, PeerRecord, SignedEnvelope,
 };
 use libp2p_identity as identity;
 use libp2p_identity::PublicKey;
@@ -80,6 +80,12 @@ pub struct Info {
     pub protocols: Vec<StreamProtocol>,
     /// Address observed by or for the remote.
     pub observed_addr: Multiaddr,
+    /// The peer's addresses as a [`PeerRecord`] in a [`SignedEnvelope`] signed by the peer.
+    ///
+    /// Received records are verified to be signed by the peer's `public_key`. Invalid records
+    /// are discarded, valid ones replace the unsigned `listen_addrs`, thus `listen_addrs` are
+    /// authenticated if this is `Some`.
+    pub signed_peer_record: Option<SignedEnvelope>,
 }
 
 impl UpgradeInfo for Identify {
@@ -171,6 +177,9 @@ where
         listenAddrs: listen_addrs,
         observedAddr: Some(info.observed_addr.to_vec()),
         protocols: info.protocols.into_iter().map(|p| p.to_string()).collect(),
+        signedPeerRecord: info
+            .signed_peer_record
+            .map(|envelope| envelope.into_protobuf_encoding()),
     };
 
     let mut framed_io = FramedWrite::new(
@@ -215,7 +224,7 @@ impl TryFrom<proto::Identify> for Info {
             Multiaddr::try_from(bytes)
         }
 
-        let listen_addrs = {
+        let mut listen_addrs = {
             let mut addrs = Vec::new();
             for addr in msg.listenAddrs.into_iter() {
                 match parse_multiaddr(addr) {
@@ -237,6 +246,39 @@ impl TryFrom<proto::Identify> for Info {
                 Multiaddr::empty()
             }
         };
+        let signed_peer_record = msg
+            .signedPeerRecord
+            .and_then(
+                |bytes| match SignedEnvelope::from_protobuf_encoding(&bytes) {
+                    Ok(envelope) => Some(envelope),
+                    Err(e) => {
+                        debug!("Unable to decode signed peer record: {e}");
+                        None
+                    }
+                },
+            )
+            .and_then(
+                |envelope| match PeerRecord::from_signed_envelope(envelope) {
+                    Ok(record) if record.peer_id() == public_key.to_peer_id() => Some(record),
+                    Ok(record) => {
+                        debug!(
+                            "Discarding signed peer record of {} received from {}",
+                            record.peer_id(),
+                            public_key.to_peer_id()
+                        );
+                        None
+                    }
+                    Err(e) => {
+                        debug!("Discarding invalid signed peer record: {e}");
+                        None
+                    }
+                },
+            )
+            .map(|record| {
+                listen_addrs = record.addresses().to_vec();
+                record.into_signed_envelope()
+            });
+
         let info = Info {
             public_key,
             protocol_version: msg.protocolVersion.unwrap_or_default(),
@@ -254,6 +296,7 @@ impl TryFrom<proto::Identify> for Info {
                 })
                 .collect(),
             observed_addr,
+            signed_peer_record,
         };
 
         Ok(info)
@@ -301,10 +344,43 @@ mod tests {
                     .public()
                     .encode_protobuf(),
             ),
+            signedPeerRecord: None,
         };
 
         let info = Info::try_from(payload).expect("not to fail");
 
         assert_eq!(info.listen_addrs, vec![valid_multiaddr])
     }
+
+    #[test]
+    fn signed_peer_record_replaces_listen_addrs() {
+        let keypair = identity::Keypair::generate_ed25519();
+        let signed_addr: Multiaddr = "/ip4/1.2.3.4/tcp/1234".parse().unwrap();
+        let unsigned_addr: Multiaddr = "/ip4/5.6.7.8/tcp/5678".parse().unwrap();
+
+        let payload = |signer: &identity::Keypair| proto::Identify {
+            agentVersion: None,
+            listenAddrs: vec![unsigned_addr.to_vec()],
+            observedAddr: None,
+            protocolVersion: None,
+            protocols: vec![],
+            publicKey: Some(keypair.public().encode_protobuf()),
+            signedPeerRecord: Some(
+                PeerRecord::new(signer, vec![signed_addr.clone()])
+                    .unwrap()
+                    .into_signed_envelope()
+                    .into_protobuf_encoding(),
+            ),
+        };
+
+        let info = Info::try_from(payload(&keypair)).expect("not to fail");
+        assert!(info.signed_peer_record.is_some());
+        assert_eq!(info.listen_addrs, vec![signed_addr.clone()]);
+
+        // A record signed by another peer is discarded.
+        let info =
+            Info::try_from(payload(&identity::Keypair::generate_ed25519())).expect("not to fail");
+        assert!(info.signed_peer_record.is_none());
+        assert_eq!(info.listen_addrs, vec![unsigned_addr.clone()]);
+    }
 }
@@ -1,8 +1,9 @@
 use libp2p_core::multiaddr::Protocol;
 use libp2p_identify as identify;
-use libp2p_swarm::{keep_alive, Swarm, SwarmEvent};
+use libp2p_swarm::{keep_alive, StreamProtocol, Swarm, SwarmEvent};
 use libp2p_swarm_test::SwarmExt;
 use std::iter;
+use std::time::{Duration, Instant};
 
 #[async_std::test]
 async fn periodic_identify() {
@@ -136,6 +137,228 @@ async fn identify_push() {
     assert!(swarm1_received_info.listen_addrs.is_empty());
 }
 
+#[async_std::test]
+async fn runtime_updates_are_pushed() {
+    let _ = env_logger::try_init();
+
+    let mut swarm1 = Swarm::new_ephemeral(|identity| {
+        Behaviour::new(identify::Config::new("a".to_string(), identity.public()))
+    });
+    let mut swarm2 = Swarm::new_ephemeral(|identity| {
+        Behaviour::new(
+            identify::Config::new("a".to_string(), identity.public())
+                .with_agent_version("b".to_string()),
+        )
+    });
+
+    swarm1.listen().await;
+    swarm2.connect(&mut swarm1).await;
+
+    // Let the initial identify complete.
+    let ([_, _], [_, _]): ([BehaviourEvent; 2], [BehaviourEvent; 2]) =
+        libp2p_swarm_test::drive(&mut swarm1, &mut swarm2).await;
+
+    let hint = StreamProtocol::new("/hint/1.0.0");
+    swarm2
+        .behaviour_mut()
+        .identify
+        .set_agent_version("b/syncing".to_string());
+    swarm2
+        .behaviour_mut()
+        .identify
+        .set_protocol_hints([hint.clone()]);
+    async_std::task::spawn(swarm2.loop_on_next());
+
+    let info = swarm1
+        .wait(|event| match event {
+            SwarmEvent::Behaviour(BehaviourEvent::Identify(identify::Event::Received {
+                info,
+                ..
+            })) if info.protocols.contains(&hint) => Some(info),
+            _ => None,
+        })
+        .await;
+    assert_eq!(info.agent_version, "b/syncing");
+}
+
+#[async_std::test]
+async fn push_respects_min_push_interval() {
+    let _ = env_logger::try_init();
+
+    let mut swarm1 = Swarm::new_ephemeral(|identity| {
+        Behaviour::new(identify::Config::new("a".to_string(), identity.public()))
+    });
+    let mut swarm2 = Swarm::new_ephemeral(|identity| {
+        Behaviour::new(
+            identify::Config::new("a".to_string(), identity.public())
+                .with_min_push_interval(Duration::from_millis(500)),
+        )
+    });
+    let swarm1_peer_id = *swarm1.local_peer_id();
+
+    swarm1.listen().await;
+    swarm2.connect(&mut swarm1).await;
+
+    // Let the periodic identify complete.
+    let _: ([BehaviourEvent; 2], [BehaviourEvent; 2]) =
+        libp2p_swarm_test::drive(&mut swarm1, &mut swarm2).await;
+
+    // Of two pushes in a row, only the first one is sent right away.
+    let start = Instant::now();
+    swarm2
+        .behaviour_mut()
+        .identify
+        .push(iter::once(swarm1_peer_id));
+    swarm2
+        .behaviour_mut()
+        .identify
+        .push(iter::once(swarm1_peer_id));
+    for _ in 0..2 {
+        match libp2p_swarm_test::drive(&mut swarm1, &mut swarm2).await {
+            (
+                [BehaviourEvent::Identify(identify::Event::Received { .. })],
+                [BehaviourEvent::Identify(identify::Event::Pushed { .. })],
+            ) => {}
+            other => panic!("Unexpected events: {other:?}"),
+        }
+    }
+    assert!(start.elapsed() >= Duration::from_millis(500));
+
+    // `push_now` ignores the minimum interval.
+    let start = Instant::now();
+    swarm2
+        .behaviour_mut()
+        .identify
+        .push_now(iter::once(swarm1_peer_id));
+    match libp2p_swarm_test::drive(&mut swarm1, &mut swarm2).await {
+        (
+            [BehaviourEvent::Identify(identify::Event::Received { .. })],
+            [BehaviourEvent::Identify(identify::Event::Pushed { .. })],
+        ) => {}
+        other => panic!("Unexpected events: {other:?}"),
+    }
+    assert!(start.elapsed() < Duration::from_millis(500));
+}
+
+#[async_std::test]
+async fn address_filter_applies_per_peer() {
+    let _ = env_logger::try_init();
+
+    let mut swarm1 = Swarm::new_ephemeral(|identity| {
+        Behaviour::new(identify::Config::new("a".to_string(), identity.public()))
+    });
+    let swarm1_peer_id = *swarm1.local_peer_id();
+    let mut swarm2 = Swarm::new_ephemeral(|identity| {
+        Behaviour::new(
+            identify::Config::new("a".to_string(), identity.public()).with_address_filter(
+                move |peer, _, addr| {
+                    *peer != swarm1_peer_id || !addr.iter().any(|p| matches!(p, Protocol::Tcp(_)))
+                },
+            ),
+        )
+    });
+
+    let (swarm2_memory_listen, swarm2_tcp_listen_addr) = swarm2.listen().await;
+    swarm1.connect(&mut swarm2).await;
+    async_std::task::spawn(swarm2.loop_on_next());
+
+    let info = swarm1
+        .wait(|event| match event {
+            SwarmEvent::Behaviour(BehaviourEvent::Identify(identify::Event::Received {
+                info,
+                ..
+            })) => Some(info),
+            _ => None,
+        })
+        .await;
+
+    assert!(info.listen_addrs.contains(&swarm2_memory_listen));
+    assert!(!info.listen_addrs.contains(&swarm2_tcp_listen_addr));
+}
+
+#[async_std::test]
+async fn signed_peer_record_is_verified() {
+    let _ = env_logger::try_init();
+
+    let mut swarm1 = Swarm::new_ephemeral(|identity| {
+        Behaviour::new(identify::Config::new("a".to_string(), identity.public()))
+    });
+    let mut swarm2 = Swarm::new_ephemeral(|identity| {
+        Behaviour::new(identify::Config::new_with_signed_peer_record(
+            "a".to_string(),
+            &identity,
+        ))
+    });
+    let swarm2_peer_id = *swarm2.local_peer_id();
+
+    let (swarm2_memory_listen, swarm2_tcp_listen_addr) = swarm2.listen().await;
+    swarm1.connect(&mut swarm2).await;
+    async_std::task::spawn(swarm2.loop_on_next());
+
+    let info = swarm1
+        .wait(|event| match event {
+            SwarmEvent::Behaviour(BehaviourEvent::Identify(identify::Event::Received {
+                info,
+                ..
+            })) => Some(info),
+            _ => None,
+        })
+        .await;
+
+    let record = libp2p_core::PeerRecord::from_signed_envelope(
+        info.signed_peer_record.expect("signed peer record"),
+    )
+    .unwrap();
+    assert_eq!(record.peer_id(), swarm2_peer_id);
+    assert_eq!(record.addresses(), info.listen_addrs.as_slice());
+    assert!(info.listen_addrs.contains(&swarm2_memory_listen));
+    assert!(info.listen_addrs.contains(&swarm2_tcp_listen_addr));
+}
+
+#[async_std::test]
+async fn info_is_cached_per_peer() {
+    let _ = env_logger::try_init();
+
+    let mut swarm1 = Swarm::new_ephemeral(|identity| {
+        Behaviour::new(
+            identify::Config::new("a".to_string(), identity.public())
+                .with_agent_version("b".to_string()),
+        )
+    });
+    let mut swarm2 = Swarm::new_ephemeral(|identity| {
+        Behaviour::new(
+            identify::Config::new("a".to_string(), identity.public())
+                .with_agent_version("c".to_string())
+                .with_info_cache_size(1),
+        )
+    });
+    let swarm1_peer_id = *swarm1.local_peer_id();
+    let swarm2_peer_id = *swarm2.local_peer_id();
+
+    swarm1.listen().await;
+    swarm2.connect(&mut swarm1).await;
+
+    let ([_, _], [_, _]): ([BehaviourEvent; 2], [BehaviourEvent; 2]) =
+        libp2p_swarm_test::drive(&mut swarm1, &mut swarm2).await;
+
+    let swarm1_info = swarm2.behaviour().identify.info(&swarm1_peer_id).unwrap();
+    assert_eq!(swarm1_info.agent_version, "b");
+    let swarm2_info = swarm1.behaviour().identify.info(&swarm2_peer_id).unwrap();
+    assert_eq!(swarm2_info.agent_version, "c");
+
+    swarm2.disconnect_peer_id(swarm1_peer_id).unwrap();
+    swarm2
+        .wait(|event| matches!(event, SwarmEvent::ConnectionClosed { .. }).then_some(()))
+        .await;
+    swarm1
+        .wait(|event| matches!(event, SwarmEvent::ConnectionClosed { .. }).then_some(()))
+        .await;
+
+    // Only `swarm2` keeps infos of disconnected peers.
+    assert!(swarm2.behaviour().identify.info(&swarm1_peer_id).is_some());
+    assert!(swarm1.behaviour().identify.info(&swarm2_peer_id).is_none());
+}
+
 #[async_std::test]
 async fn discover_peer_after_disconnect() {
     let _ = env_logger::try_init();
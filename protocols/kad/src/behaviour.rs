@@ -3276,6 +3276,17 @@ impl<'a> QueryRef<'a> {
     pub fn stats(&self) -> &QueryStats {
         self.query.stats()
     }
+
+    /// Gets the addresses of a peer discovered during the query, e.g. of a provider reported by
+    /// [`GetProvidersOk::FoundProviders`].
+    pub fn peer_addresses(&self, peer: &PeerId) -> &[Multiaddr] {
+        self.query
+            .inner
+            .addresses
+            .get(peer)
+            .map(|addresses| addresses.as_slice())
+            .unwrap_or_default()
+    }
 }
 
 /// An operation failed to due no known peers in the routing table.
@@ -323,6 +323,11 @@ impl<TInner> Query<TInner> {
         };
         if updated {
             self.stats.success += 1;
+            self.stats.hops = match &self.peer_iter {
+                QueryPeerIter::Closest(iter) => iter.num_hops(),
+                QueryPeerIter::ClosestDisjoint(iter) => iter.num_hops(),
+                QueryPeerIter::Fixed(_) => 1,
+            };
         }
     }
 
@@ -439,6 +444,7 @@ pub struct QueryStats {
     requests: u32,
     success: u32,
     failure: u32,
+    hops: u32,
     start: Option<Instant>,
     end: Option<Instant>,
 }
@@ -449,6 +455,7 @@ impl QueryStats {
             requests: 0,
             success: 0,
             failure: 0,
+            hops: 0,
             start: None,
             end: None,
         }
@@ -477,6 +484,15 @@ impl QueryStats {
         self.requests - (self.success + self.failure)
     }
 
+    /// Gets the number of hops of the query, i.e. the largest number of
+    /// consecutive requests that led to a successful response.
+    ///
+    /// The peers initially contacted by a query are one hop away. Zero is
+    /// returned if no request succeeded yet.
+    pub fn num_hops(&self) -> u32 {
+        self.hops
+    }
+
     /// Gets the duration of the query.
     ///
     /// If the query has not yet finished, the duration is measured from the
@@ -501,12 +517,15 @@ impl QueryStats {
     ///
     /// Counters are merged cumulatively while the instants for
     /// start and end of the queries are taken as the minimum and
-    /// maximum, respectively.
+    /// maximum, respectively. The number of hops is the maximum of
+    /// both, as the phases of a query do not build on each other's
+    /// requests.
     pub fn merge(self, other: QueryStats) -> Self {
         QueryStats {
             requests: self.requests + other.requests,
             success: self.success + other.success,
             failure: self.failure + other.failure,
+            hops: std::cmp::max(self.hops, other.hops),
             start: match (self.start, other.start) {
                 (Some(a), Some(b)) => Some(std::cmp::min(a, b)),
                 (a, b) => a.or(b),
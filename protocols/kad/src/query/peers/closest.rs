@@ -46,6 +46,9 @@ pub struct ClosestPeersIter {
 
     /// The number of peers for which the iterator is currently waiting for results.
     num_waiting: usize,
+
+    /// The largest number of hops of a peer that returned a result.
+    num_hops: u32,
 }
 
 /// Configuration for a `ClosestPeersIter`.
@@ -115,7 +118,8 @@ impl ClosestPeersIter {
                 .map(|key| {
                     let distance = key.distance(&target);
                     let state = PeerState::NotContacted;
-                    (distance, Peer { key, state })
+                    let hops = 1;
+                    (distance, Peer { key, state, hops })
                 })
                 .take(K_VALUE.into()),
         );
@@ -129,6 +133,7 @@ impl ClosestPeersIter {
             state,
             closest_peers,
             num_waiting: 0,
+            num_hops: 0,
         }
     }
 
@@ -160,20 +165,26 @@ impl ClosestPeersIter {
         let distance = key.distance(&self.target);
 
         // Mark the peer as succeeded.
-        match self.closest_peers.entry(distance) {
+        let hops = match self.closest_peers.entry(distance) {
             Entry::Vacant(..) => return false,
-            Entry::Occupied(mut e) => match e.get().state {
-                PeerState::Waiting(..) => {
-                    debug_assert!(self.num_waiting > 0);
-                    self.num_waiting -= 1;
-                    e.get_mut().state = PeerState::Succeeded;
-                }
-                PeerState::Unresponsive => {
-                    e.get_mut().state = PeerState::Succeeded;
+            Entry::Occupied(mut e) => {
+                match e.get().state {
+                    PeerState::Waiting(..) => {
+                        debug_assert!(self.num_waiting > 0);
+                        self.num_waiting -= 1;
+                        e.get_mut().state = PeerState::Succeeded;
+                    }
+                    PeerState::Unresponsive => {
+                        e.get_mut().state = PeerState::Succeeded;
+                    }
+                    PeerState::NotContacted | PeerState::Failed | PeerState::Succeeded => {
+                        return false
+                    }
                 }
-                PeerState::NotContacted | PeerState::Failed | PeerState::Succeeded => return false,
-            },
-        }
+                e.get().hops
+            }
+        };
+        self.num_hops = self.num_hops.max(hops);
 
         let num_closest = self.closest_peers.len();
         let mut progress = false;
@@ -185,6 +196,7 @@ impl ClosestPeersIter {
             let peer = Peer {
                 key,
                 state: PeerState::NotContacted,
+                hops: hops + 1,
             };
             self.closest_peers.entry(distance).or_insert(peer);
             // The iterator makes progress if the new peer is either closer to the target
@@ -273,6 +285,14 @@ impl ClosestPeersIter {
         self.waiting().any(|p| peer == p)
     }
 
+    /// Returns the number of hops of the iterator so far, i.e. the largest number of
+    /// consecutive responses that led to a peer which returned a result.
+    ///
+    /// The peers the iterator is initialised with are one hop away.
+    pub fn num_hops(&self) -> u32 {
+        self.num_hops
+    }
+
     /// Advances the state of the iterator, potentially getting a new peer to contact.
     pub fn next(&mut self, now: Instant) -> PeersIterState<'_> {
         if let State::Finished = self.state {
@@ -441,6 +461,9 @@ enum State {
 struct Peer {
     key: Key<PeerId>,
     state: PeerState,
+    /// The number of hops the peer is away from the local node, i.e. the
+    /// number of responses it was discovered through plus one.
+    hops: u32,
 }
 
 /// The state of a single `Peer`.
@@ -742,6 +765,7 @@ mod tests {
                 Peer {
                     key,
                     state: PeerState::Unresponsive,
+                    ..
                 } => {
                     assert_eq!(key.preimage(), &peer);
                 }
@@ -788,6 +812,30 @@ mod tests {
         QuickCheck::new().tests(10).quickcheck(prop as fn(_))
     }
 
+    #[test]
+    fn num_hops_counts_consecutive_responses() {
+        let now = Instant::now();
+        let target = Key::from(PeerId::random());
+        let first = PeerId::random();
+        let mut iter = ClosestPeersIter::new(target.into(), iter::once(Key::from(first)));
+        assert_eq!(iter.num_hops(), 0);
+
+        assert_eq!(
+            iter.next(now),
+            PeersIterState::Waiting(Some(Cow::Borrowed(&first)))
+        );
+        let second = PeerId::random();
+        assert!(iter.on_success(&first, vec![second]));
+        assert_eq!(iter.num_hops(), 1);
+
+        assert_eq!(
+            iter.next(now),
+            PeersIterState::Waiting(Some(Cow::Borrowed(&second)))
+        );
+        assert!(iter.on_success(&second, iter::empty()));
+        assert_eq!(iter.num_hops(), 2);
+    }
+
     fn stalled_at_capacity() {
         fn prop(mut iter: ClosestPeersIter) {
             iter.state = State::Stalled;
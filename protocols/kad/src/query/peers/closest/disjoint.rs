@@ -194,6 +194,11 @@ impl ClosestDisjointPeersIter {
         self.iters.iter().any(|i| i.is_waiting(peer))
     }
 
+    /// Returns the largest number of hops of any of the disjoint paths.
+    pub(crate) fn num_hops(&self) -> u32 {
+        self.iters.iter().map(|i| i.num_hops()).max().unwrap_or(0)
+    }
+
     pub(crate) fn next(&mut self, now: Instant) -> PeersIterState<'_> {
         let mut state = None;
 
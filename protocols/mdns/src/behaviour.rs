@@ -24,19 +24,22 @@ mod timer;
 
 use self::iface::InterfaceState;
 use crate::behaviour::{socket::AsyncSocket, timer::Builder};
-use crate::Config;
+use crate::{Config, InterfaceFilter};
 use futures::Stream;
 use if_watch::IfEvent;
 use libp2p_core::{Endpoint, Multiaddr};
 use libp2p_identity::PeerId;
-use libp2p_swarm::behaviour::FromSwarm;
+use libp2p_swarm::behaviour::{ExpiredListenAddr, FromSwarm};
 use libp2p_swarm::{
     dummy, ConnectionDenied, ConnectionId, ListenAddresses, NetworkBehaviour, PollParameters,
     THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
 };
 use smallvec::SmallVec;
 use std::collections::hash_map::{Entry, HashMap};
-use std::{cmp, fmt, io, net::IpAddr, pin::Pin, task::Context, task::Poll, time::Instant};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::{
+    cmp, fmt, io, net::IpAddr, pin::Pin, task::Context, task::Poll, task::Waker, time::Instant,
+};
 
 /// An abstraction to allow for compatibility with various async runtimes.
 pub trait Provider: 'static {
@@ -113,12 +116,22 @@ where
     /// Mdns interface states.
     iface_states: HashMap<IpAddr, InterfaceState<P::Socket, P::Timer>>,
 
+    /// Addresses of all non-loopback interfaces that are up, including those not selected by
+    /// the configuration.
+    if_addrs: HashSet<IpAddr>,
+
     /// List of nodes that we have discovered, the address, and when their TTL expires.
     ///
     /// Each combination of `PeerId` and `Multiaddr` can only appear once, but the same `PeerId`
     /// can appear multiple times.
     discovered_nodes: SmallVec<[(PeerId, Multiaddr, Instant); 8]>,
 
+    /// Last metadata received from each peer in `discovered_nodes`.
+    metadata: HashMap<PeerId, BTreeMap<String, String>>,
+
+    /// Metadata changes yet to be reported as [`Event::Metadata`].
+    pending_metadata: VecDeque<(PeerId, BTreeMap<String, String>)>,
+
     /// Future that fires when the TTL of at least one node in `discovered_nodes` expires.
     ///
     /// `None` if `discovered_nodes` is empty.
@@ -127,6 +140,10 @@ where
     listen_addresses: ListenAddresses,
 
     local_peer_id: PeerId,
+
+    /// Waker of the task polling the behaviour, woken when sending packets is requested
+    /// outside of [`NetworkBehaviour::poll`].
+    waker: Option<Waker>,
 }
 
 impl<P> Behaviour<P>
@@ -134,15 +151,27 @@ where
     P: Provider,
 {
     /// Builds a new `Mdns` behaviour.
+    ///
+    /// Fails if [`Config::service_name`] is not a valid DNS name or [`Config::metadata`] can't be
+    /// encoded.
     pub fn new(config: Config, local_peer_id: PeerId) -> io::Result<Self> {
+        iface::validate_service_name(&config.service_name)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        iface::validate_metadata(&config.metadata)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
         Ok(Self {
             config,
             if_watch: P::new_watcher()?,
             iface_states: Default::default(),
+            if_addrs: Default::default(),
             discovered_nodes: Default::default(),
+            metadata: Default::default(),
+            pending_metadata: Default::default(),
             closest_expiration: Default::default(),
             listen_addresses: Default::default(),
             local_peer_id,
+            waker: None,
         })
     }
 
@@ -166,6 +195,84 @@ where
         }
         self.closest_expiration = Some(P::Timer::at(now));
     }
+
+    /// Sends a query for peers on all interfaces right away, outside of the periodic schedule
+    /// configured by [`Config::query_interval`].
+    ///
+    /// Responses are reported as [`Event::Discovered`] as usual.
+    pub fn query_now(&mut self) {
+        for iface in self.iface_states.values_mut() {
+            iface.query_now();
+        }
+        self.wake();
+    }
+
+    /// Announces our listen addresses on all interfaces right away, without waiting for a query.
+    ///
+    /// This happens automatically when we start listening on a new address. When we stop
+    /// listening on an address, remotes are told to expire it.
+    pub fn reannounce(&mut self) {
+        for iface in self.iface_states.values_mut() {
+            iface.reannounce();
+        }
+        self.wake();
+    }
+
+    /// Changes which network interfaces mDNS runs on, see [`Config::interface_filter`].
+    ///
+    /// Stops mDNS on the interfaces that are no longer selected and starts it on the interfaces
+    /// that are up and selected now.
+    pub fn set_interface_filter(&mut self, filter: Option<InterfaceFilter>) {
+        self.config.interface_filter = filter;
+
+        let config = &self.config;
+        self.iface_states.retain(|addr, _| {
+            let selected = is_selected(config, addr);
+            if !selected {
+                log::info!("dropping instance {}", addr);
+            }
+            selected
+        });
+
+        let addrs = self.if_addrs.iter().copied().collect::<Vec<_>>();
+        for addr in addrs {
+            self.start_on(addr);
+        }
+        self.wake();
+    }
+
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Starts mDNS on the interface with the given address, unless it is already running or the
+    /// interface is not selected.
+    fn start_on(&mut self, addr: IpAddr) {
+        if !is_selected(&self.config, &addr) {
+            return;
+        }
+        if let Entry::Vacant(e) = self.iface_states.entry(addr) {
+            match InterfaceState::new(addr, self.config.clone(), self.local_peer_id) {
+                Ok(iface_state) => {
+                    e.insert(iface_state);
+                }
+                Err(err) => log::error!("failed to create `InterfaceState`: {}", err),
+            }
+        }
+    }
+}
+
+/// Whether mDNS should run on the interface with the given address.
+fn is_selected(config: &Config, addr: &IpAddr) -> bool {
+    if addr.is_ipv4() && config.enable_ipv6 || addr.is_ipv6() && !config.enable_ipv6 {
+        return false;
+    }
+    match &config.interface_filter {
+        Some(filter) => filter.allows(addr),
+        None => true,
+    }
 }
 
 impl<P> NetworkBehaviour for Behaviour<P>
@@ -234,13 +341,20 @@ where
                     iface.fire_timer();
                 }
             }
+            FromSwarm::NewListenAddr(_) => {
+                log::trace!("announcing addresses because a listen address was added");
+                self.reannounce();
+            }
+            FromSwarm::ExpiredListenAddr(ExpiredListenAddr { addr, .. }) => {
+                for iface in self.iface_states.values_mut() {
+                    iface.goodbye(addr);
+                }
+            }
             FromSwarm::ConnectionClosed(_)
             | FromSwarm::ConnectionEstablished(_)
             | FromSwarm::DialFailure(_)
             | FromSwarm::AddressChange(_)
             | FromSwarm::ListenFailure(_)
-            | FromSwarm::NewListenAddr(_)
-            | FromSwarm::ExpiredListenAddr(_)
             | FromSwarm::ListenerError(_)
             | FromSwarm::ListenerClosed(_)
             | FromSwarm::NewExternalAddrCandidate(_)
@@ -254,6 +368,8 @@ where
         cx: &mut Context<'_>,
         _: &mut impl PollParameters,
     ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        self.waker = Some(cx.waker().clone());
+
         // Poll ifwatch.
         while let Poll::Ready(Some(event)) = Pin::new(&mut self.if_watch).poll_next(cx) {
             match event {
@@ -262,21 +378,11 @@ where
                     if addr.is_loopback() {
                         continue;
                     }
-                    if addr.is_ipv4() && self.config.enable_ipv6
-                        || addr.is_ipv6() && !self.config.enable_ipv6
-                    {
-                        continue;
-                    }
-                    if let Entry::Vacant(e) = self.iface_states.entry(addr) {
-                        match InterfaceState::new(addr, self.config.clone(), self.local_peer_id) {
-                            Ok(iface_state) => {
-                                e.insert(iface_state);
-                            }
-                            Err(err) => log::error!("failed to create `InterfaceState`: {}", err),
-                        }
-                    }
+                    self.if_addrs.insert(addr);
+                    self.start_on(addr);
                 }
                 Ok(IfEvent::Down(inet)) => {
+                    self.if_addrs.remove(&inet.addr());
                     if self.iface_states.contains_key(&inet.addr()) {
                         log::info!("dropping instance {}", inet.addr());
                         self.iface_states.remove(&inet.addr());
@@ -288,26 +394,49 @@ where
         // Emit discovered event.
         let mut discovered = Vec::new();
         for iface_state in self.iface_states.values_mut() {
-            while let Poll::Ready((peer, addr, expiration)) =
+            while let Poll::Ready((peer, addr, expiration, metadata)) =
                 iface_state.poll(cx, &self.listen_addresses)
             {
+                // A record with a TTL of zero tells us to expire the address right away.
+                let is_goodbye = expiration <= Instant::now();
                 if let Some((_, _, cur_expires)) = self
                     .discovered_nodes
                     .iter_mut()
                     .find(|(p, a, _)| *p == peer && *a == addr)
                 {
-                    *cur_expires = cmp::max(*cur_expires, expiration);
-                } else {
+                    if is_goodbye {
+                        *cur_expires = expiration;
+                    } else {
+                        *cur_expires = cmp::max(*cur_expires, expiration);
+                    }
+                } else if !is_goodbye {
                     log::info!("discovered: {} {}", peer, addr);
                     self.discovered_nodes.push((peer, addr.clone(), expiration));
                     discovered.push((peer, addr));
                 }
+                if is_goodbye {
+                    continue;
+                }
+                // Peers whose records carry no metadata are not reported.
+                let changed = match self.metadata.get(&peer) {
+                    Some(previous) => *previous != metadata,
+                    None => !metadata.is_empty(),
+                };
+                if changed {
+                    log::debug!("metadata of {} changed: {:?}", peer, metadata);
+                    self.metadata.insert(peer, metadata.clone());
+                    self.pending_metadata.push_back((peer, metadata));
+                }
             }
         }
         if !discovered.is_empty() {
             let event = Event::Discovered(discovered);
             return Poll::Ready(ToSwarm::GenerateEvent(event));
         }
+        // Emit metadata events after the peers have been reported as discovered.
+        if let Some((peer, metadata)) = self.pending_metadata.pop_front() {
+            return Poll::Ready(ToSwarm::GenerateEvent(Event::Metadata(peer, metadata)));
+        }
         // Emit expired event.
         let now = Instant::now();
         let mut closest_expiration = None;
@@ -321,6 +450,9 @@ where
             closest_expiration = Some(closest_expiration.unwrap_or(*expiration).min(*expiration));
             true
         });
+        let discovered_nodes = &self.discovered_nodes;
+        self.metadata
+            .retain(|peer, _| discovered_nodes.iter().any(|(p, _, _)| p == peer));
         if !expired.is_empty() {
             let event = Event::Expired(expired);
             return Poll::Ready(ToSwarm::GenerateEvent(event));
@@ -341,6 +473,12 @@ pub enum Event {
     /// Discovered nodes through mDNS.
     Discovered(Vec<(PeerId, Multiaddr)>),
 
+    /// A discovered node attached new metadata to its records, see [`Config::metadata`].
+    ///
+    /// Reported after the node was first reported in [`Event::Discovered`] unless its records
+    /// carry no metadata, and again whenever its metadata changes.
+    Metadata(PeerId, BTreeMap<String, String>),
+
     /// The given combinations of `PeerId` and `Multiaddr` have expired.
     ///
     /// Each discovered record has a time-to-live. When this TTL expires and the address hasn't
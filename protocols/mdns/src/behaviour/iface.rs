@@ -21,16 +21,18 @@
 mod dns;
 mod query;
 
+pub(crate) use self::dns::{validate_metadata, validate_service_name};
+
 use self::dns::{build_query, build_query_response, build_service_discovery_response};
 use self::query::MdnsPacket;
 use crate::behaviour::{socket::AsyncSocket, timer::Builder};
-use crate::Config;
+use crate::{AddressFilter, Config};
 use libp2p_core::Multiaddr;
 use libp2p_identity::PeerId;
 use libp2p_swarm::ListenAddresses;
 use socket2::{Domain, Socket, Type};
 use std::{
-    collections::VecDeque,
+    collections::{BTreeMap, VecDeque},
     io,
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
     pin::Pin,
@@ -87,11 +89,23 @@ pub(crate) struct InterfaceState<U, T> {
     timeout: T,
     /// Multicast address.
     multicast_addr: IpAddr,
-    /// Discovered addresses.
-    discovered: VecDeque<(PeerId, Multiaddr, Instant)>,
+    /// Discovered addresses, together with the metadata of the peer.
+    discovered: VecDeque<(PeerId, Multiaddr, Instant, BTreeMap<String, String>)>,
     /// TTL
     ttl: Duration,
+    /// Decides which listen addresses are included in responses.
+    address_filter: Option<AddressFilter>,
+    /// Metadata attached to our responses.
+    metadata: BTreeMap<String, String>,
+    /// The DNS service name to query for and respond to.
+    service_name: String,
+    /// `service_name` as a Fully Qualified Domain Name.
+    service_name_fqdn: String,
     probe_state: ProbeState,
+    /// Timer for periodically announcing our addresses without being queried.
+    announce_timer: Option<T>,
+    /// Whether our addresses are to be announced on the next poll.
+    announce_pending: bool,
     local_peer_id: PeerId,
 }
 
@@ -161,7 +175,13 @@ where
             timeout: T::interval_at(Instant::now(), INITIAL_TIMEOUT_INTERVAL),
             multicast_addr,
             ttl: config.ttl,
+            address_filter: config.address_filter,
+            metadata: config.metadata,
+            service_name_fqdn: format!("{}.", config.service_name),
+            service_name: config.service_name,
             probe_state: Default::default(),
+            announce_timer: config.announce_interval.map(T::interval),
+            announce_pending: false,
             local_peer_id,
         })
     }
@@ -176,16 +196,71 @@ where
         self.timeout = T::interval_at(Instant::now(), INITIAL_TIMEOUT_INTERVAL);
     }
 
+    /// Sends a query right away, outside of the periodic schedule.
+    pub(crate) fn query_now(&mut self) {
+        log::trace!("sending immediate query on iface {}", self.addr);
+        self.send_buffer
+            .push_back(build_query(self.service_name.as_bytes()));
+    }
+
+    /// Announces our listen addresses on the next poll, without waiting for a query.
+    pub(crate) fn reannounce(&mut self) {
+        self.announce_pending = true;
+    }
+
+    /// Announces that we no longer listen on `addr`, such that remotes expire it right away.
+    pub(crate) fn goodbye(&mut self, addr: &Multiaddr) {
+        // We never announced addresses rejected by the filter, so there is nothing to expire.
+        if !self.is_announced(addr) {
+            return;
+        }
+        log::trace!("sending goodbye for {} on iface {}", addr, self.addr);
+        // Unsolicited responses carry an id of zero, a TTL of zero expires the records.
+        let packets = self.build_response(0, std::iter::once(addr), Duration::ZERO);
+        self.send_buffer.extend(packets);
+    }
+
+    /// Builds the response packets for the given id, announcing the addresses passing the
+    /// configured address filter.
+    fn build_response<'a>(
+        &self,
+        id: u16,
+        addresses: impl Iterator<Item = &'a Multiaddr>,
+        ttl: Duration,
+    ) -> Vec<Vec<u8>> {
+        let addresses = addresses
+            .filter(|addr| self.is_announced(addr))
+            .collect::<Vec<_>>();
+
+        build_query_response(
+            id,
+            self.service_name.as_bytes(),
+            self.local_peer_id,
+            addresses.into_iter(),
+            &self.metadata,
+            ttl,
+        )
+    }
+
+    /// Whether `addr` passes the configured address filter.
+    fn is_announced(&self, addr: &Multiaddr) -> bool {
+        match &self.address_filter {
+            Some(filter) => filter.allows(addr),
+            None => true,
+        }
+    }
+
     pub(crate) fn poll(
         &mut self,
         cx: &mut Context,
         listen_addresses: &ListenAddresses,
-    ) -> Poll<(PeerId, Multiaddr, Instant)> {
+    ) -> Poll<(PeerId, Multiaddr, Instant, BTreeMap<String, String>)> {
         loop {
             // 1st priority: Low latency: Create packet ASAP after timeout.
             if Pin::new(&mut self.timeout).poll_next(cx).is_ready() {
                 log::trace!("sending query on iface {}", self.addr);
-                self.send_buffer.push_back(build_query());
+                self.send_buffer
+                    .push_back(build_query(self.service_name.as_bytes()));
                 log::trace!("tick on {:#?} {:#?}", self.addr, self.probe_state);
 
                 // Stop to probe when the initial interval reach the query interval
@@ -201,6 +276,18 @@ where
                 self.reset_timer();
             }
 
+            if let Some(timer) = self.announce_timer.as_mut() {
+                if Pin::new(timer).poll_next(cx).is_ready() {
+                    self.announce_pending = true;
+                }
+            }
+            if std::mem::take(&mut self.announce_pending) {
+                log::trace!("announcing addresses on iface {}", self.addr);
+                // Unsolicited responses carry an id of zero.
+                let packets = self.build_response(0, listen_addresses.iter(), self.ttl);
+                self.send_buffer.extend(packets);
+            }
+
             // 2nd priority: Keep local buffers small: Send packets to remote.
             if let Some(packet) = self.send_buffer.pop_front() {
                 match Pin::new(&mut self.send_socket).poll_write(
@@ -230,8 +317,13 @@ where
             // 4th priority: Remote work: Answer incoming requests.
             match Pin::new(&mut self.recv_socket)
                 .poll_read(cx, &mut self.recv_buffer)
-                .map_ok(|(len, from)| MdnsPacket::new_from_bytes(&self.recv_buffer[..len], from))
-            {
+                .map_ok(|(len, from)| {
+                    MdnsPacket::new_from_bytes(
+                        &self.recv_buffer[..len],
+                        from,
+                        &self.service_name_fqdn,
+                    )
+                }) {
                 Poll::Ready(Ok(Ok(Some(MdnsPacket::Query(query))))) => {
                     log::trace!(
                         "received query from {} on {}",
@@ -239,12 +331,9 @@ where
                         self.addr
                     );
 
-                    self.send_buffer.extend(build_query_response(
-                        query.query_id(),
-                        self.local_peer_id,
-                        listen_addresses.iter(),
-                        self.ttl,
-                    ));
+                    let packets =
+                        self.build_response(query.query_id(), listen_addresses.iter(), self.ttl);
+                    self.send_buffer.extend(packets);
                     continue;
                 }
                 Poll::Ready(Ok(Ok(Some(MdnsPacket::Response(response))))) => {
@@ -271,8 +360,11 @@ where
                         self.addr
                     );
 
-                    self.send_buffer
-                        .push_back(build_service_discovery_response(disc.query_id(), self.ttl));
+                    self.send_buffer.push_back(build_service_discovery_response(
+                        disc.query_id(),
+                        self.service_name.as_bytes(),
+                        self.ttl,
+                    ));
                     continue;
                 }
                 Poll::Ready(Err(err)) if err.kind() == std::io::ErrorKind::WouldBlock => {
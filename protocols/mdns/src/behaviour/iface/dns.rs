@@ -20,12 +20,12 @@
 
 //! (M)DNS encoding and decoding on top of the `dns_parser` library.
 
-use crate::{META_QUERY_SERVICE, SERVICE_NAME};
+use crate::META_QUERY_SERVICE;
 use libp2p_core::Multiaddr;
 use libp2p_identity::PeerId;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
-use std::{borrow::Cow, cmp, error, fmt, str, time::Duration};
+use std::{borrow::Cow, cmp, collections::BTreeMap, error, fmt, str, time::Duration};
 
 /// DNS TXT records can have up to 255 characters as a single string value.
 ///
@@ -68,9 +68,9 @@ pub(crate) fn decode_character_string(mut from: &[u8]) -> Result<Cow<'_, [u8]>,
     Ok(Cow::Borrowed(from))
 }
 
-/// Builds the binary representation of a DNS query to send on the network.
-pub(crate) fn build_query() -> MdnsPacket {
-    let mut out = Vec::with_capacity(33);
+/// Builds the binary representation of a DNS query for `service_name` to send on the network.
+pub(crate) fn build_query(service_name: &[u8]) -> MdnsPacket {
+    let mut out = Vec::with_capacity(16 + qname_len(service_name));
 
     // Program-generated transaction ID; unused by our implementation.
     append_u16(&mut out, rand::random());
@@ -88,7 +88,7 @@ pub(crate) fn build_query() -> MdnsPacket {
 
     // Our single question.
     // The name.
-    append_qname(&mut out, SERVICE_NAME);
+    append_qname(&mut out, service_name);
 
     // Flags.
     append_u16(&mut out, 0x0c);
@@ -102,11 +102,14 @@ pub(crate) fn build_query() -> MdnsPacket {
 
 /// Builds the response to an address discovery DNS query.
 ///
-/// If there are more than 2^16-1 addresses, ignores the rest.
+/// Non-empty `metadata` is included in every packet as an additional TXT record of `key=value`
+/// strings. If there are more than 2^16-1 addresses, ignores the rest.
 pub(crate) fn build_query_response<'a>(
     id: u16,
+    service_name: &[u8],
     peer_id: PeerId,
     addresses: impl ExactSizeIterator<Item = &'a Multiaddr>,
+    metadata: &BTreeMap<String, String>,
     ttl: Duration,
 ) -> Vec<MdnsPacket> {
     // Convert the TTL into seconds.
@@ -121,6 +124,30 @@ pub(crate) fn build_query_response<'a>(
     // The accumulated response packets.
     let mut packets = Vec::new();
 
+    // The metadata record, taking up the space of one address record in each packet.
+    let metadata_record = if metadata.is_empty() {
+        None
+    } else {
+        let strings = metadata
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>();
+        let mut txt_record = Vec::with_capacity(MAX_TXT_RECORD_SIZE);
+        match append_txt_record(
+            &mut txt_record,
+            &peer_name_bytes,
+            ttl,
+            strings.iter().map(String::as_str),
+        ) {
+            Ok(()) => Some(txt_record),
+            Err(e) => {
+                log::warn!("Excluding metadata from response: {:?}", e);
+                None
+            }
+        }
+    };
+    let max_records = MAX_RECORDS_PER_PACKET - usize::from(metadata_record.is_some());
+
     // The records accumulated per response packet.
     let mut records = Vec::with_capacity(addresses.len() * MAX_TXT_RECORD_SIZE);
 
@@ -129,7 +156,12 @@ pub(crate) fn build_query_response<'a>(
     for addr in addresses {
         let txt_to_send = format!("dnsaddr={}/p2p/{}", addr, peer_id.to_base58());
         let mut txt_record = Vec::with_capacity(txt_to_send.len());
-        match append_txt_record(&mut txt_record, &peer_name_bytes, ttl, &txt_to_send) {
+        match append_txt_record(
+            &mut txt_record,
+            &peer_name_bytes,
+            ttl,
+            std::iter::once(txt_to_send.as_str()),
+        ) {
             Ok(()) => {
                 records.push(txt_record);
             }
@@ -138,8 +170,15 @@ pub(crate) fn build_query_response<'a>(
             }
         }
 
-        if records.len() == MAX_RECORDS_PER_PACKET {
-            packets.push(query_response_packet(id, &peer_name_bytes, &records, ttl));
+        if records.len() == max_records {
+            records.extend(metadata_record.clone());
+            packets.push(query_response_packet(
+                id,
+                service_name,
+                &peer_name_bytes,
+                &records,
+                ttl,
+            ));
             records.clear();
         }
     }
@@ -147,7 +186,14 @@ pub(crate) fn build_query_response<'a>(
     // If there are still unpacked records, i.e. if the number of records is not
     // a multiple of `MAX_RECORDS_PER_PACKET`, create a final packet.
     if !records.is_empty() {
-        packets.push(query_response_packet(id, &peer_name_bytes, &records, ttl));
+        records.extend(metadata_record);
+        packets.push(query_response_packet(
+            id,
+            service_name,
+            &peer_name_bytes,
+            &records,
+            ttl,
+        ));
     }
 
     // If no packets have been built at all, because `addresses` is empty,
@@ -155,6 +201,7 @@ pub(crate) fn build_query_response<'a>(
     if packets.is_empty() {
         packets.push(query_response_packet(
             id,
+            service_name,
             &peer_name_bytes,
             &Vec::new(),
             ttl,
@@ -165,12 +212,16 @@ pub(crate) fn build_query_response<'a>(
 }
 
 /// Builds the response to a service discovery DNS query.
-pub(crate) fn build_service_discovery_response(id: u16, ttl: Duration) -> MdnsPacket {
+pub(crate) fn build_service_discovery_response(
+    id: u16,
+    service_name: &[u8],
+    ttl: Duration,
+) -> MdnsPacket {
     // Convert the TTL into seconds.
     let ttl = duration_to_secs(ttl);
 
-    // This capacity was determined empirically.
-    let mut out = Vec::with_capacity(69);
+    // The header, question and answer fields take 52 bytes next to the service name.
+    let mut out = Vec::with_capacity(52 + qname_len(service_name));
 
     append_u16(&mut out, id);
     // 0x84 flag for an answer.
@@ -194,8 +245,8 @@ pub(crate) fn build_service_discovery_response(id: u16, ttl: Duration) -> MdnsPa
 
     // Service name.
     {
-        let mut name = Vec::with_capacity(SERVICE_NAME.len() + 2);
-        append_qname(&mut name, SERVICE_NAME);
+        let mut name = Vec::with_capacity(qname_len(service_name));
+        append_qname(&mut name, service_name);
         append_u16(&mut out, name.len() as u16);
         out.extend_from_slice(&name);
     }
@@ -207,7 +258,13 @@ pub(crate) fn build_service_discovery_response(id: u16, ttl: Duration) -> MdnsPa
 }
 
 /// Constructs an MDNS query response packet for an address lookup.
-fn query_response_packet(id: u16, peer_id: &[u8], records: &[Vec<u8>], ttl: u32) -> MdnsPacket {
+fn query_response_packet(
+    id: u16,
+    service_name: &[u8],
+    peer_id: &[u8],
+    records: &[Vec<u8>],
+    ttl: u32,
+) -> MdnsPacket {
     let mut out = Vec::with_capacity(records.len() * MAX_TXT_RECORD_SIZE);
 
     append_u16(&mut out, id);
@@ -221,7 +278,7 @@ fn query_response_packet(id: u16, peer_id: &[u8], records: &[Vec<u8>], ttl: u32)
 
     // Our single answer.
     // The name.
-    append_qname(&mut out, SERVICE_NAME);
+    append_qname(&mut out, service_name);
 
     // Flags.
     append_u16(&mut out, 0x000c);
@@ -309,6 +366,50 @@ fn append_qname(out: &mut Vec<u8>, name: &[u8]) {
     out.push(0);
 }
 
+/// Returns the number of bytes `name` occupies when encoded as a `QNAME`.
+fn qname_len(name: &[u8]) -> usize {
+    name.len() + 2
+}
+
+/// Checks that `name` can be encoded as a `QNAME` by [`append_qname`].
+pub(crate) fn validate_service_name(name: &str) -> Result<(), InvalidServiceName> {
+    if !name.is_ascii() {
+        return Err(InvalidServiceName::NonAscii);
+    }
+    if name
+        .split('.')
+        .any(|label| label.is_empty() || label.len() >= 64)
+    {
+        return Err(InvalidServiceName::InvalidLabel);
+    }
+    Ok(())
+}
+
+/// Checks that `metadata` can be encoded as `key=value` strings of a single TXT record.
+pub(crate) fn validate_metadata(
+    metadata: &BTreeMap<String, String>,
+) -> Result<(), InvalidMetadata> {
+    // Printable characters without spaces, which are encoded verbatim by
+    // `append_character_string`, and without quotes, which are stripped when decoding.
+    let is_verbatim = |c: char| c.is_ascii_graphic() && c != '"';
+
+    let mut size = 0;
+    for (key, value) in metadata {
+        if key.is_empty() || key == "dnsaddr" || !key.chars().all(|c| is_verbatim(c) && c != '=') {
+            return Err(InvalidMetadata::InvalidKey(key.clone()));
+        }
+        if !value.chars().all(is_verbatim) {
+            return Err(InvalidMetadata::InvalidValue(key.clone()));
+        }
+        // The length prefix, key, `=` and value.
+        size += key.len() + value.len() + 2;
+    }
+    if size > MAX_TXT_VALUE_LENGTH {
+        return Err(InvalidMetadata::TooLarge);
+    }
+    Ok(())
+}
+
 /// Appends a `<character-string>` (as defined by RFC1035) to the `Vec`.
 fn append_character_string(out: &mut Vec<u8>, ascii_str: &str) -> Result<(), MdnsResponseError> {
     if !ascii_str.is_ascii() {
@@ -338,12 +439,12 @@ fn append_character_string(out: &mut Vec<u8>, ascii_str: &str) -> Result<(), Mdn
     Ok(())
 }
 
-/// Appends a TXT record to `out`.
-fn append_txt_record(
+/// Appends a TXT record holding the given strings to `out`.
+fn append_txt_record<'a>(
     out: &mut Vec<u8>,
     name: &[u8],
     ttl_secs: u32,
-    value: &str,
+    values: impl Iterator<Item = &'a str>,
 ) -> Result<(), MdnsResponseError> {
     // The name.
     out.extend_from_slice(name);
@@ -358,11 +459,14 @@ fn append_txt_record(
     append_u32(out, ttl_secs);
 
     // Add the strings.
-    if value.len() > MAX_TXT_VALUE_LENGTH {
-        return Err(MdnsResponseError::TxtRecordTooLong);
+    let mut buffer = Vec::new();
+    for value in values {
+        if value.len() > MAX_TXT_VALUE_LENGTH {
+            return Err(MdnsResponseError::TxtRecordTooLong);
+        }
+        buffer.push(value.len() as u8);
+        append_character_string(&mut buffer, value)?;
     }
-    let mut buffer = vec![value.len() as u8];
-    append_character_string(&mut buffer, value)?;
 
     append_u16(out, buffer.len() as u16);
     out.extend_from_slice(&buffer);
@@ -392,16 +496,65 @@ impl fmt::Display for MdnsResponseError {
 
 impl error::Error for MdnsResponseError {}
 
+/// Errors that can occur when validating a configured service name.
+#[derive(Debug)]
+pub(crate) enum InvalidServiceName {
+    NonAscii,
+    InvalidLabel,
+}
+
+impl fmt::Display for InvalidServiceName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidServiceName::NonAscii => {
+                write!(f, "Service name contains non-ASCII characters")
+            }
+            InvalidServiceName::InvalidLabel => write!(
+                f,
+                "Service name contains an empty label or a label longer than 63 characters"
+            ),
+        }
+    }
+}
+
+impl error::Error for InvalidServiceName {}
+
+/// Errors that can occur when validating the configured metadata.
+#[derive(Debug)]
+pub(crate) enum InvalidMetadata {
+    InvalidKey(String),
+    InvalidValue(String),
+    TooLarge,
+}
+
+impl fmt::Display for InvalidMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidMetadata::InvalidKey(key) => write!(f, "Metadata key {key:?} is invalid"),
+            InvalidMetadata::InvalidValue(key) => {
+                write!(f, "Metadata value of key {key:?} is invalid")
+            }
+            InvalidMetadata::TooLarge => write!(
+                f,
+                "Metadata exceeds {MAX_TXT_VALUE_LENGTH} bytes when encoded"
+            ),
+        }
+    }
+}
+
+impl error::Error for InvalidMetadata {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::SERVICE_NAME;
     use libp2p_identity as identity;
     use std::time::Duration;
     use trust_dns_proto::op::Message;
 
     #[test]
     fn build_query_correct() {
-        let query = build_query();
+        let query = build_query(SERVICE_NAME.as_bytes());
         assert!(Message::from_vec(&query).is_ok());
     }
 
@@ -412,8 +565,10 @@ mod tests {
         let addr2 = "/ip6/::1/udp/10000".parse().unwrap();
         let packets = build_query_response(
             0xf8f8,
+            SERVICE_NAME.as_bytes(),
             my_peer_id,
             vec![&addr1, &addr2].into_iter(),
+            &BTreeMap::new(),
             Duration::from_secs(60),
         );
         for packet in packets {
@@ -423,10 +578,51 @@ mod tests {
 
     #[test]
     fn build_service_discovery_response_correct() {
-        let query = build_service_discovery_response(0x1234, Duration::from_secs(120));
+        let query = build_service_discovery_response(
+            0x1234,
+            SERVICE_NAME.as_bytes(),
+            Duration::from_secs(120),
+        );
         assert!(Message::from_vec(&query).is_ok());
     }
 
+    #[test]
+    fn build_packets_for_custom_service_name() {
+        let service_name = b"_my-app._udp.local";
+        assert!(Message::from_vec(&build_query(service_name)).is_ok());
+        assert!(Message::from_vec(&build_service_discovery_response(
+            0x1234,
+            service_name,
+            Duration::from_secs(120)
+        ))
+        .is_ok());
+    }
+
+    #[test]
+    fn validate_metadata_entries() {
+        let metadata =
+            |key: &str, value: &str| BTreeMap::from([(key.to_owned(), value.to_owned())]);
+
+        assert!(validate_metadata(&BTreeMap::new()).is_ok());
+        assert!(validate_metadata(&metadata("version", "1.2.3")).is_ok());
+        assert!(validate_metadata(&metadata("role", "")).is_ok());
+        assert!(validate_metadata(&metadata("", "relay")).is_err());
+        assert!(validate_metadata(&metadata("dnsaddr", "/ip4/1.2.3.4")).is_err());
+        assert!(validate_metadata(&metadata("ro=le", "relay")).is_err());
+        assert!(validate_metadata(&metadata("role", "relay server")).is_err());
+        assert!(validate_metadata(&metadata("role", &"a".repeat(250))).is_err());
+    }
+
+    #[test]
+    fn validate_service_names() {
+        assert!(validate_service_name(SERVICE_NAME).is_ok());
+        assert!(validate_service_name("_my-app._udp.local").is_ok());
+        assert!(validate_service_name("_my-app..local").is_err());
+        assert!(validate_service_name("_my-app._udp.local.").is_err());
+        assert!(validate_service_name("_äpp._udp.local").is_err());
+        assert!(validate_service_name(&"a".repeat(64)).is_err());
+    }
+
     #[test]
     fn test_random_string() {
         let varsize = thread_rng().gen_range(0..32);
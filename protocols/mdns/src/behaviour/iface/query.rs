@@ -19,12 +19,13 @@
 // DEALINGS IN THE SOFTWARE.
 
 use super::dns;
-use crate::{META_QUERY_SERVICE_FQDN, SERVICE_NAME_FQDN};
+use crate::META_QUERY_SERVICE_FQDN;
 use libp2p_core::{
     address_translation,
     multiaddr::{Multiaddr, Protocol},
 };
 use libp2p_identity::PeerId;
+use std::collections::BTreeMap;
 use std::time::Instant;
 use std::{fmt, net::SocketAddr, str, time::Duration};
 use trust_dns_proto::{
@@ -44,20 +45,26 @@ pub(crate) enum MdnsPacket {
 }
 
 impl MdnsPacket {
+    /// Parses a packet, only considering queries and answers for `service_name_fqdn`.
     pub(crate) fn new_from_bytes(
         buf: &[u8],
         from: SocketAddr,
+        service_name_fqdn: &str,
     ) -> Result<Option<MdnsPacket>, trust_dns_proto::error::ProtoError> {
         let packet = Message::from_vec(buf)?;
 
         if packet.query().is_none() {
-            return Ok(Some(MdnsPacket::Response(MdnsResponse::new(&packet, from))));
+            return Ok(Some(MdnsPacket::Response(MdnsResponse::new(
+                &packet,
+                from,
+                service_name_fqdn,
+            ))));
         }
 
         if packet
             .queries()
             .iter()
-            .any(|q| q.name().to_utf8() == SERVICE_NAME_FQDN)
+            .any(|q| q.name().to_utf8() == service_name_fqdn)
         {
             return Ok(Some(MdnsPacket::Query(MdnsQuery {
                 from,
@@ -147,12 +154,12 @@ pub(crate) struct MdnsResponse {
 
 impl MdnsResponse {
     /// Creates a new `MdnsResponse` based on the provided `Packet`.
-    pub(crate) fn new(packet: &Message, from: SocketAddr) -> MdnsResponse {
+    pub(crate) fn new(packet: &Message, from: SocketAddr, service_name_fqdn: &str) -> MdnsResponse {
         let peers = packet
             .answers()
             .iter()
             .filter_map(|record| {
-                if record.name().to_string() != SERVICE_NAME_FQDN {
+                if record.name().to_string() != service_name_fqdn {
                     return None;
                 }
 
@@ -172,7 +179,7 @@ impl MdnsResponse {
         &self,
         now: Instant,
         local_peer_id: PeerId,
-    ) -> impl Iterator<Item = (PeerId, Multiaddr, Instant)> + '_ {
+    ) -> impl Iterator<Item = (PeerId, Multiaddr, Instant, BTreeMap<String, String>)> + '_ {
         self.discovered_peers()
             .filter(move |peer| peer.id() != &local_peer_id)
             .flat_map(move |peer| {
@@ -182,7 +189,12 @@ impl MdnsResponse {
                 peer.addresses().iter().filter_map(move |address| {
                     let new_addr = address_translation(address, &observed)?;
 
-                    Some((*peer.id(), new_addr, new_expiration))
+                    Some((
+                        *peer.id(),
+                        new_addr,
+                        new_expiration,
+                        peer.metadata().clone(),
+                    ))
                 })
             })
     }
@@ -224,12 +236,15 @@ pub(crate) struct MdnsPeer {
     peer_id: PeerId,
     /// TTL of the record in seconds.
     ttl: u32,
+    /// Metadata the peer attached to its records.
+    metadata: BTreeMap<String, String>,
 }
 
 impl MdnsPeer {
     /// Creates a new `MdnsPeer` based on the provided `Packet`.
     pub(crate) fn new(packet: &Message, record_value: &Name, ttl: u32) -> Option<MdnsPeer> {
         let mut my_peer_id: Option<PeerId> = None;
+        let mut metadata = BTreeMap::new();
         let addrs = packet
             .additionals()
             .iter()
@@ -247,14 +262,21 @@ impl MdnsPeer {
             .flat_map(|txt| txt.iter())
             .filter_map(|txt| {
                 // TODO: wrong, txt can be multiple character strings
-                let addr = match dns::decode_character_string(txt) {
+                let txt = match dns::decode_character_string(txt) {
                     Ok(a) => a,
                     Err(_) => return None,
                 };
-                if !addr.starts_with(b"dnsaddr=") {
+                if !txt.starts_with(b"dnsaddr=") {
+                    // Any other `key=value` string is metadata of the peer.
+                    if let Some((key, value)) = str::from_utf8(&txt)
+                        .ok()
+                        .and_then(|entry| entry.split_once('='))
+                    {
+                        metadata.insert(key.to_owned(), value.to_owned());
+                    }
                     return None;
                 }
-                let addr = match str::from_utf8(&addr[8..]) {
+                let addr = match str::from_utf8(&txt[8..]) {
                     Ok(a) => a,
                     Err(_) => return None,
                 };
@@ -282,6 +304,7 @@ impl MdnsPeer {
             addrs,
             peer_id,
             ttl,
+            metadata,
         })
     }
 
@@ -303,6 +326,11 @@ impl MdnsPeer {
     pub(crate) fn addresses(&self) -> &Vec<Multiaddr> {
         &self.addrs
     }
+
+    /// Returns the metadata the peer attached to its records.
+    pub(crate) fn metadata(&self) -> &BTreeMap<String, String> {
+        &self.metadata
+    }
 }
 
 impl fmt::Debug for MdnsPeer {
@@ -317,6 +345,7 @@ impl fmt::Debug for MdnsPeer {
 mod tests {
     use super::super::dns::build_query_response;
     use super::*;
+    use crate::SERVICE_NAME;
 
     #[test]
     fn test_create_mdns_peer() {
@@ -330,8 +359,10 @@ mod tests {
 
         let packets = build_query_response(
             0xf8f8,
+            SERVICE_NAME.as_bytes(),
             peer_id,
             vec![&addr1, &addr2].into_iter(),
+            &BTreeMap::from([("role".to_owned(), "relay".to_owned())]),
             Duration::from_secs(60),
         );
 
@@ -341,7 +372,7 @@ mod tests {
                 .answers()
                 .iter()
                 .filter_map(|record| {
-                    if record.name().to_utf8() != SERVICE_NAME_FQDN {
+                    if record.name().to_utf8() != format!("{SERVICE_NAME}.") {
                         return None;
                     }
                     let record_value = match record.data() {
@@ -355,6 +386,11 @@ mod tests {
 
             let peer = MdnsPeer::new(&packet, record_value, ttl).expect("fail to create peer");
             assert_eq!(peer.peer_id, peer_id);
+            assert_eq!(peer.addrs.len(), 2);
+            assert_eq!(
+                peer.metadata,
+                BTreeMap::from([("role".to_owned(), "relay".to_owned())])
+            );
         }
     }
 }
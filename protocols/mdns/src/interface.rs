@@ -0,0 +1,109 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Looks up the network interface an address is assigned to.
+
+use std::net::IpAddr;
+
+/// Returns the name and index of the network interface `addr` is assigned to.
+#[cfg(unix)]
+pub(crate) fn lookup(addr: &IpAddr) -> Option<(String, u32)> {
+    use std::ffi::CStr;
+
+    let mut ifaddrs = std::ptr::null_mut();
+    // SAFETY: On success, `ifaddrs` points to a linked list that we free below.
+    if unsafe { libc::getifaddrs(&mut ifaddrs) } != 0 {
+        log::debug!(
+            "failed to list network interfaces: {}",
+            std::io::Error::last_os_error()
+        );
+        return None;
+    }
+
+    let mut interface = None;
+    let mut next = ifaddrs;
+    while !next.is_null() {
+        // SAFETY: `next` is a non-null entry of the list returned by `getifaddrs`.
+        let ifaddr = unsafe { &*next };
+        next = ifaddr.ifa_next;
+        // SAFETY: `ifa_addr` is null or points to a socket address of the given family.
+        if ifaddr.ifa_addr.is_null() || unsafe { to_ip(ifaddr.ifa_addr) } != Some(*addr) {
+            continue;
+        }
+        // SAFETY: `ifa_name` points to the nul-terminated name of the interface.
+        let name = unsafe { CStr::from_ptr(ifaddr.ifa_name) };
+        let index = unsafe { libc::if_nametoindex(ifaddr.ifa_name) };
+        interface = Some((name.to_string_lossy().into_owned(), index));
+        break;
+    }
+
+    // SAFETY: `ifaddrs` was returned by `getifaddrs` and is not used afterwards.
+    unsafe { libc::freeifaddrs(ifaddrs) };
+
+    interface
+}
+
+/// Interfaces are not looked up on non-Unix platforms.
+#[cfg(not(unix))]
+pub(crate) fn lookup(_: &IpAddr) -> Option<(String, u32)> {
+    None
+}
+
+/// Converts an IPv4 or IPv6 socket address to its IP address.
+///
+/// # Safety
+///
+/// `sockaddr` must point to a valid socket address of the family it specifies.
+#[cfg(unix)]
+unsafe fn to_ip(sockaddr: *const libc::sockaddr) -> Option<IpAddr> {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    match i32::from((*sockaddr).sa_family) {
+        libc::AF_INET => {
+            let sockaddr = &*(sockaddr as *const libc::sockaddr_in);
+            Some(Ipv4Addr::from(u32::from_be(sockaddr.sin_addr.s_addr)).into())
+        }
+        libc::AF_INET6 => {
+            let sockaddr = &*(sockaddr as *const libc::sockaddr_in6);
+            Some(Ipv6Addr::from(sockaddr.sin6_addr.s6_addr).into())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::InterfaceFilter;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn filters_by_name_and_index() {
+        let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let (name, index) = lookup(&localhost).expect("loopback interface to exist");
+        assert_ne!(index, 0);
+
+        assert!(InterfaceFilter::name(name.clone()).allows(&localhost));
+        assert!(InterfaceFilter::index(index).allows(&localhost));
+        assert!(!InterfaceFilter::name(format!("{name}-other")).allows(&localhost));
+        assert!(!InterfaceFilter::index(index + 1).allows(&localhost));
+        assert!(!InterfaceFilter::name(name).allows(&IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))));
+    }
+}
@@ -35,10 +35,15 @@
 
 #![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
 
-use std::net::{Ipv4Addr, Ipv6Addr};
+use libp2p_core::Multiaddr;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
 use std::time::Duration;
 
 mod behaviour;
+mod interface;
 pub use crate::behaviour::{Behaviour, Event};
 
 #[cfg(feature = "async-io")]
@@ -48,10 +53,8 @@ pub use crate::behaviour::async_io;
 pub use crate::behaviour::tokio;
 
 /// The DNS service name for all libp2p peers used to query for addresses.
-const SERVICE_NAME: &[u8] = b"_p2p._udp.local";
-/// `SERVICE_NAME` as a Fully Qualified Domain Name.
-const SERVICE_NAME_FQDN: &str = "_p2p._udp.local.";
-/// The meta query for looking up the `SERVICE_NAME`.
+const SERVICE_NAME: &str = "_p2p._udp.local";
+/// The meta query for looking up the service name.
 const META_QUERY_SERVICE: &[u8] = b"_services._dns-sd._udp.local";
 /// `META_QUERY_SERVICE` as a Fully Qualified Domain Name.
 const META_QUERY_SERVICE_FQDN: &str = "_services._dns-sd._udp.local.";
@@ -63,6 +66,8 @@ pub const IPV6_MDNS_MULTICAST_ADDRESS: Ipv6Addr = Ipv6Addr::new(0xFF02, 0, 0, 0,
 #[derive(Debug, Clone)]
 pub struct Config {
     /// TTL to use for mdns records.
+    ///
+    /// Remotes expire our addresses if they are not refreshed within the TTL.
     pub ttl: Duration,
     /// Interval at which to poll the network for new peers. This isn't
     /// necessary during normal operation but avoids the case that an
@@ -70,8 +75,152 @@ pub struct Config {
     /// peer joins the network. Receiving an mdns packet resets the timer
     /// preventing unnecessary traffic.
     pub query_interval: Duration,
+    /// Interval at which to announce our addresses without being queried, refreshing them at
+    /// remotes before their TTL expires.
+    ///
+    /// Our addresses are always announced when they change, see [`Behaviour::reannounce`].
+    /// Defaults to `None`, i.e. addresses are only periodically refreshed by remote queries.
+    pub announce_interval: Option<Duration>,
     /// Use IPv6 instead of IPv4.
+    ///
+    /// mDNS runs either on the IPv4 or on the IPv6 interfaces, never on both.
     pub enable_ipv6: bool,
+    /// Decides which network interfaces mDNS runs on, identified by their address, name or index.
+    ///
+    /// By default, mDNS runs on all non-loopback interfaces of the configured IP version.
+    /// See [`Behaviour::set_interface_filter`] to change the filter at runtime.
+    pub interface_filter: Option<InterfaceFilter>,
+    /// Decides which of our listen addresses are announced on the local network.
+    ///
+    /// By default, all listen addresses are announced.
+    pub address_filter: Option<AddressFilter>,
+    /// The DNS service name used to query for and announce addresses, without a trailing dot.
+    ///
+    /// Only peers using the same service name discover each other, allowing private deployments
+    /// to not be discovered by unrelated libp2p applications on the same network.
+    /// Defaults to `_p2p._udp.local`.
+    pub service_name: String,
+    /// Metadata attached to our records, e.g. the version or role of the application, allowing
+    /// remotes to filter peers before dialing them.
+    ///
+    /// Entries are announced as `key=value` strings and may only contain printable ASCII
+    /// characters other than spaces and `"`. Keys may not contain `=` nor be `dnsaddr`.
+    /// All entries together may take up to 255 bytes, counting one extra byte per entry.
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// Predicate deciding whether mDNS runs on a network interface.
+///
+/// Interfaces are selected by their address, see [`InterfaceFilter::new`], or by their name or
+/// index, see [`InterfaceFilter::name`] and [`InterfaceFilter::index`].
+///
+/// ```
+/// # use libp2p_mdns::{Config, InterfaceFilter};
+/// # use std::net::IpAddr;
+/// // Only run on the interfaces of the local `192.168.1.0/24` network.
+/// let config = Config {
+///     interface_filter: Some(InterfaceFilter::new(|addr| match addr {
+///         IpAddr::V4(ip) => ip.octets()[..3] == [192, 168, 1],
+///         IpAddr::V6(_) => false,
+///     })),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone)]
+pub struct InterfaceFilter(InterfaceSelector);
+
+#[derive(Clone)]
+enum InterfaceSelector {
+    Address(Arc<dyn Fn(&IpAddr) -> bool + Send + Sync>),
+    Name(String),
+    Index(u32),
+}
+
+impl InterfaceFilter {
+    /// Creates a filter selecting the interfaces with an address for which `filter` returns
+    /// `true`.
+    pub fn new<F>(filter: F) -> Self
+    where
+        F: Fn(&IpAddr) -> bool + Send + Sync + 'static,
+    {
+        InterfaceFilter(InterfaceSelector::Address(Arc::new(filter)))
+    }
+
+    /// Creates a filter selecting the interface with the given name, e.g. `eth0`.
+    ///
+    /// Interfaces can only be looked up by name on Unix platforms. Elsewhere, no interface
+    /// is selected.
+    pub fn name(name: impl Into<String>) -> Self {
+        InterfaceFilter(InterfaceSelector::Name(name.into()))
+    }
+
+    /// Creates a filter selecting the interface with the given index.
+    ///
+    /// Interfaces can only be looked up by index on Unix platforms. Elsewhere, no interface
+    /// is selected.
+    pub fn index(index: u32) -> Self {
+        InterfaceFilter(InterfaceSelector::Index(index))
+    }
+
+    pub(crate) fn allows(&self, addr: &IpAddr) -> bool {
+        match &self.0 {
+            InterfaceSelector::Address(filter) => filter(addr),
+            InterfaceSelector::Name(name) => {
+                interface::lookup(addr).map_or(false, |(n, _)| n == *name)
+            }
+            InterfaceSelector::Index(index) => {
+                interface::lookup(addr).map_or(false, |(_, i)| i == *index)
+            }
+        }
+    }
+}
+
+impl fmt::Debug for InterfaceFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            InterfaceSelector::Address(_) => f.debug_tuple("InterfaceFilter").finish(),
+            InterfaceSelector::Name(name) => f.debug_tuple("InterfaceFilter").field(name).finish(),
+            InterfaceSelector::Index(index) => {
+                f.debug_tuple("InterfaceFilter").field(index).finish()
+            }
+        }
+    }
+}
+
+/// Predicate deciding whether a listen address is included in our mDNS responses.
+///
+/// ```
+/// # use libp2p_mdns::{AddressFilter, Config};
+/// # use libp2p_core::multiaddr::Protocol;
+/// // Never announce relayed addresses.
+/// let config = Config {
+///     address_filter: Some(AddressFilter::new(|addr| {
+///         !addr.iter().any(|p| p == Protocol::P2pCircuit)
+///     })),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone)]
+pub struct AddressFilter(Arc<dyn Fn(&Multiaddr) -> bool + Send + Sync>);
+
+impl AddressFilter {
+    /// Creates a filter announcing the addresses for which `filter` returns `true`.
+    pub fn new<F>(filter: F) -> Self
+    where
+        F: Fn(&Multiaddr) -> bool + Send + Sync + 'static,
+    {
+        AddressFilter(Arc::new(filter))
+    }
+
+    pub(crate) fn allows(&self, addr: &Multiaddr) -> bool {
+        (self.0)(addr)
+    }
+}
+
+impl fmt::Debug for AddressFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AddressFilter").finish()
+    }
 }
 
 impl Default for Config {
@@ -79,7 +228,12 @@ impl Default for Config {
         Self {
             ttl: Duration::from_secs(6 * 60),
             query_interval: Duration::from_secs(5 * 60),
+            announce_interval: None,
             enable_ipv6: false,
+            interface_filter: None,
+            address_filter: None,
+            service_name: SERVICE_NAME.to_owned(),
+            metadata: BTreeMap::new(),
         }
     }
 }
@@ -19,10 +19,16 @@
 // DEALINGS IN THE SOFTWARE.use futures::StreamExt;
 
 use futures::future::Either;
+use futures::StreamExt;
+use libp2p_core::multiaddr::Protocol;
+use libp2p_core::Multiaddr;
 use libp2p_mdns::Event;
-use libp2p_mdns::{async_io::Behaviour, Config};
+use libp2p_mdns::{async_io::Behaviour, AddressFilter, Config, InterfaceFilter};
 use libp2p_swarm::{Swarm, SwarmEvent};
 use libp2p_swarm_test::SwarmExt as _;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
 #[async_std::test]
@@ -118,6 +124,298 @@ async fn test_no_expiration_on_close_async_std() {
     .await;
 }
 
+#[async_std::test]
+async fn test_address_filter_async_std() {
+    env_logger::try_init().ok();
+
+    // Only announce the addresses of the first listener of `b`.
+    let announced_port = Arc::new(AtomicU16::new(0));
+    let config = Config {
+        address_filter: Some(AddressFilter::new({
+            let announced_port = announced_port.clone();
+            move |addr| tcp_port(addr) == Some(announced_port.load(Ordering::SeqCst))
+        })),
+        ..Default::default()
+    };
+    let mut b = create_swarm(config).await;
+    let b_peer_id = *b.local_peer_id();
+    let port = b.listeners().find_map(tcp_port).unwrap();
+    announced_port.store(port, Ordering::SeqCst);
+
+    let expected_listener_id = b.listen_on("/ip4/0.0.0.0/tcp/0".parse().unwrap()).unwrap();
+    b.wait(|e| match e {
+        SwarmEvent::NewListenAddr { listener_id, .. } => {
+            (listener_id == expected_listener_id).then_some(())
+        }
+        _ => None,
+    })
+    .await;
+    async_std::task::spawn(b.loop_on_next());
+
+    let mut a = create_swarm(Config::default()).await;
+
+    loop {
+        if let Event::Discovered(peers) = a.next_behaviour_event().await {
+            let ports = peers
+                .into_iter()
+                .filter(|(p, _)| p == &b_peer_id)
+                .map(|(_, addr)| tcp_port(&addr))
+                .collect::<Vec<_>>();
+            if ports.is_empty() {
+                continue;
+            }
+            assert!(ports.into_iter().all(|p| p == Some(port)));
+            return;
+        }
+    }
+}
+
+#[async_std::test]
+async fn test_custom_service_name_async_std() {
+    env_logger::try_init().ok();
+
+    let config = Config {
+        service_name: "_my-app._udp.local".to_owned(),
+        ..Default::default()
+    };
+
+    let other = create_swarm(Config::default()).await;
+    let other_peer_id = *other.local_peer_id();
+    async_std::task::spawn(other.loop_on_next());
+
+    let mut a = create_swarm(config.clone()).await;
+    let b = create_swarm(config).await;
+    let b_peer_id = *b.local_peer_id();
+    async_std::task::spawn(b.loop_on_next());
+
+    loop {
+        if let Event::Discovered(peers) = a.next_behaviour_event().await {
+            assert!(peers.iter().all(|(p, _)| p != &other_peer_id));
+            if peers.iter().any(|(p, _)| p == &b_peer_id) {
+                return;
+            }
+        }
+    }
+}
+
+#[async_std::test]
+async fn test_interface_filter_async_std() {
+    env_logger::try_init().ok();
+
+    let config = Config {
+        interface_filter: Some(InterfaceFilter::new(|_| false)),
+        ..Default::default()
+    };
+    let mut a = create_swarm(config).await;
+
+    let b = create_swarm(Config::default()).await;
+    let b_peer_id = *b.local_peer_id();
+    async_std::task::spawn(b.loop_on_next());
+
+    // Without any interfaces selected, `a` doesn't discover `b`.
+    let discovered = async_std::future::timeout(Duration::from_secs(2), async {
+        loop {
+            if let Event::Discovered(peers) = a.next_behaviour_event().await {
+                if peers.into_iter().any(|(p, _)| p == b_peer_id) {
+                    return;
+                }
+            }
+        }
+    })
+    .await;
+    assert!(discovered.is_err());
+
+    a.behaviour_mut().set_interface_filter(None);
+
+    loop {
+        if let Event::Discovered(peers) = a.next_behaviour_event().await {
+            if peers.into_iter().any(|(p, _)| p == b_peer_id) {
+                return;
+            }
+        }
+    }
+}
+
+#[async_std::test]
+async fn test_reannounce_on_listen_addr_changes_async_std() {
+    env_logger::try_init().ok();
+
+    // Don't rely on periodic queries to learn about address changes.
+    let config = Config {
+        query_interval: Duration::from_secs(600),
+        ..Default::default()
+    };
+
+    let mut a = create_swarm(config.clone()).await;
+    let mut b = create_swarm(config).await;
+    let b_peer_id = *b.local_peer_id();
+    let old_port = b.listeners().find_map(tcp_port).unwrap();
+
+    loop {
+        if let Either::Left((Event::Discovered(peers), _)) =
+            futures::future::select(a.next_behaviour_event(), b.select_next_some()).await
+        {
+            if peers.into_iter().any(|(p, _)| p == b_peer_id) {
+                break;
+            }
+        }
+    }
+
+    // 1. A new listen address is announced right away.
+    let listener_id = b.listen_on("/ip4/0.0.0.0/tcp/0".parse().unwrap()).unwrap();
+    let new_port = loop {
+        if let Either::Left((Event::Discovered(peers), _)) =
+            futures::future::select(a.next_behaviour_event(), b.select_next_some()).await
+        {
+            if let Some(port) = peers
+                .iter()
+                .filter(|(p, _)| p == &b_peer_id)
+                .filter_map(|(_, addr)| tcp_port(addr))
+                .find(|port| *port != old_port)
+            {
+                break port;
+            }
+        }
+    };
+
+    // 2. A removed listen address is expired right away.
+    assert!(b.remove_listener(listener_id));
+    loop {
+        if let Either::Left((Event::Expired(peers), _)) =
+            futures::future::select(a.next_behaviour_event(), b.select_next_some()).await
+        {
+            let ports = peers
+                .iter()
+                .filter(|(p, _)| p == &b_peer_id)
+                .filter_map(|(_, addr)| tcp_port(addr))
+                .collect::<Vec<_>>();
+            assert!(!ports.contains(&old_port));
+            if ports.contains(&new_port) {
+                return;
+            }
+        }
+    }
+}
+
+#[async_std::test]
+async fn test_query_now_async_std() {
+    env_logger::try_init().ok();
+
+    // Don't rely on periodic queries to rediscover peers, nor on queries of other tests.
+    let config = Config {
+        query_interval: Duration::from_secs(600),
+        service_name: "_query-now._udp.local".to_owned(),
+        ..Default::default()
+    };
+
+    let mut a = create_swarm(config.clone()).await;
+    let mut b = create_swarm(config).await;
+    let b_peer_id = *b.local_peer_id();
+
+    loop {
+        if let Either::Left((Event::Discovered(peers), _)) =
+            futures::future::select(a.next_behaviour_event(), b.select_next_some()).await
+        {
+            if peers.into_iter().any(|(p, _)| p == b_peer_id) {
+                break;
+            }
+        }
+    }
+
+    // Let the packets sent while starting up settle.
+    let _ = async_std::future::timeout(Duration::from_secs(1), async {
+        loop {
+            futures::future::select(a.next_behaviour_event(), b.select_next_some()).await;
+        }
+    })
+    .await;
+
+    a.behaviour_mut().expire_node(&b_peer_id);
+    loop {
+        if let Either::Left((Event::Expired(peers), _)) =
+            futures::future::select(a.next_behaviour_event(), b.select_next_some()).await
+        {
+            if peers.into_iter().any(|(p, _)| p == b_peer_id) {
+                break;
+            }
+        }
+    }
+    assert!(!a.behaviour().has_node(&b_peer_id));
+
+    // Without periodic queries, `a` doesn't rediscover `b` by itself.
+    let rediscovered = async_std::future::timeout(Duration::from_secs(2), async {
+        loop {
+            if let Either::Left((Event::Discovered(peers), _)) =
+                futures::future::select(a.next_behaviour_event(), b.select_next_some()).await
+            {
+                if peers.into_iter().any(|(p, _)| p == b_peer_id) {
+                    return;
+                }
+            }
+        }
+    })
+    .await;
+    assert!(rediscovered.is_err());
+
+    a.behaviour_mut().query_now();
+    loop {
+        if let Either::Left((Event::Discovered(peers), _)) =
+            futures::future::select(a.next_behaviour_event(), b.select_next_some()).await
+        {
+            if peers.into_iter().any(|(p, _)| p == b_peer_id) {
+                return;
+            }
+        }
+    }
+}
+
+#[async_std::test]
+async fn test_metadata_async_std() {
+    env_logger::try_init().ok();
+
+    let mut a = create_swarm(Config::default()).await;
+
+    let metadata = BTreeMap::from([
+        ("app".to_owned(), "chat/1.2.0".to_owned()),
+        ("role".to_owned(), "relay".to_owned()),
+    ]);
+    let config = Config {
+        metadata: metadata.clone(),
+        ..Default::default()
+    };
+    let b = create_swarm(config).await;
+    let b_peer_id = *b.local_peer_id();
+    async_std::task::spawn(b.loop_on_next());
+
+    loop {
+        if let Event::Metadata(peer, m) = a.next_behaviour_event().await {
+            if peer == b_peer_id {
+                assert_eq!(m, metadata);
+                return;
+            }
+        }
+    }
+}
+
+#[test]
+fn invalid_metadata_is_rejected() {
+    let config = Config {
+        metadata: BTreeMap::from([("role".to_owned(), "relay server".to_owned())]),
+        ..Default::default()
+    };
+    let Err(error) = Behaviour::new(config, libp2p_identity::PeerId::random()) else {
+        panic!("expected invalid metadata to be rejected")
+    };
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+fn tcp_port(addr: &Multiaddr) -> Option<u16> {
+    addr.iter().find_map(|p| match p {
+        Protocol::Tcp(port) => Some(port),
+        _ => None,
+    })
+}
+
 async fn run_discovery_test(config: Config) {
     let mut a = create_swarm(config.clone()).await;
     let a_peer_id = *a.local_peer_id();
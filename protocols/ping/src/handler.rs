@@ -18,10 +18,12 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use crate::{protocol, PROTOCOL_NAME};
+use crate::protocol::{self, PING_SIZE};
+use crate::PROTOCOL_NAME;
 use futures::future::{BoxFuture, Either};
 use futures::prelude::*;
 use futures_timer::Delay;
+use instant::Instant;
 use libp2p_core::upgrade::ReadyUpgrade;
 use libp2p_identity::PeerId;
 use libp2p_swarm::handler::{
@@ -32,6 +34,7 @@ use libp2p_swarm::{
     StreamUpgradeError, SubstreamProtocol,
 };
 use std::collections::VecDeque;
+use std::sync::Arc;
 use std::{
     error::Error,
     fmt, io,
@@ -40,6 +43,9 @@ use std::{
 };
 use void::Void;
 
+/// The maximum number of bytes sent in an outbound ping, see [`Config::with_payload_size`].
+const MAX_PAYLOAD_SIZE: usize = 64 * 1024;
+
 /// The configuration for outbound pings.
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -47,6 +53,41 @@ pub struct Config {
     timeout: Duration,
     /// The duration between outbound pings.
     interval: Duration,
+    /// The duration between outbound pings on connections with recent activity.
+    active_interval: Option<Duration>,
+    /// The number of bytes sent in an outbound ping.
+    payload_size: usize,
+    /// The number of round-trip times kept per peer.
+    pub(crate) rtt_window: usize,
+    /// The relative change of the average round-trip time that is reported.
+    pub(crate) rtt_change_threshold: Option<f64>,
+    /// Decides how to react to ping failures.
+    pub(crate) failure_policy: Option<FailurePolicy>,
+}
+
+/// Policy deciding how to react to ping failures, see [`Config::with_failure_policy`].
+#[derive(Clone)]
+pub(crate) struct FailurePolicy(pub(crate) Arc<FailurePolicyFn>);
+
+type FailurePolicyFn = dyn Fn(&PeerId, u32, &Failure) -> FailureAction + Send + Sync;
+
+impl fmt::Debug for FailurePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("FailurePolicy").finish()
+    }
+}
+
+/// The reaction to a ping failure, returned by the policy set via [`Config::with_failure_policy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureAction {
+    /// Only report the failure.
+    Ignore,
+    /// Report the action in [`Event::failure_action`](crate::Event::failure_action), e.g. to
+    /// mark the peer as unhealthy.
+    Tag,
+    /// Report the action in [`Event::failure_action`](crate::Event::failure_action) and close
+    /// the connection.
+    CloseConnection,
 }
 
 impl Config {
@@ -64,6 +105,11 @@ impl Config {
         Self {
             timeout: Duration::from_secs(20),
             interval: Duration::from_secs(15),
+            active_interval: None,
+            payload_size: PING_SIZE,
+            rtt_window: 16,
+            rtt_change_threshold: None,
+            failure_policy: None,
         }
     }
 
@@ -78,6 +124,79 @@ impl Config {
         self.interval = d;
         self
     }
+
+    /// Sets the ping interval of connections with recent activity.
+    ///
+    /// A connection is considered active if application traffic was reported on it via
+    /// [`Behaviour::record_activity`](crate::Behaviour::record_activity) within the last
+    /// [`Config::with_interval`]. Answered inbound pings do not count as activity. Pings on active
+    /// connections are deferred until this interval has passed since the previous ping, while
+    /// idle connections are pinged at the regular interval.
+    ///
+    /// This allows setting a short interval to detect broken idle connections early, without
+    /// adding overhead to busy connections. Disabled by default.
+    pub fn with_active_interval(mut self, d: Duration) -> Self {
+        self.active_interval = Some(d);
+        self
+    }
+
+    /// Sets the number of bytes sent in an outbound ping.
+    ///
+    /// Remotes answer pings in chunks of 32 bytes, thus the size is rounded up to a multiple
+    /// of 32. Larger pings measure the round-trip time of larger packets, e.g. to probe whether
+    /// they make it through the path to the remote. Sizes above 64 KiB are capped at 64 KiB.
+    /// Defaults to 32.
+    pub fn with_payload_size(mut self, size: usize) -> Self {
+        let size = size.clamp(1, MAX_PAYLOAD_SIZE);
+        self.payload_size = (size + PING_SIZE - 1) / PING_SIZE * PING_SIZE;
+        self
+    }
+
+    /// Sets the number of most recent round-trip times per peer that
+    /// [`Behaviour::rtt_stats`](crate::Behaviour::rtt_stats) are computed over.
+    ///
+    /// Defaults to 16.
+    pub fn with_rtt_window(mut self, samples: usize) -> Self {
+        self.rtt_window = samples.max(1);
+        self
+    }
+
+    /// Reports the [`Event::rtt_changed`](crate::Event::rtt_changed) statistics whenever the
+    /// average round-trip time to a peer changes by more than the given fraction, e.g. `0.5` for
+    /// 50%, relative to the average when the last change was reported.
+    ///
+    /// Disabled by default.
+    pub fn with_rtt_change_threshold(mut self, threshold: f64) -> Self {
+        self.rtt_change_threshold = Some(threshold);
+        self
+    }
+
+    /// Sets the policy deciding how to react to ping failures.
+    ///
+    /// The policy is called for every failure reported in an [`Event`](crate::Event) with the
+    /// remote peer, the number of consecutive failures on the connection, including this one,
+    /// and the failure. The first failure on a connection is not reported but still counted.
+    /// For example, to close connections after three consecutive failures:
+    ///
+    /// ```
+    /// # use libp2p_ping as ping;
+    /// let config = ping::Config::new().with_failure_policy(|_, failures, _| {
+    ///     if failures >= 3 {
+    ///         ping::FailureAction::CloseConnection
+    ///     } else {
+    ///         ping::FailureAction::Ignore
+    ///     }
+    /// });
+    /// ```
+    ///
+    /// By default, failures are only reported.
+    pub fn with_failure_policy<F>(mut self, policy: F) -> Self
+    where
+        F: Fn(&PeerId, u32, &Failure) -> FailureAction + Send + Sync + 'static,
+    {
+        self.failure_policy = Some(FailurePolicy(Arc::new(policy)));
+        self
+    }
 }
 
 impl Default for Config {
@@ -101,7 +220,7 @@ pub enum Failure {
 }
 
 impl Failure {
-    fn other(e: impl std::error::Error + Send + 'static) -> Self {
+    pub(crate) fn other(e: impl std::error::Error + Send + 'static) -> Self {
         Self::Other { error: Box::new(e) }
     }
 }
@@ -149,6 +268,19 @@ pub struct Handler {
     state: State,
     /// The peer we are connected to.
     peer: PeerId,
+    /// When the last outbound ping was sent.
+    last_ping: Instant,
+    /// When we last saw activity on the connection.
+    last_activity: Option<Instant>,
+    /// Whether the connection is kept alive until the result of the first ping is reported.
+    one_shot: bool,
+}
+
+/// An event from [`Behaviour`](crate::Behaviour) to the [`Handler`].
+#[derive(Debug)]
+pub enum InEvent {
+    /// There was application traffic on the connection.
+    Activity,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -176,9 +308,40 @@ impl Handler {
             outbound: None,
             inbound: None,
             state: State::Active,
+            last_ping: Instant::now(),
+            last_activity: None,
+            one_shot: false,
+        }
+    }
+
+    /// Builds a [`Handler`] that keeps the connection alive until it reported the result of
+    /// its first ping, see [`Behaviour::ping_once`](crate::Behaviour::ping_once).
+    pub(crate) fn new_one_shot(config: Config, peer: PeerId) -> Self {
+        Handler {
+            one_shot: true,
+            ..Handler::new(config, peer)
         }
     }
 
+    /// Returns how much longer the next ping should be deferred due to recent activity.
+    fn active_interval_remaining(&self) -> Option<Duration> {
+        let active_interval = self.config.active_interval?;
+        if self.last_activity?.elapsed() >= self.config.interval {
+            return None;
+        }
+
+        active_interval
+            .checked_sub(self.last_ping.elapsed())
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    fn start_ping(&mut self, stream: Stream) {
+        self.last_ping = Instant::now();
+        self.outbound = Some(OutboundState::Ping(
+            send_ping(stream, self.config.payload_size, self.config.timeout).boxed(),
+        ));
+    }
+
     fn on_dial_upgrade_error(
         &mut self,
         DialUpgradeError { error, .. }: DialUpgradeError<
@@ -211,8 +374,10 @@ impl Handler {
 }
 
 impl ConnectionHandler for Handler {
-    type FromBehaviour = Void;
-    type ToBehaviour = Result<Duration, Failure>;
+    type FromBehaviour = InEvent;
+    /// The round-trip time of a successful ping, or a failure along with the number of
+    /// consecutive failures on the connection, including this one.
+    type ToBehaviour = Result<Duration, (Failure, u32)>;
     type Error = Void;
     type InboundProtocol = ReadyUpgrade<StreamProtocol>;
     type OutboundProtocol = ReadyUpgrade<StreamProtocol>;
@@ -223,9 +388,17 @@ impl ConnectionHandler for Handler {
         SubstreamProtocol::new(ReadyUpgrade::new(PROTOCOL_NAME), ())
     }
 
-    fn on_behaviour_event(&mut self, _: Void) {}
+    fn on_behaviour_event(&mut self, event: InEvent) {
+        match event {
+            InEvent::Activity => self.last_activity = Some(Instant::now()),
+        }
+    }
 
     fn connection_keep_alive(&self) -> KeepAlive {
+        if self.one_shot {
+            return KeepAlive::Yes;
+        }
+
         KeepAlive::No
     }
 
@@ -233,12 +406,7 @@ impl ConnectionHandler for Handler {
         &mut self,
         cx: &mut Context<'_>,
     ) -> Poll<
-        ConnectionHandlerEvent<
-            ReadyUpgrade<StreamProtocol>,
-            (),
-            Result<Duration, Failure>,
-            Self::Error,
-        >,
+        ConnectionHandlerEvent<ReadyUpgrade<StreamProtocol>, (), Self::ToBehaviour, Self::Error>,
     > {
         match self.state {
             State::Inactive { reported: true } => {
@@ -246,9 +414,11 @@ impl ConnectionHandler for Handler {
             }
             State::Inactive { reported: false } => {
                 self.state = State::Inactive { reported: true };
-                return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(Err(
+                self.one_shot = false;
+                return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(Err((
                     Failure::Unsupported,
-                )));
+                    1,
+                ))));
             }
             State::Active => {}
         }
@@ -282,8 +452,12 @@ impl ConnectionHandler for Handler {
                 // for each ping to have successful ping exchanges with peers
                 // that use a single substream, since every successful ping
                 // resets `failures` to `0`.
-                if self.failures > 1 {
-                    return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(Err(error)));
+                if self.failures > 1 || self.one_shot {
+                    self.one_shot = false;
+                    return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(Err((
+                        error,
+                        self.failures,
+                    ))));
                 }
             }
 
@@ -298,6 +472,7 @@ impl ConnectionHandler for Handler {
                         log::debug!("latency to {} is {}ms", self.peer, rtt.as_millis());
 
                         self.failures = 0;
+                        self.one_shot = false;
                         self.interval.reset(self.config.interval);
                         self.outbound = Some(OutboundState::Idle(stream));
                         return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(Ok(rtt)));
@@ -312,9 +487,13 @@ impl ConnectionHandler for Handler {
                         break;
                     }
                     Poll::Ready(()) => {
-                        self.outbound = Some(OutboundState::Ping(
-                            send_ping(stream, self.config.timeout).boxed(),
-                        ));
+                        if let Some(remaining) = self.active_interval_remaining() {
+                            log::trace!("Deferring ping to active peer {}", self.peer);
+                            self.interval.reset(remaining);
+                            self.outbound = Some(OutboundState::Idle(stream));
+                            continue;
+                        }
+                        self.start_ping(stream);
                     }
                 },
                 Some(OutboundState::OpenStream) => {
@@ -354,9 +533,7 @@ impl ConnectionHandler for Handler {
                 protocol: stream,
                 ..
             }) => {
-                self.outbound = Some(OutboundState::Ping(
-                    send_ping(stream, self.config.timeout).boxed(),
-                ));
+                self.start_ping(stream);
             }
             ConnectionEvent::DialUpgradeError(dial_upgrade_error) => {
                 self.on_dial_upgrade_error(dial_upgrade_error)
@@ -383,8 +560,12 @@ enum OutboundState {
 }
 
 /// A wrapper around [`protocol::send_ping`] that enforces a time out.
-async fn send_ping(stream: Stream, timeout: Duration) -> Result<(Stream, Duration), Failure> {
-    let ping = protocol::send_ping(stream);
+async fn send_ping(
+    stream: Stream,
+    size: usize,
+    timeout: Duration,
+) -> Result<(Stream, Duration), Failure> {
+    let ping = protocol::send_ping(stream, size);
     futures::pin_mut!(ping);
 
     match future::select(ping, Delay::new(timeout)).await {
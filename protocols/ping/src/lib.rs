@@ -49,22 +49,28 @@
 
 mod handler;
 mod protocol;
+mod stats;
 
-use handler::Handler;
+use handler::{FailurePolicy, Handler, InEvent};
 use libp2p_core::{Endpoint, Multiaddr};
 use libp2p_identity::PeerId;
 use libp2p_swarm::{
-    behaviour::FromSwarm, ConnectionDenied, ConnectionId, NetworkBehaviour, PollParameters,
-    THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
+    behaviour::{ConnectionClosed, DialFailure, FromSwarm},
+    dial_opts::DialOpts,
+    CloseConnection, ConnectionDenied, ConnectionId, NetworkBehaviour, NotifyHandler,
+    PollParameters, THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
 };
+use stats::RttWindow;
 use std::time::Duration;
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, HashSet, VecDeque},
+    io,
     task::{Context, Poll},
 };
 
 pub use self::protocol::PROTOCOL_NAME;
-pub use handler::{Config, Failure};
+pub use handler::{Config, Failure, FailureAction};
+pub use stats::RttStats;
 
 /// A [`NetworkBehaviour`] that responds to inbound pings and
 /// periodically sends outbound pings on every established connection.
@@ -74,11 +80,16 @@ pub struct Behaviour {
     /// Configuration for outbound pings.
     config: Config,
     /// Queue of events to yield to the swarm.
-    events: VecDeque<Event>,
+    events: VecDeque<ToSwarm<Event, InEvent>>,
+    /// The most recent round-trip times to each connected peer.
+    rtts: HashMap<PeerId, RttWindow>,
+    /// The connections of pending [`Behaviour::ping_once`] requests.
+    one_shots: HashSet<ConnectionId>,
 }
 
 /// Event generated by the `Ping` network behaviour.
 #[derive(Debug)]
+#[non_exhaustive]
 pub struct Event {
     /// The peer ID of the remote.
     pub peer: PeerId,
@@ -86,6 +97,12 @@ pub struct Event {
     pub connection: ConnectionId,
     /// The result of an inbound or outbound ping.
     pub result: Result<Duration, Failure>,
+    /// The statistics of the most recent round-trip times to the peer, if their average changed
+    /// by more than the threshold configured via [`Config::with_rtt_change_threshold`].
+    pub rtt_changed: Option<RttStats>,
+    /// The action taken upon a failure by the policy configured via
+    /// [`Config::with_failure_policy`], unless it ignored the failure.
+    pub failure_action: Option<FailureAction>,
 }
 
 impl Behaviour {
@@ -94,8 +111,48 @@ impl Behaviour {
         Self {
             config,
             events: VecDeque::new(),
+            rtts: HashMap::new(),
+            one_shots: HashSet::new(),
         }
     }
+
+    /// Dials the given address, pings the remote once and closes the connection again.
+    ///
+    /// The result is reported as an [`Event`] of the returned [`ConnectionId`]. The connection
+    /// is kept alive until the ping completed, regardless of other behaviours. Its address, i.e.
+    /// the transport it was established over, is reported by the swarm once established, as is
+    /// the error if it could not be established.
+    pub fn ping_once(&mut self, address: Multiaddr) -> ConnectionId {
+        let opts = DialOpts::unknown_peer_id().address(address).build();
+        let connection = opts.connection_id();
+
+        self.one_shots.insert(connection);
+        self.events.push_front(ToSwarm::Dial { opts });
+
+        connection
+    }
+
+    /// Returns statistics of the most recent round-trip times to the given peer,
+    /// measured across all connections to it.
+    ///
+    /// The number of round-trip times taken into account is configured via
+    /// [`Config::with_rtt_window`]. Returns `None` if we are not connected to the
+    /// peer or did not yet ping it successfully.
+    pub fn rtt_stats(&self, peer: &PeerId) -> Option<RttStats> {
+        self.rtts.get(peer)?.stats()
+    }
+
+    /// Records application traffic on the given connection.
+    ///
+    /// Pings on connections with recent activity are sent at the interval configured via
+    /// [`Config::with_active_interval`], if any.
+    pub fn record_activity(&mut self, peer: PeerId, connection: ConnectionId) {
+        self.events.push_front(ToSwarm::NotifyHandler {
+            peer_id: peer,
+            handler: NotifyHandler::One(connection),
+            event: InEvent::Activity,
+        });
+    }
 }
 
 impl Default for Behaviour {
@@ -120,11 +177,15 @@ impl NetworkBehaviour for Behaviour {
 
     fn handle_established_outbound_connection(
         &mut self,
-        _: ConnectionId,
+        connection: ConnectionId,
         peer: PeerId,
         _: &Multiaddr,
         _: Endpoint,
     ) -> Result<THandler<Self>, ConnectionDenied> {
+        if self.one_shots.contains(&connection) {
+            return Ok(Handler::new_one_shot(self.config.clone(), peer));
+        }
+
         Ok(Handler::new(self.config.clone(), peer))
     }
 
@@ -134,11 +195,64 @@ impl NetworkBehaviour for Behaviour {
         connection: ConnectionId,
         result: THandlerOutEvent<Self>,
     ) {
-        self.events.push_front(Event {
+        let (result, failures) = match result {
+            Ok(rtt) => (Ok(rtt), 0),
+            Err((failure, failures)) => (Err(failure), failures),
+        };
+
+        if self.one_shots.remove(&connection) {
+            self.events.push_front(ToSwarm::GenerateEvent(Event {
+                peer,
+                connection,
+                result,
+                rtt_changed: None,
+                failure_action: None,
+            }));
+            self.events.push_front(ToSwarm::CloseConnection {
+                peer_id: peer,
+                connection: CloseConnection::One(connection),
+            });
+            return;
+        }
+
+        let mut rtt_changed = None;
+        let mut failure_action = None;
+        match &result {
+            Ok(rtt) => {
+                rtt_changed = self
+                    .rtts
+                    .entry(peer)
+                    .or_insert_with(|| RttWindow::new(self.config.rtt_window))
+                    .record(*rtt, self.config.rtt_change_threshold);
+            }
+            Err(failure) => {
+                if let Some(FailurePolicy(policy)) = &self.config.failure_policy {
+                    failure_action = match policy(&peer, failures, failure) {
+                        FailureAction::Ignore => None,
+                        action => {
+                            log::debug!(
+                                "Ping failure policy triggered for {peer} after {failures} failures: {action:?}"
+                            );
+                            Some(action)
+                        }
+                    };
+                }
+            }
+        }
+
+        self.events.push_front(ToSwarm::GenerateEvent(Event {
             peer,
             connection,
             result,
-        })
+            rtt_changed,
+            failure_action,
+        }));
+        if failure_action == Some(FailureAction::CloseConnection) {
+            self.events.push_front(ToSwarm::CloseConnection {
+                peer_id: peer,
+                connection: CloseConnection::One(connection),
+            });
+        }
     }
 
     fn poll(
@@ -147,7 +261,7 @@ impl NetworkBehaviour for Behaviour {
         _: &mut impl PollParameters,
     ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
         if let Some(e) = self.events.pop_back() {
-            Poll::Ready(ToSwarm::GenerateEvent(e))
+            Poll::Ready(e)
         } else {
             Poll::Pending
         }
@@ -155,10 +269,32 @@ impl NetworkBehaviour for Behaviour {
 
     fn on_swarm_event(&mut self, event: FromSwarm<Self::ConnectionHandler>) {
         match event {
+            FromSwarm::ConnectionClosed(ConnectionClosed {
+                peer_id,
+                connection_id,
+                remaining_established,
+                ..
+            }) => {
+                if self.one_shots.remove(&connection_id) {
+                    self.events.push_front(ToSwarm::GenerateEvent(Event {
+                        peer: peer_id,
+                        connection: connection_id,
+                        result: Err(Failure::other(io::Error::from(
+                            io::ErrorKind::ConnectionAborted,
+                        ))),
+                        rtt_changed: None,
+                        failure_action: None,
+                    }));
+                }
+                if remaining_established == 0 {
+                    self.rtts.remove(&peer_id);
+                }
+            }
+            FromSwarm::DialFailure(DialFailure { connection_id, .. }) => {
+                self.one_shots.remove(&connection_id);
+            }
             FromSwarm::ConnectionEstablished(_)
-            | FromSwarm::ConnectionClosed(_)
             | FromSwarm::AddressChange(_)
-            | FromSwarm::DialFailure(_)
             | FromSwarm::ListenFailure(_)
             | FromSwarm::NewListener(_)
             | FromSwarm::NewListenAddr(_)
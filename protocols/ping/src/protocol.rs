@@ -21,16 +21,16 @@
 use futures::prelude::*;
 use instant::Instant;
 use libp2p_swarm::StreamProtocol;
-use rand::{distributions, prelude::*};
+use rand::prelude::*;
 use std::{io, time::Duration};
 
 pub const PROTOCOL_NAME: StreamProtocol = StreamProtocol::new("/ipfs/ping/1.0.0");
 
 /// The `Ping` protocol upgrade.
 ///
-/// The ping protocol sends 32 bytes of random data in configurable
-/// intervals over a single outbound substream, expecting to receive
-/// the same bytes as a response. At the same time, incoming pings
+/// The ping protocol sends 32 bytes, or a configurable multiple thereof, of
+/// random data in configurable intervals over a single outbound substream,
+/// expecting to receive the same bytes as a response. At the same time, incoming pings
 /// on inbound substreams are answered by sending back the received bytes.
 ///
 /// At most a single inbound and outbound substream is kept open at
@@ -47,18 +47,24 @@ pub const PROTOCOL_NAME: StreamProtocol = StreamProtocol::new("/ipfs/ping/1.0.0"
 /// >           connections.
 #[derive(Default, Debug, Copy, Clone)]
 pub(crate) struct Ping;
-const PING_SIZE: usize = 32;
+pub(crate) const PING_SIZE: usize = 32;
 
-/// Sends a ping and waits for the pong.
-pub(crate) async fn send_ping<S>(mut stream: S) -> io::Result<(S, Duration)>
+/// Sends a ping of `size` bytes and waits for the pong.
+///
+/// Pings larger than [`PING_SIZE`] are answered as multiple pings by the remote,
+/// thus `size` has to be a multiple of [`PING_SIZE`].
+pub(crate) async fn send_ping<S>(mut stream: S, size: usize) -> io::Result<(S, Duration)>
 where
     S: AsyncRead + AsyncWrite + Unpin,
 {
-    let payload: [u8; PING_SIZE] = thread_rng().sample(distributions::Standard);
+    debug_assert_eq!(size % PING_SIZE, 0);
+
+    let mut payload = vec![0u8; size];
+    thread_rng().fill_bytes(&mut payload);
     stream.write_all(&payload).await?;
     stream.flush().await?;
     let started = Instant::now();
-    let mut recv_payload = [0u8; PING_SIZE];
+    let mut recv_payload = vec![0u8; size];
     stream.read_exact(&mut recv_payload).await?;
     if recv_payload == payload {
         Ok((stream, started.elapsed()))
@@ -118,7 +124,7 @@ mod tests {
                 .unwrap()
                 .await
                 .unwrap();
-            let (_, rtt) = send_ping(c).await.unwrap();
+            let (_, rtt) = send_ping(c, PING_SIZE).await.unwrap();
             assert!(rtt > Duration::from_secs(0));
         });
     }
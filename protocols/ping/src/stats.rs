@@ -0,0 +1,169 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Statistics of the most recent round-trip times measured to a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RttStats {
+    /// The number of round-trip times the statistics are computed over.
+    pub samples: usize,
+    /// The smallest round-trip time.
+    pub min: Duration,
+    /// The average round-trip time.
+    pub avg: Duration,
+    /// The 95th percentile of the round-trip times.
+    pub p95: Duration,
+    /// The standard deviation of the round-trip times.
+    pub std_dev: Duration,
+    /// The average difference between consecutive round-trip times.
+    pub jitter: Duration,
+}
+
+/// A rolling window of the round-trip times measured to a peer.
+#[derive(Debug)]
+pub(crate) struct RttWindow {
+    samples: VecDeque<Duration>,
+    capacity: usize,
+    /// The average round-trip time when the last change was reported.
+    reported_avg: Option<Duration>,
+}
+
+impl RttWindow {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+            reported_avg: None,
+        }
+    }
+
+    /// Adds a round-trip time to the window, evicting the oldest one if it is full.
+    ///
+    /// Returns the new statistics if the average changed by more than `threshold`,
+    /// relative to the average when the last change was reported.
+    pub(crate) fn record(&mut self, rtt: Duration, threshold: Option<f64>) -> Option<RttStats> {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(rtt);
+
+        let threshold = threshold?;
+        let stats = self.stats()?;
+        let Some(reported) = self.reported_avg else {
+            self.reported_avg = Some(stats.avg);
+            return None;
+        };
+
+        let change = (stats.avg.as_secs_f64() - reported.as_secs_f64()).abs();
+        if change <= reported.as_secs_f64() * threshold {
+            return None;
+        }
+
+        self.reported_avg = Some(stats.avg);
+        Some(stats)
+    }
+
+    pub(crate) fn stats(&self) -> Option<RttStats> {
+        let mut sorted = Vec::from_iter(self.samples.iter().copied());
+        sorted.sort_unstable();
+
+        let samples = sorted.len();
+        let min = *sorted.first()?;
+        let avg = sorted.iter().sum::<Duration>() / samples as u32;
+        let p95 = sorted[(samples * 95 + 99) / 100 - 1];
+
+        let variance = self
+            .samples
+            .iter()
+            .map(|rtt| (rtt.as_secs_f64() - avg.as_secs_f64()).powi(2))
+            .sum::<f64>()
+            / samples as f64;
+        let std_dev = Duration::from_secs_f64(variance.sqrt());
+
+        let jitter = self
+            .samples
+            .iter()
+            .zip(self.samples.iter().skip(1))
+            .map(|(a, b)| a.max(b).saturating_sub(*a.min(b)))
+            .sum::<Duration>()
+            .checked_div(samples as u32 - 1)
+            .unwrap_or_default();
+
+        Some(RttStats {
+            samples,
+            min,
+            avg,
+            p95,
+            std_dev,
+            jitter,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn stats_over_rolling_window() {
+        let mut window = RttWindow::new(20);
+        for rtt in 1..=25 {
+            window.record(ms(rtt), None);
+        }
+
+        let stats = window.stats().unwrap();
+        assert_eq!(stats.samples, 20);
+        assert_eq!(stats.min, ms(6));
+        assert_eq!(stats.avg, Duration::from_micros(15_500));
+        assert_eq!(stats.p95, ms(24));
+        assert_eq!(stats.jitter, ms(1));
+    }
+
+    #[test]
+    fn jitter_and_std_dev() {
+        let mut window = RttWindow::new(4);
+        for rtt in [10, 30, 10, 30] {
+            window.record(ms(rtt), None);
+        }
+
+        let stats = window.stats().unwrap();
+        assert_eq!(stats.avg, ms(20));
+        assert_eq!(stats.std_dev, ms(10));
+        assert_eq!(stats.jitter, ms(20));
+    }
+
+    #[test]
+    fn reports_significant_changes() {
+        let mut window = RttWindow::new(1);
+
+        assert_eq!(window.record(ms(100), Some(0.5)), None);
+        assert_eq!(window.record(ms(140), Some(0.5)), None);
+        assert_eq!(window.record(ms(160), Some(0.5)).unwrap().avg, ms(160));
+        // Changes are relative to the last reported average.
+        assert_eq!(window.record(ms(100), Some(0.5)), None);
+        assert_eq!(window.record(ms(60), Some(0.5)).unwrap().avg, ms(60));
+    }
+}
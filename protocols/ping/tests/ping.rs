@@ -63,6 +63,71 @@ fn assert_ping_rtt_less_than_50ms(e: ping::Event) {
     assert!(rtt < Duration::from_millis(50))
 }
 
+#[test]
+fn larger_payloads_are_answered() {
+    let mut swarm1 = Swarm::new_ephemeral(|_| Behaviour::new(ping::Config::new()));
+    let mut swarm2 =
+        Swarm::new_ephemeral(|_| Behaviour::new(ping::Config::new().with_payload_size(1000)));
+
+    async_std::task::block_on(async {
+        swarm1.listen().await;
+        swarm2.connect(&mut swarm1).await;
+
+        let ([BehaviourEvent::Ping(e1)], [BehaviourEvent::Ping(e2)]) =
+            libp2p_swarm_test::drive(&mut swarm1, &mut swarm2).await;
+        assert_ping_rtt_less_than_50ms(e1);
+        assert_ping_rtt_less_than_50ms(e2);
+    });
+}
+
+#[test]
+fn oversized_payloads_are_capped() {
+    let mut swarm1 = Swarm::new_ephemeral(|_| Behaviour::new(ping::Config::new()));
+    let mut swarm2 =
+        Swarm::new_ephemeral(|_| Behaviour::new(ping::Config::new().with_payload_size(usize::MAX)));
+
+    async_std::task::block_on(async {
+        swarm1.listen().await;
+        swarm2.connect(&mut swarm1).await;
+
+        let ([BehaviourEvent::Ping(e1)], [BehaviourEvent::Ping(e2)]) =
+            libp2p_swarm_test::drive(&mut swarm1, &mut swarm2).await;
+        e1.result.expect("a ping success");
+        e2.result.expect("a ping success");
+    });
+}
+
+#[test]
+fn rtt_stats() {
+    let cfg = ping::Config::new()
+        .with_interval(Duration::from_millis(10))
+        .with_rtt_window(4);
+
+    let mut swarm1 = Swarm::new_ephemeral(|_| Behaviour::new(cfg.clone()));
+    let mut swarm2 = Swarm::new_ephemeral(|_| Behaviour::new(cfg.clone()));
+    let swarm2_peer_id = *swarm2.local_peer_id();
+
+    async_std::task::block_on(async {
+        swarm1.listen().await;
+        swarm2.connect(&mut swarm1).await;
+
+        for _ in 0..6 {
+            let ([BehaviourEvent::Ping(_)], [BehaviourEvent::Ping(_)]) =
+                libp2p_swarm_test::drive(&mut swarm1, &mut swarm2).await;
+        }
+
+        let stats = swarm1.behaviour().ping.rtt_stats(&swarm2_peer_id).unwrap();
+        assert_eq!(stats.samples, 4);
+        assert!(stats.min <= stats.avg && stats.avg <= stats.p95);
+
+        swarm1.disconnect_peer_id(swarm2_peer_id).unwrap();
+        swarm1
+            .wait(|event| matches!(event, SwarmEvent::ConnectionClosed { .. }).then_some(()))
+            .await;
+        assert!(swarm1.behaviour().ping.rtt_stats(&swarm2_peer_id).is_none());
+    });
+}
+
 #[test]
 fn unsupported_doesnt_fail() {
     let mut swarm1 = Swarm::new_ephemeral(|_| keep_alive::Behaviour);
@@ -96,6 +161,183 @@ fn unsupported_doesnt_fail() {
     result.expect("node with ping should not fail connection due to unsupported protocol");
 }
 
+#[test]
+fn pings_on_active_connections_are_deferred() {
+    let mut swarm1 = Swarm::new_ephemeral(|_| {
+        Behaviour::new(
+            ping::Config::new()
+                .with_interval(Duration::from_millis(50))
+                .with_active_interval(Duration::from_secs(60)),
+        )
+    });
+    let mut swarm2 = Swarm::new_ephemeral(|_| Behaviour::new(ping::Config::new()));
+
+    async_std::task::block_on(async {
+        swarm1.listen().await;
+        swarm2.connect(&mut swarm1).await;
+        async_std::task::spawn(swarm2.loop_on_next());
+
+        let (peer, connection) = swarm1
+            .wait(|event| match event {
+                SwarmEvent::Behaviour(BehaviourEvent::Ping(e)) => {
+                    let ids = (e.peer, e.connection);
+                    assert_ping_rtt_less_than_50ms(e);
+                    Some(ids)
+                }
+                _ => None,
+            })
+            .await;
+
+        // Application traffic keeps the connection active, deferring the next ping.
+        for _ in 0..30 {
+            swarm1
+                .behaviour_mut()
+                .ping
+                .record_activity(peer, connection);
+
+            if let Ok(SwarmEvent::Behaviour(BehaviourEvent::Ping(e))) =
+                async_std::future::timeout(Duration::from_millis(10), swarm1.next_swarm_event())
+                    .await
+            {
+                panic!("Unexpected ping on active connection: {e:?}");
+            }
+        }
+    });
+}
+
+#[test]
+fn inbound_pings_do_not_defer_pings() {
+    let mut swarm1 = Swarm::new_ephemeral(|_| {
+        Behaviour::new(
+            ping::Config::new()
+                .with_interval(Duration::from_millis(50))
+                .with_active_interval(Duration::from_secs(60)),
+        )
+    });
+    let mut swarm2 = Swarm::new_ephemeral(|_| {
+        Behaviour::new(ping::Config::new().with_interval(Duration::from_millis(5)))
+    });
+
+    async_std::task::block_on(async {
+        swarm1.listen().await;
+        swarm2.connect(&mut swarm1).await;
+        async_std::task::spawn(swarm2.loop_on_next());
+
+        // The inbound pings of `swarm2` are no application traffic, `swarm1` keeps pinging
+        // at the regular interval.
+        for _ in 0..3 {
+            let e = swarm1
+                .wait(|event| match event {
+                    SwarmEvent::Behaviour(BehaviourEvent::Ping(e)) => Some(e),
+                    _ => None,
+                })
+                .await;
+            assert_ping_rtt_less_than_50ms(e);
+        }
+    });
+}
+
+#[test]
+fn failure_policy_closes_connection() {
+    let mut swarm1 = Swarm::new_ephemeral(|_| keep_alive::Behaviour);
+    let mut swarm2 = Swarm::new_ephemeral(|_| {
+        Behaviour::new(
+            ping::Config::new().with_failure_policy(|_, failures, failure| {
+                assert_eq!(failures, 1);
+                assert!(matches!(failure, ping::Failure::Unsupported));
+
+                ping::FailureAction::CloseConnection
+            }),
+        )
+    });
+
+    async_std::task::block_on(async {
+        swarm1.listen().await;
+        swarm2.connect(&mut swarm1).await;
+        let swarm1_peer_id = *swarm1.local_peer_id();
+        async_std::task::spawn(swarm1.loop_on_next());
+
+        let mut triggered = false;
+        loop {
+            match swarm2.next_swarm_event().await {
+                SwarmEvent::Behaviour(BehaviourEvent::Ping(ping::Event {
+                    peer,
+                    failure_action,
+                    ..
+                })) => {
+                    assert_eq!(peer, swarm1_peer_id);
+                    assert_eq!(failure_action, Some(ping::FailureAction::CloseConnection));
+                    triggered = true;
+                }
+                SwarmEvent::ConnectionClosed { .. } => break,
+                _ => {}
+            }
+        }
+        assert!(triggered);
+    });
+}
+
+#[test]
+fn ping_once() {
+    let mut swarm1 = Swarm::new_ephemeral(|_| Behaviour::new(ping::Config::new()));
+    let mut swarm2 = Swarm::new_ephemeral(|_| ping::Behaviour::new(ping::Config::new()));
+
+    async_std::task::block_on(async {
+        let (listen_addr, _) = swarm1.listen().await;
+        let swarm1_peer_id = *swarm1.local_peer_id();
+        async_std::task::spawn(swarm1.loop_on_next());
+
+        let connection_id = swarm2.behaviour_mut().ping_once(listen_addr.clone());
+
+        let address = swarm2
+            .wait(|event| match event {
+                SwarmEvent::ConnectionEstablished { endpoint, .. } => {
+                    Some(endpoint.get_remote_address().clone())
+                }
+                _ => None,
+            })
+            .await;
+        assert_eq!(address, listen_addr);
+
+        let event = swarm2
+            .wait(|event| match event {
+                SwarmEvent::Behaviour(e) => Some(e),
+                _ => None,
+            })
+            .await;
+        assert_eq!(event.connection, connection_id);
+        assert_eq!(event.peer, swarm1_peer_id);
+        assert_ping_rtt_less_than_50ms(event);
+
+        swarm2
+            .wait(|event| match event {
+                SwarmEvent::ConnectionClosed { connection_id, .. } => Some(connection_id),
+                _ => None,
+            })
+            .await;
+    });
+}
+
+#[test]
+fn ping_once_reports_dial_failure() {
+    let mut swarm = Swarm::new_ephemeral(|_| ping::Behaviour::new(ping::Config::new()));
+
+    async_std::task::block_on(async {
+        let connection_id = swarm
+            .behaviour_mut()
+            .ping_once("/memory/1234".parse().unwrap());
+
+        let failed_connection_id = swarm
+            .wait(|event| match event {
+                SwarmEvent::OutgoingConnectionError { connection_id, .. } => Some(connection_id),
+                SwarmEvent::Behaviour(e) => panic!("Unexpected event: {e:?}"),
+                _ => None,
+            })
+            .await;
+        assert_eq!(failed_connection_id, connection_id);
+    });
+}
+
 #[derive(NetworkBehaviour, Default)]
 #[behaviour(prelude = "libp2p_swarm::derive_prelude")]
 struct Behaviour {
@@ -0,0 +1,512 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Advertisement of relay servers and discovery of relays by clients.
+//!
+//! Relay servers are advertised under the well-known [`NAMESPACE`], either as Kademlia provider
+//! records or as rendezvous registrations, see the `kad` and `rendezvous` modules behind the
+//! features of the same name. A relay server wraps the [`NetworkBehaviour`] advertising it in the
+//! advertisement [`Behaviour`], which advertises the relay server while it is enabled and
+//! publicly reachable, and withdraws the advertisement otherwise.
+//!
+//! Clients discover relays from the same sources and add them as candidates to the relay
+//! selection, i.e. `client::selection::Behaviour` of the `selection` feature.
+
+mod handler;
+
+pub use handler::Handler;
+
+use crate::multiaddr_ext::MultiaddrExt;
+use either::Either;
+use libp2p_core::{Endpoint, Multiaddr};
+use libp2p_identity::PeerId;
+use libp2p_swarm::behaviour::{
+    ConnectionClosed, ExternalAddrConfirmed, ExternalAddrExpired, FromSwarm,
+};
+use libp2p_swarm::{
+    ConnectionDenied, ConnectionId, NetworkBehaviour, PollParameters, THandler, THandlerInEvent,
+    THandlerOutEvent, ToSwarm,
+};
+use std::collections::HashSet;
+use std::task::{Context, Poll};
+
+/// The well-known namespace relay servers are advertised under.
+pub const NAMESPACE: &str = "/libp2p/relay";
+
+/// A [`NetworkBehaviour`] able to advertise the local node as a relay server.
+pub trait Advertise: NetworkBehaviour {
+    /// Starts advertising the local node as a relay server.
+    fn advertise(&mut self);
+
+    /// Stops advertising the local node as a relay server.
+    fn withdraw(&mut self);
+}
+
+/// [`NetworkBehaviour`] advertising the local relay server through the wrapped [`Advertise`]
+/// behaviour.
+///
+/// The relay server is advertised while it is enabled, i.e. a relay server
+/// [`Behaviour`](crate::Behaviour) is part of the swarm, and publicly reachable, i.e. it has at
+/// least one confirmed external address that is not itself relayed. All events of the wrapped
+/// behaviour are passed through.
+pub struct Behaviour<A> {
+    inner: A,
+
+    /// Whether a relay server is part of the swarm, as reported by the connection handlers.
+    relay_server: bool,
+    /// The confirmed external addresses the relay server is reachable at.
+    external_addresses: HashSet<Multiaddr>,
+    /// Whether the relay server is currently advertised.
+    advertised: bool,
+}
+
+impl<A> Behaviour<A>
+where
+    A: Advertise,
+{
+    pub fn new(inner: A) -> Self {
+        Self {
+            inner,
+            relay_server: false,
+            external_addresses: Default::default(),
+            advertised: false,
+        }
+    }
+
+    /// Returns whether the relay server is currently advertised.
+    pub fn is_advertised(&self) -> bool {
+        self.advertised
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.inner
+    }
+
+    /// Advertises or withdraws the relay server if its state changed.
+    fn update(&mut self) {
+        let advertise = self.relay_server && !self.external_addresses.is_empty();
+        if advertise == self.advertised {
+            return;
+        }
+
+        if advertise {
+            log::debug!("Advertising relay server");
+            self.inner.advertise();
+        } else {
+            log::debug!("Withdrawing relay server advertisement");
+            self.inner.withdraw();
+        }
+        self.advertised = advertise;
+    }
+}
+
+impl<A> NetworkBehaviour for Behaviour<A>
+where
+    A: Advertise,
+{
+    type ConnectionHandler = Handler<THandler<A>>;
+    type ToSwarm = A::ToSwarm;
+
+    fn handle_pending_inbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        local_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<(), ConnectionDenied> {
+        self.inner
+            .handle_pending_inbound_connection(connection_id, local_addr, remote_addr)
+    }
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        local_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        let handler = self.inner.handle_established_inbound_connection(
+            connection_id,
+            peer,
+            local_addr,
+            remote_addr,
+        )?;
+
+        Ok(Handler::new(handler))
+    }
+
+    fn handle_pending_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        maybe_peer: Option<PeerId>,
+        addresses: &[Multiaddr],
+        effective_role: Endpoint,
+    ) -> Result<Vec<Multiaddr>, ConnectionDenied> {
+        self.inner.handle_pending_outbound_connection(
+            connection_id,
+            maybe_peer,
+            addresses,
+            effective_role,
+        )
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        addr: &Multiaddr,
+        role_override: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        let handler = self.inner.handle_established_outbound_connection(
+            connection_id,
+            peer,
+            addr,
+            role_override,
+        )?;
+
+        Ok(Handler::new(handler))
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm<Self::ConnectionHandler>) {
+        let event = match event {
+            FromSwarm::ConnectionClosed(ConnectionClosed {
+                peer_id,
+                connection_id,
+                endpoint,
+                handler,
+                remaining_established,
+            }) => FromSwarm::ConnectionClosed(ConnectionClosed {
+                peer_id,
+                connection_id,
+                endpoint,
+                handler: handler.into_inner(),
+                remaining_established,
+            }),
+            FromSwarm::ExternalAddrConfirmed(e @ ExternalAddrConfirmed { addr }) => {
+                if !addr.is_relayed() {
+                    self.external_addresses.insert(addr.clone());
+                    self.update();
+                }
+                FromSwarm::ExternalAddrConfirmed(e)
+            }
+            FromSwarm::ExternalAddrExpired(e @ ExternalAddrExpired { addr }) => {
+                if self.external_addresses.remove(addr) {
+                    self.update();
+                }
+                FromSwarm::ExternalAddrExpired(e)
+            }
+            FromSwarm::ConnectionEstablished(e) => FromSwarm::ConnectionEstablished(e),
+            FromSwarm::AddressChange(e) => FromSwarm::AddressChange(e),
+            FromSwarm::DialFailure(e) => FromSwarm::DialFailure(e),
+            FromSwarm::ListenFailure(e) => FromSwarm::ListenFailure(e),
+            FromSwarm::NewListener(e) => FromSwarm::NewListener(e),
+            FromSwarm::NewListenAddr(e) => FromSwarm::NewListenAddr(e),
+            FromSwarm::ExpiredListenAddr(e) => FromSwarm::ExpiredListenAddr(e),
+            FromSwarm::ListenerError(e) => FromSwarm::ListenerError(e),
+            FromSwarm::ListenerClosed(e) => FromSwarm::ListenerClosed(e),
+            FromSwarm::NewExternalAddrCandidate(e) => FromSwarm::NewExternalAddrCandidate(e),
+        };
+
+        self.inner.on_swarm_event(event);
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        peer_id: PeerId,
+        connection_id: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {
+            Either::Left(event) => {
+                self.inner
+                    .on_connection_handler_event(peer_id, connection_id, event)
+            }
+            Either::Right(relay_server) => {
+                self.relay_server = relay_server;
+                self.update();
+            }
+        }
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+        params: &mut impl PollParameters,
+    ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        self.inner.poll(cx, params)
+    }
+}
+
+/// Advertisement and discovery of relays via Kademlia provider records.
+#[cfg(feature = "kad")]
+pub mod kad {
+    use libp2p_core::Multiaddr;
+    use libp2p_identity::PeerId;
+    use libp2p_kad::store::RecordStore;
+    use libp2p_kad::{GetProvidersOk, Kademlia, KademliaEvent, QueryId, QueryResult, RecordKey};
+
+    /// Returns the key relay servers provide.
+    pub fn key() -> RecordKey {
+        RecordKey::new(&super::NAMESPACE)
+    }
+
+    /// Kademlia republishes the provider record by itself, until it is withdrawn.
+    impl<TStore> super::Advertise for Kademlia<TStore>
+    where
+        TStore: RecordStore + Send + 'static,
+    {
+        fn advertise(&mut self) {
+            if let Err(e) = self.start_providing(key()) {
+                log::warn!("Failed to store relay provider record: {e}");
+            }
+        }
+
+        fn withdraw(&mut self) {
+            self.stop_providing(&key())
+        }
+    }
+
+    /// Starts a query for relay servers, reported through [`discovered_relays`].
+    pub fn discover<TStore>(kademlia: &mut Kademlia<TStore>) -> QueryId
+    where
+        TStore: RecordStore + Send + 'static,
+    {
+        kademlia.get_providers(key())
+    }
+
+    /// Returns the relay servers found in the given Kademlia event, together with the addresses
+    /// the providing peers reported for them.
+    ///
+    /// These can be added via `client::selection::Behaviour::add_candidate_peer`.
+    pub fn discovered_relays<'a, TStore>(
+        event: &'a KademliaEvent,
+        kademlia: &'a Kademlia<TStore>,
+    ) -> impl Iterator<Item = (PeerId, Vec<Multiaddr>)> + 'a
+    where
+        TStore: RecordStore + Send + 'static,
+    {
+        let (id, providers) = match event {
+            KademliaEvent::OutboundQueryProgressed {
+                id,
+                result:
+                    QueryResult::GetProviders(Ok(GetProvidersOk::FoundProviders { key: k, providers })),
+                ..
+            } if *k == key() => (Some(*id), Some(providers)),
+            _ => (None, None),
+        };
+        let query = id.and_then(|id| kademlia.query(&id));
+
+        providers.into_iter().flatten().map(move |peer| {
+            let addresses = query
+                .as_ref()
+                .map(|query| query.peer_addresses(peer).to_vec())
+                .unwrap_or_default();
+            (*peer, addresses)
+        })
+    }
+}
+
+/// Advertisement and discovery of relays via rendezvous registrations.
+#[cfg(feature = "rendezvous")]
+pub mod rendezvous {
+    use libp2p_core::{Endpoint, Multiaddr};
+    use libp2p_identity::PeerId;
+    use libp2p_rendezvous::client;
+    use libp2p_rendezvous::{Namespace, Ttl};
+    use libp2p_swarm::behaviour::FromSwarm;
+    use libp2p_swarm::{
+        ConnectionDenied, ConnectionId, NetworkBehaviour, PollParameters, THandler,
+        THandlerInEvent, THandlerOutEvent, ToSwarm,
+    };
+    use std::collections::HashSet;
+    use std::task::{Context, Poll};
+
+    /// Returns the namespace relay servers register in.
+    pub fn namespace() -> Namespace {
+        Namespace::from_static(super::NAMESPACE)
+    }
+
+    /// A rendezvous client, registering the local relay server with the given rendezvous nodes
+    /// when wrapped in the advertisement [`Behaviour`](super::Behaviour).
+    ///
+    /// The registrations are renewed by the client before their TTL expires, unless disabled
+    /// via [`client::Config::with_renewal`].
+    pub struct Registrar {
+        client: client::Behaviour,
+        rendezvous_nodes: HashSet<PeerId>,
+        ttl: Option<Ttl>,
+    }
+
+    impl Registrar {
+        pub fn new(client: client::Behaviour, ttl: Option<Ttl>) -> Self {
+            Self {
+                client,
+                rendezvous_nodes: Default::default(),
+                ttl,
+            }
+        }
+
+        /// Registers the local relay server with the given rendezvous node, while advertised.
+        pub fn add_rendezvous_node(&mut self, rendezvous_node: PeerId) {
+            self.rendezvous_nodes.insert(rendezvous_node);
+        }
+
+        pub fn client(&self) -> &client::Behaviour {
+            &self.client
+        }
+
+        pub fn client_mut(&mut self) -> &mut client::Behaviour {
+            &mut self.client
+        }
+    }
+
+    impl super::Advertise for Registrar {
+        fn advertise(&mut self) {
+            for rendezvous_node in &self.rendezvous_nodes {
+                if let Err(e) = self
+                    .client
+                    .register(namespace(), *rendezvous_node, self.ttl)
+                {
+                    log::warn!("Failed to register relay server with {rendezvous_node}: {e}");
+                }
+            }
+        }
+
+        fn withdraw(&mut self) {
+            for rendezvous_node in &self.rendezvous_nodes {
+                self.client.unregister(namespace(), *rendezvous_node);
+            }
+        }
+    }
+
+    impl NetworkBehaviour for Registrar {
+        type ConnectionHandler = THandler<client::Behaviour>;
+        type ToSwarm = client::Event;
+
+        fn handle_pending_inbound_connection(
+            &mut self,
+            connection_id: ConnectionId,
+            local_addr: &Multiaddr,
+            remote_addr: &Multiaddr,
+        ) -> Result<(), ConnectionDenied> {
+            self.client
+                .handle_pending_inbound_connection(connection_id, local_addr, remote_addr)
+        }
+
+        fn handle_established_inbound_connection(
+            &mut self,
+            connection_id: ConnectionId,
+            peer: PeerId,
+            local_addr: &Multiaddr,
+            remote_addr: &Multiaddr,
+        ) -> Result<THandler<Self>, ConnectionDenied> {
+            self.client.handle_established_inbound_connection(
+                connection_id,
+                peer,
+                local_addr,
+                remote_addr,
+            )
+        }
+
+        fn handle_pending_outbound_connection(
+            &mut self,
+            connection_id: ConnectionId,
+            maybe_peer: Option<PeerId>,
+            addresses: &[Multiaddr],
+            effective_role: Endpoint,
+        ) -> Result<Vec<Multiaddr>, ConnectionDenied> {
+            self.client.handle_pending_outbound_connection(
+                connection_id,
+                maybe_peer,
+                addresses,
+                effective_role,
+            )
+        }
+
+        fn handle_established_outbound_connection(
+            &mut self,
+            connection_id: ConnectionId,
+            peer: PeerId,
+            addr: &Multiaddr,
+            role_override: Endpoint,
+        ) -> Result<THandler<Self>, ConnectionDenied> {
+            self.client.handle_established_outbound_connection(
+                connection_id,
+                peer,
+                addr,
+                role_override,
+            )
+        }
+
+        fn on_swarm_event(&mut self, event: FromSwarm<Self::ConnectionHandler>) {
+            self.client.on_swarm_event(event)
+        }
+
+        fn on_connection_handler_event(
+            &mut self,
+            peer_id: PeerId,
+            connection_id: ConnectionId,
+            event: THandlerOutEvent<Self>,
+        ) {
+            self.client
+                .on_connection_handler_event(peer_id, connection_id, event)
+        }
+
+        fn poll(
+            &mut self,
+            cx: &mut Context<'_>,
+            params: &mut impl PollParameters,
+        ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+            self.client.poll(cx, params)
+        }
+    }
+
+    /// Asks the given rendezvous node for relay servers, reported through [`discovered_relays`].
+    pub fn discover(client: &mut client::Behaviour, rendezvous_node: PeerId) {
+        client.discover(Some(namespace()), None, None, rendezvous_node)
+    }
+
+    /// Returns the relay servers found in the given rendezvous client event, together with the
+    /// addresses of their registration.
+    ///
+    /// These can be added via `client::selection::Behaviour::add_candidate_peer`.
+    pub fn discovered_relays(
+        event: &client::Event,
+    ) -> impl Iterator<Item = (PeerId, Vec<Multiaddr>)> + '_ {
+        let registrations = match event {
+            client::Event::Discovered { registrations, .. } => Some(registrations),
+            _ => None,
+        };
+
+        registrations
+            .into_iter()
+            .flatten()
+            .filter(|registration| registration.namespace == namespace())
+            .map(|registration| {
+                (
+                    registration.record.peer_id(),
+                    registration.record.addresses().to_vec(),
+                )
+            })
+    }
+}
@@ -0,0 +1,123 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use crate::HOP_PROTOCOL_NAME;
+use either::Either;
+use libp2p_swarm::handler::{ConnectionEvent, ProtocolsChange};
+use libp2p_swarm::{ConnectionHandler, ConnectionHandlerEvent, KeepAlive, SubstreamProtocol};
+use std::task::{Context, Poll};
+
+/// Handler wrapping the handler of the advertising [`NetworkBehaviour`](libp2p_swarm::NetworkBehaviour),
+/// reporting whether the local node runs a relay server.
+///
+/// The relay server is detected through the locally supported protocols, which include
+/// [`HOP_PROTOCOL_NAME`] while a relay server [`Behaviour`](crate::Behaviour) is part of the
+/// swarm.
+pub struct Handler<H> {
+    inner: H,
+    /// Whether the relay server is supported, to be reported to the behaviour.
+    pending_report: Option<bool>,
+}
+
+impl<H> Handler<H> {
+    pub(crate) fn new(inner: H) -> Self {
+        Self {
+            inner,
+            pending_report: None,
+        }
+    }
+
+    pub(crate) fn into_inner(self) -> H {
+        self.inner
+    }
+}
+
+impl<H> ConnectionHandler for Handler<H>
+where
+    H: ConnectionHandler,
+{
+    type FromBehaviour = H::FromBehaviour;
+    type ToBehaviour = Either<H::ToBehaviour, bool>;
+    type Error = H::Error;
+    type InboundProtocol = H::InboundProtocol;
+    type OutboundProtocol = H::OutboundProtocol;
+    type InboundOpenInfo = H::InboundOpenInfo;
+    type OutboundOpenInfo = H::OutboundOpenInfo;
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
+        self.inner.listen_protocol()
+    }
+
+    fn on_behaviour_event(&mut self, event: Self::FromBehaviour) {
+        self.inner.on_behaviour_event(event)
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        self.inner.connection_keep_alive()
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<
+        ConnectionHandlerEvent<
+            Self::OutboundProtocol,
+            Self::OutboundOpenInfo,
+            Self::ToBehaviour,
+            Self::Error,
+        >,
+    > {
+        if let Some(supported) = self.pending_report.take() {
+            return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(Either::Right(
+                supported,
+            )));
+        }
+
+        self.inner
+            .poll(cx)
+            .map(|event| event.map_custom(Either::Left))
+    }
+
+    fn on_connection_event(
+        &mut self,
+        event: ConnectionEvent<
+            Self::InboundProtocol,
+            Self::OutboundProtocol,
+            Self::InboundOpenInfo,
+            Self::OutboundOpenInfo,
+        >,
+    ) {
+        match &event {
+            ConnectionEvent::LocalProtocolsChange(ProtocolsChange::Added(added))
+                if added.clone().any(|p| *p == HOP_PROTOCOL_NAME) =>
+            {
+                self.pending_report = Some(true);
+            }
+            ConnectionEvent::LocalProtocolsChange(ProtocolsChange::Removed(removed))
+                if removed.clone().any(|p| *p == HOP_PROTOCOL_NAME) =>
+            {
+                self.pending_report = Some(false);
+            }
+            _ => {}
+        }
+
+        self.inner.on_connection_event(event);
+    }
+}
@@ -23,6 +23,7 @@
 mod handler;
 pub(crate) mod rate_limiter;
 use crate::behaviour::handler::Handler;
+use crate::limit_warning::CircuitLimit;
 use crate::multiaddr_ext::MultiaddrExt;
 use crate::proto;
 use crate::protocol::{inbound_hop, outbound_stop};
@@ -39,10 +40,15 @@ use libp2p_swarm::{
 use std::collections::{hash_map, HashMap, HashSet, VecDeque};
 use std::num::NonZeroU32;
 use std::ops::Add;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::time::Duration;
 use void::Void;
 
+type ReservationAcl = Box<dyn Fn(&PeerId, &Multiaddr) -> bool + Send>;
+type CircuitAcl = Box<dyn Fn(&PeerId, &Multiaddr, &PeerId) -> bool + Send>;
+
 /// Configuration for the relay [`Behaviour`].
 ///
 /// # Panics
@@ -160,6 +166,32 @@ impl Default for Config {
     }
 }
 
+/// The reason an inbound reservation or circuit request has been denied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DenialReason {
+    /// A limit of the [`Config`] or of the destination of the circuit has been exceeded.
+    ResourceLimitExceeded,
+    /// The peer is not permitted to use the relay, or the destination refused the circuit.
+    ///
+    /// See [`Behaviour::set_reservation_acl`] and [`Behaviour::set_circuit_acl`].
+    PermissionDenied,
+    /// The destination of the circuit has no reservation with the relay.
+    NoReservation,
+    /// Connecting to the destination of the circuit failed.
+    ConnectionFailed,
+}
+
+impl From<DenialReason> for proto::Status {
+    fn from(reason: DenialReason) -> Self {
+        match reason {
+            DenialReason::ResourceLimitExceeded => proto::Status::RESOURCE_LIMIT_EXCEEDED,
+            DenialReason::PermissionDenied => proto::Status::PERMISSION_DENIED,
+            DenialReason::NoReservation => proto::Status::NO_RESERVATION,
+            DenialReason::ConnectionFailed => proto::Status::CONNECTION_FAILED,
+        }
+    }
+}
+
 /// The events produced by the relay `Behaviour`.
 #[derive(Debug)]
 pub enum Event {
@@ -175,7 +207,10 @@ pub enum Event {
         error: inbound_hop::UpgradeError,
     },
     /// An inbound reservation request has been denied.
-    ReservationReqDenied { src_peer_id: PeerId },
+    ReservationReqDenied {
+        src_peer_id: PeerId,
+        reason: DenialReason,
+    },
     /// Denying an inbound reservation request has failed.
     ReservationReqDenyFailed {
         src_peer_id: PeerId,
@@ -183,10 +218,13 @@ pub enum Event {
     },
     /// An inbound reservation has timed out.
     ReservationTimedOut { src_peer_id: PeerId },
+    /// An inbound reservation ended as the connection it was made on closed.
+    ReservationClosed { src_peer_id: PeerId },
     /// An inbound circuit request has been denied.
     CircuitReqDenied {
         src_peer_id: PeerId,
         dst_peer_id: PeerId,
+        reason: DenialReason,
     },
     /// Denying an inbound circuit request failed.
     CircuitReqDenyFailed {
@@ -211,21 +249,36 @@ pub enum Event {
         dst_peer_id: PeerId,
         error: inbound_hop::UpgradeError,
     },
+    /// An inbound circuit is approaching its [`Config::max_circuit_duration`] or
+    /// [`Config::max_circuit_bytes`], after which it will be closed.
+    CircuitLimitApproaching {
+        src_peer_id: PeerId,
+        dst_peer_id: PeerId,
+        limit: CircuitLimit,
+    },
     /// An inbound circuit has closed.
     CircuitClosed {
         src_peer_id: PeerId,
         dst_peer_id: PeerId,
+        /// The number of bytes relayed in both directions.
+        bytes_relayed: u64,
         error: Option<std::io::Error>,
     },
 }
 
 /// [`NetworkBehaviour`] implementation of the relay server
 /// functionality of the circuit relay v2 protocol.
+///
+/// Beyond the limits of the [`Config`], the peers allowed to use the relay can be restricted with
+/// [`Behaviour::set_reservation_acl`] and [`Behaviour::set_circuit_acl`].
 pub struct Behaviour {
     config: Config,
 
     local_peer_id: PeerId,
 
+    reservation_acl: Option<ReservationAcl>,
+    circuit_acl: Option<CircuitAcl>,
+
     reservations: HashMap<PeerId, HashSet<ConnectionId>>,
     circuits: CircuitsTracker,
 
@@ -240,6 +293,8 @@ impl Behaviour {
         Self {
             config,
             local_peer_id,
+            reservation_acl: None,
+            circuit_acl: None,
             reservations: Default::default(),
             circuits: Default::default(),
             queued_actions: Default::default(),
@@ -247,6 +302,30 @@ impl Behaviour {
         }
     }
 
+    /// Sets a predicate deciding whether a peer may make a reservation.
+    ///
+    /// The predicate is called with the ID and the remote address of the peer for every
+    /// reservation request, including renewals. Denied requests are answered with
+    /// `PERMISSION_DENIED` and not counted towards any rate limit.
+    pub fn set_reservation_acl<F>(&mut self, acl: F)
+    where
+        F: Fn(&PeerId, &Multiaddr) -> bool + Send + 'static,
+    {
+        self.reservation_acl = Some(Box::new(acl));
+    }
+
+    /// Sets a predicate deciding whether a peer may open a circuit to a destination peer.
+    ///
+    /// The predicate is called with the ID and the remote address of the source peer and the ID
+    /// of the destination peer for every circuit request. Denied requests are answered with
+    /// `PERMISSION_DENIED` and not counted towards any rate limit.
+    pub fn set_circuit_acl<F>(&mut self, acl: F)
+    where
+        F: Fn(&PeerId, &Multiaddr, &PeerId) -> bool + Send + 'static,
+    {
+        self.circuit_acl = Some(Box::new(acl));
+    }
+
     fn on_connection_closed(
         &mut self,
         ConnectionClosed {
@@ -256,7 +335,14 @@ impl Behaviour {
         }: ConnectionClosed<<Self as NetworkBehaviour>::ConnectionHandler>,
     ) {
         if let hash_map::Entry::Occupied(mut peer) = self.reservations.entry(peer_id) {
-            peer.get_mut().remove(&connection_id);
+            if peer.get_mut().remove(&connection_id) {
+                self.queued_actions.push_back(
+                    ToSwarm::GenerateEvent(Event::ReservationClosed {
+                        src_peer_id: peer_id,
+                    })
+                    .into(),
+                );
+            }
             if peer.get().is_empty() {
                 peer.remove();
             }
@@ -273,6 +359,7 @@ impl Behaviour {
                 ToSwarm::GenerateEvent(Event::CircuitClosed {
                     src_peer_id: circuit.src_peer_id,
                     dst_peer_id: circuit.dst_peer_id,
+                    bytes_relayed: circuit.bytes_relayed.load(Ordering::Relaxed),
                     error: Some(std::io::ErrorKind::ConnectionAborted.into()),
                 })
                 .into(),
@@ -382,7 +469,21 @@ impl NetworkBehaviour for Behaviour {
                      denies all inbound substreams."
                 );
 
-                let action = if
+                let permitted = self.reservation_acl.as_ref().map_or(true, |acl| {
+                    acl(&event_source, endpoint.get_remote_address())
+                });
+
+                let action = if !permitted {
+                    ToSwarm::NotifyHandler {
+                        handler: NotifyHandler::One(connection),
+                        peer_id: event_source,
+                        event: Either::Left(handler::In::DenyReservationReq {
+                            inbound_reservation_req,
+                            reason: DenialReason::PermissionDenied,
+                        }),
+                    }
+                    .into()
+                } else if
                 // Deny if it is a new reservation and exceeds `max_reservations_per_peer`.
                 (!renewed
                     && self
@@ -405,13 +506,14 @@ impl NetworkBehaviour for Behaviour {
                         .iter_mut()
                         .all(|limiter| {
                             limiter.try_next(event_source, endpoint.get_remote_address(), now)
-                        }) {
+                        })
+                {
                     ToSwarm::NotifyHandler {
                         handler: NotifyHandler::One(connection),
                         peer_id: event_source,
                         event: Either::Left(handler::In::DenyReservationReq {
                             inbound_reservation_req,
-                            status: proto::Status::RESOURCE_LIMIT_EXCEEDED,
+                            reason: DenialReason::ResourceLimitExceeded,
                         }),
                     }
                     .into()
@@ -456,10 +558,11 @@ impl NetworkBehaviour for Behaviour {
                     .into(),
                 );
             }
-            handler::Event::ReservationReqDenied {} => {
+            handler::Event::ReservationReqDenied { reason } => {
                 self.queued_actions.push_back(
                     ToSwarm::GenerateEvent(Event::ReservationReqDenied {
                         src_peer_id: event_source,
+                        reason,
                     })
                     .into(),
                 );
@@ -509,7 +612,25 @@ impl NetworkBehaviour for Behaviour {
                      denies all inbound substreams."
                 );
 
-                let action = if self.circuits.num_circuits_of_peer(event_source)
+                let permitted = self.circuit_acl.as_ref().map_or(true, |acl| {
+                    acl(
+                        &event_source,
+                        endpoint.get_remote_address(),
+                        &inbound_circuit_req.dst(),
+                    )
+                });
+
+                let action = if !permitted {
+                    ToSwarm::NotifyHandler {
+                        handler: NotifyHandler::One(connection),
+                        peer_id: event_source,
+                        event: Either::Left(handler::In::DenyCircuitReq {
+                            circuit_id: None,
+                            inbound_circuit_req,
+                            reason: DenialReason::PermissionDenied,
+                        }),
+                    }
+                } else if self.circuits.num_circuits_of_peer(event_source)
                     > self.config.max_circuits_per_peer
                     || self.circuits.len() >= self.config.max_circuits
                     || !self
@@ -518,7 +639,8 @@ impl NetworkBehaviour for Behaviour {
                         .iter_mut()
                         .all(|limiter| {
                             limiter.try_next(event_source, endpoint.get_remote_address(), now)
-                        }) {
+                        })
+                {
                     // Deny circuit exceeding limits.
                     ToSwarm::NotifyHandler {
                         handler: NotifyHandler::One(connection),
@@ -526,7 +648,7 @@ impl NetworkBehaviour for Behaviour {
                         event: Either::Left(handler::In::DenyCircuitReq {
                             circuit_id: None,
                             inbound_circuit_req,
-                            status: proto::Status::RESOURCE_LIMIT_EXCEEDED,
+                            reason: DenialReason::ResourceLimitExceeded,
                         }),
                     }
                 } else if let Some(dst_conn) = self
@@ -541,6 +663,7 @@ impl NetworkBehaviour for Behaviour {
                         src_connection_id: connection,
                         dst_peer_id: inbound_circuit_req.dst(),
                         dst_connection_id: *dst_conn,
+                        bytes_relayed: Default::default(),
                     });
 
                     ToSwarm::NotifyHandler {
@@ -561,7 +684,7 @@ impl NetworkBehaviour for Behaviour {
                         event: Either::Left(handler::In::DenyCircuitReq {
                             circuit_id: None,
                             inbound_circuit_req,
-                            status: proto::Status::NO_RESERVATION,
+                            reason: DenialReason::NoReservation,
                         }),
                     }
                 };
@@ -570,6 +693,7 @@ impl NetworkBehaviour for Behaviour {
             handler::Event::CircuitReqDenied {
                 circuit_id,
                 dst_peer_id,
+                reason,
             } => {
                 if let Some(circuit_id) = circuit_id {
                     self.circuits.remove(circuit_id);
@@ -579,6 +703,7 @@ impl NetworkBehaviour for Behaviour {
                     ToSwarm::GenerateEvent(Event::CircuitReqDenied {
                         src_peer_id: event_source,
                         dst_peer_id,
+                        reason,
                     })
                     .into(),
                 );
@@ -621,6 +746,7 @@ impl NetworkBehaviour for Behaviour {
                             dst_handler_notifier,
                             dst_stream,
                             dst_pending_data,
+                            bytes_relayed: self.circuits.bytes_relayed(circuit_id),
                         }),
                     }
                     .into(),
@@ -631,7 +757,7 @@ impl NetworkBehaviour for Behaviour {
                 src_peer_id,
                 src_connection_id,
                 inbound_circuit_req,
-                status,
+                reason,
                 error,
             } => {
                 self.queued_actions.push_back(
@@ -641,7 +767,7 @@ impl NetworkBehaviour for Behaviour {
                         event: Either::Left(handler::In::DenyCircuitReq {
                             circuit_id: Some(circuit_id),
                             inbound_circuit_req,
-                            status,
+                            reason,
                         }),
                     }
                     .into(),
@@ -683,17 +809,35 @@ impl NetworkBehaviour for Behaviour {
                     .into(),
                 );
             }
+            handler::Event::CircuitLimitApproaching {
+                circuit_id: _,
+                dst_peer_id,
+                limit,
+            } => {
+                self.queued_actions.push_back(
+                    ToSwarm::GenerateEvent(Event::CircuitLimitApproaching {
+                        src_peer_id: event_source,
+                        dst_peer_id,
+                        limit,
+                    })
+                    .into(),
+                );
+            }
             handler::Event::CircuitClosed {
                 dst_peer_id,
                 circuit_id,
                 error,
             } => {
-                self.circuits.remove(circuit_id);
+                // Already reported if closed due to the connection to the destination closing.
+                let Some(circuit) = self.circuits.remove(circuit_id) else {
+                    return;
+                };
 
                 self.queued_actions.push_back(
                     ToSwarm::GenerateEvent(Event::CircuitClosed {
                         src_peer_id: event_source,
                         dst_peer_id,
+                        bytes_relayed: circuit.bytes_relayed.load(Ordering::Relaxed),
                         error,
                     })
                     .into(),
@@ -741,6 +885,14 @@ impl CircuitsTracker {
         };
     }
 
+    /// Returns the counter of the bytes relayed over the given circuit.
+    fn bytes_relayed(&self, circuit_id: CircuitId) -> Arc<AtomicU64> {
+        self.circuits
+            .get(&circuit_id)
+            .map(|c| c.bytes_relayed.clone())
+            .unwrap_or_default()
+    }
+
     fn remove(&mut self, circuit_id: CircuitId) -> Option<Circuit> {
         self.circuits.remove(&circuit_id)
     }
@@ -786,6 +938,7 @@ struct Circuit {
     dst_peer_id: PeerId,
     dst_connection_id: ConnectionId,
     status: CircuitStatus,
+    bytes_relayed: Arc<AtomicU64>,
 }
 
 #[derive(Clone)]
@@ -18,9 +18,9 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use crate::behaviour::CircuitId;
+use crate::behaviour::{CircuitId, DenialReason};
 use crate::copy_future::CopyFuture;
-use crate::proto;
+use crate::limit_warning::{self, CircuitLimit};
 use crate::protocol::{inbound_hop, outbound_stop};
 use bytes::Bytes;
 use either::Either;
@@ -42,6 +42,8 @@ use libp2p_swarm::{
 };
 use std::collections::VecDeque;
 use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::time::Duration;
 
@@ -59,12 +61,12 @@ pub enum In {
     },
     DenyReservationReq {
         inbound_reservation_req: inbound_hop::ReservationReq,
-        status: proto::Status,
+        reason: DenialReason,
     },
     DenyCircuitReq {
         circuit_id: Option<CircuitId>,
         inbound_circuit_req: inbound_hop::CircuitReq,
-        status: proto::Status,
+        reason: DenialReason,
     },
     NegotiateOutboundConnect {
         circuit_id: CircuitId,
@@ -79,6 +81,8 @@ pub enum In {
         dst_handler_notifier: oneshot::Sender<()>,
         dst_stream: Stream,
         dst_pending_data: Bytes,
+        /// Counter of the bytes relayed over the circuit.
+        bytes_relayed: Arc<AtomicU64>,
     },
 }
 
@@ -94,19 +98,19 @@ impl fmt::Debug for In {
                 .finish(),
             In::DenyReservationReq {
                 inbound_reservation_req: _,
-                status,
+                reason,
             } => f
                 .debug_struct("In::DenyReservationReq")
-                .field("status", status)
+                .field("reason", reason)
                 .finish(),
             In::DenyCircuitReq {
                 circuit_id,
                 inbound_circuit_req: _,
-                status,
+                reason,
             } => f
                 .debug_struct("In::DenyCircuitReq")
                 .field("circuit_id", circuit_id)
-                .field("status", status)
+                .field("reason", reason)
                 .finish(),
             In::NegotiateOutboundConnect {
                 circuit_id,
@@ -126,6 +130,7 @@ impl fmt::Debug for In {
                 dst_handler_notifier: _,
                 dst_stream: _,
                 dst_pending_data: _,
+                bytes_relayed: _,
             } => f
                 .debug_struct("In::AcceptAndDriveCircuit")
                 .field("circuit_id", circuit_id)
@@ -153,7 +158,7 @@ pub enum Event {
     /// Accepting an inbound reservation request failed.
     ReservationReqAcceptFailed { error: inbound_hop::UpgradeError },
     /// An inbound reservation request has been denied.
-    ReservationReqDenied {},
+    ReservationReqDenied { reason: DenialReason },
     /// Denying an inbound reservation request has failed.
     ReservationReqDenyFailed { error: inbound_hop::UpgradeError },
     /// An inbound reservation has timed out.
@@ -167,6 +172,7 @@ pub enum Event {
     CircuitReqDenied {
         circuit_id: Option<CircuitId>,
         dst_peer_id: PeerId,
+        reason: DenialReason,
     },
     /// Denying an inbound circuit request failed.
     CircuitReqDenyFailed {
@@ -202,9 +208,15 @@ pub enum Event {
         src_peer_id: PeerId,
         src_connection_id: ConnectionId,
         inbound_circuit_req: inbound_hop::CircuitReq,
-        status: proto::Status,
+        reason: DenialReason,
         error: StreamUpgradeError<outbound_stop::CircuitFailedReason>,
     },
+    /// An inbound circuit is approaching one of its limits.
+    CircuitLimitApproaching {
+        circuit_id: CircuitId,
+        dst_peer_id: PeerId,
+        limit: CircuitLimit,
+    },
     /// An inbound circuit has closed.
     CircuitClosed {
         circuit_id: CircuitId,
@@ -233,9 +245,10 @@ impl fmt::Debug for Event {
                 .debug_struct("Event::ReservationReqAcceptFailed")
                 .field("error", error)
                 .finish(),
-            Event::ReservationReqDenied {} => {
-                f.debug_struct("Event::ReservationReqDenied").finish()
-            }
+            Event::ReservationReqDenied { reason } => f
+                .debug_struct("Event::ReservationReqDenied")
+                .field("reason", reason)
+                .finish(),
             Event::ReservationReqDenyFailed { error } => f
                 .debug_struct("Event::ReservationReqDenyFailed")
                 .field("error", error)
@@ -251,10 +264,12 @@ impl fmt::Debug for Event {
             Event::CircuitReqDenied {
                 circuit_id,
                 dst_peer_id,
+                reason,
             } => f
                 .debug_struct("Event::CircuitReqDenied")
                 .field("circuit_id", circuit_id)
                 .field("dst_peer_id", dst_peer_id)
+                .field("reason", reason)
                 .finish(),
             Event::CircuitReqDenyFailed {
                 circuit_id,
@@ -303,16 +318,26 @@ impl fmt::Debug for Event {
                 src_peer_id,
                 src_connection_id,
                 inbound_circuit_req: _,
-                status,
+                reason,
                 error,
             } => f
                 .debug_struct("Event::OutboundConnectNegotiationFailed")
                 .field("circuit_id", circuit_id)
                 .field("src_peer_id", src_peer_id)
                 .field("src_connection_id", src_connection_id)
-                .field("status", status)
+                .field("reason", reason)
                 .field("error", error)
                 .finish(),
+            Event::CircuitLimitApproaching {
+                circuit_id,
+                dst_peer_id,
+                limit,
+            } => f
+                .debug_struct("Event::CircuitLimitApproaching")
+                .field("circuit_id", circuit_id)
+                .field("dst_peer_id", dst_peer_id)
+                .field("limit", limit)
+                .finish(),
             Event::CircuitClosed {
                 circuit_id,
                 dst_peer_id,
@@ -367,7 +392,7 @@ pub struct Handler {
     circuit_deny_futures: Futures<(
         Option<CircuitId>,
         PeerId,
-        Result<(), inbound_hop::UpgradeError>,
+        Result<DenialReason, inbound_hop::UpgradeError>,
     )>,
     /// Tracks substreams lend out to other [`Handler`]s.
     ///
@@ -380,6 +405,8 @@ pub struct Handler {
     alive_lend_out_substreams: FuturesUnordered<oneshot::Receiver<()>>,
     /// Futures relaying data for circuit between two peers.
     circuits: Futures<(CircuitId, PeerId, Result<(), std::io::Error>)>,
+    /// Futures resolving once a circuit approaches one of its limits or closes.
+    circuit_limit_warnings: Futures<(CircuitId, PeerId, Option<CircuitLimit>)>,
 }
 
 impl Handler {
@@ -394,6 +421,7 @@ impl Handler {
             circuit_deny_futures: Default::default(),
             alive_lend_out_substreams: Default::default(),
             circuits: Default::default(),
+            circuit_limit_warnings: Default::default(),
             active_reservation: Default::default(),
             keep_alive: KeepAlive::Yes,
         }
@@ -487,11 +515,10 @@ impl Handler {
             <Self as ConnectionHandler>::OutboundProtocol,
         >,
     ) {
-        let (non_fatal_error, status) = match error {
-            StreamUpgradeError::Timeout => (
-                StreamUpgradeError::Timeout,
-                proto::Status::CONNECTION_FAILED,
-            ),
+        let (non_fatal_error, reason) = match error {
+            StreamUpgradeError::Timeout => {
+                (StreamUpgradeError::Timeout, DenialReason::ConnectionFailed)
+            }
             StreamUpgradeError::NegotiationFailed => {
                 // The remote has previously done a reservation. Doing a reservation but not
                 // supporting the stop protocol is pointless, thus disconnecting.
@@ -508,15 +535,15 @@ impl Handler {
                     return;
                 }
                 outbound_stop::UpgradeError::CircuitFailed(error) => {
-                    let status = match error {
+                    let reason = match error {
                         outbound_stop::CircuitFailedReason::ResourceLimitExceeded => {
-                            proto::Status::RESOURCE_LIMIT_EXCEEDED
+                            DenialReason::ResourceLimitExceeded
                         }
                         outbound_stop::CircuitFailedReason::PermissionDenied => {
-                            proto::Status::PERMISSION_DENIED
+                            DenialReason::PermissionDenied
                         }
                     };
-                    (StreamUpgradeError::Apply(error), status)
+                    (StreamUpgradeError::Apply(error), reason)
                 }
             },
         };
@@ -535,7 +562,7 @@ impl Handler {
                     src_peer_id,
                     src_connection_id,
                     inbound_circuit_req,
-                    status,
+                    reason,
                     error: non_fatal_error,
                 },
             ));
@@ -544,7 +571,7 @@ impl Handler {
 
 enum ReservationRequestFuture {
     Accepting(BoxFuture<'static, Result<(), inbound_hop::UpgradeError>>),
-    Denying(BoxFuture<'static, Result<(), inbound_hop::UpgradeError>>),
+    Denying(BoxFuture<'static, Result<DenialReason, inbound_hop::UpgradeError>>),
 }
 
 type Futures<T> = FuturesUnordered<BoxFuture<'static, T>>;
@@ -589,12 +616,15 @@ impl ConnectionHandler for Handler {
             }
             In::DenyReservationReq {
                 inbound_reservation_req,
-                status,
+                reason,
             } => {
                 if self
                     .reservation_request_future
                     .replace(ReservationRequestFuture::Denying(
-                        inbound_reservation_req.deny(status).boxed(),
+                        inbound_reservation_req
+                            .deny(reason.into())
+                            .map_ok(move |()| reason)
+                            .boxed(),
                     ))
                     .is_some()
                 {
@@ -627,12 +657,13 @@ impl ConnectionHandler for Handler {
             In::DenyCircuitReq {
                 circuit_id,
                 inbound_circuit_req,
-                status,
+                reason,
             } => {
                 let dst_peer_id = inbound_circuit_req.dst();
                 self.circuit_deny_futures.push(
                     inbound_circuit_req
-                        .deny(status)
+                        .deny(reason.into())
+                        .map_ok(move |()| reason)
                         .map(move |result| (circuit_id, dst_peer_id, result))
                         .boxed(),
                 );
@@ -644,10 +675,14 @@ impl ConnectionHandler for Handler {
                 dst_handler_notifier,
                 dst_stream,
                 dst_pending_data,
+                bytes_relayed,
             } => {
                 self.circuit_accept_futures.push(
                     inbound_circuit_req
-                        .accept()
+                        .accept(
+                            self.config.max_circuit_duration,
+                            self.config.max_circuit_bytes,
+                        )
                         .map_ok(move |(src_stream, src_pending_data)| CircuitParts {
                             circuit_id,
                             src_stream,
@@ -656,6 +691,7 @@ impl ConnectionHandler for Handler {
                             dst_handler_notifier,
                             dst_stream,
                             dst_pending_data,
+                            bytes_relayed,
                         })
                         .map_err(move |e| (circuit_id, dst_peer_id, e))
                         .boxed(),
@@ -721,11 +757,12 @@ impl ConnectionHandler for Handler {
             self.circuit_deny_futures.poll_next_unpin(cx)
         {
             match result {
-                Ok(()) => {
+                Ok(reason) => {
                     return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(
                         Event::CircuitReqDenied {
                             circuit_id,
                             dst_peer_id,
+                            reason,
                         },
                     ));
                 }
@@ -753,9 +790,12 @@ impl ConnectionHandler for Handler {
                         dst_handler_notifier,
                         mut dst_stream,
                         dst_pending_data,
+                        bytes_relayed,
                     } = parts;
                     let max_circuit_duration = self.config.max_circuit_duration;
                     let max_circuit_bytes = self.config.max_circuit_bytes;
+                    let (mut data_counter, limit_warning) =
+                        limit_warning::new(Some(max_circuit_duration), Some(max_circuit_bytes));
 
                     let circuit = async move {
                         let (result_1, result_2) = futures::future::join(
@@ -765,12 +805,18 @@ impl ConnectionHandler for Handler {
                         .await;
                         result_1?;
                         result_2?;
+                        let pending_data_len =
+                            (src_pending_data.len() + dst_pending_data.len()) as u64;
+                        bytes_relayed.fetch_add(pending_data_len, Ordering::Relaxed);
+                        data_counter.record(pending_data_len);
 
                         CopyFuture::new(
                             src_stream,
                             dst_stream,
                             max_circuit_duration,
                             max_circuit_bytes,
+                            bytes_relayed,
+                            data_counter,
                         )
                         .await?;
 
@@ -782,6 +828,11 @@ impl ConnectionHandler for Handler {
                     .boxed();
 
                     self.circuits.push(circuit);
+                    self.circuit_limit_warnings.push(
+                        limit_warning
+                            .map(move |limit| (circuit_id, dst_peer_id, limit))
+                            .boxed(),
+                    );
 
                     return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(
                         Event::CircuitReqAccepted {
@@ -843,9 +894,9 @@ impl ConnectionHandler for Handler {
                     self.reservation_request_future = None;
 
                     match result {
-                        Ok(()) => {
+                        Ok(reason) => {
                             return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(
-                                Event::ReservationReqDenied {},
+                                Event::ReservationReqDenied { reason },
                             ))
                         }
                         Err(error) => {
@@ -859,6 +910,21 @@ impl ConnectionHandler for Handler {
             None => {}
         }
 
+        // Report circuits approaching their limits.
+        while let Poll::Ready(Some((circuit_id, dst_peer_id, limit))) =
+            self.circuit_limit_warnings.poll_next_unpin(cx)
+        {
+            if let Some(limit) = limit {
+                return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(
+                    Event::CircuitLimitApproaching {
+                        circuit_id,
+                        dst_peer_id,
+                        limit,
+                    },
+                ));
+            }
+        }
+
         // Check lend out substreams.
         while let Poll::Ready(Some(Err(Canceled))) =
             self.alive_lend_out_substreams.poll_next_unpin(cx)
@@ -930,4 +996,5 @@ pub(crate) struct CircuitParts {
     dst_handler_notifier: oneshot::Sender<()>,
     dst_stream: Stream,
     dst_pending_data: Bytes,
+    bytes_relayed: Arc<AtomicU64>,
 }
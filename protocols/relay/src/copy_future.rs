@@ -24,6 +24,7 @@
 //!
 //! Inspired by [`futures::io::Copy`].
 
+use crate::limit_warning::DataCounter;
 use futures::future::Future;
 use futures::future::FutureExt;
 use futures::io::{AsyncBufRead, BufReader};
@@ -33,6 +34,8 @@ use futures_timer::Delay;
 use std::convert::TryInto;
 use std::io;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::time::Duration;
 
@@ -43,6 +46,9 @@ pub(crate) struct CopyFuture<S, D> {
     max_circuit_duration: Delay,
     max_circuit_bytes: u64,
     bytes_sent: u64,
+    /// Counter of the bytes sent, shared with the relay [`Behaviour`](crate::Behaviour).
+    bytes_relayed: Arc<AtomicU64>,
+    data_counter: DataCounter,
 }
 
 impl<S: AsyncRead, D: AsyncRead> CopyFuture<S, D> {
@@ -51,6 +57,8 @@ impl<S: AsyncRead, D: AsyncRead> CopyFuture<S, D> {
         dst: D,
         max_circuit_duration: Duration,
         max_circuit_bytes: u64,
+        bytes_relayed: Arc<AtomicU64>,
+        data_counter: DataCounter,
     ) -> Self {
         CopyFuture {
             src: BufReader::new(src),
@@ -58,6 +66,8 @@ impl<S: AsyncRead, D: AsyncRead> CopyFuture<S, D> {
             max_circuit_duration: Delay::new(max_circuit_duration),
             max_circuit_bytes,
             bytes_sent: Default::default(),
+            bytes_relayed,
+            data_counter,
         }
     }
 }
@@ -91,6 +101,8 @@ where
                 Poll::Ready(Ok(0)) => Status::Done,
                 Poll::Ready(Ok(i)) => {
                     this.bytes_sent += i;
+                    this.bytes_relayed.fetch_add(i, Ordering::Relaxed);
+                    this.data_counter.record(i);
                     Status::Progressed
                 }
                 Poll::Pending => Status::Pending,
@@ -101,6 +113,8 @@ where
                 Poll::Ready(Ok(0)) => Status::Done,
                 Poll::Ready(Ok(i)) => {
                     this.bytes_sent += i;
+                    this.bytes_relayed.fetch_add(i, Ordering::Relaxed);
+                    this.data_counter.record(i);
                     Status::Progressed
                 }
                 Poll::Pending => Status::Pending,
@@ -163,6 +177,7 @@ fn forward_data<S: AsyncBufRead + Unpin, D: AsyncWrite + Unpin>(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::limit_warning;
     use futures::executor::block_on;
     use futures::io::{AsyncRead, AsyncWrite, BufReader, BufWriter};
     use quickcheck::QuickCheck;
@@ -226,15 +241,22 @@ mod tests {
                 write: Vec::new(),
             };
 
+            let bytes_relayed = Arc::new(AtomicU64::new(0));
             let mut copy_future = CopyFuture::new(
                 connection_a,
                 connection_b,
                 Duration::from_secs(60),
                 max_circuit_bytes,
+                bytes_relayed.clone(),
+                limit_warning::new(None, None).0,
             );
 
             match block_on(&mut copy_future) {
                 Ok(()) => {
+                    assert_eq!(
+                        bytes_relayed.load(Ordering::Relaxed),
+                        (a.len() + b.len()) as u64
+                    );
                     assert_eq!(copy_future.src.into_inner().write, b);
                     assert_eq!(copy_future.dst.into_inner().write, a);
                 }
@@ -292,6 +314,8 @@ mod tests {
             PendingConnection {},
             Duration::from_millis(1),
             u64::MAX,
+            Default::default(),
+            limit_warning::new(None, None).0,
         );
 
         std::thread::sleep(Duration::from_millis(2));
@@ -23,8 +23,10 @@
 
 #![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
 
+pub mod advertisement;
 mod behaviour;
 mod copy_future;
+mod limit_warning;
 mod multiaddr_ext;
 mod priv_client;
 mod protocol;
@@ -39,7 +41,8 @@ mod proto {
     };
 }
 
-pub use behaviour::{rate_limiter::RateLimiter, Behaviour, CircuitId, Config, Event};
+pub use behaviour::{rate_limiter::RateLimiter, Behaviour, CircuitId, Config, DenialReason, Event};
+pub use limit_warning::CircuitLimit;
 pub use protocol::{HOP_PROTOCOL_NAME, STOP_PROTOCOL_NAME};
 
 /// Types related to the relay protocol inbound.
@@ -55,7 +58,9 @@ pub mod inbound {
 /// Types related to the relay protocol outbound.
 pub mod outbound {
     pub mod hop {
-        pub use crate::protocol::outbound_hop::FatalUpgradeError;
+        pub use crate::protocol::outbound_hop::{
+            CircuitFailedReason, FatalUpgradeError, ReservationFailedReason,
+        };
     }
     pub mod stop {
         pub use crate::protocol::outbound_stop::FatalUpgradeError;
@@ -69,6 +74,11 @@ pub mod client {
     pub mod transport {
         pub use crate::priv_client::transport::Error;
     }
+
+    #[cfg(feature = "selection")]
+    pub mod selection {
+        pub use crate::priv_client::selection::{Behaviour, Config, Event};
+    }
 }
 
 // Check that we can safely cast a `usize` to a `u64`.
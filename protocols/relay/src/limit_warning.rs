@@ -0,0 +1,98 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Warnings ahead of a circuit being closed for reaching its duration or data limit.
+
+use futures::channel::oneshot;
+use futures::future::{self, BoxFuture, Either, FutureExt};
+use futures_timer::Delay;
+use std::time::Duration;
+
+/// Fraction of a circuit's duration or data limit after which the circuit is reported to
+/// approach its limit.
+const WARNING_FRACTION: f64 = 0.9;
+
+/// A limit of a relayed circuit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CircuitLimit {
+    /// The maximum duration of the circuit.
+    Duration,
+    /// The maximum number of bytes relayed over the circuit.
+    Data,
+}
+
+/// Resolves with the limit a circuit approaches first, or with `None` if the circuit is closed
+/// before.
+pub(crate) type LimitWarning = BoxFuture<'static, Option<CircuitLimit>>;
+
+/// Counts the bytes relayed over a circuit, triggering the [`LimitWarning`] once its data limit
+/// is approached.
+///
+/// Dropping the counter, i.e. closing the circuit, resolves the [`LimitWarning`] with `None`.
+pub(crate) struct DataCounter {
+    /// The number of bytes left until the warning threshold.
+    remaining: Option<u64>,
+    notifier: Option<oneshot::Sender<()>>,
+}
+
+impl DataCounter {
+    pub(crate) fn record(&mut self, bytes: u64) {
+        let Some(remaining) = self.remaining.as_mut() else {
+            return;
+        };
+
+        *remaining = remaining.saturating_sub(bytes);
+        if *remaining == 0 {
+            self.remaining = None;
+            if let Some(notifier) = self.notifier.take() {
+                let _ = notifier.send(());
+            }
+        }
+    }
+}
+
+/// Creates a [`LimitWarning`] for a circuit with the given limits.
+pub(crate) fn new(
+    max_duration: Option<Duration>,
+    max_data: Option<u64>,
+) -> (DataCounter, LimitWarning) {
+    let (notifier, data_limit_approached) = oneshot::channel();
+    let counter = DataCounter {
+        remaining: max_data.map(|max| (max as f64 * WARNING_FRACTION) as u64),
+        notifier: Some(notifier),
+    };
+
+    let data_limit_approached =
+        data_limit_approached.map(|result| result.ok().map(|()| CircuitLimit::Data));
+    let warning = match max_duration {
+        Some(max_duration) => future::select(
+            Delay::new(max_duration.mul_f64(WARNING_FRACTION)),
+            data_limit_approached,
+        )
+        .map(|either| match either {
+            Either::Left(((), _)) => Some(CircuitLimit::Duration),
+            Either::Right((limit, _)) => limit,
+        })
+        .boxed(),
+        None => data_limit_approached.boxed(),
+    };
+
+    (counter, warning)
+}
@@ -21,8 +21,12 @@
 //! [`NetworkBehaviour`] to act as a circuit relay v2 **client**.
 
 mod handler;
+#[cfg(feature = "selection")]
+pub(crate) mod selection;
 pub(crate) mod transport;
 
+use crate::behaviour::rate_limiter;
+use crate::limit_warning::{CircuitLimit, DataCounter};
 use crate::multiaddr_ext::MultiaddrExt;
 use crate::priv_client::handler::Handler;
 use crate::protocol::{self, inbound_stop, outbound_hop};
@@ -34,6 +38,7 @@ use futures::future::{BoxFuture, FutureExt};
 use futures::io::{AsyncRead, AsyncWrite};
 use futures::ready;
 use futures::stream::StreamExt;
+use instant::Instant;
 use libp2p_core::{Endpoint, Multiaddr};
 use libp2p_identity::PeerId;
 use libp2p_swarm::behaviour::{ConnectionClosed, ConnectionEstablished, FromSwarm};
@@ -43,10 +48,12 @@ use libp2p_swarm::{
     NotifyHandler, PollParameters, Stream, StreamUpgradeError, THandler, THandlerInEvent,
     THandlerOutEvent, ToSwarm,
 };
-use std::collections::{hash_map, HashMap, VecDeque};
+use std::collections::{hash_map, HashMap, HashSet, VecDeque};
 use std::io::{Error, ErrorKind, IoSlice};
+use std::num::NonZeroU32;
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::Duration;
 use transport::Transport;
 use void::Void;
 
@@ -66,6 +73,19 @@ pub enum Event {
         renewal: bool,
         error: StreamUpgradeError<outbound_hop::ReservationFailedReason>,
     },
+    /// A reservation has been renewed ahead of its expiry or re-established after it was lost.
+    ReservationRenewed {
+        relay_peer_id: PeerId,
+        limit: Option<protocol::Limit>,
+    },
+    /// A reservation expired as all attempts to renew it failed.
+    ///
+    /// The relayed listen addresses are expired. Renewal continues to be retried with exponential
+    /// backoff, emitting [`Event::ReservationRenewed`] once it succeeds.
+    ReservationLost {
+        relay_peer_id: PeerId,
+        error: StreamUpgradeError<outbound_hop::ReservationFailedReason>,
+    },
     OutboundCircuitEstablished {
         relay_peer_id: PeerId,
         limit: Option<protocol::Limit>,
@@ -86,6 +106,16 @@ pub enum Event {
         src_peer_id: PeerId,
         error: inbound_stop::UpgradeError,
     },
+    /// A circuit is approaching the limit announced by the relay, after which the relay will
+    /// close it.
+    ///
+    /// The application may migrate to a direct connection or a new circuit ahead of that.
+    CircuitLimitApproaching {
+        relay_peer_id: PeerId,
+        /// The peer at the other end of the circuit.
+        remote_peer_id: PeerId,
+        limit: CircuitLimit,
+    },
 }
 
 /// [`NetworkBehaviour`] implementation of the relay client
@@ -102,6 +132,11 @@ pub struct Behaviour {
     queued_actions: VecDeque<ToSwarm<Event, Either<handler::In, Void>>>,
 
     pending_handler_commands: HashMap<ConnectionId, handler::In>,
+
+    /// Rate limiters applied to inbound circuits, i.e. circuits relayed to the local node.
+    circuit_src_rate_limiters: Vec<Box<dyn rate_limiter::RateLimiter>>,
+    /// Peers whose inbound circuits are exempt from the rate limits.
+    trusted_peers: HashSet<PeerId>,
 }
 
 /// Create a new client relay [`Behaviour`] with it's corresponding [`Transport`].
@@ -113,11 +148,37 @@ pub fn new(local_peer_id: PeerId) -> (Transport, Behaviour) {
         directly_connected_peers: Default::default(),
         queued_actions: Default::default(),
         pending_handler_commands: Default::default(),
+        circuit_src_rate_limiters: Default::default(),
+        trusted_peers: Default::default(),
     };
     (transport, behaviour)
 }
 
 impl Behaviour {
+    /// Limits the inbound circuits from each source peer to `limit` per `interval`, across all
+    /// relays.
+    ///
+    /// Inbound circuits exceeding the limit are denied, protecting the local node from being
+    /// flooded through a public relay. Peers added via [`Behaviour::add_trusted_peer`] are exempt.
+    pub fn circuit_src_per_peer(&mut self, limit: NonZeroU32, interval: Duration) {
+        self.circuit_src_rate_limiters
+            .push(rate_limiter::new_per_peer(
+                rate_limiter::GenericRateLimiterConfig { limit, interval },
+            ));
+    }
+
+    /// Exempts inbound circuits from the given peer from the rate limits.
+    pub fn add_trusted_peer(&mut self, peer_id: PeerId) {
+        self.trusted_peers.insert(peer_id);
+    }
+
+    /// Subjects inbound circuits from the given peer to the rate limits again.
+    ///
+    /// Returns whether the peer was trusted.
+    pub fn remove_trusted_peer(&mut self, peer_id: &PeerId) -> bool {
+        self.trusted_peers.remove(peer_id)
+    }
+
     fn on_connection_closed(
         &mut self,
         ConnectionClosed {
@@ -238,7 +299,7 @@ impl NetworkBehaviour for Behaviour {
     fn on_connection_handler_event(
         &mut self,
         event_source: PeerId,
-        _connection: ConnectionId,
+        connection: ConnectionId,
         handler_event: THandlerOutEvent<Self>,
     ) {
         let handler_event = match handler_event {
@@ -261,6 +322,14 @@ impl NetworkBehaviour for Behaviour {
                     error,
                 }
             }
+            handler::Event::ReservationRenewed { limit } => Event::ReservationRenewed {
+                relay_peer_id: event_source,
+                limit,
+            },
+            handler::Event::ReservationLost { error } => Event::ReservationLost {
+                relay_peer_id: event_source,
+                error,
+            },
             handler::Event::OutboundCircuitEstablished { limit } => {
                 Event::OutboundCircuitEstablished {
                     relay_peer_id: event_source,
@@ -271,6 +340,26 @@ impl NetworkBehaviour for Behaviour {
                 relay_peer_id: event_source,
                 error,
             },
+            handler::Event::InboundCircuitReqReceived { src_peer_id } => {
+                let now = Instant::now();
+                let accept = self.trusted_peers.contains(&src_peer_id)
+                    || self
+                        .circuit_src_rate_limiters
+                        .iter_mut()
+                        .all(|limiter| limiter.try_next(src_peer_id, &Multiaddr::empty(), now));
+
+                let command = if accept {
+                    handler::In::AcceptInboundCircuit { src_peer_id }
+                } else {
+                    handler::In::DenyInboundCircuit { src_peer_id }
+                };
+                self.queued_actions.push_back(ToSwarm::NotifyHandler {
+                    peer_id: event_source,
+                    handler: NotifyHandler::One(connection),
+                    event: Either::Left(command),
+                });
+                return;
+            }
             handler::Event::InboundCircuitEstablished { src_peer_id, limit } => {
                 Event::InboundCircuitEstablished { src_peer_id, limit }
             }
@@ -280,6 +369,14 @@ impl NetworkBehaviour for Behaviour {
             handler::Event::InboundCircuitReqDenyFailed { src_peer_id, error } => {
                 Event::InboundCircuitReqDenyFailed { src_peer_id, error }
             }
+            handler::Event::CircuitLimitApproaching {
+                remote_peer_id,
+                limit,
+            } => Event::CircuitLimitApproaching {
+                relay_peer_id: event_source,
+                remote_peer_id,
+                limit,
+            },
         };
 
         self.queued_actions.push_back(ToSwarm::GenerateEvent(event))
@@ -394,6 +491,8 @@ enum ConnectionState {
         /// However, it is actual use is to trigger the `Canceled` error in the `Transport` when this `Sender` is dropped.
         #[allow(dead_code)]
         drop_notifier: oneshot::Sender<void::Void>,
+        /// Counts the bytes relayed, warning ahead of the circuit's data limit.
+        data_counter: DataCounter,
     },
 }
 
@@ -403,6 +502,7 @@ impl ConnectionState {
     pub(crate) fn new_inbound(
         circuit: inbound_stop::Circuit,
         drop_notifier: oneshot::Sender<void::Void>,
+        data_counter: DataCounter,
     ) -> Self {
         ConnectionState::InboundAccepting {
             accept: async {
@@ -414,6 +514,7 @@ impl ConnectionState {
                     read_buffer,
                     substream,
                     drop_notifier,
+                    data_counter,
                 })
             }
             .boxed(),
@@ -424,11 +525,13 @@ impl ConnectionState {
         substream: Stream,
         read_buffer: Bytes,
         drop_notifier: oneshot::Sender<void::Void>,
+        data_counter: DataCounter,
     ) -> Self {
         ConnectionState::Operational {
             substream,
             read_buffer,
             drop_notifier,
+            data_counter,
         }
     }
 }
@@ -446,8 +549,14 @@ impl AsyncWrite for Connection {
                         state: ready!(accept.poll_unpin(cx))?,
                     };
                 }
-                ConnectionState::Operational { substream, .. } => {
-                    return Pin::new(substream).poll_write(cx, buf);
+                ConnectionState::Operational {
+                    substream,
+                    data_counter,
+                    ..
+                } => {
+                    let n = ready!(Pin::new(substream).poll_write(cx, buf))?;
+                    data_counter.record(n as u64);
+                    return Poll::Ready(Ok(n));
                 }
             }
         }
@@ -493,8 +602,14 @@ impl AsyncWrite for Connection {
                         state: ready!(accept.poll_unpin(cx))?,
                     };
                 }
-                ConnectionState::Operational { substream, .. } => {
-                    return Pin::new(substream).poll_write_vectored(cx, bufs);
+                ConnectionState::Operational {
+                    substream,
+                    data_counter,
+                    ..
+                } => {
+                    let n = ready!(Pin::new(substream).poll_write_vectored(cx, bufs))?;
+                    data_counter.record(n as u64);
+                    return Poll::Ready(Ok(n));
                 }
             }
         }
@@ -517,16 +632,20 @@ impl AsyncRead for Connection {
                 ConnectionState::Operational {
                     read_buffer,
                     substream,
+                    data_counter,
                     ..
                 } => {
                     if !read_buffer.is_empty() {
                         let n = std::cmp::min(read_buffer.len(), buf.len());
                         let data = read_buffer.split_to(n);
                         buf[0..n].copy_from_slice(&data[..]);
+                        data_counter.record(n as u64);
                         return Poll::Ready(Ok(n));
                     }
 
-                    return Pin::new(substream).poll_read(cx, buf);
+                    let n = ready!(Pin::new(substream).poll_read(cx, buf))?;
+                    data_counter.record(n as u64);
+                    return Poll::Ready(Ok(n));
                 }
             }
         }
@@ -18,6 +18,7 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
+use crate::limit_warning::{self, CircuitLimit, DataCounter};
 use crate::priv_client::transport;
 use crate::proto;
 use crate::protocol::{self, inbound_stop, outbound_hop};
@@ -39,8 +40,10 @@ use libp2p_swarm::{
     ConnectionHandler, ConnectionHandlerEvent, KeepAlive, StreamUpgradeError, SubstreamProtocol,
 };
 use log::debug;
+use rand::Rng;
 use std::collections::{HashMap, VecDeque};
 use std::fmt;
+use std::ops::Range;
 use std::task::{Context, Poll};
 use std::time::Duration;
 
@@ -49,6 +52,22 @@ use std::time::Duration;
 /// Circuits to be denied exceeding the limit are dropped.
 const MAX_NUMBER_DENYING_CIRCUIT: usize = 8;
 
+/// The maximum number of inbound circuit requests awaiting a decision by the behaviour.
+///
+/// Requests exceeding the limit are denied right away.
+const MAX_PENDING_INBOUND_CIRCUITS: usize = 16;
+
+/// Fraction of the reservation's lifetime after which it is renewed.
+///
+/// The exact point is chosen at random so that clients of the same relay don't renew in lockstep.
+const RENEWAL_FRACTION: Range<f64> = 0.5..0.75;
+
+/// Delay before retrying a failed renewal, doubled with every further failure.
+const INITIAL_RENEWAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Upper bound of the delay between two renewal attempts.
+const MAX_RENEWAL_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
 pub enum In {
     Reserve {
         to_listener: mpsc::Sender<transport::ToListenerMsg>,
@@ -57,6 +76,10 @@ pub enum In {
         dst_peer_id: PeerId,
         send_back: oneshot::Sender<Result<super::Connection, ()>>,
     },
+    /// Accept the pending inbound circuit request from the given peer.
+    AcceptInboundCircuit { src_peer_id: PeerId },
+    /// Deny the pending inbound circuit request from the given peer.
+    DenyInboundCircuit { src_peer_id: PeerId },
 }
 
 impl fmt::Debug for In {
@@ -70,6 +93,14 @@ impl fmt::Debug for In {
                 .debug_struct("In::EstablishCircuit")
                 .field("dst_peer_id", dst_peer_id)
                 .finish(),
+            In::AcceptInboundCircuit { src_peer_id } => f
+                .debug_struct("In::AcceptInboundCircuit")
+                .field("src_peer_id", src_peer_id)
+                .finish(),
+            In::DenyInboundCircuit { src_peer_id } => f
+                .debug_struct("In::DenyInboundCircuit")
+                .field("src_peer_id", src_peer_id)
+                .finish(),
         }
     }
 }
@@ -86,11 +117,24 @@ pub enum Event {
         renewal: bool,
         error: StreamUpgradeError<outbound_hop::ReservationFailedReason>,
     },
+    /// The reservation has been renewed ahead of its expiry or re-established after it was lost.
+    ReservationRenewed { limit: Option<protocol::Limit> },
+    /// The reservation expired as all attempts to renew it failed.
+    ///
+    /// Renewal continues to be retried with exponential backoff.
+    ReservationLost {
+        error: StreamUpgradeError<outbound_hop::ReservationFailedReason>,
+    },
     /// An outbound circuit has been established.
     OutboundCircuitEstablished { limit: Option<protocol::Limit> },
     OutboundCircuitReqFailed {
         error: StreamUpgradeError<outbound_hop::CircuitFailedReason>,
     },
+    /// An inbound circuit request has been received while holding a reservation.
+    ///
+    /// The request is pending until the behaviour either accepts or denies it via
+    /// [`In::AcceptInboundCircuit`] or [`In::DenyInboundCircuit`].
+    InboundCircuitReqReceived { src_peer_id: PeerId },
     /// An inbound circuit has been established.
     InboundCircuitEstablished {
         src_peer_id: PeerId,
@@ -103,6 +147,11 @@ pub enum Event {
         src_peer_id: PeerId,
         error: inbound_stop::UpgradeError,
     },
+    /// A circuit is approaching the limit announced by the relay.
+    CircuitLimitApproaching {
+        remote_peer_id: PeerId,
+        limit: CircuitLimit,
+    },
 }
 
 pub struct Handler {
@@ -140,6 +189,10 @@ pub struct Handler {
     /// eventually.
     alive_lend_out_substreams: FuturesUnordered<oneshot::Receiver<void::Void>>,
 
+    /// Inbound circuit requests awaiting a decision by the behaviour, in the order they were
+    /// reported.
+    pending_inbound_circuits: HashMap<PeerId, VecDeque<inbound_stop::Circuit>>,
+
     circuit_deny_futs:
         HashMap<PeerId, BoxFuture<'static, Result<(), protocol::inbound_stop::UpgradeError>>>,
 
@@ -148,6 +201,9 @@ pub struct Handler {
     /// We may drop errors if this handler ends up in a terminal state (by returning
     /// [`ConnectionHandlerEvent::Close`]).
     send_error_futs: FuturesUnordered<BoxFuture<'static, ()>>,
+
+    /// Futures resolving once a circuit approaches its limit or closes.
+    circuit_limit_warnings: FuturesUnordered<BoxFuture<'static, (PeerId, Option<CircuitLimit>)>>,
 }
 
 impl Handler {
@@ -160,8 +216,10 @@ impl Handler {
             pending_error: Default::default(),
             reservation: Reservation::None,
             alive_lend_out_substreams: Default::default(),
+            pending_inbound_circuits: Default::default(),
             circuit_deny_futs: Default::default(),
             send_error_futs: Default::default(),
+            circuit_limit_warnings: Default::default(),
             keep_alive: KeepAlive::Yes,
         }
     }
@@ -176,53 +234,112 @@ impl Handler {
             <Self as ConnectionHandler>::InboundOpenInfo,
         >,
     ) {
-        match &mut self.reservation {
-            Reservation::Accepted { pending_msgs, .. }
-            | Reservation::Renewing { pending_msgs, .. } => {
-                let src_peer_id = inbound_circuit.src_peer_id();
-                let limit = inbound_circuit.limit();
-
-                let (tx, rx) = oneshot::channel();
-                self.alive_lend_out_substreams.push(rx);
-                let connection = super::ConnectionState::new_inbound(inbound_circuit, tx);
+        let src_peer_id = inbound_circuit.src_peer_id();
 
-                pending_msgs.push_back(transport::ToListenerMsg::IncomingRelayedConnection {
-                    // stream: connection,
-                    stream: super::Connection { state: connection },
+        match self.reservation {
+            Reservation::Accepted { .. } | Reservation::Renewing { .. }
+                if self.num_pending_inbound_circuits() >= MAX_PENDING_INBOUND_CIRCUITS =>
+            {
+                log::debug!(
+                    "Denying inbound circuit request from {:?} due to exceeding limit of pending requests.",
                     src_peer_id,
-                    relay_peer_id: self.remote_peer_id,
-                    relay_addr: self.remote_addr.clone(),
-                });
+                );
+                self.deny_inbound_circuit(inbound_circuit, proto::Status::RESOURCE_LIMIT_EXCEEDED)
+            }
+            Reservation::Accepted { .. } | Reservation::Renewing { .. } => {
+                self.pending_inbound_circuits
+                    .entry(src_peer_id)
+                    .or_default()
+                    .push_back(inbound_circuit);
 
                 self.queued_events
                     .push_back(ConnectionHandlerEvent::NotifyBehaviour(
-                        Event::InboundCircuitEstablished { src_peer_id, limit },
+                        Event::InboundCircuitReqReceived { src_peer_id },
                     ));
             }
             Reservation::None => {
-                let src_peer_id = inbound_circuit.src_peer_id();
-
-                if self.circuit_deny_futs.len() == MAX_NUMBER_DENYING_CIRCUIT
-                    && !self.circuit_deny_futs.contains_key(&src_peer_id)
-                {
-                    log::warn!(
-                        "Dropping inbound circuit request to be denied from {:?} due to exceeding limit.",
-                        src_peer_id,
-                    );
-                } else if self
-                    .circuit_deny_futs
-                    .insert(
-                        src_peer_id,
-                        inbound_circuit.deny(proto::Status::NO_RESERVATION).boxed(),
-                    )
-                    .is_some()
-                {
-                    log::warn!(
-                            "Dropping existing inbound circuit request to be denied from {:?} in favor of new one.",
-                            src_peer_id
-                        )
-                }
+                self.deny_inbound_circuit(inbound_circuit, proto::Status::NO_RESERVATION)
+            }
+        }
+    }
+
+    /// Returns the number of inbound circuit requests awaiting a decision, across all peers.
+    fn num_pending_inbound_circuits(&self) -> usize {
+        self.pending_inbound_circuits
+            .values()
+            .map(VecDeque::len)
+            .sum()
+    }
+
+    /// Takes the oldest pending inbound circuit request from the given peer.
+    fn take_pending_inbound_circuit(
+        &mut self,
+        src_peer_id: PeerId,
+    ) -> Option<inbound_stop::Circuit> {
+        let pending = self.pending_inbound_circuits.get_mut(&src_peer_id)?;
+        let inbound_circuit = pending.pop_front();
+        if pending.is_empty() {
+            self.pending_inbound_circuits.remove(&src_peer_id);
+        }
+        inbound_circuit
+    }
+
+    fn accept_inbound_circuit(&mut self, inbound_circuit: inbound_stop::Circuit) {
+        let pending_msgs = match &mut self.reservation {
+            Reservation::Accepted { pending_msgs, .. }
+            | Reservation::Renewing { pending_msgs, .. } => pending_msgs,
+            Reservation::None => {
+                // The reservation has been lost while the request was pending.
+                self.deny_inbound_circuit(inbound_circuit, proto::Status::NO_RESERVATION);
+                return;
             }
+        };
+
+        let src_peer_id = inbound_circuit.src_peer_id();
+        let limit = inbound_circuit.limit();
+
+        let (tx, rx) = oneshot::channel();
+        self.alive_lend_out_substreams.push(rx);
+        let data_counter = watch_limit(&mut self.circuit_limit_warnings, src_peer_id, limit);
+        let connection = super::ConnectionState::new_inbound(inbound_circuit, tx, data_counter);
+
+        pending_msgs.push_back(transport::ToListenerMsg::IncomingRelayedConnection {
+            // stream: connection,
+            stream: super::Connection { state: connection },
+            src_peer_id,
+            relay_peer_id: self.remote_peer_id,
+            relay_addr: self.remote_addr.clone(),
+        });
+
+        self.queued_events
+            .push_back(ConnectionHandlerEvent::NotifyBehaviour(
+                Event::InboundCircuitEstablished { src_peer_id, limit },
+            ));
+    }
+
+    fn deny_inbound_circuit(
+        &mut self,
+        inbound_circuit: inbound_stop::Circuit,
+        status: proto::Status,
+    ) {
+        let src_peer_id = inbound_circuit.src_peer_id();
+
+        if self.circuit_deny_futs.len() == MAX_NUMBER_DENYING_CIRCUIT
+            && !self.circuit_deny_futs.contains_key(&src_peer_id)
+        {
+            log::warn!(
+                "Dropping inbound circuit request to be denied from {:?} due to exceeding limit.",
+                src_peer_id,
+            );
+        } else if self
+            .circuit_deny_futs
+            .insert(src_peer_id, inbound_circuit.deny(status).boxed())
+            .is_some()
+        {
+            log::warn!(
+                "Dropping existing inbound circuit request to be denied from {:?} in favor of new one.",
+                src_peer_id
+            )
         }
     }
 
@@ -240,14 +357,14 @@ impl Handler {
             // Outbound reservation
             (
                 outbound_hop::Output::Reservation {
-                    renewal_timeout,
+                    expires_in,
                     addrs,
                     limit,
                 },
                 OutboundOpenInfo::Reserve { to_listener },
             ) => {
                 let event = self.reservation.accepted(
-                    renewal_timeout,
+                    expires_in,
                     addrs,
                     to_listener,
                     self.local_peer_id,
@@ -258,6 +375,29 @@ impl Handler {
                     .push_back(ConnectionHandlerEvent::NotifyBehaviour(event));
             }
 
+            // Automatic renewal of an existing reservation
+            (
+                outbound_hop::Output::Reservation {
+                    expires_in,
+                    addrs,
+                    limit,
+                },
+                OutboundOpenInfo::Renew { to_listener },
+            ) => {
+                self.reservation.accepted(
+                    expires_in,
+                    addrs,
+                    to_listener,
+                    self.local_peer_id,
+                    limit,
+                );
+
+                self.queued_events
+                    .push_back(ConnectionHandlerEvent::NotifyBehaviour(
+                        Event::ReservationRenewed { limit },
+                    ));
+            }
+
             // Outbound circuit
             (
                 outbound_hop::Output::Circuit {
@@ -265,11 +405,21 @@ impl Handler {
                     read_buffer,
                     limit,
                 },
-                OutboundOpenInfo::Connect { send_back },
+                OutboundOpenInfo::Connect {
+                    dst_peer_id,
+                    send_back,
+                },
             ) => {
                 let (tx, rx) = oneshot::channel();
+                let data_counter =
+                    watch_limit(&mut self.circuit_limit_warnings, dst_peer_id, limit);
                 match send_back.send(Ok(super::Connection {
-                    state: super::ConnectionState::new_outbound(substream, read_buffer, tx),
+                    state: super::ConnectionState::new_outbound(
+                        substream,
+                        read_buffer,
+                        tx,
+                        data_counter,
+                    ),
                 })) {
                     Ok(()) => {
                         self.alive_lend_out_substreams.push(rx);
@@ -314,7 +464,7 @@ impl Handler {
         >,
     ) {
         match open_info {
-            OutboundOpenInfo::Reserve { mut to_listener } => {
+            OutboundOpenInfo::Reserve { .. } | OutboundOpenInfo::Renew { .. } => {
                 let non_fatal_error = match error {
                     StreamUpgradeError::Timeout => StreamUpgradeError::Timeout,
                     StreamUpgradeError::NegotiationFailed => StreamUpgradeError::NegotiationFailed,
@@ -337,6 +487,21 @@ impl Handler {
                     },
                 };
 
+                let mut to_listener = match open_info {
+                    OutboundOpenInfo::Reserve { to_listener } => to_listener,
+                    OutboundOpenInfo::Renew { to_listener } => {
+                        if let Some(event) = self
+                            .reservation
+                            .renewal_failed(to_listener, non_fatal_error)
+                        {
+                            self.queued_events
+                                .push_back(ConnectionHandlerEvent::NotifyBehaviour(event));
+                        }
+                        return;
+                    }
+                    OutboundOpenInfo::Connect { .. } => unreachable!("Matched above."),
+                };
+
                 if self.pending_error.is_none() {
                     self.send_error_futs.push(
                         async move {
@@ -360,7 +525,7 @@ impl Handler {
                         },
                     ));
             }
-            OutboundOpenInfo::Connect { send_back } => {
+            OutboundOpenInfo::Connect { send_back, .. } => {
                 let non_fatal_error = match error {
                     StreamUpgradeError::Timeout => StreamUpgradeError::Timeout,
                     StreamUpgradeError::NegotiationFailed => StreamUpgradeError::NegotiationFailed,
@@ -430,10 +595,26 @@ impl ConnectionHandler for Handler {
                     .push_back(ConnectionHandlerEvent::OutboundSubstreamRequest {
                         protocol: SubstreamProtocol::new(
                             outbound_hop::Upgrade::Connect { dst_peer_id },
-                            OutboundOpenInfo::Connect { send_back },
+                            OutboundOpenInfo::Connect {
+                                dst_peer_id,
+                                send_back,
+                            },
                         ),
                     });
             }
+            In::AcceptInboundCircuit { src_peer_id } => {
+                if let Some(inbound_circuit) = self.take_pending_inbound_circuit(src_peer_id) {
+                    self.accept_inbound_circuit(inbound_circuit);
+                }
+            }
+            In::DenyInboundCircuit { src_peer_id } => {
+                if let Some(inbound_circuit) = self.take_pending_inbound_circuit(src_peer_id) {
+                    self.deny_inbound_circuit(
+                        inbound_circuit,
+                        proto::Status::RESOURCE_LIMIT_EXCEEDED,
+                    );
+                }
+            }
         }
     }
 
@@ -492,6 +673,20 @@ impl ConnectionHandler for Handler {
             return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(event));
         }
 
+        // Report circuits approaching their limits.
+        while let Poll::Ready(Some((remote_peer_id, limit))) =
+            self.circuit_limit_warnings.poll_next_unpin(cx)
+        {
+            if let Some(limit) = limit {
+                return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(
+                    Event::CircuitLimitApproaching {
+                        remote_peer_id,
+                        limit,
+                    },
+                ));
+            }
+        }
+
         // Send errors to transport.
         while let Poll::Ready(Some(())) = self.send_error_futs.poll_next_unpin(cx) {}
 
@@ -508,6 +703,7 @@ impl ConnectionHandler for Handler {
         if matches!(self.reservation, Reservation::None)
             && self.alive_lend_out_substreams.is_empty()
             && self.circuit_deny_futs.is_empty()
+            && self.pending_inbound_circuits.is_empty()
         {
             match self.keep_alive {
                 KeepAlive::Yes => {
@@ -556,12 +752,21 @@ enum Reservation {
     /// The Reservation is accepted by the relay.
     Accepted {
         renewal_timeout: Delay,
+        /// When the reservation expires at the relay unless renewed.
+        expires_at: Instant,
+        /// Delay before retrying should the next renewal fail.
+        backoff: Duration,
+        /// Whether the reservation expired before it could be renewed.
+        lost: bool,
         /// Buffer of messages to be send to the transport listener.
         pending_msgs: VecDeque<transport::ToListenerMsg>,
         to_listener: mpsc::Sender<transport::ToListenerMsg>,
     },
     /// The reservation is being renewed with the relay.
     Renewing {
+        expires_at: Instant,
+        backoff: Duration,
+        lost: bool,
         /// Buffer of messages to be send to the transport listener.
         pending_msgs: VecDeque<transport::ToListenerMsg>,
     },
@@ -571,7 +776,7 @@ enum Reservation {
 impl Reservation {
     fn accepted(
         &mut self,
-        renewal_timeout: Delay,
+        expires_in: Duration,
         addrs: Vec<Multiaddr>,
         to_listener: mpsc::Sender<transport::ToListenerMsg>,
         local_peer_id: PeerId,
@@ -595,8 +800,13 @@ impl Reservation {
             },
         )));
 
+        let renewal_in = expires_in.mul_f64(rand::thread_rng().gen_range(RENEWAL_FRACTION));
+
         *self = Reservation::Accepted {
-            renewal_timeout,
+            renewal_timeout: Delay::new(renewal_in),
+            expires_at: Instant::now() + expires_in,
+            backoff: INITIAL_RENEWAL_BACKOFF,
+            lost: false,
             pending_msgs,
             to_listener,
         };
@@ -618,6 +828,60 @@ impl Reservation {
         renewal
     }
 
+    /// Schedules another attempt after an automatic renewal failed.
+    ///
+    /// Returns [`Event::ReservationLost`] once the reservation expired without being renewed.
+    fn renewal_failed(
+        &mut self,
+        to_listener: mpsc::Sender<transport::ToListenerMsg>,
+        error: StreamUpgradeError<outbound_hop::ReservationFailedReason>,
+    ) -> Option<Event> {
+        let (expires_at, backoff, lost, mut pending_msgs) =
+            match std::mem::replace(self, Reservation::None) {
+                Reservation::Renewing {
+                    expires_at,
+                    backoff,
+                    lost,
+                    pending_msgs,
+                } => (expires_at, backoff, lost, pending_msgs),
+                r => {
+                    // The reservation has since been replaced through a new listen call.
+                    debug!(
+                        "Ignoring failed renewal of replaced reservation: {:?}",
+                        error
+                    );
+                    *self = r;
+                    return None;
+                }
+            };
+
+        let now = Instant::now();
+        let newly_lost = !lost && now >= expires_at;
+        if newly_lost {
+            pending_msgs.push_back(transport::ToListenerMsg::ReservationLost);
+        } else {
+            debug!("Failed to renew reservation: {:?}", error);
+        }
+
+        // While the reservation is still valid, make the last attempt right before it expires.
+        let retry_in = if lost || newly_lost {
+            backoff
+        } else {
+            backoff.min(expires_at - now)
+        };
+
+        *self = Reservation::Accepted {
+            renewal_timeout: Delay::new(retry_in),
+            expires_at,
+            backoff: (backoff * 2).min(MAX_RENEWAL_BACKOFF),
+            lost: lost || newly_lost,
+            pending_msgs,
+            to_listener,
+        };
+
+        newly_lost.then_some(Event::ReservationLost { error })
+    }
+
     fn forward_messages_to_transport_listener(&mut self, cx: &mut Context<'_>) {
         if let Reservation::Accepted {
             pending_msgs,
@@ -655,19 +919,30 @@ impl Reservation {
         let (next_reservation, poll_val) = match std::mem::replace(self, Reservation::None) {
             Reservation::Accepted {
                 mut renewal_timeout,
+                expires_at,
+                backoff,
+                lost,
                 pending_msgs,
                 to_listener,
             } => match renewal_timeout.poll_unpin(cx) {
                 Poll::Ready(()) => (
-                    Reservation::Renewing { pending_msgs },
+                    Reservation::Renewing {
+                        expires_at,
+                        backoff,
+                        lost,
+                        pending_msgs,
+                    },
                     Poll::Ready(Some(SubstreamProtocol::new(
                         outbound_hop::Upgrade::Reserve,
-                        OutboundOpenInfo::Reserve { to_listener },
+                        OutboundOpenInfo::Renew { to_listener },
                     ))),
                 ),
                 Poll::Pending => (
                     Reservation::Accepted {
                         renewal_timeout,
+                        expires_at,
+                        backoff,
+                        lost,
                         pending_msgs,
                         to_listener,
                     },
@@ -682,11 +957,41 @@ impl Reservation {
     }
 }
 
+/// Watches a new circuit to the given peer for approaching the given limit.
+///
+/// Returns the [`DataCounter`] of the circuit's connection.
+fn watch_limit(
+    circuit_limit_warnings: &mut FuturesUnordered<
+        BoxFuture<'static, (PeerId, Option<CircuitLimit>)>,
+    >,
+    remote_peer_id: PeerId,
+    limit: Option<protocol::Limit>,
+) -> DataCounter {
+    let (max_duration, max_data) = limit
+        .map(|limit| (limit.duration(), limit.data_in_bytes()))
+        .unwrap_or_default();
+    let (data_counter, limit_warning) = limit_warning::new(max_duration, max_data);
+    if max_duration.is_some() || max_data.is_some() {
+        circuit_limit_warnings.push(
+            limit_warning
+                .map(move |limit| (remote_peer_id, limit))
+                .boxed(),
+        );
+    }
+
+    data_counter
+}
+
 pub enum OutboundOpenInfo {
     Reserve {
         to_listener: mpsc::Sender<transport::ToListenerMsg>,
     },
+    /// Renewal of the current reservation, triggered by the handler itself.
+    Renew {
+        to_listener: mpsc::Sender<transport::ToListenerMsg>,
+    },
     Connect {
+        dst_peer_id: PeerId,
         send_back: oneshot::Sender<Result<super::Connection, ()>>,
     },
 }
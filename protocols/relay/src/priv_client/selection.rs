@@ -0,0 +1,744 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Selection of the relays to make reservations with, based on their round-trip time.
+//!
+//! Reservations are held with several distinct relays at once, so that the local node stays
+//! reachable through the others when one of them disappears.
+
+mod handler;
+
+pub use handler::Handler;
+
+use libp2p_core::multiaddr::Protocol;
+use libp2p_core::transport::ListenerId;
+use libp2p_core::{Endpoint, Multiaddr};
+use libp2p_identity::PeerId;
+use libp2p_ping as ping;
+use libp2p_swarm::behaviour::{
+    ConnectionClosed, DialFailure, ExpiredListenAddr, FromSwarm, ListenerClosed, NewListenAddr,
+};
+use libp2p_swarm::dial_opts::{DialOpts, PeerCondition};
+use libp2p_swarm::{
+    ConnectionDenied, ConnectionId, ListenOpts, NetworkBehaviour, NotifyHandler, PollParameters,
+    THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
+};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// Configuration for the relay selection [`Behaviour`].
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The number of distinct relays to hold reservations with at the same time.
+    ///
+    /// Degraded relays, i.e. relays whose reservation was lost, do not count towards this
+    /// number, thus a replacement is selected while they recover.
+    pub max_relays: usize,
+    /// Configuration of the pings measuring the round-trip time to the candidates.
+    pub ping: ping::Config,
+    /// The fraction of a selected relay's average round-trip time that an unselected candidate
+    /// needs to undercut for the relay to be replaced by the candidate.
+    ///
+    /// The relay is only deselected once the reservation with the candidate has been accepted.
+    /// Set to `0.0` to keep selected relays as long as their reservation holds.
+    pub reselect_threshold: f64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_relays: 2,
+            ping: ping::Config::new(),
+            reselect_threshold: 0.5,
+        }
+    }
+}
+
+/// The events produced by the relay selection [`Behaviour`].
+#[derive(Debug)]
+pub enum Event {
+    /// A reservation with a selected relay has been accepted.
+    RelaySelected {
+        relay_peer_id: PeerId,
+        /// The average round-trip time to the relay at the time it was selected.
+        rtt: Duration,
+    },
+    /// The reservation with a previously selected relay closed, e.g. because the connection to
+    /// the relay closed or because a candidate with a lower round-trip time replaced the relay.
+    RelayDeselected { relay_peer_id: PeerId },
+    /// The reservation with a selected relay was lost, expiring the relayed addresses through it.
+    ///
+    /// The reservation continues to be renewed in the background. Meanwhile, another candidate is
+    /// selected in its place, if available.
+    RelayDegraded { relay_peer_id: PeerId },
+    /// The reservation with a degraded relay has been re-established.
+    ///
+    /// If a replacement has been selected in the meantime, the relay is deselected right after.
+    RelayRecovered { relay_peer_id: PeerId },
+    /// A candidate could not be reached or refused our reservation.
+    ///
+    /// The candidate is not selected again unless it is added anew via
+    /// [`Behaviour::add_candidate`].
+    CandidateFailed { relay_peer_id: PeerId },
+}
+
+/// [`NetworkBehaviour`] selecting the relays to make reservations with.
+///
+/// Candidate relays, e.g. discovered via Kademlia or rendezvous, are added through
+/// [`Behaviour::add_candidate`] or [`Behaviour::add_candidate_peer`]. Each candidate is
+/// connected to, reusing an existing connection if any, and pinged for as long as it remains a
+/// candidate. Whenever fewer than [`Config::max_relays`] relays are selected, the candidates with
+/// the lowest average round-trip time are selected by listening on a relayed address through
+/// them. Candidates whose connection closed are dialed again when needed. Selected relays are
+/// kept as long as their reservation holds, unless a candidate turns out to be considerably
+/// faster, see [`Config::reselect_threshold`].
+///
+/// The relayed addresses of all selected relays are confirmed as external addresses, and thus
+/// advertised to other peers, e.g. via identify. They expire as soon as the reservation through
+/// the respective relay is lost.
+///
+/// Reservations are made through the relay client [`Transport`](super::Transport), thus the
+/// relay client [`Behaviour`](super::Behaviour) needs to be part of the same
+/// [`Swarm`](libp2p_swarm::Swarm).
+pub struct Behaviour {
+    config: Config,
+
+    ping: ping::Behaviour,
+
+    candidates: HashMap<PeerId, Candidate>,
+    /// Listeners on relayed addresses, one for each selected relay.
+    selected: HashMap<ListenerId, Selection>,
+
+    /// The direct connections of each peer, with the dialed address of outbound connections.
+    connections: HashMap<PeerId, HashMap<ConnectionId, Option<Multiaddr>>>,
+
+    /// Queue of actions to return when polled.
+    queued_actions: VecDeque<ToSwarm<Event, THandlerInEvent<Self>>>,
+}
+
+struct Candidate {
+    /// The address the candidate is reachable at, if known.
+    address: Option<Multiaddr>,
+    /// The addresses the candidate is possibly reachable at, dialed while its address is unknown.
+    dial_addresses: Vec<Multiaddr>,
+    /// The connection to the candidate on which it is pinged.
+    connection: Option<ConnectionId>,
+    /// The connection being dialed to the candidate, if any.
+    pending_dial: Option<ConnectionId>,
+    /// Whether the candidate could not be reached or refused our reservation.
+    failed: bool,
+}
+
+struct Selection {
+    relay_peer_id: PeerId,
+    rtt: Duration,
+    /// Whether the relay accepted our reservation.
+    reserved: bool,
+    /// Whether the reservation was lost and is being re-established.
+    degraded: bool,
+    /// Whether the relay is replaced as soon as the reservation with a faster candidate is
+    /// accepted.
+    superseded: bool,
+    /// The relayed addresses confirmed as external addresses.
+    addresses: HashSet<Multiaddr>,
+}
+
+impl Behaviour {
+    pub fn new(config: Config) -> Self {
+        Self {
+            ping: ping::Behaviour::new(config.ping.clone()),
+            config,
+            candidates: Default::default(),
+            selected: Default::default(),
+            connections: Default::default(),
+            queued_actions: Default::default(),
+        }
+    }
+
+    /// Adds a candidate relay reachable at the given address.
+    ///
+    /// Adding a candidate again, e.g. after [`Event::CandidateFailed`], updates its address and
+    /// makes it eligible for selection again.
+    pub fn add_candidate(&mut self, relay_peer_id: PeerId, address: Multiaddr) {
+        self.insert_candidate(relay_peer_id, Some(address), Vec::new());
+    }
+
+    /// Adds a candidate relay possibly reachable at any of the given addresses, e.g. a relay
+    /// found through a Kademlia provider query.
+    ///
+    /// Addresses known to other [`NetworkBehaviour`]s are dialed as well. The relayed address is
+    /// built from the address the candidate is eventually dialed at.
+    pub fn add_candidate_peer(&mut self, relay_peer_id: PeerId, addresses: Vec<Multiaddr>) {
+        self.insert_candidate(relay_peer_id, None, addresses);
+    }
+
+    fn insert_candidate(
+        &mut self,
+        relay_peer_id: PeerId,
+        address: Option<Multiaddr>,
+        dial_addresses: Vec<Multiaddr>,
+    ) {
+        let candidate = self
+            .candidates
+            .entry(relay_peer_id)
+            .or_insert_with(|| Candidate {
+                address: None,
+                dial_addresses: Vec::new(),
+                connection: None,
+                pending_dial: None,
+                failed: false,
+            });
+        if address.is_some() {
+            candidate.address = address;
+        }
+        candidate.dial_addresses = dial_addresses;
+        candidate.failed = false;
+
+        if candidate.connection.is_none() && candidate.pending_dial.is_none() {
+            self.probe(relay_peer_id);
+        }
+
+        self.select();
+    }
+
+    /// Starts pinging a candidate, on an existing connection if possible, otherwise on a new one.
+    fn probe(&mut self, relay_peer_id: PeerId) {
+        let candidate = self
+            .candidates
+            .get_mut(&relay_peer_id)
+            .expect("to probe known candidates");
+
+        let has_address = candidate.address.is_some();
+        let existing = self
+            .connections
+            .get(&relay_peer_id)
+            .and_then(|connections| {
+                connections
+                    .iter()
+                    .find(|(_, addr)| has_address || addr.is_some())
+            });
+        if let Some((connection_id, addr)) = existing {
+            if candidate.address.is_none() {
+                candidate.address = addr.clone();
+            }
+            candidate.connection = Some(*connection_id);
+            self.queued_actions.push_back(ToSwarm::NotifyHandler {
+                peer_id: relay_peer_id,
+                handler: NotifyHandler::One(*connection_id),
+                event: handler::In::SetProbing(true),
+            });
+            return;
+        }
+
+        // Dial even if connected, as the existing connections may lack a usable address.
+        let addresses = match &candidate.address {
+            Some(address) => vec![address.clone()],
+            None => candidate.dial_addresses.clone(),
+        };
+        let opts = DialOpts::peer_id(relay_peer_id)
+            .addresses(addresses)
+            .condition(PeerCondition::Always)
+            .build();
+        candidate.pending_dial = Some(opts.connection_id());
+        self.queued_actions.push_back(ToSwarm::Dial { opts });
+    }
+
+    /// Stops pinging a candidate, allowing its connection to close when idle.
+    fn stop_probing(&mut self, relay_peer_id: PeerId, connection_id: ConnectionId) {
+        self.queued_actions.push_back(ToSwarm::NotifyHandler {
+            peer_id: relay_peer_id,
+            handler: NotifyHandler::One(connection_id),
+            event: handler::In::SetProbing(false),
+        });
+    }
+
+    /// Removes a candidate relay, closing the reservation with it if it is selected.
+    pub fn remove_candidate(&mut self, relay_peer_id: &PeerId) {
+        let Some(candidate) = self.candidates.remove(relay_peer_id) else {
+            return;
+        };
+        if let Some(connection_id) = candidate.connection {
+            self.stop_probing(*relay_peer_id, connection_id);
+        }
+
+        let listeners = self
+            .selected
+            .iter()
+            .filter(|(_, selection)| selection.relay_peer_id == *relay_peer_id)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in listeners {
+            self.queued_actions
+                .push_back(ToSwarm::RemoveListener { id });
+        }
+    }
+
+    /// Returns the currently selected relays whose reservation has been accepted and is not
+    /// degraded.
+    pub fn selected_relays(&self) -> impl Iterator<Item = &PeerId> {
+        self.selected
+            .values()
+            .filter(|selection| selection.reserved && !selection.degraded)
+            .map(|selection| &selection.relay_peer_id)
+    }
+
+    /// Returns the number of selected relays that are not degraded.
+    fn num_healthy(&self) -> usize {
+        self.selected
+            .values()
+            .filter(|selection| !selection.degraded)
+            .count()
+    }
+
+    /// Returns the connected candidates that are not selected, ordered by their average
+    /// round-trip time.
+    fn ranked_candidates(&self) -> Vec<(Duration, PeerId)> {
+        let mut ranked = self
+            .candidates
+            .iter()
+            .filter(|(peer_id, candidate)| {
+                !candidate.failed && candidate.connection.is_some() && !self.is_selected(peer_id)
+            })
+            .filter_map(|(peer_id, _)| Some((self.ping.rtt_stats(peer_id)?.avg, *peer_id)))
+            .collect::<Vec<_>>();
+        ranked.sort();
+        ranked
+    }
+
+    fn is_selected(&self, peer_id: &PeerId) -> bool {
+        self.selected
+            .values()
+            .any(|selection| selection.relay_peer_id == *peer_id)
+    }
+
+    /// Fills free relay slots with the candidates having the lowest round-trip time.
+    fn select(&mut self) {
+        let free = self.config.max_relays.saturating_sub(self.num_healthy());
+        if free == 0 {
+            return;
+        }
+
+        let ranked = self.ranked_candidates();
+        if ranked.len() < free {
+            // Reconnect to the candidates whose connection closed in the meantime.
+            let disconnected = self
+                .candidates
+                .iter()
+                .filter(|(peer_id, candidate)| {
+                    !candidate.failed
+                        && candidate.connection.is_none()
+                        && candidate.pending_dial.is_none()
+                        && !self.is_selected(peer_id)
+                })
+                .map(|(peer_id, _)| *peer_id)
+                .collect::<Vec<_>>();
+            for relay_peer_id in disconnected {
+                self.probe(relay_peer_id);
+            }
+        }
+
+        for (rtt, relay_peer_id) in ranked.into_iter().take(free) {
+            self.listen_via(relay_peer_id, rtt);
+        }
+    }
+
+    /// Replaces the slowest selected relay by the fastest unselected candidate, if the latter
+    /// undercuts the former by [`Config::reselect_threshold`].
+    fn reselect(&mut self) {
+        if self.num_healthy() < self.config.max_relays
+            || self
+                .selected
+                .values()
+                .any(|selection| !selection.reserved || selection.superseded)
+        {
+            // Wait for pending reservations first.
+            return;
+        }
+
+        let Some((rtt, relay_peer_id)) = self.ranked_candidates().into_iter().next() else {
+            return;
+        };
+        let slowest = self
+            .selected
+            .values_mut()
+            .filter(|selection| !selection.degraded)
+            .map(|selection| {
+                let current = self
+                    .ping
+                    .rtt_stats(&selection.relay_peer_id)
+                    .map_or(selection.rtt, |stats| stats.avg);
+                (current, selection)
+            })
+            .max_by_key(|(current, _)| *current);
+        let Some((slowest_rtt, slowest)) = slowest else {
+            return;
+        };
+        if rtt >= slowest_rtt.mul_f64(self.config.reselect_threshold) {
+            return;
+        }
+
+        slowest.superseded = true;
+        self.listen_via(relay_peer_id, rtt);
+    }
+
+    /// Makes a reservation with the given relay by listening on a relayed address through it.
+    fn listen_via(&mut self, relay_peer_id: PeerId, rtt: Duration) {
+        let address = self.candidates[&relay_peer_id]
+            .address
+            .as_ref()
+            .expect("connected candidates to have an address");
+        let relay_addr = match address.iter().last() {
+            Some(Protocol::P2p(_)) => address.clone(),
+            _ => address.clone().with(Protocol::P2p(relay_peer_id)),
+        };
+
+        let opts = ListenOpts::new(relay_addr.with(Protocol::P2pCircuit));
+        self.selected.insert(
+            opts.listener_id(),
+            Selection {
+                relay_peer_id,
+                rtt,
+                reserved: false,
+                degraded: false,
+                superseded: false,
+                addresses: Default::default(),
+            },
+        );
+        self.queued_actions.push_back(ToSwarm::ListenOn { opts });
+    }
+
+    fn on_listener_closed(&mut self, listener_id: ListenerId, failed: bool) {
+        let Some(selection) = self.selected.remove(&listener_id) else {
+            return;
+        };
+        let relay_peer_id = selection.relay_peer_id;
+        for addr in selection.addresses {
+            self.queued_actions
+                .push_back(ToSwarm::ExternalAddrExpired(addr));
+        }
+
+        let event = if selection.reserved && !failed {
+            Event::RelayDeselected { relay_peer_id }
+        } else {
+            if !selection.reserved {
+                // The candidate that was to replace a slower relay failed, keep the latter.
+                for selection in self.selected.values_mut() {
+                    selection.superseded = false;
+                }
+            }
+            if let Some(candidate) = self.candidates.get_mut(&relay_peer_id) {
+                candidate.failed = true;
+                if let Some(connection_id) = candidate.connection {
+                    self.stop_probing(relay_peer_id, connection_id);
+                }
+            }
+            Event::CandidateFailed { relay_peer_id }
+        };
+        self.queued_actions.push_back(ToSwarm::GenerateEvent(event));
+
+        self.select();
+    }
+
+    fn on_expired_listen_addr(&mut self, listener_id: ListenerId, addr: &Multiaddr) {
+        let Some(selection) = self.selected.get_mut(&listener_id) else {
+            return;
+        };
+        if !selection.addresses.remove(addr) {
+            return;
+        }
+        self.queued_actions
+            .push_back(ToSwarm::ExternalAddrExpired(addr.clone()));
+
+        if !selection.reserved || selection.degraded || !selection.addresses.is_empty() {
+            return;
+        }
+
+        // The reservation was lost. Should the listener close instead, the relay is deselected
+        // when notified about it.
+        selection.degraded = true;
+        self.queued_actions
+            .push_back(ToSwarm::GenerateEvent(Event::RelayDegraded {
+                relay_peer_id: selection.relay_peer_id,
+            }));
+        self.select();
+    }
+
+    /// Deselects the relays the last connection to which closed, without waiting for their
+    /// listener to close.
+    fn on_relay_disconnected(&mut self, relay_peer_id: PeerId) {
+        let listeners = self
+            .selected
+            .iter()
+            .filter(|(_, selection)| selection.relay_peer_id == relay_peer_id)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        if listeners.is_empty() {
+            return;
+        }
+
+        for id in listeners {
+            self.queued_actions
+                .push_back(ToSwarm::RemoveListener { id });
+            self.on_listener_closed(id, false);
+        }
+    }
+
+    fn on_new_listen_addr(&mut self, listener_id: ListenerId, addr: &Multiaddr) {
+        let Some(selection) = self.selected.get_mut(&listener_id) else {
+            return;
+        };
+        let relay_peer_id = selection.relay_peer_id;
+
+        if selection.addresses.insert(addr.clone()) {
+            self.queued_actions
+                .push_back(ToSwarm::ExternalAddrConfirmed(addr.clone()));
+        }
+
+        let event = if !selection.reserved {
+            selection.reserved = true;
+            Event::RelaySelected {
+                relay_peer_id,
+                rtt: selection.rtt,
+            }
+        } else if selection.degraded {
+            selection.degraded = false;
+            Event::RelayRecovered { relay_peer_id }
+        } else {
+            return;
+        };
+        self.queued_actions.push_back(ToSwarm::GenerateEvent(event));
+
+        let superseded = self
+            .selected
+            .iter()
+            .filter(|(_, selection)| selection.superseded)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        if !superseded.is_empty() {
+            // A faster relay replaces a slower one.
+            for id in superseded {
+                self.queued_actions
+                    .push_back(ToSwarm::RemoveListener { id });
+            }
+        } else if self.num_healthy() > self.config.max_relays {
+            // A replacement has been selected while the relay was degraded.
+            self.queued_actions
+                .push_back(ToSwarm::RemoveListener { id: listener_id });
+        }
+    }
+
+    /// Returns whether a new connection to the given peer is to be pinged.
+    ///
+    /// `dialed_address` is the address of outbound connections, used for candidates added
+    /// without an address.
+    fn on_established(
+        &mut self,
+        peer_id: PeerId,
+        connection_id: ConnectionId,
+        remote_address: &Multiaddr,
+        dialed_address: Option<&Multiaddr>,
+    ) -> bool {
+        if remote_address.iter().any(|p| p == Protocol::P2pCircuit) {
+            // Reservations cannot be made over relayed connections.
+            return false;
+        }
+        self.connections
+            .entry(peer_id)
+            .or_default()
+            .insert(connection_id, dialed_address.cloned());
+
+        match self.candidates.get_mut(&peer_id) {
+            Some(candidate) if candidate.connection.is_none() && !candidate.failed => {
+                if candidate.address.is_none() {
+                    let Some(address) = dialed_address else {
+                        return false;
+                    };
+                    candidate.address = Some(address.clone());
+                }
+                if candidate.pending_dial == Some(connection_id) {
+                    candidate.pending_dial = None;
+                }
+                candidate.connection = Some(connection_id);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl NetworkBehaviour for Behaviour {
+    type ConnectionHandler = Handler;
+    type ToSwarm = Event;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        local_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        let probing = self.on_established(peer, connection_id, remote_addr, None);
+
+        Ok(Handler::new(
+            self.ping.handle_established_inbound_connection(
+                connection_id,
+                peer,
+                local_addr,
+                remote_addr,
+            )?,
+            probing,
+        ))
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        addr: &Multiaddr,
+        role_override: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        let probing = self.on_established(peer, connection_id, addr, Some(addr));
+
+        Ok(Handler::new(
+            self.ping.handle_established_outbound_connection(
+                connection_id,
+                peer,
+                addr,
+                role_override,
+            )?,
+            probing,
+        ))
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm<Self::ConnectionHandler>) {
+        match event {
+            FromSwarm::ConnectionClosed(ConnectionClosed {
+                peer_id,
+                connection_id,
+                endpoint,
+                handler,
+                remaining_established,
+            }) => {
+                if let Some(connections) = self.connections.get_mut(&peer_id) {
+                    connections.remove(&connection_id);
+                    if connections.is_empty() {
+                        self.connections.remove(&peer_id);
+                    }
+                }
+                if let Some(candidate) = self.candidates.get_mut(&peer_id) {
+                    if candidate.connection == Some(connection_id) {
+                        candidate.connection = None;
+                    }
+                }
+
+                self.ping
+                    .on_swarm_event(FromSwarm::ConnectionClosed(ConnectionClosed {
+                        peer_id,
+                        connection_id,
+                        endpoint,
+                        handler: handler.into_ping(),
+                        remaining_established,
+                    }));
+
+                if remaining_established == 0 {
+                    self.on_relay_disconnected(peer_id);
+                }
+            }
+            FromSwarm::DialFailure(DialFailure {
+                peer_id: Some(peer_id),
+                connection_id,
+                ..
+            }) => {
+                let Some(candidate) = self.candidates.get_mut(&peer_id) else {
+                    return;
+                };
+                if candidate.pending_dial != Some(connection_id) {
+                    return;
+                }
+
+                candidate.pending_dial = None;
+                candidate.failed = true;
+                self.queued_actions
+                    .push_back(ToSwarm::GenerateEvent(Event::CandidateFailed {
+                        relay_peer_id: peer_id,
+                    }));
+            }
+            FromSwarm::NewListenAddr(NewListenAddr { listener_id, addr }) => {
+                self.on_new_listen_addr(listener_id, addr)
+            }
+            FromSwarm::ExpiredListenAddr(ExpiredListenAddr { listener_id, addr }) => {
+                self.on_expired_listen_addr(listener_id, addr)
+            }
+            FromSwarm::ListenerClosed(ListenerClosed {
+                listener_id,
+                reason,
+            }) => self.on_listener_closed(listener_id, reason.is_err()),
+            FromSwarm::ConnectionEstablished(_)
+            | FromSwarm::DialFailure(_)
+            | FromSwarm::AddressChange(_)
+            | FromSwarm::ListenFailure(_)
+            | FromSwarm::NewListener(_)
+            | FromSwarm::ListenerError(_)
+            | FromSwarm::NewExternalAddrCandidate(_)
+            | FromSwarm::ExternalAddrExpired(_)
+            | FromSwarm::ExternalAddrConfirmed(_) => {}
+        }
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        peer_id: PeerId,
+        connection_id: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        self.ping
+            .on_connection_handler_event(peer_id, connection_id, event)
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+        params: &mut impl PollParameters,
+    ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        loop {
+            if let Some(action) = self.queued_actions.pop_front() {
+                return Poll::Ready(action);
+            }
+
+            match self.ping.poll(cx, params) {
+                Poll::Ready(ToSwarm::GenerateEvent(ping::Event { result: Ok(_), .. })) => {
+                    self.select();
+                    self.reselect();
+                }
+                // Failed pings are of no interest for the selection.
+                Poll::Ready(ToSwarm::GenerateEvent(_)) => {}
+                Poll::Ready(action) => {
+                    return Poll::Ready(
+                        action
+                            .map_in(handler::In::Ping)
+                            .map_out(|_| unreachable!("Events are handled above.")),
+                    )
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
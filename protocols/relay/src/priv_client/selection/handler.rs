@@ -0,0 +1,109 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use libp2p_ping as ping;
+use libp2p_swarm::handler::ConnectionEvent;
+use libp2p_swarm::{
+    ConnectionHandler, ConnectionHandlerEvent, KeepAlive, SubstreamProtocol, THandler,
+    THandlerInEvent, THandlerOutEvent,
+};
+use std::task::{Context, Poll};
+
+#[derive(Debug)]
+pub enum In {
+    /// Start or stop probing the remote as a candidate relay.
+    SetProbing(bool),
+    Ping(THandlerInEvent<ping::Behaviour>),
+}
+
+/// Handler of every connection, keeping connections to candidate relays alive.
+///
+/// All connections are pinged, as remotes stop pinging us for good once we refuse their pings.
+/// Connections to candidates are additionally kept alive, since the round-trip time is only
+/// measured on open connections.
+pub struct Handler {
+    ping: THandler<ping::Behaviour>,
+    probing: bool,
+}
+
+impl Handler {
+    pub(crate) fn new(ping: THandler<ping::Behaviour>, probing: bool) -> Self {
+        Self { ping, probing }
+    }
+
+    pub(crate) fn into_ping(self) -> THandler<ping::Behaviour> {
+        self.ping
+    }
+}
+
+impl ConnectionHandler for Handler {
+    type FromBehaviour = In;
+    type ToBehaviour = THandlerOutEvent<ping::Behaviour>;
+    type Error = <THandler<ping::Behaviour> as ConnectionHandler>::Error;
+    type InboundProtocol = <THandler<ping::Behaviour> as ConnectionHandler>::InboundProtocol;
+    type OutboundProtocol = <THandler<ping::Behaviour> as ConnectionHandler>::OutboundProtocol;
+    type InboundOpenInfo = ();
+    type OutboundOpenInfo = ();
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
+        self.ping.listen_protocol()
+    }
+
+    fn on_behaviour_event(&mut self, event: Self::FromBehaviour) {
+        match event {
+            In::SetProbing(probing) => self.probing = probing,
+            In::Ping(event) => self.ping.on_behaviour_event(event),
+        }
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        if self.probing {
+            KeepAlive::Yes
+        } else {
+            self.ping.connection_keep_alive()
+        }
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<
+        ConnectionHandlerEvent<
+            Self::OutboundProtocol,
+            Self::OutboundOpenInfo,
+            Self::ToBehaviour,
+            Self::Error,
+        >,
+    > {
+        self.ping.poll(cx)
+    }
+
+    fn on_connection_event(
+        &mut self,
+        event: ConnectionEvent<
+            Self::InboundProtocol,
+            Self::OutboundProtocol,
+            Self::InboundOpenInfo,
+            Self::OutboundOpenInfo,
+        >,
+    ) {
+        self.ping.on_connection_event(event);
+    }
+}
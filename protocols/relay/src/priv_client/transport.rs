@@ -147,6 +147,7 @@ impl libp2p_core::Transport for Transport {
         let listener = Listener {
             listener_id,
             queued_events: Default::default(),
+            listen_addrs: Default::default(),
             from_behaviour,
             is_closed: false,
         };
@@ -305,6 +306,8 @@ pub(crate) struct Listener {
     listener_id: ListenerId,
     /// Queue of events to report when polled.
     queued_events: VecDeque<<Self as Stream>::Item>,
+    /// Addresses of the current reservation, expired once the reservation is lost.
+    listen_addrs: Vec<Multiaddr>,
     /// Channel for messages from the behaviour [`Handler`][super::handler::Handler].
     from_behaviour: mpsc::Receiver<ToListenerMsg>,
     /// The listener can be closed either manually with [`Transport::remove_listener`](libp2p_core::Transport) or if
@@ -359,12 +362,26 @@ impl Stream for Listener {
                     );
                     // Returned as [`ListenerEvent::NewAddress`] in next iteration of loop.
                     self.queued_events = addrs
-                        .into_iter()
+                        .iter()
+                        .cloned()
                         .map(|listen_addr| TransportEvent::NewAddress {
                             listener_id: self.listener_id,
                             listen_addr,
                         })
                         .collect();
+                    self.listen_addrs = addrs;
+                }
+                ToListenerMsg::ReservationLost => {
+                    let listener_id = self.listener_id;
+                    let expired = std::mem::take(&mut self.listen_addrs);
+
+                    self.queued_events
+                        .extend(expired.into_iter().map(|listen_addr| {
+                            TransportEvent::AddressExpired {
+                                listener_id,
+                                listen_addr,
+                            }
+                        }));
                 }
                 ToListenerMsg::IncomingRelayedConnection {
                     stream,
@@ -444,6 +461,8 @@ pub(crate) enum TransportToBehaviourMsg {
 #[allow(clippy::large_enum_variant)]
 pub enum ToListenerMsg {
     Reservation(Result<Reservation, ()>),
+    /// The reservation expired without being renewed, though renewal is still being attempted.
+    ReservationLost,
     IncomingRelayedConnection {
         stream: Connection,
         src_peer_id: PeerId,
@@ -152,15 +152,10 @@ impl ReservationReq {
                     .as_secs(),
                 voucher: None,
             }),
-            limit: Some(proto::Limit {
-                duration: Some(
-                    self.max_circuit_duration
-                        .as_secs()
-                        .try_into()
-                        .expect("`max_circuit_duration` not to exceed `u32::MAX`."),
-                ),
-                data: Some(self.max_circuit_bytes),
-            }),
+            limit: Some(circuit_limit(
+                self.max_circuit_duration,
+                self.max_circuit_bytes,
+            )),
             status: Some(proto::Status::OK),
         };
 
@@ -198,12 +193,17 @@ impl CircuitReq {
         self.dst
     }
 
-    pub async fn accept(mut self) -> Result<(Stream, Bytes), UpgradeError> {
+    /// Accepts the circuit request, announcing the limits of the circuit to the source.
+    pub async fn accept(
+        mut self,
+        max_circuit_duration: Duration,
+        max_circuit_bytes: u64,
+    ) -> Result<(Stream, Bytes), UpgradeError> {
         let msg = proto::HopMessage {
             type_pb: proto::HopMessageType::STATUS,
             peer: None,
             reservation: None,
-            limit: None,
+            limit: Some(circuit_limit(max_circuit_duration, max_circuit_bytes)),
             status: Some(proto::Status::OK),
         };
 
@@ -242,3 +242,15 @@ impl CircuitReq {
         Ok(())
     }
 }
+
+fn circuit_limit(max_circuit_duration: Duration, max_circuit_bytes: u64) -> proto::Limit {
+    proto::Limit {
+        duration: Some(
+            max_circuit_duration
+                .as_secs()
+                .try_into()
+                .expect("`max_circuit_duration` not to exceed `u32::MAX`."),
+        ),
+        data: Some(max_circuit_bytes),
+    }
+}
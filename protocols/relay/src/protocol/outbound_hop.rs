@@ -23,7 +23,6 @@ use crate::protocol::{Limit, HOP_PROTOCOL_NAME, MAX_MESSAGE_SIZE};
 use asynchronous_codec::{Framed, FramedParts};
 use bytes::Bytes;
 use futures::{future::BoxFuture, prelude::*};
-use futures_timer::Delay;
 use instant::{Duration, SystemTime};
 use libp2p_core::{upgrade, Multiaddr};
 use libp2p_identity::PeerId;
@@ -114,6 +113,9 @@ impl upgrade::OutboundUpgrade<Stream> for Upgrade {
                         proto::Status::RESOURCE_LIMIT_EXCEEDED => {
                             return Err(ReservationFailedReason::ResourceLimitExceeded.into())
                         }
+                        proto::Status::PERMISSION_DENIED => {
+                            return Err(ReservationFailedReason::PermissionDenied.into())
+                        }
                         s => return Err(FatalUpgradeError::UnexpectedStatus(s).into()),
                     }
 
@@ -131,7 +133,7 @@ impl upgrade::OutboundUpgrade<Stream> for Upgrade {
                         .collect::<Result<Vec<Multiaddr>, _>>()
                         .map_err(|_| FatalUpgradeError::InvalidReservationAddrs)?;
 
-                    let renewal_timeout = reservation
+                    let expires_in = reservation
                         .expire
                         .checked_sub(
                             SystemTime::now()
@@ -139,16 +141,13 @@ impl upgrade::OutboundUpgrade<Stream> for Upgrade {
                                 .unwrap()
                                 .as_secs(),
                         )
-                        // Renew the reservation after 3/4 of the reservation expiration timestamp.
-                        .and_then(|duration| duration.checked_sub(duration / 4))
                         .map(Duration::from_secs)
-                        .map(Delay::new)
                         .ok_or(FatalUpgradeError::InvalidReservationExpiration)?;
 
                     substream.close().await?;
 
                     Output::Reservation {
-                        renewal_timeout,
+                        expires_in,
                         addrs,
                         limit,
                     }
@@ -232,6 +231,8 @@ pub enum ReservationFailedReason {
     Refused,
     #[error("Remote reported resource limit exceeded.")]
     ResourceLimitExceeded,
+    #[error("Remote reported permission denied.")]
+    PermissionDenied,
 }
 
 #[derive(Debug, Error)]
@@ -264,7 +265,8 @@ pub enum FatalUpgradeError {
 
 pub enum Output {
     Reservation {
-        renewal_timeout: Delay,
+        /// Time until the reservation expires at the relay.
+        expires_in: Duration,
         addrs: Vec<Multiaddr>,
         limit: Option<Limit>,
     },
@@ -0,0 +1,231 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use futures::executor::LocalPool;
+use futures::future::{self, Either, FutureExt};
+use futures::io::{AsyncRead, AsyncWrite};
+use futures::stream::StreamExt;
+use futures::task::Spawn;
+use futures_timer::Delay;
+use libp2p_core::multiaddr::{Multiaddr, Protocol};
+use libp2p_core::muxing::StreamMuxerBox;
+use libp2p_core::transport::{Boxed, MemoryTransport, Transport};
+use libp2p_core::{upgrade, Endpoint};
+use libp2p_identity as identity;
+use libp2p_identity::PeerId;
+use libp2p_identity::PublicKey;
+use libp2p_plaintext::PlainText2Config;
+use libp2p_relay as relay;
+use libp2p_swarm::behaviour::toggle::Toggle;
+use libp2p_swarm::{
+    dummy, keep_alive, ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, PollParameters,
+    Swarm, SwarmBuilder, SwarmEvent, THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
+};
+use std::collections::VecDeque;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+#[test]
+fn advertises_relay_server_while_reachable() {
+    let _ = env_logger::try_init();
+    let mut pool = LocalPool::new();
+
+    let (mut relay, relay_addr) = build_relay(true);
+    let public_addr = Multiaddr::empty().with(Protocol::Memory(rand::random::<u64>()));
+    let relayed_addr = public_addr
+        .clone()
+        .with(Protocol::P2p(PeerId::random()))
+        .with(Protocol::P2pCircuit);
+
+    // Relayed addresses don't make the relay server reachable.
+    relay.add_external_address(relayed_addr.clone());
+    relay.add_external_address(public_addr.clone());
+
+    // The relay server is detected once the first connection is established.
+    let mut client = build_client();
+    client.dial(relay_addr).unwrap();
+    spawn_swarm_on_pool(&pool, client);
+    assert!(pool.run_until(wait_for_advertisement(&mut relay)));
+    assert!(relay.behaviour().advertisement.is_advertised());
+
+    relay.remove_external_address(&relayed_addr);
+    relay.remove_external_address(&public_addr);
+    assert!(!pool.run_until(wait_for_advertisement(&mut relay)));
+    assert!(!relay.behaviour().advertisement.is_advertised());
+}
+
+#[test]
+fn does_not_advertise_without_relay_server() {
+    let _ = env_logger::try_init();
+    let mut pool = LocalPool::new();
+
+    let (mut relay, relay_addr) = build_relay(false);
+    relay.add_external_address(Multiaddr::empty().with(Protocol::Memory(rand::random::<u64>())));
+
+    let mut client = build_client();
+    client.dial(relay_addr).unwrap();
+    spawn_swarm_on_pool(&pool, client);
+    let advertised = pool.run_until(async {
+        match future::select(
+            Box::pin(wait_for_advertisement(&mut relay)),
+            Delay::new(Duration::from_secs(1)),
+        )
+        .await
+        {
+            Either::Left((advertised, _)) => Some(advertised),
+            Either::Right(_) => None,
+        }
+    });
+    assert_eq!(advertised, None);
+    assert!(!relay.behaviour().advertisement.is_advertised());
+}
+
+fn build_relay(relay_server: bool) -> (Swarm<AdvertisingNode>, Multiaddr) {
+    let local_key = identity::Keypair::generate_ed25519();
+    let local_peer_id = local_key.public().to_peer_id();
+    let transport = upgrade_transport(MemoryTransport::default().boxed(), local_key.public());
+
+    let mut swarm = SwarmBuilder::with_async_std_executor(
+        transport,
+        AdvertisingNode {
+            relay: relay_server
+                .then(|| relay::Behaviour::new(local_peer_id, Default::default()))
+                .into(),
+            advertisement: relay::advertisement::Behaviour::new(Recorder::default()),
+            keep_alive: keep_alive::Behaviour,
+        },
+        local_peer_id,
+    )
+    .build();
+    let addr = Multiaddr::empty().with(Protocol::Memory(rand::random::<u64>()));
+    swarm.listen_on(addr.clone()).unwrap();
+
+    (swarm, addr)
+}
+
+fn build_client() -> Swarm<keep_alive::Behaviour> {
+    let local_key = identity::Keypair::generate_ed25519();
+    let local_peer_id = local_key.public().to_peer_id();
+    let transport = upgrade_transport(MemoryTransport::default().boxed(), local_key.public());
+
+    SwarmBuilder::with_async_std_executor(transport, keep_alive::Behaviour, local_peer_id).build()
+}
+
+fn upgrade_transport<StreamSink>(
+    transport: Boxed<StreamSink>,
+    local_public_key: PublicKey,
+) -> Boxed<(PeerId, StreamMuxerBox)>
+where
+    StreamSink: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    transport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(PlainText2Config { local_public_key })
+        .multiplex(libp2p_yamux::Config::default())
+        .boxed()
+}
+
+fn spawn_swarm_on_pool<B: NetworkBehaviour + Send>(pool: &LocalPool, swarm: Swarm<B>) {
+    pool.spawner()
+        .spawn_obj(swarm.collect::<Vec<_>>().map(|_| ()).boxed().into())
+        .unwrap();
+}
+
+/// Waits for the relay server to be advertised or withdrawn.
+async fn wait_for_advertisement(swarm: &mut Swarm<AdvertisingNode>) -> bool {
+    loop {
+        if let SwarmEvent::Behaviour(AdvertisingNodeEvent::Advertisement(advertised)) =
+            swarm.select_next_some().await
+        {
+            return advertised;
+        }
+    }
+}
+
+#[derive(NetworkBehaviour)]
+#[behaviour(prelude = "libp2p_swarm::derive_prelude")]
+struct AdvertisingNode {
+    relay: Toggle<relay::Behaviour>,
+    advertisement: relay::advertisement::Behaviour<Recorder>,
+    keep_alive: keep_alive::Behaviour,
+}
+
+/// Reports whether the relay server is advertised.
+#[derive(Default)]
+struct Recorder {
+    events: VecDeque<bool>,
+}
+
+impl relay::advertisement::Advertise for Recorder {
+    fn advertise(&mut self) {
+        self.events.push_back(true);
+    }
+
+    fn withdraw(&mut self) {
+        self.events.push_back(false);
+    }
+}
+
+impl NetworkBehaviour for Recorder {
+    type ConnectionHandler = dummy::ConnectionHandler;
+    type ToSwarm = bool;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: PeerId,
+        _: &Multiaddr,
+        _: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: PeerId,
+        _: &Multiaddr,
+        _: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn on_swarm_event(&mut self, _: FromSwarm<Self::ConnectionHandler>) {}
+
+    fn on_connection_handler_event(
+        &mut self,
+        _: PeerId,
+        _: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        void::unreachable(event)
+    }
+
+    fn poll(
+        &mut self,
+        _: &mut Context<'_>,
+        _: &mut impl PollParameters,
+    ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        match self.events.pop_front() {
+            Some(advertised) => Poll::Ready(ToSwarm::GenerateEvent(advertised)),
+            None => Poll::Pending,
+        }
+    }
+}
@@ -34,7 +34,11 @@ use libp2p_identity::PublicKey;
 use libp2p_ping as ping;
 use libp2p_plaintext::PlainText2Config;
 use libp2p_relay as relay;
-use libp2p_swarm::{NetworkBehaviour, Swarm, SwarmBuilder, SwarmEvent};
+use libp2p_swarm::{
+    keep_alive, NetworkBehaviour, StreamUpgradeError, Swarm, SwarmBuilder, SwarmEvent,
+};
+use std::collections::HashMap;
+use std::num::NonZeroU32;
 use std::time::Duration;
 
 #[test]
@@ -78,6 +82,86 @@ fn reservation() {
     ));
 }
 
+#[test]
+fn lost_reservation_is_reestablished() {
+    let _ = env_logger::try_init();
+    let mut pool = LocalPool::new();
+
+    let relay_addr = Multiaddr::empty().with(Protocol::Memory(rand::random::<u64>()));
+    // Deny all renewals until the rate limiter refills, long after the reservation expired.
+    let mut relay = build_relay_with_config(
+        relay::Config {
+            reservation_duration: Duration::from_secs(2),
+            reservation_rate_limiters: Vec::new(),
+            ..Default::default()
+        }
+        .reservation_rate_per_peer(NonZeroU32::new(1).unwrap(), Duration::from_secs(5)),
+    );
+    let relay_peer_id = *relay.local_peer_id();
+
+    relay.listen_on(relay_addr.clone()).unwrap();
+    relay.add_external_address(relay_addr.clone());
+    spawn_swarm_on_pool(&pool, relay);
+
+    let client_addr = relay_addr
+        .with(Protocol::P2p(relay_peer_id))
+        .with(Protocol::P2pCircuit);
+    let mut client = build_client();
+    let client_peer_id = *client.local_peer_id();
+    let client_addr_with_peer_id = client_addr.clone().with(Protocol::P2p(client_peer_id));
+
+    client.listen_on(client_addr).unwrap();
+
+    assert!(pool.run_until(wait_for_dial(&mut client, relay_peer_id)));
+    pool.run_until(wait_for_reservation(
+        &mut client,
+        client_addr_with_peer_id.clone(),
+        relay_peer_id,
+        false, // No renewal.
+    ));
+
+    // Wait for the reservation to be lost and its address to expire.
+    pool.run_until(async {
+        let mut reservation_lost = false;
+        let mut address_expired = false;
+        loop {
+            match client.select_next_some().await {
+                SwarmEvent::Behaviour(ClientEvent::Relay(
+                    relay::client::Event::ReservationLost {
+                        relay_peer_id: peer_id,
+                        ..
+                    },
+                )) => {
+                    assert_eq!(relay_peer_id, peer_id);
+
+                    reservation_lost = true;
+                    if address_expired {
+                        break;
+                    }
+                }
+                SwarmEvent::ExpiredListenAddr { address, .. } => {
+                    assert_eq!(address, client_addr_with_peer_id);
+
+                    address_expired = true;
+                    if reservation_lost {
+                        break;
+                    }
+                }
+                SwarmEvent::Behaviour(ClientEvent::Ping(_)) => {}
+                e => panic!("{e:?}"),
+            }
+        }
+    });
+
+    // Wait for the reservation to be re-established without a new listen call.
+    pool.run_until(wait_for_reservation(
+        &mut client,
+        client_addr_with_peer_id,
+        relay_peer_id,
+        true, // Renewal.
+    ));
+}
+
 #[test]
 fn new_reservation_to_same_relay_replaces_old() {
     let _ = env_logger::try_init();
@@ -169,6 +253,135 @@ fn new_reservation_to_same_relay_replaces_old() {
     });
 }
 
+#[test]
+fn reservation_denied_by_acl() {
+    let _ = env_logger::try_init();
+    let mut pool = LocalPool::new();
+
+    let relay_addr = Multiaddr::empty().with(Protocol::Memory(rand::random::<u64>()));
+    let mut relay = build_relay();
+    let relay_peer_id = *relay.local_peer_id();
+    relay
+        .behaviour_mut()
+        .relay
+        .set_reservation_acl(|_, _| false);
+
+    relay.listen_on(relay_addr.clone()).unwrap();
+    relay.add_external_address(relay_addr.clone());
+    spawn_swarm_on_pool(&pool, relay);
+
+    let client_addr = relay_addr
+        .with(Protocol::P2p(relay_peer_id))
+        .with(Protocol::P2pCircuit);
+    let mut client = build_client();
+    let listener = client.listen_on(client_addr).unwrap();
+
+    assert!(pool.run_until(wait_for_dial(&mut client, relay_peer_id)));
+
+    pool.run_until(async {
+        let mut reservation_failed = false;
+        let mut listener_closed = false;
+        loop {
+            match client.select_next_some().await {
+                SwarmEvent::Behaviour(ClientEvent::Relay(
+                    relay::client::Event::ReservationReqFailed {
+                        relay_peer_id: peer_id,
+                        error:
+                            StreamUpgradeError::Apply(
+                                relay::outbound::hop::ReservationFailedReason::PermissionDenied,
+                            ),
+                        ..
+                    },
+                )) => {
+                    assert_eq!(relay_peer_id, peer_id);
+
+                    reservation_failed = true;
+                    if listener_closed {
+                        break;
+                    }
+                }
+                SwarmEvent::ListenerClosed { listener_id, .. } => {
+                    assert_eq!(listener_id, listener);
+
+                    listener_closed = true;
+                    if reservation_failed {
+                        break;
+                    }
+                }
+                SwarmEvent::Behaviour(ClientEvent::Ping(_)) => {}
+                e => panic!("{e:?}"),
+            }
+        }
+    });
+}
+
+#[test]
+fn circuit_denied_by_acl() {
+    let _ = env_logger::try_init();
+    let mut pool = LocalPool::new();
+
+    let mut src = build_client();
+    let src_peer_id = *src.local_peer_id();
+
+    let relay_addr = Multiaddr::empty().with(Protocol::Memory(rand::random::<u64>()));
+    let mut relay = build_relay();
+    let relay_peer_id = *relay.local_peer_id();
+    relay
+        .behaviour_mut()
+        .relay
+        .set_circuit_acl(move |peer_id, _, _| *peer_id != src_peer_id);
+
+    relay.listen_on(relay_addr.clone()).unwrap();
+    relay.add_external_address(relay_addr.clone());
+    spawn_swarm_on_pool(&pool, relay);
+
+    let mut dst = build_client();
+    let dst_peer_id = *dst.local_peer_id();
+    let dst_addr = relay_addr
+        .with(Protocol::P2p(relay_peer_id))
+        .with(Protocol::P2pCircuit)
+        .with(Protocol::P2p(dst_peer_id));
+
+    dst.listen_on(dst_addr.clone()).unwrap();
+
+    assert!(pool.run_until(wait_for_dial(&mut dst, relay_peer_id)));
+    pool.run_until(wait_for_reservation(
+        &mut dst,
+        dst_addr.clone(),
+        relay_peer_id,
+        false, // No renewal.
+    ));
+    spawn_swarm_on_pool(&pool, dst);
+
+    src.dial(dst_addr).unwrap();
+
+    pool.run_until(async {
+        loop {
+            match src.select_next_some().await {
+                SwarmEvent::Behaviour(ClientEvent::Relay(
+                    relay::client::Event::OutboundCircuitReqFailed {
+                        relay_peer_id: peer_id,
+                        error,
+                    },
+                )) => {
+                    assert_eq!(relay_peer_id, peer_id);
+                    assert!(matches!(
+                        error,
+                        StreamUpgradeError::Apply(
+                            relay::outbound::hop::CircuitFailedReason::PermissionDenied
+                        )
+                    ));
+                    break;
+                }
+                SwarmEvent::Dialing { .. }
+                | SwarmEvent::ConnectionEstablished { .. }
+                | SwarmEvent::Behaviour(ClientEvent::Ping(_)) => {}
+                e => panic!("{e:?}"),
+            }
+        }
+    });
+}
+
 #[test]
 fn connect() {
     let _ = env_logger::try_init();
@@ -211,6 +424,132 @@ fn connect() {
     ));
 }
 
+#[test]
+fn circuit_limit_approaching_is_reported() {
+    let _ = env_logger::try_init();
+    let mut pool = LocalPool::new();
+
+    let relay_addr = Multiaddr::empty().with(Protocol::Memory(rand::random::<u64>()));
+    let mut relay = build_relay_with_config(relay::Config {
+        max_circuit_duration: Duration::from_secs(1),
+        ..Default::default()
+    });
+    let relay_peer_id = *relay.local_peer_id();
+
+    relay.listen_on(relay_addr.clone()).unwrap();
+    relay.add_external_address(relay_addr.clone());
+    spawn_swarm_on_pool(&pool, relay);
+
+    let mut dst = build_client();
+    let dst_peer_id = *dst.local_peer_id();
+    let dst_addr = relay_addr
+        .with(Protocol::P2p(relay_peer_id))
+        .with(Protocol::P2pCircuit)
+        .with(Protocol::P2p(dst_peer_id));
+
+    dst.listen_on(dst_addr.clone()).unwrap();
+
+    assert!(pool.run_until(wait_for_dial(&mut dst, relay_peer_id)));
+
+    pool.run_until(wait_for_reservation(
+        &mut dst,
+        dst_addr.clone(),
+        relay_peer_id,
+        false, // No renewal.
+    ));
+    spawn_swarm_on_pool(&pool, dst);
+
+    let mut src = build_client();
+
+    src.dial(dst_addr).unwrap();
+
+    pool.run_until(async {
+        loop {
+            if let SwarmEvent::Behaviour(ClientEvent::Relay(
+                relay::client::Event::CircuitLimitApproaching {
+                    relay_peer_id: peer,
+                    remote_peer_id,
+                    limit,
+                },
+            )) = src.select_next_some().await
+            {
+                assert_eq!(peer, relay_peer_id);
+                assert_eq!(remote_peer_id, dst_peer_id);
+                assert_eq!(limit, relay::CircuitLimit::Duration);
+                break;
+            }
+        }
+    });
+}
+
+#[test]
+fn inbound_circuits_are_rate_limited_per_source() {
+    let _ = env_logger::try_init();
+    let mut pool = LocalPool::new();
+
+    let relay_addr = Multiaddr::empty().with(Protocol::Memory(rand::random::<u64>()));
+    let mut relay = build_relay();
+    let relay_peer_id = *relay.local_peer_id();
+
+    relay.listen_on(relay_addr.clone()).unwrap();
+    relay.add_external_address(relay_addr.clone());
+    spawn_swarm_on_pool(&pool, relay);
+
+    let mut src = build_client();
+    let src_peer_id = *src.local_peer_id();
+    let mut trusted = build_client();
+    let trusted_peer_id = *trusted.local_peer_id();
+
+    let mut dst = build_client();
+    let dst_peer_id = *dst.local_peer_id();
+    let dst_addr = relay_addr
+        .with(Protocol::P2p(relay_peer_id))
+        .with(Protocol::P2pCircuit)
+        .with(Protocol::P2p(dst_peer_id));
+    dst.behaviour_mut()
+        .relay
+        .circuit_src_per_peer(NonZeroU32::new(1).unwrap(), Duration::from_secs(60 * 60));
+    dst.behaviour_mut().relay.add_trusted_peer(trusted_peer_id);
+
+    dst.listen_on(dst_addr.clone()).unwrap();
+
+    assert!(pool.run_until(wait_for_dial(&mut dst, relay_peer_id)));
+    pool.run_until(wait_for_reservation(
+        &mut dst,
+        dst_addr.clone(),
+        relay_peer_id,
+        false, // No renewal.
+    ));
+
+    for _ in 0..2 {
+        src.dial(dst_addr.clone()).unwrap();
+        trusted.dial(dst_addr.clone()).unwrap();
+    }
+    spawn_swarm_on_pool(&pool, src);
+    spawn_swarm_on_pool(&pool, trusted);
+
+    let mut established = HashMap::<PeerId, usize>::new();
+    let mut denied = HashMap::<PeerId, usize>::new();
+    pool.run_until(async {
+        while established.values().sum::<usize>() + denied.values().sum::<usize>() < 4 {
+            match dst.select_next_some().await {
+                SwarmEvent::Behaviour(ClientEvent::Relay(
+                    relay::client::Event::InboundCircuitEstablished { src_peer_id, .. },
+                )) => *established.entry(src_peer_id).or_default() += 1,
+                SwarmEvent::Behaviour(ClientEvent::Relay(
+                    relay::client::Event::InboundCircuitReqDenied { src_peer_id },
+                )) => *denied.entry(src_peer_id).or_default() += 1,
+                _ => {}
+            }
+        }
+    });
+
+    assert_eq!(established.get(&src_peer_id), Some(&1));
+    assert_eq!(denied.get(&src_peer_id), Some(&1));
+    assert_eq!(established.get(&trusted_peer_id), Some(&2));
+    assert_eq!(denied.get(&trusted_peer_id), None);
+}
+
 async fn connection_established_to(
     swarm: &mut Swarm<Client>,
     relay_peer_id: PeerId,
@@ -306,6 +645,13 @@ fn reuse_connection() {
 }
 
 fn build_relay() -> Swarm<Relay> {
+    build_relay_with_config(relay::Config {
+        reservation_duration: Duration::from_secs(2),
+        ..Default::default()
+    })
+}
+
+fn build_relay_with_config(config: relay::Config) -> Swarm<Relay> {
     let local_key = identity::Keypair::generate_ed25519();
     let local_public_key = local_key.public();
     let local_peer_id = local_public_key.to_peer_id();
@@ -316,13 +662,7 @@ fn build_relay() -> Swarm<Relay> {
         transport,
         Relay {
             ping: ping::Behaviour::new(ping::Config::new()),
-            relay: relay::Behaviour::new(
-                local_peer_id,
-                relay::Config {
-                    reservation_duration: Duration::from_secs(2),
-                    ..Default::default()
-                },
-            ),
+            relay: relay::Behaviour::new(local_peer_id, config),
         },
         local_peer_id,
     )
@@ -345,6 +685,7 @@ fn build_client() -> Swarm<Client> {
         Client {
             ping: ping::Behaviour::new(ping::Config::new()),
             relay: behaviour,
+            keep_alive: keep_alive::Behaviour,
         },
         local_peer_id,
     )
@@ -377,6 +718,7 @@ struct Relay {
 struct Client {
     relay: relay::client::Behaviour,
     ping: ping::Behaviour,
+    keep_alive: keep_alive::Behaviour,
 }
 
 fn spawn_swarm_on_pool<B: NetworkBehaviour + Send>(pool: &LocalPool, swarm: Swarm<B>) {
@@ -399,10 +741,21 @@ async fn wait_for_reservation(
             SwarmEvent::Behaviour(ClientEvent::Relay(
                 relay::client::Event::ReservationReqAccepted {
                     relay_peer_id: peer_id,
-                    renewal,
+                    renewal: false,
+                    ..
+                },
+            )) if relay_peer_id == peer_id && !is_renewal => {
+                reservation_req_accepted = true;
+                if new_listen_addr {
+                    break;
+                }
+            }
+            SwarmEvent::Behaviour(ClientEvent::Relay(
+                relay::client::Event::ReservationRenewed {
+                    relay_peer_id: peer_id,
                     ..
                 },
-            )) if relay_peer_id == peer_id && renewal == is_renewal => {
+            )) if relay_peer_id == peer_id && is_renewal => {
                 reservation_req_accepted = true;
                 if new_listen_addr {
                     break;
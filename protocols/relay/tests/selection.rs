@@ -0,0 +1,485 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+#![cfg(feature = "selection")]
+
+use futures::executor::LocalPool;
+use futures::future::{self, AbortHandle, FutureExt};
+use futures::io::{AsyncRead, AsyncWrite};
+use futures::stream::StreamExt;
+use futures::task::Spawn;
+use futures_timer::Delay;
+use libp2p_core::multiaddr::{Multiaddr, Protocol};
+use libp2p_core::muxing::StreamMuxerBox;
+use libp2p_core::transport::choice::OrTransport;
+use libp2p_core::transport::memory::LinkConditions;
+use libp2p_core::transport::{Boxed, MemoryTransport, Transport};
+use libp2p_core::upgrade;
+use libp2p_identity as identity;
+use libp2p_identity::PeerId;
+use libp2p_identity::PublicKey;
+use libp2p_ping as ping;
+use libp2p_plaintext::PlainText2Config;
+use libp2p_relay as relay;
+use libp2p_swarm::behaviour::toggle::Toggle;
+use libp2p_swarm::{keep_alive, NetworkBehaviour, Swarm, SwarmBuilder, SwarmEvent};
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[test]
+fn selects_relays_accepting_reservations() {
+    let _ = env_logger::try_init();
+    let mut pool = LocalPool::new();
+
+    let (relay_peer_id, relay_addr) = spawn_relay(&pool, build_relay());
+
+    let mut refusing_relay = build_relay();
+    refusing_relay
+        .behaviour_mut()
+        .relay
+        .set_reservation_acl(|_, _| false);
+    let (refusing_relay_peer_id, refusing_relay_addr) = spawn_relay(&pool, refusing_relay);
+
+    let mut client = build_selecting_client(Default::default(), MemoryTransport::default());
+    let selection = &mut client.behaviour_mut().selection;
+    selection.add_candidate(relay_peer_id, relay_addr);
+    selection.add_candidate(refusing_relay_peer_id, refusing_relay_addr);
+
+    pool.run_until(async {
+        let mut relay_selected = false;
+        let mut candidate_failed = false;
+        loop {
+            match client.select_next_some().await {
+                SwarmEvent::Behaviour(SelectingClientEvent::Selection(
+                    relay::client::selection::Event::RelaySelected {
+                        relay_peer_id: peer_id,
+                        ..
+                    },
+                )) => {
+                    assert_eq!(peer_id, relay_peer_id);
+
+                    relay_selected = true;
+                    if candidate_failed {
+                        break;
+                    }
+                }
+                SwarmEvent::Behaviour(SelectingClientEvent::Selection(
+                    relay::client::selection::Event::CandidateFailed {
+                        relay_peer_id: peer_id,
+                    },
+                )) => {
+                    assert_eq!(peer_id, refusing_relay_peer_id);
+
+                    candidate_failed = true;
+                    if relay_selected {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+    });
+
+    assert_eq!(
+        client
+            .behaviour()
+            .selection
+            .selected_relays()
+            .collect::<Vec<_>>(),
+        vec![&relay_peer_id]
+    );
+}
+
+#[test]
+fn selects_relays_with_lowest_rtt() {
+    let _ = env_logger::try_init();
+    let mut pool = LocalPool::new();
+
+    let mut transport = MemoryTransport::default();
+    let mut relays = Vec::new();
+    for latency in [200, 0, 100] {
+        let (relay_peer_id, relay_addr) = spawn_relay(&pool, build_relay());
+        transport = transport.with_link_conditions_to(
+            memory_port(&relay_addr),
+            LinkConditions::default().with_latency(Duration::from_millis(latency)),
+        );
+        relays.push((relay_peer_id, relay_addr));
+    }
+
+    let mut client = build_selecting_client(Default::default(), transport);
+    for (relay_peer_id, relay_addr) in relays.iter().cloned() {
+        client
+            .behaviour_mut()
+            .selection
+            .add_candidate(relay_peer_id, relay_addr);
+    }
+
+    let mut selected = pool.run_until(wait_for_relays_selected(&mut client, 2));
+    selected.sort();
+    let mut expected = vec![relays[1].0, relays[2].0];
+    expected.sort();
+    assert_eq!(selected, expected);
+}
+
+#[test]
+fn replaces_relay_with_faster_candidate() {
+    let _ = env_logger::try_init();
+    let mut pool = LocalPool::new();
+
+    let (slow_relay_peer_id, slow_relay_addr) = spawn_relay(&pool, build_relay());
+    let (fast_relay_peer_id, fast_relay_addr) = spawn_relay(&pool, build_relay());
+
+    let transport = MemoryTransport::default().with_link_conditions_to(
+        memory_port(&slow_relay_addr),
+        LinkConditions::default().with_latency(Duration::from_millis(100)),
+    );
+    let mut client = build_selecting_client(
+        relay::client::selection::Config {
+            max_relays: 1,
+            ..Default::default()
+        },
+        transport,
+    );
+
+    client
+        .behaviour_mut()
+        .selection
+        .add_candidate(slow_relay_peer_id, slow_relay_addr);
+    assert_eq!(
+        pool.run_until(wait_for_relays_selected(&mut client, 1)),
+        vec![slow_relay_peer_id]
+    );
+
+    client
+        .behaviour_mut()
+        .selection
+        .add_candidate(fast_relay_peer_id, fast_relay_addr);
+    pool.run_until(async {
+        let mut fast_selected = false;
+        let mut slow_deselected = false;
+        while !fast_selected || !slow_deselected {
+            match client.select_next_some().await {
+                SwarmEvent::Behaviour(SelectingClientEvent::Selection(
+                    relay::client::selection::Event::RelaySelected { relay_peer_id, .. },
+                )) => {
+                    assert_eq!(relay_peer_id, fast_relay_peer_id);
+                    fast_selected = true;
+                }
+                SwarmEvent::Behaviour(SelectingClientEvent::Selection(
+                    relay::client::selection::Event::RelayDeselected { relay_peer_id },
+                )) => {
+                    assert!(
+                        fast_selected,
+                        "to deselect once the replacement is reserved"
+                    );
+                    assert_eq!(relay_peer_id, slow_relay_peer_id);
+                    slow_deselected = true;
+                }
+                _ => {}
+            }
+        }
+    });
+
+    assert_eq!(
+        client
+            .behaviour()
+            .selection
+            .selected_relays()
+            .collect::<Vec<_>>(),
+        vec![&fast_relay_peer_id]
+    );
+    assert!(client
+        .external_addresses()
+        .all(|addr| addr.iter().any(|p| p == Protocol::P2p(fast_relay_peer_id))));
+}
+
+#[test]
+fn probes_existing_connection() {
+    let _ = env_logger::try_init();
+    let mut pool = LocalPool::new();
+
+    let (relay_peer_id, relay_addr) = spawn_relay(&pool, build_relay());
+
+    let mut client = build_selecting_client(Default::default(), MemoryTransport::default());
+    // Keep the connection open until the relay becomes a candidate.
+    client.behaviour_mut().keep_alive = Some(keep_alive::Behaviour).into();
+    client.dial(relay_addr).unwrap();
+    pool.run_until(async {
+        loop {
+            if let SwarmEvent::ConnectionEstablished { peer_id, .. } =
+                client.select_next_some().await
+            {
+                assert_eq!(peer_id, relay_peer_id);
+                break;
+            }
+        }
+    });
+
+    client
+        .behaviour_mut()
+        .selection
+        .add_candidate_peer(relay_peer_id, vec![]);
+    assert_eq!(
+        pool.run_until(wait_for_relays_selected(&mut client, 1)),
+        vec![relay_peer_id]
+    );
+    assert_eq!(
+        client
+            .network_info()
+            .connection_counters()
+            .num_established(),
+        1
+    );
+}
+
+#[test]
+fn fails_over_to_another_relay() {
+    let _ = env_logger::try_init();
+    let mut pool = LocalPool::new();
+
+    let mut client = build_selecting_client(Default::default(), MemoryTransport::default());
+    let mut relays = HashMap::new();
+    for _ in 0..3 {
+        let relay = build_relay();
+        let relay_peer_id = *relay.local_peer_id();
+        let (relay, relay_addr) = listen_on_memory(relay);
+        relays.insert(relay_peer_id, spawn_abortable_swarm_on_pool(&pool, relay));
+
+        client
+            .behaviour_mut()
+            .selection
+            .add_candidate(relay_peer_id, relay_addr);
+    }
+
+    // Two relays are selected by default.
+    let mut selected = pool.run_until(wait_for_relays_selected(&mut client, 2));
+    assert_eq!(client.external_addresses().count(), 2);
+
+    let failed_relay = selected.pop().unwrap();
+    relays[&failed_relay].abort();
+
+    pool.run_until(async {
+        loop {
+            if let SwarmEvent::Behaviour(SelectingClientEvent::Selection(
+                relay::client::selection::Event::RelayDeselected { relay_peer_id },
+            )) = client.select_next_some().await
+            {
+                assert_eq!(relay_peer_id, failed_relay);
+                break;
+            }
+        }
+    });
+    let replacement = pool.run_until(wait_for_relays_selected(&mut client, 1));
+    assert_ne!(replacement, vec![failed_relay]);
+
+    let mut selected_relays = client
+        .behaviour()
+        .selection
+        .selected_relays()
+        .copied()
+        .collect::<Vec<_>>();
+    selected_relays.sort();
+    let mut expected = [selected, replacement].concat();
+    expected.sort();
+    assert_eq!(selected_relays, expected);
+    assert_eq!(client.external_addresses().count(), 2);
+    assert!(client
+        .external_addresses()
+        .all(|addr| !addr.iter().any(|p| p == Protocol::P2p(failed_relay))));
+}
+
+#[test]
+fn fails_over_after_idling() {
+    let _ = env_logger::try_init();
+    let mut pool = LocalPool::new();
+
+    let mut client = build_selecting_client(
+        relay::client::selection::Config {
+            max_relays: 1,
+            ..Default::default()
+        },
+        MemoryTransport::default(),
+    );
+    let mut relays = HashMap::new();
+    for _ in 0..2 {
+        let (relay, relay_addr) = listen_on_memory(build_relay());
+        let relay_peer_id = *relay.local_peer_id();
+        relays.insert(relay_peer_id, spawn_abortable_swarm_on_pool(&pool, relay));
+
+        client
+            .behaviour_mut()
+            .selection
+            .add_candidate(relay_peer_id, relay_addr);
+    }
+
+    let selected = pool.run_until(wait_for_relays_selected(&mut client, 1));
+
+    // Idle for longer than the connection keep-alive timeout.
+    pool.run_until(async {
+        let mut idle = Delay::new(Duration::from_secs(12)).fuse();
+        loop {
+            futures::select! {
+                _ = client.select_next_some() => {}
+                _ = idle => break,
+            }
+        }
+    });
+
+    relays[&selected[0]].abort();
+    let replacement = pool.run_until(wait_for_relays_selected(&mut client, 1));
+    assert_ne!(replacement, selected);
+    assert_eq!(
+        client
+            .behaviour()
+            .selection
+            .selected_relays()
+            .collect::<Vec<_>>(),
+        vec![&replacement[0]]
+    );
+}
+
+fn build_relay() -> Swarm<Relay> {
+    let local_key = identity::Keypair::generate_ed25519();
+    let local_public_key = local_key.public();
+    let local_peer_id = local_public_key.to_peer_id();
+
+    let transport = upgrade_transport(MemoryTransport::default().boxed(), local_public_key);
+
+    SwarmBuilder::with_async_std_executor(
+        transport,
+        Relay {
+            ping: ping::Behaviour::new(ping::Config::new()),
+            relay: relay::Behaviour::new(
+                local_peer_id,
+                relay::Config {
+                    reservation_duration: Duration::from_secs(2),
+                    ..Default::default()
+                },
+            ),
+        },
+        local_peer_id,
+    )
+    .build()
+}
+
+fn build_selecting_client(
+    config: relay::client::selection::Config,
+    transport: MemoryTransport,
+) -> Swarm<SelectingClient> {
+    let local_key = identity::Keypair::generate_ed25519();
+    let local_public_key = local_key.public();
+    let local_peer_id = local_public_key.to_peer_id();
+
+    let (relay_transport, behaviour) = relay::client::new(local_peer_id);
+    let transport = upgrade_transport(
+        OrTransport::new(relay_transport, transport).boxed(),
+        local_public_key,
+    );
+
+    SwarmBuilder::with_async_std_executor(
+        transport,
+        SelectingClient {
+            relay: behaviour,
+            selection: relay::client::selection::Behaviour::new(config),
+            keep_alive: None.into(),
+        },
+        local_peer_id,
+    )
+    .build()
+}
+
+fn upgrade_transport<StreamSink>(
+    transport: Boxed<StreamSink>,
+    local_public_key: PublicKey,
+) -> Boxed<(PeerId, StreamMuxerBox)>
+where
+    StreamSink: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    transport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(PlainText2Config { local_public_key })
+        .multiplex(libp2p_yamux::Config::default())
+        .boxed()
+}
+
+#[derive(NetworkBehaviour)]
+#[behaviour(prelude = "libp2p_swarm::derive_prelude")]
+struct Relay {
+    relay: relay::Behaviour,
+    ping: ping::Behaviour,
+}
+
+#[derive(NetworkBehaviour)]
+#[behaviour(prelude = "libp2p_swarm::derive_prelude")]
+struct SelectingClient {
+    relay: relay::client::Behaviour,
+    selection: relay::client::selection::Behaviour,
+    keep_alive: Toggle<keep_alive::Behaviour>,
+}
+
+fn listen_on_memory(mut relay: Swarm<Relay>) -> (Swarm<Relay>, Multiaddr) {
+    let relay_addr = Multiaddr::empty().with(Protocol::Memory(rand::random::<u64>()));
+    relay.listen_on(relay_addr.clone()).unwrap();
+    relay.add_external_address(relay_addr.clone());
+    (relay, relay_addr)
+}
+
+fn spawn_relay(pool: &LocalPool, relay: Swarm<Relay>) -> (PeerId, Multiaddr) {
+    let relay_peer_id = *relay.local_peer_id();
+    let (relay, relay_addr) = listen_on_memory(relay);
+    pool.spawner()
+        .spawn_obj(relay.collect::<Vec<_>>().map(|_| ()).boxed().into())
+        .unwrap();
+    (relay_peer_id, relay_addr)
+}
+
+fn spawn_abortable_swarm_on_pool<B: NetworkBehaviour + Send>(
+    pool: &LocalPool,
+    swarm: Swarm<B>,
+) -> AbortHandle {
+    let (swarm, handle) = future::abortable(swarm.collect::<Vec<_>>());
+    pool.spawner()
+        .spawn_obj(swarm.map(|_| ()).boxed().into())
+        .unwrap();
+    handle
+}
+
+fn memory_port(addr: &Multiaddr) -> u64 {
+    match addr.iter().next() {
+        Some(Protocol::Memory(port)) => port,
+        _ => panic!("expected a memory address"),
+    }
+}
+
+async fn wait_for_relays_selected(
+    client: &mut Swarm<SelectingClient>,
+    num_relays: usize,
+) -> Vec<PeerId> {
+    let mut selected = Vec::new();
+    while selected.len() < num_relays {
+        if let SwarmEvent::Behaviour(SelectingClientEvent::Selection(
+            relay::client::selection::Event::RelaySelected { relay_peer_id, .. },
+        )) = client.select_next_some().await
+        {
+            selected.push(relay_peer_id);
+        }
+    }
+    selected
+}
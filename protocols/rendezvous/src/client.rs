@@ -20,10 +20,12 @@
 
 use crate::codec::Message::*;
 use crate::codec::{Cookie, ErrorCode, Message, Namespace, NewRegistration, Registration, Ttl};
+use crate::policy::{RecordPolicy, RecordRejected};
 use futures::future::BoxFuture;
 use futures::future::FutureExt;
 use futures::stream::FuturesUnordered;
 use futures::stream::StreamExt;
+use futures_timer::Delay;
 use libp2p_core::{Endpoint, Multiaddr, PeerRecord};
 use libp2p_identity::{Keypair, PeerId, SigningError};
 use libp2p_request_response::{ProtocolSupport, RequestId};
@@ -31,23 +33,36 @@ use libp2p_swarm::{
     ConnectionDenied, ConnectionId, ExternalAddresses, FromSwarm, NetworkBehaviour, PollParameters,
     THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
 };
-use std::collections::HashMap;
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
 use std::iter;
 use std::task::{Context, Poll};
 use std::time::Duration;
 
+/// The shortest delay before renewing a registration, i.e. half the minimum TTL a server can
+/// grant, such that a TTL of zero does not make us re-register in a tight loop.
+const MIN_RENEWAL_DELAY: Duration = Duration::from_millis(500);
+
 pub struct Behaviour {
     inner: libp2p_request_response::Behaviour<crate::codec::Codec>,
 
     keypair: Keypair,
+    config: Config,
 
     waiting_for_register: HashMap<RequestId, (PeerId, Namespace)>,
-    waiting_for_discovery: HashMap<RequestId, (PeerId, Option<Namespace>)>,
+    /// The register requests in `waiting_for_register` that renew an active registration.
+    renewal_requests: HashSet<RequestId>,
+    /// Our own registrations that are renewed before they expire, by rendezvous node and namespace.
+    active_registrations: HashMap<(PeerId, Namespace), ActiveRegistration>,
+    waiting_for_discovery: HashMap<RequestId, (PeerId, Option<Namespace>, Option<Cookie>)>,
 
     /// Hold addresses of all peers that we have discovered so far.
     ///
     /// Storing these internally allows us to assist the [`libp2p_swarm::Swarm`] in dialing by returning addresses from [`NetworkBehaviour::handle_pending_outbound_connection`].
-    discovered_peers: HashMap<(PeerId, Namespace), Vec<Multiaddr>>,
+    ///
+    /// Next to the addresses, the sequence number of the peer's record is kept to detect stale
+    /// records.
+    discovered_peers: HashMap<(PeerId, Namespace), (u64, Vec<Multiaddr>)>,
 
     /// Tracks the expiry of registrations that we have discovered and stored in `discovered_peers` otherwise we have a memory leak.
     expiring_registrations: FuturesUnordered<BoxFuture<'static, (PeerId, Namespace)>>,
@@ -55,9 +70,88 @@ pub struct Behaviour {
     external_addresses: ExternalAddresses,
 }
 
+/// Configuration for the rendezvous client [`Behaviour`].
+#[derive(Debug, Clone)]
+pub struct Config {
+    renew_registrations: bool,
+    renewal_jitter: Duration,
+    retry_backoff: Duration,
+    max_retry_backoff: Duration,
+    record_policy: RecordPolicy,
+}
+
+impl Config {
+    /// Sets whether our registrations are renewed before their TTL expires.
+    ///
+    /// Registrations are renewed once half of their TTL elapsed, until they are removed via
+    /// [`Behaviour::unregister`].
+    pub fn with_renewal(mut self, renew_registrations: bool) -> Self {
+        self.renew_registrations = renew_registrations;
+        self
+    }
+
+    /// Sets the maximum random delay by which renewals are brought forward, to avoid many
+    /// clients renewing at the same time.
+    ///
+    /// The jitter is capped at a quarter of the registration's TTL.
+    pub fn with_renewal_jitter(mut self, renewal_jitter: Duration) -> Self {
+        self.renewal_jitter = renewal_jitter;
+        self
+    }
+
+    /// Sets the delay before retrying a failed renewal, doubled with every consecutive failure up
+    /// to the given maximum.
+    pub fn with_retry_backoff(mut self, retry_backoff: Duration, max: Duration) -> Self {
+        self.retry_backoff = retry_backoff;
+        self.max_retry_backoff = max;
+        self
+    }
+
+    /// Sets the [`RecordPolicy`] discovered registrations are checked against.
+    ///
+    /// Rejected registrations are reported in [`Event::Discovered`].
+    pub fn with_record_policy(mut self, record_policy: RecordPolicy) -> Self {
+        self.record_policy = record_policy;
+        self
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            renew_registrations: true,
+            renewal_jitter: Duration::from_secs(60),
+            retry_backoff: Duration::from_secs(10),
+            max_retry_backoff: Duration::from_secs(10 * 60),
+            record_policy: Default::default(),
+        }
+    }
+}
+
+/// A registration of ours that is renewed before it expires.
+struct ActiveRegistration {
+    /// The TTL we requested.
+    ttl: Option<Ttl>,
+    /// Fires once the registration is due for renewal or, after a failure, for the next attempt.
+    ///
+    /// `None` while a register request is in flight.
+    renewal: Option<Delay>,
+    /// Fires once the registration expires at the rendezvous node unless renewed before.
+    ///
+    /// `None` until the registration is confirmed and after it expired.
+    expiry: Option<Delay>,
+    /// The number of consecutive failed renewals.
+    failures: u32,
+}
+
 impl Behaviour {
     /// Create a new instance of the rendezvous [`NetworkBehaviour`].
     pub fn new(keypair: Keypair) -> Self {
+        Self::with_config(keypair, Config::default())
+    }
+
+    /// Create a new instance of the rendezvous [`NetworkBehaviour`] with the given [`Config`].
+    pub fn with_config(keypair: Keypair, config: Config) -> Self {
         Self {
             inner: libp2p_request_response::Behaviour::with_codec(
                 crate::codec::Codec::default(),
@@ -65,7 +159,10 @@ impl Behaviour {
                 libp2p_request_response::Config::default(),
             ),
             keypair,
+            config,
             waiting_for_register: Default::default(),
+            renewal_requests: Default::default(),
+            active_registrations: Default::default(),
             waiting_for_discovery: Default::default(),
             discovered_peers: Default::default(),
             expiring_registrations: FuturesUnordered::from_iter(vec![
@@ -79,12 +176,51 @@ impl Behaviour {
     ///
     /// External addresses are either manually added via [`libp2p_swarm::Swarm::add_external_address`] or reported
     /// by other [`NetworkBehaviour`]s via [`ToSwarm::ExternalAddrConfirmed`].
+    ///
+    /// Unless disabled via [`Config::with_renewal`], the registration is renewed before its TTL
+    /// expires, reported through [`Event::RegistrationRenewed`].
     pub fn register(
         &mut self,
         namespace: Namespace,
         rendezvous_node: PeerId,
         ttl: Option<Ttl>,
     ) -> Result<(), RegisterError> {
+        self.send_register(namespace.clone(), rendezvous_node, ttl)?;
+
+        if self.config.renew_registrations {
+            let registration = self
+                .active_registrations
+                .entry((rendezvous_node, namespace))
+                .or_insert(ActiveRegistration {
+                    ttl,
+                    renewal: None,
+                    expiry: None,
+                    failures: 0,
+                });
+            registration.ttl = ttl;
+            registration.renewal = None;
+        }
+
+        Ok(())
+    }
+
+    /// Unregister ourselves from the given namespace with the given rendezvous peer.
+    ///
+    /// This also stops renewing the registration.
+    pub fn unregister(&mut self, namespace: Namespace, rendezvous_node: PeerId) {
+        self.active_registrations
+            .remove(&(rendezvous_node, namespace.clone()));
+
+        self.inner
+            .send_request(&rendezvous_node, Unregister(namespace));
+    }
+
+    fn send_register(
+        &mut self,
+        namespace: Namespace,
+        rendezvous_node: PeerId,
+        ttl: Option<Ttl>,
+    ) -> Result<RequestId, RegisterError> {
         let external_addresses = self.external_addresses.iter().cloned().collect::<Vec<_>>();
         if external_addresses.is_empty() {
             return Err(RegisterError::NoExternalAddresses);
@@ -98,13 +234,7 @@ impl Behaviour {
         self.waiting_for_register
             .insert(req_id, (rendezvous_node, namespace));
 
-        Ok(())
-    }
-
-    /// Unregister ourselves from the given namespace with the given rendezvous peer.
-    pub fn unregister(&mut self, namespace: Namespace, rendezvous_node: PeerId) {
-        self.inner
-            .send_request(&rendezvous_node, Unregister(namespace));
+        Ok(req_id)
     }
 
     /// Discover other peers at a given rendezvous peer.
@@ -114,24 +244,55 @@ impl Behaviour {
     /// A successfully discovery returns a cookie within [`Event::Discovered`].
     /// Such a cookie can be used to only fetch the _delta_ of registrations since
     /// the cookie was acquired.
+    ///
+    /// Rendezvous nodes may return fewer registrations than the given limit, the remaining ones
+    /// are fetched by discovering again with the returned cookie. A failed discovery can be
+    /// resumed with the cookie reported in [`Event::DiscoverFailed`].
     pub fn discover(
         &mut self,
         namespace: Option<Namespace>,
         cookie: Option<Cookie>,
         limit: Option<u64>,
         rendezvous_node: PeerId,
+    ) {
+        self.send_discover(namespace, false, cookie, limit, rendezvous_node)
+    }
+
+    /// Discover other peers at a given rendezvous peer in all namespaces starting with the given
+    /// prefix.
+    ///
+    /// Apart from that, this behaves like [`Behaviour::discover`]. Rendezvous nodes that don't
+    /// support discovery by prefix only return registrations in the namespace equal to the prefix.
+    pub fn discover_prefix(
+        &mut self,
+        prefix: Namespace,
+        cookie: Option<Cookie>,
+        limit: Option<u64>,
+        rendezvous_node: PeerId,
+    ) {
+        self.send_discover(Some(prefix), true, cookie, limit, rendezvous_node)
+    }
+
+    fn send_discover(
+        &mut self,
+        namespace: Option<Namespace>,
+        prefix: bool,
+        cookie: Option<Cookie>,
+        limit: Option<u64>,
+        rendezvous_node: PeerId,
     ) {
         let req_id = self.inner.send_request(
             &rendezvous_node,
             Discover {
                 namespace: namespace.clone(),
-                cookie,
+                prefix,
+                cookie: cookie.clone(),
                 limit,
             },
         );
 
         self.waiting_for_discovery
-            .insert(req_id, (rendezvous_node, namespace));
+            .insert(req_id, (rendezvous_node, namespace, cookie));
     }
 }
 
@@ -150,12 +311,16 @@ pub enum Event {
     Discovered {
         rendezvous_node: PeerId,
         registrations: Vec<Registration>,
+        /// The registrations whose records were rejected by the configured [`RecordPolicy`].
+        rejected: Vec<(Registration, RecordRejected)>,
         cookie: Cookie,
     },
     /// We failed to discover other nodes on the contained rendezvous node.
     DiscoverFailed {
         rendezvous_node: PeerId,
         namespace: Option<Namespace>,
+        /// The cookie the discovery was started with, to resume it from.
+        cookie: Option<Cookie>,
         error: ErrorCode,
     },
     /// We successfully registered with the contained rendezvous node.
@@ -172,6 +337,19 @@ pub enum Event {
     },
     /// The connection details we learned from this node expired.
     Expired { peer: PeerId },
+    /// We successfully renewed our registration with the contained rendezvous node.
+    RegistrationRenewed {
+        rendezvous_node: PeerId,
+        ttl: Ttl,
+        namespace: Namespace,
+    },
+    /// Our registration with the contained rendezvous node expired before it could be renewed.
+    ///
+    /// Renewal is still retried until the registration is removed via [`Behaviour::unregister`].
+    RegistrationExpired {
+        rendezvous_node: PeerId,
+        namespace: Namespace,
+    },
 }
 
 impl NetworkBehaviour for Behaviour {
@@ -293,6 +471,36 @@ impl NetworkBehaviour for Behaviour {
                 }));
             }
 
+            let mut due_for_renewal = Vec::new();
+            for ((rendezvous_node, namespace), registration) in self.active_registrations.iter_mut()
+            {
+                if let Some(expiry) = registration.expiry.as_mut() {
+                    if expiry.poll_unpin(cx).is_ready() {
+                        registration.expiry = None;
+
+                        return Poll::Ready(ToSwarm::GenerateEvent(Event::RegistrationExpired {
+                            rendezvous_node: *rendezvous_node,
+                            namespace: namespace.clone(),
+                        }));
+                    }
+                }
+
+                if let Some(renewal) = registration.renewal.as_mut() {
+                    if renewal.poll_unpin(cx).is_ready() {
+                        registration.renewal = None;
+                        due_for_renewal.push((*rendezvous_node, namespace.clone()));
+                    }
+                }
+            }
+
+            if !due_for_renewal.is_empty() {
+                for (rendezvous_node, namespace) in due_for_renewal {
+                    self.renew(rendezvous_node, namespace);
+                }
+
+                continue; // poll the requests we just sent
+            }
+
             return Poll::Pending;
         }
     }
@@ -312,7 +520,9 @@ impl NetworkBehaviour for Behaviour {
         let addresses = self
             .discovered_peers
             .iter()
-            .filter_map(|((candidate, _), addresses)| (candidate == &peer).then_some(addresses))
+            .filter_map(|((candidate, _), (_, addresses))| {
+                (candidate == &peer).then_some(addresses)
+            })
             .flatten()
             .cloned()
             .collect();
@@ -322,8 +532,114 @@ impl NetworkBehaviour for Behaviour {
 }
 
 impl Behaviour {
+    fn renew(&mut self, rendezvous_node: PeerId, namespace: Namespace) {
+        let Some(ttl) = self
+            .active_registrations
+            .get(&(rendezvous_node, namespace.clone()))
+            .map(|registration| registration.ttl)
+        else {
+            return;
+        };
+
+        match self.send_register(namespace.clone(), rendezvous_node, ttl) {
+            Ok(req_id) => {
+                self.renewal_requests.insert(req_id);
+            }
+            Err(e) => {
+                log::debug!(
+                    "Failed to renew registration in namespace {namespace} with {rendezvous_node}: {e}"
+                );
+                self.on_renewal_failed(rendezvous_node, namespace);
+            }
+        }
+    }
+
+    /// Schedules the next renewal of a registration, if it is still active.
+    fn on_registered(&mut self, rendezvous_node: PeerId, namespace: Namespace, ttl: Ttl) {
+        let Some(registration) = self
+            .active_registrations
+            .get_mut(&(rendezvous_node, namespace))
+        else {
+            return;
+        };
+
+        let ttl = Duration::from_secs(ttl);
+        let max_jitter = self.config.renewal_jitter.min(ttl / 4);
+        let jitter = rand::thread_rng().gen_range(Duration::ZERO..=max_jitter);
+
+        registration.renewal = Some(Delay::new((ttl / 2 - jitter).max(MIN_RENEWAL_DELAY)));
+        registration.expiry = Some(Delay::new(ttl));
+        registration.failures = 0;
+    }
+
+    /// Retries the renewal of a registration with exponential backoff, if it is still active.
+    fn on_renewal_failed(&mut self, rendezvous_node: PeerId, namespace: Namespace) {
+        let Some(registration) = self
+            .active_registrations
+            .get_mut(&(rendezvous_node, namespace))
+        else {
+            return;
+        };
+
+        let backoff = self
+            .config
+            .retry_backoff
+            .saturating_mul(2u32.saturating_pow(registration.failures))
+            .min(self.config.max_retry_backoff);
+
+        registration.renewal = Some(Delay::new(backoff));
+        registration.failures += 1;
+    }
+
+    fn on_register_failed(
+        &mut self,
+        request_id: &RequestId,
+        rendezvous_node: PeerId,
+        namespace: Namespace,
+    ) {
+        if self.renewal_requests.remove(request_id) {
+            self.on_renewal_failed(rendezvous_node, namespace);
+        } else {
+            self.active_registrations
+                .remove(&(rendezvous_node, namespace));
+        }
+    }
+
+    /// Splits the discovered registrations into the ones accepted by the [`RecordPolicy`] and
+    /// the rejected ones.
+    fn check_records(
+        &self,
+        registrations: Vec<Registration>,
+    ) -> (Vec<Registration>, Vec<(Registration, RecordRejected)>) {
+        let mut accepted = Vec::new();
+        let mut rejected = Vec::new();
+
+        for registration in registrations {
+            let latest_seq = self
+                .discovered_peers
+                .get(&(
+                    registration.record.peer_id(),
+                    registration.namespace.clone(),
+                ))
+                .map(|(seq, _)| *seq);
+
+            match self
+                .config
+                .record_policy
+                .check(&registration.record, latest_seq)
+            {
+                Ok(()) => accepted.push(registration),
+                Err(reason) => rejected.push((registration, reason)),
+            }
+        }
+
+        (accepted, rejected)
+    }
+
     fn event_for_outbound_failure(&mut self, req_id: &RequestId) -> Option<Event> {
         if let Some((rendezvous_node, namespace)) = self.waiting_for_register.remove(req_id) {
+            self.on_register_failed(req_id, rendezvous_node, namespace.clone());
+
             return Some(Event::RegisterFailed {
                 rendezvous_node,
                 namespace,
@@ -331,10 +647,13 @@ impl Behaviour {
             });
         };
 
-        if let Some((rendezvous_node, namespace)) = self.waiting_for_discovery.remove(req_id) {
+        if let Some((rendezvous_node, namespace, cookie)) =
+            self.waiting_for_discovery.remove(req_id)
+        {
             return Some(Event::DiscoverFailed {
                 rendezvous_node,
                 namespace,
+                cookie,
                 error: ErrorCode::Unavailable,
             });
         };
@@ -348,6 +667,16 @@ impl Behaviour {
                 if let Some((rendezvous_node, namespace)) =
                     self.waiting_for_register.remove(request_id)
                 {
+                    self.on_registered(rendezvous_node, namespace.clone(), ttl);
+
+                    if self.renewal_requests.remove(request_id) {
+                        return Some(Event::RegistrationRenewed {
+                            rendezvous_node,
+                            ttl,
+                            namespace,
+                        });
+                    }
+
                     return Some(Event::Registered {
                         rendezvous_node,
                         ttl,
@@ -361,6 +690,8 @@ impl Behaviour {
                 if let Some((rendezvous_node, namespace)) =
                     self.waiting_for_register.remove(request_id)
                 {
+                    self.on_register_failed(request_id, rendezvous_node, namespace.clone());
+
                     return Some(Event::RegisterFailed {
                         rendezvous_node,
                         namespace,
@@ -371,16 +702,24 @@ impl Behaviour {
                 None
             }
             DiscoverResponse(Ok((registrations, cookie))) => {
-                if let Some((rendezvous_node, _ns)) = self.waiting_for_discovery.remove(request_id)
+                if let Some((rendezvous_node, _ns, _cookie)) =
+                    self.waiting_for_discovery.remove(request_id)
                 {
+                    let (registrations, rejected) = self.check_records(registrations);
+
                     self.discovered_peers
                         .extend(registrations.iter().map(|registration| {
                             let peer_id = registration.record.peer_id();
                             let namespace = registration.namespace.clone();
 
-                            let addresses = registration.record.addresses().to_vec();
+                            let addresses = self
+                                .config
+                                .record_policy
+                                .honored_addresses(&registration.record)
+                                .cloned()
+                                .collect();
 
-                            ((peer_id, namespace), addresses)
+                            ((peer_id, namespace), (registration.record.seq(), addresses))
                         }));
 
                     self.expiring_registrations
@@ -398,6 +737,7 @@ impl Behaviour {
                     return Some(Event::Discovered {
                         rendezvous_node,
                         registrations,
+                        rejected,
                         cookie,
                     });
                 }
@@ -405,10 +745,13 @@ impl Behaviour {
                 None
             }
             DiscoverResponse(Err(error_code)) => {
-                if let Some((rendezvous_node, ns)) = self.waiting_for_discovery.remove(request_id) {
+                if let Some((rendezvous_node, ns, cookie)) =
+                    self.waiting_for_discovery.remove(request_id)
+                {
                     return Some(Event::DiscoverFailed {
                         rendezvous_node,
                         namespace: ns,
+                        cookie,
                         error: error_code,
                     });
                 }
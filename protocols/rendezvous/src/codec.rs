@@ -43,6 +43,8 @@ pub enum Message {
     Unregister(Namespace),
     Discover {
         namespace: Option<Namespace>,
+        /// Whether `namespace` is a prefix of the namespaces to discover.
+        prefix: bool,
         cookie: Option<Cookie>,
         limit: Option<Limit>,
     },
@@ -71,6 +73,11 @@ impl Namespace {
 
         Ok(Namespace(value))
     }
+
+    /// Returns whether this namespace starts with the given prefix.
+    pub fn starts_with(&self, prefix: &Namespace) -> bool {
+        self.0.starts_with(&prefix.0)
+    }
 }
 
 impl From<Namespace> for String {
@@ -101,10 +108,19 @@ impl PartialEq<Namespace> for str {
 #[error("Namespace is too long")]
 pub struct NamespaceTooLong;
 
+/// The position of a client within the registrations of a rendezvous node.
+///
+/// A cookie does not refer to any state held by the rendezvous node. Discovering again with the
+/// same cookie returns the same registrations again (minus the ones that expired since), thus an
+/// interrupted discovery can be resumed from the last cookie received.
 #[derive(Debug, Eq, PartialEq, Hash, Clone)]
 pub struct Cookie {
+    /// Identifies the registrations `position` refers to.
     id: u64,
+    /// All registrations up to this position were already returned.
+    position: u64,
     namespace: Option<Namespace>,
+    prefix: bool,
 }
 
 impl Cookie {
@@ -114,7 +130,19 @@ impl Cookie {
     pub fn for_namespace(namespace: Namespace) -> Self {
         Self {
             id: rand::thread_rng().next_u64(),
+            position: 0,
             namespace: Some(namespace),
+            prefix: false,
+        }
+    }
+
+    /// Construct a new [`Cookie`] for a given namespace prefix.
+    ///
+    /// This cookie will only be valid for subsequent DISCOVER requests targeting the same prefix.
+    pub fn for_namespace_prefix(prefix: Namespace) -> Self {
+        Self {
+            prefix: true,
+            ..Self::for_namespace(prefix)
         }
     }
 
@@ -122,46 +150,105 @@ impl Cookie {
     pub fn for_all_namespaces() -> Self {
         Self {
             id: rand::random(),
+            position: 0,
             namespace: None,
+            prefix: false,
+        }
+    }
+
+    pub(crate) fn at_position(
+        id: u64,
+        position: u64,
+        namespace: Option<Namespace>,
+        prefix: bool,
+    ) -> Self {
+        Self {
+            id,
+            position,
+            namespace,
+            prefix,
         }
     }
 
+    /// Encodes the cookie as the 8 byte id, followed by the position as 16 hex digits, the
+    /// prefix flag as `0` or `1` and the namespace.
+    ///
+    /// Everything after the id is valid UTF-8, such that clients expecting the legacy encoding
+    /// of an id followed by a namespace can still use the cookie.
     pub fn into_wire_encoding(self) -> Vec<u8> {
         let id_bytes = self.id.to_be_bytes();
+        let position = format!("{:016x}{}", self.position, self.prefix as u8);
         let namespace = self.namespace.map(|ns| ns.0).unwrap_or_default();
 
-        let mut buffer = Vec::with_capacity(id_bytes.len() + namespace.len());
+        let mut buffer = Vec::with_capacity(id_bytes.len() + position.len() + namespace.len());
         buffer.extend_from_slice(&id_bytes);
+        buffer.extend_from_slice(position.as_bytes());
         buffer.extend_from_slice(namespace.as_bytes());
 
         buffer
     }
 
+    /// Decodes a cookie encoded via [`Cookie::into_wire_encoding`].
+    ///
+    /// Cookies in the legacy encoding, i.e. an 8 byte id followed by a namespace, are accepted
+    /// as well and refer to the start of the registrations.
     pub fn from_wire_encoding(mut bytes: Vec<u8>) -> Result<Self, InvalidCookie> {
         // check length early to avoid panic during slicing
         if bytes.len() < 8 {
             return Err(InvalidCookie);
         }
 
-        let namespace = bytes.split_off(8);
+        let rest = String::from_utf8(bytes.split_off(8)).map_err(|_| InvalidCookie)?;
+        let (position, prefix, namespace) = match parse_position(&rest) {
+            Some((position, prefix)) => (position, prefix, &rest[17..]),
+            None => (0, false, rest.as_str()),
+        };
         let namespace = if namespace.is_empty() {
             None
         } else {
-            Some(
-                Namespace::new(String::from_utf8(namespace).map_err(|_| InvalidCookie)?)
-                    .map_err(|_| InvalidCookie)?,
-            )
+            Some(Namespace::new(namespace.to_owned()).map_err(|_| InvalidCookie)?)
         };
+        let id = u64::from_be_bytes(bytes.try_into().map_err(|_| InvalidCookie)?);
 
-        let bytes = <[u8; 8]>::try_from(bytes).map_err(|_| InvalidCookie)?;
-        let id = u64::from_be_bytes(bytes);
-
-        Ok(Self { id, namespace })
+        Ok(Self {
+            id,
+            position,
+            namespace,
+            prefix,
+        })
     }
 
     pub fn namespace(&self) -> Option<&Namespace> {
         self.namespace.as_ref()
     }
+
+    /// Whether this cookie is valid for DISCOVER requests by namespace prefix.
+    pub fn is_prefix(&self) -> bool {
+        self.prefix
+    }
+
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub(crate) fn position(&self) -> u64 {
+        self.position
+    }
+}
+
+/// Parses the position and prefix flag at the start of an encoded cookie, after its id.
+fn parse_position(rest: &str) -> Option<(u64, bool)> {
+    let position = rest.get(..16)?;
+    if !position.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let prefix = match rest.as_bytes().get(16)? {
+        b'0' => false,
+        b'1' => true,
+        _ => return None,
+    };
+
+    Some((u64::from_str_radix(position, 16).ok()?, prefix))
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -378,6 +465,7 @@ impl From<Message> for proto::Message {
             },
             Message::Discover {
                 namespace,
+                prefix,
                 cookie,
                 limit,
             } => proto::Message {
@@ -386,6 +474,7 @@ impl From<Message> for proto::Message {
                     ns: namespace.map(|ns| ns.into()),
                     cookie: cookie.map(|cookie| cookie.into_wire_encoding()),
                     limit,
+                    prefix: prefix.then_some(true),
                 }),
                 register: None,
                 registerResponse: None,
@@ -467,10 +556,17 @@ impl TryFrom<proto::Message> for Message {
             } => Message::RegisterResponse(Ok(ttl.ok_or(ConversionError::MissingTtl)?)),
             proto::Message {
                 type_pb: Some(proto::MessageType::DISCOVER),
-                discover: Some(proto::Discover { ns, limit, cookie }),
+                discover:
+                    Some(proto::Discover {
+                        ns,
+                        limit,
+                        cookie,
+                        prefix,
+                    }),
                 ..
             } => Message::Discover {
                 namespace: ns.map(Namespace::new).transpose()?,
+                prefix: prefix.unwrap_or(false),
                 cookie: cookie.map(Cookie::from_wire_encoding).transpose()?,
                 limit,
             },
@@ -655,12 +751,45 @@ mod tests {
         assert_eq!(parsed, cookie);
     }
 
+    #[test]
+    fn prefix_cookie_wire_encoding_roundtrip() {
+        let cookie = Cookie::at_position(1, 42, Some(Namespace::from_static("foo/")), true);
+
+        let bytes = cookie.clone().into_wire_encoding();
+        let parsed = Cookie::from_wire_encoding(bytes).unwrap();
+
+        assert_eq!(parsed, cookie);
+    }
+
     #[test]
     fn cookie_wire_encoding_length() {
         let cookie = Cookie::for_namespace(Namespace::from_static("foo"));
 
         let bytes = cookie.into_wire_encoding();
 
-        assert_eq!(bytes.len(), 8 + 3)
+        assert_eq!(bytes.len(), 8 + 16 + 1 + 3)
+    }
+
+    #[test]
+    fn cookie_wire_encoding_is_legacy_compatible() {
+        let cookie = Cookie::at_position(1, u64::MAX, Some(Namespace::from_static("foo")), true);
+
+        let bytes = cookie.into_wire_encoding();
+
+        assert!(String::from_utf8(bytes[8..].to_vec()).is_ok());
+    }
+
+    #[test]
+    fn legacy_cookie_refers_to_start() {
+        let mut bytes = 42u64.to_be_bytes().to_vec();
+        bytes.extend_from_slice(b"foo");
+        assert_eq!(bytes.len(), 11);
+
+        let parsed = Cookie::from_wire_encoding(bytes).unwrap();
+
+        assert_eq!(
+            parsed,
+            Cookie::at_position(42, 0, Some(Namespace::from_static("foo")), false)
+        );
     }
 }
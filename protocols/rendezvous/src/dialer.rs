@@ -0,0 +1,367 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Dialing of peers discovered through a rendezvous [`client`](crate::client).
+//!
+//! The dialer [`Behaviour`] is added next to the rendezvous client and fed with its events via
+//! [`Behaviour::on_client_event`]. It dials every discovered peer that is not connected yet,
+//! limiting the number of concurrent dials and backing off from peers that could not be dialed.
+//! Only the addresses honored by the configured [`RecordPolicy`] are dialed.
+
+use crate::client;
+use crate::codec::Registration;
+use crate::policy::RecordPolicy;
+use instant::Instant;
+use libp2p_core::{Endpoint, Multiaddr};
+use libp2p_identity::PeerId;
+use libp2p_swarm::behaviour::{ConnectionClosed, ConnectionEstablished, DialFailure, FromSwarm};
+use libp2p_swarm::dial_opts::{DialOpts, PeerCondition};
+use libp2p_swarm::{
+    dummy, ConnectionDenied, ConnectionId, DialError, NetworkBehaviour, PollParameters, THandler,
+    THandlerInEvent, THandlerOutEvent, ToSwarm,
+};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+/// Configuration for the dialer [`Behaviour`].
+#[derive(Debug, Clone)]
+pub struct Config {
+    max_concurrent_dials: usize,
+    max_connected_peers: Option<usize>,
+    backoff: Duration,
+    max_backoff: Duration,
+    record_policy: RecordPolicy,
+}
+
+impl Config {
+    /// Sets the maximum number of discovered peers that are dialed at the same time.
+    pub fn with_max_concurrent_dials(mut self, max_concurrent_dials: usize) -> Self {
+        self.max_concurrent_dials = max_concurrent_dials;
+        self
+    }
+
+    /// Sets the maximum number of peers connected through this behaviour, after which no more
+    /// discovered peers are dialed.
+    pub fn with_max_connected_peers(mut self, max_connected_peers: usize) -> Self {
+        self.max_connected_peers = Some(max_connected_peers);
+        self
+    }
+
+    /// Sets the time a peer is not dialed again after a failed dial, doubled with every
+    /// consecutive failure up to the given maximum.
+    pub fn with_backoff(mut self, backoff: Duration, max: Duration) -> Self {
+        self.backoff = backoff;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Sets the [`RecordPolicy`] deciding which addresses of discovered peers are dialed.
+    ///
+    /// This should be the policy the rendezvous client is configured with.
+    pub fn with_record_policy(mut self, record_policy: RecordPolicy) -> Self {
+        self.record_policy = record_policy;
+        self
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_concurrent_dials: 8,
+            max_connected_peers: None,
+            backoff: Duration::from_secs(30),
+            max_backoff: Duration::from_secs(10 * 60),
+            record_policy: Default::default(),
+        }
+    }
+}
+
+/// The events produced by the dialer [`Behaviour`].
+#[derive(Debug)]
+pub enum Event {
+    /// Dialing a discovered peer failed. It is not dialed again before the backoff elapsed.
+    DialFailed { peer: PeerId, backoff: Duration },
+}
+
+/// A peer that could not be dialed.
+struct Backoff {
+    until: Instant,
+    failures: u32,
+}
+
+/// [`NetworkBehaviour`] dialing the peers discovered through a rendezvous
+/// [`client::Behaviour`].
+pub struct Behaviour {
+    local_peer_id: PeerId,
+    config: Config,
+
+    /// The discovered peers waiting to be dialed, in the order they were discovered.
+    queued_peers: VecDeque<PeerId>,
+    /// The addresses of the peers in `queued_peers`.
+    addresses: HashMap<PeerId, Vec<Multiaddr>>,
+    /// The dials started by this behaviour.
+    pending_dials: HashMap<ConnectionId, PeerId>,
+    /// All peers we are connected to.
+    connected_peers: HashSet<PeerId>,
+    /// The connected peers that were dialed by this behaviour.
+    dialed_peers: HashSet<PeerId>,
+    backoffs: HashMap<PeerId, Backoff>,
+
+    queued_events: VecDeque<Event>,
+
+    /// Waker of the task polling the behaviour, woken when peers are queued outside of
+    /// [`NetworkBehaviour::poll`].
+    waker: Option<Waker>,
+}
+
+impl Behaviour {
+    pub fn new(local_peer_id: PeerId, config: Config) -> Self {
+        Self {
+            local_peer_id,
+            config,
+            queued_peers: Default::default(),
+            addresses: Default::default(),
+            pending_dials: Default::default(),
+            connected_peers: Default::default(),
+            dialed_peers: Default::default(),
+            backoffs: Default::default(),
+            queued_events: Default::default(),
+            waker: None,
+        }
+    }
+
+    /// Queues the peers discovered in [`client::Event::Discovered`] for dialing and forgets the
+    /// addresses of peers whose registration expired.
+    pub fn on_client_event(&mut self, event: &client::Event) {
+        match event {
+            client::Event::Discovered { registrations, .. } => {
+                for registration in registrations {
+                    self.add_registration(registration);
+                }
+            }
+            client::Event::Expired { peer } => {
+                self.addresses.remove(peer);
+            }
+            _ => {}
+        }
+    }
+
+    /// Queues the peer of the given registration for dialing.
+    ///
+    /// The peer is skipped if it is the local peer, is already connected or being dialed, if it
+    /// is backed off after a failed dial, or if none of its addresses is honored by the
+    /// configured [`RecordPolicy`].
+    pub fn add_registration(&mut self, registration: &Registration) {
+        let peer = registration.record.peer_id();
+
+        if peer == self.local_peer_id
+            || self.connected_peers.contains(&peer)
+            || self.pending_dials.values().any(|p| *p == peer)
+            || self.is_backed_off(&peer)
+        {
+            return;
+        }
+
+        let mut honored_addresses = self
+            .config
+            .record_policy
+            .honored_addresses(&registration.record)
+            .peekable();
+        if honored_addresses.peek().is_none() {
+            return;
+        }
+
+        let addresses = self.addresses.entry(peer).or_default();
+        if addresses.is_empty() {
+            self.queued_peers.push_back(peer);
+        }
+        for address in honored_addresses {
+            if !addresses.contains(address) {
+                addresses.push(address.clone());
+            }
+        }
+
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn is_backed_off(&self, peer: &PeerId) -> bool {
+        self.backoffs
+            .get(peer)
+            .map_or(false, |backoff| backoff.until > Instant::now())
+    }
+
+    fn has_capacity(&self) -> bool {
+        if self.pending_dials.len() >= self.config.max_concurrent_dials {
+            return false;
+        }
+
+        match self.config.max_connected_peers {
+            Some(max) => self.dialed_peers.len() + self.pending_dials.len() < max,
+            None => true,
+        }
+    }
+
+    fn on_dial_failure(
+        &mut self,
+        DialFailure {
+            connection_id,
+            error,
+            ..
+        }: DialFailure,
+    ) {
+        let Some(peer) = self.pending_dials.remove(&connection_id) else {
+            return;
+        };
+
+        // Another dial or connection to the peer is already in progress.
+        if matches!(error, DialError::DialPeerConditionFalse(_)) {
+            return;
+        }
+
+        let failures = self
+            .backoffs
+            .get(&peer)
+            .map_or(0, |backoff| backoff.failures);
+        let backoff = self
+            .config
+            .backoff
+            .saturating_mul(2u32.saturating_pow(failures))
+            .min(self.config.max_backoff);
+
+        self.backoffs.insert(
+            peer,
+            Backoff {
+                until: Instant::now() + backoff,
+                failures: failures + 1,
+            },
+        );
+        self.queued_events
+            .push_back(Event::DialFailed { peer, backoff });
+    }
+}
+
+impl NetworkBehaviour for Behaviour {
+    type ConnectionHandler = dummy::ConnectionHandler;
+    type ToSwarm = Event;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: PeerId,
+        _: &Multiaddr,
+        _: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: PeerId,
+        _: &Multiaddr,
+        _: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm<Self::ConnectionHandler>) {
+        match event {
+            FromSwarm::ConnectionEstablished(ConnectionEstablished {
+                peer_id,
+                connection_id,
+                ..
+            }) => {
+                self.connected_peers.insert(peer_id);
+                self.addresses.remove(&peer_id);
+
+                if self.pending_dials.remove(&connection_id).is_some() {
+                    self.dialed_peers.insert(peer_id);
+                    self.backoffs.remove(&peer_id);
+                }
+            }
+            FromSwarm::ConnectionClosed(ConnectionClosed {
+                peer_id,
+                remaining_established: 0,
+                ..
+            }) => {
+                self.connected_peers.remove(&peer_id);
+                self.dialed_peers.remove(&peer_id);
+            }
+            FromSwarm::DialFailure(dial_failure) => self.on_dial_failure(dial_failure),
+            FromSwarm::ConnectionClosed(_)
+            | FromSwarm::AddressChange(_)
+            | FromSwarm::ListenFailure(_)
+            | FromSwarm::NewListener(_)
+            | FromSwarm::NewListenAddr(_)
+            | FromSwarm::ExpiredListenAddr(_)
+            | FromSwarm::ListenerError(_)
+            | FromSwarm::ListenerClosed(_)
+            | FromSwarm::NewExternalAddrCandidate(_)
+            | FromSwarm::ExternalAddrConfirmed(_)
+            | FromSwarm::ExternalAddrExpired(_) => {}
+        }
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        _: PeerId,
+        _: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        void::unreachable(event)
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+        _: &mut impl PollParameters,
+    ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        if let Some(event) = self.queued_events.pop_front() {
+            return Poll::Ready(ToSwarm::GenerateEvent(event));
+        }
+
+        while self.has_capacity() {
+            let Some(peer) = self.queued_peers.pop_front() else {
+                break;
+            };
+            let Some(addresses) = self.addresses.remove(&peer) else {
+                continue; // expired or connected in the meantime
+            };
+            if self.connected_peers.contains(&peer) || self.is_backed_off(&peer) {
+                continue;
+            }
+
+            let opts = DialOpts::peer_id(peer)
+                .addresses(addresses)
+                .condition(PeerCondition::Disconnected)
+                .build();
+            self.pending_dials.insert(opts.connection_id(), peer);
+
+            return Poll::Ready(ToSwarm::Dial { opts });
+        }
+
+        self.waker = Some(cx.waker().clone());
+
+        Poll::Pending
+    }
+}
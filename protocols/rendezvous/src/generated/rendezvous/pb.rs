@@ -191,6 +191,7 @@ pub struct Discover {
     pub ns: Option<String>,
     pub limit: Option<u64>,
     pub cookie: Option<Vec<u8>>,
+    pub prefix: Option<bool>,
 }
 
 impl<'a> MessageRead<'a> for Discover {
@@ -201,6 +202,7 @@ impl<'a> MessageRead<'a> for Discover {
                 Ok(10) => msg.ns = Some(r.read_string(bytes)?.to_owned()),
                 Ok(16) => msg.limit = Some(r.read_uint64(bytes)?),
                 Ok(26) => msg.cookie = Some(r.read_bytes(bytes)?.to_owned()),
+                Ok(32) => msg.prefix = Some(r.read_bool(bytes)?),
                 Ok(t) => { r.read_unknown(bytes, t)?; }
                 Err(e) => return Err(e),
             }
@@ -215,12 +217,14 @@ impl MessageWrite for Discover {
         + self.ns.as_ref().map_or(0, |m| 1 + sizeof_len((m).len()))
         + self.limit.as_ref().map_or(0, |m| 1 + sizeof_varint(*(m) as u64))
         + self.cookie.as_ref().map_or(0, |m| 1 + sizeof_len((m).len()))
+        + self.prefix.as_ref().map_or(0, |m| 1 + sizeof_varint(*(m) as u64))
     }
 
     fn write_message<W: WriterBackend>(&self, w: &mut Writer<W>) -> Result<()> {
         if let Some(ref s) = self.ns { w.write_with_tag(10, |w| w.write_string(&**s))?; }
         if let Some(ref s) = self.limit { w.write_with_tag(16, |w| w.write_uint64(*s))?; }
         if let Some(ref s) = self.cookie { w.write_with_tag(26, |w| w.write_bytes(&**s))?; }
+        if let Some(ref s) = self.prefix { w.write_with_tag(32, |w| w.write_bool(*s))?; }
         Ok(())
     }
 }
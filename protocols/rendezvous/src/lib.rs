@@ -23,9 +23,11 @@
 #![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
 
 pub use self::codec::{Cookie, ErrorCode, Namespace, NamespaceTooLong, Registration, Ttl};
+pub use self::policy::{RecordPolicy, RecordRejected};
 use libp2p_swarm::StreamProtocol;
 
 mod codec;
+mod policy;
 
 /// If unspecified, rendezvous nodes should assume a TTL of 2h.
 ///
@@ -50,4 +52,5 @@ pub const MAX_NAMESPACE: usize = 255;
 pub(crate) const PROTOCOL_IDENT: StreamProtocol = StreamProtocol::new("/rendezvous/1.0.0");
 
 pub mod client;
+pub mod dialer;
 pub mod server;
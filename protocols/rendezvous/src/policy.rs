@@ -0,0 +1,148 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use instant::SystemTime;
+use libp2p_core::multiaddr::Protocol;
+use libp2p_core::{Multiaddr, PeerRecord};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Sequence numbers below this are UNIX timestamps in seconds (i.e. before the year 5138), those
+/// above in nanoseconds (i.e. more than 100 seconds after the UNIX epoch).
+const MAX_SECONDS_SEQ: u64 = 100_000_000_000;
+
+/// Policy on which signed peer records of registrations are accepted.
+///
+/// The signature of a record is always verified. Records that are not signed by the peer they
+/// describe can't be decoded and are never accepted.
+///
+/// By default, all records with a valid signature are accepted.
+#[derive(Debug, Clone)]
+pub struct RecordPolicy {
+    max_age: Option<Duration>,
+    accept_stale: bool,
+    honor_relayed_addresses: bool,
+}
+
+impl RecordPolicy {
+    /// Rejects records created more than the given duration ago.
+    ///
+    /// The creation time is taken from the sequence number of the record, which [`PeerRecord::new`]
+    /// sets to the current UNIX timestamp in seconds. Other implementations, e.g. go-libp2p, use
+    /// the UNIX timestamp in nanoseconds, which is told apart by its magnitude.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Sets whether records are accepted that are older than the last record of the same peer in
+    /// the same namespace, e.g. because they are replayed.
+    pub fn with_stale_records(mut self, accept_stale: bool) -> Self {
+        self.accept_stale = accept_stale;
+        self
+    }
+
+    /// Sets whether relayed addresses in records are honored.
+    ///
+    /// If not, records consisting of relayed addresses only are rejected and relayed addresses are
+    /// not used to dial discovered peers. As records are signed, relayed addresses can't be
+    /// removed from them though.
+    pub fn with_relayed_addresses(mut self, honor_relayed_addresses: bool) -> Self {
+        self.honor_relayed_addresses = honor_relayed_addresses;
+        self
+    }
+
+    /// Checks the record against this policy, given the sequence number of the last accepted
+    /// record of the same peer in the same namespace.
+    pub(crate) fn check(
+        &self,
+        record: &PeerRecord,
+        latest_seq: Option<u64>,
+    ) -> Result<(), RecordRejected> {
+        if let Some(max_age) = self.max_age {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            let age = now.saturating_sub(created_at(record.seq()));
+            if age > max_age {
+                return Err(RecordRejected::TooOld { age, max_age });
+            }
+        }
+
+        if let Some(latest_seq) = latest_seq {
+            if !self.accept_stale && record.seq() < latest_seq {
+                return Err(RecordRejected::Stale {
+                    seq: record.seq(),
+                    latest_seq,
+                });
+            }
+        }
+
+        if !record.addresses().is_empty() && self.honored_addresses(record).next().is_none() {
+            return Err(RecordRejected::OnlyRelayedAddresses);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the addresses of the record that are honored by this policy.
+    pub(crate) fn honored_addresses<'a>(
+        &'a self,
+        record: &'a PeerRecord,
+    ) -> impl Iterator<Item = &'a Multiaddr> + 'a {
+        record
+            .addresses()
+            .iter()
+            .filter(|address| self.honor_relayed_addresses || !is_relayed(address))
+    }
+}
+
+impl Default for RecordPolicy {
+    fn default() -> Self {
+        Self {
+            max_age: None,
+            accept_stale: true,
+            honor_relayed_addresses: true,
+        }
+    }
+}
+
+/// The reason a signed peer record was rejected by a [`RecordPolicy`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum RecordRejected {
+    #[error("Record is {age:?} old; max {max_age:?}")]
+    TooOld { age: Duration, max_age: Duration },
+    #[error("Record (seq {seq}) is older than the last record of the peer (seq {latest_seq})")]
+    Stale { seq: u64, latest_seq: u64 },
+    #[error("Record only contains relayed addresses")]
+    OnlyRelayedAddresses,
+}
+
+/// Returns the creation time of a record since the UNIX epoch, given its sequence number.
+fn created_at(seq: u64) -> Duration {
+    if seq < MAX_SECONDS_SEQ {
+        Duration::from_secs(seq)
+    } else {
+        Duration::from_nanos(seq)
+    }
+}
+
+fn is_relayed(address: &Multiaddr) -> bool {
+    address.iter().any(|p| p == Protocol::P2pCircuit)
+}
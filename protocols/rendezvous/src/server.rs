@@ -19,11 +19,13 @@
 // DEALINGS IN THE SOFTWARE.
 
 use crate::codec::{Cookie, ErrorCode, Message, Namespace, NewRegistration, Registration, Ttl};
+use crate::policy::{RecordPolicy, RecordRejected};
 use crate::{MAX_TTL, MIN_TTL};
 use bimap::BiMap;
 use futures::future::BoxFuture;
 use futures::stream::FuturesUnordered;
 use futures::{FutureExt, StreamExt};
+use instant::SystemTime;
 use libp2p_core::{Endpoint, Multiaddr};
 use libp2p_identity::PeerId;
 use libp2p_request_response::ProtocolSupport;
@@ -32,12 +34,18 @@ use libp2p_swarm::{
     ConnectionDenied, ConnectionId, NetworkBehaviour, PollParameters, THandler, THandlerInEvent,
     THandlerOutEvent, ToSwarm,
 };
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io;
 use std::iter;
 use std::iter::FromIterator;
+use std::ops::Bound;
 use std::task::{ready, Context, Poll};
 use std::time::Duration;
 
+pub use store::{FileStore, MemoryStore, Store, StoredRegistration};
+
+mod store;
+
 pub struct Behaviour {
     inner: libp2p_request_response::Behaviour<crate::codec::Codec>,
 
@@ -47,6 +55,11 @@ pub struct Behaviour {
 pub struct Config {
     min_ttl: Ttl,
     max_ttl: Ttl,
+    max_discover_limit: u64,
+    max_registrations_per_peer: Option<usize>,
+    namespace_policies: HashMap<Namespace, NamespacePolicy>,
+    default_namespace_policy: NamespacePolicy,
+    record_policy: RecordPolicy,
 }
 
 impl Config {
@@ -59,6 +72,42 @@ impl Config {
         self.max_ttl = max_ttl;
         self
     }
+
+    /// Sets the maximum number of registrations returned for a single DISCOVER request.
+    ///
+    /// Requests without a limit or with a higher one are capped at this limit, the remaining
+    /// registrations can be fetched with the returned cookie.
+    pub fn with_max_discover_limit(mut self, max_discover_limit: u64) -> Self {
+        self.max_discover_limit = max_discover_limit;
+        self
+    }
+
+    /// Sets the maximum number of namespaces a single peer may be registered in.
+    pub fn with_max_registrations_per_peer(mut self, max_registrations: usize) -> Self {
+        self.max_registrations_per_peer = Some(max_registrations);
+        self
+    }
+
+    /// Sets the [`NamespacePolicy`] for registrations in the given namespace.
+    pub fn with_namespace_policy(mut self, namespace: Namespace, policy: NamespacePolicy) -> Self {
+        self.namespace_policies.insert(namespace, policy);
+        self
+    }
+
+    /// Sets the [`NamespacePolicy`] for registrations in all namespaces without a policy of
+    /// their own.
+    ///
+    /// By default, any peer may register in these namespaces.
+    pub fn with_default_namespace_policy(mut self, policy: NamespacePolicy) -> Self {
+        self.default_namespace_policy = policy;
+        self
+    }
+
+    /// Sets the [`RecordPolicy`] the records of registrations are checked against.
+    pub fn with_record_policy(mut self, record_policy: RecordPolicy) -> Self {
+        self.record_policy = record_policy;
+        self
+    }
 }
 
 impl Default for Config {
@@ -66,13 +115,75 @@ impl Default for Config {
         Self {
             min_ttl: MIN_TTL,
             max_ttl: MAX_TTL,
+            max_discover_limit: DEFAULT_MAX_DISCOVER_LIMIT,
+            max_registrations_per_peer: None,
+            namespace_policies: Default::default(),
+            default_namespace_policy: Default::default(),
+            record_policy: Default::default(),
         }
     }
 }
 
+/// Restrictions on the registrations within a namespace.
+#[derive(Debug, Clone, Default)]
+pub struct NamespacePolicy {
+    allowed_peers: Option<HashSet<PeerId>>,
+    max_registrations: Option<usize>,
+    min_ttl: Option<Ttl>,
+    max_ttl: Option<Ttl>,
+}
+
+impl NamespacePolicy {
+    /// Only allows the given peers to register in the namespace.
+    pub fn with_allowed_peers(mut self, peers: impl IntoIterator<Item = PeerId>) -> Self {
+        self.allowed_peers = Some(peers.into_iter().collect());
+        self
+    }
+
+    /// Denies all registrations in the namespace.
+    pub fn deny_all(self) -> Self {
+        self.with_allowed_peers(iter::empty())
+    }
+
+    /// Sets the maximum number of registrations in the namespace.
+    pub fn with_max_registrations(mut self, max_registrations: usize) -> Self {
+        self.max_registrations = Some(max_registrations);
+        self
+    }
+
+    /// Sets the minimum TTL of registrations in the namespace, overriding
+    /// [`Config::with_min_ttl`].
+    pub fn with_min_ttl(mut self, min_ttl: Ttl) -> Self {
+        self.min_ttl = Some(min_ttl);
+        self
+    }
+
+    /// Sets the maximum TTL of registrations in the namespace, overriding
+    /// [`Config::with_max_ttl`].
+    pub fn with_max_ttl(mut self, max_ttl: Ttl) -> Self {
+        self.max_ttl = Some(max_ttl);
+        self
+    }
+}
+
 impl Behaviour {
     /// Create a new instance of the rendezvous [`NetworkBehaviour`].
     pub fn new(config: Config) -> Self {
+        Self::with_registrations(Registrations::with_config(config))
+    }
+
+    /// Create a new instance of the rendezvous [`NetworkBehaviour`] whose registrations are
+    /// persisted in the given [`Store`].
+    ///
+    /// Registrations that are still valid are loaded from the store, thus a restarted rendezvous
+    /// point keeps serving them until they expire.
+    pub fn with_store(config: Config, store: impl Store) -> io::Result<Self> {
+        Ok(Self::with_registrations(Registrations::with_store(
+            config, store,
+        )?))
+    }
+
+    fn with_registrations(registrations: Registrations) -> Self {
         Self {
             inner: libp2p_request_response::Behaviour::with_codec(
                 crate::codec::Codec::default(),
@@ -80,7 +191,7 @@ impl Behaviour {
                 libp2p_request_response::Config::default(),
             ),
 
-            registrations: Registrations::with_config(config),
+            registrations,
         }
     }
 }
@@ -106,10 +217,19 @@ pub enum Event {
         namespace: Namespace,
         error: ErrorCode,
     },
+    /// We denied a registration from a peer because of the configured access control or quotas.
+    RegistrationDenied {
+        peer: PeerId,
+        namespace: Namespace,
+        reason: RegistrationDenied,
+    },
     /// A peer successfully unregistered with us.
     PeerUnregistered { peer: PeerId, namespace: Namespace },
     /// A registration from a peer expired.
     RegistrationExpired(Registration),
+    /// The [`Store`] failed to persist a change of the registrations, thus it may not be restored
+    /// after a restart.
+    StoreFailed(io::Error),
 }
 
 impl NetworkBehaviour for Behaviour {
@@ -160,6 +280,10 @@ impl NetworkBehaviour for Behaviour {
         cx: &mut Context<'_>,
         params: &mut impl PollParameters,
     ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        if let Some(error) = self.registrations.store_errors.pop_front() {
+            return Poll::Ready(ToSwarm::GenerateEvent(Event::StoreFailed(error)));
+        }
+
         if let Poll::Ready(ExpiredRegistration(registration)) = self.registrations.poll(cx) {
             return Poll::Ready(ToSwarm::GenerateEvent(Event::RegistrationExpired(
                 registration,
@@ -268,7 +392,7 @@ fn handle_request(
 
                     Some((event, Some(response)))
                 }
-                Err(TtlOutOfRange::TooLong { .. }) | Err(TtlOutOfRange::TooShort { .. }) => {
+                Err(RegistrationDenied::TtlOutOfRange(_)) => {
                     let error = ErrorCode::InvalidTtl;
 
                     let response = Message::RegisterResponse(Err(error));
@@ -279,6 +403,17 @@ fn handle_request(
                         error,
                     };
 
+                    Some((event, Some(response)))
+                }
+                Err(reason) => {
+                    let response = Message::RegisterResponse(Err(reason.to_error_code()));
+
+                    let event = Event::RegistrationDenied {
+                        peer: peer_id,
+                        namespace,
+                        reason,
+                    };
+
                     Some((event, Some(response)))
                 }
             }
@@ -295,60 +430,91 @@ fn handle_request(
         }
         Message::Discover {
             namespace,
+            prefix,
             cookie,
             limit,
-        } => match registrations.get(namespace, cookie, limit) {
-            Ok((registrations, cookie)) => {
-                let discovered = registrations.cloned().collect::<Vec<_>>();
+        } => {
+            let result = match namespace {
+                Some(prefix_namespace) if prefix => registrations
+                    .get_by_prefix(prefix_namespace, cookie, limit)
+                    .map(|(registrations, cookie)| {
+                        (registrations.cloned().collect::<Vec<_>>(), cookie)
+                    }),
+                namespace => registrations
+                    .get(namespace, cookie, limit)
+                    .map(|(registrations, cookie)| (registrations.cloned().collect(), cookie)),
+            };
 
-                let response = Message::DiscoverResponse(Ok((discovered.clone(), cookie)));
+            match result {
+                Ok((discovered, cookie)) => {
+                    let response = Message::DiscoverResponse(Ok((discovered.clone(), cookie)));
 
-                let event = Event::DiscoverServed {
-                    enquirer: peer_id,
-                    registrations: discovered,
-                };
+                    let event = Event::DiscoverServed {
+                        enquirer: peer_id,
+                        registrations: discovered,
+                    };
 
-                Some((event, Some(response)))
-            }
-            Err(_) => {
-                let error = ErrorCode::InvalidCookie;
+                    Some((event, Some(response)))
+                }
+                Err(_) => {
+                    let error = ErrorCode::InvalidCookie;
 
-                let response = Message::DiscoverResponse(Err(error));
+                    let response = Message::DiscoverResponse(Err(error));
 
-                let event = Event::DiscoverNotServed {
-                    enquirer: peer_id,
-                    error,
-                };
+                    let event = Event::DiscoverNotServed {
+                        enquirer: peer_id,
+                        error,
+                    };
 
-                Some((event, Some(response)))
+                    Some((event, Some(response)))
+                }
             }
-        },
+        }
         Message::RegisterResponse(_) => None,
         Message::DiscoverResponse(_) => None,
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Hash, Copy, Clone)]
+/// Registrations are numbered in the order they are added, which is the order they are
+/// discovered in.
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone)]
 struct RegistrationId(u64);
 
-impl RegistrationId {
-    fn new() -> Self {
-        Self(rand::random())
-    }
-}
+/// By default, a single DISCOVER request returns at most this many registrations.
+const DEFAULT_MAX_DISCOVER_LIMIT: u64 = 1000;
 
 #[derive(Debug, PartialEq)]
 struct ExpiredRegistration(Registration);
 
 pub struct Registrations {
     registrations_for_peer: BiMap<(PeerId, Namespace), RegistrationId>,
-    registrations: HashMap<RegistrationId, Registration>,
-    cookies: HashMap<Cookie, HashSet<RegistrationId>>,
+    registrations: BTreeMap<RegistrationId, Registration>,
+    /// Identifies this set of registrations in cookies, such that cookies of another instance,
+    /// e.g. from before a restart, are not applied to the positions of this one.
+    id: u64,
+    next_registration_id: u64,
     min_ttl: Ttl,
     max_ttl: Ttl,
+    max_discover_limit: u64,
+    max_registrations_per_peer: Option<usize>,
+    namespace_policies: HashMap<Namespace, NamespacePolicy>,
+    default_namespace_policy: NamespacePolicy,
+    record_policy: RecordPolicy,
+    /// The number of registrations of each peer, across all namespaces.
+    registrations_per_peer: HashMap<PeerId, usize>,
+    /// The number of registrations in each namespace.
+    registrations_per_namespace: HashMap<Namespace, usize>,
     next_expiry: FuturesUnordered<BoxFuture<'static, RegistrationId>>,
+    store: Box<dyn Store>,
+    /// The errors of the store not yet reported.
+    store_errors: VecDeque<io::Error>,
+    /// Fires when the changes of the store are to be flushed, if there are any.
+    next_flush: Option<futures_timer::Delay>,
 }
 
+/// How long the changes of a [`Store`] are buffered before it is flushed.
+const STORE_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Debug, thiserror::Error)]
 pub enum TtlOutOfRange {
     #[error("Requested TTL ({requested}s) is too long; max {bound}s")]
@@ -357,6 +523,34 @@ pub enum TtlOutOfRange {
     TooShort { bound: Ttl, requested: Ttl },
 }
 
+/// The reason a registration was not accepted.
+#[derive(Debug, thiserror::Error)]
+pub enum RegistrationDenied {
+    #[error(transparent)]
+    TtlOutOfRange(#[from] TtlOutOfRange),
+    #[error("Peer is not allowed to register in this namespace")]
+    NotAllowed,
+    #[error("Peer already has the maximum of {limit} registrations")]
+    TooManyRegistrationsForPeer { limit: usize },
+    #[error("Namespace already has the maximum of {limit} registrations")]
+    TooManyRegistrationsInNamespace { limit: usize },
+    #[error("Record was rejected")]
+    RecordRejected(#[from] RecordRejected),
+}
+
+impl RegistrationDenied {
+    /// The error code reported to the registering peer.
+    pub fn to_error_code(&self) -> ErrorCode {
+        match self {
+            RegistrationDenied::TtlOutOfRange(_) => ErrorCode::InvalidTtl,
+            RegistrationDenied::NotAllowed => ErrorCode::NotAuthorized,
+            RegistrationDenied::TooManyRegistrationsForPeer { .. }
+            | RegistrationDenied::TooManyRegistrationsInNamespace { .. } => ErrorCode::Unavailable,
+            RegistrationDenied::RecordRejected(_) => ErrorCode::InvalidSignedPeerRecord,
+        }
+    }
+}
+
 impl Default for Registrations {
     fn default() -> Self {
         Registrations::with_config(Config::default())
@@ -368,76 +562,220 @@ impl Registrations {
         Self {
             registrations_for_peer: Default::default(),
             registrations: Default::default(),
+            id: rand::random(),
+            next_registration_id: 1,
             min_ttl: config.min_ttl,
             max_ttl: config.max_ttl,
-            cookies: Default::default(),
+            max_discover_limit: config.max_discover_limit,
+            max_registrations_per_peer: config.max_registrations_per_peer,
+            namespace_policies: config.namespace_policies,
+            default_namespace_policy: config.default_namespace_policy,
+            record_policy: config.record_policy,
+            registrations_per_peer: Default::default(),
+            registrations_per_namespace: Default::default(),
             next_expiry: FuturesUnordered::from_iter(vec![futures::future::pending().boxed()]),
+            store: Box::<MemoryStore>::default(),
+            store_errors: Default::default(),
+            next_flush: None,
         }
     }
 
+    /// Creates the registrations backed by the given [`Store`], restoring all registrations from
+    /// it that did not expire yet.
+    ///
+    /// The TTL of restored registrations is the time remaining until they expire.
+    pub fn with_store(config: Config, mut store: impl Store) -> io::Result<Self> {
+        let stored_registrations = store.load()?;
+        let now = SystemTime::now();
+
+        let mut registrations = Self::with_config(config);
+
+        for StoredRegistration {
+            mut registration,
+            expires_at,
+        } in stored_registrations
+        {
+            let remaining = expires_at.duration_since(now).unwrap_or_default();
+            if remaining.as_secs() == 0 {
+                continue;
+            }
+
+            registration.ttl = remaining.as_secs();
+            registrations.insert(registration, remaining);
+        }
+        registrations.store = Box::new(store);
+
+        Ok(registrations)
+    }
+
     pub fn add(
         &mut self,
         new_registration: NewRegistration,
-    ) -> Result<Registration, TtlOutOfRange> {
+    ) -> Result<Registration, RegistrationDenied> {
+        let peer = new_registration.record.peer_id();
+        let namespace = &new_registration.namespace;
+        let policy = self
+            .namespace_policies
+            .get(namespace)
+            .unwrap_or(&self.default_namespace_policy);
+
+        if let Some(allowed_peers) = policy.allowed_peers.as_ref() {
+            if !allowed_peers.contains(&peer) {
+                return Err(RegistrationDenied::NotAllowed);
+            }
+        }
+
+        let max_ttl = policy.max_ttl.unwrap_or(self.max_ttl);
+        let min_ttl = policy.min_ttl.unwrap_or(self.min_ttl);
         let ttl = new_registration.effective_ttl();
-        if ttl > self.max_ttl {
+        if ttl > max_ttl {
             return Err(TtlOutOfRange::TooLong {
-                bound: self.max_ttl,
+                bound: max_ttl,
                 requested: ttl,
-            });
+            }
+            .into());
         }
-        if ttl < self.min_ttl {
+        if ttl < min_ttl {
             return Err(TtlOutOfRange::TooShort {
-                bound: self.min_ttl,
+                bound: min_ttl,
                 requested: ttl,
-            });
+            }
+            .into());
         }
 
-        let namespace = new_registration.namespace;
-        let registration_id = RegistrationId::new();
-
-        if let Some(old_registration) = self
+        let existing_registration = self
             .registrations_for_peer
-            .get_by_left(&(new_registration.record.peer_id(), namespace.clone()))
-        {
-            self.registrations.remove(old_registration);
+            .get_by_left(&(peer, namespace.clone()))
+            .and_then(|id| self.registrations.get(id));
+        self.record_policy.check(
+            &new_registration.record,
+            existing_registration.map(|registration| registration.record.seq()),
+        )?;
+
+        // Renewing an existing registration never exceeds a quota.
+        if existing_registration.is_none() {
+            if let Some(limit) = self.max_registrations_per_peer {
+                if self.registrations_per_peer.get(&peer).copied().unwrap_or(0) >= limit {
+                    return Err(RegistrationDenied::TooManyRegistrationsForPeer { limit });
+                }
+            }
+            if let Some(limit) = policy.max_registrations {
+                if self
+                    .registrations_per_namespace
+                    .get(namespace)
+                    .copied()
+                    .unwrap_or(0)
+                    >= limit
+                {
+                    return Err(RegistrationDenied::TooManyRegistrationsInNamespace { limit });
+                }
+            }
         }
 
-        self.registrations_for_peer.insert(
-            (new_registration.record.peer_id(), namespace.clone()),
-            registration_id,
-        );
-
         let registration = Registration {
-            namespace,
+            namespace: new_registration.namespace,
             record: new_registration.record,
             ttl,
         };
-        self.registrations
-            .insert(registration_id, registration.clone());
+        let expires_in = Duration::from_secs(ttl);
+
+        let result = self.store.insert(StoredRegistration {
+            registration: registration.clone(),
+            expires_at: SystemTime::now() + expires_in,
+        });
+        self.on_store_changed(result);
+
+        self.insert(registration.clone(), expires_in);
+
+        Ok(registration)
+    }
+
+    /// Adds the registration to the index and schedules its expiry.
+    fn insert(&mut self, registration: Registration, expires_in: Duration) {
+        let registration_id = RegistrationId(self.next_registration_id);
+        self.next_registration_id += 1;
+        let key = (
+            registration.record.peer_id(),
+            registration.namespace.clone(),
+        );
+
+        if let Some(old_registration) = self.registrations_for_peer.get_by_left(&key) {
+            self.registrations.remove(old_registration);
+        } else {
+            *self.registrations_per_peer.entry(key.0).or_default() += 1;
+            *self
+                .registrations_per_namespace
+                .entry(key.1.clone())
+                .or_default() += 1;
+        }
+
+        self.registrations_for_peer.insert(key, registration_id);
+        self.registrations.insert(registration_id, registration);
 
-        let next_expiry = futures_timer::Delay::new(Duration::from_secs(ttl))
+        let next_expiry = futures_timer::Delay::new(expires_in)
             .map(move |_| registration_id)
             .boxed();
 
         self.next_expiry.push(next_expiry);
-
-        Ok(registration)
     }
 
     pub fn remove(&mut self, namespace: Namespace, peer_id: PeerId) {
+        let result = self.store.remove(&namespace, peer_id);
+        self.on_store_changed(result);
+
         let reggo_to_remove = self
             .registrations_for_peer
             .remove_by_left(&(peer_id, namespace));
 
-        if let Some((_, reggo_to_remove)) = reggo_to_remove {
+        if let Some(((peer_id, namespace), reggo_to_remove)) = reggo_to_remove {
             self.registrations.remove(&reggo_to_remove);
+            self.on_removed(peer_id, namespace);
         }
     }
 
+    /// Updates the registration counts after the registration of a peer in a namespace was
+    /// removed.
+    fn on_removed(&mut self, peer_id: PeerId, namespace: Namespace) {
+        if let Some(count) = self.registrations_per_peer.get_mut(&peer_id) {
+            *count -= 1;
+            if *count == 0 {
+                self.registrations_per_peer.remove(&peer_id);
+            }
+        }
+        if let Some(count) = self.registrations_per_namespace.get_mut(&namespace) {
+            *count -= 1;
+            if *count == 0 {
+                self.registrations_per_namespace.remove(&namespace);
+            }
+        }
+    }
+
+    /// Returns the registrations in the given namespace, or in all namespaces if none is given,
+    /// that were added after the position of the given cookie.
     pub fn get(
-        &mut self,
+        &self,
+        discover_namespace: Option<Namespace>,
+        cookie: Option<Cookie>,
+        limit: Option<u64>,
+    ) -> Result<(impl Iterator<Item = &Registration> + '_, Cookie), CookieNamespaceMismatch> {
+        self.discover(discover_namespace, false, cookie, limit)
+    }
+
+    /// Returns the registrations in all namespaces starting with the given prefix that were
+    /// added after the position of the given cookie.
+    pub fn get_by_prefix(
+        &self,
+        prefix: Namespace,
+        cookie: Option<Cookie>,
+        limit: Option<u64>,
+    ) -> Result<(impl Iterator<Item = &Registration> + '_, Cookie), CookieNamespaceMismatch> {
+        self.discover(Some(prefix), true, cookie, limit)
+    }
+
+    fn discover(
+        &self,
         discover_namespace: Option<Namespace>,
+        prefix: bool,
         cookie: Option<Cookie>,
         limit: Option<u64>,
     ) -> Result<(impl Iterator<Item = &Registration> + '_, Cookie), CookieNamespaceMismatch> {
@@ -450,44 +788,49 @@ impl Registrations {
             (Some(namespace), Some(cookie_namespace)) if namespace != cookie_namespace => {
                 return Err(CookieNamespaceMismatch)
             }
+            // discover by prefix but cookie is for an exact namespace or vice versa? => bad
+            (Some(_), Some(_)) if cookie.as_ref().map(Cookie::is_prefix) != Some(prefix) => {
+                return Err(CookieNamespaceMismatch)
+            }
             // every other combination is fine
             _ => {}
         }
 
-        let mut reggos_of_last_discover = cookie
-            .and_then(|cookie| self.cookies.get(&cookie))
-            .cloned()
+        // Cookies of other instances refer to different positions, thus start from scratch.
+        let start = cookie
+            .filter(|cookie| cookie.id() == self.id)
+            .map(|cookie| cookie.position())
             .unwrap_or_default();
+        let limit = limit
+            .unwrap_or(u64::MAX)
+            .min(self.max_discover_limit)
+            .try_into()
+            .unwrap_or(usize::MAX);
+
+        let mut position = start;
+        let mut ids = Vec::new();
+        for (id, registration) in self
+            .registrations
+            .range((Bound::Excluded(RegistrationId(start)), Bound::Unbounded))
+        {
+            if ids.len() >= limit {
+                break;
+            }
+            position = id.0;
 
-        let ids = self
-            .registrations_for_peer
-            .iter()
-            .filter_map({
-                |((_, namespace), registration_id)| {
-                    if reggos_of_last_discover.contains(registration_id) {
-                        return None;
-                    }
-
-                    match discover_namespace.as_ref() {
-                        Some(discover_namespace) if discover_namespace == namespace => {
-                            Some(registration_id)
-                        }
-                        Some(_) => None,
-                        None => Some(registration_id),
-                    }
+            let matches = match discover_namespace.as_ref() {
+                Some(discover_namespace) if prefix => {
+                    registration.namespace.starts_with(discover_namespace)
                 }
-            })
-            .take(limit.unwrap_or(u64::MAX) as usize)
-            .cloned()
-            .collect::<Vec<_>>();
-
-        reggos_of_last_discover.extend(&ids);
+                Some(discover_namespace) => &registration.namespace == discover_namespace,
+                None => true,
+            };
+            if matches {
+                ids.push(*id);
+            }
+        }
 
-        let new_cookie = discover_namespace
-            .map(Cookie::for_namespace)
-            .unwrap_or_else(Cookie::for_all_namespaces);
-        self.cookies
-            .insert(new_cookie.clone(), reggos_of_last_discover);
+        let new_cookie = Cookie::at_position(self.id, position, discover_namespace, prefix);
 
         let regs = &self.registrations;
         let registrations = ids
@@ -497,27 +840,45 @@ impl Registrations {
         Ok((registrations, new_cookie))
     }
 
+    /// Records the failure of a change of the store, if any, and schedules a flush of it.
+    fn on_store_changed(&mut self, result: io::Result<()>) {
+        if let Err(e) = result {
+            self.store_errors.push_back(e);
+        }
+        self.next_flush
+            .get_or_insert_with(|| futures_timer::Delay::new(STORE_FLUSH_INTERVAL));
+    }
+
     fn poll(&mut self, cx: &mut Context<'_>) -> Poll<ExpiredRegistration> {
+        if let Some(Poll::Ready(())) = self.next_flush.as_mut().map(|f| f.poll_unpin(cx)) {
+            self.next_flush = None;
+            if let Err(e) = self.store.flush() {
+                self.store_errors.push_back(e);
+                cx.waker().wake_by_ref();
+            }
+        }
+
         loop {
             let expired_registration = ready!(self.next_expiry.poll_next_unpin(cx)).expect(
                 "This stream should never finish because it is initialised with a pending future",
             );
 
-            // clean up our cookies
-            self.cookies.retain(|_, registrations| {
-                registrations.remove(&expired_registration);
-
-                // retain all cookies where there are still registrations left
-                !registrations.is_empty()
-            });
-
-            self.registrations_for_peer
-                .remove_by_right(&expired_registration);
+            if let Some(((peer_id, namespace), _)) = self
+                .registrations_for_peer
+                .remove_by_right(&expired_registration)
+            {
+                self.on_removed(peer_id, namespace);
+            }
             match self.registrations.remove(&expired_registration) {
                 None => {
                     continue;
                 }
                 Some(registration) => {
+                    let result = self
+                        .store
+                        .remove(&registration.namespace, registration.record.peer_id());
+                    self.on_store_changed(result);
+
                     return Poll::Ready(ExpiredRegistration(registration));
                 }
             }
@@ -531,11 +892,12 @@ pub struct CookieNamespaceMismatch;
 
 #[cfg(test)]
 mod tests {
-    use instant::SystemTime;
     use std::option::Option::None;
 
-    use libp2p_core::PeerRecord;
+    use libp2p_core::{PeerRecord, SignedEnvelope};
     use libp2p_identity as identity;
+    use quick_protobuf::Writer;
+    use std::time::UNIX_EPOCH;
 
     use super::*;
 
@@ -640,6 +1002,7 @@ mod tests {
         let mut registrations = Registrations::with_config(Config {
             min_ttl: 0,
             max_ttl: 4,
+            ..Config::default()
         });
 
         let start_time = SystemTime::now();
@@ -676,6 +1039,7 @@ mod tests {
         let mut registrations = Registrations::with_config(Config {
             min_ttl: 1,
             max_ttl: 10,
+            ..Config::default()
         });
         let dummy_registration = new_dummy_registration_with_ttl("foo", 2);
         let namespace = dummy_registration.namespace.clone();
@@ -698,6 +1062,7 @@ mod tests {
         let mut registrations = Registrations::with_config(Config {
             min_ttl: 0,
             max_ttl: 10,
+            ..Config::default()
         });
         let dummy_registration = new_dummy_registration_with_ttl("foo", 1);
 
@@ -710,25 +1075,6 @@ mod tests {
         let _ = registrations.next_event_in_at_most(2).await;
     }
 
-    #[tokio::test]
-    async fn cookies_are_cleaned_up_if_registrations_expire() {
-        let mut registrations = Registrations::with_config(Config {
-            min_ttl: 1,
-            max_ttl: 10,
-        });
-
-        registrations
-            .add(new_dummy_registration_with_ttl("foo", 2))
-            .unwrap();
-        let (_, _) = registrations.get(None, None, None).unwrap();
-
-        assert_eq!(registrations.cookies.len(), 1);
-
-        let _ = registrations.next_event_in_at_most(3).await;
-
-        assert_eq!(registrations.cookies.len(), 0);
-    }
-
     #[test]
     fn given_limit_discover_only_returns_n_results() {
         let mut registrations = Registrations::default();
@@ -753,6 +1099,389 @@ mod tests {
         assert_eq!(discover2.count(), 1);
     }
 
+    #[test]
+    fn cookie_can_be_used_to_resume_discovery() {
+        let mut registrations = Registrations::default();
+        registrations.add(new_dummy_registration("foo")).unwrap();
+        let second = registrations.add(new_dummy_registration("foo")).unwrap();
+
+        let (_, cookie) = registrations.get(None, None, Some(1)).unwrap();
+
+        let (discover1, _) = registrations.get(None, Some(cookie.clone()), None).unwrap();
+        assert_eq!(discover1.collect::<Vec<_>>(), vec![&second]);
+
+        let (discover2, _) = registrations.get(None, Some(cookie), None).unwrap();
+        assert_eq!(discover2.collect::<Vec<_>>(), vec![&second]);
+    }
+
+    #[test]
+    fn cookie_of_other_registrations_discovers_from_scratch() {
+        let mut registrations = Registrations::default();
+        registrations.add(new_dummy_registration("foo")).unwrap();
+        let (_, cookie) = registrations.get(None, None, None).unwrap();
+
+        let mut other_registrations = Registrations::default();
+        other_registrations
+            .add(new_dummy_registration("foo"))
+            .unwrap();
+        let (discover, _) = other_registrations.get(None, Some(cookie), None).unwrap();
+
+        assert_eq!(discover.count(), 1);
+    }
+
+    #[test]
+    fn discover_limit_is_capped() {
+        let mut registrations =
+            Registrations::with_config(Config::default().with_max_discover_limit(1));
+        registrations.add(new_dummy_registration("foo")).unwrap();
+        registrations.add(new_dummy_registration("foo")).unwrap();
+
+        let (discover1, cookie) = registrations.get(None, None, None).unwrap();
+        assert_eq!(discover1.count(), 1);
+
+        let (discover2, _) = registrations.get(None, Some(cookie), Some(10)).unwrap();
+        assert_eq!(discover2.count(), 1);
+    }
+
+    #[test]
+    fn given_registrations_when_discover_by_prefix_then_all_matching_are_returned() {
+        let mut registrations = Registrations::default();
+        registrations.add(new_dummy_registration("foo/a")).unwrap();
+        registrations.add(new_dummy_registration("bar")).unwrap();
+        registrations.add(new_dummy_registration("foo/b")).unwrap();
+
+        let (discover, cookie) = registrations
+            .get_by_prefix(Namespace::from_static("foo/"), None, None)
+            .unwrap();
+
+        assert_eq!(
+            discover.map(|r| &r.namespace).collect::<Vec<_>>(),
+            vec!["foo/a", "foo/b"]
+        );
+        assert!(cookie.is_prefix());
+    }
+
+    #[test]
+    fn prefix_cookie_is_not_valid_for_exact_namespace() {
+        let mut registrations = Registrations::default();
+        registrations.add(new_dummy_registration("foo")).unwrap();
+
+        let (_, cookie) = registrations
+            .get_by_prefix(Namespace::from_static("foo"), None, None)
+            .unwrap();
+        let result = registrations.get(Some(Namespace::from_static("foo")), Some(cookie), None);
+
+        assert!(matches!(result, Err(CookieNamespaceMismatch)))
+    }
+
+    #[test]
+    fn only_allowed_peers_can_register_in_namespace() {
+        let alice = identity::Keypair::generate_ed25519();
+        let mut registrations =
+            Registrations::with_config(Config::default().with_namespace_policy(
+                Namespace::from_static("foo"),
+                NamespacePolicy::default().with_allowed_peers([alice.public().to_peer_id()]),
+            ));
+
+        registrations
+            .add(new_registration("foo", alice, None))
+            .unwrap();
+        let result = registrations.add(new_dummy_registration("foo"));
+
+        assert!(matches!(result, Err(RegistrationDenied::NotAllowed)));
+        registrations.add(new_dummy_registration("bar")).unwrap();
+    }
+
+    #[test]
+    fn registrations_per_peer_are_limited() {
+        let alice = identity::Keypair::generate_ed25519();
+        let mut registrations =
+            Registrations::with_config(Config::default().with_max_registrations_per_peer(1));
+
+        registrations
+            .add(new_registration("foo", alice.clone(), None))
+            .unwrap();
+        registrations
+            .add(new_registration("foo", alice.clone(), None))
+            .unwrap();
+        let result = registrations.add(new_registration("bar", alice.clone(), None));
+        assert!(matches!(
+            result,
+            Err(RegistrationDenied::TooManyRegistrationsForPeer { limit: 1 })
+        ));
+
+        registrations.remove(Namespace::from_static("foo"), alice.public().to_peer_id());
+        registrations
+            .add(new_registration("bar", alice, None))
+            .unwrap();
+    }
+
+    #[test]
+    fn registrations_per_namespace_are_limited() {
+        let mut registrations =
+            Registrations::with_config(Config::default().with_namespace_policy(
+                Namespace::from_static("foo"),
+                NamespacePolicy::default().with_max_registrations(1),
+            ));
+
+        registrations.add(new_dummy_registration("foo")).unwrap();
+        let result = registrations.add(new_dummy_registration("foo"));
+
+        assert!(matches!(
+            result,
+            Err(RegistrationDenied::TooManyRegistrationsInNamespace { limit: 1 })
+        ));
+        registrations.add(new_dummy_registration("bar")).unwrap();
+    }
+
+    #[test]
+    fn namespace_ttl_bounds_override_global_ones() {
+        let mut registrations = Registrations::with_config(
+            Config::default()
+                .with_namespace_policy(
+                    Namespace::from_static("foo"),
+                    NamespacePolicy::default().with_min_ttl(10).with_max_ttl(60),
+                )
+                .with_default_namespace_policy(NamespacePolicy::default().deny_all()),
+        );
+
+        registrations
+            .add(new_dummy_registration_with_ttl("foo", 30))
+            .unwrap();
+        let too_long = registrations.add(new_dummy_registration_with_ttl("foo", 61));
+        let other_namespace = registrations.add(new_dummy_registration_with_ttl("bar", 30));
+
+        assert!(matches!(
+            too_long,
+            Err(RegistrationDenied::TtlOutOfRange(TtlOutOfRange::TooLong {
+                bound: 60,
+                requested: 61
+            }))
+        ));
+        assert!(matches!(
+            other_namespace,
+            Err(RegistrationDenied::NotAllowed)
+        ));
+    }
+
+    #[test]
+    fn stale_records_are_rejected() {
+        let alice = identity::Keypair::generate_ed25519();
+        let mut registrations = Registrations::with_config(
+            Config::default().with_record_policy(RecordPolicy::default().with_stale_records(false)),
+        );
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        registrations
+            .add(new_registration_with_seq("foo", &alice, now))
+            .unwrap();
+        let result = registrations.add(new_registration_with_seq("foo", &alice, now - 1));
+
+        assert!(matches!(
+            result,
+            Err(RegistrationDenied::RecordRejected(
+                RecordRejected::Stale { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn records_with_nanosecond_sequence_numbers_are_aged() {
+        let alice = identity::Keypair::generate_ed25519();
+        let mut registrations = Registrations::with_config(
+            Config::default()
+                .with_record_policy(RecordPolicy::default().with_max_age(Duration::from_secs(60))),
+        );
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+
+        let result = registrations.add(new_registration_with_seq(
+            "foo",
+            &alice,
+            (now - Duration::from_secs(120)).as_nanos() as u64,
+        ));
+
+        assert!(matches!(
+            result,
+            Err(RegistrationDenied::RecordRejected(
+                RecordRejected::TooOld { .. }
+            ))
+        ));
+        registrations
+            .add(new_registration_with_seq(
+                "foo",
+                &alice,
+                now.as_nanos() as u64,
+            ))
+            .unwrap();
+    }
+
+    #[test]
+    fn records_with_only_relayed_addresses_are_rejected() {
+        let mut registrations = Registrations::with_config(
+            Config::default()
+                .with_record_policy(RecordPolicy::default().with_relayed_addresses(false)),
+        );
+        let relayed_address = format!(
+            "/ip4/127.0.0.1/tcp/1234/p2p/{}/p2p-circuit",
+            PeerId::random()
+        )
+        .parse()
+        .unwrap();
+        let record = PeerRecord::new(
+            &identity::Keypair::generate_ed25519(),
+            vec![relayed_address],
+        )
+        .unwrap();
+
+        let result = registrations.add(NewRegistration::new(
+            Namespace::from_static("foo"),
+            record,
+            None,
+        ));
+
+        assert!(matches!(
+            result,
+            Err(RegistrationDenied::RecordRejected(
+                RecordRejected::OnlyRelayedAddresses
+            ))
+        ));
+        registrations.add(new_dummy_registration("foo")).unwrap();
+    }
+
+    #[test]
+    fn registrations_are_restored_from_file_store() {
+        let path =
+            std::env::temp_dir().join(format!("libp2p-rendezvous-store-{}", rand::random::<u64>()));
+
+        let mut registrations =
+            Registrations::with_store(Config::default(), FileStore::new(&path)).unwrap();
+        let foo = registrations.add(new_dummy_registration("foo")).unwrap();
+        let bar = registrations.add(new_dummy_registration("bar")).unwrap();
+        registrations.remove(bar.namespace, bar.record.peer_id());
+        drop(registrations);
+
+        let registrations =
+            Registrations::with_store(Config::default(), FileStore::new(&path)).unwrap();
+        let (discover, _) = registrations.get(None, None, None).unwrap();
+        let restored = discover.cloned().collect::<Vec<_>>();
+
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored[0].namespace, foo.namespace);
+        assert_eq!(restored[0].record, foo.record);
+        assert!(restored[0].ttl <= foo.ttl);
+        assert!(registrations.store_errors.is_empty());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn file_store_is_compacted() {
+        let path =
+            std::env::temp_dir().join(format!("libp2p-rendezvous-store-{}", rand::random::<u64>()));
+        let registration = new_stored_registration(Duration::from_secs(60));
+
+        let mut store = FileStore::new(&path);
+        for _ in 0..2 * store::MIN_ENTRIES_BEFORE_COMPACTION {
+            store.insert(registration.clone()).unwrap();
+        }
+        store.flush().unwrap();
+        let compacted_len = std::fs::metadata(&path).unwrap().len();
+        let mut single_entry_store = FileStore::new(path.with_extension("single"));
+        single_entry_store.insert(registration.clone()).unwrap();
+        single_entry_store.flush().unwrap();
+        let single_entry_len = std::fs::metadata(single_entry_store.path()).unwrap().len();
+
+        assert!(compacted_len < single_entry_len * store::MIN_ENTRIES_BEFORE_COMPACTION as u64);
+        let restored = FileStore::new(&path).load().unwrap();
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored[0].registration, registration.registration);
+
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(single_entry_store.path()).unwrap();
+    }
+
+    #[test]
+    fn file_store_changes_are_written_on_flush() {
+        let path =
+            std::env::temp_dir().join(format!("libp2p-rendezvous-store-{}", rand::random::<u64>()));
+        let mut store = FileStore::new(&path);
+
+        store
+            .insert(new_stored_registration(Duration::from_secs(60)))
+            .unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+
+        store.flush().unwrap();
+        assert!(std::fs::metadata(&path).unwrap().len() > 0);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn restored_registrations_have_remaining_ttl() {
+        let mut store = MemoryStore::default();
+        store
+            .insert(new_stored_registration(Duration::from_secs(100)))
+            .unwrap();
+
+        let registrations = Registrations::with_store(Config::default(), store).unwrap();
+        let (mut discover, _) = registrations.get(None, None, None).unwrap();
+
+        assert!((99..=100).contains(&discover.next().unwrap().ttl));
+    }
+
+    #[test]
+    fn store_failures_are_reported() {
+        struct FailingStore;
+
+        impl Store for FailingStore {
+            fn load(&mut self) -> io::Result<Vec<StoredRegistration>> {
+                Ok(Vec::new())
+            }
+
+            fn insert(&mut self, _: StoredRegistration) -> io::Result<()> {
+                Err(io::ErrorKind::Other.into())
+            }
+
+            fn remove(&mut self, _: &Namespace, _: PeerId) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut registrations = Registrations::with_store(Config::default(), FailingStore).unwrap();
+        registrations.add(new_dummy_registration("foo")).unwrap();
+
+        assert_eq!(registrations.store_errors.len(), 1);
+    }
+
+    #[test]
+    fn expired_registrations_are_not_restored() {
+        let mut store = MemoryStore::default();
+        let mut registration = new_stored_registration(Duration::ZERO);
+        registration.expires_at -= Duration::from_secs(1);
+        store.insert(registration).unwrap();
+
+        let mut registrations = Registrations::with_store(Config::default(), store).unwrap();
+        let (discover, _) = registrations.get(None, None, None).unwrap();
+
+        assert_eq!(discover.count(), 0);
+        assert!(registrations.store.load().unwrap().is_empty());
+    }
+
+    fn new_stored_registration(expires_in: Duration) -> StoredRegistration {
+        StoredRegistration {
+            registration: Registration {
+                namespace: Namespace::from_static("foo"),
+                record: PeerRecord::new(&identity::Keypair::generate_ed25519(), vec![]).unwrap(),
+                ttl: 7200,
+            },
+            expires_at: SystemTime::now() + expires_in,
+        }
+    }
+
     fn new_dummy_registration(namespace: &'static str) -> NewRegistration {
         let identity = identity::Keypair::generate_ed25519();
 
@@ -777,6 +1506,46 @@ mod tests {
         )
     }
 
+    /// Returns a registration of a record with the given sequence number, which
+    /// [`PeerRecord::new`] sets to the current time.
+    fn new_registration_with_seq(
+        namespace: &'static str,
+        identity: &identity::Keypair,
+        seq: u64,
+    ) -> NewRegistration {
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/1234".parse().unwrap();
+        let mut address_info = Vec::new();
+        Writer::new(&mut address_info)
+            .write_with_tag(10, |w| w.write_bytes(&address.to_vec()))
+            .unwrap();
+
+        let mut payload = Vec::new();
+        let mut writer = Writer::new(&mut payload);
+        writer
+            .write_with_tag(10, |w| {
+                w.write_bytes(&identity.public().to_peer_id().to_bytes())
+            })
+            .unwrap();
+        writer.write_with_tag(16, |w| w.write_uint64(seq)).unwrap();
+        writer
+            .write_with_tag(26, |w| w.write_bytes(&address_info))
+            .unwrap();
+
+        let envelope = SignedEnvelope::new(
+            identity,
+            String::from("libp2p-routing-state"),
+            b"/libp2p/routing-state-record".to_vec(),
+            payload,
+        )
+        .unwrap();
+
+        NewRegistration::new(
+            Namespace::from_static(namespace),
+            PeerRecord::from_signed_envelope(envelope).unwrap(),
+            None,
+        )
+    }
+
     /// Defines utility functions that make the tests more readable.
     impl Registrations {
         async fn next_event(&mut self) -> ExpiredRegistration {
@@ -0,0 +1,363 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Persistence of the registrations held by a rendezvous [`Behaviour`](super::Behaviour).
+//!
+//! The server keeps its own index of registrations and writes every change through to a
+//! [`Store`], which it flushes about once per second while there are changes. Failures of the
+//! store are reported as
+//! [`Event::StoreFailed`](super::Event::StoreFailed). When the server is created via
+//! [`Behaviour::with_store`](super::Behaviour::with_store), the registrations that are still
+//! valid are loaded back from the store, thus a restarted rendezvous point keeps serving the
+//! registrations it accepted before.
+
+use crate::codec::{Namespace, Registration, Ttl};
+use instant::SystemTime;
+use libp2p_core::{PeerRecord, SignedEnvelope};
+use libp2p_identity::PeerId;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// A registration as persisted by a [`Store`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredRegistration {
+    pub registration: Registration,
+    /// The point in time the registration expires at.
+    pub expires_at: SystemTime,
+}
+
+/// Storage backend for the registrations of a rendezvous server.
+///
+/// There is at most one registration per peer and namespace. Inserting a registration for a
+/// peer and namespace that is already present replaces the existing one.
+pub trait Store: Send + 'static {
+    /// Returns all registrations held by the store that did not expire yet, removing the expired
+    /// ones from the store.
+    fn load(&mut self) -> io::Result<Vec<StoredRegistration>>;
+
+    /// Inserts or replaces the registration of a peer in a namespace.
+    fn insert(&mut self, registration: StoredRegistration) -> io::Result<()>;
+
+    /// Removes the registration of the given peer in the given namespace, if any.
+    fn remove(&mut self, namespace: &Namespace, peer: PeerId) -> io::Result<()>;
+
+    /// Persists the changes buffered by the store, if any.
+    ///
+    /// Stores may buffer changes to keep I/O out of the poll path of the server, which calls
+    /// this about once per second while there are changes. Buffered changes should also be
+    /// persisted when the store is dropped.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A [`Store`] that holds registrations in memory only.
+///
+/// This is the default store. Registrations are lost once the store is dropped.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    registrations: HashMap<(PeerId, Namespace), StoredRegistration>,
+}
+
+impl Store for MemoryStore {
+    fn load(&mut self) -> io::Result<Vec<StoredRegistration>> {
+        let now = SystemTime::now();
+        self.registrations
+            .retain(|_, stored| stored.expires_at > now);
+
+        Ok(self.registrations.values().cloned().collect())
+    }
+
+    fn insert(&mut self, registration: StoredRegistration) -> io::Result<()> {
+        let key = (
+            registration.registration.record.peer_id(),
+            registration.registration.namespace.clone(),
+        );
+        self.registrations.insert(key, registration);
+
+        Ok(())
+    }
+
+    fn remove(&mut self, namespace: &Namespace, peer: PeerId) -> io::Result<()> {
+        self.registrations.remove(&(peer, namespace.clone()));
+
+        Ok(())
+    }
+}
+
+/// A [`Store`] that persists registrations to a file on disk.
+///
+/// Changes are appended to the file through an in-memory buffer, which is written once it is
+/// full, on [`Store::flush`] and when the store is dropped, without waiting for the file to be
+/// synced to disk. Changes not yet written are lost if the process crashes. The file is compacted when it is loaded and once most of its entries are superseded, by writing the
+/// current registrations to a temporary file next to it that then replaces the original one.
+/// The signed peer records are stored in their signed envelope and thus verified again when the
+/// file is loaded.
+#[derive(Debug)]
+pub struct FileStore {
+    path: PathBuf,
+    registrations: MemoryStore,
+    /// The file changes are appended to, opened on the first change.
+    log: Option<BufWriter<fs::File>>,
+    /// The number of entries in the file, including superseded ones.
+    entries: usize,
+}
+
+/// The file of a [`FileStore`] is not compacted before it holds this many entries.
+pub(crate) const MIN_ENTRIES_BEFORE_COMPACTION: usize = 1024;
+
+/// Marks an entry inserting a registration.
+const INSERT: u8 = 0;
+/// Marks an entry removing a registration.
+const REMOVE: u8 = 1;
+
+impl FileStore {
+    /// Creates a store backed by the file at the given path.
+    ///
+    /// The file is created once the store is loaded or changed, if it does not exist yet.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            registrations: MemoryStore::default(),
+            log: None,
+            entries: 0,
+        }
+    }
+
+    /// Returns the path of the file backing this store.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends the given entry to the file, compacting it instead if most of its entries are
+    /// superseded.
+    fn append(&mut self, entry: &[u8]) -> io::Result<()> {
+        self.entries += 1;
+        if self.entries >= MIN_ENTRIES_BEFORE_COMPACTION
+            && self.entries > 2 * self.registrations.registrations.len()
+        {
+            return self.compact();
+        }
+
+        let log = match self.log.as_mut() {
+            Some(log) => log,
+            None => self.log.insert(BufWriter::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?,
+            )),
+        };
+
+        log.write_all(entry)
+    }
+
+    /// Replaces the file with one holding only the current registrations.
+    fn compact(&mut self) -> io::Result<()> {
+        let mut bytes = Vec::new();
+        for stored in self.registrations.registrations.values() {
+            encode_insert(stored, &mut bytes);
+        }
+
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+
+        // The replaced file may still be appended to when the buffer is written, which is
+        // harmless as the compacted file holds all current registrations.
+        self.log = None;
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+        self.entries = self.registrations.registrations.len();
+
+        Ok(())
+    }
+}
+
+impl Store for FileStore {
+    fn load(&mut self) -> io::Result<Vec<StoredRegistration>> {
+        let mut bytes = Vec::new();
+        match fs::File::open(&self.path) {
+            Ok(mut file) => {
+                file.read_to_end(&mut bytes)?;
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        let mut remaining = bytes.as_slice();
+        while !remaining.is_empty() {
+            match decode_entry(&mut remaining) {
+                Ok(Entry::Insert(stored)) => self.registrations.insert(stored)?,
+                Ok(Entry::Remove(namespace, peer)) => {
+                    self.registrations.remove(&namespace, peer)?
+                }
+                // The last change may have been appended only partially, e.g. on a crash.
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    log::warn!("Ignoring truncated entry at the end of {:?}", self.path);
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let registrations = self.registrations.load()?;
+        self.compact()?;
+
+        Ok(registrations)
+    }
+
+    fn insert(&mut self, registration: StoredRegistration) -> io::Result<()> {
+        let mut entry = Vec::new();
+        encode_insert(&registration, &mut entry);
+        self.registrations.insert(registration)?;
+
+        self.append(&entry)
+    }
+
+    fn remove(&mut self, namespace: &Namespace, peer: PeerId) -> io::Result<()> {
+        if !self
+            .registrations
+            .registrations
+            .contains_key(&(peer, namespace.clone()))
+        {
+            return Ok(());
+        }
+
+        let mut entry = vec![REMOVE];
+        write_length_prefixed(namespace.to_string().as_bytes(), &mut entry);
+        write_length_prefixed(&peer.to_bytes(), &mut entry);
+        self.registrations.remove(namespace, peer)?;
+
+        self.append(&entry)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.log.as_mut() {
+            Some(log) => log.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// An entry of the file of a [`FileStore`].
+#[allow(clippy::large_enum_variant)]
+enum Entry {
+    Insert(StoredRegistration),
+    Remove(Namespace, PeerId),
+}
+
+fn decode_entry(bytes: &mut &[u8]) -> io::Result<Entry> {
+    match take(bytes, 1)?[0] {
+        INSERT => decode(bytes).map(Entry::Insert),
+        REMOVE => {
+            let namespace = decode_namespace(bytes)?;
+            let peer = PeerId::from_bytes(read_length_prefixed(bytes)?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+            Ok(Entry::Remove(namespace, peer))
+        }
+        tag => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unknown registration store entry {tag}"),
+        )),
+    }
+}
+
+fn encode_insert(stored: &StoredRegistration, bytes: &mut Vec<u8>) {
+    bytes.push(INSERT);
+    let namespace = stored.registration.namespace.to_string().into_bytes();
+    let envelope = stored
+        .registration
+        .record
+        .to_signed_envelope()
+        .into_protobuf_encoding();
+    let expires_at = stored
+        .expires_at
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    write_length_prefixed(&namespace, bytes);
+    write_length_prefixed(&envelope, bytes);
+    bytes.extend_from_slice(&stored.registration.ttl.to_be_bytes());
+    bytes.extend_from_slice(&expires_at.to_be_bytes());
+}
+
+fn decode(bytes: &mut &[u8]) -> io::Result<StoredRegistration> {
+    let namespace = decode_namespace(bytes)?;
+    let envelope = SignedEnvelope::from_protobuf_encoding(read_length_prefixed(bytes)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let record = PeerRecord::from_signed_envelope(envelope)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let ttl: Ttl = read_u64(bytes)?;
+    let expires_at = UNIX_EPOCH + Duration::from_secs(read_u64(bytes)?);
+
+    Ok(StoredRegistration {
+        registration: Registration {
+            namespace,
+            record,
+            ttl,
+        },
+        expires_at,
+    })
+}
+
+fn decode_namespace(bytes: &mut &[u8]) -> io::Result<Namespace> {
+    let namespace = String::from_utf8(read_length_prefixed(bytes)?.to_vec())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    Namespace::new(namespace).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_length_prefixed(data: &[u8], bytes: &mut Vec<u8>) {
+    bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(data);
+}
+
+fn read_length_prefixed<'a>(bytes: &mut &'a [u8]) -> io::Result<&'a [u8]> {
+    let len = u32::from_be_bytes(take(bytes, 4)?.try_into().expect("4 bytes")) as usize;
+
+    take(bytes, len)
+}
+
+fn read_u64(bytes: &mut &[u8]) -> io::Result<u64> {
+    Ok(u64::from_be_bytes(
+        take(bytes, 8)?.try_into().expect("8 bytes"),
+    ))
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+    if bytes.len() < len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated registration store",
+        ));
+    }
+
+    let (taken, remaining) = bytes.split_at(len);
+    *bytes = remaining;
+
+    Ok(taken)
+}
@@ -20,7 +20,9 @@
 
 use futures::stream::FuturesUnordered;
 use futures::StreamExt;
+use libp2p_core::PeerRecord;
 use libp2p_identity as identity;
+use libp2p_identity::PeerId;
 use libp2p_rendezvous as rendezvous;
 use libp2p_rendezvous::client::RegisterError;
 use libp2p_swarm::{DialError, Swarm, SwarmEvent};
@@ -81,6 +83,67 @@ async fn given_successful_registration_then_successful_discovery() {
     }
 }
 
+#[tokio::test]
+async fn given_registrations_then_discovery_by_prefix_is_paginated() {
+    let _ = env_logger::try_init();
+    let ([mut alice, mut bob], mut robert) =
+        new_server_with_connected_clients(rendezvous::server::Config::default()).await;
+
+    for namespace in ["app/chat", "other", "app/files"] {
+        alice
+            .behaviour_mut()
+            .register(
+                rendezvous::Namespace::from_static(namespace),
+                *robert.local_peer_id(),
+                None,
+            )
+            .unwrap();
+
+        match libp2p_swarm_test::drive(&mut alice, &mut robert).await {
+            (
+                [rendezvous::client::Event::Registered { .. }],
+                [rendezvous::server::Event::PeerRegistered { .. }],
+            ) => {}
+            events => panic!("Unexpected events: {events:?}"),
+        }
+    }
+
+    let prefix = rendezvous::Namespace::from_static("app/");
+    let mut cookie = None;
+    let mut discovered = Vec::new();
+    loop {
+        bob.behaviour_mut().discover_prefix(
+            prefix.clone(),
+            cookie,
+            Some(1),
+            *robert.local_peer_id(),
+        );
+
+        match libp2p_swarm_test::drive(&mut bob, &mut robert).await {
+            (
+                [rendezvous::client::Event::Discovered {
+                    registrations,
+                    cookie: next_cookie,
+                    ..
+                }],
+                [rendezvous::server::Event::DiscoverServed { .. }],
+            ) => {
+                if registrations.is_empty() {
+                    break;
+                }
+                assert_eq!(registrations.len(), 1);
+                assert!(next_cookie.is_prefix());
+
+                discovered.extend(registrations.into_iter().map(|r| r.namespace.to_string()));
+                cookie = Some(next_cookie);
+            }
+            events => panic!("Unexpected events: {events:?}"),
+        }
+    }
+
+    assert_eq!(discovered, vec!["app/chat", "app/files"]);
+}
+
 #[tokio::test]
 async fn should_return_error_when_no_external_addresses() {
     let _ = env_logger::try_init();
@@ -189,6 +252,93 @@ async fn given_invalid_ttl_then_unsuccessful_registration() {
     }
 }
 
+#[tokio::test]
+async fn given_namespace_not_allowed_then_registration_is_denied() {
+    let _ = env_logger::try_init();
+    let namespace = rendezvous::Namespace::from_static("some-namespace");
+    let ([mut alice], mut robert) = new_server_with_connected_clients(
+        rendezvous::server::Config::default().with_namespace_policy(
+            namespace.clone(),
+            rendezvous::server::NamespacePolicy::default().deny_all(),
+        ),
+    )
+    .await;
+
+    alice
+        .behaviour_mut()
+        .register(namespace.clone(), *robert.local_peer_id(), None)
+        .unwrap();
+
+    match libp2p_swarm_test::drive(&mut alice, &mut robert).await {
+        (
+            [rendezvous::client::Event::RegisterFailed { error, .. }],
+            [rendezvous::server::Event::RegistrationDenied {
+                peer,
+                namespace: denied_namespace,
+                reason: rendezvous::server::RegistrationDenied::NotAllowed,
+            }],
+        ) => {
+            assert_eq!(error, rendezvous::ErrorCode::NotAuthorized);
+            assert_eq!(&peer, alice.local_peer_id());
+            assert_eq!(denied_namespace, namespace);
+        }
+        events => panic!("Unexpected events: {events:?}"),
+    }
+}
+
+#[tokio::test]
+async fn given_record_too_old_then_discovered_registration_is_rejected() {
+    let _ = env_logger::try_init();
+    let namespace = rendezvous::Namespace::from_static("some-namespace");
+    let ([mut alice], mut robert) =
+        new_server_with_connected_clients(rendezvous::server::Config::default()).await;
+    let mut bob = new_client_with_config(rendezvous::client::Config::default().with_record_policy(
+        rendezvous::RecordPolicy::default().with_max_age(Duration::from_secs(1)),
+    ))
+    .await;
+    bob.connect(&mut robert).await;
+
+    alice
+        .behaviour_mut()
+        .register(namespace.clone(), *robert.local_peer_id(), None)
+        .unwrap();
+    match libp2p_swarm_test::drive(&mut alice, &mut robert).await {
+        (
+            [rendezvous::client::Event::Registered { .. }],
+            [rendezvous::server::Event::PeerRegistered { .. }],
+        ) => {}
+        events => panic!("Unexpected events: {events:?}"),
+    }
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    bob.behaviour_mut()
+        .discover(Some(namespace), None, None, *robert.local_peer_id());
+
+    match libp2p_swarm_test::drive(&mut bob, &mut robert).await {
+        (
+            [rendezvous::client::Event::Discovered {
+                registrations,
+                rejected,
+                ..
+            }],
+            [rendezvous::server::Event::DiscoverServed { .. }],
+        ) => {
+            assert!(registrations.is_empty());
+            match rejected.as_slice() {
+                [(registration, rendezvous::RecordRejected::TooOld { .. })] => {
+                    assert_eq!(registration.record.peer_id(), *alice.local_peer_id());
+                }
+                _ => panic!("Expected the registration to be rejected as too old"),
+            }
+        }
+        events => panic!("Unexpected events: {events:?}"),
+    }
+    assert!(matches!(
+        bob.dial(*alice.local_peer_id()).unwrap_err(),
+        DialError::NoAddresses
+    ));
+}
+
 #[tokio::test]
 async fn discover_allows_for_dial_by_peer_id() {
     let _ = env_logger::try_init();
@@ -348,6 +498,241 @@ async fn registration_on_clients_expire() {
     assert!(matches!(error, DialError::NoAddresses));
 }
 
+#[tokio::test]
+async fn registrations_are_renewed_before_expiry() {
+    let _ = env_logger::try_init();
+    let namespace = rendezvous::Namespace::from_static("some-namespace");
+    let mut alice = new_client_with_config(
+        rendezvous::client::Config::default().with_renewal_jitter(Duration::ZERO),
+    )
+    .await;
+    let mut robert = new_server(rendezvous::server::Config::default().with_min_ttl(1)).await;
+    alice.connect(&mut robert).await;
+
+    let roberts_peer_id = *robert.local_peer_id();
+    tokio::spawn(robert.loop_on_next());
+
+    alice
+        .behaviour_mut()
+        .register(namespace.clone(), roberts_peer_id, Some(2))
+        .unwrap();
+    match alice.next_behaviour_event().await {
+        rendezvous::client::Event::Registered { .. } => {}
+        event => panic!("Unexpected event: {event:?}"),
+    }
+
+    for _ in 0..2 {
+        match tokio::time::timeout(Duration::from_secs(2), alice.next_behaviour_event())
+            .await
+            .unwrap()
+        {
+            rendezvous::client::Event::RegistrationRenewed {
+                rendezvous_node,
+                ttl,
+                namespace: renewed_namespace,
+            } => {
+                assert_eq!(rendezvous_node, roberts_peer_id);
+                assert_eq!(ttl, 2);
+                assert_eq!(renewed_namespace, namespace);
+            }
+            event => panic!("Unexpected event: {event:?}"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn registration_expires_if_renewal_fails() {
+    let _ = env_logger::try_init();
+    let namespace = rendezvous::Namespace::from_static("some-namespace");
+    let mut alice = new_client_with_config(
+        rendezvous::client::Config::default()
+            .with_renewal_jitter(Duration::ZERO)
+            .with_retry_backoff(Duration::from_millis(200), Duration::from_millis(200)),
+    )
+    .await;
+    let mut robert = new_server(rendezvous::server::Config::default().with_min_ttl(1)).await;
+    alice.connect(&mut robert).await;
+
+    let roberts_peer_id = *robert.local_peer_id();
+    alice
+        .behaviour_mut()
+        .register(namespace.clone(), roberts_peer_id, Some(2))
+        .unwrap();
+    match libp2p_swarm_test::drive(&mut alice, &mut robert).await {
+        (
+            [rendezvous::client::Event::Registered { .. }],
+            [rendezvous::server::Event::PeerRegistered { .. }],
+        ) => {}
+        events => panic!("Unexpected events: {events:?}"),
+    }
+    drop(robert);
+
+    let expired = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            match alice.next_behaviour_event().await {
+                rendezvous::client::Event::RegisterFailed { .. } => {}
+                event => return event,
+            }
+        }
+    })
+    .await
+    .unwrap();
+
+    assert!(matches!(
+        expired,
+        rendezvous::client::Event::RegistrationExpired { rendezvous_node, namespace: expired_namespace }
+            if rendezvous_node == roberts_peer_id && expired_namespace == namespace
+    ));
+}
+
+#[tokio::test]
+async fn discovered_peers_are_dialed() {
+    let _ = env_logger::try_init();
+    let namespace = rendezvous::Namespace::from_static("some-namespace");
+    let ([mut alice], mut robert) =
+        new_server_with_connected_clients(rendezvous::server::Config::default()).await;
+    let mut bob = new_dialing_client(rendezvous::dialer::Config::default()).await;
+    bob.connect(&mut robert).await;
+
+    alice
+        .behaviour_mut()
+        .register(namespace.clone(), *robert.local_peer_id(), None)
+        .unwrap();
+    match libp2p_swarm_test::drive(&mut alice, &mut robert).await {
+        (
+            [rendezvous::client::Event::Registered { .. }],
+            [rendezvous::server::Event::PeerRegistered { .. }],
+        ) => {}
+        events => panic!("Unexpected events: {events:?}"),
+    }
+
+    let roberts_peer_id = *robert.local_peer_id();
+    let alices_peer_id = *alice.local_peer_id();
+    tokio::spawn(robert.loop_on_next());
+    tokio::spawn(alice.loop_on_next());
+
+    bob.behaviour_mut()
+        .client
+        .discover(Some(namespace), None, None, roberts_peer_id);
+
+    loop {
+        match bob.next_swarm_event().await {
+            SwarmEvent::Behaviour(DialingClientEvent::Client(event)) => {
+                bob.behaviour_mut().dialer.on_client_event(&event);
+            }
+            SwarmEvent::ConnectionEstablished { peer_id, .. } if peer_id == alices_peer_id => {
+                break;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[tokio::test]
+async fn unreachable_discovered_peers_are_backed_off() {
+    let _ = env_logger::try_init();
+    let namespace = rendezvous::Namespace::from_static("some-namespace");
+    let ([mut alice], mut robert) =
+        new_server_with_connected_clients(rendezvous::server::Config::default()).await;
+    let mut bob = new_dialing_client(rendezvous::dialer::Config::default()).await;
+    bob.connect(&mut robert).await;
+
+    alice
+        .behaviour_mut()
+        .register(namespace.clone(), *robert.local_peer_id(), None)
+        .unwrap();
+    match libp2p_swarm_test::drive(&mut alice, &mut robert).await {
+        (
+            [rendezvous::client::Event::Registered { .. }],
+            [rendezvous::server::Event::PeerRegistered { .. }],
+        ) => {}
+        events => panic!("Unexpected events: {events:?}"),
+    }
+
+    let roberts_peer_id = *robert.local_peer_id();
+    let alices_peer_id = *alice.local_peer_id();
+    drop(alice);
+    tokio::spawn(robert.loop_on_next());
+
+    bob.behaviour_mut()
+        .client
+        .discover(Some(namespace.clone()), None, None, roberts_peer_id);
+
+    let backoff = loop {
+        match bob.next_behaviour_event().await {
+            DialingClientEvent::Client(event) => {
+                bob.behaviour_mut().dialer.on_client_event(&event);
+            }
+            DialingClientEvent::Dialer(rendezvous::dialer::Event::DialFailed { peer, backoff }) => {
+                assert_eq!(peer, alices_peer_id);
+                break backoff;
+            }
+        }
+    };
+    assert_eq!(backoff, Duration::from_secs(30));
+
+    // Discovering the peer again while it is backed off doesn't dial it.
+    bob.behaviour_mut()
+        .client
+        .discover(Some(namespace), None, None, roberts_peer_id);
+    loop {
+        match bob.next_swarm_event().await {
+            SwarmEvent::Behaviour(DialingClientEvent::Client(
+                event @ rendezvous::client::Event::Discovered { .. },
+            )) => {
+                bob.behaviour_mut().dialer.on_client_event(&event);
+                break;
+            }
+            SwarmEvent::Dialing { .. } => panic!("Unexpected dial"),
+            _ => {}
+        }
+    }
+    tokio::time::timeout(Duration::from_secs(1), async {
+        loop {
+            if let SwarmEvent::Dialing { .. } = bob.next_swarm_event().await {
+                panic!("Unexpected dial");
+            }
+        }
+    })
+    .await
+    .unwrap_err();
+}
+
+#[tokio::test]
+async fn relayed_addresses_are_not_dialed_if_not_honored() {
+    let _ = env_logger::try_init();
+    let mut bob = new_dialing_client(
+        rendezvous::dialer::Config::default()
+            .with_record_policy(rendezvous::RecordPolicy::default().with_relayed_addresses(false)),
+    )
+    .await;
+
+    let relay = PeerId::random();
+    let relayed_address = format!("/ip4/127.0.0.1/tcp/4001/p2p/{relay}/p2p-circuit")
+        .parse()
+        .unwrap();
+    let registration = rendezvous::Registration {
+        namespace: rendezvous::Namespace::from_static("some-namespace"),
+        record: PeerRecord::new(
+            &identity::Keypair::generate_ed25519(),
+            vec![relayed_address],
+        )
+        .unwrap(),
+        ttl: rendezvous::DEFAULT_TTL,
+    };
+    bob.behaviour_mut().dialer.add_registration(&registration);
+
+    tokio::time::timeout(Duration::from_secs(1), async {
+        loop {
+            if let SwarmEvent::Dialing { .. } = bob.next_swarm_event().await {
+                panic!("Unexpected dial");
+            }
+        }
+    })
+    .await
+    .unwrap_err();
+}
+
 async fn new_server_with_connected_clients<const N: usize>(
     config: rendezvous::server::Config,
 ) -> (
@@ -375,7 +760,15 @@ async fn new_server_with_connected_clients<const N: usize>(
 }
 
 async fn new_client() -> Swarm<rendezvous::client::Behaviour> {
-    let mut client = Swarm::new_ephemeral(rendezvous::client::Behaviour::new);
+    new_client_with_config(rendezvous::client::Config::default()).await
+}
+
+async fn new_client_with_config(
+    config: rendezvous::client::Config,
+) -> Swarm<rendezvous::client::Behaviour> {
+    let mut client = Swarm::new_ephemeral(|identity| {
+        rendezvous::client::Behaviour::with_config(identity, config)
+    });
     client.listen().await; // we need to listen otherwise we don't have addresses to register
 
     client
@@ -410,6 +803,23 @@ async fn new_impersonating_client() -> Swarm<rendezvous::client::Behaviour> {
     eve
 }
 
+async fn new_dialing_client(config: rendezvous::dialer::Config) -> Swarm<DialingClient> {
+    let mut client = Swarm::new_ephemeral(|identity| DialingClient {
+        dialer: rendezvous::dialer::Behaviour::new(identity.public().to_peer_id(), config),
+        client: rendezvous::client::Behaviour::new(identity),
+    });
+    client.listen().await;
+
+    client
+}
+
+#[derive(libp2p_swarm::NetworkBehaviour)]
+#[behaviour(prelude = "libp2p_swarm::derive_prelude")]
+struct DialingClient {
+    client: rendezvous::client::Behaviour,
+    dialer: rendezvous::dialer::Behaviour,
+}
+
 #[derive(libp2p_swarm::NetworkBehaviour)]
 #[behaviour(prelude = "libp2p_swarm::derive_prelude")]
 struct Combined {
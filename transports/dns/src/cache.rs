@@ -0,0 +1,189 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use libp2p_core::multiaddr::Protocol;
+use std::{collections::HashMap, time::Instant};
+
+/// A DNS lookup performed for a DNS protocol component of an address.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum Query {
+    Ip(String),
+    Ipv4(String),
+    Ipv6(String),
+    Txt(String),
+}
+
+impl Query {
+    /// The lookup needed to resolve the given protocol, if it is a DNS protocol.
+    pub(crate) fn from_protocol(proto: &Protocol<'_>) -> Option<Self> {
+        match proto {
+            Protocol::Dns(name) => Some(Query::Ip(name.to_string())),
+            Protocol::Dns4(name) => Some(Query::Ipv4(name.to_string())),
+            Protocol::Dns6(name) => Some(Query::Ipv6(name.to_string())),
+            Protocol::Dnsaddr(name) => Some(Query::Txt(name.to_string())),
+            _ => None,
+        }
+    }
+
+    /// The name that is looked up.
+    pub(crate) fn name(&self) -> String {
+        match self {
+            Query::Ip(name) | Query::Ipv4(name) | Query::Ipv6(name) => name.clone(),
+            Query::Txt(name) => format!("{}{}", crate::DNSADDR_PREFIX, name),
+        }
+    }
+}
+
+/// A bounded cache of the results of DNS lookups, kept for as long as they are valid.
+#[derive(Debug)]
+pub(crate) struct Cache<V> {
+    capacity: usize,
+    entries: HashMap<Query, Entry<V>>,
+}
+
+#[derive(Debug)]
+struct Entry<V> {
+    value: V,
+    valid_until: Instant,
+}
+
+impl<V: Clone> Cache<V> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Cache {
+            capacity,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Returns the cached result of `query`, unless it expired.
+    pub(crate) fn get(&mut self, query: &Query, now: Instant) -> Option<V> {
+        match self.entries.get(query) {
+            Some(entry) if entry.valid_until > now => Some(entry.value.clone()),
+            Some(_) => {
+                self.entries.remove(query);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Caches the result of `query` until `valid_until`.
+    ///
+    /// If the cache is full, expired entries are removed first, then the
+    /// entry that expires soonest.
+    pub(crate) fn insert(&mut self, query: Query, value: V, valid_until: Instant, now: Instant) {
+        if self.capacity == 0 || valid_until <= now {
+            return;
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&query) {
+            self.entries.retain(|_, entry| entry.valid_until > now);
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&query) {
+            let soonest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.valid_until)
+                .map(|(query, _)| query.clone())
+                .expect("cache is full, hence not empty");
+            self.entries.remove(&soonest);
+        }
+        self.entries.insert(query, Entry { value, valid_until });
+    }
+
+    /// Removes the result of `query`, if cached.
+    pub(crate) fn remove(&mut self, query: &Query) {
+        self.entries.remove(query);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Resolved;
+    use std::{net::Ipv4Addr, time::Duration};
+
+    fn resolved(ip: [u8; 4]) -> Resolved<'static> {
+        Resolved::One(Protocol::Ip4(Ipv4Addr::from(ip)))
+    }
+
+    fn is(resolved: Option<Resolved<'_>>, ip: [u8; 4]) -> bool {
+        matches!(resolved, Some(Resolved::One(Protocol::Ip4(a))) if a == Ipv4Addr::from(ip))
+    }
+
+    #[test]
+    fn honours_validity_and_capacity() {
+        let now = Instant::now();
+        let mut cache = Cache::new(2);
+        let a = Query::Ipv4("a.example.com".into());
+        let b = Query::Ipv4("b.example.com".into());
+        let c = Query::Ipv4("c.example.com".into());
+
+        cache.insert(
+            a.clone(),
+            resolved([1, 1, 1, 1]),
+            now + Duration::from_secs(10),
+            now,
+        );
+        cache.insert(
+            b.clone(),
+            resolved([2, 2, 2, 2]),
+            now + Duration::from_secs(20),
+            now,
+        );
+        assert!(is(cache.get(&a, now), [1, 1, 1, 1]));
+        assert!(cache.get(&a, now + Duration::from_secs(10)).is_none());
+
+        // `a` expired and was removed, so there is room for `c`.
+        cache.insert(
+            c.clone(),
+            resolved([3, 3, 3, 3]),
+            now + Duration::from_secs(5),
+            now,
+        );
+        assert!(is(cache.get(&b, now), [2, 2, 2, 2]));
+        assert!(is(cache.get(&c, now), [3, 3, 3, 3]));
+
+        // The cache is full, so the entry expiring soonest, `c`, is evicted.
+        cache.insert(
+            a.clone(),
+            resolved([1, 1, 1, 1]),
+            now + Duration::from_secs(30),
+            now,
+        );
+        assert!(cache.get(&c, now).is_none());
+        assert!(is(cache.get(&a, now), [1, 1, 1, 1]));
+        assert!(is(cache.get(&b, now), [2, 2, 2, 2]));
+    }
+
+    #[test]
+    fn disabled_without_capacity() {
+        let now = Instant::now();
+        let mut cache = Cache::new(0);
+        let a = Query::Ipv4("a.example.com".into());
+
+        cache.insert(
+            a.clone(),
+            resolved([1, 1, 1, 1]),
+            now + Duration::from_secs(10),
+            now,
+        );
+        assert!(cache.get(&a, now).is_none());
+    }
+}
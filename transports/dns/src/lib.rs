@@ -35,7 +35,10 @@
 //! enabled by default. Tokio users can furthermore opt-in
 //! to the `tokio-dns-over-rustls` and `tokio-dns-over-https-rustls`
 //! features. For more information about these features, please
-//! refer to the documentation of [trust-dns-resolver].
+//! refer to the documentation of [trust-dns-resolver]. With the latter,
+//! `TokioDnsConfig::dns_over_https` resolves all names via a DNS-over-HTTPS
+//! server that is reached at fixed bootstrap IP addresses, bypassing the
+//! DNS servers of the local network.
 //!
 //! On Unix systems, if no custom configuration is given, [trust-dns-resolver]
 //! will try to parse the `/etc/resolv.conf` file. This approach comes with a
@@ -48,30 +51,38 @@
 //!      any system APIs (like libc's `gethostbyname`). Again this is
 //!      problematic on platforms like Android, where there's a lot of
 //!      complexity hidden behind the system APIs.
+//!
 //! If the implementation requires different characteristics, one should
-//! consider providing their own implementation of [`GenDnsConfig`] or use
-//! platform specific APIs to extract the host's DNS configuration (if possible)
-//! and provide a custom [`ResolverConfig`].
+//! consider providing their own [`Resolver`] to
+//! [`GenDnsConfig::with_resolver`] or use platform specific APIs to extract
+//! the host's DNS configuration (if possible) and provide a custom
+//! [`ResolverConfig`].
 //!
 //![trust-dns-resolver]: https://docs.rs/trust-dns-resolver/latest/trust_dns_resolver/#dns-over-tls-and-dns-over-https
 
 #![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
 
+mod cache;
+
 #[cfg(feature = "async-std")]
 use async_std_resolver::AsyncStdResolver;
 use async_trait::async_trait;
-use futures::{future::BoxFuture, prelude::*};
+use base64::Engine;
+use cache::{Cache, Query};
+use futures::{channel::mpsc, future::BoxFuture, prelude::*};
 use libp2p_core::{
     connection::Endpoint,
     multiaddr::{Multiaddr, Protocol},
     transport::{ListenerId, TransportError, TransportEvent},
-    Transport,
+    PeerRecord, SignedEnvelope, Subscribers, Transport,
 };
+use libp2p_identity::PeerId;
 use parking_lot::Mutex;
 use smallvec::SmallVec;
 use std::io;
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::{
+    collections::HashMap,
     convert::TryFrom,
     error, fmt, iter,
     ops::DerefMut,
@@ -79,22 +90,28 @@ use std::{
     str,
     sync::Arc,
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 #[cfg(any(feature = "async-std", feature = "tokio"))]
 use trust_dns_resolver::system_conf;
 #[cfg(feature = "tokio")]
 use trust_dns_resolver::TokioAsyncResolver;
 
+#[cfg(feature = "tokio-dns-over-https-rustls")]
+use trust_dns_resolver::config::NameServerConfigGroup;
+
 pub use trust_dns_resolver::config::{ResolverConfig, ResolverOpts};
 pub use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
-use trust_dns_resolver::lookup::{Ipv4Lookup, Ipv6Lookup, TxtLookup};
-use trust_dns_resolver::lookup_ip::LookupIp;
 use trust_dns_resolver::name_server::ConnectionProvider;
+use trust_dns_resolver::proto::op::ResponseCode;
 use trust_dns_resolver::AsyncResolver;
 
 /// The prefix for `dnsaddr` protocol TXT record lookups.
 const DNSADDR_PREFIX: &str = "_dnsaddr.";
 
+/// The prefix of TXT records of a `dnsaddr` that contain a signed peer record.
+const PEER_RECORD_PREFIX: &str = "peer-record=";
+
 /// The maximum number of dialing attempts to resolved addresses.
 const MAX_DIAL_ATTEMPTS: usize = 16;
 
@@ -127,6 +144,156 @@ pub struct GenDnsConfig<T, R> {
     inner: Arc<Mutex<T>>,
     /// The DNS resolver used when dialing addresses with DNS components.
     resolver: R,
+    /// The configuration of the DNS resolution.
+    config: Config,
+    /// The results of DNS lookups, for as long as they are valid.
+    cache: Arc<Mutex<Cache<Resolved<'static>>>>,
+    /// The DNS lookups that found a name not to exist, i.e. `NXDOMAIN` responses.
+    negative_cache: Arc<Mutex<Cache<ResolveError>>>,
+    /// The receivers of [`Event`]s.
+    subscribers: Subscribers<Event>,
+}
+
+impl<T, R> GenDnsConfig<T, R>
+where
+    R: Resolver,
+{
+    /// Creates a [`GenDnsConfig`] using the given [`Resolver`] for all DNS lookups.
+    pub fn with_resolver(inner: T, resolver: R) -> Self {
+        let config = Config::default();
+        GenDnsConfig {
+            inner: Arc::new(Mutex::new(inner)),
+            resolver,
+            cache: Arc::new(Mutex::new(Cache::new(config.cache_size))),
+            negative_cache: Arc::new(Mutex::new(Cache::new(config.negative_cache_size))),
+            config,
+            subscribers: Default::default(),
+        }
+    }
+
+    /// Sets the [`Config`] of the DNS resolution, discarding cached records.
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.cache = Arc::new(Mutex::new(Cache::new(config.cache_size)));
+        self.negative_cache = Arc::new(Mutex::new(Cache::new(config.negative_cache_size)));
+        self.config = config;
+        self
+    }
+
+    /// Returns a receiver of the [`Event`]s of the DNS resolution, see [`Subscribers`].
+    pub fn subscribe(&self) -> mpsc::Receiver<Event> {
+        self.subscribers.subscribe()
+    }
+}
+
+/// Configuration of the DNS resolution of a [`GenDnsConfig`].
+#[derive(Debug, Clone)]
+pub struct Config {
+    max_dnsaddr_depth: usize,
+    cache_size: usize,
+    negative_cache_size: usize,
+    negative_ttl: Duration,
+    verify_peer_records: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            max_dnsaddr_depth: MAX_DNS_LOOKUPS,
+            cache_size: 0,
+            negative_cache_size: 0,
+            negative_ttl: Duration::from_secs(60),
+            verify_peer_records: false,
+        }
+    }
+}
+
+impl Config {
+    /// Sets the maximum number of nested `/dnsaddr` lookups when resolving an address,
+    /// i.e. how many `/dnsaddr` indirections are followed.
+    ///
+    /// Regardless of this setting, no more than 32 DNS lookups are made per dial.
+    /// Defaults to 32.
+    pub fn with_max_dnsaddr_depth(mut self, depth: usize) -> Self {
+        self.max_dnsaddr_depth = depth;
+        self
+    }
+
+    /// Sets the number of DNS lookup results that are cached for as long as the TTL of
+    /// their records allows.
+    ///
+    /// If dialing the addresses obtained from records fails, the records are discarded,
+    /// also from the cache of the [`Resolver`], and looked up again for another attempt,
+    /// regardless of the size of this cache.
+    ///
+    /// Defaults to 0, i.e. no caching. Note that the resolvers of [trust-dns-resolver]
+    /// have a cache of their own, see [`ResolverOpts::cache_size`].
+    ///
+    /// [trust-dns-resolver]: https://docs.rs/trust-dns-resolver
+    pub fn with_cache_size(mut self, size: usize) -> Self {
+        self.cache_size = size;
+        self
+    }
+
+    /// Sets the number of names that are remembered not to exist, i.e. for which
+    /// a lookup failed with `NXDOMAIN`, so that dialing them fails without a lookup.
+    ///
+    /// Defaults to 0, i.e. no negative caching.
+    pub fn with_negative_cache_size(mut self, size: usize) -> Self {
+        self.negative_cache_size = size;
+        self
+    }
+
+    /// Sets for how long a name is remembered not to exist, unless the `NXDOMAIN`
+    /// response carries a TTL of its own.
+    ///
+    /// Defaults to 60 seconds.
+    pub fn with_negative_ttl(mut self, ttl: Duration) -> Self {
+        self.negative_ttl = ttl;
+        self
+    }
+
+    /// Sets whether the addresses obtained from a `/dnsaddr` are checked against
+    /// the signed peer records published along with them.
+    ///
+    /// A peer record is published as a TXT record `peer-record=<envelope>`, where
+    /// `<envelope>` is the base64-encoded [`SignedEnvelope`] of the [`PeerRecord`].
+    /// If enabled, records with an invalid signature are ignored and the addresses
+    /// of a peer with a valid record are only dialed if the record contains them.
+    /// Addresses of peers without a record are kept. Regardless of this setting,
+    /// addresses not ending in the peer ID of the dialed address, if any, are dropped.
+    ///
+    /// Defaults to `false`.
+    pub fn with_peer_record_verification(mut self, verify: bool) -> Self {
+        self.verify_peer_records = verify;
+        self
+    }
+}
+
+/// An event of the DNS resolution of a [`GenDnsConfig`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Event {
+    /// Resolving a dialed address was cut short, so some of the addresses it
+    /// resolves to were not dialed.
+    ResolutionTruncated {
+        /// The dialed address.
+        address: Multiaddr,
+        /// Why the resolution was cut short.
+        reason: Truncation,
+    },
+}
+
+/// The reason for an [`Event::ResolutionTruncated`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Truncation {
+    /// A `/dnsaddr` was not resolved, as it is nested deeper than the configured
+    /// maximum depth.
+    MaxDnsaddrDepth,
+    /// The maximum number of DNS lookups per dial was reached.
+    TooManyLookups,
+    /// A `/dnsaddr` lookup yielded more applicable TXT records than are considered.
+    TooManyTxtRecords,
 }
 
 #[cfg(feature = "async-std")]
@@ -147,10 +314,10 @@ where
         opts: ResolverOpts,
     ) -> Result<DnsConfig<T>, io::Error> {
         // TODO: Make infallible in next breaking release. Or deprecation?
-        Ok(DnsConfig {
-            inner: Arc::new(Mutex::new(inner)),
-            resolver: async_std_resolver::resolver(cfg, opts).await,
-        })
+        Ok(DnsConfig::with_resolver(
+            inner,
+            async_std_resolver::resolver(cfg, opts).await,
+        ))
     }
 }
 
@@ -173,10 +340,31 @@ where
         opts: ResolverOpts,
     ) -> Result<TokioDnsConfig<T>, io::Error> {
         // TODO: Make infallible in next breaking release. Or deprecation?
-        Ok(TokioDnsConfig {
-            inner: Arc::new(Mutex::new(inner)),
-            resolver: TokioAsyncResolver::tokio(cfg, opts),
-        })
+        Ok(TokioDnsConfig::with_resolver(
+            inner,
+            TokioAsyncResolver::tokio(cfg, opts),
+        ))
+    }
+
+    /// Creates a [`TokioDnsConfig`] resolving names via DNS-over-HTTPS.
+    ///
+    /// The DoH server is reached at the given `bootstrap` IP addresses and `port`,
+    /// and its certificate is verified against `server_name` (e.g. `cloudflare-dns.com`).
+    /// Since the server itself needs no name resolution, no queries are sent to the
+    /// DNS servers of the local network.
+    #[cfg(feature = "tokio-dns-over-https-rustls")]
+    pub fn dns_over_https(
+        inner: T,
+        bootstrap: &[IpAddr],
+        port: u16,
+        server_name: impl Into<String>,
+        opts: ResolverOpts,
+    ) -> TokioDnsConfig<T> {
+        let name_servers =
+            NameServerConfigGroup::from_ips_https(bootstrap, port, server_name.into(), true);
+        let cfg = ResolverConfig::from_parts(None, Vec::new(), name_servers);
+
+        TokioDnsConfig::with_resolver(inner, TokioAsyncResolver::tokio(cfg, opts))
     }
 }
 
@@ -252,6 +440,10 @@ where
     ) -> Result<<Self as Transport>::Dial, TransportError<<Self as Transport>::Error>> {
         let resolver = self.resolver.clone();
         let inner = self.inner.clone();
+        let cache = self.cache.clone();
+        let negative_cache = self.negative_cache.clone();
+        let subscribers = self.subscribers.clone();
+        let config = self.config.clone();
 
         // Asynchronlously resolve all DNS names in the address before proceeding
         // with dialing on the underlying transport.
@@ -259,117 +451,219 @@ where
             let mut last_err = None;
             let mut dns_lookups = 0;
             let mut dial_attempts = 0;
+            // Every reason for truncation is reported once per dial.
+            let mut truncations = SmallVec::<[Truncation; 1]>::new();
+            let mut truncate = |reason| {
+                if !truncations.contains(&reason) {
+                    truncations.push(reason);
+                    subscribers.emit(Event::ResolutionTruncated {
+                        address: addr.clone(),
+                        reason,
+                    });
+                }
+            };
+            // Whether dialing an address obtained from DNS records failed,
+            // in which case the records may be stale.
+            let mut stale = false;
+            // Whether the address is re-resolved without the caches.
+            let mut bypass_cache = false;
             // We optimise for the common case of a single DNS component
             // in the address that is resolved with a single lookup.
-            let mut unresolved = SmallVec::<[Multiaddr; 1]>::new();
-            unresolved.push(addr.clone());
-
-            // Resolve (i.e. replace) all DNS protocol components, initiating
-            // dialing attempts as soon as there is another fully resolved
-            // address.
-            while let Some(addr) = unresolved.pop() {
-                if let Some((i, name)) = addr.iter().enumerate().find(|(_, p)| {
-                    matches!(
-                        p,
-                        Protocol::Dns(_)
-                            | Protocol::Dns4(_)
-                            | Protocol::Dns6(_)
-                            | Protocol::Dnsaddr(_)
-                    )
-                }) {
-                    if dns_lookups == MAX_DNS_LOOKUPS {
-                        log::debug!("Too many DNS lookups. Dropping unresolved {}.", addr);
-                        last_err = Some(DnsErr::TooManyLookups);
-                        // There may still be fully resolved addresses in `unresolved`,
-                        // so keep going until `unresolved` is empty.
-                        continue;
-                    }
-                    dns_lookups += 1;
-                    match resolve(&name, &resolver).await {
-                        Err(e) => {
-                            if unresolved.is_empty() {
-                                return Err(e);
-                            }
-                            // If there are still unresolved addresses, there is
-                            // a chance of success, but we track the last error.
-                            last_err = Some(e);
+            let mut unresolved = SmallVec::<[Candidate; 1]>::new();
+            unresolved.push(Candidate::new(addr.clone()));
+
+            loop {
+                // Resolve (i.e. replace) all DNS protocol components, initiating
+                // dialing attempts as soon as there is another fully resolved
+                // address.
+                while let Some(candidate) = unresolved.pop() {
+                    let Candidate {
+                        addr,
+                        dnsaddr_depth,
+                        lookups,
+                    } = candidate;
+                    if let Some((i, name, query)) = addr
+                        .iter()
+                        .enumerate()
+                        .find_map(|(i, p)| Query::from_protocol(&p).map(|q| (i, p, q)))
+                    {
+                        if dns_lookups == MAX_DNS_LOOKUPS {
+                            log::debug!("Too many DNS lookups. Dropping unresolved {}.", addr);
+                            truncate(Truncation::TooManyLookups);
+                            last_err = Some(DnsErr::TooManyLookups);
+                            // There may still be fully resolved addresses in `unresolved`,
+                            // so keep going until `unresolved` is empty.
+                            continue;
                         }
-                        Ok(Resolved::One(ip)) => {
-                            log::trace!("Resolved {} -> {}", name, ip);
-                            let addr = addr.replace(i, |_| Some(ip)).expect("`i` is a valid index");
-                            unresolved.push(addr);
+                        if matches!(name, Protocol::Dnsaddr(_))
+                            && dnsaddr_depth >= config.max_dnsaddr_depth
+                        {
+                            log::debug!(
+                                "Too deeply nested /dnsaddr. Dropping unresolved {}.",
+                                addr
+                            );
+                            truncate(Truncation::MaxDnsaddrDepth);
+                            last_err = Some(DnsErr::TooManyLookups);
+                            continue;
                         }
-                        Ok(Resolved::Many(ips)) => {
-                            for ip in ips {
+                        dns_lookups += 1;
+
+                        let mut lookups = lookups;
+                        lookups.push(query.clone());
+                        let nx_domain = negative_cache.lock().get(&query, Instant::now());
+                        let cached_resolved = if bypass_cache {
+                            None
+                        } else {
+                            cache.lock().get(&query, Instant::now())
+                        };
+                        let resolved = if let Some(e) = nx_domain {
+                            log::trace!("{} is known not to exist", name);
+                            Err(DnsErr::ResolveError(e))
+                        } else if let Some(resolved) = cached_resolved {
+                            Ok(resolved)
+                        } else {
+                            match resolve(&name, &resolver, config.verify_peer_records).await {
+                                Ok((resolved, valid_until)) => {
+                                    if let Some(valid_until) = valid_until {
+                                        cache.lock().insert(
+                                            query,
+                                            resolved.clone().into_owned(),
+                                            valid_until,
+                                            Instant::now(),
+                                        );
+                                    }
+                                    Ok(resolved)
+                                }
+                                Err(DnsErr::ResolveError(e)) => {
+                                    if let Some(ttl) = nx_domain_ttl(&e, config.negative_ttl) {
+                                        negative_cache.lock().insert(
+                                            query,
+                                            e.clone(),
+                                            Instant::now() + ttl,
+                                            Instant::now(),
+                                        );
+                                    }
+                                    Err(DnsErr::ResolveError(e))
+                                }
+                                Err(e) => Err(e),
+                            }
+                        };
+                        match resolved {
+                            Err(e) => {
+                                // If there are still unresolved addresses, there is
+                                // a chance of success, but we track the last error.
+                                last_err = Some(e);
+                            }
+                            Ok(Resolved::One(ip)) => {
                                 log::trace!("Resolved {} -> {}", name, ip);
                                 let addr =
                                     addr.replace(i, |_| Some(ip)).expect("`i` is a valid index");
-                                unresolved.push(addr);
+                                unresolved.push(Candidate {
+                                    addr,
+                                    dnsaddr_depth,
+                                    lookups,
+                                });
                             }
-                        }
-                        Ok(Resolved::Addrs(addrs)) => {
-                            let suffix = addr.iter().skip(i + 1).collect::<Multiaddr>();
-                            let prefix = addr.iter().take(i).collect::<Multiaddr>();
-                            let mut n = 0;
-                            for a in addrs {
-                                if a.ends_with(&suffix) {
-                                    if n < MAX_TXT_RECORDS {
-                                        n += 1;
-                                        log::trace!("Resolved {} -> {}", name, a);
-                                        let addr =
-                                            prefix.iter().chain(a.iter()).collect::<Multiaddr>();
-                                        unresolved.push(addr);
-                                    } else {
-                                        log::debug!(
-                                            "Too many TXT records. Dropping resolved {}.",
-                                            a
-                                        );
+                            Ok(Resolved::Many(ips)) => {
+                                for ip in ips {
+                                    log::trace!("Resolved {} -> {}", name, ip);
+                                    let addr = addr
+                                        .replace(i, |_| Some(ip))
+                                        .expect("`i` is a valid index");
+                                    unresolved.push(Candidate {
+                                        addr,
+                                        dnsaddr_depth,
+                                        lookups: lookups.clone(),
+                                    });
+                                }
+                            }
+                            Ok(Resolved::Addrs(addrs)) => {
+                                let suffix = addr.iter().skip(i + 1).collect::<Multiaddr>();
+                                let prefix = addr.iter().take(i).collect::<Multiaddr>();
+                                let mut n = 0;
+                                for a in addrs {
+                                    if a.ends_with(&suffix) {
+                                        if n < MAX_TXT_RECORDS {
+                                            n += 1;
+                                            log::trace!("Resolved {} -> {}", name, a);
+                                            let addr = prefix
+                                                .iter()
+                                                .chain(a.iter())
+                                                .collect::<Multiaddr>();
+                                            unresolved.push(Candidate {
+                                                addr,
+                                                dnsaddr_depth: dnsaddr_depth + 1,
+                                                lookups: lookups.clone(),
+                                            });
+                                        } else {
+                                            log::debug!(
+                                                "Too many TXT records. Dropping resolved {}.",
+                                                a
+                                            );
+                                            truncate(Truncation::TooManyTxtRecords);
+                                        }
                                     }
                                 }
                             }
                         }
-                    }
-                } else {
-                    // We have a fully resolved address, so try to dial it.
-                    log::debug!("Dialing {}", addr);
-
-                    let transport = inner.clone();
-                    let dial = match role_override {
-                        Endpoint::Dialer => transport.lock().dial(addr),
-                        Endpoint::Listener => transport.lock().dial_as_listener(addr),
-                    };
-                    let result = match dial {
-                        Ok(out) => {
-                            // We only count attempts that the inner transport
-                            // actually accepted, i.e. for which it produced
-                            // a dialing future.
-                            dial_attempts += 1;
-                            out.await.map_err(DnsErr::Transport)
-                        }
-                        Err(TransportError::MultiaddrNotSupported(a)) => {
-                            Err(DnsErr::MultiaddrNotSupported(a))
-                        }
-                        Err(TransportError::Other(err)) => Err(DnsErr::Transport(err)),
-                    };
-
-                    match result {
-                        Ok(out) => return Ok(out),
-                        Err(err) => {
-                            log::debug!("Dial error: {:?}.", err);
-                            if unresolved.is_empty() {
-                                return Err(err);
+                    } else {
+                        // We have a fully resolved address, so try to dial it.
+                        log::debug!("Dialing {}", addr);
+
+                        let transport = inner.clone();
+                        let dial = match role_override {
+                            Endpoint::Dialer => transport.lock().dial(addr),
+                            Endpoint::Listener => transport.lock().dial_as_listener(addr),
+                        };
+                        let result = match dial {
+                            Ok(out) => {
+                                // We only count attempts that the inner transport
+                                // actually accepted, i.e. for which it produced
+                                // a dialing future.
+                                dial_attempts += 1;
+                                out.await.map_err(DnsErr::Transport)
                             }
-                            if dial_attempts == MAX_DIAL_ATTEMPTS {
-                                log::debug!(
-                                    "Aborting dialing after {} attempts.",
-                                    MAX_DIAL_ATTEMPTS
-                                );
-                                return Err(err);
+                            Err(TransportError::MultiaddrNotSupported(a)) => {
+                                Err(DnsErr::MultiaddrNotSupported(a))
+                            }
+                            Err(TransportError::Other(err)) => Err(DnsErr::Transport(err)),
+                        };
+
+                        match result {
+                            Ok(out) => return Ok(out),
+                            Err(err) => {
+                                log::debug!("Dial error: {:?}.", err);
+                                if !lookups.is_empty() {
+                                    // The records the address was obtained from may be
+                                    // outdated, so they are looked up again.
+                                    stale = true;
+                                    let mut cache = cache.lock();
+                                    for query in &lookups {
+                                        cache.remove(query);
+                                        resolver.discard_cached(&query.name());
+                                    }
+                                }
+                                if dial_attempts == MAX_DIAL_ATTEMPTS {
+                                    log::debug!(
+                                        "Aborting dialing after {} attempts.",
+                                        MAX_DIAL_ATTEMPTS
+                                    );
+                                    return Err(err);
+                                }
+                                last_err = Some(err);
                             }
-                            last_err = Some(err);
                         }
                     }
                 }
+
+                if !stale || bypass_cache {
+                    break;
+                }
+                // Dialing addresses from DNS records failed, so try once more
+                // with fresh records.
+                log::debug!("Re-resolving {} without cached records.", addr);
+                bypass_cache = true;
+                unresolved.push(Candidate::new(addr.clone()));
             }
 
             // At this point, if there was at least one failed dialing
@@ -385,6 +679,37 @@ where
     }
 }
 
+/// An address being resolved when dialing.
+struct Candidate {
+    addr: Multiaddr,
+    /// The number of `/dnsaddr` indirections followed to obtain `addr`.
+    dnsaddr_depth: usize,
+    /// The lookups whose results were used to obtain `addr`.
+    lookups: SmallVec<[Query; 1]>,
+}
+
+impl Candidate {
+    fn new(addr: Multiaddr) -> Self {
+        Candidate {
+            addr,
+            dnsaddr_depth: 0,
+            lookups: SmallVec::new(),
+        }
+    }
+}
+
+/// Returns for how long a name is known not to exist if `e` is an `NXDOMAIN` response.
+fn nx_domain_ttl(e: &ResolveError, default: Duration) -> Option<Duration> {
+    match e.kind() {
+        ResolveErrorKind::NoRecordsFound {
+            response_code: ResponseCode::NXDomain,
+            negative_ttl,
+            ..
+        } => Some(negative_ttl.map_or(default, |ttl| Duration::from_secs(ttl.into()))),
+        _ => None,
+    }
+}
+
 /// The possible errors of a [`GenDnsConfig`] wrapped transport.
 #[derive(Debug)]
 #[allow(clippy::large_enum_variant)]
@@ -397,10 +722,11 @@ pub enum DnsErr<TErr> {
     MultiaddrNotSupported(Multiaddr),
     /// DNS resolution involved too many lookups.
     ///
-    /// DNS resolution on dialing performs up to 32 DNS lookups. If these
-    /// are not sufficient to obtain a fully-resolved address, this error
-    /// is returned and the DNS records for the domain(s) being dialed
-    /// should be investigated.
+    /// DNS resolution on dialing performs up to 32 DNS lookups and follows
+    /// `/dnsaddr` indirections up to the configured depth, see
+    /// [`Config::with_max_dnsaddr_depth`]. If these are not sufficient to
+    /// obtain a fully-resolved address, this error is returned and the DNS
+    /// records for the domain(s) being dialed should be investigated.
     TooManyLookups,
 }
 
@@ -433,6 +759,7 @@ where
 }
 
 /// The successful outcome of [`resolve`] for a given [`Protocol`].
+#[derive(Debug, Clone)]
 enum Resolved<'a> {
     /// The given `Protocol` has been resolved to a single `Protocol`,
     /// which may be identical to the one given, in case it is not
@@ -447,84 +774,42 @@ enum Resolved<'a> {
     Addrs(Vec<Multiaddr>),
 }
 
+impl Resolved<'_> {
+    fn into_owned(self) -> Resolved<'static> {
+        match self {
+            Resolved::One(p) => Resolved::One(p.acquire()),
+            Resolved::Many(ps) => Resolved::Many(ps.into_iter().map(Protocol::acquire).collect()),
+            Resolved::Addrs(addrs) => Resolved::Addrs(addrs),
+        }
+    }
+}
+
+/// A [`Resolved`] protocol along with the time until which it may be cached, if at all.
+type Resolution<'a> = (Resolved<'a>, Option<Instant>);
+
 /// Asynchronously resolves the domain name of a `Dns`, `Dns4`, `Dns6` or `Dnsaddr` protocol
 /// component. If the given protocol is of a different type, it is returned unchanged as a
 /// [`Resolved::One`].
+///
+/// If `verify_peer_records` is set, the addresses of a `Dnsaddr` of peers that published
+/// a signed peer record are only kept if contained in the record.
 fn resolve<'a, E: 'a + Send, R: Resolver>(
     proto: &Protocol<'a>,
     resolver: &'a R,
-) -> BoxFuture<'a, Result<Resolved<'a>, DnsErr<E>>> {
+    verify_peer_records: bool,
+) -> BoxFuture<'a, Result<Resolution<'a>, DnsErr<E>>> {
     match proto {
         Protocol::Dns(ref name) => resolver
             .lookup_ip(name.clone().into_owned())
-            .map(move |res| match res {
-                Ok(ips) => {
-                    let mut ips = ips.into_iter();
-                    let one = ips
-                        .next()
-                        .expect("If there are no results, `Err(NoRecordsFound)` is expected.");
-                    if let Some(two) = ips.next() {
-                        Ok(Resolved::Many(
-                            iter::once(one)
-                                .chain(iter::once(two))
-                                .chain(ips)
-                                .map(Protocol::from)
-                                .collect(),
-                        ))
-                    } else {
-                        Ok(Resolved::One(Protocol::from(one)))
-                    }
-                }
-                Err(e) => Err(DnsErr::ResolveError(e)),
-            })
+            .map(|res| resolved_ips(res.map_err(DnsErr::ResolveError)?))
             .boxed(),
         Protocol::Dns4(ref name) => resolver
             .ipv4_lookup(name.clone().into_owned())
-            .map(move |res| match res {
-                Ok(ips) => {
-                    let mut ips = ips.into_iter();
-                    let one = ips
-                        .next()
-                        .expect("If there are no results, `Err(NoRecordsFound)` is expected.");
-                    if let Some(two) = ips.next() {
-                        Ok(Resolved::Many(
-                            iter::once(one)
-                                .chain(iter::once(two))
-                                .chain(ips)
-                                .map(Ipv4Addr::from)
-                                .map(Protocol::from)
-                                .collect(),
-                        ))
-                    } else {
-                        Ok(Resolved::One(Protocol::from(Ipv4Addr::from(one))))
-                    }
-                }
-                Err(e) => Err(DnsErr::ResolveError(e)),
-            })
+            .map(|res| resolved_ips(res.map_err(DnsErr::ResolveError)?))
             .boxed(),
         Protocol::Dns6(ref name) => resolver
             .ipv6_lookup(name.clone().into_owned())
-            .map(move |res| match res {
-                Ok(ips) => {
-                    let mut ips = ips.into_iter();
-                    let one = ips
-                        .next()
-                        .expect("If there are no results, `Err(NoRecordsFound)` is expected.");
-                    if let Some(two) = ips.next() {
-                        Ok(Resolved::Many(
-                            iter::once(one)
-                                .chain(iter::once(two))
-                                .chain(ips)
-                                .map(Ipv6Addr::from)
-                                .map(Protocol::from)
-                                .collect(),
-                        ))
-                    } else {
-                        Ok(Resolved::One(Protocol::from(Ipv6Addr::from(one))))
-                    }
-                }
-                Err(e) => Err(DnsErr::ResolveError(e)),
-            })
+            .map(|res| resolved_ips(res.map_err(DnsErr::ResolveError)?))
             .boxed(),
         Protocol::Dnsaddr(ref name) => {
             let name = [DNSADDR_PREFIX, name].concat();
@@ -533,29 +818,104 @@ fn resolve<'a, E: 'a + Send, R: Resolver>(
                 .map(move |res| match res {
                     Ok(txts) => {
                         let mut addrs = Vec::new();
-                        for txt in txts {
-                            if let Some(chars) = txt.txt_data().first() {
-                                match parse_dnsaddr_txt(chars) {
+                        let mut peer_records = HashMap::new();
+                        for txt in txts.records {
+                            if txt.starts_with(PEER_RECORD_PREFIX.as_bytes()) {
+                                if !verify_peer_records {
+                                    continue;
+                                }
+                                match parse_peer_record_txt(&txt) {
                                     Err(e) => {
-                                        // Skip over seemingly invalid entries.
-                                        log::debug!("Invalid TXT record: {:?}", e);
+                                        log::debug!("Invalid peer record: {:?}", e);
                                     }
-                                    Ok(a) => {
-                                        addrs.push(a);
+                                    Ok(record) => {
+                                        peer_records.insert(record.peer_id(), record);
                                     }
                                 }
+                                continue;
                             }
+                            match parse_dnsaddr_txt(&txt) {
+                                Err(e) => {
+                                    // Skip over seemingly invalid entries.
+                                    log::debug!("Invalid TXT record: {:?}", e);
+                                }
+                                Ok(a) => {
+                                    addrs.push(a);
+                                }
+                            }
+                        }
+                        if !peer_records.is_empty() {
+                            addrs.retain(|a| {
+                                let recorded = is_recorded(a, &peer_records);
+                                if !recorded {
+                                    log::debug!("Dropping {} missing from its peer record.", a);
+                                }
+                                recorded
+                            });
                         }
-                        Ok(Resolved::Addrs(addrs))
+                        Ok((Resolved::Addrs(addrs), txts.valid_until))
                     }
                     Err(e) => Err(DnsErr::ResolveError(e)),
                 })
                 .boxed()
         }
-        proto => future::ready(Ok(Resolved::One(proto.clone()))).boxed(),
+        proto => future::ready(Ok((Resolved::One(proto.clone()), None))).boxed(),
     }
 }
 
+/// Turns the records of an address lookup into a [`Resolved::One`] or [`Resolved::Many`].
+fn resolved_ips<'a, A, E>(records: Records<A>) -> Result<Resolution<'a>, DnsErr<E>>
+where
+    Protocol<'a>: From<A>,
+{
+    let mut ips = records.records.into_iter();
+    let one = ips.next().ok_or_else(no_records_found)?;
+    let resolved = if let Some(two) = ips.next() {
+        Resolved::Many(
+            iter::once(one)
+                .chain(iter::once(two))
+                .chain(ips)
+                .map(Protocol::from)
+                .collect(),
+        )
+    } else {
+        Resolved::One(Protocol::from(one))
+    };
+
+    Ok((resolved, records.valid_until))
+}
+
+/// Whether the peer record of the peer that `addr` ends with contains `addr`, if there is one.
+fn is_recorded(addr: &Multiaddr, peer_records: &HashMap<PeerId, PeerRecord>) -> bool {
+    let mut addr = addr.clone();
+    let peer_id = match addr.pop() {
+        Some(Protocol::P2p(peer_id)) => peer_id,
+        _ => return true,
+    };
+    match peer_records.get(&peer_id) {
+        Some(record) => {
+            let with_peer_id = addr.clone().with(Protocol::P2p(peer_id));
+            record
+                .addresses()
+                .iter()
+                .any(|a| a == &addr || a == &with_peer_id)
+        }
+        None => true,
+    }
+}
+
+/// Parses a `peer-record` TXT record into the signed peer record it contains.
+fn parse_peer_record_txt(txt: &[u8]) -> io::Result<PeerRecord> {
+    let encoded = txt
+        .strip_prefix(PEER_RECORD_PREFIX.as_bytes())
+        .ok_or_else(|| invalid_data("Missing `peer-record=` prefix."))?;
+    let envelope = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(invalid_data)?;
+    let envelope = SignedEnvelope::from_protobuf_encoding(&envelope).map_err(invalid_data)?;
+    PeerRecord::from_signed_envelope(envelope).map_err(invalid_data)
+}
+
 /// Parses a `<character-string>` of a `dnsaddr` TXT record.
 fn parse_dnsaddr_txt(txt: &[u8]) -> io::Result<Multiaddr> {
     let s = str::from_utf8(txt).map_err(invalid_data)?;
@@ -565,17 +925,59 @@ fn parse_dnsaddr_txt(txt: &[u8]) -> io::Result<Multiaddr> {
     }
 }
 
+fn no_records_found<E>() -> DnsErr<E> {
+    DnsErr::ResolveError(ResolveErrorKind::Message("No records found.").into())
+}
+
 fn invalid_data(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
     io::Error::new(io::ErrorKind::InvalidData, e)
 }
 
-#[async_trait::async_trait]
-#[doc(hidden)]
+/// The DNS lookups needed by a [`GenDnsConfig`].
+///
+/// Implemented for the resolvers of [trust-dns-resolver], and can be implemented
+/// to plug in a different resolver, e.g. a caching service or a mock in tests.
+/// A lookup yielding no records may either return no [`Records`] or an error.
+///
+/// [trust-dns-resolver]: https://docs.rs/trust-dns-resolver
+#[async_trait]
 pub trait Resolver {
-    async fn lookup_ip(&self, name: String) -> Result<LookupIp, ResolveError>;
-    async fn ipv4_lookup(&self, name: String) -> Result<Ipv4Lookup, ResolveError>;
-    async fn ipv6_lookup(&self, name: String) -> Result<Ipv6Lookup, ResolveError>;
-    async fn txt_lookup(&self, name: String) -> Result<TxtLookup, ResolveError>;
+    /// Looks up the IPv4 and IPv6 addresses of `name`.
+    async fn lookup_ip(&self, name: String) -> Result<Records<IpAddr>, ResolveError>;
+    /// Looks up the IPv4 addresses, i.e. A records, of `name`.
+    async fn ipv4_lookup(&self, name: String) -> Result<Records<Ipv4Addr>, ResolveError>;
+    /// Looks up the IPv6 addresses, i.e. AAAA records, of `name`.
+    async fn ipv6_lookup(&self, name: String) -> Result<Records<Ipv6Addr>, ResolveError>;
+    /// Looks up the TXT records of `name`, returning the data of each record,
+    /// i.e. its `<character-string>`s concatenated.
+    async fn txt_lookup(&self, name: String) -> Result<Records<Vec<u8>>, ResolveError>;
+    /// Discards the records of `name` cached by the resolver, if any, as dialing the
+    /// addresses obtained from them failed.
+    ///
+    /// The resolvers of [trust-dns-resolver] discard all cached records, as they can't
+    /// discard the records of a single name. Does nothing by default.
+    ///
+    /// [trust-dns-resolver]: https://docs.rs/trust-dns-resolver
+    fn discard_cached(&self, _name: &str) {}
+}
+
+/// The records found by a [`Resolver`] lookup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Records<T> {
+    /// The records, i.e. addresses or TXT data.
+    pub records: Vec<T>,
+    /// The time until which the records are valid according to their TTL,
+    /// or `None` if they are not to be cached.
+    pub valid_until: Option<Instant>,
+}
+
+impl<T> From<Vec<T>> for Records<T> {
+    fn from(records: Vec<T>) -> Self {
+        Records {
+            records,
+            valid_until: None,
+        }
+    }
 }
 
 #[async_trait]
@@ -583,20 +985,43 @@ impl<C> Resolver for AsyncResolver<C>
 where
     C: ConnectionProvider,
 {
-    async fn lookup_ip(&self, name: String) -> Result<LookupIp, ResolveError> {
-        self.lookup_ip(name).await
+    async fn lookup_ip(&self, name: String) -> Result<Records<IpAddr>, ResolveError> {
+        let lookup = self.lookup_ip(name).await?;
+        Ok(Records {
+            valid_until: Some(lookup.valid_until()),
+            records: lookup.into_iter().collect(),
+        })
     }
 
-    async fn ipv4_lookup(&self, name: String) -> Result<Ipv4Lookup, ResolveError> {
-        self.ipv4_lookup(name).await
+    async fn ipv4_lookup(&self, name: String) -> Result<Records<Ipv4Addr>, ResolveError> {
+        let lookup = self.ipv4_lookup(name).await?;
+        Ok(Records {
+            valid_until: Some(lookup.valid_until()),
+            records: lookup.into_iter().map(Ipv4Addr::from).collect(),
+        })
     }
 
-    async fn ipv6_lookup(&self, name: String) -> Result<Ipv6Lookup, ResolveError> {
-        self.ipv6_lookup(name).await
+    async fn ipv6_lookup(&self, name: String) -> Result<Records<Ipv6Addr>, ResolveError> {
+        let lookup = self.ipv6_lookup(name).await?;
+        Ok(Records {
+            valid_until: Some(lookup.valid_until()),
+            records: lookup.into_iter().map(Ipv6Addr::from).collect(),
+        })
     }
 
-    async fn txt_lookup(&self, name: String) -> Result<TxtLookup, ResolveError> {
-        self.txt_lookup(name).await
+    async fn txt_lookup(&self, name: String) -> Result<Records<Vec<u8>>, ResolveError> {
+        let lookup = self.txt_lookup(name).await?;
+        Ok(Records {
+            valid_until: Some(lookup.valid_until()),
+            records: lookup
+                .into_iter()
+                .map(|txt| txt.txt_data().concat())
+                .collect(),
+        })
+    }
+
+    fn discard_cached(&self, _: &str) {
+        self.clear_cache();
     }
 }
 
@@ -611,6 +1036,109 @@ mod tests {
     };
     use libp2p_identity::PeerId;
 
+    /// Checks that dialed addresses are fully resolved.
+    #[derive(Clone)]
+    struct MockTransport;
+
+    impl Transport for MockTransport {
+        type Output = ();
+        type Error = std::io::Error;
+        type ListenerUpgrade = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+        type Dial = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+        fn listen_on(
+            &mut self,
+            _: ListenerId,
+            _: Multiaddr,
+        ) -> Result<(), TransportError<Self::Error>> {
+            unreachable!()
+        }
+
+        fn remove_listener(&mut self, _: ListenerId) -> bool {
+            false
+        }
+
+        fn dial(&mut self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+            // Check that all DNS components have been resolved, i.e. replaced.
+            assert!(!addr.iter().any(|p| matches!(
+                p,
+                Protocol::Dns(_) | Protocol::Dns4(_) | Protocol::Dns6(_) | Protocol::Dnsaddr(_)
+            )));
+            Ok(Box::pin(future::ready(Ok(()))))
+        }
+
+        fn dial_as_listener(
+            &mut self,
+            addr: Multiaddr,
+        ) -> Result<Self::Dial, TransportError<Self::Error>> {
+            self.dial(addr)
+        }
+
+        fn address_translation(&self, _: &Multiaddr, _: &Multiaddr) -> Option<Multiaddr> {
+            None
+        }
+
+        fn poll(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<TransportEvent<Self::ListenerUpgrade, Self::Error>> {
+            unreachable!()
+        }
+    }
+
+    /// Like [`MockTransport`], but refuses connections to unspecified addresses.
+    #[derive(Clone)]
+    struct RefusingTransport;
+
+    impl Transport for RefusingTransport {
+        type Output = ();
+        type Error = std::io::Error;
+        type ListenerUpgrade = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+        type Dial = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+        fn listen_on(
+            &mut self,
+            _: ListenerId,
+            _: Multiaddr,
+        ) -> Result<(), TransportError<Self::Error>> {
+            unreachable!()
+        }
+
+        fn remove_listener(&mut self, _: ListenerId) -> bool {
+            false
+        }
+
+        fn dial(&mut self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+            if addr
+                .iter()
+                .any(|p| p == Protocol::Ip4(Ipv4Addr::UNSPECIFIED))
+            {
+                return Ok(Box::pin(future::ready(Err(
+                    std::io::ErrorKind::ConnectionRefused.into(),
+                ))));
+            }
+            MockTransport.dial(addr)
+        }
+
+        fn dial_as_listener(
+            &mut self,
+            addr: Multiaddr,
+        ) -> Result<Self::Dial, TransportError<Self::Error>> {
+            self.dial(addr)
+        }
+
+        fn address_translation(&self, _: &Multiaddr, _: &Multiaddr) -> Option<Multiaddr> {
+            None
+        }
+
+        fn poll(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<TransportEvent<Self::ListenerUpgrade, Self::Error>> {
+            unreachable!()
+        }
+    }
+
     #[test]
     fn basic_resolve() {
         let _ = env_logger::try_init();
@@ -766,4 +1294,316 @@ mod tests {
             ));
         }
     }
+
+    #[test]
+    fn custom_resolver() {
+        #[derive(Clone)]
+        struct MockResolver;
+
+        #[async_trait]
+        impl Resolver for MockResolver {
+            async fn lookup_ip(&self, name: String) -> Result<Records<IpAddr>, ResolveError> {
+                match name.as_str() {
+                    "example.com" => {
+                        Ok(vec![[1, 2, 3, 4].into(), Ipv6Addr::LOCALHOST.into()].into())
+                    }
+                    _ => Ok(Vec::new().into()),
+                }
+            }
+
+            async fn ipv4_lookup(&self, name: String) -> Result<Records<Ipv4Addr>, ResolveError> {
+                match name.as_str() {
+                    "example.com" => Ok(vec![[1, 2, 3, 4].into()].into()),
+                    _ => Err(ResolveErrorKind::Message("unknown name").into()),
+                }
+            }
+
+            async fn ipv6_lookup(&self, _: String) -> Result<Records<Ipv6Addr>, ResolveError> {
+                Ok(Vec::new().into())
+            }
+
+            async fn txt_lookup(&self, name: String) -> Result<Records<Vec<u8>>, ResolveError> {
+                match name.as_str() {
+                    "_dnsaddr.example.com" => Ok(vec![
+                        b"dnsaddr=/dns4/example.com/tcp/20000".to_vec(),
+                        b"not a dnsaddr".to_vec(),
+                    ]
+                    .into()),
+                    _ => Ok(Vec::new().into()),
+                }
+            }
+        }
+
+        let mut transport = GenDnsConfig::with_resolver(MockTransport, MockResolver);
+
+        futures::executor::block_on(async {
+            transport
+                .dial("/dns/example.com/tcp/20000".parse().unwrap())
+                .unwrap()
+                .await
+                .unwrap();
+            transport
+                .dial("/dnsaddr/example.com".parse().unwrap())
+                .unwrap()
+                .await
+                .unwrap();
+
+            // Empty lookup results are an error, just like lookup failures.
+            assert!(matches!(
+                transport
+                    .dial("/dns6/example.com/tcp/20000".parse().unwrap())
+                    .unwrap()
+                    .await,
+                Err(DnsErr::ResolveError(_))
+            ));
+            assert!(matches!(
+                transport
+                    .dial("/dns4/example.invalid/tcp/20000".parse().unwrap())
+                    .unwrap()
+                    .await,
+                Err(DnsErr::ResolveError(_))
+            ));
+        });
+    }
+
+    #[test]
+    fn dnsaddr_depth_and_caching() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+
+        /// Resolves `/dnsaddr/a.example.com` via `/dnsaddr/b.example.com`, counting lookups.
+        #[derive(Clone, Default)]
+        struct NestedResolver(Arc<AtomicUsize>);
+
+        #[async_trait]
+        impl Resolver for NestedResolver {
+            async fn lookup_ip(&self, _: String) -> Result<Records<IpAddr>, ResolveError> {
+                unreachable!()
+            }
+
+            async fn ipv4_lookup(&self, _: String) -> Result<Records<Ipv4Addr>, ResolveError> {
+                unreachable!()
+            }
+
+            async fn ipv6_lookup(&self, _: String) -> Result<Records<Ipv6Addr>, ResolveError> {
+                unreachable!()
+            }
+
+            async fn txt_lookup(&self, name: String) -> Result<Records<Vec<u8>>, ResolveError> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                let txt: &[u8] = match name.as_str() {
+                    "_dnsaddr.a.example.com" => b"dnsaddr=/dnsaddr/b.example.com",
+                    "_dnsaddr.b.example.com" => b"dnsaddr=/ip4/1.2.3.4/tcp/20000",
+                    _ => return Ok(Vec::new().into()),
+                };
+                Ok(Records {
+                    records: vec![txt.to_vec()],
+                    valid_until: Some(Instant::now() + Duration::from_secs(60)),
+                })
+            }
+        }
+
+        let addr: Multiaddr = "/dnsaddr/a.example.com".parse().unwrap();
+
+        futures::executor::block_on(async {
+            let mut transport =
+                GenDnsConfig::with_resolver(MockTransport, NestedResolver::default())
+                    .with_config(Config::default().with_max_dnsaddr_depth(1));
+            let mut events = transport.subscribe();
+            assert!(matches!(
+                transport.dial(addr.clone()).unwrap().await,
+                Err(DnsErr::TooManyLookups)
+            ));
+            assert_eq!(
+                events.next().await,
+                Some(Event::ResolutionTruncated {
+                    address: addr.clone(),
+                    reason: Truncation::MaxDnsaddrDepth
+                })
+            );
+
+            let resolver = NestedResolver::default();
+            let mut transport = GenDnsConfig::with_resolver(MockTransport, resolver.clone())
+                .with_config(Config::default().with_cache_size(8));
+            transport.dial(addr.clone()).unwrap().await.unwrap();
+            transport.dial(addr.clone()).unwrap().await.unwrap();
+            // The second dial is resolved from the cache.
+            assert_eq!(resolver.0.load(Ordering::SeqCst), 2);
+        });
+    }
+
+    #[test]
+    fn re_resolve_and_negative_caching() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+
+        /// Moves `example.com` from an unreachable to a reachable address after the
+        /// first lookup, while `gone.example.com` does not exist. Counts the lookups
+        /// and the discarded names.
+        #[derive(Clone, Default)]
+        struct MovingResolver(Arc<AtomicUsize>, Arc<AtomicUsize>);
+
+        #[async_trait]
+        impl Resolver for MovingResolver {
+            async fn lookup_ip(&self, _: String) -> Result<Records<IpAddr>, ResolveError> {
+                unreachable!()
+            }
+
+            async fn ipv4_lookup(&self, name: String) -> Result<Records<Ipv4Addr>, ResolveError> {
+                let lookups = self.0.fetch_add(1, Ordering::SeqCst);
+                if name == "gone.example.com" {
+                    return Err(ResolveErrorKind::NoRecordsFound {
+                        query: Box::default(),
+                        soa: None,
+                        negative_ttl: None,
+                        response_code: ResponseCode::NXDomain,
+                        trusted: true,
+                    }
+                    .into());
+                }
+                let ip = if lookups == 0 {
+                    Ipv4Addr::UNSPECIFIED
+                } else {
+                    Ipv4Addr::new(1, 2, 3, 4)
+                };
+                Ok(Records {
+                    records: vec![ip],
+                    valid_until: Some(Instant::now() + Duration::from_secs(60)),
+                })
+            }
+
+            async fn ipv6_lookup(&self, _: String) -> Result<Records<Ipv6Addr>, ResolveError> {
+                unreachable!()
+            }
+
+            async fn txt_lookup(&self, _: String) -> Result<Records<Vec<u8>>, ResolveError> {
+                unreachable!()
+            }
+
+            fn discard_cached(&self, name: &str) {
+                assert_eq!(name, "example.com");
+                self.1.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let addr: Multiaddr = "/dns4/example.com/tcp/20000".parse().unwrap();
+
+        // Without a cache, the records of the resolver are discarded and looked up again.
+        let resolver = MovingResolver::default();
+        let mut transport = GenDnsConfig::with_resolver(RefusingTransport, resolver.clone());
+        futures::executor::block_on(async {
+            transport.dial(addr.clone()).unwrap().await.unwrap();
+        });
+        assert_eq!(resolver.0.load(Ordering::SeqCst), 2);
+        assert_eq!(resolver.1.load(Ordering::SeqCst), 1);
+
+        let resolver = MovingResolver::default();
+        let mut transport = GenDnsConfig::with_resolver(RefusingTransport, resolver.clone())
+            .with_config(
+                Config::default()
+                    .with_cache_size(8)
+                    .with_negative_cache_size(8),
+            );
+
+        futures::executor::block_on(async {
+            // The looked up address is unreachable, so the name is looked up again.
+            transport.dial(addr.clone()).unwrap().await.unwrap();
+            assert_eq!(resolver.0.load(Ordering::SeqCst), 2);
+            // The new address is cached.
+            transport.dial(addr).unwrap().await.unwrap();
+            assert_eq!(resolver.0.load(Ordering::SeqCst), 2);
+
+            let gone: Multiaddr = "/dns4/gone.example.com/tcp/20000".parse().unwrap();
+            for _ in 0..2 {
+                assert!(matches!(
+                    transport.dial(gone.clone()).unwrap().await,
+                    Err(DnsErr::ResolveError(_))
+                ));
+            }
+            // The second dial fails without a lookup.
+            assert_eq!(resolver.0.load(Ordering::SeqCst), 3);
+        });
+    }
+
+    #[test]
+    fn peer_record_verification() {
+        let key = libp2p_identity::Keypair::generate_ed25519();
+        let peer_id = key.public().to_peer_id();
+        let record = PeerRecord::new(&key, vec!["/ip4/1.2.3.4/tcp/20000".parse().unwrap()])
+            .unwrap()
+            .into_signed_envelope()
+            .into_protobuf_encoding();
+        let peer_record = format!(
+            "{PEER_RECORD_PREFIX}{}",
+            base64::engine::general_purpose::STANDARD.encode(record)
+        );
+
+        /// Publishes the peer record along with an address it does not contain.
+        #[derive(Clone)]
+        struct RecordResolver(Vec<String>);
+
+        #[async_trait]
+        impl Resolver for RecordResolver {
+            async fn lookup_ip(&self, _: String) -> Result<Records<IpAddr>, ResolveError> {
+                unreachable!()
+            }
+
+            async fn ipv4_lookup(&self, _: String) -> Result<Records<Ipv4Addr>, ResolveError> {
+                unreachable!()
+            }
+
+            async fn ipv6_lookup(&self, _: String) -> Result<Records<Ipv6Addr>, ResolveError> {
+                unreachable!()
+            }
+
+            async fn txt_lookup(&self, _: String) -> Result<Records<Vec<u8>>, ResolveError> {
+                Ok(self
+                    .0
+                    .iter()
+                    .map(|txt| txt.as_bytes().to_vec())
+                    .collect::<Vec<_>>()
+                    .into())
+            }
+        }
+
+        let rogue = format!("dnsaddr=/ip4/0.0.0.0/tcp/20000/p2p/{peer_id}");
+        let addr: Multiaddr = format!("/dnsaddr/example.com/p2p/{peer_id}")
+            .parse()
+            .unwrap();
+
+        futures::executor::block_on(async {
+            // Without verification, the unreachable address is dialed.
+            let mut transport = GenDnsConfig::with_resolver(
+                RefusingTransport,
+                RecordResolver(vec![peer_record.clone(), rogue.clone()]),
+            );
+            assert!(matches!(
+                transport.dial(addr.clone()).unwrap().await,
+                Err(DnsErr::Transport(_))
+            ));
+
+            // With verification, it is dropped.
+            let config = Config::default().with_peer_record_verification(true);
+            let mut transport = GenDnsConfig::with_resolver(
+                RefusingTransport,
+                RecordResolver(vec![peer_record.clone(), rogue.clone()]),
+            )
+            .with_config(config.clone());
+            assert!(matches!(
+                transport.dial(addr.clone()).unwrap().await,
+                Err(DnsErr::ResolveError(_))
+            ));
+
+            let mut transport = GenDnsConfig::with_resolver(
+                RefusingTransport,
+                RecordResolver(vec![
+                    format!("dnsaddr=/ip4/1.2.3.4/tcp/20000/p2p/{peer_id}"),
+                    peer_record,
+                    rogue,
+                ]),
+            )
+            .with_config(config);
+            transport.dial(addr).unwrap().await.unwrap();
+        });
+    }
 }
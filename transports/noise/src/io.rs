@@ -20,12 +20,14 @@
 
 //! Noise protocol I/O.
 
-mod framed;
+pub(crate) mod framed;
 pub(crate) mod handshake;
+use crate::RekeyPolicy;
 use bytes::Bytes;
 use framed::{NoiseFramed, MAX_FRAME_LEN};
 use futures::prelude::*;
 use futures::ready;
+use instant::Instant;
 use log::trace;
 use std::{
     cmp::min,
@@ -43,6 +45,37 @@ pub struct Output<T> {
     recv_offset: usize,
     send_buffer: Vec<u8>,
     send_offset: usize,
+    remote_early_data: Option<Vec<u8>>,
+    remote_static_key: [u8; 32],
+    /// The rekeying of the session, if both peers enabled it.
+    rekey: Option<Rekey>,
+    stats: Stats,
+}
+
+/// The state of the rekeying of a session.
+#[derive(Debug)]
+struct Rekey {
+    policy: RekeyPolicy,
+    /// Bytes sent since the outgoing key was last rotated.
+    bytes_sent: u64,
+    /// When the outgoing key was last rotated.
+    last_rekey: Instant,
+    /// Whether the outgoing key is to be rotated before sending the next frame.
+    pending: bool,
+}
+
+/// Statistics of a noise session.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Stats {
+    /// The number of payload bytes sent.
+    pub bytes_sent: u64,
+    /// The number of payload bytes received.
+    pub bytes_received: u64,
+    /// The number of times the key encrypting outgoing data was rotated.
+    pub outbound_rekeys: u64,
+    /// The number of times the key decrypting incoming data was rotated.
+    pub inbound_rekeys: u64,
 }
 
 impl<T> fmt::Debug for Output<T> {
@@ -59,8 +92,46 @@ impl<T> Output<T> {
             recv_offset: 0,
             send_buffer: Vec::new(),
             send_offset: 0,
+            remote_early_data: None,
+            remote_static_key: [0; 32],
+            rekey: None,
+            stats: Stats::default(),
         }
     }
+
+    /// The static DH public key of the remote.
+    ///
+    /// The key can be cached to perform the `IK` handshake on later connections to the remote,
+    /// see [`Config::with_remote_static_key`](crate::Config::with_remote_static_key).
+    pub fn remote_static_key(&self) -> [u8; 32] {
+        self.remote_static_key
+    }
+
+    /// Enables rotating the keys of the session according to `policy`.
+    ///
+    /// Must only be called if the remote enabled rekeying as well.
+    pub(crate) fn enable_rekey(&mut self, policy: RekeyPolicy) {
+        self.rekey = Some(Rekey {
+            policy,
+            bytes_sent: 0,
+            last_rekey: Instant::now(),
+            pending: false,
+        });
+    }
+
+    /// Returns the [`Stats`] of the session, including how often its keys were rotated.
+    ///
+    /// See [`Config::with_rekey_policy`](crate::Config::with_rekey_policy).
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// The early data the remote sent along with the handshake, if any.
+    ///
+    /// See [`Config::with_early_data`](crate::Config::with_early_data).
+    pub fn remote_early_data(&self) -> Option<&[u8]> {
+        self.remote_early_data.as_deref()
+    }
 }
 
 impl<T: AsyncRead + Unpin> AsyncRead for Output<T> {
@@ -90,7 +161,14 @@ impl<T: AsyncRead + Unpin> AsyncRead for Output<T> {
                 Poll::Pending => return Poll::Pending,
                 Poll::Ready(None) => return Poll::Ready(Ok(0)),
                 Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+                Poll::Ready(Some(Ok(frame))) if frame.is_empty() && self.rekey.is_some() => {
+                    // An empty frame announces that the remote rotated its outgoing key.
+                    trace!("read: rekey");
+                    self.io.rekey_incoming();
+                    self.stats.inbound_rekeys += 1;
+                }
                 Poll::Ready(Some(Ok(frame))) => {
+                    self.stats.bytes_received += frame.len() as u64;
                     self.recv_buffer = frame;
                     self.recv_offset = 0;
                 }
@@ -109,11 +187,19 @@ impl<T: AsyncWrite + Unpin> AsyncWrite for Output<T> {
         let mut io = Pin::new(&mut this.io);
         let frame_buf = &mut this.send_buffer;
 
+        ready!(poll_rekey(
+            io.as_mut(),
+            &mut this.rekey,
+            &mut this.stats,
+            cx
+        ))?;
+
         // The MAX_FRAME_LEN is the maximum buffer size before a frame must be sent.
         if this.send_offset == MAX_FRAME_LEN {
             trace!("write: sending {} bytes", MAX_FRAME_LEN);
             ready!(io.as_mut().poll_ready(cx))?;
             io.as_mut().start_send(frame_buf)?;
+            on_frame_sent(&mut this.rekey, &mut this.stats, MAX_FRAME_LEN);
             this.send_offset = 0;
         }
 
@@ -138,9 +224,17 @@ impl<T: AsyncWrite + Unpin> AsyncWrite for Output<T> {
             ready!(io.as_mut().poll_ready(cx))?;
             trace!("flush: sending {} bytes", this.send_offset);
             io.as_mut().start_send(frame_buf)?;
+            on_frame_sent(&mut this.rekey, &mut this.stats, this.send_offset);
             this.send_offset = 0;
         }
 
+        ready!(poll_rekey(
+            io.as_mut(),
+            &mut this.rekey,
+            &mut this.stats,
+            cx
+        ))?;
+
         io.as_mut().poll_flush(cx)
     }
 
@@ -149,3 +243,49 @@ impl<T: AsyncWrite + Unpin> AsyncWrite for Output<T> {
         Pin::new(&mut self.io).poll_close(cx)
     }
 }
+
+/// Accounts for a sent frame of `len` bytes, scheduling a rekey if it is due.
+fn on_frame_sent(rekey: &mut Option<Rekey>, stats: &mut Stats, len: usize) {
+    stats.bytes_sent += len as u64;
+
+    if let Some(rekey) = rekey {
+        rekey.bytes_sent += len as u64;
+        rekey.pending = rekey
+            .policy
+            .is_due(rekey.bytes_sent, rekey.last_rekey.elapsed());
+    }
+}
+
+/// Rotates the outgoing key if a rekey is pending.
+///
+/// The remote is notified via an empty frame, which is encrypted with the previous key.
+fn poll_rekey<T: AsyncWrite + Unpin>(
+    mut io: Pin<&mut NoiseFramed<T, snow::TransportState>>,
+    rekey: &mut Option<Rekey>,
+    stats: &mut Stats,
+    cx: &mut Context<'_>,
+) -> Poll<io::Result<()>> {
+    let rekey = match rekey {
+        Some(rekey)
+            if rekey.pending
+                || rekey
+                    .policy
+                    .is_due(rekey.bytes_sent, rekey.last_rekey.elapsed()) =>
+        {
+            rekey
+        }
+        _ => return Poll::Ready(Ok(())),
+    };
+
+    ready!(io.as_mut().poll_ready(cx))?;
+    trace!("write: rekey");
+    io.as_mut().start_send(&Vec::new())?;
+    io.rekey_outgoing();
+
+    rekey.bytes_sent = 0;
+    rekey.last_rekey = Instant::now();
+    rekey.pending = false;
+    stats.outbound_rekeys += 1;
+
+    Poll::Ready(Ok(()))
+}
@@ -37,6 +37,9 @@ use std::{
 const MAX_NOISE_MSG_LEN: usize = 65535;
 /// Space given to the encryption buffer to hold key material.
 const EXTRA_ENCRYPT_SPACE: usize = 1024;
+/// Space given to the encryption buffer of handshake messages, which may additionally hold the
+/// public key and the ciphertext of a KEM, e.g. 1568 bytes each for Kyber1024.
+const EXTRA_HANDSHAKE_ENCRYPT_SPACE: usize = EXTRA_ENCRYPT_SPACE + 2 * 1568;
 /// Max. length for Noise protocol message payloads.
 pub(crate) const MAX_FRAME_LEN: usize = MAX_NOISE_MSG_LEN - EXTRA_ENCRYPT_SPACE;
 static_assertions::const_assert! {
@@ -121,6 +124,69 @@ impl<T> NoiseFramed<T, snow::HandshakeState> {
     }
 }
 
+impl<T> NoiseFramed<T, snow::TransportState> {
+    /// Rotates the key of the cipher encrypting outgoing frames.
+    pub(crate) fn rekey_outgoing(&mut self) {
+        self.session.rekey_outgoing();
+    }
+
+    /// Rotates the key of the cipher decrypting incoming frames.
+    pub(crate) fn rekey_incoming(&mut self) {
+        self.session.rekey_incoming();
+    }
+}
+
+impl<T> NoiseFramed<T, snow::HandshakeState> {
+    /// Decrypts a handshake message which was received outside of this `NoiseFramed`.
+    pub(crate) fn read_message(&mut self, msg: &[u8]) -> Result<Vec<u8>, snow::Error> {
+        let mut buf = vec![0; msg.len()];
+        let n = self.session.read_message(msg, &mut buf)?;
+        buf.truncate(n);
+        Ok(buf)
+    }
+
+    /// Returns the underlying I/O resource, e.g. to restart the handshake.
+    ///
+    /// Must only be called between frames.
+    pub(crate) fn into_io(self) -> T {
+        debug_assert!(matches!(self.read_state, ReadState::Ready));
+        debug_assert!(self.write_state.is_ready());
+        self.io
+    }
+}
+
+impl<T: AsyncRead + Unpin> NoiseFramed<T, snow::HandshakeState> {
+    /// Receives and decrypts the next handshake message, or `None` if the remote sent an empty
+    /// frame, which the [`Stream`] implementation skips.
+    ///
+    /// Must only be called between frames.
+    pub(crate) async fn next_or_empty(&mut self) -> io::Result<Option<Vec<u8>>> {
+        debug_assert!(matches!(self.read_state, ReadState::Ready));
+        let msg = recv_raw_frame(&mut self.io).await?;
+        if msg.is_empty() {
+            return Ok(None);
+        }
+        self.read_message(&msg)
+            .map(Some)
+            .map_err(|_| io::ErrorKind::InvalidData.into())
+    }
+}
+
+/// Receives a frame without decrypting it.
+pub(crate) async fn recv_raw_frame<T: AsyncRead + Unpin>(io: &mut T) -> io::Result<Vec<u8>> {
+    let mut len = [0; 2];
+    io.read_exact(&mut len).await?;
+    let mut msg = vec![0; usize::from(u16::from_be_bytes(len))];
+    io.read_exact(&mut msg).await?;
+    Ok(msg)
+}
+
+/// Sends an empty frame.
+pub(crate) async fn send_empty_frame<T: AsyncWrite + Unpin>(io: &mut T) -> io::Result<()> {
+    io.write_all(&[0, 0]).await?;
+    io.flush().await
+}
+
 /// The states for reading Noise protocol frames.
 #[derive(Debug)]
 enum ReadState {
@@ -332,8 +398,10 @@ where
         let this = Pin::into_inner(self);
         assert!(this.write_state.is_ready());
 
-        this.write_buffer
-            .resize(frame.len() + EXTRA_ENCRYPT_SPACE, 0u8);
+        this.write_buffer.resize(
+            (frame.len() + S::EXTRA_ENCRYPT_SPACE).min(MAX_NOISE_MSG_LEN),
+            0u8,
+        );
         match this
             .session
             .write_message(frame, &mut this.write_buffer[..])
@@ -369,11 +437,16 @@ where
 
 /// A stateful context in which Noise protocol messages can be read and written.
 pub(crate) trait SessionState {
+    /// Space needed in addition to the payload to write a message.
+    const EXTRA_ENCRYPT_SPACE: usize;
+
     fn read_message(&mut self, msg: &[u8], buf: &mut [u8]) -> Result<usize, snow::Error>;
     fn write_message(&mut self, msg: &[u8], buf: &mut [u8]) -> Result<usize, snow::Error>;
 }
 
 impl SessionState for snow::HandshakeState {
+    const EXTRA_ENCRYPT_SPACE: usize = EXTRA_HANDSHAKE_ENCRYPT_SPACE;
+
     fn read_message(&mut self, msg: &[u8], buf: &mut [u8]) -> Result<usize, snow::Error> {
         self.read_message(msg, buf)
     }
@@ -384,6 +457,8 @@ impl SessionState for snow::HandshakeState {
 }
 
 impl SessionState for snow::TransportState {
+    const EXTRA_ENCRYPT_SPACE: usize = EXTRA_ENCRYPT_SPACE;
+
     fn read_message(&mut self, msg: &[u8], buf: &mut [u8]) -> Result<usize, snow::Error> {
         self.read_message(msg, buf)
     }
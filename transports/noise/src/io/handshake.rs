@@ -27,9 +27,12 @@ mod proto {
     pub use self::payload::proto::NoiseHandshakePayload;
 }
 
-use crate::io::{framed::NoiseFramed, Output};
+use crate::io::{
+    framed::{NoiseFramed, MAX_FRAME_LEN},
+    Output,
+};
 use crate::protocol::{KeypairIdentity, STATIC_KEY_DOMAIN};
-use crate::{DecodeError, Error};
+use crate::{DecodeError, Error, RekeyPolicy};
 use bytes::Bytes;
 use futures::prelude::*;
 use libp2p_identity as identity;
@@ -56,11 +59,19 @@ pub(crate) struct State<T> {
     responder_webtransport_certhashes: Option<HashSet<Multihash<64>>>,
     /// The received extensions of the remote, if any.
     remote_extensions: Option<Extensions>,
+    /// The early data to send along with the local identity, if any.
+    early_data: Vec<u8>,
+    /// The local rekeying policy, if rekeying is enabled.
+    rekey_policy: Option<RekeyPolicy>,
+    /// The maximum size of the payload of a received handshake message.
+    max_payload_size: usize,
 }
 
 /// Extensions
 struct Extensions {
     webtransport_certhashes: HashSet<Multihash<64>>,
+    early_data: Vec<u8>,
+    rekey: bool,
 }
 
 impl<T> State<T> {
@@ -76,6 +87,8 @@ impl<T> State<T> {
         identity: KeypairIdentity,
         expected_remote_key: Option<identity::PublicKey>,
         responder_webtransport_certhashes: Option<HashSet<Multihash<64>>>,
+        early_data: Vec<u8>,
+        rekey_policy: Option<RekeyPolicy>,
     ) -> Self {
         Self {
             identity,
@@ -84,16 +97,30 @@ impl<T> State<T> {
             id_remote_pubkey: expected_remote_key,
             responder_webtransport_certhashes,
             remote_extensions: None,
+            early_data,
+            rekey_policy,
+            max_payload_size: MAX_FRAME_LEN,
         }
     }
+
+    /// Limits the size of the payload of received handshake messages.
+    pub(crate) fn with_max_payload_size(mut self, max_payload_size: usize) -> Self {
+        self.max_payload_size = max_payload_size;
+        self
+    }
 }
 
 impl<T> State<T> {
+    /// Returns the underlying I/O resource, to restart the handshake.
+    pub(crate) fn into_io(self) -> T {
+        self.io.into_io()
+    }
+
     /// Finish a handshake, yielding the established remote identity and the
     /// [`Output`] for communicating on the encrypted channel.
     pub(crate) fn finish(self) -> Result<(identity::PublicKey, Output<T>), Error> {
         let is_initiator = self.io.is_initiator();
-        let (pubkey, io) = self.io.into_transport()?;
+        let (pubkey, mut io) = self.io.into_transport()?;
 
         let id_pk = self
             .id_remote_pubkey
@@ -106,12 +133,26 @@ impl<T> State<T> {
         if !is_valid_signature {
             return Err(Error::BadSignature);
         }
+        io.remote_static_key = pubkey.into();
+
+        let mut remote_extensions = self.remote_extensions;
+        io.remote_early_data = remote_extensions
+            .as_mut()
+            .map(|ext| std::mem::take(&mut ext.early_data))
+            .filter(|data| !data.is_empty());
+
+        let remote_rekey = remote_extensions.as_ref().map_or(false, |ext| ext.rekey);
+        match self.rekey_policy {
+            Some(policy) if remote_rekey => io.enable_rekey(policy),
+            Some(_) => log::debug!("Remote does not support rekeying"),
+            None => {}
+        }
 
         // Check WebTransport certhashes that responder reported back to us.
         if is_initiator {
             // We check only if we care (i.e. Config::with_webtransport_certhashes was used).
             if let Some(expected_certhashes) = self.responder_webtransport_certhashes {
-                let ext = self.remote_extensions.ok_or_else(|| {
+                let ext = remote_extensions.ok_or_else(|| {
                     Error::UnknownWebTransportCerthashes(
                         expected_certhashes.to_owned(),
                         HashSet::new(),
@@ -143,6 +184,8 @@ impl From<proto::NoiseExtensions> for Extensions {
                 .into_iter()
                 .filter_map(|bytes| Multihash::read(&bytes[..]).ok())
                 .collect(),
+            early_data: value.early_data,
+            rekey: value.rekey,
         }
     }
 }
@@ -191,9 +234,51 @@ where
     T: AsyncRead + Unpin,
 {
     let msg = recv(state).await?;
-    let mut reader = BytesReader::from_bytes(&msg[..]);
-    let pb =
-        proto::NoiseHandshakePayload::from_reader(&mut reader, &msg[..]).map_err(DecodeError)?;
+    handle_identity(state, &msg)
+}
+
+/// A future for receiving a Noise handshake message with a payload identifying the remote,
+/// unless the remote sent an empty frame to request falling back to the `XX` handshake.
+///
+/// Returns whether the remote identity was received.
+pub(crate) async fn recv_identity_or_fallback<T>(state: &mut State<T>) -> Result<bool, Error>
+where
+    T: AsyncRead + Unpin,
+{
+    match state.io.next_or_empty().await? {
+        Some(msg) => handle_identity(state, &msg).map(|()| true),
+        None => Ok(false),
+    }
+}
+
+/// Processes the payload of a handshake message identifying the remote, which was received
+/// outside of the handshake state, via [`crate::io::framed::recv_raw_frame`].
+pub(crate) fn read_identity<T>(state: &mut State<T>, msg: &[u8]) -> Result<(), Error> {
+    let payload = state.io.read_message(msg)?;
+    handle_identity(state, &payload)
+}
+
+/// Processes the first handshake message of the `XX` pattern, which was received outside of the
+/// handshake state.
+pub(crate) fn read_empty<T>(state: &mut State<T>, msg: &[u8]) -> Result<(), Error> {
+    if !state.io.read_message(msg)?.is_empty() {
+        return Err(
+            io::Error::new(io::ErrorKind::InvalidData, "Unexpected handshake payload.").into(),
+        );
+    }
+    Ok(())
+}
+
+fn handle_identity<T>(state: &mut State<T>, msg: &[u8]) -> Result<(), Error> {
+    if msg.len() > state.max_payload_size {
+        return Err(Error::HandshakePayloadTooLarge {
+            size: msg.len(),
+            max: state.max_payload_size,
+        });
+    }
+
+    let mut reader = BytesReader::from_bytes(msg);
+    let pb = proto::NoiseHandshakePayload::from_reader(&mut reader, msg).map_err(DecodeError)?;
 
     state.id_remote_pubkey = Some(identity::PublicKey::try_decode_protobuf(&pb.identity_key)?);
 
@@ -231,6 +316,18 @@ where
         }
     }
 
+    if !state.early_data.is_empty() {
+        pb.extensions
+            .get_or_insert_with(proto::NoiseExtensions::default)
+            .early_data = state.early_data.clone();
+    }
+
+    if state.rekey_policy.is_some() {
+        pb.extensions
+            .get_or_insert_with(proto::NoiseExtensions::default)
+            .rekey = true;
+    }
+
     let mut msg = Vec::with_capacity(pb.get_size());
 
     let mut writer = Writer::new(&mut msg);
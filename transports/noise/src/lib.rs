@@ -30,6 +30,17 @@
 //! > **Note**: Only the `XX` handshake pattern is currently guaranteed to provide
 //! >           interoperability with other libp2p implementations.
 //!
+//! When dialing a peer whose static DH key is known from a previous session, the `IK` handshake
+//! pattern saves a round trip, see [`Config::with_ik`] and [`Config::with_remote_peer`]. It is
+//! negotiated via the separate `/noise-ik` protocol and falls back to `XX` if the remote's static
+//! key changed in the meantime.
+//!
+//! With the `pq` feature, a post-quantum hybrid variant of the `XX` handshake can be enabled via
+//! [`Config::with_post_quantum`]. It additionally mixes the secret of a Kyber1024 KEM into the
+//! session keys, protecting the confidentiality of sessions against adversaries recording traffic
+//! today in order to decrypt it with a quantum computer later. It is negotiated via the separate
+//! `/noise-pq` protocol, so peers without support keep using `/noise`.
+//!
 //! All upgrades produce as output a pair, consisting of the remote's static public key
 //! and a `NoiseOutput` which represents the established cryptographic session with the
 //! remote, implementing `futures::io::AsyncRead` and `futures::io::AsyncWrite`.
@@ -59,21 +70,42 @@
 mod io;
 mod protocol;
 
-pub use io::Output;
+pub use io::{Output, Stats};
 
 use crate::handshake::State;
+use crate::io::framed;
 use crate::io::handshake;
-use crate::protocol::{noise_params_into_builder, AuthenticKeypair, Keypair, PARAMS_XX};
+#[cfg(feature = "pq")]
+use crate::protocol::PARAMS_XX_PQ;
+use crate::protocol::{
+    noise_params_into_builder, AuthenticKeypair, Keypair, PublicKey, PARAMS_IK, PARAMS_XX,
+};
 use futures::prelude::*;
+use futures_timer::Delay;
 use libp2p_core::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
 use libp2p_identity as identity;
 use libp2p_identity::PeerId;
 use multiaddr::Protocol;
 use multihash::Multihash;
 use snow::params::NoiseParams;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The protocol name of the `XX` handshake.
+const PROTOCOL_XX: &str = "/noise";
+/// The protocol name of the `IK` handshake, falling back to `XX`.
+const PROTOCOL_IK: &str = "/noise-ik";
+/// The protocol name of the post-quantum hybrid `XX` handshake.
+const PROTOCOL_PQ: &str = "/noise-pq";
+
+/// The length of the first message of the `XX` handshake, the initiator's ephemeral key.
+const XX_FIRST_MESSAGE_LEN: usize = 32;
+
+/// The max. number of peers whose static DH key is remembered for `IK` handshakes.
+const MAX_KNOWN_STATIC_KEYS: usize = 1024;
 
 /// The configuration for the noise handshake.
 #[derive(Clone)]
@@ -81,6 +113,17 @@ pub struct Config {
     dh_keys: AuthenticKeypair,
     params: NoiseParams,
     webtransport_certhashes: Option<HashSet<Multihash<64>>>,
+    early_data: Vec<u8>,
+    rekey_policy: Option<RekeyPolicy>,
+    ik: bool,
+    /// The static DH keys of remotes, learned from previous handshakes and shared by all clones.
+    known_static_keys: Arc<Mutex<HashMap<PeerId, PublicKey>>>,
+    /// The peer being dialed, if known.
+    remote_peer: Option<PeerId>,
+    #[cfg(feature = "pq")]
+    post_quantum: bool,
+    handshake_timeout: Option<Duration>,
+    max_handshake_payload_size: usize,
 
     /// Prologue to use in the noise handshake.
     ///
@@ -100,6 +143,15 @@ impl Config {
             dh_keys: noise_keys,
             params: PARAMS_XX.clone(),
             webtransport_certhashes: None,
+            early_data: vec![],
+            rekey_policy: None,
+            ik: false,
+            known_static_keys: Default::default(),
+            remote_peer: None,
+            #[cfg(feature = "pq")]
+            post_quantum: false,
+            handshake_timeout: None,
+            max_handshake_payload_size: framed::MAX_FRAME_LEN,
             prologue: vec![],
         })
     }
@@ -121,34 +173,154 @@ impl Config {
         self
     }
 
-    fn into_responder<S>(self, socket: S) -> Result<State<S>, Error> {
-        let session = noise_params_into_builder(
-            self.params,
-            &self.prologue,
-            self.dh_keys.keypair.secret(),
-            None,
-        )
-        .build_responder()?;
+    /// Set application data to send to the remote along with the handshake.
+    ///
+    /// The data is sent with the message carrying the local identity, saving a round trip for
+    /// protocols exchanging data right after the handshake. The remote's early data is available
+    /// via [`Output::remote_early_data`].
+    ///
+    /// The data is encrypted, but is sent before the handshake completes, i.e. before the
+    /// identity of the remote has been verified. It must fit into a single handshake message,
+    /// i.e. be well below 64 KiB.
+    pub fn with_early_data(mut self, data: Vec<u8>) -> Self {
+        self.early_data = data;
+        self
+    }
 
-        let state = State::new(
-            socket,
-            session,
-            self.dh_keys.identity,
-            None,
-            self.webtransport_certhashes,
-        );
+    /// Rotate the keys of established sessions according to the given policy.
+    ///
+    /// Rekeying limits the amount of data exposed by the compromise of a session key, which
+    /// matters for long-lived connections. Keys are only rotated if the remote enabled rekeying
+    /// as well, which is announced in the handshake, as both peers need to agree on when keys
+    /// change. How often keys were rotated is reported by [`Output::stats`].
+    pub fn with_rekey_policy(mut self, policy: RekeyPolicy) -> Self {
+        self.rekey_policy = Some(policy);
+        self
+    }
 
-        Ok(state)
+    /// Support the `IK` handshake pattern, in addition to `XX`.
+    ///
+    /// The `IK` handshake is negotiated via the `/noise-ik` protocol. It allows remotes knowing
+    /// the local static DH key to complete the handshake in a single round trip.
+    ///
+    /// The static DH keys of remotes are remembered from completed handshakes, in a cache shared
+    /// by all clones of this configuration. When dialing a peer set via
+    /// [`Config::with_remote_peer`] whose key is known, `/noise-ik` is offered first. Otherwise
+    /// `/noise` is preferred.
+    pub fn with_ik(mut self) -> Self {
+        self.ik = true;
+        self
+    }
+
+    /// Remember the given static DH key of `peer`, e.g. persisted from a previous session via
+    /// [`Output::remote_static_key`].
+    ///
+    /// This implies [`Config::with_ik`]. If the remote's static key changed, the handshake falls
+    /// back to the `XX` pattern, which takes a round trip more than a plain `XX` handshake.
+    pub fn with_remote_static_key(mut self, peer: PeerId, key: [u8; 32]) -> Self {
+        self.ik = true;
+        self.remember_static_key(peer, PublicKey::from(key));
+        self
+    }
+
+    /// Set the peer being dialed, used to look up its static DH key for an `IK` handshake.
+    pub fn with_remote_peer(mut self, peer: PeerId) -> Self {
+        self.remote_peer = Some(peer);
+        self
+    }
+
+    /// Prefer a post-quantum hybrid `XX` handshake, mixing the shared secret of a Kyber1024 KEM
+    /// into the session keys.
+    ///
+    /// The hybrid handshake is negotiated via the `/noise-pq` protocol, which is offered in
+    /// addition to the other protocols. Its sessions remain secure as long as either X25519 or
+    /// Kyber1024 is unbroken.
+    #[cfg(feature = "pq")]
+    pub fn with_post_quantum(mut self) -> Self {
+        self.post_quantum = true;
+        self
+    }
+
+    /// Abort the handshake with [`Error::HandshakeTimeout`] if it does not complete within the
+    /// given duration.
+    ///
+    /// By default, the handshake is only limited by the upgrade timeout of the transport.
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = Some(timeout);
+        self
+    }
+
+    /// Abort the handshake with [`Error::HandshakePayloadTooLarge`] if the payload of a handshake
+    /// message of the remote exceeds the given number of bytes.
+    ///
+    /// The payload contains the identity of the remote, its signature and the extensions,
+    /// including any early data. By default, it is only limited by the maximum Noise frame size.
+    pub fn with_max_handshake_payload_size(mut self, size: usize) -> Self {
+        self.max_handshake_payload_size = size;
+        self
+    }
+
+    /// The known static DH key of the peer being dialed, if any.
+    fn remote_static_key(&self) -> Option<PublicKey> {
+        let peer = self.remote_peer.as_ref()?;
+        self.known_static_keys
+            .lock()
+            .expect("lock not to be poisoned")
+            .get(peer)
+            .cloned()
+    }
+
+    fn remember_static_key(&self, peer: PeerId, key: PublicKey) {
+        let mut keys = self
+            .known_static_keys
+            .lock()
+            .expect("lock not to be poisoned");
+        if keys.len() >= MAX_KNOWN_STATIC_KEYS && !keys.contains_key(&peer) {
+            let evicted = *keys.keys().next().expect("cache not to be empty");
+            keys.remove(&evicted);
+        }
+        keys.insert(peer, key);
+    }
+
+    /// Remembers the remote's static DH key after a completed handshake, if `IK` is enabled.
+    fn finish<T>(&self, state: State<T>) -> Result<(PeerId, Output<T>), Error> {
+        let (pk, io) = state.finish()?;
+        let peer = pk.to_peer_id();
+        if self.ik {
+            self.remember_static_key(peer, PublicKey::from(io.remote_static_key()));
+        }
+
+        Ok((peer, io))
+    }
+
+    fn into_responder<S>(self, socket: S) -> Result<State<S>, Error> {
+        let params = self.params.clone();
+        self.into_state(socket, params, None, false)
     }
 
     fn into_initiator<S>(self, socket: S) -> Result<State<S>, Error> {
-        let session = noise_params_into_builder(
-            self.params,
+        let params = self.params.clone();
+        self.into_state(socket, params, None, true)
+    }
+
+    fn into_state<S>(
+        self,
+        socket: S,
+        params: NoiseParams,
+        remote_static_key: Option<&PublicKey>,
+        initiator: bool,
+    ) -> Result<State<S>, Error> {
+        let builder = noise_params_into_builder(
+            params,
             &self.prologue,
             self.dh_keys.keypair.secret(),
-            None,
-        )
-        .build_initiator()?;
+            remote_static_key,
+        );
+        let session = if initiator {
+            builder.build_initiator()?
+        } else {
+            builder.build_responder()?
+        };
 
         let state = State::new(
             socket,
@@ -156,18 +328,71 @@ impl Config {
             self.dh_keys.identity,
             None,
             self.webtransport_certhashes,
-        );
+            self.early_data,
+            self.rekey_policy,
+        )
+        .with_max_payload_size(self.max_handshake_payload_size);
 
         Ok(state)
     }
 }
 
+/// When to rotate the key encrypting the outgoing data of a session.
+///
+/// The key is rotated once any of the configured limits is reached. A policy without limits
+/// never rotates keys itself, but allows the remote to do so.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RekeyPolicy {
+    max_bytes: Option<u64>,
+    max_duration: Option<Duration>,
+}
+
+impl RekeyPolicy {
+    /// Create a policy without limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rotate the key after the given number of bytes was sent with it.
+    pub fn with_max_bytes(mut self, bytes: u64) -> Self {
+        self.max_bytes = Some(bytes);
+        self
+    }
+
+    /// Rotate the key after it was in use for the given duration.
+    ///
+    /// Keys are only rotated when data is sent.
+    pub fn with_max_duration(mut self, duration: Duration) -> Self {
+        self.max_duration = Some(duration);
+        self
+    }
+
+    fn is_due(&self, bytes: u64, elapsed: Duration) -> bool {
+        self.max_bytes.map_or(false, |max| bytes >= max)
+            || self.max_duration.map_or(false, |max| elapsed >= max)
+    }
+}
+
 impl UpgradeInfo for Config {
     type Info = &'static str;
-    type InfoIter = std::iter::Once<Self::Info>;
+    type InfoIter = std::vec::IntoIter<Self::Info>;
 
     fn protocol_info(&self) -> Self::InfoIter {
-        std::iter::once("/noise")
+        let mut protocols = Vec::with_capacity(3);
+        #[cfg(feature = "pq")]
+        if self.post_quantum {
+            protocols.push(PROTOCOL_PQ);
+        }
+        let ik_preferred = self.ik && self.remote_static_key().is_some();
+        if ik_preferred {
+            protocols.push(PROTOCOL_IK);
+        }
+        protocols.push(PROTOCOL_XX);
+        // Still accepted from remotes that know the local static key.
+        if self.ik && !ik_preferred {
+            protocols.push(PROTOCOL_IK);
+        }
+        protocols.into_iter()
     }
 }
 
@@ -179,19 +404,20 @@ where
     type Error = Error;
     type Future = Pin<Box<dyn Future<Output = Result<Self::Output, Self::Error>> + Send>>;
 
-    fn upgrade_inbound(self, socket: T, _: Self::Info) -> Self::Future {
-        async move {
-            let mut state = self.into_responder(socket)?;
+    fn upgrade_inbound(self, socket: T, info: Self::Info) -> Self::Future {
+        let timeout = self.handshake_timeout;
+        let handshake = async move {
+            let config = self.clone();
+            let state = if info == PROTOCOL_IK {
+                self.respond_ik(socket).await?
+            } else {
+                self.for_protocol(info)?.respond_xx(socket).await?
+            };
 
-            handshake::recv_empty(&mut state).await?;
-            handshake::send_identity(&mut state).await?;
-            handshake::recv_identity(&mut state).await?;
-
-            let (pk, io) = state.finish()?;
+            config.finish(state)
+        };
 
-            Ok((pk.to_peer_id(), io))
-        }
-        .boxed()
+        with_timeout(handshake, timeout).boxed()
     }
 }
 
@@ -203,19 +429,141 @@ where
     type Error = Error;
     type Future = Pin<Box<dyn Future<Output = Result<Self::Output, Self::Error>> + Send>>;
 
-    fn upgrade_outbound(self, socket: T, _: Self::Info) -> Self::Future {
-        async move {
-            let mut state = self.into_initiator(socket)?;
+    fn upgrade_outbound(self, socket: T, info: Self::Info) -> Self::Future {
+        let timeout = self.handshake_timeout;
+        let handshake = async move {
+            let config = self.clone();
+            let state = match self.remote_static_key() {
+                Some(key) if info == PROTOCOL_IK => self.initiate_ik(socket, key).await?,
+                _ => self.for_protocol(info)?.initiate_xx(socket).await?,
+            };
 
-            handshake::send_empty(&mut state).await?;
-            handshake::recv_identity(&mut state).await?;
+            config.finish(state)
+        };
+
+        with_timeout(handshake, timeout).boxed()
+    }
+}
+
+/// Runs the handshake, failing with [`Error::HandshakeTimeout`] if it takes longer than
+/// `timeout`.
+async fn with_timeout<F, O>(handshake: F, timeout: Option<Duration>) -> Result<O, Error>
+where
+    F: Future<Output = Result<O, Error>>,
+{
+    let Some(timeout) = timeout else {
+        return handshake.await;
+    };
+
+    futures::pin_mut!(handshake);
+    match future::select(handshake, Delay::new(timeout)).await {
+        future::Either::Left((result, _)) => result,
+        future::Either::Right(((), _)) => Err(Error::HandshakeTimeout(timeout)),
+    }
+}
+
+impl Config {
+    /// Selects the handshake parameters of the negotiated protocol.
+    ///
+    /// Fails if the post-quantum handshake is negotiated without the `pq` feature.
+    fn for_protocol(self, info: &str) -> Result<Self, Error> {
+        match info {
+            #[cfg(feature = "pq")]
+            PROTOCOL_PQ => Ok(Config {
+                params: PARAMS_XX_PQ.clone(),
+                ..self
+            }),
+            #[cfg(not(feature = "pq"))]
+            PROTOCOL_PQ => Err(Error::UnsupportedProtocol(info.to_owned())),
+            _ => Ok(self),
+        }
+    }
+
+    async fn respond_xx<T>(self, socket: T) -> Result<State<T>, Error>
+    where
+        T: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut state = self.into_responder(socket)?;
+
+        handshake::recv_empty(&mut state).await?;
+        handshake::send_identity(&mut state).await?;
+        handshake::recv_identity(&mut state).await?;
+
+        Ok(state)
+    }
+
+    async fn initiate_xx<T>(self, socket: T) -> Result<State<T>, Error>
+    where
+        T: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut state = self.into_initiator(socket)?;
+
+        handshake::send_empty(&mut state).await?;
+        handshake::recv_identity(&mut state).await?;
+        handshake::send_identity(&mut state).await?;
+
+        Ok(state)
+    }
+
+    /// Responds to a handshake on the `/noise-ik` protocol.
+    ///
+    /// Initiators not knowing the local static key start an `XX` handshake, which is recognized
+    /// by the length of the first message. If the initiator used an outdated static key, the
+    /// `IK` handshake fails and the initiator is asked to start an `XX` handshake instead, via an
+    /// empty frame.
+    async fn respond_ik<T>(self, mut socket: T) -> Result<State<T>, Error>
+    where
+        T: AsyncRead + AsyncWrite + Unpin,
+    {
+        let msg = framed::recv_raw_frame(&mut socket).await?;
+
+        if msg.len() == XX_FIRST_MESSAGE_LEN {
+            let mut state = self.into_responder(socket)?;
+
+            handshake::read_empty(&mut state, &msg)?;
             handshake::send_identity(&mut state).await?;
+            handshake::recv_identity(&mut state).await?;
 
-            let (pk, io) = state.finish()?;
+            return Ok(state);
+        }
 
-            Ok((pk.to_peer_id(), io))
+        let fallback = self.clone();
+        let mut state = self.into_state(socket, PARAMS_IK.clone(), None, false)?;
+        match handshake::read_identity(&mut state, &msg) {
+            Ok(()) => {
+                handshake::send_identity(&mut state).await?;
+                Ok(state)
+            }
+            Err(e) => {
+                log::debug!("IK handshake failed, falling back to XX: {}", e);
+                let mut socket = state.into_io();
+                framed::send_empty_frame(&mut socket).await?;
+                fallback.respond_xx(socket).await
+            }
         }
-        .boxed()
+    }
+
+    /// Initiates an `IK` handshake on the `/noise-ik` protocol, falling back to `XX` if the
+    /// responder asks for it.
+    async fn initiate_ik<T>(
+        self,
+        socket: T,
+        remote_static_key: PublicKey,
+    ) -> Result<State<T>, Error>
+    where
+        T: AsyncRead + AsyncWrite + Unpin,
+    {
+        let fallback = self.clone();
+        let mut state =
+            self.into_state(socket, PARAMS_IK.clone(), Some(&remote_static_key), true)?;
+
+        handshake::send_identity(&mut state).await?;
+        if handshake::recv_identity_or_fallback(&mut state).await? {
+            return Ok(state);
+        }
+
+        log::debug!("Remote static key changed, falling back to XX");
+        fallback.initiate_xx(state.into_io()).await
     }
 }
 
@@ -241,6 +589,12 @@ pub enum Error {
     InvalidPayload(#[from] DecodeError),
     #[error(transparent)]
     SigningError(#[from] libp2p_identity::SigningError),
+    #[error("Handshake protocol {0} is not supported")]
+    UnsupportedProtocol(String),
+    #[error("Handshake did not complete within {0:?}")]
+    HandshakeTimeout(Duration),
+    #[error("Handshake payload of {size} bytes exceeds the limit of {max} bytes")]
+    HandshakePayloadTooLarge { size: usize, max: usize },
     #[error("Expected WebTransport certhashes ({}) are not a subset of received ones ({})", certhashes_to_string(.0), certhashes_to_string(.1))]
     UnknownWebTransportCerthashes(HashSet<Multihash<64>>, HashSet<Multihash<64>>),
 }
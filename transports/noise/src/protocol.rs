@@ -37,6 +37,21 @@ pub(crate) static PARAMS_XX: Lazy<NoiseParams> = Lazy::new(|| {
         .expect("Invalid protocol name")
 });
 
+pub(crate) static PARAMS_IK: Lazy<NoiseParams> = Lazy::new(|| {
+    "Noise_IK_25519_ChaChaPoly_SHA256"
+        .parse()
+        .expect("Invalid protocol name")
+});
+
+/// The `XX` handshake pattern with the `hfs` modifier, mixing the secret of a Kyber1024 KEM into
+/// the keys derived from X25519.
+#[cfg(feature = "pq")]
+pub(crate) static PARAMS_XX_PQ: Lazy<NoiseParams> = Lazy::new(|| {
+    "Noise_XXhfs_25519+Kyber1024_ChaChaPoly_SHA256"
+        .parse()
+        .expect("Invalid protocol name")
+});
+
 pub(crate) fn noise_params_into_builder<'b>(
     params: NoiseParams,
     prologue: &'b [u8],
@@ -153,6 +168,18 @@ impl PublicKey {
     }
 }
 
+impl From<[u8; 32]> for PublicKey {
+    fn from(key: [u8; 32]) -> Self {
+        PublicKey(key)
+    }
+}
+
+impl From<PublicKey> for [u8; 32] {
+    fn from(key: PublicKey) -> Self {
+        key.0
+    }
+}
+
 impl AsRef<[u8]> for PublicKey {
     fn as_ref(&self) -> &[u8] {
         self.0.as_ref()
@@ -162,7 +189,8 @@ impl AsRef<[u8]> for PublicKey {
 /// Custom `snow::CryptoResolver` which delegates to either the
 /// `RingResolver` on native or the `DefaultResolver` on wasm
 /// for hash functions and symmetric ciphers, while using x25519-dalek
-/// for Curve25519 DH.
+/// for Curve25519 DH. With the `pq` feature, the Kyber1024 KEM is provided by the
+/// `DefaultResolver`.
 struct Resolver;
 
 impl snow::resolvers::CryptoResolver for Resolver {
@@ -205,6 +233,11 @@ impl snow::resolvers::CryptoResolver for Resolver {
             snow::resolvers::RingResolver.resolve_cipher(choice)
         }
     }
+
+    #[cfg(feature = "pq")]
+    fn resolve_kem(&self, choice: &snow::params::KemChoice) -> Option<Box<dyn snow::types::Kem>> {
+        snow::resolvers::DefaultResolver.resolve_kem(choice)
+    }
 }
 
 /// Wrapper around a CSPRNG to implement `snow::Random` trait for.
@@ -0,0 +1,52 @@
+use libp2p_core::{InboundUpgrade, OutboundUpgrade};
+use libp2p_identity as identity;
+use libp2p_noise as noise;
+
+#[test]
+fn early_data_is_exchanged() {
+    let (client_data, server_data) =
+        handshake_with_early_data(b"client hello".to_vec(), b"server hello".to_vec());
+
+    assert_eq!(client_data.as_deref(), Some(&b"client hello"[..]));
+    assert_eq!(server_data.as_deref(), Some(&b"server hello"[..]));
+}
+
+#[test]
+fn early_data_is_optional() {
+    let (client_data, server_data) = handshake_with_early_data(b"client hello".to_vec(), vec![]);
+
+    assert_eq!(client_data.as_deref(), Some(&b"client hello"[..]));
+    assert_eq!(server_data, None);
+}
+
+/// Returns the early data received by the server and the client, respectively.
+fn handshake_with_early_data(
+    client_data: Vec<u8>,
+    server_data: Vec<u8>,
+) -> (Option<Vec<u8>>, Option<Vec<u8>>) {
+    let client_id = identity::Keypair::generate_ed25519();
+    let server_id = identity::Keypair::generate_ed25519();
+
+    let (client, server) = futures_ringbuf::Endpoint::pair(100, 100);
+
+    futures::executor::block_on(async move {
+        let client_config = noise::Config::new(&client_id)
+            .unwrap()
+            .with_early_data(client_data);
+        let server_config = noise::Config::new(&server_id)
+            .unwrap()
+            .with_early_data(server_data);
+
+        let ((_, server_session), (_, client_session)) = futures::future::try_join(
+            server_config.upgrade_inbound(server, ""),
+            client_config.upgrade_outbound(client, ""),
+        )
+        .await
+        .unwrap();
+
+        (
+            server_session.remote_early_data().map(<[u8]>::to_vec),
+            client_session.remote_early_data().map(<[u8]>::to_vec),
+        )
+    })
+}
@@ -0,0 +1,102 @@
+use libp2p_core::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
+use libp2p_identity as identity;
+use libp2p_noise as noise;
+
+#[test]
+fn ik_is_only_preferred_for_peers_with_known_key() {
+    let server_id = identity::Keypair::generate_ed25519();
+    let server_peer = server_id.public().to_peer_id();
+    let server_config = noise::Config::new(&server_id).unwrap().with_ik();
+    let client_config = noise::Config::new(&identity::Keypair::generate_ed25519())
+        .unwrap()
+        .with_ik();
+
+    assert_eq!(protocols(&server_config), ["/noise", "/noise-ik"]);
+    assert_eq!(
+        protocols(&client_config.clone().with_remote_peer(server_peer)),
+        ["/noise", "/noise-ik"]
+    );
+
+    // The server's static key is learned through a first handshake.
+    handshake(server_config, client_config.clone(), "/noise");
+
+    assert_eq!(
+        protocols(&client_config.clone().with_remote_peer(server_peer)),
+        ["/noise-ik", "/noise"]
+    );
+    let other_peer = identity::Keypair::generate_ed25519().public().to_peer_id();
+    assert_eq!(
+        protocols(&client_config.with_remote_peer(other_peer)),
+        ["/noise", "/noise-ik"]
+    );
+}
+
+#[test]
+fn ik_handshake_with_known_key() {
+    let server_id = identity::Keypair::generate_ed25519();
+    let server_peer = server_id.public().to_peer_id();
+    let server_config = noise::Config::new(&server_id).unwrap().with_ik();
+
+    // Learn the server's static key through a first `XX` handshake.
+    let client_config = noise::Config::new(&identity::Keypair::generate_ed25519())
+        .unwrap()
+        .with_ik();
+    let server_key = handshake(server_config.clone(), client_config, "/noise");
+
+    let client_config = noise::Config::new(&identity::Keypair::generate_ed25519())
+        .unwrap()
+        .with_remote_static_key(server_peer, server_key)
+        .with_remote_peer(server_peer);
+    assert_eq!(
+        handshake(server_config, client_config, "/noise-ik"),
+        server_key
+    );
+}
+
+#[test]
+fn ik_handshake_falls_back_to_xx_with_stale_key() {
+    let server_id = identity::Keypair::generate_ed25519();
+    let server_peer = server_id.public().to_peer_id();
+    let server_config = noise::Config::new(&server_id).unwrap().with_ik();
+    let stale_key = rand::random::<[u8; 32]>();
+    let client_config = noise::Config::new(&identity::Keypair::generate_ed25519())
+        .unwrap()
+        .with_remote_static_key(server_peer, stale_key)
+        .with_remote_peer(server_peer);
+
+    let server_key = handshake(server_config.clone(), client_config.clone(), "/noise-ik");
+    assert_ne!(server_key, stale_key);
+
+    // The stale key is replaced by the one learned through the fallback.
+    assert_eq!(
+        handshake(server_config, client_config, "/noise-ik"),
+        server_key
+    );
+}
+
+fn protocols(config: &noise::Config) -> Vec<&'static str> {
+    config.protocol_info().collect()
+}
+
+/// Performs a handshake on the given protocol and returns the server's static key as learned by
+/// the client.
+fn handshake(
+    server_config: noise::Config,
+    client_config: noise::Config,
+    protocol: &'static str,
+) -> [u8; 32] {
+    let (client, server) = futures_ringbuf::Endpoint::pair(100, 100);
+
+    futures::executor::block_on(async move {
+        let ((_, server_session), (_, client_session)) = futures::future::try_join(
+            server_config.upgrade_inbound(server, protocol),
+            client_config.upgrade_outbound(client, protocol),
+        )
+        .await
+        .unwrap();
+
+        assert_ne!(server_session.remote_static_key(), [0; 32]);
+
+        client_session.remote_static_key()
+    })
+}
@@ -0,0 +1,58 @@
+use libp2p_core::{InboundUpgrade, OutboundUpgrade};
+use libp2p_identity as identity;
+use libp2p_noise as noise;
+use std::time::Duration;
+
+#[test]
+fn handshake_times_out() {
+    let server_config = noise::Config::new(&identity::Keypair::generate_ed25519())
+        .unwrap()
+        .with_handshake_timeout(Duration::from_millis(100));
+
+    // The client never starts the handshake.
+    let (_client, server) = futures_ringbuf::Endpoint::pair(100, 100);
+
+    let result = futures::executor::block_on(server_config.upgrade_inbound(server, ""));
+
+    assert!(matches!(
+        result,
+        Err(noise::Error::HandshakeTimeout(timeout)) if timeout == Duration::from_millis(100)
+    ));
+}
+
+#[cfg(not(feature = "pq"))]
+#[test]
+fn post_quantum_handshake_is_rejected_without_pq_feature() {
+    let server_config = noise::Config::new(&identity::Keypair::generate_ed25519()).unwrap();
+
+    let (_client, server) = futures_ringbuf::Endpoint::pair(100, 100);
+
+    let result = futures::executor::block_on(server_config.upgrade_inbound(server, "/noise-pq"));
+
+    assert!(matches!(
+        result,
+        Err(noise::Error::UnsupportedProtocol(protocol)) if protocol == "/noise-pq"
+    ));
+}
+
+#[test]
+fn oversized_handshake_payload_is_rejected() {
+    let server_config = noise::Config::new(&identity::Keypair::generate_ed25519())
+        .unwrap()
+        .with_max_handshake_payload_size(512);
+    let client_config = noise::Config::new(&identity::Keypair::generate_ed25519())
+        .unwrap()
+        .with_early_data(vec![0; 1024]);
+
+    let (client, server) = futures_ringbuf::Endpoint::pair(100, 100);
+
+    let (server_result, _) = futures::executor::block_on(futures::future::join(
+        server_config.upgrade_inbound(server, ""),
+        client_config.upgrade_outbound(client, ""),
+    ));
+
+    assert!(matches!(
+        server_result,
+        Err(noise::Error::HandshakePayloadTooLarge { max: 512, .. })
+    ));
+}
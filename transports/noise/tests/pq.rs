@@ -0,0 +1,48 @@
+#![cfg(feature = "pq")]
+
+use futures::prelude::*;
+use libp2p_core::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
+use libp2p_identity as identity;
+use libp2p_noise as noise;
+
+#[test]
+fn post_quantum_protocol_is_preferred() {
+    let config = noise::Config::new(&identity::Keypair::generate_ed25519())
+        .unwrap()
+        .with_post_quantum();
+
+    assert_eq!(
+        config.protocol_info().collect::<Vec<_>>(),
+        ["/noise-pq", "/noise"]
+    );
+}
+
+#[test]
+fn post_quantum_handshake() {
+    let client_id = identity::Keypair::generate_ed25519();
+    let server_id = identity::Keypair::generate_ed25519();
+
+    let (client, server) = futures_ringbuf::Endpoint::pair(100, 100);
+
+    futures::executor::block_on(async move {
+        let client_config = noise::Config::new(&client_id).unwrap().with_post_quantum();
+        let server_config = noise::Config::new(&server_id).unwrap().with_post_quantum();
+
+        let ((client_peer, mut server_session), (server_peer, mut client_session)) =
+            futures::future::try_join(
+                server_config.upgrade_inbound(server, "/noise-pq"),
+                client_config.upgrade_outbound(client, "/noise-pq"),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(client_peer, client_id.public().to_peer_id());
+        assert_eq!(server_peer, server_id.public().to_peer_id());
+
+        client_session.write_all(b"hello").await.unwrap();
+        client_session.flush().await.unwrap();
+        let mut msg = [0; 5];
+        server_session.read_exact(&mut msg).await.unwrap();
+        assert_eq!(&msg, b"hello");
+    })
+}
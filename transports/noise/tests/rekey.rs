@@ -0,0 +1,74 @@
+use futures::prelude::*;
+use libp2p_core::{InboundUpgrade, OutboundUpgrade};
+use libp2p_identity as identity;
+use libp2p_noise as noise;
+
+#[test]
+fn keys_are_rotated_if_both_peers_enable_rekeying() {
+    let policy = noise::RekeyPolicy::new().with_max_bytes(1000);
+    let (client, server) = exchange(Some(policy), Some(noise::RekeyPolicy::new()));
+
+    assert_eq!(client.bytes_sent, 10 * 300);
+    assert_eq!(server.bytes_received, 10 * 300);
+    // A rekey is due after every 4th message.
+    assert_eq!(client.outbound_rekeys, 2);
+    assert_eq!(server.inbound_rekeys, 2);
+    assert_eq!(server.outbound_rekeys, 0);
+}
+
+#[test]
+fn keys_are_not_rotated_if_remote_does_not_enable_rekeying() {
+    let policy = noise::RekeyPolicy::new().with_max_bytes(1000);
+    let (client, server) = exchange(Some(policy), None);
+
+    assert_eq!(client.bytes_sent, 10 * 300);
+    assert_eq!(client.outbound_rekeys, 0);
+    assert_eq!(server.inbound_rekeys, 0);
+}
+
+/// Sends ten messages of 300 bytes from the client to the server, returning the [`noise::Stats`]
+/// of the client and the server, respectively.
+fn exchange(
+    client_policy: Option<noise::RekeyPolicy>,
+    server_policy: Option<noise::RekeyPolicy>,
+) -> (noise::Stats, noise::Stats) {
+    let client_id = identity::Keypair::generate_ed25519();
+    let server_id = identity::Keypair::generate_ed25519();
+
+    let (client, server) = futures_ringbuf::Endpoint::pair(100, 100);
+
+    futures::executor::block_on(async move {
+        let mut client_config = noise::Config::new(&client_id).unwrap();
+        let mut server_config = noise::Config::new(&server_id).unwrap();
+        if let Some(policy) = client_policy {
+            client_config = client_config.with_rekey_policy(policy);
+        }
+        if let Some(policy) = server_policy {
+            server_config = server_config.with_rekey_policy(policy);
+        }
+
+        let ((_, mut server_session), (_, mut client_session)) = futures::future::try_join(
+            server_config.upgrade_inbound(server, ""),
+            client_config.upgrade_outbound(client, ""),
+        )
+        .await
+        .unwrap();
+
+        let client_fut = async {
+            for i in 0..10 {
+                client_session.write_all(&[i; 300]).await.unwrap();
+                client_session.flush().await.unwrap();
+            }
+        };
+        let server_fut = async {
+            for i in 0..10 {
+                let mut msg = [0; 300];
+                server_session.read_exact(&mut msg).await.unwrap();
+                assert_eq!(msg, [i; 300]);
+            }
+        };
+        futures::future::join(client_fut, server_fut).await;
+
+        (client_session.stats(), server_session.stats())
+    })
+}
@@ -18,6 +18,7 @@ use super::*;
 pub struct Exchange {
     pub id: Option<Vec<u8>>,
     pub pubkey: Option<Vec<u8>>,
+    pub extension: Option<Vec<u8>>,
 }
 
 impl<'a> MessageRead<'a> for Exchange {
@@ -27,6 +28,7 @@ impl<'a> MessageRead<'a> for Exchange {
             match r.next_tag(bytes) {
                 Ok(10) => msg.id = Some(r.read_bytes(bytes)?.to_owned()),
                 Ok(18) => msg.pubkey = Some(r.read_bytes(bytes)?.to_owned()),
+                Ok(26) => msg.extension = Some(r.read_bytes(bytes)?.to_owned()),
                 Ok(t) => { r.read_unknown(bytes, t)?; }
                 Err(e) => return Err(e),
             }
@@ -40,11 +42,13 @@ impl MessageWrite for Exchange {
         0
         + self.id.as_ref().map_or(0, |m| 1 + sizeof_len((m).len()))
         + self.pubkey.as_ref().map_or(0, |m| 1 + sizeof_len((m).len()))
+        + self.extension.as_ref().map_or(0, |m| 1 + sizeof_len((m).len()))
     }
 
     fn write_message<W: WriterBackend>(&self, w: &mut Writer<W>) -> Result<()> {
         if let Some(ref s) = self.id { w.write_with_tag(10, |w| w.write_bytes(&**s))?; }
         if let Some(ref s) = self.pubkey { w.write_with_tag(18, |w| w.write_bytes(&**s))?; }
+        if let Some(ref s) = self.extension { w.write_with_tag(26, |w| w.write_bytes(&**s))?; }
         Ok(())
     }
 }
@@ -20,7 +20,7 @@
 
 use crate::error::{DecodeError, PlainTextError};
 use crate::proto::Exchange;
-use crate::PlainText2Config;
+use crate::Config;
 
 use asynchronous_codec::{Framed, FramedParts};
 use bytes::{Bytes, BytesMut};
@@ -32,7 +32,7 @@ use std::io::{Error as IoError, ErrorKind as IoErrorKind};
 use unsigned_varint::codec::UviBytes;
 
 struct HandshakeContext<T> {
-    config: PlainText2Config,
+    config: Config,
     state: T,
 }
 
@@ -47,13 +47,16 @@ pub(crate) struct Remote {
     // The remote's peer ID:
     pub(crate) peer_id: PeerId, // The remote's public key:
     pub(crate) public_key: PublicKey,
+    // The extension sent by the remote, if any:
+    pub(crate) extension: Option<Vec<u8>>,
 }
 
 impl HandshakeContext<Local> {
-    fn new(config: PlainText2Config) -> Self {
+    fn new(config: Config) -> Self {
         let exchange = Exchange {
             id: Some(config.local_public_key.to_peer_id().to_bytes()),
             pubkey: Some(config.local_public_key.encode_protobuf()),
+            extension: config.extension.clone(),
         };
         let mut buf = Vec::with_capacity(exchange.get_size());
         let mut writer = Writer::new(&mut buf);
@@ -89,6 +92,7 @@ impl HandshakeContext<Local> {
             state: Remote {
                 peer_id,
                 public_key,
+                extension: prop.extension,
             },
         })
     }
@@ -96,7 +100,7 @@ impl HandshakeContext<Local> {
 
 pub(crate) async fn handshake<S>(
     socket: S,
-    config: PlainText2Config,
+    config: Config,
 ) -> Result<(S, Remote, Bytes), PlainTextError>
 where
     S: AsyncRead + AsyncWrite + Send + Unpin,
@@ -46,19 +46,29 @@ mod proto {
     pub(crate) use self::structs::Exchange;
 }
 
+const PROTOCOL_NAME: &str = "/plaintext/2.0.0";
+
 /// `PlainText2Config` is an insecure connection handshake for testing purposes only, implementing
 /// the libp2p plaintext connection handshake specification.
+///
+/// See [`Config`] for a configuration which can additionally exchange an extension.
 #[derive(Clone)]
 pub struct PlainText2Config {
     pub local_public_key: identity::PublicKey,
 }
 
+impl From<PlainText2Config> for Config {
+    fn from(config: PlainText2Config) -> Self {
+        Config::new(config.local_public_key)
+    }
+}
+
 impl UpgradeInfo for PlainText2Config {
     type Info = &'static str;
     type InfoIter = iter::Once<Self::Info>;
 
     fn protocol_info(&self) -> Self::InfoIter {
-        iter::once("/plaintext/2.0.0")
+        iter::once(PROTOCOL_NAME)
     }
 }
 
@@ -71,7 +81,7 @@ where
     type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
 
     fn upgrade_inbound(self, socket: C, _: Self::Info) -> Self::Future {
-        Box::pin(self.handshake(socket))
+        Box::pin(Config::from(self).handshake(socket))
     }
 }
 
@@ -84,11 +94,38 @@ where
     type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
 
     fn upgrade_outbound(self, socket: C, _: Self::Info) -> Self::Future {
-        Box::pin(self.handshake(socket))
+        Box::pin(Config::from(self).handshake(socket))
     }
 }
 
-impl PlainText2Config {
+/// Configuration of the plaintext handshake, an insecure connection handshake for testing
+/// purposes only, implementing the libp2p plaintext connection handshake specification.
+///
+/// Unlike [`PlainText2Config`], it can send an application-supplied extension to the remote,
+/// e.g. allowing test harnesses to pass configuration during the handshake.
+#[derive(Clone)]
+pub struct Config {
+    local_public_key: identity::PublicKey,
+    extension: Option<Vec<u8>>,
+}
+
+impl Config {
+    pub fn new(local_public_key: identity::PublicKey) -> Self {
+        Self {
+            local_public_key,
+            extension: None,
+        }
+    }
+
+    /// Send the given extension to the remote as part of the handshake.
+    ///
+    /// The extension is carried in a field of the exchange message which is not part of the
+    /// specification and thus ignored by other implementations.
+    pub fn with_extension(mut self, extension: impl Into<Vec<u8>>) -> Self {
+        self.extension = Some(extension.into());
+        self
+    }
+
     async fn handshake<T>(self, socket: T) -> Result<(PeerId, PlainTextOutput<T>), PlainTextError>
     where
         T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
@@ -102,12 +139,48 @@ impl PlainText2Config {
             PlainTextOutput {
                 socket,
                 remote_key: remote.public_key,
+                remote_extension: remote.extension,
                 read_buffer,
             },
         ))
     }
 }
 
+impl UpgradeInfo for Config {
+    type Info = &'static str;
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(PROTOCOL_NAME)
+    }
+}
+
+impl<C> InboundUpgrade<C> for Config
+where
+    C: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    type Output = (PeerId, PlainTextOutput<C>);
+    type Error = PlainTextError;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, socket: C, _: Self::Info) -> Self::Future {
+        Box::pin(self.handshake(socket))
+    }
+}
+
+impl<C> OutboundUpgrade<C> for Config
+where
+    C: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    type Output = (PeerId, PlainTextOutput<C>);
+    type Error = PlainTextError;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, socket: C, _: Self::Info) -> Self::Future {
+        Box::pin(self.handshake(socket))
+    }
+}
+
 /// Output of the plaintext protocol.
 pub struct PlainTextOutput<S>
 where
@@ -117,12 +190,21 @@ where
     pub socket: S,
     /// The public key of the remote.
     pub remote_key: PublicKey,
+    /// The extension sent by the remote, if any.
+    remote_extension: Option<Vec<u8>>,
     /// Remaining bytes that have been already buffered
     /// during the handshake but are not part of the
     /// handshake. These must be consumed first by `poll_read`.
     read_buffer: Bytes,
 }
 
+impl<S: AsyncRead + AsyncWrite + Unpin> PlainTextOutput<S> {
+    /// The extension sent by the remote during the handshake, if any.
+    pub fn remote_extension(&self) -> Option<&[u8]> {
+        self.remote_extension.as_deref()
+    }
+}
+
 impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for PlainTextOutput<S> {
     fn poll_read(
         mut self: Pin<&mut Self>,
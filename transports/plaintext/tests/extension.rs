@@ -0,0 +1,68 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use libp2p_core::{InboundUpgrade, OutboundUpgrade};
+use libp2p_identity as identity;
+use libp2p_plaintext::{Config, PlainText2Config};
+
+#[test]
+fn exchanges_extension() {
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_ed25519();
+
+    let (server, client) = futures_ringbuf::Endpoint::pair(100, 100);
+
+    let ((_, server_output), (_, client_output)) =
+        futures::executor::block_on(futures::future::try_join(
+            Config::new(server_id.public())
+                .with_extension(b"server".to_vec())
+                .upgrade_inbound(server, ""),
+            Config::new(client_id.public())
+                .with_extension(b"client".to_vec())
+                .upgrade_outbound(client, ""),
+        ))
+        .unwrap();
+
+    assert_eq!(server_output.remote_extension(), Some(&b"client"[..]));
+    assert_eq!(client_output.remote_extension(), Some(&b"server"[..]));
+}
+
+#[test]
+fn extension_is_optional() {
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_ed25519();
+
+    let (server, client) = futures_ringbuf::Endpoint::pair(100, 100);
+
+    let ((_, server_output), (_, client_output)) =
+        futures::executor::block_on(futures::future::try_join(
+            PlainText2Config {
+                local_public_key: server_id.public(),
+            }
+            .upgrade_inbound(server, ""),
+            Config::new(client_id.public())
+                .with_extension(b"client".to_vec())
+                .upgrade_outbound(client, ""),
+        ))
+        .unwrap();
+
+    assert_eq!(server_output.remote_extension(), Some(&b"client"[..]));
+    assert_eq!(client_output.remote_extension(), None);
+}
@@ -0,0 +1,244 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use chacha20poly1305::{
+    aead::{Aead, NewAead},
+    XChaCha20Poly1305, XNonce,
+};
+use futures::{
+    io::{self, AsyncRead, AsyncWrite},
+    ready,
+    task::{Context, Poll},
+};
+use log::trace;
+use pin_project::pin_project;
+use std::pin::Pin;
+
+/// Size of the length prefix of a frame.
+const LENGTH_SIZE: usize = 2;
+/// Size of the authentication tag of a frame.
+const TAG_SIZE: usize = 16;
+/// Maximum size of the payload of a frame.
+const MAX_PAYLOAD_SIZE: usize = 16 * 1024;
+
+/// The cipher and nonces of one direction of a connection.
+struct Direction {
+    cipher: XChaCha20Poly1305,
+    nonce: [u8; 24],
+    counter: u64,
+}
+
+impl Direction {
+    fn new(key: &[u8; 32], nonce: [u8; 24]) -> Self {
+        Self {
+            cipher: XChaCha20Poly1305::new(key.into()),
+            nonce,
+            counter: 0,
+        }
+    }
+
+    /// The nonce of the next frame, i.e. the nonce exchanged during the handshake with the
+    /// frame counter XORed into its last 8 bytes.
+    fn next_nonce(&mut self) -> XNonce {
+        let mut nonce = self.nonce;
+        nonce[16..]
+            .iter_mut()
+            .zip(self.counter.to_be_bytes())
+            .for_each(|(n, c)| *n ^= c);
+        self.counter += 1;
+        nonce.into()
+    }
+}
+
+/// A connection encrypted with XChaCha20-Poly1305, as negotiated by pnet v2.
+///
+/// Data is sent in frames, each consisting of a 2 byte big endian length prefix followed by
+/// the encrypted payload and its authentication tag.
+#[pin_project]
+pub(crate) struct AeadOutput<S> {
+    #[pin]
+    inner: S,
+    write: Direction,
+    read: Direction,
+    /// encoded frame not yet written to the inner socket.
+    write_buf: Vec<u8>,
+    /// frame currently being received.
+    read_buf: Vec<u8>,
+    /// number of bytes of `read_buf` received so far.
+    read_filled: usize,
+    /// decrypted payload not yet returned to the reader.
+    decrypted: Vec<u8>,
+}
+
+impl<S> AeadOutput<S> {
+    pub(crate) fn new(
+        inner: S,
+        write_key: &[u8; 32],
+        write_nonce: [u8; 24],
+        read_key: &[u8; 32],
+        read_nonce: [u8; 24],
+    ) -> Self {
+        Self {
+            inner,
+            write: Direction::new(write_key, write_nonce),
+            read: Direction::new(read_key, read_nonce),
+            write_buf: Vec::new(),
+            read_buf: vec![0; LENGTH_SIZE],
+            read_filled: 0,
+            decrypted: Vec::new(),
+        }
+    }
+}
+
+impl<S: AsyncWrite> AeadOutput<S> {
+    /// Writes the pending frame to the inner socket.
+    fn poll_write_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut this = self.project();
+        while !this.write_buf.is_empty() {
+            let n = ready!(this.inner.as_mut().poll_write(cx, this.write_buf))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            this.write_buf.drain(..n);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead> AsyncRead for AeadOutput<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut this = self.project();
+        loop {
+            if !this.decrypted.is_empty() {
+                let size = buf.len().min(this.decrypted.len());
+                buf[..size].copy_from_slice(&this.decrypted[..size]);
+                this.decrypted.drain(..size);
+                return Poll::Ready(Ok(size));
+            }
+
+            let n = ready!(this
+                .inner
+                .as_mut()
+                .poll_read(cx, &mut this.read_buf[*this.read_filled..]))?;
+            if n == 0 {
+                if *this.read_filled == 0 && this.read_buf.len() == LENGTH_SIZE {
+                    return Poll::Ready(Ok(0));
+                }
+                return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+            }
+            *this.read_filled += n;
+            if *this.read_filled < this.read_buf.len() {
+                continue;
+            }
+
+            if this.read_buf.len() == LENGTH_SIZE {
+                let len = u16::from_be_bytes([this.read_buf[0], this.read_buf[1]]) as usize;
+                if len < TAG_SIZE {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "pnet frame too short",
+                    )));
+                }
+                this.read_buf.resize(LENGTH_SIZE + len, 0);
+                continue;
+            }
+
+            let nonce = this.read.next_nonce();
+            *this.decrypted = this
+                .read
+                .cipher
+                .decrypt(&nonce, &this.read_buf[LENGTH_SIZE..])
+                .map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "pnet frame authentication failed",
+                    )
+                })?;
+            trace!("decrypted frame of {} bytes", this.decrypted.len());
+            this.read_buf.truncate(LENGTH_SIZE);
+            *this.read_filled = 0;
+        }
+    }
+}
+
+impl<S: AsyncWrite> AsyncWrite for AeadOutput<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        ready!(self.as_mut().poll_write_frame(cx))?;
+        let this = self.project();
+        let size = buf.len().min(MAX_PAYLOAD_SIZE);
+        let nonce = this.write.next_nonce();
+        let ciphertext = this
+            .write
+            .cipher
+            .encrypt(&nonce, &buf[..size])
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "pnet frame encryption failed"))?;
+        this.write_buf
+            .extend_from_slice(&(ciphertext.len() as u16).to_be_bytes());
+        this.write_buf.extend_from_slice(&ciphertext);
+        trace!("encrypted frame of {} bytes", size);
+        Poll::Ready(Ok(size))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.as_mut().poll_write_frame(cx))?;
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.as_mut().poll_write_frame(cx))?;
+        self.project().inner.poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{executor::block_on, io::Cursor, AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn roundtrip_and_tampering() {
+        let key = [1; 32];
+        let nonce = [2; 24];
+        let data = vec![3; MAX_PAYLOAD_SIZE * 2 + 1];
+
+        let mut writer = AeadOutput::new(Cursor::new(Vec::new()), &key, nonce, &key, nonce);
+        block_on(writer.write_all(&data)).unwrap();
+        block_on(writer.flush()).unwrap();
+        let mut encrypted = writer.inner.into_inner();
+
+        let mut reader = AeadOutput::new(Cursor::new(encrypted.clone()), &key, nonce, &key, nonce);
+        let mut received = Vec::new();
+        block_on(reader.read_to_end(&mut received)).unwrap();
+        assert_eq!(received, data);
+
+        encrypted[LENGTH_SIZE] ^= 1;
+        let mut reader = AeadOutput::new(Cursor::new(encrypted), &key, nonce, &key, nonce);
+        let err = block_on(reader.read_to_end(&mut Vec::new())).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -26,9 +26,12 @@
 
 #![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
 
+mod aead_io;
 mod crypt_writer;
+use aead_io::AeadOutput;
 use crypt_writer::CryptWriter;
-use futures::prelude::*;
+use futures::{future::Either, prelude::*};
+use hmac::{Hmac, Mac};
 use log::trace;
 use pin_project::pin_project;
 use rand::RngCore;
@@ -36,22 +39,34 @@ use salsa20::{
     cipher::{KeyIvInit, StreamCipher},
     Salsa20, XSalsa20,
 };
+use sha2::Sha256;
 use sha3::{digest::ExtendableOutput, Shake128};
 use std::{
     error,
     fmt::{self, Write},
-    io,
+    fs, io,
     io::Error as IoError,
     num::ParseIntError,
+    path::Path,
     pin::Pin,
     str::FromStr,
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 
 const KEY_SIZE: usize = 32;
 const NONCE_SIZE: usize = 24;
 const WRITE_BUFFER_SIZE: usize = 1024;
 const FINGERPRINT_SIZE: usize = 16;
+/// Size of the tag at the end of a nonce announcing support for pnet v2.
+const V2_TAG_SIZE: usize = 8;
+/// Number of PBKDF2 iterations when deriving a key from a passphrase.
+const PASSPHRASE_ITERATIONS: u32 = 100_000;
+/// The first message of multistream-select, which is what every libp2p node sends first on a
+/// connection protected by pnet.
+///
+/// Used to determine which key the remote encrypts with while a previous key is accepted.
+const MULTISTREAM_HEADER: &[u8] = b"\x13/multistream/1.0.0\n";
 
 /// A pre-shared key, consisting of 32 bytes of random data.
 #[derive(Copy, Clone, PartialEq, Eq)]
@@ -83,6 +98,44 @@ impl PreSharedKey {
             .expect("shake128 failed");
         Fingerprint(out)
     }
+
+    /// Loads a PreSharedKey from a key file, e.g. the `swarm.key` file used by go-libp2p
+    /// and kubo.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, KeyLoadError> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(KeyLoadError::Parse)
+    }
+
+    /// Derive a PreSharedKey from a passphrase.
+    ///
+    /// The key is derived with PBKDF2-HMAC-SHA256 using 100000 iterations, so nodes
+    /// configured with the same passphrase and salt derive the same key.
+    pub fn from_passphrase(passphrase: &str, salt: &[u8]) -> Self {
+        Self(pbkdf2_hmac_sha256(
+            passphrase.as_bytes(),
+            salt,
+            PASSPHRASE_ITERATIONS,
+        ))
+    }
+}
+
+/// PBKDF2 ([RFC 8018](https://www.rfc-editor.org/rfc/rfc8018#section-5.2)) with HMAC-SHA256,
+/// producing a single block of output.
+fn pbkdf2_hmac_sha256(password: &[u8], salt: &[u8], iterations: u32) -> [u8; KEY_SIZE] {
+    let mac = Hmac::<Sha256>::new_from_slice(password).expect("HMAC can take keys of any size");
+    let mut block = mac.clone();
+    block.update(salt);
+    block.update(&1u32.to_be_bytes());
+    let mut u = block.finalize().into_bytes();
+    let mut key = u;
+    for _ in 1..iterations {
+        let mut block = mac.clone();
+        block.update(&u);
+        u = block.finalize().into_bytes();
+        key.iter_mut().zip(u.iter()).for_each(|(k, u)| *k ^= u);
+    }
+    key.into()
 }
 
 fn parse_hex_key(s: &str) -> Result<[u8; KEY_SIZE], KeyParseError> {
@@ -187,21 +240,84 @@ impl error::Error for KeyParseError {
     }
 }
 
+/// Error when loading a PreSharedKey from a file
+#[derive(Debug)]
+pub enum KeyLoadError {
+    /// the file could not be read
+    Io(IoError),
+    /// the file does not contain a valid key
+    Parse(KeyParseError),
+}
+
+impl From<IoError> for KeyLoadError {
+    fn from(err: IoError) -> KeyLoadError {
+        KeyLoadError::Io(err)
+    }
+}
+
+impl fmt::Display for KeyLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyLoadError::Io(e) => write!(f, "Failed to read key file: {e}"),
+            KeyLoadError::Parse(e) => write!(f, "Invalid key file: {e}"),
+        }
+    }
+}
+
+impl error::Error for KeyLoadError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            KeyLoadError::Io(e) => Some(e),
+            KeyLoadError::Parse(e) => Some(e),
+        }
+    }
+}
+
 /// Private network configuration
 #[derive(Debug, Copy, Clone)]
 pub struct PnetConfig {
     /// the PreSharedKey to use for encryption
     key: PreSharedKey,
+    /// a previously used PreSharedKey that remote peers may still encrypt with, until the
+    /// given instant.
+    previous_key: Option<(PreSharedKey, Instant)>,
+    /// the versions of the protocol to support
+    mode: Mode,
 }
 impl PnetConfig {
     pub fn new(key: PreSharedKey) -> Self {
-        Self { key }
+        Self {
+            key,
+            previous_key: None,
+            mode: Mode::V1,
+        }
+    }
+
+    /// Set the versions of the protocol to support, see [`Mode`].
+    pub fn with_mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Additionally accept traffic encrypted with a previous key for the given grace period,
+    /// allowing the key of a network to be rotated without downtime.
+    ///
+    /// Outbound traffic is always encrypted with the current key. Which key the remote
+    /// encrypts with is determined from the first bytes it sends, which for libp2p
+    /// connections are the multistream-select header.
+    ///
+    /// To rotate the key of a network, first configure all nodes to additionally accept the
+    /// new key while still using the old one, then switch all nodes to the new key while
+    /// accepting the old one until the grace period ends.
+    pub fn with_previous_key(mut self, key: PreSharedKey, grace_period: Duration) -> Self {
+        self.previous_key = Some((key, Instant::now() + grace_period));
+        self
     }
 
     /// upgrade a connection to use pre shared key encryption.
     ///
     /// the upgrade works by both sides exchanging 24 byte nonces and then encrypting
-    /// subsequent traffic with XSalsa20
+    /// subsequent traffic with XSalsa20, or XChaCha20-Poly1305 if both sides support pnet v2
     pub async fn handshake<TSocket>(
         self,
         mut socket: TSocket,
@@ -213,6 +329,10 @@ impl PnetConfig {
         let mut local_nonce = [0u8; NONCE_SIZE];
         let mut remote_nonce = [0u8; NONCE_SIZE];
         rand::thread_rng().fill_bytes(&mut local_nonce);
+        if self.mode != Mode::V1 {
+            let (random, tag) = local_nonce.split_at_mut(NONCE_SIZE - V2_TAG_SIZE);
+            tag.copy_from_slice(&v2_tag(&self.key, random));
+        }
         socket
             .write_all(&local_nonce)
             .await
@@ -222,38 +342,209 @@ impl PnetConfig {
             .read_exact(&mut remote_nonce)
             .await
             .map_err(PnetError::HandshakeError)?;
+        let previous_key = self
+            .previous_key
+            .filter(|(_, expiry)| Instant::now() < *expiry)
+            .map(|(key, _)| key);
+
+        if self.mode != Mode::V1 {
+            let (random, tag) = remote_nonce.split_at(NONCE_SIZE - V2_TAG_SIZE);
+            let remote_key = std::iter::once(self.key)
+                .chain(previous_key)
+                .find(|key| v2_tag(key, random) == tag);
+            if let Some(remote_key) = remote_key {
+                trace!("setting up authenticated encryption");
+                return Ok(PnetOutput {
+                    inner: Either::Right(AeadOutput::new(
+                        socket,
+                        &self.key.0,
+                        local_nonce,
+                        &remote_key.0,
+                        remote_nonce,
+                    )),
+                });
+            }
+            if self.mode == Mode::V2 {
+                return Err(PnetError::HandshakeError(IoError::new(
+                    io::ErrorKind::InvalidData,
+                    "remote does not support authenticated encryption",
+                )));
+            }
+            trace!("remote does not support authenticated encryption, falling back to v1");
+        }
+
         trace!("setting up ciphers");
         let write_cipher = XSalsa20::new(&self.key.0.into(), &local_nonce.into());
         let read_cipher = XSalsa20::new(&self.key.0.into(), &remote_nonce.into());
-        Ok(PnetOutput::new(socket, write_cipher, read_cipher))
+        let mut output = StreamCipherOutput::new(socket, write_cipher, read_cipher);
+        if let Some(key) = previous_key {
+            output.previous_read_cipher = Some(XSalsa20::new(&key.0.into(), &remote_nonce.into()));
+        }
+        Ok(PnetOutput {
+            inner: Either::Left(output),
+        })
     }
 }
 
+/// Versions of the pnet protocol to support.
+///
+/// Version 1 encrypts traffic with the XSalsa20 stream cipher, which does not protect its
+/// integrity. Version 2 instead encrypts traffic in frames authenticated with
+/// XChaCha20-Poly1305, such that tampering is detected. Support for version 2 is announced
+/// in the nonces exchanged by version 1, so it can fall back to version 1.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    /// Only support version 1.
+    V1,
+    /// Use version 2 if the remote supports it, version 1 otherwise.
+    PreferV2,
+    /// Only support version 2.
+    V2,
+}
+
+/// Computes the tag announcing support for pnet v2, proving knowledge of the key.
+fn v2_tag(key: &PreSharedKey, random: &[u8]) -> [u8; V2_TAG_SIZE] {
+    use std::io::{Read, Write};
+    let mut out = [0u8; V2_TAG_SIZE];
+    let mut hasher = Shake128::default();
+    hasher
+        .write_all(b"/key/swarm/psk/2.0.0/")
+        .and_then(|()| hasher.write_all(&key.0))
+        .and_then(|()| hasher.write_all(random))
+        .expect("shake128 failed");
+    hasher
+        .finalize_xof()
+        .read_exact(&mut out)
+        .expect("shake128 failed");
+    out
+}
+
 /// The result of a handshake. This implements AsyncRead and AsyncWrite and can therefore
 /// be used as base for additional upgrades.
 #[pin_project]
 pub struct PnetOutput<S> {
+    #[pin]
+    inner: Either<StreamCipherOutput<S>, AeadOutput<S>>,
+}
+
+impl<S> PnetOutput<S> {
+    /// Whether the connection uses the authenticated encryption of pnet v2.
+    pub fn is_authenticated(&self) -> bool {
+        matches!(self.inner, Either::Right(_))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite> AsyncRead for PnetOutput<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        self.project().inner.poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite> AsyncWrite for PnetOutput<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        self.project().inner.poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// A connection encrypted with XSalsa20, as specified by pnet v1.
+#[pin_project]
+struct StreamCipherOutput<S> {
     #[pin]
     inner: CryptWriter<S>,
     read_cipher: XSalsa20,
+    /// cipher for the previous key, as long as it is undecided which key the remote uses.
+    previous_read_cipher: Option<XSalsa20>,
+    /// encrypted bytes received while it is undecided which key the remote uses.
+    undecided: Vec<u8>,
+    /// decrypted bytes not yet returned to the reader.
+    decrypted: Vec<u8>,
 }
 
-impl<S: AsyncRead + AsyncWrite> PnetOutput<S> {
+impl<S: AsyncRead + AsyncWrite> StreamCipherOutput<S> {
     fn new(inner: S, write_cipher: XSalsa20, read_cipher: XSalsa20) -> Self {
         Self {
             inner: CryptWriter::with_capacity(WRITE_BUFFER_SIZE, inner, write_cipher),
             read_cipher,
+            previous_read_cipher: None,
+            undecided: Vec::new(),
+            decrypted: Vec::new(),
         }
     }
 }
 
-impl<S: AsyncRead + AsyncWrite> AsyncRead for PnetOutput<S> {
+/// Decrypts `data` with whichever of the two ciphers yields the multistream-select header,
+/// defaulting to `current`, and leaves that cipher in `current`.
+fn decrypt_with_either(current: &mut XSalsa20, mut previous: XSalsa20, data: &[u8]) -> Vec<u8> {
+    let mut plain = data.to_vec();
+    current.apply_keystream(&mut plain);
+    if plain == MULTISTREAM_HEADER {
+        return plain;
+    }
+    let mut previous_plain = data.to_vec();
+    previous.apply_keystream(&mut previous_plain);
+    if previous_plain == MULTISTREAM_HEADER {
+        trace!("remote uses the previous key");
+        *current = previous;
+        return previous_plain;
+    }
+    trace!("remote does not start with the multistream-select header, using the current key");
+    plain
+}
+
+impl<S: AsyncRead + AsyncWrite> AsyncRead for StreamCipherOutput<S> {
     fn poll_read(
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
         buf: &mut [u8],
     ) -> Poll<Result<usize, io::Error>> {
-        let this = self.project();
+        let mut this = self.project();
+        loop {
+            if !this.decrypted.is_empty() {
+                let size = buf.len().min(this.decrypted.len());
+                buf[..size].copy_from_slice(&this.decrypted[..size]);
+                this.decrypted.drain(..size);
+                return Poll::Ready(Ok(size));
+            }
+            if this.previous_read_cipher.is_none() {
+                break;
+            }
+            let mut chunk = [0u8; MULTISTREAM_HEADER.len()];
+            let missing = MULTISTREAM_HEADER.len() - this.undecided.len();
+            let size = futures::ready!(this
+                .inner
+                .as_mut()
+                .get_pin_mut()
+                .poll_read(cx, &mut chunk[..missing]))?;
+            this.undecided.extend_from_slice(&chunk[..size]);
+            if size != 0 && this.undecided.len() < MULTISTREAM_HEADER.len() {
+                continue;
+            }
+            let previous = this
+                .previous_read_cipher
+                .take()
+                .expect("checked to be `Some` above");
+            *this.decrypted = decrypt_with_either(this.read_cipher, previous, this.undecided);
+            this.undecided.clear();
+            if this.decrypted.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+        }
         let result = this.inner.get_pin_mut().poll_read(cx, buf);
         if let Poll::Ready(Ok(size)) = &result {
             trace!("read {} bytes", size);
@@ -264,7 +555,7 @@ impl<S: AsyncRead + AsyncWrite> AsyncRead for PnetOutput<S> {
     }
 }
 
-impl<S: AsyncRead + AsyncWrite> AsyncWrite for PnetOutput<S> {
+impl<S: AsyncRead + AsyncWrite> AsyncWrite for StreamCipherOutput<S> {
     fn poll_write(
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
@@ -364,6 +655,41 @@ mod tests {
         );
     }
 
+    #[test]
+    fn psk_from_file() {
+        let key = PreSharedKey::new([7; KEY_SIZE]);
+        let path = std::env::temp_dir().join(format!("pnet-swarm-{}.key", std::process::id()));
+        fs::write(&path, key.to_string()).unwrap();
+        let loaded = PreSharedKey::from_file(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), key);
+
+        assert!(matches!(
+            PreSharedKey::from_file(path),
+            Err(KeyLoadError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn psk_from_passphrase() {
+        let key = PreSharedKey::from_passphrase("password", b"salt");
+        assert_eq!(key, PreSharedKey::from_passphrase("password", b"salt"));
+        assert_ne!(key, PreSharedKey::from_passphrase("password", b"pepper"));
+        assert_ne!(key, PreSharedKey::from_passphrase("passphrase", b"salt"));
+    }
+
+    #[test]
+    fn pbkdf2_test_vectors() {
+        assert_eq!(
+            to_hex(&pbkdf2_hmac_sha256(b"password", b"salt", 1)),
+            "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"
+        );
+        assert_eq!(
+            to_hex(&pbkdf2_hmac_sha256(b"password", b"salt", 4096)),
+            "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"
+        );
+    }
+
     #[test]
     fn fingerprint() {
         // checked against go-ipfs output
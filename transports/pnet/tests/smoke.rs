@@ -1,12 +1,13 @@
 use std::time::Duration;
 
-use futures::{future, AsyncRead, AsyncWrite, StreamExt};
-use libp2p_core::transport::MemoryTransport;
+use futures::{future, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, StreamExt};
+use libp2p_core::transport::{ListenerId, MemoryTransport, TransportEvent};
 use libp2p_core::upgrade::Version;
 use libp2p_core::Transport;
 use libp2p_core::{multiaddr::Protocol, Multiaddr};
-use libp2p_pnet::{PnetConfig, PreSharedKey};
+use libp2p_pnet::{Mode, PnetConfig, PnetError, PnetOutput, PreSharedKey};
 use libp2p_swarm::{keep_alive, NetworkBehaviour, Swarm, SwarmBuilder, SwarmEvent};
+use std::pin::Pin;
 
 const TIMEOUT: Duration = Duration::from_secs(5);
 
@@ -98,6 +99,155 @@ where
     assert_eq!(&outbound_peer_id, swarm1.local_peer_id());
 }
 
+#[tokio::test]
+async fn can_establish_connection_during_key_rotation() {
+    let old_key = PreSharedKey::new([0; 32]);
+    let new_key = PreSharedKey::new([1; 32]);
+    let grace_period = Duration::from_secs(60);
+
+    let rotated = PnetConfig::new(new_key).with_previous_key(old_key, grace_period);
+    let accepting = PnetConfig::new(old_key).with_previous_key(new_key, grace_period);
+
+    assert!(try_connect(rotated, accepting).await);
+    assert!(try_connect(accepting, rotated).await);
+    assert!(try_connect(rotated, rotated).await);
+    assert!(!try_connect(rotated, PnetConfig::new(old_key)).await);
+}
+
+#[tokio::test]
+async fn previous_key_is_rejected_after_grace_period() {
+    let old_key = PreSharedKey::new([0; 32]);
+    let new_key = PreSharedKey::new([1; 32]);
+
+    let expired = PnetConfig::new(new_key).with_previous_key(old_key, Duration::ZERO);
+    let accepting = PnetConfig::new(old_key).with_previous_key(new_key, TIMEOUT);
+
+    assert!(!try_connect(expired, accepting).await);
+}
+
+#[tokio::test]
+async fn negotiates_authenticated_encryption() {
+    let key = PreSharedKey::new([0; 32]);
+    let v1 = PnetConfig::new(key);
+    let prefer_v2 = PnetConfig::new(key).with_mode(Mode::PreferV2);
+    let v2 = PnetConfig::new(key).with_mode(Mode::V2);
+
+    let (mut dialer, mut listener) = handshake(prefer_v2, v2).await.unwrap();
+    assert!(dialer.is_authenticated() && listener.is_authenticated());
+    assert_roundtrip(&mut dialer, &mut listener).await;
+
+    let (mut dialer, mut listener) = handshake(prefer_v2, v1).await.unwrap();
+    assert!(!dialer.is_authenticated() && !listener.is_authenticated());
+    assert_roundtrip(&mut dialer, &mut listener).await;
+
+    assert!(handshake(v2, v1).await.is_err());
+    assert!(try_connect(prefer_v2, prefer_v2).await);
+    assert!(try_connect(prefer_v2, v1).await);
+}
+
+#[tokio::test]
+async fn authenticated_encryption_accepts_previous_key() {
+    let old_key = PreSharedKey::new([0; 32]);
+    let new_key = PreSharedKey::new([1; 32]);
+    let grace_period = Duration::from_secs(60);
+
+    let rotated = PnetConfig::new(new_key)
+        .with_previous_key(old_key, grace_period)
+        .with_mode(Mode::V2);
+    let accepting = PnetConfig::new(old_key)
+        .with_previous_key(new_key, grace_period)
+        .with_mode(Mode::V2);
+
+    let (mut dialer, mut listener) = handshake(rotated, accepting).await.unwrap();
+    assert_roundtrip(&mut dialer, &mut listener).await;
+    assert!(
+        handshake(rotated, PnetConfig::new(old_key).with_mode(Mode::V2))
+            .await
+            .is_err()
+    );
+}
+
+/// Performs the pnet handshake over a connection of the memory transport.
+async fn handshake(
+    dialer: PnetConfig,
+    listener: PnetConfig,
+) -> Result<
+    (
+        PnetOutput<impl AsyncRead + AsyncWrite>,
+        PnetOutput<impl AsyncRead + AsyncWrite>,
+    ),
+    PnetError,
+> {
+    let mut transport = MemoryTransport::default();
+    transport
+        .listen_on(ListenerId::next(), Protocol::Memory(0).into())
+        .unwrap();
+    let addr = match future::poll_fn(|cx| Pin::new(&mut transport).poll(cx)).await {
+        TransportEvent::NewAddress { listen_addr, .. } => listen_addr,
+        e => panic!("Unexpected event: {e:?}"),
+    };
+    let dial = MemoryTransport::default().dial(addr).unwrap();
+    let accept = async {
+        match future::poll_fn(|cx| Pin::new(&mut transport).poll(cx)).await {
+            TransportEvent::Incoming { upgrade, .. } => upgrade.await.unwrap(),
+            e => panic!("Unexpected event: {e:?}"),
+        }
+    };
+    let (outbound, inbound) = future::join(dial, accept).await;
+    let (dialer, listener) = future::join(
+        dialer.handshake(outbound.unwrap()),
+        listener.handshake(inbound),
+    )
+    .await;
+    Ok((dialer?, listener?))
+}
+
+async fn assert_roundtrip(
+    a: &mut (impl AsyncRead + AsyncWrite + Unpin),
+    b: &mut (impl AsyncRead + AsyncWrite + Unpin),
+) {
+    let msg = b"/multistream/1.0.0 and more";
+    a.write_all(msg).await.unwrap();
+    a.flush().await.unwrap();
+    let mut buf = [0; 27];
+    b.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, msg);
+}
+
+/// Connects a swarm using `dialer` to a swarm using `listener` over the memory transport,
+/// returning whether both sides established the connection.
+async fn try_connect(dialer: PnetConfig, listener: PnetConfig) -> bool {
+    let mut swarm1 = make_swarm(MemoryTransport::default(), listener);
+    let mut swarm2 = make_swarm(MemoryTransport::default(), dialer);
+
+    let listen_address = listen_on(&mut swarm1, Protocol::Memory(0).into()).await;
+    swarm2.dial(listen_address).unwrap();
+    let await_inbound_connection = async {
+        loop {
+            match swarm1.select_next_some().await {
+                SwarmEvent::ConnectionEstablished { .. } => break true,
+                SwarmEvent::IncomingConnectionError { .. } => break false,
+                _ => continue,
+            };
+        }
+    };
+    let await_outbound_connection = async {
+        loop {
+            match swarm2.select_next_some().await {
+                SwarmEvent::ConnectionEstablished { .. } => break true,
+                SwarmEvent::OutgoingConnectionError { .. } => break false,
+                _ => continue,
+            };
+        }
+    };
+
+    let connected = future::join(await_inbound_connection, await_outbound_connection);
+    match tokio::time::timeout(TIMEOUT, connected).await {
+        Ok((inbound, outbound)) => inbound && outbound,
+        Err(_) => false,
+    }
+}
+
 fn make_swarm<T>(transport: T, pnet: PnetConfig) -> Swarm<keep_alive::Behaviour>
 where
     T: Transport + Send + Unpin + 'static,
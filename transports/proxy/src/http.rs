@@ -0,0 +1,542 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Falling back to tunneling through HTTP proxies via `CONNECT` requests.
+
+use crate::{invalid_data, Error, Target};
+use base64::Engine;
+use futures::{channel::mpsc, future::BoxFuture, prelude::*};
+use futures_timer::Delay;
+use libp2p_core::{
+    connection::Endpoint,
+    multiaddr::Multiaddr,
+    transport::{ListenerId, TransportError, TransportEvent},
+    Subscribers,
+};
+use parking_lot::Mutex;
+use std::{
+    error, io,
+    net::IpAddr,
+    ops::DerefMut,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// Max. size of the response to an HTTP `CONNECT` request.
+const MAX_RESPONSE_SIZE: usize = 8 * 1024;
+
+/// Max. number of headers of the response to an HTTP `CONNECT` request.
+const MAX_NUM_HEADERS: usize = 32;
+
+/// Configuration of a [`Transport`].
+#[derive(Debug, Clone)]
+pub struct Config {
+    proxy: Multiaddr,
+    credentials: Option<(String, String)>,
+    direct_timeout: Duration,
+}
+
+impl Config {
+    /// Fall back to the HTTP proxy listening on the given address.
+    ///
+    /// The proxy is dialed with the inner transport, unless the [`Transport`] is created with a
+    /// separate transport for the proxy via [`Transport::with_proxy_transport`], e.g. to
+    /// connect to proxies requiring TLS, i.e. `https://` proxies.
+    pub fn new(proxy: Multiaddr) -> Self {
+        Config {
+            proxy,
+            credentials: None,
+            direct_timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Authenticate with the given username and password, via basic authentication.
+    pub fn with_credentials(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.credentials = Some((username.into(), password.into()));
+        self
+    }
+
+    /// Sets how long to wait for a direct connection before falling back to the proxy.
+    ///
+    /// Networks blocking outbound connections often silently drop them instead of refusing
+    /// them. Defaults to 10 seconds.
+    pub fn with_direct_timeout(mut self, timeout: Duration) -> Self {
+        self.direct_timeout = timeout;
+        self
+    }
+}
+
+/// How a connection was established.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Path {
+    /// The address was dialed directly.
+    Direct,
+    /// The connection was tunneled through the proxy.
+    Proxy,
+}
+
+/// Event of a [`Transport`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum Event {
+    /// A dialed address was connected to.
+    Connected {
+        /// The dialed address.
+        address: Multiaddr,
+        /// How the connection was established.
+        path: Path,
+    },
+}
+
+/// A transport dialing TCP addresses directly, falling back to tunneling through an HTTP proxy.
+///
+/// `/onion3` addresses, which cannot be dialed directly, and addresses the inner transport does
+/// not support, e.g. DNS names if it does not resolve them, are dialed through the proxy.
+/// Listening is delegated to the inner transport. Addresses which are not TCP addresses are not
+/// supported.
+///
+/// Connections established directly are [`Either::Left`](future::Either::Left), connections
+/// tunneled through a proxy dialed with a separate transport are
+/// [`Either::Right`](future::Either::Right).
+#[derive(Debug)]
+pub struct Transport<T, P = T> {
+    /// The underlying transport.
+    inner: Arc<Mutex<T>>,
+    /// The transport dialing the proxy, if it is not dialed with `inner`.
+    proxy_transport: Option<Arc<Mutex<P>>>,
+    config: Config,
+    /// The receivers of [`Event`]s.
+    subscribers: Subscribers<Event>,
+}
+
+impl<T> Transport<T> {
+    /// Create a new transport wrapping `inner`, which also dials the proxy.
+    pub fn new(inner: T, config: Config) -> Self {
+        Transport {
+            inner: Arc::new(Mutex::new(inner)),
+            proxy_transport: None,
+            config,
+            subscribers: Default::default(),
+        }
+    }
+}
+
+impl<T, P> Transport<T, P> {
+    /// Create a new transport wrapping `inner`, dialing the proxy with `proxy_transport`.
+    ///
+    /// Errors of `proxy_transport` are reported as [`Error::Proxy`].
+    pub fn with_proxy_transport(inner: T, proxy_transport: P, config: Config) -> Self {
+        Transport {
+            inner: Arc::new(Mutex::new(inner)),
+            proxy_transport: Some(Arc::new(Mutex::new(proxy_transport))),
+            config,
+            subscribers: Default::default(),
+        }
+    }
+
+    /// Returns a receiver of the [`Event`]s of the transport, reporting how connections were
+    /// established, see [`Subscribers`].
+    pub fn subscribe(&self) -> mpsc::Receiver<Event> {
+        self.subscribers.subscribe()
+    }
+}
+
+impl<T, P> libp2p_core::Transport for Transport<T, P>
+where
+    T: libp2p_core::Transport + Send + Unpin + 'static,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T::Error: Send + 'static,
+    T::Dial: Send + 'static,
+    P: libp2p_core::Transport + Send + Unpin + 'static,
+    P::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    P::Error: error::Error + Send + Sync + 'static,
+    P::Dial: Send + 'static,
+{
+    type Output = future::Either<T::Output, P::Output>;
+    type Error = Error<T::Error>;
+    type ListenerUpgrade = future::MapOk<
+        future::MapErr<T::ListenerUpgrade, fn(T::Error) -> Self::Error>,
+        fn(T::Output) -> Self::Output,
+    >;
+    type Dial = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn listen_on(
+        &mut self,
+        id: ListenerId,
+        addr: Multiaddr,
+    ) -> Result<(), TransportError<Self::Error>> {
+        self.inner
+            .lock()
+            .listen_on(id, addr)
+            .map_err(|e| e.map(Error::Transport))
+    }
+
+    fn remove_listener(&mut self, id: ListenerId) -> bool {
+        self.inner.lock().remove_listener(id)
+    }
+
+    fn dial(&mut self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        self.do_dial(addr, Endpoint::Dialer)
+    }
+
+    fn dial_as_listener(
+        &mut self,
+        addr: Multiaddr,
+    ) -> Result<Self::Dial, TransportError<Self::Error>> {
+        self.do_dial(addr, Endpoint::Listener)
+    }
+
+    fn address_translation(&self, server: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        self.inner.lock().address_translation(server, observed)
+    }
+
+    fn poll(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<TransportEvent<Self::ListenerUpgrade, Self::Error>> {
+        let mut inner = self.inner.lock();
+        libp2p_core::Transport::poll(Pin::new(inner.deref_mut()), cx).map(|event| {
+            event
+                .map_upgrade(|upgr| {
+                    upgr.map_err::<_, fn(_) -> _>(Error::Transport)
+                        .map_ok::<_, fn(_) -> _>(future::Either::Left)
+                })
+                .map_err(Error::Transport)
+        })
+    }
+}
+
+impl<T, P> Transport<T, P>
+where
+    T: libp2p_core::Transport + Send + Unpin + 'static,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T::Error: Send + 'static,
+    T::Dial: Send + 'static,
+    P: libp2p_core::Transport + Send + Unpin + 'static,
+    P::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    P::Error: error::Error + Send + Sync + 'static,
+    P::Dial: Send + 'static,
+{
+    fn do_dial(
+        &mut self,
+        addr: Multiaddr,
+        role_override: Endpoint,
+    ) -> Result<<Self as libp2p_core::Transport>::Dial, TransportError<Error<T::Error>>> {
+        let target = match Target::from_multiaddr(&addr) {
+            Some(target) => target,
+            None => return Err(TransportError::MultiaddrNotSupported(addr)),
+        };
+
+        let direct = if target.onion {
+            None
+        } else {
+            let dial = match role_override {
+                Endpoint::Dialer => self.inner.lock().dial(addr.clone()),
+                Endpoint::Listener => self.inner.lock().dial_as_listener(addr.clone()),
+            };
+            match dial {
+                Ok(dial) => Some(dial),
+                Err(TransportError::MultiaddrNotSupported(_)) => {
+                    log::debug!("Can't dial {} directly", addr);
+                    None
+                }
+                Err(TransportError::Other(e)) => {
+                    log::debug!("Failed to dial {} directly: {:?}", addr, e);
+                    None
+                }
+            }
+        };
+
+        let inner = self.inner.clone();
+        let proxy_transport = self.proxy_transport.clone();
+        let config = self.config.clone();
+        let subscribers = self.subscribers.clone();
+
+        Ok(async move {
+            if let Some(direct) = direct {
+                match future::select(direct.boxed(), Delay::new(config.direct_timeout)).await {
+                    future::Either::Left((Ok(output), _)) => {
+                        subscribers.emit(Event::Connected {
+                            address: addr,
+                            path: Path::Direct,
+                        });
+                        return Ok(future::Either::Left(output));
+                    }
+                    future::Either::Left((Err(e), _)) => {
+                        log::debug!("Failed to dial {} directly: {:?}", addr, e);
+                    }
+                    future::Either::Right(((), _)) => {
+                        log::debug!("Timed out dialing {} directly", addr);
+                    }
+                }
+            }
+
+            log::debug!("Dialing {} via HTTP proxy {}", addr, config.proxy);
+
+            let mut stream = match proxy_transport {
+                Some(proxy_transport) => {
+                    let dial = match role_override {
+                        Endpoint::Dialer => proxy_transport.lock().dial(config.proxy.clone()),
+                        Endpoint::Listener => proxy_transport
+                            .lock()
+                            .dial_as_listener(config.proxy.clone()),
+                    };
+                    let proxy_error = |e| Error::Proxy(io::Error::new(io::ErrorKind::Other, e));
+                    match dial {
+                        Ok(dial) => future::Either::Right(dial.await.map_err(proxy_error)?),
+                        Err(TransportError::MultiaddrNotSupported(a)) => {
+                            return Err(Error::UnsupportedProxy(a))
+                        }
+                        Err(TransportError::Other(e)) => return Err(proxy_error(e)),
+                    }
+                }
+                None => {
+                    let dial = match role_override {
+                        Endpoint::Dialer => inner.lock().dial(config.proxy.clone()),
+                        Endpoint::Listener => inner.lock().dial_as_listener(config.proxy.clone()),
+                    };
+                    match dial {
+                        Ok(dial) => future::Either::Left(dial.await.map_err(Error::Transport)?),
+                        Err(TransportError::MultiaddrNotSupported(a)) => {
+                            return Err(Error::UnsupportedProxy(a))
+                        }
+                        Err(TransportError::Other(e)) => return Err(Error::Transport(e)),
+                    }
+                }
+            };
+            let credentials = config
+                .credentials
+                .as_ref()
+                .map(|(username, password)| (username.as_str(), password.as_str()));
+            connect(&mut stream, &target.host, target.port, credentials)
+                .await
+                .map_err(Error::Proxy)?;
+
+            subscribers.emit(Event::Connected {
+                address: addr,
+                path: Path::Proxy,
+            });
+            Ok(stream)
+        }
+        .boxed())
+    }
+}
+
+/// Asks the HTTP proxy, connected to via `stream`, to tunnel the stream to `host:port` via a
+/// `CONNECT` request, authenticating with the given username and password, if any.
+///
+/// This is the handshake performed by [`Transport`], for transports that establish the
+/// connection to the proxy on their own.
+pub async fn connect<S>(
+    stream: &mut S,
+    host: &str,
+    port: u16,
+    credentials: Option<(&str, &str)>,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let authority = match host.parse() {
+        Ok(IpAddr::V6(ip)) => format!("[{ip}]:{port}"),
+        _ => format!("{host}:{port}"),
+    };
+    let mut request = format!("CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n");
+    if let Some((username, password)) = credentials {
+        let token =
+            base64::engine::general_purpose::STANDARD.encode(format!("{username}:{password}"));
+        request.push_str(&format!("Proxy-Authorization: Basic {token}\r\n"));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+    stream.flush().await?;
+
+    // Read byte by byte so as not to consume anything the remote sends after the response.
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() == MAX_RESPONSE_SIZE {
+            return Err(invalid_data("proxy response too large"));
+        }
+        let mut byte = [0];
+        stream.read_exact(&mut byte).await?;
+        response.push(byte[0]);
+    }
+
+    let mut headers = [httparse::EMPTY_HEADER; MAX_NUM_HEADERS];
+    let mut parsed = httparse::Response::new(&mut headers);
+    parsed
+        .parse(&response)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    match parsed.code {
+        Some(code) if (200..300).contains(&code) => Ok(()),
+        Some(code) => Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("proxy refused to connect; status code = {code}"),
+        )),
+        None => Err(invalid_data("incomplete proxy response")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::net::TcpListener;
+    use libp2p_core::{multiaddr::Protocol, Transport as _};
+
+    fn tcp() -> libp2p_tcp::async_io::Transport {
+        libp2p_tcp::async_io::Transport::new(libp2p_tcp::Config::default())
+    }
+
+    fn tcp_addr(listener: &TcpListener) -> Multiaddr {
+        let addr = listener.local_addr().unwrap();
+        Multiaddr::empty()
+            .with(addr.ip().into())
+            .with(Protocol::Tcp(addr.port()))
+    }
+
+    /// An address nothing listens on.
+    async fn unused_addr() -> Multiaddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        tcp_addr(&listener)
+    }
+
+    /// Accepts a connection on a fake HTTP proxy, which returns the `CONNECT` request it
+    /// received after answering `response`.
+    async fn serve_proxy(listener: TcpListener, response: &[u8]) -> String {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        while !request.ends_with(b"\r\n\r\n") {
+            let mut byte = [0];
+            stream.read_exact(&mut byte).await.unwrap();
+            request.push(byte[0]);
+        }
+        stream.write_all(response).await.unwrap();
+        String::from_utf8(request).unwrap()
+    }
+
+    #[async_std::test]
+    async fn dials_directly_if_possible() {
+        let mut transport = Transport::new(tcp(), Config::new(unused_addr().await));
+        let mut events = transport.subscribe();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = tcp_addr(&listener);
+        let (accepted, dialed) =
+            futures::join!(listener.accept(), transport.dial(addr.clone()).unwrap());
+        accepted.unwrap();
+        dialed.unwrap();
+
+        assert!(matches!(
+            events.next().await,
+            Some(Event::Connected { address, path: Path::Direct }) if address == addr
+        ));
+    }
+
+    #[async_std::test]
+    async fn falls_back_to_proxy() {
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = Config::new(tcp_addr(&proxy)).with_credentials("user", "pass");
+        let mut transport = Transport::new(tcp(), config);
+        let mut events = transport.subscribe();
+
+        let addr = unused_addr().await;
+        let port = addr
+            .iter()
+            .find_map(|p| match p {
+                Protocol::Tcp(port) => Some(port),
+                _ => None,
+            })
+            .unwrap();
+        let (request, dialed) = futures::join!(
+            serve_proxy(proxy, b"HTTP/1.1 200 Connection established\r\n\r\n"),
+            transport.dial(addr.clone()).unwrap()
+        );
+        dialed.unwrap();
+        assert_eq!(
+            request,
+            format!(
+                "CONNECT 127.0.0.1:{port} HTTP/1.1\r\nHost: 127.0.0.1:{port}\r\n\
+                Proxy-Authorization: Basic dXNlcjpwYXNz\r\n\r\n"
+            )
+        );
+
+        assert!(matches!(
+            events.next().await,
+            Some(Event::Connected { address, path: Path::Proxy }) if address == addr
+        ));
+    }
+
+    #[async_std::test]
+    async fn falls_back_to_proxy_transport_for_unsupported_addresses() {
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut transport =
+            Transport::with_proxy_transport(tcp(), tcp(), Config::new(tcp_addr(&proxy)));
+
+        // The inner transport does not resolve DNS names.
+        let addr = "/dns4/example.com/tcp/443".parse().unwrap();
+        let (request, dialed) = futures::join!(
+            serve_proxy(proxy, b"HTTP/1.1 200 Connection established\r\n\r\n"),
+            transport.dial(addr).unwrap()
+        );
+        assert!(matches!(dialed, Ok(future::Either::Right(_))));
+        assert_eq!(
+            request,
+            "CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n"
+        );
+    }
+
+    #[async_std::test]
+    async fn proxy_refusal() {
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut transport = Transport::new(tcp(), Config::new(tcp_addr(&proxy)));
+
+        let (_, dialed) = futures::join!(
+            serve_proxy(proxy, b"HTTP/1.1 403 Forbidden\r\n\r\n"),
+            transport.dial(unused_addr().await).unwrap()
+        );
+        match dialed {
+            Err(Error::Proxy(e)) => assert_eq!(e.kind(), io::ErrorKind::ConnectionRefused),
+            _ => panic!("expected proxy error"),
+        }
+    }
+
+    #[async_std::test]
+    async fn connect_ipv6() {
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = proxy.local_addr().unwrap();
+
+        let client = async {
+            let mut stream = async_std::net::TcpStream::connect(addr).await.unwrap();
+            connect(&mut stream, "::1", 80, None).await
+        };
+        let (request, connected) =
+            futures::join!(serve_proxy(proxy, b"HTTP/1.1 200 OK\r\n\r\n"), client);
+        connected.unwrap();
+        assert_eq!(
+            request,
+            "CONNECT [::1]:80 HTTP/1.1\r\nHost: [::1]:80\r\n\r\n"
+        );
+    }
+}
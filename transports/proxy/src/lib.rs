@@ -0,0 +1,163 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Transports establishing TCP connections through proxies.
+//!
+//! [`socks5::Transport`] wraps a TCP-based transport and dials addresses through a SOCKS5
+//! ([RFC 1928](https://datatracker.ietf.org/doc/html/rfc1928)) proxy, e.g. Tor. DNS names are
+//! resolved by the proxy, so the transport must not be wrapped in a DNS transport, which would
+//! resolve them locally. `/onion3` addresses are dialed as `<address>.onion` hosts.
+//!
+//! [`http::Transport`] dials addresses directly with the wrapped transport and falls back to
+//! tunneling the connection through an HTTP proxy via a `CONNECT` request if that fails, e.g.
+//! in networks only permitting outbound connections through a proxy.
+
+#![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
+
+pub mod http;
+pub mod socks5;
+
+use data_encoding::BASE32;
+use libp2p_core::multiaddr::{Multiaddr, Protocol};
+use std::{error, fmt, io};
+
+/// The host and port a proxy is asked to connect to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Target {
+    host: String,
+    port: u16,
+    onion: bool,
+}
+
+impl Target {
+    /// Extracts the target of a TCP address, i.e. an IP address or DNS name followed by `/tcp`,
+    /// or an `/onion3` address, optionally followed by `/p2p`.
+    pub(crate) fn from_multiaddr(addr: &Multiaddr) -> Option<Self> {
+        let mut iter = addr.iter();
+        let (host, onion) = match iter.next()? {
+            Protocol::Ip4(ip) => (ip.to_string(), false),
+            Protocol::Ip6(ip) => (ip.to_string(), false),
+            Protocol::Dns(name) | Protocol::Dns4(name) | Protocol::Dns6(name) => {
+                (name.to_string(), false)
+            }
+            Protocol::Onion3(onion) => {
+                let host = format!("{}.onion", BASE32.encode(onion.hash()).to_lowercase());
+                let port = onion.port();
+                return Self::finish(iter, host, port, true);
+            }
+            _ => return None,
+        };
+        let port = match iter.next()? {
+            Protocol::Tcp(port) => port,
+            _ => return None,
+        };
+        Self::finish(iter, host, port, onion)
+    }
+
+    fn finish<'a>(
+        mut rest: impl Iterator<Item = Protocol<'a>>,
+        host: String,
+        port: u16,
+        onion: bool,
+    ) -> Option<Self> {
+        match (rest.next(), rest.next()) {
+            (None, _) | (Some(Protocol::P2p(_)), None) => Some(Target { host, port, onion }),
+            _ => None,
+        }
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Error of the transports of this crate.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error<TErr> {
+    /// Error in the underlying transport.
+    Transport(TErr),
+    /// The proxy failed to or refused to establish the connection.
+    Proxy(io::Error),
+    /// The underlying transport does not support the address of the proxy.
+    UnsupportedProxy(Multiaddr),
+}
+
+impl<TErr> fmt::Display for Error<TErr>
+where
+    TErr: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Transport(err) => write!(f, "{err}"),
+            Error::Proxy(err) => write!(f, "Proxy error: {err}"),
+            Error::UnsupportedProxy(a) => write!(f, "Unsupported proxy address: {a}"),
+        }
+    }
+}
+
+impl<TErr> error::Error for Error<TErr>
+where
+    TErr: error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Transport(err) => Some(err),
+            Error::Proxy(err) => Some(err),
+            Error::UnsupportedProxy(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(addr: &str) -> Option<(String, u16, bool)> {
+        Target::from_multiaddr(&addr.parse().unwrap()).map(|t| (t.host, t.port, t.onion))
+    }
+
+    #[test]
+    fn extracts_targets() {
+        assert_eq!(
+            target("/ip4/10.0.0.1/tcp/4001"),
+            Some(("10.0.0.1".into(), 4001, false))
+        );
+        assert_eq!(
+            target("/ip6/::1/tcp/4001/p2p/12D3KooWGQmdpzHXCqLno4mMxWXKNFQHASBeF99gTm2JR8Vu5Bdc"),
+            Some(("::1".into(), 4001, false))
+        );
+        assert_eq!(
+            target("/dns4/example.com/tcp/443"),
+            Some(("example.com".into(), 443, false))
+        );
+        assert_eq!(
+            target("/onion3/vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd:1234"),
+            Some((
+                "vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd.onion".into(),
+                1234,
+                true
+            ))
+        );
+        assert_eq!(target("/ip4/10.0.0.1/udp/4001/quic-v1"), None);
+        assert_eq!(target("/ip4/10.0.0.1/tcp/4001/ws"), None);
+        assert_eq!(target("/dnsaddr/example.com"), None);
+    }
+}
@@ -0,0 +1,539 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Dialing through SOCKS5 ([RFC 1928](https://datatracker.ietf.org/doc/html/rfc1928)) proxies.
+
+use crate::{invalid_data, Error, Target};
+use futures::{future::BoxFuture, prelude::*};
+use libp2p_core::{
+    connection::Endpoint,
+    multiaddr::Multiaddr,
+    transport::{ListenerId, TransportError, TransportEvent},
+};
+use std::{
+    fmt, io,
+    net::IpAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+const SOCKS_VERSION: u8 = 5;
+const SOCKS_AUTH_NONE: u8 = 0;
+const SOCKS_AUTH_PASSWORD: u8 = 2;
+const SOCKS_AUTH_UNACCEPTABLE: u8 = 0xff;
+const SOCKS_CMD_CONNECT: u8 = 1;
+const SOCKS_ATYP_IPV4: u8 = 1;
+const SOCKS_ATYP_DOMAIN: u8 = 3;
+const SOCKS_ATYP_IPV6: u8 = 4;
+
+type BypassRule = dyn Fn(&Multiaddr) -> bool + Send + Sync;
+
+/// Configuration of a [`Transport`].
+#[derive(Clone)]
+pub struct Config {
+    proxy: Multiaddr,
+    credentials: Option<(String, String)>,
+    bypass: Vec<Arc<BypassRule>>,
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("proxy", &self.proxy)
+            .field("bypass", &self.bypass.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Config {
+    /// Dial through the SOCKS5 proxy listening on the given address, e.g.
+    /// `/ip4/127.0.0.1/tcp/9050` for a local Tor daemon.
+    pub fn new(proxy: Multiaddr) -> Self {
+        Config {
+            proxy,
+            credentials: None,
+            bypass: Vec::new(),
+        }
+    }
+
+    /// Authenticate with the given username and password
+    /// ([RFC 1929](https://datatracker.ietf.org/doc/html/rfc1929)).
+    ///
+    /// Tor isolates the circuits of connections using different credentials.
+    pub fn with_credentials(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.credentials = Some((username.into(), password.into()));
+        self
+    }
+
+    /// Add a rule deciding per dialed address whether to bypass the proxy and dial directly,
+    /// e.g. for addresses in the local network.
+    ///
+    /// An address is dialed directly if any rule returns `true` for it. `/onion3` addresses are
+    /// always dialed through the proxy.
+    pub fn with_bypass<F>(mut self, rule: F) -> Self
+    where
+        F: Fn(&Multiaddr) -> bool + Send + Sync + 'static,
+    {
+        self.bypass.push(Arc::new(rule));
+        self
+    }
+
+    fn bypasses(&self, addr: &Multiaddr) -> bool {
+        self.bypass.iter().any(|rule| rule(addr))
+    }
+}
+
+/// A transport dialing TCP addresses through a SOCKS5 proxy.
+///
+/// The proxy is dialed with the inner transport, which must hence support its address. Listening
+/// is delegated to the inner transport. Addresses which are not TCP addresses are not supported.
+#[derive(Debug)]
+pub struct Transport<T> {
+    inner: T,
+    config: Config,
+}
+
+impl<T> Transport<T> {
+    /// Create a new transport wrapping `inner`.
+    pub fn new(inner: T, config: Config) -> Self {
+        Transport { inner, config }
+    }
+}
+
+impl<T> libp2p_core::Transport for Transport<T>
+where
+    T: libp2p_core::Transport + Unpin,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T::Error: Send + 'static,
+    T::Dial: Send + 'static,
+{
+    type Output = T::Output;
+    type Error = Error<T::Error>;
+    type ListenerUpgrade = future::MapErr<T::ListenerUpgrade, fn(T::Error) -> Self::Error>;
+    type Dial = future::Either<
+        future::MapErr<T::Dial, fn(T::Error) -> Self::Error>,
+        BoxFuture<'static, Result<Self::Output, Self::Error>>,
+    >;
+
+    fn listen_on(
+        &mut self,
+        id: ListenerId,
+        addr: Multiaddr,
+    ) -> Result<(), TransportError<Self::Error>> {
+        self.inner
+            .listen_on(id, addr)
+            .map_err(|e| e.map(Error::Transport))
+    }
+
+    fn remove_listener(&mut self, id: ListenerId) -> bool {
+        self.inner.remove_listener(id)
+    }
+
+    fn dial(&mut self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        self.do_dial(addr, Endpoint::Dialer)
+    }
+
+    fn dial_as_listener(
+        &mut self,
+        addr: Multiaddr,
+    ) -> Result<Self::Dial, TransportError<Self::Error>> {
+        self.do_dial(addr, Endpoint::Listener)
+    }
+
+    fn address_translation(&self, server: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        self.inner.address_translation(server, observed)
+    }
+
+    fn poll(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<TransportEvent<Self::ListenerUpgrade, Self::Error>> {
+        Pin::new(&mut self.inner).poll(cx).map(|event| {
+            event
+                .map_upgrade(|upgr| upgr.map_err::<_, fn(_) -> _>(Error::Transport))
+                .map_err(Error::Transport)
+        })
+    }
+}
+
+impl<T> Transport<T>
+where
+    T: libp2p_core::Transport + Unpin,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T::Error: Send + 'static,
+    T::Dial: Send + 'static,
+{
+    fn do_dial(
+        &mut self,
+        addr: Multiaddr,
+        role_override: Endpoint,
+    ) -> Result<<Self as libp2p_core::Transport>::Dial, TransportError<Error<T::Error>>> {
+        let target = match Target::from_multiaddr(&addr) {
+            Some(target) => target,
+            None => return Err(TransportError::MultiaddrNotSupported(addr)),
+        };
+
+        if !target.onion && self.config.bypasses(&addr) {
+            log::debug!("Dialing {} directly, bypassing the proxy", addr);
+            let dial = match role_override {
+                Endpoint::Dialer => self.inner.dial(addr),
+                Endpoint::Listener => self.inner.dial_as_listener(addr),
+            };
+            return dial
+                .map(|dial| future::Either::Left(dial.map_err::<_, fn(_) -> _>(Error::Transport)))
+                .map_err(|e| e.map(Error::Transport));
+        }
+
+        let proxy = self.config.proxy.clone();
+        let dial = match role_override {
+            Endpoint::Dialer => self.inner.dial(proxy.clone()),
+            Endpoint::Listener => self.inner.dial_as_listener(proxy.clone()),
+        };
+        let dial = match dial {
+            Ok(dial) => dial,
+            Err(TransportError::MultiaddrNotSupported(a)) => {
+                return Err(TransportError::Other(Error::UnsupportedProxy(a)))
+            }
+            Err(TransportError::Other(e)) => {
+                return Err(TransportError::Other(Error::Transport(e)))
+            }
+        };
+        let credentials = self.config.credentials.clone();
+
+        log::debug!("Dialing {} via SOCKS5 proxy {}", addr, proxy);
+
+        Ok(future::Either::Right(
+            async move {
+                let mut stream = dial.await.map_err(Error::Transport)?;
+                let credentials = credentials
+                    .as_ref()
+                    .map(|(username, password)| (username.as_str(), password.as_str()));
+                connect(&mut stream, &target.host, target.port, credentials)
+                    .await
+                    .map_err(Error::Proxy)?;
+                Ok(stream)
+            }
+            .boxed(),
+        ))
+    }
+}
+
+/// Asks the SOCKS5 proxy, connected to via `stream`, to tunnel the stream to `host:port`,
+/// authenticating with the given username and password, if any.
+///
+/// `host` is an IP address or a DNS name, which is resolved by the proxy. This is the handshake
+/// performed by [`Transport`], for transports that establish the connection to the proxy on
+/// their own.
+pub async fn connect<S>(
+    stream: &mut S,
+    host: &str,
+    port: u16,
+    credentials: Option<(&str, &str)>,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let method = if credentials.is_some() {
+        SOCKS_AUTH_PASSWORD
+    } else {
+        SOCKS_AUTH_NONE
+    };
+    stream.write_all(&[SOCKS_VERSION, 1, method]).await?;
+    stream.flush().await?;
+
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != SOCKS_VERSION {
+        return Err(invalid_data("unexpected SOCKS version"));
+    }
+    if reply[1] == SOCKS_AUTH_UNACCEPTABLE || reply[1] != method {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "no acceptable SOCKS authentication method",
+        ));
+    }
+
+    if let Some((username, password)) = credentials {
+        let mut request = vec![1];
+        push_len_prefixed(&mut request, username.as_bytes())?;
+        push_len_prefixed(&mut request, password.as_bytes())?;
+        stream.write_all(&request).await?;
+        stream.flush().await?;
+
+        let mut reply = [0; 2];
+        stream.read_exact(&mut reply).await?;
+        if reply[1] != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "SOCKS authentication failed",
+            ));
+        }
+    }
+
+    let mut request = vec![SOCKS_VERSION, SOCKS_CMD_CONNECT, 0];
+    match host.parse() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(SOCKS_ATYP_IPV4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(SOCKS_ATYP_IPV6);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            request.push(SOCKS_ATYP_DOMAIN);
+            push_len_prefixed(&mut request, host.as_bytes())?;
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+    stream.flush().await?;
+
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != SOCKS_VERSION {
+        return Err(invalid_data("unexpected SOCKS version"));
+    }
+    if reply[1] != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("proxy refused to connect; reply code = {}", reply[1]),
+        ));
+    }
+
+    // Skip the bound address and port, which are of no use to us.
+    let addr_len = match reply[3] {
+        SOCKS_ATYP_IPV4 => 4,
+        SOCKS_ATYP_IPV6 => 16,
+        SOCKS_ATYP_DOMAIN => {
+            let mut len = [0];
+            stream.read_exact(&mut len).await?;
+            usize::from(len[0])
+        }
+        _ => return Err(invalid_data("unexpected SOCKS address type")),
+    };
+    let mut bound = vec![0; addr_len + 2];
+    stream.read_exact(&mut bound).await?;
+
+    Ok(())
+}
+
+fn push_len_prefixed(buf: &mut Vec<u8>, bytes: &[u8]) -> io::Result<()> {
+    let len = u8::try_from(bytes.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "SOCKS field too long"))?;
+    buf.push(len);
+    buf.extend_from_slice(bytes);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::net::{TcpListener, TcpStream};
+    use libp2p_core::{multiaddr::Protocol, Transport as _};
+    use std::net::Ipv4Addr;
+
+    fn tcp() -> libp2p_tcp::async_io::Transport {
+        libp2p_tcp::async_io::Transport::new(libp2p_tcp::Config::default())
+    }
+
+    fn tcp_addr(listener: &TcpListener) -> Multiaddr {
+        let addr = listener.local_addr().unwrap();
+        Multiaddr::empty()
+            .with(addr.ip().into())
+            .with(Protocol::Tcp(addr.port()))
+    }
+
+    /// Accepts a connection on a fake SOCKS5 proxy, which expects a `CONNECT` request for a
+    /// domain name, returns it and then echoes one message.
+    async fn serve_proxy(listener: TcpListener) -> (String, u16) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+
+        let mut greeting = [0; 3];
+        stream.read_exact(&mut greeting).await.unwrap();
+        assert_eq!(greeting, [5, 1, 0]);
+        stream.write_all(&[5, 0]).await.unwrap();
+
+        let mut request = [0; 5];
+        stream.read_exact(&mut request).await.unwrap();
+        assert_eq!(request[..4], [5, 1, 0, 3]);
+        let mut host = vec![0; usize::from(request[4])];
+        stream.read_exact(&mut host).await.unwrap();
+        let mut port = [0; 2];
+        stream.read_exact(&mut port).await.unwrap();
+        stream
+            .write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0])
+            .await
+            .unwrap();
+
+        let mut msg = [0; 4];
+        stream.read_exact(&mut msg).await.unwrap();
+        stream.write_all(&msg).await.unwrap();
+
+        (String::from_utf8(host).unwrap(), u16::from_be_bytes(port))
+    }
+
+    #[async_std::test]
+    async fn dial_onion_through_proxy() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut transport = Transport::new(tcp(), Config::new(tcp_addr(&listener)));
+
+        let addr = "/onion3/vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd:1234"
+            .parse()
+            .unwrap();
+        let client = async {
+            let mut stream = transport.dial(addr).unwrap().await.unwrap();
+            stream.write_all(b"ping").await.unwrap();
+            let mut msg = [0; 4];
+            stream.read_exact(&mut msg).await.unwrap();
+            msg
+        };
+
+        let (target, msg) = futures::join!(serve_proxy(listener), client);
+        assert_eq!(
+            target,
+            (
+                "vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd.onion".to_string(),
+                1234
+            )
+        );
+        assert_eq!(&msg, b"ping");
+    }
+
+    #[async_std::test]
+    async fn dns_names_are_resolved_by_proxy() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut transport = Transport::new(tcp(), Config::new(tcp_addr(&listener)));
+
+        let client = async {
+            let mut stream = transport
+                .dial("/dns4/example.com/tcp/4001".parse().unwrap())
+                .unwrap()
+                .await
+                .unwrap();
+            stream.write_all(b"ping").await.unwrap();
+            let mut msg = [0; 4];
+            stream.read_exact(&mut msg).await.unwrap();
+        };
+
+        let (target, ()) = futures::join!(serve_proxy(listener), client);
+        assert_eq!(target, ("example.com".to_string(), 4001));
+    }
+
+    #[async_std::test]
+    async fn bypass_rules() {
+        // Nothing listens on the proxy address, so only direct dials can succeed.
+        let unused = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = tcp_addr(&unused);
+        drop(unused);
+
+        let config = Config::new(proxy).with_bypass(|addr| {
+            addr.iter()
+                .any(|p| matches!(p, Protocol::Ip4(ip) if ip.is_loopback()))
+        });
+        let mut transport = Transport::new(tcp(), config);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (accepted, dialed) = futures::join!(
+            listener.accept(),
+            transport.dial(tcp_addr(&listener)).unwrap()
+        );
+        accepted.unwrap();
+        dialed.unwrap();
+
+        let proxied = Multiaddr::empty()
+            .with(Protocol::Ip4(Ipv4Addr::new(10, 0, 0, 1)))
+            .with(Protocol::Tcp(4001));
+        assert!(matches!(
+            transport.dial(proxied).unwrap().await,
+            Err(Error::Transport(_))
+        ));
+
+        assert!(matches!(
+            transport.dial("/ip4/127.0.0.1/udp/4001/quic-v1".parse().unwrap()),
+            Err(TransportError::MultiaddrNotSupported(_))
+        ));
+    }
+
+    #[async_std::test]
+    async fn proxy_refusal() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = async {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream
+                .write_all(&[5, 0, 5, 5, 0, 1, 0, 0, 0, 0, 0, 0])
+                .await
+                .unwrap();
+            let mut request = [0; 13];
+            stream.read_exact(&mut request).await.unwrap();
+            assert_eq!(request, [5, 1, 0, 5, 1, 0, 1, 127, 0, 0, 1, 0, 80]);
+            stream
+        };
+        let client = async {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            connect(&mut stream, "127.0.0.1", 80, None).await
+        };
+
+        let err = futures::join!(server, client).1.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+    }
+
+    #[async_std::test]
+    async fn connect_with_credentials() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = async {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream
+                .write_all(&[5, 2, 1, 0, 5, 0, 0, 1, 0, 0, 0, 0, 0, 0])
+                .await
+                .unwrap();
+            let expected = [
+                &[5, 1, 2][..],
+                &[1, 4][..],
+                b"user",
+                &[4][..],
+                b"pass",
+                &[5, 1, 0, 3, 11][..],
+                b"example.com",
+                &[1, 187][..],
+            ]
+            .concat();
+            let mut request = vec![0; expected.len()];
+            stream.read_exact(&mut request).await.unwrap();
+            assert_eq!(request, expected);
+            stream
+        };
+        let client = async {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            connect(&mut stream, "example.com", 443, Some(("user", "pass"))).await
+        };
+
+        futures::join!(server, client).1.unwrap();
+    }
+}
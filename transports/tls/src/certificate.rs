@@ -105,6 +105,18 @@ pub struct P2pExtension {
     signature: Vec<u8>,
 }
 
+impl P2pExtension {
+    /// The public host key of the peer.
+    pub fn public_key(&self) -> &identity::PublicKey {
+        &self.public_key
+    }
+
+    /// The signature over the public key of the certificate, made with the private host key.
+    pub fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 #[error(transparent)]
 pub struct GenError(#[from] rcgen::RcgenError);
@@ -232,6 +244,11 @@ impl P2pCertificate<'_> {
         self.extension.public_key.to_peer_id()
     }
 
+    /// The libp2p extension of the certificate.
+    pub fn extension(&self) -> &P2pExtension {
+        &self.extension
+    }
+
     /// Verify the `signature` of the `message` signed by the private key corresponding to the public key stored
     /// in the certificate.
     pub fn verify_signature(
@@ -353,7 +370,7 @@ impl P2pCertificate<'_> {
     /// Return the signature scheme corresponding to [`AlgorithmIdentifier`]s
     /// of `subject_pki` and `signature_algorithm`
     /// according to <https://www.rfc-editor.org/rfc/rfc8446.html#section-4.2.3>.
-    fn signature_scheme(&self) -> Result<rustls::SignatureScheme, webpki::Error> {
+    pub(crate) fn signature_scheme(&self) -> Result<rustls::SignatureScheme, webpki::Error> {
         // Certificates MUST use the NamedCurve encoding for elliptic curve parameters.
         // Endpoints MUST abort the connection attempt if it is not used.
         use oid_registry::*;
@@ -31,11 +31,16 @@ mod verifier;
 
 use libp2p_identity::Keypair;
 use libp2p_identity::PeerId;
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
 use std::sync::Arc;
 
 pub use futures_rustls::TlsStream;
 pub use upgrade::Config;
+pub use upgrade::ConnectionInfo;
+pub use upgrade::Event;
 pub use upgrade::UpgradeError;
+pub use verifier::AdditionalVerifier;
 
 const P2P_ALPN: [u8; 6] = *b"libp2p";
 
@@ -46,36 +51,87 @@ pub fn make_client_config(
 ) -> Result<rustls::ClientConfig, certificate::GenError> {
     let (certificate, private_key) = certificate::generate(keypair)?;
 
+    Ok(client_config(
+        certificate,
+        private_key,
+        remote_peer_id,
+        None,
+    ))
+}
+
+/// Create a TLS server configuration for libp2p.
+pub fn make_server_config(
+    keypair: &Keypair,
+) -> Result<rustls::ServerConfig, certificate::GenError> {
+    let (certificate, private_key) = certificate::generate(keypair)?;
+
+    Ok(server_config(certificate, private_key, None, None))
+}
+
+fn client_config(
+    certificate: rustls::Certificate,
+    private_key: rustls::PrivateKey,
+    remote_peer_id: Option<PeerId>,
+    additional_verifier: Option<AdditionalVerifier>,
+) -> rustls::ClientConfig {
     let mut crypto = rustls::ClientConfig::builder()
         .with_cipher_suites(verifier::CIPHERSUITES)
         .with_safe_default_kx_groups()
         .with_protocol_versions(verifier::PROTOCOL_VERSIONS)
         .expect("Cipher suites and kx groups are configured; qed")
         .with_custom_certificate_verifier(Arc::new(
-            verifier::Libp2pCertificateVerifier::with_remote_peer_id(remote_peer_id),
+            verifier::Libp2pCertificateVerifier::with_remote_peer_id(remote_peer_id)
+                .with_additional_verifier(additional_verifier),
         ))
         .with_client_auth_cert(vec![certificate], private_key)
         .expect("Client cert key DER is valid; qed");
     crypto.alpn_protocols = vec![P2P_ALPN.to_vec()];
 
-    Ok(crypto)
+    crypto
 }
 
-/// Create a TLS server configuration for libp2p.
-pub fn make_server_config(
-    keypair: &Keypair,
-) -> Result<rustls::ServerConfig, certificate::GenError> {
-    let (certificate, private_key) = certificate::generate(keypair)?;
+fn server_config(
+    certificate: rustls::Certificate,
+    private_key: rustls::PrivateKey,
+    additional_verifier: Option<AdditionalVerifier>,
+    ca_certificate: Option<Arc<CertifiedKey>>,
+) -> rustls::ServerConfig {
+    let libp2p_certificate = Arc::new(CertifiedKey::new(
+        vec![certificate],
+        rustls::sign::any_supported_type(&private_key).expect("Server cert key DER is valid; qed"),
+    ));
 
     let mut crypto = rustls::ServerConfig::builder()
         .with_cipher_suites(verifier::CIPHERSUITES)
         .with_safe_default_kx_groups()
         .with_protocol_versions(verifier::PROTOCOL_VERSIONS)
         .expect("Cipher suites and kx groups are configured; qed")
-        .with_client_cert_verifier(Arc::new(verifier::Libp2pCertificateVerifier::new()))
-        .with_single_cert(vec![certificate], private_key)
-        .expect("Server cert key DER is valid; qed");
+        .with_client_cert_verifier(Arc::new(
+            verifier::Libp2pCertificateVerifier::new()
+                .with_additional_verifier(additional_verifier),
+        ))
+        .with_cert_resolver(Arc::new(CertResolver {
+            libp2p_certificate,
+            ca_certificate,
+        }));
     crypto.alpn_protocols = vec![P2P_ALPN.to_vec()];
 
-    Ok(crypto)
+    crypto
+}
+
+/// Selects the certificate presented by a server.
+///
+/// libp2p clients never request a server name, see [`Config::with_ca_certificate`].
+struct CertResolver {
+    libp2p_certificate: Arc<CertifiedKey>,
+    ca_certificate: Option<Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        match (&self.ca_certificate, client_hello.server_name()) {
+            (Some(ca_certificate), Some(_)) => Some(ca_certificate.clone()),
+            _ => Some(self.libp2p_certificate.clone()),
+        }
+    }
 }
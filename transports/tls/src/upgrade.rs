@@ -20,17 +20,31 @@
 
 use crate::certificate;
 use crate::certificate::P2pCertificate;
+use crate::verifier::Libp2pCertificateVerifier;
+use crate::AdditionalVerifier;
+use futures::channel::mpsc;
 use futures::future::BoxFuture;
 use futures::AsyncWrite;
 use futures::{AsyncRead, FutureExt};
 use futures_rustls::TlsStream;
-use libp2p_core::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
+use libp2p_core::{Endpoint, InboundUpgrade, OutboundUpgrade, Subscribers, UpgradeInfo};
 use libp2p_identity as identity;
 use libp2p_identity::PeerId;
-use rustls::{CommonState, ServerName};
+use rustls::client::{
+    ClientSessionMemoryCache, ClientSessionStore, Resumption, Tls12ClientSessionValue,
+    Tls13ClientSessionValue,
+};
+use rustls::server::{NoServerSessionStorage, ServerSessionMemoryCache};
+use rustls::sign::{CertifiedKey, SignError};
+use rustls::{CommonState, NamedGroup, ServerName};
 use std::net::{IpAddr, Ipv4Addr};
 use std::sync::Arc;
 
+/// The number of session tickets sent to clients after a full handshake.
+const TLS13_TICKETS: usize = 2;
+/// The number of sessions cached by clients by default, like by `rustls`.
+const DEFAULT_CLIENT_SESSIONS: usize = 256;
+
 #[derive(thiserror::Error, Debug)]
 pub enum UpgradeError {
     #[error("Failed to generate certificate")]
@@ -47,15 +61,192 @@ pub enum UpgradeError {
 pub struct Config {
     server: rustls::ServerConfig,
     client: rustls::ClientConfig,
+    certificate: rustls::Certificate,
+    private_key: rustls::PrivateKey,
+    additional_verifier: Option<AdditionalVerifier>,
+    session_cache_size: Option<usize>,
+    /// The sessions cached as client, shared by all clones of the configuration.
+    client_sessions: Option<Arc<ClientSessionMemoryCache>>,
+    remote_peer_id: Option<PeerId>,
+    ca_certificate: Option<Arc<CertifiedKey>>,
+    subscribers: Subscribers<Event>,
+}
+
+/// Event of a [`Config`], see [`Config::subscribe`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum Event {
+    /// A handshake with a remote completed.
+    HandshakeCompleted {
+        peer_id: PeerId,
+        /// Whether the local node is the client or the server of the handshake.
+        endpoint: Endpoint,
+        /// Whether a previous session with the remote was resumed, skipping the transfer and
+        /// verification of the certificates. The handshake still takes a full round trip, as no
+        /// early data is sent.
+        resumed: bool,
+        /// The negotiated parameters of the session.
+        info: ConnectionInfo,
+    },
+}
+
+/// The parameters negotiated by a TLS handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ConnectionInfo {
+    /// The cipher suite protecting the session.
+    pub cipher_suite: rustls::CipherSuite,
+    /// The version of the TLS protocol.
+    pub protocol_version: rustls::ProtocolVersion,
+    /// The algorithm the certificate of the remote is signed with.
+    pub signature_scheme: rustls::SignatureScheme,
+}
+
+impl ConnectionInfo {
+    /// Extracts the negotiated parameters of a connection, e.g. of a [`TlsStream`] via
+    /// [`TlsStream::get_ref`].
+    ///
+    /// Returns `None` if the handshake did not complete.
+    pub fn new(state: &CommonState) -> Option<Self> {
+        let certificate = extract_single_certificate(state).ok()?;
+
+        Self::with_certificate(state, &certificate)
+    }
+
+    fn with_certificate(state: &CommonState, certificate: &P2pCertificate<'_>) -> Option<Self> {
+        Some(ConnectionInfo {
+            cipher_suite: state.negotiated_cipher_suite()?.suite(),
+            protocol_version: state.protocol_version()?,
+            signature_scheme: certificate.signature_scheme().ok()?,
+        })
+    }
 }
 
 impl Config {
     pub fn new(identity: &identity::Keypair) -> Result<Self, certificate::GenError> {
+        let (certificate, private_key) = certificate::generate(identity)?;
+
         Ok(Self {
-            server: crate::make_server_config(identity)?,
-            client: crate::make_client_config(identity, None)?,
+            server: crate::server_config(certificate.clone(), private_key.clone(), None, None),
+            client: crate::client_config(certificate.clone(), private_key.clone(), None, None),
+            certificate,
+            private_key,
+            additional_verifier: None,
+            session_cache_size: None,
+            client_sessions: Some(Arc::new(ClientSessionMemoryCache::new(
+                DEFAULT_CLIENT_SESSIONS,
+            ))),
+            remote_peer_id: None,
+            ca_certificate: None,
+            subscribers: Default::default(),
         })
     }
+
+    /// Verify the certificates of remotes with the given function, in addition to the
+    /// verification required by the libp2p TLS specification.
+    ///
+    /// This allows layering policies on top of the libp2p authentication, e.g. only accepting
+    /// certain types of host keys:
+    ///
+    /// ```
+    /// # use libp2p_identity as identity;
+    /// # use std::sync::Arc;
+    /// let keypair = identity::Keypair::generate_ed25519();
+    /// let config = libp2p_tls::Config::new(&keypair)
+    ///     .unwrap()
+    ///     .with_additional_verifier(Arc::new(|_, certificate| {
+    ///         match certificate.extension().public_key().key_type() {
+    ///             identity::KeyType::Ed25519 => Ok(()),
+    ///             _ => Err(rustls::Error::General("Unsupported host key type".into())),
+    ///         }
+    ///     }));
+    /// ```
+    pub fn with_additional_verifier(mut self, verifier: AdditionalVerifier) -> Self {
+        self.additional_verifier = Some(verifier);
+        self.rebuild();
+        self
+    }
+
+    /// Cache up to `size` TLS 1.3 sessions for resumption, as client and as server.
+    ///
+    /// Resuming a session skips the transfer and verification of the certificates, as they
+    /// were verified by the resumed session. Resumption requires a connection to the same remote
+    /// to have been established before, with both nodes caching sessions. A `size` of 0 disables
+    /// resumption. By default, the defaults of `rustls` apply.
+    ///
+    /// Clients cache sessions per remote peer, as learned from the verified certificate. As the
+    /// remote is not known before the handshake otherwise, sessions are only resumed on outbound
+    /// upgrades with a known remote, see [`Config::with_remote_peer_id`].
+    pub fn with_session_resumption(mut self, size: usize) -> Self {
+        self.session_cache_size = Some(size);
+        self.rebuild();
+        self
+    }
+
+    /// Expect the given remote peer on outbound upgrades, failing the upgrade if the remote
+    /// presents the certificate of a different peer.
+    ///
+    /// Sessions are only resumed on outbound upgrades with a known remote, see
+    /// [`Config::with_session_resumption`].
+    pub fn with_remote_peer_id(mut self, peer_id: PeerId) -> Self {
+        self.remote_peer_id = Some(peer_id);
+        self
+    }
+
+    /// Present the given CA-signed certificate chain to clients requesting a server name, when
+    /// listening.
+    ///
+    /// libp2p clients never request a server name and keep being presented the libp2p
+    /// certificate. Clients validating the certificate of the server against the WebPKI, e.g.
+    /// gateways or TLS-inspecting middleboxes, request the name of the server and are presented
+    /// the CA-signed chain instead. They still have to authenticate with a libp2p certificate and
+    /// offer the `libp2p` ALPN protocol, if any, so the peer ID of the client is verified as usual.
+    pub fn with_ca_certificate(
+        mut self,
+        chain: Vec<rustls::Certificate>,
+        private_key: rustls::PrivateKey,
+    ) -> Result<Self, SignError> {
+        let key = rustls::sign::any_supported_type(&private_key)?;
+        self.ca_certificate = Some(Arc::new(CertifiedKey::new(chain, key)));
+        self.rebuild();
+        Ok(self)
+    }
+
+    /// Returns a receiver of the [`Event`]s of upgrades with this configuration or its clones,
+    /// see [`Subscribers`].
+    pub fn subscribe(&self) -> mpsc::Receiver<Event> {
+        self.subscribers.subscribe()
+    }
+
+    /// Rebuilds the `rustls` configurations after a change of the configuration.
+    fn rebuild(&mut self) {
+        self.server = crate::server_config(
+            self.certificate.clone(),
+            self.private_key.clone(),
+            self.additional_verifier.clone(),
+            self.ca_certificate.clone(),
+        );
+        self.client = crate::client_config(
+            self.certificate.clone(),
+            self.private_key.clone(),
+            None,
+            self.additional_verifier.clone(),
+        );
+
+        match self.session_cache_size {
+            None => {}
+            Some(0) => {
+                self.client_sessions = None;
+                self.server.session_storage = Arc::new(NoServerSessionStorage {});
+                self.server.send_tls13_tickets = 0;
+            }
+            Some(size) => {
+                self.client_sessions = Some(Arc::new(ClientSessionMemoryCache::new(size)));
+                self.server.session_storage = ServerSessionMemoryCache::new(size);
+                self.server.send_tls13_tickets = TLS13_TICKETS;
+            }
+        }
+    }
 }
 
 impl UpgradeInfo for Config {
@@ -77,12 +268,23 @@ where
 
     fn upgrade_inbound(self, socket: C, _: Self::Info) -> Self::Future {
         async move {
-            let stream = futures_rustls::TlsAcceptor::from(Arc::new(self.server))
+            let stream = futures_rustls::TlsAcceptor::from(Arc::new(self.server.clone()))
                 .accept(socket)
                 .await
                 .map_err(UpgradeError::ServerUpgrade)?;
 
-            let peer_id = extract_single_certificate(stream.get_ref().1)?.peer_id();
+            let (_, state) = stream.get_ref();
+            let certificate = extract_single_certificate(state)?;
+            let peer_id = certificate.peer_id();
+
+            if let Some(info) = ConnectionInfo::with_certificate(state, &certificate) {
+                self.subscribers.emit(Event::HandshakeCompleted {
+                    peer_id,
+                    endpoint: Endpoint::Listener,
+                    resumed: state.received_resumption_data().is_some(),
+                    info,
+                });
+            }
 
             Ok((peer_id, stream.into()))
         }
@@ -100,16 +302,46 @@ where
 
     fn upgrade_outbound(self, socket: C, _: Self::Info) -> Self::Future {
         async move {
+            let mut client = self.client.clone();
+
             // Spec: In order to keep this flexibility for future versions, clients that only support the version of the handshake defined in this document MUST NOT send any value in the Server Name Indication.
-            // Setting `ServerName` to unspecified will disable the use of the SNI extension.
+            client.enable_sni = false;
             let name = ServerName::IpAddress(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
 
-            let stream = futures_rustls::TlsConnector::from(Arc::new(self.client))
+            // Use a dedicated verifier, to learn the remote and whether the session was resumed.
+            let verifier = Arc::new(
+                Libp2pCertificateVerifier::with_remote_peer_id(self.remote_peer_id)
+                    .with_additional_verifier(self.additional_verifier.clone()),
+            );
+            client
+                .dangerous()
+                .set_certificate_verifier(verifier.clone());
+            client.resumption = match &self.client_sessions {
+                Some(sessions) => Resumption::store(Arc::new(PeerSessionStore {
+                    sessions: sessions.clone(),
+                    remote_peer_id: self.remote_peer_id,
+                    verifier: verifier.clone(),
+                })),
+                None => Resumption::disabled(),
+            };
+
+            let stream = futures_rustls::TlsConnector::from(Arc::new(client))
                 .connect(name, socket)
                 .await
                 .map_err(UpgradeError::ClientUpgrade)?;
 
-            let peer_id = extract_single_certificate(stream.get_ref().1)?.peer_id();
+            let (_, state) = stream.get_ref();
+            let certificate = extract_single_certificate(state)?;
+            let peer_id = certificate.peer_id();
+
+            if let Some(info) = ConnectionInfo::with_certificate(state, &certificate) {
+                self.subscribers.emit(Event::HandshakeCompleted {
+                    peer_id,
+                    endpoint: Endpoint::Dialer,
+                    resumed: !verifier.has_verified(),
+                    info,
+                });
+            }
 
             Ok((peer_id, stream.into()))
         }
@@ -117,6 +349,69 @@ where
     }
 }
 
+/// A [`ClientSessionStore`] of a single outbound upgrade, keying the sessions of the shared cache
+/// by the peer ID of the remote instead of the unused [`ServerName`].
+///
+/// Sessions are looked up for the expected remote, if any, and stored for the remote whose
+/// certificate was verified, thus also for upgrades without a known remote.
+struct PeerSessionStore {
+    sessions: Arc<ClientSessionMemoryCache>,
+    remote_peer_id: Option<PeerId>,
+    verifier: Arc<Libp2pCertificateVerifier>,
+}
+
+impl PeerSessionStore {
+    /// The key of the sessions with the expected remote.
+    fn lookup_key(&self) -> Option<ServerName> {
+        self.remote_peer_id.and_then(session_key)
+    }
+
+    /// The key sessions are stored under, i.e. that of the verified remote, or of the expected
+    /// remote if a session with it was resumed.
+    fn store_key(&self) -> Option<ServerName> {
+        self.verifier
+            .verified_peer_id()
+            .or(self.remote_peer_id)
+            .and_then(session_key)
+    }
+}
+
+fn session_key(peer_id: PeerId) -> Option<ServerName> {
+    ServerName::try_from(peer_id.to_base58().as_str()).ok()
+}
+
+impl ClientSessionStore for PeerSessionStore {
+    fn set_kx_hint(&self, _: &ServerName, group: NamedGroup) {
+        if let Some(key) = self.store_key() {
+            self.sessions.set_kx_hint(&key, group);
+        }
+    }
+
+    fn kx_hint(&self, _: &ServerName) -> Option<NamedGroup> {
+        self.sessions.kx_hint(&self.lookup_key()?)
+    }
+
+    // Only TLS 1.3 is supported.
+
+    fn set_tls12_session(&self, _: &ServerName, _: Tls12ClientSessionValue) {}
+
+    fn tls12_session(&self, _: &ServerName) -> Option<Tls12ClientSessionValue> {
+        None
+    }
+
+    fn remove_tls12_session(&self, _: &ServerName) {}
+
+    fn insert_tls13_ticket(&self, _: &ServerName, value: Tls13ClientSessionValue) {
+        if let Some(key) = self.store_key() {
+            self.sessions.insert_tls13_ticket(&key, value);
+        }
+    }
+
+    fn take_tls13_ticket(&self, _: &ServerName) -> Option<Tls13ClientSessionValue> {
+        self.sessions.take_tls13_ticket(&self.lookup_key()?)
+    }
+}
+
 fn extract_single_certificate(
     state: &CommonState,
 ) -> Result<P2pCertificate<'_>, certificate::ParseError> {
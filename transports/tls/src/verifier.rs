@@ -24,6 +24,7 @@
 //! and signatures allegedly by the given certificates.
 
 use crate::certificate;
+use crate::certificate::P2pCertificate;
 use libp2p_identity::PeerId;
 use rustls::{
     cipher_suite::{
@@ -34,7 +35,7 @@ use rustls::{
     Certificate, CertificateError, DigitallySignedStruct, DistinguishedName, SignatureScheme,
     SupportedCipherSuite, SupportedProtocolVersion,
 };
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 /// The protocol versions supported by this verifier.
 ///
@@ -53,12 +54,26 @@ pub(crate) static CIPHERSUITES: &[SupportedCipherSuite] = &[
     TLS13_AES_128_GCM_SHA256,
 ];
 
+/// Verification of the certificate of a remote, in addition to the verification required by the
+/// libp2p TLS specification.
+///
+/// It is passed the DER-encoded end-entity certificate and the parsed [`P2pCertificate`], once
+/// the certificate passed the libp2p verification. Returning an error aborts the handshake.
+pub type AdditionalVerifier = Arc<
+    dyn Fn(&Certificate, &P2pCertificate<'_>) -> Result<(), rustls::Error> + Send + Sync + 'static,
+>;
+
 /// Implementation of the `rustls` certificate verification traits for libp2p.
 ///
 /// Only TLS 1.3 is supported. TLS 1.2 should be disabled in the configuration of `rustls`.
 pub(crate) struct Libp2pCertificateVerifier {
     /// The peer ID we intend to connect to
     remote_peer_id: Option<PeerId>,
+    /// Verification to apply in addition to the libp2p one, if any.
+    additional_verifier: Option<AdditionalVerifier>,
+    /// The peer ID of the remote whose certificate has been verified, if a full handshake was
+    /// performed.
+    verified_peer_id: Mutex<Option<PeerId>>,
 }
 
 /// libp2p requires the following of X.509 server certificate chains:
@@ -71,10 +86,47 @@ impl Libp2pCertificateVerifier {
     pub(crate) fn new() -> Self {
         Self {
             remote_peer_id: None,
+            additional_verifier: None,
+            verified_peer_id: Mutex::new(None),
         }
     }
     pub(crate) fn with_remote_peer_id(remote_peer_id: Option<PeerId>) -> Self {
-        Self { remote_peer_id }
+        Self {
+            remote_peer_id,
+            additional_verifier: None,
+            verified_peer_id: Mutex::new(None),
+        }
+    }
+    pub(crate) fn with_additional_verifier(mut self, verifier: Option<AdditionalVerifier>) -> Self {
+        self.additional_verifier = verifier;
+        self
+    }
+
+    /// Whether a certificate has been verified.
+    ///
+    /// Resumed sessions skip the verification, as the certificate was verified by the session
+    /// that is resumed.
+    pub(crate) fn has_verified(&self) -> bool {
+        self.verified_peer_id().is_some()
+    }
+
+    /// The peer ID of the remote whose certificate has been verified, if any.
+    pub(crate) fn verified_peer_id(&self) -> Option<PeerId> {
+        *self.verified_peer_id.lock().expect("lock not poisoned")
+    }
+
+    fn set_verified(&self, peer_id: PeerId) {
+        *self.verified_peer_id.lock().expect("lock not poisoned") = Some(peer_id);
+    }
+
+    /// Applies the [`AdditionalVerifier`], if any, to a certificate that passed the libp2p
+    /// verification.
+    fn verify_additional(&self, end_entity: &Certificate) -> Result<(), rustls::Error> {
+        if let Some(verifier) = &self.additional_verifier {
+            verifier(end_entity, &certificate::parse(end_entity)?)?;
+        }
+
+        Ok(())
     }
 
     /// Return the list of SignatureSchemes that this verifier will handle,
@@ -124,6 +176,9 @@ impl ServerCertVerifier for Libp2pCertificateVerifier {
             }
         }
 
+        self.verify_additional(end_entity)?;
+        self.set_verified(peer_id);
+
         Ok(ServerCertVerified::assertion())
     }
 
@@ -172,7 +227,9 @@ impl ClientCertVerifier for Libp2pCertificateVerifier {
         intermediates: &[Certificate],
         _now: std::time::SystemTime,
     ) -> Result<ClientCertVerified, rustls::Error> {
-        verify_presented_certs(end_entity, intermediates)?;
+        let peer_id = verify_presented_certs(end_entity, intermediates)?;
+        self.verify_additional(end_entity)?;
+        self.set_verified(peer_id);
 
         Ok(ClientCertVerified::assertion())
     }
@@ -0,0 +1,90 @@
+use futures::future;
+use libp2p_core::transport::{ListenerId, MemoryTransport, TransportEvent};
+use libp2p_core::{InboundUpgrade, Transport};
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn presents_ca_certificate_to_clients_requesting_server_name() {
+    let ca = {
+        let mut params = rcgen::CertificateParams::new(vec![]);
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        rcgen::Certificate::from_params(params).unwrap()
+    };
+    let leaf = rcgen::Certificate::from_params(rcgen::CertificateParams::new(vec![
+        "example.com".to_string()
+    ]))
+    .unwrap();
+    let leaf_der = rustls::Certificate(leaf.serialize_der_with_signer(&ca).unwrap());
+    let leaf_key = rustls::PrivateKey(leaf.serialize_private_key_der());
+
+    let server_identity = libp2p_identity::Keypair::generate_ed25519();
+    let server_config = libp2p_tls::Config::new(&server_identity)
+        .unwrap()
+        .with_ca_certificate(vec![leaf_der.clone()], leaf_key)
+        .unwrap();
+
+    // A client validating the certificate of the server against the WebPKI, while
+    // authenticating with a libp2p certificate.
+    let client_identity = libp2p_identity::Keypair::generate_ed25519();
+    let client_config = {
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add(&rustls::Certificate(ca.serialize_der().unwrap()))
+            .unwrap();
+        let (certificate, private_key) =
+            libp2p_tls::certificate::generate(&client_identity).unwrap();
+        let mut config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_client_auth_cert(vec![certificate], private_key)
+            .unwrap();
+        config.alpn_protocols = vec![b"libp2p".to_vec()];
+        config
+    };
+
+    let (server_socket, client_socket) = connect().await;
+
+    let (server_result, client_result) = future::join(
+        server_config.upgrade_inbound(server_socket, "/tls/1.0.0"),
+        futures_rustls::TlsConnector::from(Arc::new(client_config))
+            .connect("example.com".try_into().unwrap(), client_socket),
+    )
+    .await;
+
+    let (client_peer_id, _) = server_result.unwrap();
+    assert_eq!(client_peer_id, client_identity.public().to_peer_id());
+    let client_stream = client_result.unwrap();
+    assert_eq!(
+        client_stream.get_ref().1.peer_certificates(),
+        Some(&[leaf_der][..])
+    );
+}
+
+/// Returns the listener's and the dialer's end of a memory connection.
+async fn connect() -> (
+    <MemoryTransport as Transport>::Output,
+    <MemoryTransport as Transport>::Output,
+) {
+    let mut listener = MemoryTransport::default();
+    listener
+        .listen_on(ListenerId::next(), "/memory/0".parse().unwrap())
+        .unwrap();
+    let address = match poll_fn(|cx| Pin::new(&mut listener).poll(cx)).await {
+        TransportEvent::NewAddress { listen_addr, .. } => listen_addr,
+        e => panic!("Unexpected event: {e:?}"),
+    };
+
+    let dial = MemoryTransport::default().dial(address).unwrap();
+    let accept = async {
+        match poll_fn(|cx| Pin::new(&mut listener).poll(cx)).await {
+            TransportEvent::Incoming { upgrade, .. } => upgrade.await.unwrap(),
+            e => panic!("Unexpected event: {e:?}"),
+        }
+    };
+
+    let (server_socket, client_socket) = future::join(accept, dial).await;
+
+    (server_socket, client_socket.unwrap())
+}
@@ -1,9 +1,11 @@
-use futures::{future, StreamExt};
+use futures::{future, AsyncReadExt, AsyncWriteExt, StreamExt};
 use libp2p_core::multiaddr::Protocol;
-use libp2p_core::transport::MemoryTransport;
+use libp2p_core::transport::{ListenerId, MemoryTransport, TransportEvent};
 use libp2p_core::upgrade::Version;
-use libp2p_core::Transport;
+use libp2p_core::{Endpoint, InboundUpgrade, OutboundUpgrade, Transport};
+use libp2p_identity::PeerId;
 use libp2p_swarm::{keep_alive, Swarm, SwarmBuilder, SwarmEvent};
+use std::sync::Arc;
 
 #[tokio::test]
 async fn can_establish_connection() {
@@ -55,12 +57,205 @@ async fn can_establish_connection() {
     assert_eq!(&outbound_peer_id, swarm1.local_peer_id());
 }
 
+#[tokio::test]
+async fn additional_verifier_can_reject_connection() {
+    let mut swarm1 = make_swarm();
+    let mut swarm2 = make_swarm_with(|config| {
+        config.with_additional_verifier(Arc::new(|_, _| {
+            Err(rustls::Error::General("Rejected by policy".into()))
+        }))
+    });
+
+    let listen_address = {
+        let expected_listener_id = swarm1.listen_on(Protocol::Memory(0).into()).unwrap();
+
+        loop {
+            match swarm1.next().await.unwrap() {
+                SwarmEvent::NewListenAddr {
+                    address,
+                    listener_id,
+                } if listener_id == expected_listener_id => break address,
+                _ => continue,
+            };
+        }
+    };
+    swarm2.dial(listen_address).unwrap();
+
+    let await_inbound_error = async {
+        loop {
+            match swarm1.next().await.unwrap() {
+                SwarmEvent::IncomingConnectionError { .. } => break,
+                SwarmEvent::ConnectionEstablished { .. } => panic!("Connection was established"),
+                _ => continue,
+            };
+        }
+    };
+    let await_outbound_error = async {
+        loop {
+            match swarm2.next().await.unwrap() {
+                SwarmEvent::OutgoingConnectionError { .. } => break,
+                SwarmEvent::ConnectionEstablished { .. } => panic!("Connection was established"),
+                _ => continue,
+            };
+        }
+    };
+
+    future::join(await_inbound_error, await_outbound_error).await;
+}
+
+#[tokio::test]
+async fn sessions_are_resumed_with_known_remote() {
+    let resumed = dial_twice(|config, remote| config.with_remote_peer_id(remote)).await;
+
+    assert_eq!(resumed, [false, true]);
+}
+
+#[tokio::test]
+async fn sessions_are_not_resumed_with_unknown_remote() {
+    let resumed = dial_twice(|config, _| config).await;
+
+    assert_eq!(resumed, [false, false]);
+}
+
+#[tokio::test]
+async fn sessions_with_unknown_remote_are_resumed_once_remote_is_known() {
+    let server_id = libp2p_identity::Keypair::generate_ed25519();
+    let server_config = libp2p_tls::Config::new(&server_id)
+        .unwrap()
+        .with_session_resumption(16);
+    let client_config = libp2p_tls::Config::new(&libp2p_identity::Keypair::generate_ed25519())
+        .unwrap()
+        .with_session_resumption(16);
+    let mut events = client_config.subscribe();
+
+    let remote = server_id.public().to_peer_id();
+    for config in [
+        client_config.clone(),
+        client_config.with_remote_peer_id(remote),
+    ] {
+        let (server_socket, client_socket) = memory_socket_pair().await;
+        let ((_, mut server_stream), (_, mut client_stream)) = future::try_join(
+            server_config.clone().upgrade_inbound(server_socket, ""),
+            config.upgrade_outbound(client_socket, ""),
+        )
+        .await
+        .unwrap();
+
+        // Reading from the stream processes the session tickets sent after the handshake.
+        server_stream.write_all(b"x").await.unwrap();
+        server_stream.flush().await.unwrap();
+        client_stream.read_exact(&mut [0; 1]).await.unwrap();
+    }
+
+    let mut resumed_handshakes = Vec::new();
+    for _ in 0..2 {
+        let libp2p_tls::Event::HandshakeCompleted { resumed, .. } = events.next().await.unwrap()
+        else {
+            unreachable!()
+        };
+        resumed_handshakes.push(resumed);
+    }
+    assert_eq!(resumed_handshakes, [false, true]);
+}
+
+/// Returns a listening and a dialing socket connected to each other in memory.
+async fn memory_socket_pair() -> (
+    libp2p_core::transport::memory::Channel<Vec<u8>>,
+    libp2p_core::transport::memory::Channel<Vec<u8>>,
+) {
+    let mut listener = MemoryTransport::default().boxed();
+    listener
+        .listen_on(ListenerId::next(), Protocol::Memory(0).into())
+        .unwrap();
+    let TransportEvent::NewAddress { listen_addr, .. } = listener.select_next_some().await else {
+        unreachable!()
+    };
+
+    let dial = MemoryTransport::default().dial(listen_addr).unwrap();
+    let (event, dialed) = future::join(listener.select_next_some(), dial).await;
+    let TransportEvent::Incoming { upgrade, .. } = event else {
+        unreachable!()
+    };
+
+    (upgrade.await.unwrap(), dialed.unwrap())
+}
+
+/// Dials a remote twice with session resumption enabled, returning whether the handshakes
+/// resumed a session.
+async fn dial_twice(
+    configure: impl FnOnce(libp2p_tls::Config, PeerId) -> libp2p_tls::Config,
+) -> Vec<bool> {
+    let mut swarm1 = make_swarm_with(|config| config.with_session_resumption(16));
+    let remote = *swarm1.local_peer_id();
+    let mut events = None;
+    let mut swarm2 = make_swarm_with(|config| {
+        let config = configure(config.with_session_resumption(16), remote);
+        events = Some(config.subscribe());
+        config
+    });
+    let mut events = events.unwrap();
+
+    let listen_address = {
+        let expected_listener_id = swarm1.listen_on(Protocol::Memory(0).into()).unwrap();
+
+        loop {
+            match swarm1.next().await.unwrap() {
+                SwarmEvent::NewListenAddr {
+                    address,
+                    listener_id,
+                } if listener_id == expected_listener_id => break address,
+                _ => continue,
+            };
+        }
+    };
+    tokio::spawn(async move {
+        loop {
+            swarm1.next().await;
+        }
+    });
+
+    let mut resumed_handshakes = Vec::new();
+    for _ in 0..2 {
+        swarm2.dial(listen_address.clone()).unwrap();
+        loop {
+            if let SwarmEvent::ConnectionEstablished { .. } = swarm2.next().await.unwrap() {
+                break;
+            }
+        }
+
+        let libp2p_tls::Event::HandshakeCompleted {
+            endpoint,
+            resumed,
+            info,
+            ..
+        } = events.next().await.unwrap()
+        else {
+            unreachable!()
+        };
+        assert_eq!(endpoint, Endpoint::Dialer);
+        assert_eq!(info.protocol_version, rustls::ProtocolVersion::TLSv1_3);
+        assert_eq!(
+            info.signature_scheme,
+            rustls::SignatureScheme::ECDSA_NISTP256_SHA256
+        );
+        resumed_handshakes.push(resumed);
+    }
+
+    resumed_handshakes
+}
+
 fn make_swarm() -> Swarm<keep_alive::Behaviour> {
+    make_swarm_with(|config| config)
+}
+
+fn make_swarm_with(
+    configure: impl FnOnce(libp2p_tls::Config) -> libp2p_tls::Config,
+) -> Swarm<keep_alive::Behaviour> {
     let identity = libp2p_identity::Keypair::generate_ed25519();
 
     let transport = MemoryTransport::default()
         .upgrade(Version::V1)
-        .authenticate(libp2p_tls::Config::new(&identity).unwrap())
+        .authenticate(configure(libp2p_tls::Config::new(&identity).unwrap()))
         .multiplex(libp2p_yamux::Config::default())
         .boxed();
 
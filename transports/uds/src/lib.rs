@@ -51,9 +51,14 @@ use libp2p_core::{
 };
 use log::debug;
 use std::collections::VecDeque;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
 use std::pin::Pin;
 use std::task::{Context, Poll};
-use std::{io, path::PathBuf};
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
 
 pub type Listener<T> = BoxStream<
     'static,
@@ -68,6 +73,7 @@ macro_rules! codegen {
         /// Represents the configuration for a Unix domain sockets transport capability for libp2p.
         pub struct $uds_config {
             listeners: VecDeque<(ListenerId, Listener<Self>)>,
+            permissions: Option<u32>,
         }
 
         impl $uds_config {
@@ -75,8 +81,20 @@ macro_rules! codegen {
             pub fn new() -> $uds_config {
                 $uds_config {
                     listeners: VecDeque::new(),
+                    permissions: None,
                 }
             }
+
+            /// Sets the permissions of the socket files of listeners, e.g. `0o600` to only
+            /// allow processes of the same user to connect.
+            ///
+            /// By default, the permissions are determined by the umask of the process.
+            /// Sockets with explicit permissions are bound in a private directory next to their
+            /// path first, so that they never have wider permissions than requested.
+            pub fn with_permissions(mut self, mode: u32) -> Self {
+                self.permissions = Some(mode);
+                self
+            }
         }
 
         impl Default for $uds_config {
@@ -97,8 +115,20 @@ macro_rules! codegen {
                 addr: Multiaddr,
             ) -> Result<(), TransportError<Self::Error>> {
                 if let Ok(path) = multiaddr_to_path(&addr) {
+                    let permissions = self.permissions;
                     #[allow(clippy::redundant_closure_call)]
-                    let listener = $build_listener(path)
+                    let bind = async move {
+                        let Some(mode) = permissions else {
+                            return $build_listener(path).await;
+                        };
+                        // Bind in a private directory, so that the socket is not accessible
+                        // before its permissions are set.
+                        let staging = StagingDir::new(&path)?;
+                        let listener = $build_listener(staging.socket.clone()).await?;
+                        staging.publish(&path, mode)?;
+                        Ok(listener)
+                    };
+                    let listener = bind
                         .map_err(Err)
                         .map_ok(move |listener| {
                             stream::once({
@@ -233,6 +263,48 @@ codegen!(
     tokio::net::UnixStream,
 );
 
+/// A directory next to a socket path that only the current user can access, in which the socket
+/// is bound before it is moved to its path.
+///
+/// The directory is removed on drop.
+struct StagingDir {
+    dir: PathBuf,
+    socket: PathBuf,
+}
+
+impl StagingDir {
+    fn new(path: &Path) -> io::Result<Self> {
+        let (Some(parent), Some(file_name)) = (path.parent(), path.file_name()) else {
+            return Err(io::ErrorKind::InvalidInput.into());
+        };
+        let mut name = OsString::from(".");
+        name.push(file_name);
+        name.push(format!(".{}", std::process::id()));
+        let dir = parent.join(name);
+        fs::DirBuilder::new().mode(0o700).create(&dir)?;
+
+        Ok(StagingDir {
+            socket: dir.join(file_name),
+            dir,
+        })
+    }
+
+    /// Sets the permissions of the bound socket and moves it to `path`.
+    ///
+    /// Like binding, this fails if `path` already exists.
+    fn publish(&self, path: &Path, mode: u32) -> io::Result<()> {
+        fs::set_permissions(&self.socket, fs::Permissions::from_mode(mode))?;
+        fs::hard_link(&self.socket, path)
+    }
+}
+
+impl Drop for StagingDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.socket);
+        let _ = fs::remove_dir(&self.dir);
+    }
+}
+
 /// Turns a `Multiaddr` containing a single `Unix` component into a path.
 ///
 /// Also returns an error if the path is not absolute, as we don't want to dial/listen on relative
@@ -324,6 +396,32 @@ mod tests {
         });
     }
 
+    #[test]
+    fn listener_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let socket = temp_dir.path().join("socket");
+        let addr = Multiaddr::from(Protocol::Unix(Cow::Owned(
+            socket.to_string_lossy().into_owned(),
+        )));
+
+        async_std::task::block_on(async move {
+            let mut transport = UdsConfig::new().with_permissions(0o600).boxed();
+            transport.listen_on(ListenerId::next(), addr).unwrap();
+            transport
+                .select_next_some()
+                .await
+                .into_new_address()
+                .expect("listen address");
+
+            let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+            // The private directory the socket was bound in is gone.
+            assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+        });
+    }
+
     #[test]
     #[ignore] // TODO: for the moment unix addresses fail to parse
     fn larger_addr_denied() {
@@ -0,0 +1,146 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use bytes::Bytes;
+use futures::{channel::oneshot, future::BoxFuture, FutureExt};
+use webrtc::data::data_channel::DataChannel as DetachedDataChannel;
+use webrtc::data::message::message_channel_open::ChannelType;
+
+use std::{io, sync::Arc};
+
+use crate::tokio::{connection::Reliability, error::Error};
+
+/// A message-oriented channel on top of a WebRTC data channel that isn't reliable and ordered.
+///
+/// Unlike a [`Substream`](crate::tokio::Substream), which needs reliable and ordered delivery to
+/// provide a byte stream, a `Channel` exposes the messages of the data channel as they are. Each
+/// message is either delivered as a whole or, depending on the [`Reliability`], lost. Unordered
+/// channels may deliver messages in a different order than they were sent.
+///
+/// Dropping a `Channel` closes the underlying data channel.
+pub struct Channel {
+    data_channel: Arc<DetachedDataChannel>,
+    reliability: Reliability,
+    max_message_size: usize,
+    /// Dropping this will close the oneshot and notify the receiver by emitting `Canceled`.
+    _drop_notifier: oneshot::Sender<()>,
+}
+
+impl Channel {
+    /// Returns a new `Channel` and a future closing the data channel once the `Channel` is
+    /// dropped.
+    pub(crate) fn new(
+        data_channel: Arc<DetachedDataChannel>,
+        reliability: Reliability,
+        max_message_size: usize,
+    ) -> (Self, BoxFuture<'static, io::Result<()>>) {
+        let (sender, receiver) = oneshot::channel::<()>();
+
+        let channel = Self {
+            data_channel: data_channel.clone(),
+            reliability,
+            max_message_size,
+            _drop_notifier: sender,
+        };
+        let listener = async move {
+            let _ = receiver.await;
+            data_channel.close().await?;
+
+            Ok(())
+        }
+        .boxed();
+
+        (channel, listener)
+    }
+
+    /// Returns the delivery guarantees of this channel.
+    pub fn reliability(&self) -> Reliability {
+        self.reliability
+    }
+
+    /// Sends a message.
+    ///
+    /// Messages must neither be empty nor exceed the maximum message size configured via
+    /// [`Transport::with_max_message_size`](crate::tokio::Transport::with_max_message_size).
+    pub async fn send(&self, message: &[u8]) -> Result<(), Error> {
+        if message.is_empty() || message.len() > self.max_message_size {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "message length must be between 1 and {} bytes",
+                    self.max_message_size
+                ),
+            )));
+        }
+
+        self.data_channel
+            .write(&Bytes::copy_from_slice(message))
+            .await
+            .map_err(|e| Error::WebRTC(e.into()))?;
+
+        Ok(())
+    }
+
+    /// Receives the next message, or `None` if the remote closed the channel.
+    pub async fn recv(&self) -> Result<Option<Vec<u8>>, Error> {
+        let mut buf = vec![0; self.max_message_size];
+        let n = self
+            .data_channel
+            .read(&mut buf)
+            .await
+            .map_err(|e| Error::WebRTC(e.into()))?;
+
+        if n == 0 {
+            return Ok(None);
+        }
+        buf.truncate(n);
+
+        Ok(Some(buf))
+    }
+}
+
+/// Returns the delivery guarantees of the given data channel, or `None` if it is reliable and
+/// ordered and thus backs a [`Substream`](crate::tokio::Substream).
+pub(crate) fn unreliable(data_channel: &DetachedDataChannel) -> Option<Reliability> {
+    let parameter = u16::try_from(data_channel.config.reliability_parameter).unwrap_or(u16::MAX);
+
+    let reliability = match data_channel.config.channel_type {
+        ChannelType::Reliable => return None,
+        ChannelType::ReliableUnordered => Reliability::Reliable { ordered: false },
+        ChannelType::PartialReliableRexmit => Reliability::MaxRetransmits {
+            ordered: true,
+            max_retransmits: parameter,
+        },
+        ChannelType::PartialReliableRexmitUnordered => Reliability::MaxRetransmits {
+            ordered: false,
+            max_retransmits: parameter,
+        },
+        ChannelType::PartialReliableTimed => Reliability::MaxPacketLifeTime {
+            ordered: true,
+            max_packet_life_time: parameter,
+        },
+        ChannelType::PartialReliableTimedUnordered => Reliability::MaxPacketLifeTime {
+            ordered: false,
+            max_packet_life_time: parameter,
+        },
+    };
+
+    Some(reliability)
+}
@@ -25,27 +25,122 @@ use futures::{
         oneshot::{self, Sender},
     },
     lock::Mutex as FutMutex,
-    StreamExt,
+    FutureExt, StreamExt,
     {future::BoxFuture, ready},
 };
 use libp2p_core::muxing::{StreamMuxer, StreamMuxerEvent};
 use webrtc::data::data_channel::DataChannel as DetachedDataChannel;
+use webrtc::data_channel::data_channel_init::RTCDataChannelInit;
 use webrtc::data_channel::RTCDataChannel;
 use webrtc::peer_connection::RTCPeerConnection;
 
 use std::task::Waker;
 use std::{
+    collections::VecDeque,
+    io,
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
 };
 
-use crate::tokio::{error::Error, substream, substream::Substream};
+use crate::tokio::{
+    channel::{self, Channel},
+    error::Error,
+    substream::{Substream, MAX_MSG_LEN},
+};
 
 /// Maximum number of unprocessed data channels.
 /// See [`Connection::poll_inbound`].
 const MAX_DATA_CHANNELS_IN_FLIGHT: usize = 10;
 
+/// Delivery guarantees of the data channel backing a [`Channel`].
+///
+/// Substreams are always backed by reliable and ordered data channels, as their byte stream can't
+/// tolerate lost or reordered messages. Use [`Connection::open_channel`] to exchange messages with
+/// other delivery guarantees, e.g. for latency-sensitive protocols that tolerate loss.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reliability {
+    /// Messages are retransmitted until they are acknowledged.
+    Reliable {
+        /// Whether messages are delivered in the order they were sent.
+        ordered: bool,
+    },
+    /// Messages are retransmitted at most `max_retransmits` times, which must be at least 1.
+    MaxRetransmits {
+        /// Whether messages are delivered in the order they were sent.
+        ordered: bool,
+        max_retransmits: u16,
+    },
+    /// Messages are (re)transmitted for at most `max_packet_life_time` milliseconds, which must be
+    /// at least 1.
+    MaxPacketLifeTime {
+        /// Whether messages are delivered in the order they were sent.
+        ordered: bool,
+        max_packet_life_time: u16,
+    },
+}
+
+impl Reliability {
+    /// Returns the [`RTCDataChannelInit`] requesting these delivery guarantees.
+    fn to_data_channel_init(self) -> RTCDataChannelInit {
+        match self {
+            Reliability::Reliable { ordered } => RTCDataChannelInit {
+                ordered: Some(ordered),
+                ..RTCDataChannelInit::default()
+            },
+            Reliability::MaxRetransmits {
+                ordered,
+                max_retransmits,
+            } => RTCDataChannelInit {
+                ordered: Some(ordered),
+                max_retransmits: Some(max_retransmits),
+                ..RTCDataChannelInit::default()
+            },
+            Reliability::MaxPacketLifeTime {
+                ordered,
+                max_packet_life_time,
+            } => RTCDataChannelInit {
+                ordered: Some(ordered),
+                max_packet_life_time: Some(max_packet_life_time),
+                ..RTCDataChannelInit::default()
+            },
+        }
+    }
+}
+
+/// Limits on the number of concurrently open substreams of a [`Connection`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct StreamLimits {
+    pub(crate) max_inbound: usize,
+    pub(crate) max_outbound: usize,
+}
+
+impl Default for StreamLimits {
+    fn default() -> Self {
+        Self {
+            max_inbound: usize::MAX,
+            max_outbound: usize::MAX,
+        }
+    }
+}
+
+/// Whether a substream or channel tracked by its drop listener counts against the inbound or
+/// outbound [`StreamLimits`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SubstreamKind {
+    Inbound,
+    Outbound,
+    /// An inbound substream which exceeded the limit and is being reset.
+    Rejected,
+}
+
+/// A data channel opened by the remote.
+#[allow(clippy::large_enum_variant)]
+enum Inbound {
+    Substream(Substream),
+    Channel(Channel),
+}
+
 /// A WebRTC connection, wrapping [`RTCPeerConnection`] and implementing [`StreamMuxer`] trait.
 pub struct Connection {
     /// [`RTCPeerConnection`] to the remote peer.
@@ -62,9 +157,30 @@ pub struct Connection {
     /// Future, which, once polled, will result in closing the entire connection.
     close_fut: Option<BoxFuture<'static, Result<(), Error>>>,
 
-    /// A list of futures, which, once completed, signal that a [`Substream`] has been dropped.
-    drop_listeners: FuturesUnordered<substream::DropListener>,
+    /// Inbound substreams received while polling for inbound channels.
+    inbound_substreams: VecDeque<Substream>,
+    /// Waker of a [`Connection::poll_inbound`] call waiting for an inbound substream.
+    inbound_waker: Option<Waker>,
+    /// Inbound channels received while polling for inbound substreams.
+    inbound_channels: VecDeque<Channel>,
+    /// Waker of a [`Connection::poll_inbound_channel`] call waiting for an inbound channel.
+    inbound_channel_waker: Option<Waker>,
+
+    /// A list of futures, which, once completed, signal that a [`Substream`] or [`Channel`] has
+    /// been dropped.
+    drop_listeners: FuturesUnordered<BoxFuture<'static, (SubstreamKind, io::Result<()>)>>,
     no_drop_listeners_waker: Option<Waker>,
+
+    stream_limits: StreamLimits,
+    /// Number of currently open inbound substreams.
+    num_inbound: usize,
+    /// Number of currently open outbound substreams.
+    num_outbound: usize,
+    /// Waker of a [`Connection::poll_outbound`] call waiting for an outbound substream to close.
+    outbound_slot_waker: Option<Waker>,
+
+    /// Maximum length of a message sent or received on a substream.
+    max_message_size: usize,
 }
 
 impl Unpin for Connection {}
@@ -85,8 +201,199 @@ impl Connection {
             incoming_data_channels_rx: data_channel_rx,
             outbound_fut: None,
             close_fut: None,
+            inbound_substreams: VecDeque::default(),
+            inbound_waker: None,
+            inbound_channels: VecDeque::default(),
+            inbound_channel_waker: None,
             drop_listeners: FuturesUnordered::default(),
             no_drop_listeners_waker: None,
+            stream_limits: StreamLimits::default(),
+            num_inbound: 0,
+            num_outbound: 0,
+            outbound_slot_waker: None,
+            max_message_size: MAX_MSG_LEN,
+        }
+    }
+
+    /// Limits the number of concurrently open substreams.
+    pub(crate) fn with_stream_limits(mut self, stream_limits: StreamLimits) -> Self {
+        self.stream_limits = stream_limits;
+        self
+    }
+
+    /// Sets the maximum length of a message sent or received on a substream.
+    pub(crate) fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// Opens a new outbound [`Channel`] backed by a data channel with the given delivery
+    /// guarantees.
+    ///
+    /// Messages sent on a channel that isn't [`Reliability::Reliable`] may be lost or, if
+    /// unordered, arrive out of order. Reliable and ordered delivery is provided by substreams,
+    /// which is why opening a channel with these guarantees fails. So does a limit of 0, which the
+    /// underlying WebRTC implementation takes as no limit at all.
+    ///
+    /// Returns [`Error::StreamLimitReached`] if the maximum number of outbound substreams is
+    /// already open.
+    pub async fn open_channel(&mut self, reliability: Reliability) -> Result<Channel, Error> {
+        if reliability == (Reliability::Reliable { ordered: true }) {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "reliable and ordered data channels back substreams",
+            )));
+        }
+        if let Reliability::MaxRetransmits {
+            max_retransmits: 0, ..
+        }
+        | Reliability::MaxPacketLifeTime {
+            max_packet_life_time: 0,
+            ..
+        } = reliability
+        {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a limit of 0 would open a reliable data channel",
+            )));
+        }
+        if self.num_outbound >= self.stream_limits.max_outbound {
+            return Err(Error::StreamLimitReached);
+        }
+
+        let detached =
+            new_outbound_data_channel(self.peer_conn.clone(), reliability.to_data_channel_init())
+                .await?;
+
+        log::trace!(
+            "Outbound channel {} ({reliability:?})",
+            detached.stream_identifier()
+        );
+
+        Ok(self.new_channel(detached, reliability, SubstreamKind::Outbound))
+    }
+
+    /// Polls for the next [`Channel`] opened by the remote.
+    ///
+    /// Reliable and ordered data channels, i.e. substreams, received while polling are returned by
+    /// [`StreamMuxer::poll_inbound`].
+    pub fn poll_inbound_channel(&mut self, cx: &mut Context<'_>) -> Poll<Channel> {
+        if let Some(channel) = self.inbound_channels.pop_front() {
+            return Poll::Ready(channel);
+        }
+
+        loop {
+            match self.poll_next_inbound(cx) {
+                Poll::Ready(Inbound::Channel(channel)) => return Poll::Ready(channel),
+                Poll::Ready(Inbound::Substream(substream)) => {
+                    if self.inbound_substreams.len() >= MAX_DATA_CHANNELS_IN_FLIGHT {
+                        log::debug!("Resetting incoming substream: too many pending substreams");
+                        continue;
+                    }
+
+                    self.inbound_substreams.push_back(substream);
+                    if let Some(waker) = self.inbound_waker.take() {
+                        waker.wake()
+                    }
+                }
+                Poll::Pending => {
+                    self.inbound_channel_waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+
+    /// Polls for the next data channel opened by the remote, resetting those exceeding the limit.
+    fn poll_next_inbound(&mut self, cx: &mut Context<'_>) -> Poll<Inbound> {
+        loop {
+            match ready!(self.incoming_data_channels_rx.poll_next_unpin(cx)) {
+                Some(detached) if self.num_inbound >= self.stream_limits.max_inbound => {
+                    log::debug!(
+                        "Resetting incoming substream {}: maximum number of inbound substreams reached",
+                        detached.stream_identifier()
+                    );
+
+                    // Dropping the substream or channel right away makes its drop listener reset
+                    // or close it.
+                    match channel::unreliable(&detached) {
+                        Some(reliability) => {
+                            drop(self.new_channel(detached, reliability, SubstreamKind::Rejected))
+                        }
+                        None => drop(self.new_substream(detached, SubstreamKind::Rejected)),
+                    }
+                }
+                Some(detached) => match channel::unreliable(&detached) {
+                    Some(reliability) => {
+                        log::trace!(
+                            "Incoming channel {} ({reliability:?})",
+                            detached.stream_identifier()
+                        );
+
+                        return Poll::Ready(Inbound::Channel(self.new_channel(
+                            detached,
+                            reliability,
+                            SubstreamKind::Inbound,
+                        )));
+                    }
+                    None => {
+                        log::trace!("Incoming substream {}", detached.stream_identifier());
+
+                        return Poll::Ready(Inbound::Substream(
+                            self.new_substream(detached, SubstreamKind::Inbound),
+                        ));
+                    }
+                },
+                None => {
+                    debug_assert!(
+                        false,
+                        "Sender-end of channel should be owned by `RTCPeerConnection`"
+                    );
+
+                    return Poll::Pending; // Return `Pending` without registering a waker: If the channel is closed, we don't need to be called anymore.
+                }
+            }
+        }
+    }
+
+    /// Wraps the given data channel in a [`Substream`] and keeps track of it being dropped.
+    fn new_substream(
+        &mut self,
+        detached: Arc<DetachedDataChannel>,
+        kind: SubstreamKind,
+    ) -> Substream {
+        let (substream, drop_listener) = Substream::new(detached, self.max_message_size);
+        self.track(kind, drop_listener.boxed());
+
+        substream
+    }
+
+    /// Wraps the given data channel in a [`Channel`] and keeps track of it being dropped.
+    fn new_channel(
+        &mut self,
+        detached: Arc<DetachedDataChannel>,
+        reliability: Reliability,
+        kind: SubstreamKind,
+    ) -> Channel {
+        let (channel, drop_listener) = Channel::new(detached, reliability, self.max_message_size);
+        self.track(kind, drop_listener);
+
+        channel
+    }
+
+    /// Counts a substream or channel against the [`StreamLimits`] until its drop listener
+    /// completes.
+    fn track(&mut self, kind: SubstreamKind, drop_listener: BoxFuture<'static, io::Result<()>>) {
+        match kind {
+            SubstreamKind::Inbound => self.num_inbound += 1,
+            SubstreamKind::Outbound => self.num_outbound += 1,
+            SubstreamKind::Rejected => {}
+        }
+
+        self.drop_listeners
+            .push(drop_listener.map(move |result| (kind, result)).boxed());
+        if let Some(waker) = self.no_drop_listeners_waker.take() {
+            waker.wake()
         }
     }
 
@@ -154,25 +461,31 @@ impl StreamMuxer for Connection {
         mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Result<Self::Substream, Self::Error>> {
-        match ready!(self.incoming_data_channels_rx.poll_next_unpin(cx)) {
-            Some(detached) => {
-                log::trace!("Incoming substream {}", detached.stream_identifier());
-
-                let (substream, drop_listener) = Substream::new(detached);
-                self.drop_listeners.push(drop_listener);
-                if let Some(waker) = self.no_drop_listeners_waker.take() {
-                    waker.wake()
-                }
+        if let Some(substream) = self.inbound_substreams.pop_front() {
+            return Poll::Ready(Ok(substream));
+        }
 
-                Poll::Ready(Ok(substream))
-            }
-            None => {
-                debug_assert!(
-                    false,
-                    "Sender-end of channel should be owned by `RTCPeerConnection`"
-                );
+        loop {
+            match self.poll_next_inbound(cx) {
+                Poll::Ready(Inbound::Substream(substream)) => return Poll::Ready(Ok(substream)),
+                Poll::Ready(Inbound::Channel(channel)) => {
+                    if self.inbound_channels.len() >= MAX_DATA_CHANNELS_IN_FLIGHT {
+                        log::debug!(
+                            "Closing incoming channel: too many pending channels ({:?})",
+                            channel.reliability()
+                        );
+                        continue;
+                    }
 
-                Poll::Pending // Return `Pending` without registering a waker: If the channel is closed, we don't need to be called anymore.
+                    self.inbound_channels.push_back(channel);
+                    if let Some(waker) = self.inbound_channel_waker.take() {
+                        waker.wake()
+                    }
+                }
+                Poll::Pending => {
+                    self.inbound_waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
             }
         }
     }
@@ -183,9 +496,21 @@ impl StreamMuxer for Connection {
     ) -> Poll<Result<StreamMuxerEvent, Self::Error>> {
         loop {
             match ready!(self.drop_listeners.poll_next_unpin(cx)) {
-                Some(Ok(())) => {}
-                Some(Err(e)) => {
-                    log::debug!("a DropListener failed: {e}")
+                Some((kind, result)) => {
+                    match kind {
+                        SubstreamKind::Inbound => self.num_inbound -= 1,
+                        SubstreamKind::Outbound => {
+                            self.num_outbound -= 1;
+                            if let Some(waker) = self.outbound_slot_waker.take() {
+                                waker.wake();
+                            }
+                        }
+                        SubstreamKind::Rejected => {}
+                    }
+
+                    if let Err(e) = result {
+                        log::debug!("a DropListener failed: {e}")
+                    }
                 }
                 None => {
                     self.no_drop_listeners_waker = Some(cx.waker().clone());
@@ -199,28 +524,20 @@ impl StreamMuxer for Connection {
         mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Result<Self::Substream, Self::Error>> {
-        let peer_conn = self.peer_conn.clone();
-        let fut = self.outbound_fut.get_or_insert(Box::pin(async move {
-            let peer_conn = peer_conn.lock().await;
-
-            let data_channel = peer_conn.create_data_channel("", None).await?;
-
-            // No need to hold the lock during the DTLS handshake.
-            drop(peer_conn);
-
-            log::trace!("Opening data channel {}", data_channel.id());
-
-            let (tx, rx) = oneshot::channel::<Arc<DetachedDataChannel>>();
-
-            // Wait until the data channel is opened and detach it.
-            register_data_channel_open_handler(data_channel, tx).await;
+        if self.outbound_fut.is_none() && self.num_outbound >= self.stream_limits.max_outbound {
+            // Unlike `open_channel`, wait for a substream to be closed instead of failing, as
+            // errors are fatal to the entire connection.
+            self.outbound_slot_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
 
-            // Wait until data channel is opened and ready to use
-            match rx.await {
-                Ok(detached) => Ok(detached),
-                Err(e) => Err(Error::Internal(e.to_string())),
-            }
-        }));
+        let peer_conn = self.peer_conn.clone();
+        let fut = self
+            .outbound_fut
+            .get_or_insert(Box::pin(new_outbound_data_channel(
+                peer_conn,
+                RTCDataChannelInit::default(),
+            )));
 
         match ready!(fut.as_mut().poll(cx)) {
             Ok(detached) => {
@@ -228,13 +545,7 @@ impl StreamMuxer for Connection {
 
                 log::trace!("Outbound substream {}", detached.stream_identifier());
 
-                let (substream, drop_listener) = Substream::new(detached);
-                self.drop_listeners.push(drop_listener);
-                if let Some(waker) = self.no_drop_listeners_waker.take() {
-                    waker.wake()
-                }
-
-                Poll::Ready(Ok(substream))
+                Poll::Ready(Ok(self.new_substream(detached, SubstreamKind::Outbound)))
             }
             Err(e) => {
                 self.outbound_fut = None;
@@ -268,6 +579,32 @@ impl StreamMuxer for Connection {
     }
 }
 
+/// Creates a new data channel and waits until it is opened.
+async fn new_outbound_data_channel(
+    peer_conn: Arc<FutMutex<RTCPeerConnection>>,
+    init: RTCDataChannelInit,
+) -> Result<Arc<DetachedDataChannel>, Error> {
+    let peer_conn = peer_conn.lock().await;
+
+    let data_channel = peer_conn.create_data_channel("", Some(init)).await?;
+
+    // No need to hold the lock during the DTLS handshake.
+    drop(peer_conn);
+
+    log::trace!("Opening data channel {}", data_channel.id());
+
+    let (tx, rx) = oneshot::channel::<Arc<DetachedDataChannel>>();
+
+    // Wait until the data channel is opened and detach it.
+    register_data_channel_open_handler(data_channel, tx).await;
+
+    // Wait until data channel is opened and ready to use
+    match rx.await {
+        Ok(detached) => Ok(detached),
+        Err(e) => Err(Error::Internal(e.to_string())),
+    }
+}
+
 pub(crate) async fn register_data_channel_open_handler(
     data_channel: Arc<RTCDataChannel>,
     data_channel_tx: Sender<Arc<DetachedDataChannel>>,
@@ -35,9 +35,18 @@ pub enum Error {
     #[error("invalid peer ID (expected {expected}, got {got})")]
     InvalidPeerID { expected: PeerId, got: PeerId },
 
+    #[error("maximum number of concurrent outbound streams reached")]
+    StreamLimitReached,
+
     #[error("no active listeners, can not dial without a previous listen")]
     NoListeners,
 
+    #[error("mDNS error")]
+    Mdns(#[from] webrtc::mdns::Error),
+
+    #[error("TURN error")]
+    Turn(#[from] webrtc::turn::Error),
+
     #[error("UDP mux error: {0}")]
     UDPMux(std::io::Error),
 
@@ -0,0 +1,147 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! `.local` host names (RFC 6762) in place of private IP addresses.
+//!
+//! Listeners advertise `/dns4/<random>.local/...` instead of the private IPv4 addresses of the
+//! host, and answer mDNS queries for that name with the address of the interface the query
+//! arrived on. Dialers resolve such names through mDNS before connecting. This allows two peers
+//! on the same LAN to connect without a STUN server and without exposing private IPs.
+
+use futures::future::Either;
+use futures_timer::Delay;
+use webrtc::mdns::{config::Config, conn::DnsConn};
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use crate::tokio::error::Error;
+
+/// The mDNS port.
+const MDNS_PORT: u16 = 5353;
+
+/// How long to wait for an answer to a query.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Returns whether `name` is to be resolved through mDNS.
+pub(crate) fn is_local_name(name: &str) -> bool {
+    name.trim_end_matches('.').ends_with(".local")
+}
+
+/// Returns whether `ip` is a private address that should be advertised through mDNS instead.
+pub(crate) fn is_private(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_private() || ip.is_link_local(),
+        // Answers to queries are IPv4 only.
+        IpAddr::V6(_) => false,
+    }
+}
+
+/// Generates a random `.local` host name.
+pub(crate) fn random_host_name() -> String {
+    format!("{:032x}.local", rand::random::<u128>())
+}
+
+/// An mDNS responder for our host name, also used to resolve the host names of remotes.
+pub(crate) struct Mdns {
+    conn: DnsConn,
+}
+
+impl Mdns {
+    /// Starts answering queries for `host_name`.
+    ///
+    /// Like `libp2p-mdns`, the responder binds the mDNS port with `SO_REUSEADDR` and, on Unix,
+    /// `SO_REUSEPORT`, sharing it with other responders on the host such as avahi. The latter is
+    /// set through the `reuse_port` feature of `webrtc-mdns`.
+    ///
+    /// Must be called from within a tokio runtime.
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn new(host_name: &str) -> Result<Self, Error> {
+        let conn = DnsConn::server(
+            SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), MDNS_PORT),
+            Config {
+                local_names: vec![host_name.to_owned()],
+                ..Config::default()
+            },
+        )?;
+
+        Ok(Self { conn })
+    }
+
+    /// Resolves the given `.local` host name.
+    pub(crate) async fn resolve(&self, host_name: &str) -> Result<IpAddr, Error> {
+        // Dropping the sender would abort the query right away.
+        let (_close_query, close_query_signal) = tokio::sync::mpsc::channel(1);
+
+        let query = Box::pin(self.conn.query(host_name, close_query_signal));
+        match futures::future::select(query, Delay::new(QUERY_TIMEOUT)).await {
+            Either::Left((Ok((_, addr)), _)) => {
+                log::debug!("resolved {host_name} to {}", addr.ip());
+
+                Ok(addr.ip())
+            }
+            Either::Left((Err(e), _)) => Err(e.into()),
+            Either::Right(((), _)) => Err(Error::Io(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("no mDNS answer for {host_name}"),
+            ))),
+        }
+    }
+}
+
+impl Drop for Mdns {
+    fn drop(&mut self) {
+        // Stops the responder task. Sending never blocks, as the command channel is only ever
+        // used once.
+        let _ = futures::FutureExt::now_or_never(self.conn.close());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_names() {
+        assert!(is_local_name("a1b2.local"));
+        assert!(is_local_name("a1b2.local."));
+        assert!(is_local_name(&random_host_name()));
+        assert!(!is_local_name("example.com"));
+        assert!(!is_local_name("local"));
+    }
+
+    #[tokio::test]
+    async fn responders_share_the_port() {
+        let _first = Mdns::new(&random_host_name()).unwrap();
+        let _second = Mdns::new(&random_host_name()).unwrap();
+    }
+
+    #[test]
+    fn private_addresses() {
+        assert!(is_private(&Ipv4Addr::new(192, 168, 1, 2).into()));
+        assert!(is_private(&Ipv4Addr::new(10, 0, 0, 1).into()));
+        assert!(is_private(&Ipv4Addr::new(169, 254, 0, 1).into()));
+        assert!(!is_private(&Ipv4Addr::LOCALHOST.into()));
+        assert!(!is_private(&Ipv4Addr::new(1, 2, 3, 4).into()));
+    }
+}
@@ -19,18 +19,24 @@
 // DEALINGS IN THE SOFTWARE.
 
 pub mod certificate;
+mod channel;
 mod connection;
 mod error;
 mod fingerprint;
+mod mdns;
 mod req_res_chan;
 mod sdp;
 mod substream;
 mod transport;
+mod turn;
 mod udp_mux;
 mod upgrade;
 
 pub use certificate::Certificate;
-pub use connection::Connection;
+pub use channel::Channel;
+pub use connection::{Connection, Reliability};
 pub use error::Error;
 pub use fingerprint::Fingerprint;
+pub use substream::Substream;
 pub use transport::Transport;
+pub use turn::TurnServer;
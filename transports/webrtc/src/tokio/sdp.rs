@@ -27,16 +27,20 @@ use std::net::{IpAddr, SocketAddr};
 use crate::tokio::fingerprint::Fingerprint;
 
 /// Creates the SDP answer used by the client.
+///
+/// The server is expected to present a certificate matching any of the given fingerprints.
 pub(crate) fn answer(
     addr: SocketAddr,
-    server_fingerprint: &Fingerprint,
+    server_fingerprints: &[Fingerprint],
     client_ufrag: &str,
+    max_message_size: usize,
 ) -> RTCSessionDescription {
     RTCSessionDescription::answer(render_description(
         SERVER_SESSION_DESCRIPTION,
         addr,
-        server_fingerprint,
+        server_fingerprints,
         client_ufrag,
+        max_message_size,
     ))
     .unwrap()
 }
@@ -44,12 +48,17 @@ pub(crate) fn answer(
 /// Creates the SDP offer used by the server.
 ///
 /// Certificate verification is disabled which is why we hardcode a dummy fingerprint here.
-pub(crate) fn offer(addr: SocketAddr, client_ufrag: &str) -> RTCSessionDescription {
+pub(crate) fn offer(
+    addr: SocketAddr,
+    client_ufrag: &str,
+    max_message_size: usize,
+) -> RTCSessionDescription {
     RTCSessionDescription::offer(render_description(
         CLIENT_SESSION_DESCRIPTION,
         addr,
-        &Fingerprint::FF,
+        &[Fingerprint::FF],
         client_ufrag,
+        max_message_size,
     ))
     .unwrap()
 }
@@ -111,6 +120,7 @@ pub(crate) fn offer(addr: SocketAddr, client_ufrag: &str) -> RTCSessionDescripti
 //
 //     Fingerprint of the certificate that the remote will use during the TLS
 //     handshake. (RFC8122)
+//     May be repeated, in which case the remote may use any of the certificates.
 //
 // a=setup:actpass
 //
@@ -137,10 +147,10 @@ a=mid:0
 a=ice-options:ice2
 a=ice-ufrag:{ufrag}
 a=ice-pwd:{pwd}
-a=fingerprint:{fingerprint_algorithm} {fingerprint_value}
-a=setup:actpass
+{{ for fingerprint in fingerprints }}a=fingerprint:{fingerprint.algorithm} {fingerprint.value}
+{{ endfor }}a=setup:actpass
 a=sctp-port:5000
-a=max-message-size:16384
+a=max-message-size:{max_message_size}
 ";
 
 // See [`CLIENT_SESSION_DESCRIPTION`].
@@ -193,11 +203,11 @@ a=mid:0
 a=ice-options:ice2
 a=ice-ufrag:{ufrag}
 a=ice-pwd:{pwd}
-a=fingerprint:{fingerprint_algorithm} {fingerprint_value}
-
+{{ for fingerprint in fingerprints }}a=fingerprint:{fingerprint.algorithm} {fingerprint.value}
+{{ endfor }}
 a=setup:passive
 a=sctp-port:5000
-a=max-message-size:16384
+a=max-message-size:{max_message_size}
 a=candidate:1 1 UDP 1 {target_ip} {target_port} typ host
 a=end-of-candidates
 ";
@@ -216,18 +226,33 @@ struct DescriptionContext {
     pub(crate) ip_version: IpVersion,
     pub(crate) target_ip: IpAddr,
     pub(crate) target_port: u16,
-    pub(crate) fingerprint_algorithm: String,
-    pub(crate) fingerprint_value: String,
+    pub(crate) fingerprints: Vec<FingerprintContext>,
     pub(crate) ufrag: String,
     pub(crate) pwd: String,
+    pub(crate) max_message_size: usize,
+}
+
+/// Returns the value of the `a=max-message-size` attribute of an SDP description, if any.
+pub(crate) fn max_message_size(sdp: &str) -> Option<usize> {
+    sdp.lines()
+        .find_map(|line| line.trim_end().strip_prefix("a=max-message-size:"))
+        .and_then(|value| value.parse().ok())
+}
+
+/// A certificate fingerprint in the format expected by the `a=fingerprint` attribute.
+#[derive(Serialize)]
+struct FingerprintContext {
+    pub(crate) algorithm: String,
+    pub(crate) value: String,
 }
 
 /// Renders a [`TinyTemplate`] description using the provided arguments.
 fn render_description(
     description: &str,
     addr: SocketAddr,
-    fingerprint: &Fingerprint,
+    fingerprints: &[Fingerprint],
     ufrag: &str,
+    max_message_size: usize,
 ) -> String {
     let mut tt = TinyTemplate::new();
     tt.add_template("description", description).unwrap();
@@ -242,11 +267,48 @@ fn render_description(
         },
         target_ip: addr.ip(),
         target_port: addr.port(),
-        fingerprint_algorithm: fingerprint.algorithm(),
-        fingerprint_value: fingerprint.to_sdp_format(),
+        fingerprints: fingerprints
+            .iter()
+            .map(|fingerprint| FingerprintContext {
+                algorithm: fingerprint.algorithm(),
+                value: fingerprint.to_sdp_format(),
+            })
+            .collect(),
         // NOTE: ufrag is equal to pwd.
         ufrag: ufrag.to_owned(),
         pwd: ufrag.to_owned(),
+        max_message_size,
     };
     tt.render("description", &context).unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn announces_max_message_size() {
+        let addr = "127.0.0.1:1234".parse().unwrap();
+
+        let offer = offer(addr, "ufrag", 65536);
+        assert!(offer.sdp.contains("a=max-message-size:65536\n"));
+
+        let answer = answer(addr, &[Fingerprint::FF], "ufrag", 4096);
+        assert!(answer.sdp.contains("a=max-message-size:4096\n"));
+    }
+
+    #[test]
+    fn parses_max_message_size() {
+        let addr = "127.0.0.1:1234".parse().unwrap();
+
+        assert_eq!(
+            max_message_size(&offer(addr, "ufrag", 65536).sdp),
+            Some(65536)
+        );
+        assert_eq!(
+            max_message_size("v=0\r\na=max-message-size:4096\r\n"),
+            Some(4096)
+        );
+        assert_eq!(max_message_size("v=0\r\na=sctp-port:5000\r\n"), None);
+    }
+}
@@ -18,11 +18,9 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use asynchronous_codec::Framed;
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use futures::{channel::oneshot, prelude::*, ready};
-use tokio_util::compat::Compat;
-use webrtc::data::data_channel::{DataChannel, PollDataChannel};
+use webrtc::data::data_channel::DataChannel;
 
 use std::{
     io,
@@ -31,29 +29,34 @@ use std::{
     task::{Context, Poll},
 };
 
-use crate::proto::{Flag, Message};
+use crate::proto::Flag;
 use crate::tokio::{
+    substream::codec::Frame,
     substream::drop_listener::GracefullyClosed,
     substream::framed_dc::FramedDc,
     substream::state::{Closing, State},
 };
 
+mod codec;
 mod drop_listener;
 mod framed_dc;
 mod state;
 
-/// Maximum length of a message.
+/// Default maximum length of a message, including its length prefix.
 ///
 /// "As long as message interleaving is not supported, the sender SHOULD limit the maximum message
 /// size to 16 KB to avoid monopolization."
 /// Source: <https://www.rfc-editor.org/rfc/rfc8831#name-transferring-user-data-on-a>
-const MAX_MSG_LEN: usize = 16384; // 16kiB
-/// Length of varint, in bytes.
-const VARINT_LEN: usize = 2;
-/// Overhead of the protobuf encoding, in bytes.
-const PROTO_OVERHEAD: usize = 5;
-/// Maximum length of data, in bytes.
-const MAX_DATA_LEN: usize = MAX_MSG_LEN - VARINT_LEN - PROTO_OVERHEAD;
+pub(crate) const MAX_MSG_LEN: usize = 16384; // 16kiB
+
+/// Largest message the underlying SCTP association accepts for sending.
+pub(crate) const MAX_SCTP_MSG_LEN: usize = 65536; // 64kiB
+
+/// Returns whether messages of at most `max_msg_len` bytes can carry data next to their framing
+/// and still be sent over SCTP.
+pub(crate) fn is_valid_max_msg_len(max_msg_len: usize) -> bool {
+    codec::max_data_len(max_msg_len) > 0 && max_msg_len <= MAX_SCTP_MSG_LEN
+}
 
 pub(crate) use drop_listener::DropListener;
 /// A substream on top of a WebRTC data channel.
@@ -64,6 +67,10 @@ pub struct Substream {
     io: FramedDc,
     state: State,
     read_buffer: Bytes,
+    /// Small writes are coalesced in here until a message of `max_data_len` bytes can be sent or
+    /// the substream is flushed.
+    write_buffer: BytesMut,
+    max_data_len: usize,
     /// Dropping this will close the oneshot and notify the receiver by emitting `Canceled`.
     drop_notifier: Option<oneshot::Sender<GracefullyClosed>>,
 }
@@ -71,20 +78,42 @@ pub struct Substream {
 impl Substream {
     /// Returns a new `Substream` and a listener, which will notify the receiver when/if the substream
     /// is dropped.
-    pub(crate) fn new(data_channel: Arc<DataChannel>) -> (Self, DropListener) {
+    ///
+    /// `max_msg_len` is the maximum length of an encoded message, sent or received.
+    pub(crate) fn new(data_channel: Arc<DataChannel>, max_msg_len: usize) -> (Self, DropListener) {
         let (sender, receiver) = oneshot::channel();
+        let max_data_len = codec::max_data_len(max_msg_len);
 
         let substream = Self {
-            io: framed_dc::new(data_channel.clone()),
+            io: framed_dc::new(data_channel.clone(), max_msg_len),
             state: State::Open,
             read_buffer: Bytes::default(),
+            write_buffer: BytesMut::with_capacity(max_data_len),
+            max_data_len,
             drop_notifier: Some(sender),
         };
-        let listener = DropListener::new(framed_dc::new(data_channel), receiver);
+        let listener = DropListener::new(framed_dc::new(data_channel, max_msg_len), receiver);
 
         (substream, listener)
     }
 
+    /// Hands the coalesced writes to the underlying data channel as a single message.
+    fn poll_send_write_buffer(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.write_buffer.is_empty() {
+            return Poll::Ready(Ok(()));
+        }
+
+        ready!(self.io.poll_ready_unpin(cx))?;
+
+        let message = self.write_buffer.split().freeze();
+        self.io.start_send_unpin(Frame {
+            flag: None,
+            message: Some(message),
+        })?;
+
+        Poll::Ready(Ok(()))
+    }
+
     /// Gracefully closes the "read-half" of the substream.
     pub fn poll_close_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         loop {
@@ -92,10 +121,7 @@ impl Substream {
                 Some(Closing::Requested) => {
                     ready!(self.io.poll_ready_unpin(cx))?;
 
-                    self.io.start_send_unpin(Message {
-                        flag: Some(Flag::STOP_SENDING),
-                        message: None,
-                    })?;
+                    self.io.start_send_unpin(Frame::flag(Flag::STOP_SENDING))?;
                     self.state.close_read_message_sent();
 
                     continue;
@@ -138,14 +164,14 @@ impl AsyncRead for Substream {
             } = &mut *self;
 
             match ready!(io_poll_next(io, cx))? {
-                Some((flag, message)) => {
+                Some(Frame { flag, message }) => {
                     if let Some(flag) = flag {
                         state.handle_inbound_flag(flag, read_buffer);
                     }
 
                     debug_assert!(read_buffer.is_empty());
                     if let Some(message) = message {
-                        *read_buffer = message.into();
+                        *read_buffer = message;
                     }
                 }
                 None => {
@@ -175,45 +201,49 @@ impl AsyncWrite for Substream {
             } = &mut *self;
 
             match io_poll_next(io, cx)? {
-                Poll::Ready(Some((Some(flag), message))) => {
+                Poll::Ready(Some(Frame {
+                    flag: Some(flag),
+                    message,
+                })) => {
                     // Read side is closed. Discard any incoming messages.
                     drop(message);
                     // But still handle flags, e.g. a `Flag::StopSending`.
                     state.handle_inbound_flag(flag, read_buffer)
                 }
-                Poll::Ready(Some((None, message))) => drop(message),
+                Poll::Ready(Some(Frame {
+                    flag: None,
+                    message,
+                })) => drop(message),
                 Poll::Ready(None) | Poll::Pending => break,
             }
         }
 
         self.state.write_barrier()?;
 
-        ready!(self.io.poll_ready_unpin(cx))?;
-
-        let n = usize::min(buf.len(), MAX_DATA_LEN);
+        if self.write_buffer.len() >= self.max_data_len {
+            ready!(self.poll_send_write_buffer(cx))?;
+        }
 
-        Pin::new(&mut self.io).start_send(Message {
-            flag: None,
-            message: Some(buf[0..n].into()),
-        })?;
+        let n = usize::min(buf.len(), self.max_data_len - self.write_buffer.len());
+        self.write_buffer.extend_from_slice(&buf[..n]);
 
         Poll::Ready(Ok(n))
     }
 
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        self.io.poll_flush_unpin(cx).map_err(Into::into)
+        ready!(self.poll_send_write_buffer(cx))?;
+
+        self.io.poll_flush_unpin(cx)
     }
 
     fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         loop {
             match self.state.close_write_barrier()? {
                 Some(Closing::Requested) => {
+                    ready!(self.poll_send_write_buffer(cx))?;
                     ready!(self.io.poll_ready_unpin(cx))?;
 
-                    self.io.start_send_unpin(Message {
-                        flag: Some(Flag::FIN),
-                        message: None,
-                    })?;
+                    self.io.start_send_unpin(Frame::flag(Flag::FIN))?;
                     self.state.close_write_message_sent();
 
                     continue;
@@ -236,35 +266,25 @@ impl AsyncWrite for Substream {
     }
 }
 
-fn io_poll_next(
-    io: &mut Framed<Compat<PollDataChannel>, quick_protobuf_codec::Codec<Message>>,
-    cx: &mut Context<'_>,
-) -> Poll<io::Result<Option<(Option<Flag>, Option<Vec<u8>>)>>> {
-    match ready!(io.poll_next_unpin(cx))
-        .transpose()
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
-    {
-        Some(Message { flag, message }) => Poll::Ready(Ok(Some((flag, message)))),
-        None => Poll::Ready(Ok(None)),
-    }
+fn io_poll_next(io: &mut FramedDc, cx: &mut Context<'_>) -> Poll<io::Result<Option<Frame>>> {
+    io.poll_next_unpin(cx).map(Option::transpose)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use asynchronous_codec::Encoder;
-    use bytes::BytesMut;
     use quick_protobuf::{MessageWrite, Writer};
     use unsigned_varint::codec::UviBytes;
 
     #[test]
     fn max_data_len() {
         // Largest possible message.
-        let message = [0; MAX_DATA_LEN];
+        let message = vec![0; codec::max_data_len(MAX_MSG_LEN)];
 
         let protobuf = crate::proto::Message {
             flag: Some(crate::proto::Flag::FIN),
-            message: Some(message.to_vec()),
+            message: Some(message),
         };
 
         let mut encoded_msg = Vec::new();
@@ -272,7 +292,6 @@ mod tests {
         protobuf
             .write_message(&mut writer)
             .expect("Encoding to succeed");
-        assert_eq!(encoded_msg.len(), message.len() + PROTO_OVERHEAD);
 
         let mut uvi = UviBytes::default();
         let mut dst = BytesMut::new();
@@ -281,7 +300,5 @@ mod tests {
         // Ensure the varint prefixed and protobuf encoded largest message is no longer than the
         // maximum limit specified in the libp2p WebRTC specification.
         assert_eq!(dst.len(), MAX_MSG_LEN);
-
-        assert_eq!(dst.len() - encoded_msg.len(), VARINT_LEN);
     }
 }
@@ -0,0 +1,287 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! A codec for the varint-prefixed protobuf `Message`s exchanged on data channels.
+//!
+//! Unlike [`quick_protobuf_codec::Codec`], payloads are never copied into intermediate `Vec`s:
+//! they are encoded straight from [`Bytes`] into the send buffer and decoded as slices of the
+//! receive buffer.
+
+use asynchronous_codec::{Decoder, Encoder};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use unsigned_varint::{decode, encode};
+
+use std::io;
+
+use crate::proto::Flag;
+
+/// Tag of the `flag` field (field number 1, wire type varint).
+const FLAG_TAG: u8 = 0x08;
+/// Tag of the `message` field (field number 2, wire type length-delimited).
+const MESSAGE_TAG: u8 = 0x12;
+
+/// A message exchanged on a data channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Frame {
+    pub(crate) flag: Option<Flag>,
+    pub(crate) message: Option<Bytes>,
+}
+
+impl Frame {
+    /// A frame only carrying the given flag.
+    pub(crate) fn flag(flag: Flag) -> Self {
+        Self {
+            flag: Some(flag),
+            message: None,
+        }
+    }
+}
+
+/// Returns the length of a message once encoded, including its length prefix.
+fn encoded_len(flag: Option<Flag>, message_len: Option<usize>) -> usize {
+    let body_len = body_len(flag, message_len);
+
+    varint_len(body_len as u64) + body_len
+}
+
+fn body_len(flag: Option<Flag>, message_len: Option<usize>) -> usize {
+    flag.map_or(0, |flag| 1 + varint_len(flag as u64))
+        + message_len.map_or(0, |len| 1 + varint_len(len as u64) + len)
+}
+
+fn varint_len(value: u64) -> usize {
+    encode::u64(value, &mut encode::u64_buffer()).len()
+}
+
+/// Returns the maximum length of data a single message can carry without its encoding exceeding
+/// `max_msg_len` bytes.
+pub(crate) fn max_data_len(max_msg_len: usize) -> usize {
+    (0..max_msg_len)
+        .rev()
+        .find(|&len| encoded_len(Some(Flag::RESET), Some(len)) <= max_msg_len)
+        .unwrap_or(0)
+}
+
+pub(crate) struct Codec {
+    /// Maximum length of an encoded message, including its length prefix.
+    max_msg_len: usize,
+}
+
+impl Codec {
+    pub(crate) fn new(max_msg_len: usize) -> Self {
+        Self { max_msg_len }
+    }
+}
+
+impl Encoder for Codec {
+    type Item = Frame;
+    type Error = io::Error;
+
+    fn encode(&mut self, frame: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let body_len = body_len(frame.flag, frame.message.as_ref().map(Bytes::len));
+        let len = varint_len(body_len as u64) + body_len;
+
+        if len > self.max_msg_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "message of {len} bytes exceeds the maximum of {} bytes",
+                    self.max_msg_len
+                ),
+            ));
+        }
+
+        dst.reserve(len);
+        dst.put_slice(encode::usize(body_len, &mut encode::usize_buffer()));
+        if let Some(flag) = frame.flag {
+            dst.put_u8(FLAG_TAG);
+            dst.put_slice(encode::u64(flag as u64, &mut encode::u64_buffer()));
+        }
+        if let Some(message) = frame.message {
+            dst.put_u8(MESSAGE_TAG);
+            dst.put_slice(encode::usize(message.len(), &mut encode::usize_buffer()));
+            dst.put_slice(&message);
+        }
+
+        Ok(())
+    }
+}
+
+impl Decoder for Codec {
+    type Item = Frame;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let (body_len, remaining) = match decode::usize(src) {
+            Ok((body_len, remaining)) => (body_len, remaining.len()),
+            Err(decode::Error::Insufficient) => return Ok(None),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        let prefix_len = src.len() - remaining;
+
+        if prefix_len + body_len > self.max_msg_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "message of {} bytes exceeds the maximum of {} bytes",
+                    prefix_len + body_len,
+                    self.max_msg_len
+                ),
+            ));
+        }
+
+        if remaining < body_len {
+            src.reserve(body_len - remaining);
+            return Ok(None);
+        }
+
+        src.advance(prefix_len);
+        let body = src.split_to(body_len).freeze();
+
+        decode_body(body).map(Some)
+    }
+}
+
+/// Decodes the protobuf encoded body of a message, slicing the payload out of `body`.
+fn decode_body(mut body: Bytes) -> io::Result<Frame> {
+    let mut frame = Frame {
+        flag: None,
+        message: None,
+    };
+
+    while body.has_remaining() {
+        let tag = read_varint(&mut body)?;
+
+        match (tag >> 3, tag & 0b111) {
+            (1, 0) => frame.flag = Some(Flag::from(read_varint(&mut body)? as i32)),
+            (2, 2) => frame.message = Some(read_length_delimited(&mut body)?),
+            // Skip unknown fields.
+            (_, 0) => {
+                read_varint(&mut body)?;
+            }
+            (_, 1) => skip(&mut body, 8)?,
+            (_, 2) => {
+                read_length_delimited(&mut body)?;
+            }
+            (_, 5) => skip(&mut body, 4)?,
+            (_, wire_type) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unsupported wire type {wire_type}"),
+                ))
+            }
+        }
+    }
+
+    Ok(frame)
+}
+
+fn read_varint(buf: &mut Bytes) -> io::Result<u64> {
+    let (value, remaining) =
+        decode::u64(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let consumed = buf.len() - remaining.len();
+    buf.advance(consumed);
+
+    Ok(value)
+}
+
+fn read_length_delimited(buf: &mut Bytes) -> io::Result<Bytes> {
+    let len = read_varint(buf)? as usize;
+    if buf.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(buf.split_to(len))
+}
+
+fn skip(buf: &mut Bytes, len: usize) -> io::Result<()> {
+    if buf.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    buf.advance(len);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokio::substream::MAX_MSG_LEN;
+    use quick_protobuf::Writer;
+
+    #[test]
+    fn encoding_matches_quick_protobuf() {
+        let frames = [
+            Frame::flag(Flag::FIN),
+            Frame::flag(Flag::RESET),
+            Frame {
+                flag: None,
+                message: Some(Bytes::from_static(b"hello")),
+            },
+            Frame {
+                flag: Some(Flag::STOP_SENDING),
+                message: Some(Bytes::from(vec![1; max_data_len(MAX_MSG_LEN)])),
+            },
+        ];
+
+        for frame in frames {
+            let protobuf = crate::proto::Message {
+                flag: frame.flag,
+                message: frame.message.as_ref().map(|m| m.to_vec()),
+            };
+            let mut expected = Vec::new();
+            let mut writer = Writer::new(&mut expected);
+            writer.write_message(&protobuf).unwrap();
+
+            let mut encoded = BytesMut::new();
+            Codec::new(MAX_MSG_LEN)
+                .encode(frame.clone(), &mut encoded)
+                .unwrap();
+            assert_eq!(encoded[..], expected[..]);
+
+            let decoded = Codec::new(MAX_MSG_LEN).decode(&mut encoded).unwrap();
+            assert_eq!(decoded, Some(frame));
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_messages() {
+        let frame = Frame {
+            flag: None,
+            message: Some(Bytes::from(vec![0; MAX_MSG_LEN])),
+        };
+
+        assert!(Codec::new(MAX_MSG_LEN)
+            .encode(frame, &mut BytesMut::new())
+            .is_err());
+    }
+
+    #[test]
+    fn waits_for_complete_message() {
+        let mut encoded = BytesMut::new();
+        Codec::new(MAX_MSG_LEN)
+            .encode(Frame::flag(Flag::FIN), &mut encoded)
+            .unwrap();
+        let mut partial = encoded.split_to(encoded.len() - 1);
+
+        assert_eq!(Codec::new(MAX_MSG_LEN).decode(&mut partial).unwrap(), None);
+    }
+}
@@ -27,8 +27,8 @@ use std::io;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
-use crate::proto::{Flag, Message};
-use crate::tokio::substream::framed_dc::FramedDc;
+use crate::proto::Flag;
+use crate::tokio::substream::{codec::Frame, framed_dc::FramedDc};
 
 #[must_use]
 pub(crate) struct DropListener {
@@ -99,10 +99,7 @@ impl Future for DropListener {
                 },
                 State::SendingReset { mut stream } => match stream.poll_ready_unpin(cx)? {
                     Poll::Ready(()) => {
-                        stream.start_send_unpin(Message {
-                            flag: Some(Flag::RESET),
-                            message: None,
-                        })?;
+                        stream.start_send_unpin(Frame::flag(Flag::RESET))?;
                         *state = State::Flushing { stream };
                         continue;
                     }
@@ -25,20 +25,16 @@ use webrtc::data::data_channel::{DataChannel, PollDataChannel};
 
 use std::sync::Arc;
 
-use super::{MAX_DATA_LEN, MAX_MSG_LEN, VARINT_LEN};
-use crate::proto::Message;
+use super::codec::{self, Codec};
 
-pub(crate) type FramedDc = Framed<Compat<PollDataChannel>, quick_protobuf_codec::Codec<Message>>;
-pub(crate) fn new(data_channel: Arc<DataChannel>) -> FramedDc {
+pub(crate) type FramedDc = Framed<Compat<PollDataChannel>, Codec>;
+pub(crate) fn new(data_channel: Arc<DataChannel>, max_msg_len: usize) -> FramedDc {
     let mut inner = PollDataChannel::new(data_channel);
-    inner.set_read_buf_capacity(MAX_MSG_LEN);
+    inner.set_read_buf_capacity(max_msg_len);
 
-    let mut framed = Framed::new(
-        inner.compat(),
-        quick_protobuf_codec::Codec::new(MAX_MSG_LEN - VARINT_LEN),
-    );
+    let mut framed = Framed::new(inner.compat(), Codec::new(max_msg_len));
     // If not set, `Framed` buffers up to 131kB of data before sending, which leads to "outbound
     // packet larger than maximum message size" error in webrtc-rs.
-    framed.set_send_high_water_mark(MAX_DATA_LEN);
+    framed.set_send_high_water_mark(codec::max_data_len(max_msg_len));
     framed
 }
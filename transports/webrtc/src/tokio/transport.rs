@@ -33,15 +33,19 @@ use std::{
     io,
     net::SocketAddr,
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll, Waker},
 };
 
 use crate::tokio::{
     certificate::Certificate,
-    connection::Connection,
+    connection::{Connection, StreamLimits},
     error::Error,
     fingerprint::Fingerprint,
-    udp_mux::{UDPMuxEvent, UDPMuxNewAddr},
+    mdns::{self, Mdns},
+    substream::{self, MAX_MSG_LEN},
+    turn::{self, Permissions, RelayedSocket, TurnServer},
+    udp_mux::{NewAddr, UDPMuxEvent, UDPMuxNewAddr},
     upgrade,
 };
 
@@ -51,6 +55,8 @@ pub struct Transport {
     config: Config,
     /// All the active listeners.
     listeners: SelectAll<ListenStream>,
+    /// The mDNS responder, started on first use if enabled.
+    mdns: Option<Arc<Mdns>>,
 }
 
 impl Transport {
@@ -70,8 +76,91 @@ impl Transport {
         Self {
             config: Config::new(id_keys, certificate),
             listeners: SelectAll::new(),
+            mdns: None,
         }
     }
+
+    /// Announces the certificate this transport is going to use after the current one.
+    ///
+    /// Until the transport is re-created with `next` as its certificate, listen addresses carry
+    /// the certhashes of both the current and the next certificate. Dialers accept either of
+    /// them, so addresses learned during this grace period remain valid after the switch.
+    ///
+    /// Combined with `Certificate::serialize_pem` and `Certificate::from_pem` (see the `pem`
+    /// feature) to persist certificates across restarts, this allows rotating certificates without invalidating
+    /// the advertised addresses of a node.
+    pub fn with_next_certificate(mut self, next: Certificate) -> Self {
+        self.config.next_fingerprint = Some(next.fingerprint());
+        self
+    }
+
+    /// Sets the maximum number of concurrently open inbound substreams per connection.
+    ///
+    /// Inbound [`Channel`](crate::tokio::Channel)s count against this limit as well. Data channels
+    /// opened by the remote beyond this limit are reset right away. Unlimited by default.
+    pub fn with_max_inbound_streams(mut self, max: usize) -> Self {
+        self.config.stream_limits.max_inbound = max;
+        self
+    }
+
+    /// Sets the maximum number of concurrently open outbound substreams per connection.
+    ///
+    /// Outbound [`Channel`](crate::tokio::Channel)s count against this limit as well. Once the
+    /// limit is reached, [`Connection::open_channel`] fails with
+    /// [`Error::StreamLimitReached`] and new substreams requested through the
+    /// [`StreamMuxer`](libp2p_core::muxing::StreamMuxer) interface are delayed until another one is
+    /// closed. Unlimited by default.
+    pub fn with_max_outbound_streams(mut self, max: usize) -> Self {
+        self.config.stream_limits.max_outbound = max;
+        self
+    }
+
+    /// Sets the maximum length of a message sent or received on a substream, including framing.
+    ///
+    /// Larger messages reduce the per-message overhead on high-bandwidth data channels. Messages
+    /// exceeding the limit are rejected as invalid data, so the remote must use the same value.
+    /// The value is announced as `a=max-message-size` in the SDP of every connection, and each
+    /// connection uses the smaller of it and the value in the remote description. Defaults to
+    /// 16 KiB as recommended by RFC 8831.
+    ///
+    /// Writes to a [`Substream`](crate::tokio::Substream) are coalesced into a single message until
+    /// it is full or the substream is flushed, so small writes are not sent until the next flush.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_message_size` leaves no room for data next to the framing or exceeds the
+    /// 64 KiB supported by the underlying SCTP association.
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        assert!(
+            substream::is_valid_max_msg_len(max_message_size),
+            "invalid max message size: {max_message_size}"
+        );
+        self.config.max_message_size = max_message_size;
+        self
+    }
+
+    /// Advertises a random `.local` host name instead of private IPv4 addresses and resolves the
+    /// `.local` host names of remotes through mDNS (RFC 6762) when dialing.
+    ///
+    /// This allows two peers on the same LAN to connect without a STUN server and without exposing
+    /// their private IPs. Listeners answer mDNS queries on UDP port 5353, sharing it with other
+    /// responders on the host where the platform allows it.
+    pub fn with_mdns(mut self) -> Self {
+        self.config.mdns_host_name = Some(mdns::random_host_name());
+        self
+    }
+
+    /// Acquires an allocation on the given TURN server for every listener and advertises the
+    /// relayed transport address as an additional listen address.
+    ///
+    /// This allows accepting `/webrtc-direct` connections on hosts that cannot receive UDP packets
+    /// on a public address. Before dialing a peer, a permission for it is installed on every
+    /// allocation, so that it can reach the relayed address as well, e.g. during a hole punch.
+    /// Other peers can only connect if the TURN server relays packets from any peer.
+    pub fn with_turn_server(mut self, server: TurnServer) -> Self {
+        self.config.turn_server = Some(server);
+        self
+    }
 }
 
 impl libp2p_core::Transport for Transport {
@@ -87,6 +176,7 @@ impl libp2p_core::Transport for Transport {
     ) -> Result<(), TransportError<Self::Error>> {
         let socket_addr =
             parse_webrtc_listen_addr(&addr).ok_or(TransportError::MultiaddrNotSupported(addr))?;
+        self.mdns().map_err(TransportError::Other)?;
         let udp_mux = UDPMuxNewAddr::listen_on(socket_addr)
             .map_err(|io| TransportError::Other(Error::Io(io)))?;
 
@@ -119,14 +209,30 @@ impl libp2p_core::Transport for Transport {
     }
 
     fn dial(&mut self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
-        let (sock_addr, server_fingerprint) = parse_webrtc_dial_addr(&addr)
+        let (dial_addr, server_fingerprints) = parse_webrtc_dial_addr(&addr)
             .ok_or_else(|| TransportError::MultiaddrNotSupported(addr.clone()))?;
-        if sock_addr.port() == 0 || sock_addr.ip().is_unspecified() {
-            return Err(TransportError::MultiaddrNotSupported(addr));
-        }
+        let resolve_addr: BoxFuture<'static, Result<SocketAddr, Error>> = match dial_addr {
+            DialAddr::Socket(sock_addr) => {
+                if sock_addr.port() == 0 || sock_addr.ip().is_unspecified() {
+                    return Err(TransportError::MultiaddrNotSupported(addr));
+                }
+
+                future::ready(Ok(sock_addr)).boxed()
+            }
+            DialAddr::Local { host_name, port } => {
+                let mdns = match self.mdns().map_err(TransportError::Other)? {
+                    Some(mdns) if port != 0 => mdns,
+                    _ => return Err(TransportError::MultiaddrNotSupported(addr)),
+                };
+
+                async move { Ok(SocketAddr::new(mdns.resolve(&host_name).await?, port)) }.boxed()
+            }
+        };
 
         let config = self.config.clone();
         let client_fingerprint = self.config.fingerprint;
+        let stream_limits = self.config.stream_limits;
+        let max_message_size = self.config.max_message_size;
         let udp_mux = self
             .listeners
             .iter()
@@ -134,19 +240,33 @@ impl libp2p_core::Transport for Transport {
             .ok_or(TransportError::Other(Error::NoListeners))?
             .udp_mux
             .udp_mux_handle();
+        let permissions = self
+            .listeners
+            .iter()
+            .filter_map(|listener| listener.relayed_permissions.clone())
+            .collect::<Vec<_>>();
 
         Ok(async move {
+            let sock_addr = resolve_addr.await?;
+
+            for permissions in permissions {
+                if let Err(e) = permissions.install(sock_addr).await {
+                    log::debug!("failed to install TURN permission for {sock_addr}: {e}");
+                }
+            }
+
             let (peer_id, connection) = upgrade::outbound(
                 sock_addr,
                 config.inner,
                 udp_mux,
                 client_fingerprint,
-                server_fingerprint,
+                server_fingerprints,
+                max_message_size,
                 config.id_keys,
             )
             .await?;
 
-            Ok((peer_id, connection))
+            Ok((peer_id, connection.with_stream_limits(stream_limits)))
         }
         .boxed())
     }
@@ -167,6 +287,23 @@ impl libp2p_core::Transport for Transport {
     }
 }
 
+impl Transport {
+    /// Returns the mDNS responder, starting it if mDNS is enabled but not running yet.
+    #[allow(clippy::result_large_err)]
+    fn mdns(&mut self) -> Result<Option<Arc<Mdns>>, Error> {
+        let host_name = match self.config.mdns_host_name.as_ref() {
+            Some(host_name) => host_name,
+            None => return Ok(None),
+        };
+
+        if self.mdns.is_none() {
+            self.mdns = Some(Arc::new(Mdns::new(host_name)?));
+        }
+
+        Ok(self.mdns.clone())
+    }
+}
+
 /// A stream of incoming connections on one or more interfaces.
 struct ListenStream {
     /// The ID of this listener.
@@ -183,6 +320,18 @@ struct ListenStream {
     /// The UDP muxer that manages all ICE connections.
     udp_mux: UDPMuxNewAddr,
 
+    /// The TURN allocation being acquired for this listener.
+    pending_allocation: Option<BoxFuture<'static, Result<RelayedSocket, Error>>>,
+
+    /// The UDP muxer that manages all ICE connections arriving through our TURN allocation.
+    relayed_udp_mux: Option<UDPMuxNewAddr>,
+
+    /// Installs permissions for the peers we dial on our TURN allocation.
+    relayed_permissions: Option<Permissions>,
+
+    /// Number of private addresses that are up and advertised through our mDNS host name.
+    num_private_addrs: usize,
+
     /// Set to `Some` if this listener should close.
     ///
     /// Optionally contains a [`TransportEvent::ListenerClosed`] that should be
@@ -215,18 +364,29 @@ impl ListenStream {
             pending_event = None;
         } else {
             if_watcher = None;
-            let ma = socketaddr_to_multiaddr(&listen_addr, Some(config.fingerprint));
+            let ma = config
+                .mdns_multiaddr(&listen_addr)
+                .unwrap_or_else(|| config.listen_multiaddr(&listen_addr));
             pending_event = Some(TransportEvent::NewAddress {
                 listener_id,
                 listen_addr: ma,
             })
         }
 
+        let pending_allocation = config
+            .turn_server
+            .clone()
+            .map(|server| turn::allocate(server).boxed());
+
         Ok(ListenStream {
             listener_id,
             listen_addr,
             config,
             udp_mux,
+            pending_allocation,
+            relayed_udp_mux: None,
+            relayed_permissions: None,
+            num_private_addrs: 0,
             report_closed: None,
             if_watcher,
             pending_event,
@@ -257,18 +417,30 @@ impl ListenStream {
     }
 
     fn poll_if_watcher(&mut self, cx: &mut Context<'_>) -> Poll<<Self as Stream>::Item> {
-        let if_watcher = match self.if_watcher.as_mut() {
-            Some(w) => w,
-            None => return Poll::Pending,
-        };
-
-        while let Poll::Ready(event) = if_watcher.poll_if_event(cx) {
+        while let Some(Poll::Ready(event)) = self
+            .if_watcher
+            .as_mut()
+            .map(|if_watcher| if_watcher.poll_if_event(cx))
+        {
             match event {
                 Ok(IfEvent::Up(inet)) => {
                     let ip = inet.addr();
                     if self.listen_addr.is_ipv4() == ip.is_ipv4()
                         || self.listen_addr.is_ipv6() == ip.is_ipv6()
                     {
+                        if let Some(listen_addr) = self.mdns_multiaddress(ip) {
+                            // All private addresses share the same host name.
+                            self.num_private_addrs += 1;
+                            if self.num_private_addrs > 1 {
+                                continue;
+                            }
+
+                            return Poll::Ready(TransportEvent::NewAddress {
+                                listener_id: self.listener_id,
+                                listen_addr,
+                            });
+                        }
+
                         return Poll::Ready(TransportEvent::NewAddress {
                             listener_id: self.listener_id,
                             listen_addr: self.listen_multiaddress(ip),
@@ -280,6 +452,18 @@ impl ListenStream {
                     if self.listen_addr.is_ipv4() == ip.is_ipv4()
                         || self.listen_addr.is_ipv6() == ip.is_ipv6()
                     {
+                        if let Some(listen_addr) = self.mdns_multiaddress(ip) {
+                            self.num_private_addrs = self.num_private_addrs.saturating_sub(1);
+                            if self.num_private_addrs > 0 {
+                                continue;
+                            }
+
+                            return Poll::Ready(TransportEvent::AddressExpired {
+                                listener_id: self.listener_id,
+                                listen_addr,
+                            });
+                        }
+
                         return Poll::Ready(TransportEvent::AddressExpired {
                             listener_id: self.listener_id,
                             listen_addr: self.listen_multiaddress(ip),
@@ -298,11 +482,104 @@ impl ListenStream {
         Poll::Pending
     }
 
+    fn poll_relayed(&mut self, cx: &mut Context<'_>) -> Poll<<Self as Stream>::Item> {
+        if let Some(allocation) = self.pending_allocation.as_mut() {
+            if let Poll::Ready(result) = allocation.poll_unpin(cx) {
+                self.pending_allocation = None;
+
+                return match result {
+                    Ok(socket) => {
+                        self.relayed_permissions = Some(socket.permissions());
+                        let udp_mux = UDPMuxNewAddr::relayed(socket);
+                        let listen_addr = self.config.listen_multiaddr(&udp_mux.listen_addr());
+                        self.relayed_udp_mux = Some(udp_mux);
+
+                        Poll::Ready(TransportEvent::NewAddress {
+                            listener_id: self.listener_id,
+                            listen_addr,
+                        })
+                    }
+                    Err(error) => Poll::Ready(TransportEvent::ListenerError {
+                        listener_id: self.listener_id,
+                        error,
+                    }),
+                };
+            }
+        }
+
+        let udp_mux = match self.relayed_udp_mux.as_mut() {
+            Some(udp_mux) => udp_mux,
+            None => return Poll::Pending,
+        };
+
+        match udp_mux.poll(cx) {
+            Poll::Ready(UDPMuxEvent::NewAddr(new_addr)) => {
+                let udp_mux = self.relayed_udp_mux.as_ref().expect("polled above");
+
+                Poll::Ready(self.incoming(udp_mux, new_addr))
+            }
+            Poll::Ready(UDPMuxEvent::Error(e)) => {
+                // Losing the allocation doesn't affect connections on the local socket, thus only
+                // expire the relayed address instead of closing the entire listener.
+                let udp_mux = self.relayed_udp_mux.take().expect("polled above");
+                self.relayed_permissions = None;
+                self.pending_event = Some(TransportEvent::AddressExpired {
+                    listener_id: self.listener_id,
+                    listen_addr: self.config.listen_multiaddr(&udp_mux.listen_addr()),
+                });
+
+                Poll::Ready(TransportEvent::ListenerError {
+                    listener_id: self.listener_id,
+                    error: Error::UDPMux(e),
+                })
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// Constructs a [`TransportEvent::Incoming`] for an ICE connection attempt from a previously
+    /// unseen address on the given muxer.
+    fn incoming(&self, udp_mux: &UDPMuxNewAddr, new_addr: NewAddr) -> <Self as Stream>::Item {
+        let local_addr = self.config.listen_multiaddr(&udp_mux.listen_addr());
+        let send_back_addr = socketaddr_to_multiaddr(&new_addr.addr, None);
+
+        let stream_limits = self.config.stream_limits;
+        let max_message_size = self.config.max_message_size;
+        let upgrade = upgrade::inbound(
+            new_addr.addr,
+            self.config.inner.clone(),
+            udp_mux.udp_mux_handle(),
+            self.config.fingerprint,
+            new_addr.ufrag,
+            max_message_size,
+            self.config.id_keys.clone(),
+        )
+        .map_ok(move |(peer_id, connection)| {
+            (peer_id, connection.with_stream_limits(stream_limits))
+        })
+        .boxed();
+
+        TransportEvent::Incoming {
+            upgrade,
+            local_addr,
+            send_back_addr,
+            listener_id: self.listener_id,
+        }
+    }
+
     /// Constructs a [`Multiaddr`] for the given IP address that represents our listen address.
     fn listen_multiaddress(&self, ip: IpAddr) -> Multiaddr {
         let socket_addr = SocketAddr::new(ip, self.listen_addr.port());
 
-        socketaddr_to_multiaddr(&socket_addr, Some(self.config.fingerprint))
+        self.config.listen_multiaddr(&socket_addr)
+    }
+
+    /// Constructs the `.local` multiaddress we advertise in place of the private `ip`, if mDNS is
+    /// enabled.
+    fn mdns_multiaddress(&self, ip: IpAddr) -> Option<Multiaddr> {
+        let socket_addr = SocketAddr::new(ip, self.listen_addr.port());
+
+        self.config.mdns_multiaddr(&socket_addr)
     }
 }
 
@@ -326,29 +603,14 @@ impl Stream for ListenStream {
                 return Poll::Ready(Some(event));
             }
 
+            if let Poll::Ready(event) = self.poll_relayed(cx) {
+                return Poll::Ready(Some(event));
+            }
+
             // Poll UDP muxer for new addresses or incoming data for streams.
             match self.udp_mux.poll(cx) {
                 Poll::Ready(UDPMuxEvent::NewAddr(new_addr)) => {
-                    let local_addr =
-                        socketaddr_to_multiaddr(&self.listen_addr, Some(self.config.fingerprint));
-                    let send_back_addr = socketaddr_to_multiaddr(&new_addr.addr, None);
-
-                    let upgrade = upgrade::inbound(
-                        new_addr.addr,
-                        self.config.inner.clone(),
-                        self.udp_mux.udp_mux_handle(),
-                        self.config.fingerprint,
-                        new_addr.ufrag,
-                        self.config.id_keys.clone(),
-                    )
-                    .boxed();
-
-                    return Poll::Ready(Some(TransportEvent::Incoming {
-                        upgrade,
-                        local_addr,
-                        send_back_addr,
-                        listener_id: self.listener_id,
-                    }));
+                    return Poll::Ready(Some(self.incoming(&self.udp_mux, new_addr)));
                 }
                 Poll::Ready(UDPMuxEvent::Error(e)) => {
                     self.close(Err(Error::UDPMux(e)));
@@ -370,6 +632,11 @@ struct Config {
     inner: RTCConfiguration,
     fingerprint: Fingerprint,
     id_keys: identity::Keypair,
+    next_fingerprint: Option<Fingerprint>,
+    turn_server: Option<TurnServer>,
+    stream_limits: StreamLimits,
+    max_message_size: usize,
+    mdns_host_name: Option<String>,
 }
 
 impl Config {
@@ -384,6 +651,42 @@ impl Config {
                 ..RTCConfiguration::default()
             },
             fingerprint,
+            next_fingerprint: None,
+            turn_server: None,
+            stream_limits: StreamLimits::default(),
+            max_message_size: MAX_MSG_LEN,
+            mdns_host_name: None,
+        }
+    }
+
+    /// Turns an IP address and port into the WebRTC multiaddr we advertise for it.
+    fn listen_multiaddr(&self, socket_addr: &SocketAddr) -> Multiaddr {
+        self.with_certhashes(socketaddr_to_multiaddr(socket_addr, None))
+    }
+
+    /// Returns the `.local` multiaddr we advertise in place of a private IP address and port, or
+    /// `None` if mDNS is disabled or the address isn't private.
+    fn mdns_multiaddr(&self, socket_addr: &SocketAddr) -> Option<Multiaddr> {
+        let host_name = self.mdns_host_name.as_ref()?;
+        if !mdns::is_private(&socket_addr.ip()) {
+            return None;
+        }
+
+        let addr = Multiaddr::empty()
+            .with(Protocol::Dns4(host_name.clone().into()))
+            .with(Protocol::Udp(socket_addr.port()))
+            .with(Protocol::WebRTCDirect);
+
+        Some(self.with_certhashes(addr))
+    }
+
+    /// Appends the certhashes of our current and, if any, next certificate.
+    fn with_certhashes(&self, addr: Multiaddr) -> Multiaddr {
+        let addr = addr.with(Protocol::Certhash(self.fingerprint.to_multihash()));
+
+        match self.next_fingerprint {
+            Some(next) => addr.with(Protocol::Certhash(next.to_multihash())),
+            None => addr,
         }
     }
 }
@@ -427,38 +730,68 @@ fn parse_webrtc_listen_addr(addr: &Multiaddr) -> Option<SocketAddr> {
     Some(SocketAddr::new(ip, port))
 }
 
-/// Parse the given [`Multiaddr`] into a [`SocketAddr`] and a [`Fingerprint`] for dialing.
-fn parse_webrtc_dial_addr(addr: &Multiaddr) -> Option<(SocketAddr, Fingerprint)> {
+/// The address of a remote to dial.
+#[derive(Debug, PartialEq, Eq)]
+enum DialAddr {
+    Socket(SocketAddr),
+    /// A `.local` host name, to be resolved through mDNS.
+    Local {
+        host_name: String,
+        port: u16,
+    },
+}
+
+/// Parse the given [`Multiaddr`] into a [`DialAddr`] and the [`Fingerprint`]s of the
+/// certificates the remote may present for dialing.
+fn parse_webrtc_dial_addr(addr: &Multiaddr) -> Option<(DialAddr, Vec<Fingerprint>)> {
     let mut iter = addr.iter();
 
-    let ip = match iter.next()? {
-        Protocol::Ip4(ip) => IpAddr::from(ip),
-        Protocol::Ip6(ip) => IpAddr::from(ip),
-        _ => return None,
-    };
+    let host = iter.next()?;
 
     let port = iter.next()?;
     let webrtc = iter.next()?;
     let certhash = iter.next()?;
 
-    let (port, fingerprint) = match (port, webrtc, certhash) {
+    let (port, mut fingerprints) = match (port, webrtc, certhash) {
         (Protocol::Udp(port), Protocol::WebRTCDirect, Protocol::Certhash(cert_hash)) => {
             let fingerprint = Fingerprint::try_from_multihash(cert_hash)?;
 
-            (port, fingerprint)
+            (port, vec![fingerprint])
         }
         _ => return None,
     };
 
-    match iter.next() {
-        Some(Protocol::P2p(_)) => {}
-        // peer ID is optional
-        None => {}
-        // unexpected protocol
-        Some(_) => return None,
+    loop {
+        match iter.next() {
+            // the remote may be rotating its certificate
+            Some(Protocol::Certhash(cert_hash)) => {
+                fingerprints.push(Fingerprint::try_from_multihash(cert_hash)?);
+            }
+            Some(Protocol::P2p(_)) => break,
+            // peer ID is optional
+            None => break,
+            // unexpected protocol
+            Some(_) => return None,
+        }
+    }
+
+    if iter.next().is_some() {
+        return None;
     }
 
-    Some((SocketAddr::new(ip, port), fingerprint))
+    let dial_addr = match host {
+        Protocol::Ip4(ip) => DialAddr::Socket(SocketAddr::new(ip.into(), port)),
+        Protocol::Ip6(ip) => DialAddr::Socket(SocketAddr::new(ip.into(), port)),
+        Protocol::Dns(name) | Protocol::Dns4(name) if mdns::is_local_name(&name) => {
+            DialAddr::Local {
+                host_name: name.into_owned(),
+                port,
+            }
+        }
+        _ => return None,
+    };
+
+    Some((dial_addr, fingerprints))
 }
 
 // Tests //////////////////////////////////////////////////////////////////////////////////////////
@@ -491,10 +824,13 @@ mod tests {
         assert_eq!(
             maybe_parsed,
             Some((
-                SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 39901),
-                Fingerprint::raw(hex_literal::hex!(
+                DialAddr::Socket(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    39901
+                )),
+                vec![Fingerprint::raw(hex_literal::hex!(
                     "e2929e4a5548242ed6b512350df8829b1e4f9d50183c5732a07f99d7c4b2b8eb"
-                ))
+                ))]
             ))
         );
     }
@@ -510,12 +846,70 @@ mod tests {
         assert_eq!(
             maybe_parsed,
             Some((
-                SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 39901),
-                Fingerprint::raw(hex_literal::hex!(
+                DialAddr::Socket(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    39901
+                )),
+                vec![Fingerprint::raw(hex_literal::hex!(
+                    "e2929e4a5548242ed6b512350df8829b1e4f9d50183c5732a07f99d7c4b2b8eb"
+                ))]
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_multiple_certhashes() {
+        let addr = "/ip4/127.0.0.1/udp/39901/webrtc-direct/certhash/uEiDikp5KVUgkLta1EjUN-IKbHk-dUBg8VzKgf5nXxLK46w/certhash/uEiAw_J9GnCB0Gd_dCqtfJ6hslzyU5AVI25N1zKLpFZc7mQ/p2p/12D3KooWNpDk9w6WrEEcdsEH1y47W71S36yFjw4sd3j7omzgCSMS"
+            .parse()
+            .unwrap();
+
+        let maybe_parsed = parse_webrtc_dial_addr(&addr);
+
+        assert_eq!(
+            maybe_parsed,
+            Some((
+                DialAddr::Socket(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    39901
+                )),
+                vec![
+                    Fingerprint::raw(hex_literal::hex!(
+                        "e2929e4a5548242ed6b512350df8829b1e4f9d50183c5732a07f99d7c4b2b8eb"
+                    )),
+                    Fingerprint::raw(hex_literal::hex!(
+                        "30fc9f469c207419dfdd0aab5f27a86c973c94e40548db9375cca2e915973b99"
+                    ))
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_local_host_name() {
+        let addr = "/dns4/a1b2c3.local/udp/39901/webrtc-direct/certhash/uEiDikp5KVUgkLta1EjUN-IKbHk-dUBg8VzKgf5nXxLK46w"
+            .parse()
+            .unwrap();
+
+        let maybe_parsed = parse_webrtc_dial_addr(&addr);
+
+        assert_eq!(
+            maybe_parsed,
+            Some((
+                DialAddr::Local {
+                    host_name: "a1b2c3.local".to_owned(),
+                    port: 39901
+                },
+                vec![Fingerprint::raw(hex_literal::hex!(
                     "e2929e4a5548242ed6b512350df8829b1e4f9d50183c5732a07f99d7c4b2b8eb"
-                ))
+                ))]
             ))
         );
+
+        let addr = "/dns4/example.com/udp/39901/webrtc-direct/certhash/uEiDikp5KVUgkLta1EjUN-IKbHk-dUBg8VzKgf5nXxLK46w"
+            .parse()
+            .unwrap();
+
+        assert!(parse_webrtc_dial_addr(&addr).is_none());
     }
 
     #[test]
@@ -552,10 +946,10 @@ mod tests {
         assert_eq!(
             maybe_parsed,
             Some((
-                SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 12345),
-                Fingerprint::raw(hex_literal::hex!(
+                DialAddr::Socket(SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 12345)),
+                vec![Fingerprint::raw(hex_literal::hex!(
                     "e2929e4a5548242ed6b512350df8829b1e4f9d50183c5732a07f99d7c4b2b8eb"
-                ))
+                ))]
             ))
         );
     }
@@ -645,4 +1039,41 @@ mod tests {
             assert!(transport.listeners.is_empty());
         }
     }
+
+    fn new_transport() -> Transport {
+        let id_keys = identity::Keypair::generate_ed25519();
+        Transport::new(id_keys, Certificate::generate(&mut thread_rng()).unwrap())
+    }
+
+    #[test]
+    fn max_message_size_accepts_bounds() {
+        let min = (0..MAX_MSG_LEN)
+            .find(|&len| substream::is_valid_max_msg_len(len))
+            .unwrap();
+
+        let transport = new_transport().with_max_message_size(min);
+        assert_eq!(transport.config.max_message_size, min);
+
+        let transport = new_transport().with_max_message_size(substream::MAX_SCTP_MSG_LEN);
+        assert_eq!(
+            transport.config.max_message_size,
+            substream::MAX_SCTP_MSG_LEN
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid max message size")]
+    fn max_message_size_without_room_for_data() {
+        let min = (0..MAX_MSG_LEN)
+            .find(|&len| substream::is_valid_max_msg_len(len))
+            .unwrap();
+
+        let _ = new_transport().with_max_message_size(min - 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid max message size")]
+    fn max_message_size_above_sctp_limit() {
+        let _ = new_transport().with_max_message_size(substream::MAX_SCTP_MSG_LEN + 1);
+    }
 }
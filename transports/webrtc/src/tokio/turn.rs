@@ -0,0 +1,320 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Listening through a TURN relay.
+//!
+//! A server that cannot accept UDP packets on a public address can acquire an allocation on a TURN
+//! server (RFC 8656) and advertise the relayed transport address as its `/webrtc-direct` listen
+//! address. Packets sent by dialers to that address are forwarded by the TURN server to us and
+//! handed to the same ICE-lite machinery that serves directly bound sockets.
+//!
+//! Note that TURN servers only forward packets from peers for which a permission has been
+//! installed. Before dialing a peer, e.g. as part of a hole punch where both sides dial each other,
+//! we install a permission for it on every allocation. Other dialers can only reach us if the TURN
+//! server is configured to relay packets from any peer to the allocation.
+
+use futures::{future::BoxFuture, FutureExt};
+use tokio::{io::ReadBuf, net::UdpSocket};
+use webrtc::turn::client::{Client, ClientConfig};
+use webrtc::util::Conn;
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use crate::tokio::error::Error;
+
+/// Maximum size of a packet read from the relayed transport address.
+const RECEIVE_MTU: usize = 8192;
+
+/// A TURN server on which to acquire an allocation for every listener.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TurnServer {
+    addr: SocketAddr,
+    username: String,
+    password: String,
+    realm: String,
+}
+
+impl TurnServer {
+    /// Creates a new [`TurnServer`] reachable at `addr`, authenticating with the given long-term
+    /// credentials.
+    pub fn new(addr: SocketAddr, username: impl Into<String>, password: impl Into<String>) -> Self {
+        Self {
+            addr,
+            username: username.into(),
+            password: password.into(),
+            realm: String::new(),
+        }
+    }
+
+    /// Sets the realm used for authentication.
+    ///
+    /// Most servers announce their realm in the first `401` response, in which case this doesn't
+    /// need to be set.
+    pub fn with_realm(mut self, realm: impl Into<String>) -> Self {
+        self.realm = realm.into();
+        self
+    }
+
+    /// Returns the address of the TURN server.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+/// Acquires an allocation on the given TURN server.
+pub(crate) async fn allocate(server: TurnServer) -> Result<RelayedSocket, Error> {
+    let bind_ip = match server.addr {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let socket = UdpSocket::bind(SocketAddr::new(bind_ip, 0)).await?;
+
+    let client = Client::new(ClientConfig {
+        stun_serv_addr: String::new(),
+        turn_serv_addr: server.addr.to_string(),
+        username: server.username,
+        password: server.password,
+        realm: server.realm,
+        software: String::new(),
+        rto_in_ms: 0,
+        conn: Arc::new(socket),
+        vnet: None,
+    })
+    .await?;
+    client.listen().await?;
+
+    let conn: Arc<dyn Conn + Send + Sync> = Arc::new(client.allocate().await?);
+    let relayed_addr = conn.local_addr().map_err(to_io_error)?;
+
+    log::debug!("acquired TURN allocation {relayed_addr} on {}", server.addr);
+
+    Ok(RelayedSocket {
+        _client: client,
+        conn,
+        relayed_addr,
+        recv: None,
+        send: None,
+    })
+}
+
+/// A received datagram and its sender.
+type Datagram = (Vec<u8>, SocketAddr);
+
+/// The relayed transport address of a TURN allocation, exposing a poll-based interface similar to
+/// [`UdpSocket`].
+pub(crate) struct RelayedSocket {
+    /// Keeps the allocation refreshed for as long as we are alive.
+    _client: Client,
+    conn: Arc<dyn Conn + Send + Sync>,
+    relayed_addr: SocketAddr,
+
+    recv: Option<BoxFuture<'static, io::Result<Datagram>>>,
+    send: Option<BoxFuture<'static, io::Result<usize>>>,
+}
+
+impl RelayedSocket {
+    /// The relayed transport address allocated on the TURN server.
+    pub(crate) fn local_addr(&self) -> SocketAddr {
+        self.relayed_addr
+    }
+
+    /// Returns a handle for installing permissions on this allocation.
+    pub(crate) fn permissions(&self) -> Permissions {
+        Permissions {
+            conn: self.conn.clone(),
+        }
+    }
+
+    pub(crate) fn poll_recv_from(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<SocketAddr>> {
+        let recv = self.recv.get_or_insert_with(|| {
+            let conn = self.conn.clone();
+
+            async move {
+                let mut packet = vec![0u8; RECEIVE_MTU];
+                let (n, addr) = conn.recv_from(&mut packet).await.map_err(to_io_error)?;
+                packet.truncate(n);
+
+                Ok((packet, addr))
+            }
+            .boxed()
+        });
+
+        let result = futures::ready!(recv.poll_unpin(cx));
+        self.recv = None;
+        let (packet, addr) = result?;
+
+        let len = packet.len().min(buf.remaining());
+        buf.put_slice(&packet[..len]);
+
+        Poll::Ready(Ok(addr))
+    }
+
+    /// Sends `buf` to `target` through the TURN server.
+    ///
+    /// Like [`UdpSocket::poll_send_to`], callers must retry with the same arguments after
+    /// [`Poll::Pending`] was returned.
+    pub(crate) fn poll_send_to(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        target: SocketAddr,
+    ) -> Poll<io::Result<usize>> {
+        let send = self.send.get_or_insert_with(|| {
+            let conn = self.conn.clone();
+            let packet = buf.to_vec();
+
+            async move { conn.send_to(&packet, target).await.map_err(to_io_error) }.boxed()
+        });
+
+        let result = futures::ready!(send.poll_unpin(cx));
+        self.send = None;
+
+        Poll::Ready(result)
+    }
+}
+
+/// Installs permissions on a TURN allocation, allowing peers to send packets to its relayed
+/// transport address.
+#[derive(Clone)]
+pub(crate) struct Permissions {
+    conn: Arc<dyn Conn + Send + Sync>,
+}
+
+impl Permissions {
+    /// Installs a permission for `remote`.
+    ///
+    /// The TURN client doesn't expose CreatePermission requests on their own, but issues one
+    /// before relaying the first packet to a new peer. We therefore send an empty packet, which
+    /// the remote discards.
+    pub(crate) async fn install(self, remote: SocketAddr) -> Result<(), Error> {
+        self.conn.send_to(&[], remote).await.map_err(to_io_error)?;
+
+        Ok(())
+    }
+}
+
+fn to_io_error(e: webrtc::util::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use webrtc::turn::{
+        auth::{generate_auth_key, AuthHandler},
+        relay::relay_static::RelayAddressGeneratorStatic,
+        server::{
+            config::{ConnConfig, ServerConfig},
+            Server,
+        },
+    };
+    use webrtc::util::vnet::net::Net;
+
+    struct TestAuthHandler;
+
+    impl AuthHandler for TestAuthHandler {
+        fn auth_handle(
+            &self,
+            username: &str,
+            realm: &str,
+            _: SocketAddr,
+        ) -> Result<Vec<u8>, webrtc::turn::Error> {
+            Ok(generate_auth_key(username, realm, "password"))
+        }
+    }
+
+    async fn turn_server() -> (Server, SocketAddr) {
+        let conn = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = conn.local_addr().unwrap();
+
+        let server = Server::new(ServerConfig {
+            conn_configs: vec![ConnConfig {
+                conn: Arc::new(conn),
+                relay_addr_generator: Box::new(RelayAddressGeneratorStatic {
+                    relay_address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                    address: "127.0.0.1".to_owned(),
+                    net: Arc::new(Net::new(None)),
+                }),
+            }],
+            realm: "libp2p".to_owned(),
+            auth_handler: Arc::new(TestAuthHandler),
+            channel_bind_timeout: Duration::from_secs(0),
+        })
+        .await
+        .unwrap();
+
+        (server, addr)
+    }
+
+    async fn recv_from(socket: &mut RelayedSocket) -> (Vec<u8>, SocketAddr) {
+        let mut buf = [0; RECEIVE_MTU];
+        let mut buf = ReadBuf::new(&mut buf);
+        let addr = futures::future::poll_fn(|cx| socket.poll_recv_from(cx, &mut buf))
+            .await
+            .unwrap();
+
+        (buf.filled().to_vec(), addr)
+    }
+
+    #[tokio::test]
+    async fn permitted_peers_reach_the_allocation() {
+        let (server, server_addr) = turn_server().await;
+        let mut socket = allocate(TurnServer::new(server_addr, "user", "password"))
+            .await
+            .unwrap();
+        let relayed_addr = socket.local_addr();
+
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let peer_addr = peer.local_addr().unwrap();
+
+        // Without a permission, the TURN server drops the packet.
+        peer.send_to(b"dropped", relayed_addr).await.unwrap();
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), recv_from(&mut socket))
+                .await
+                .is_err()
+        );
+
+        socket.permissions().install(peer_addr).await.unwrap();
+
+        // The empty packet installing the permission is relayed to the peer.
+        let mut buf = [0; RECEIVE_MTU];
+        let (n, from) = peer.recv_from(&mut buf).await.unwrap();
+        assert_eq!((n, from), (0, relayed_addr));
+
+        peer.send_to(b"relayed", relayed_addr).await.unwrap();
+        assert_eq!(
+            recv_from(&mut socket).await,
+            (b"relayed".to_vec(), peer_addr)
+        );
+
+        server.close().await.unwrap();
+    }
+}
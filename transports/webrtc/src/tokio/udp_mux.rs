@@ -43,7 +43,7 @@ use std::{
     task::{Context, Poll},
 };
 
-use crate::tokio::req_res_chan;
+use crate::tokio::{req_res_chan, turn::RelayedSocket};
 
 const RECEIVE_MTU: usize = 8192;
 
@@ -68,7 +68,7 @@ pub(crate) enum UDPMuxEvent {
 /// - It has been rewritten to work without locks and channels instead.
 /// - It reports previously unseen addresses instead of ignoring them.
 pub(crate) struct UDPMuxNewAddr {
-    udp_sock: UdpSocket,
+    udp_sock: Socket,
 
     listen_addr: SocketAddr,
 
@@ -107,12 +107,24 @@ impl UDPMuxNewAddr {
         let tokio_socket = UdpSocket::from_std(std_sock)?;
         let listen_addr = tokio_socket.local_addr()?;
 
+        Ok(Self::new(Socket::Udp(tokio_socket), listen_addr))
+    }
+
+    /// Creates a UDP muxer for connections arriving on the relayed transport address of a TURN
+    /// allocation.
+    pub(crate) fn relayed(socket: RelayedSocket) -> Self {
+        let listen_addr = socket.local_addr();
+
+        Self::new(Socket::Relayed(socket), listen_addr)
+    }
+
+    fn new(udp_sock: Socket, listen_addr: SocketAddr) -> Self {
         let (udp_mux_handle, close_command, get_conn_command, remove_conn_command) =
             UdpMuxHandle::new();
         let (udp_mux_writer_handle, registration_command, send_command) = UdpMuxWriterHandle::new();
 
-        Ok(Self {
-            udp_sock: tokio_socket,
+        Self {
+            udp_sock,
             listen_addr,
             conns: HashMap::default(),
             address_map: HashMap::default(),
@@ -128,7 +140,7 @@ impl UDPMuxNewAddr {
             send_command,
             udp_mux_handle: Arc::new(udp_mux_handle),
             udp_mux_writer_handle: Arc::new(udp_mux_writer_handle),
-        })
+        }
     }
 
     pub(crate) fn listen_addr(&self) -> SocketAddr {
@@ -141,7 +153,7 @@ impl UDPMuxNewAddr {
 
     /// Create a muxed connection for a given ufrag.
     fn create_muxed_conn(&self, ufrag: &str) -> Result<UDPMuxConn, Error> {
-        let local_addr = self.udp_sock.local_addr()?;
+        let local_addr = self.listen_addr;
 
         let params = UDPMuxConnParams {
             local_addr,
@@ -417,6 +429,39 @@ impl UDPMuxNewAddr {
     }
 }
 
+/// The socket [`UDPMuxNewAddr`] reads packets from and writes packets to.
+enum Socket {
+    /// A UDP socket bound to one of our local addresses.
+    Udp(UdpSocket),
+    /// The relayed transport address of a TURN allocation.
+    Relayed(RelayedSocket),
+}
+
+impl Socket {
+    fn poll_recv_from(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<SocketAddr>> {
+        match self {
+            Socket::Udp(socket) => socket.poll_recv_from(cx, buf),
+            Socket::Relayed(socket) => socket.poll_recv_from(cx, buf),
+        }
+    }
+
+    fn poll_send_to(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        target: SocketAddr,
+    ) -> Poll<io::Result<usize>> {
+        match self {
+            Socket::Udp(socket) => socket.poll_send_to(cx, buf, target),
+            Socket::Relayed(socket) => socket.poll_send_to(cx, buf, target),
+        }
+    }
+}
+
 /// Handle which utilizes [`req_res_chan`] to transmit commands (e.g. remove connection) from the
 /// WebRTC ICE agent to [`UDPMuxNewAddr::poll`].
 pub(crate) struct UdpMuxHandle {
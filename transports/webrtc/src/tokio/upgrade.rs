@@ -40,7 +40,13 @@ use webrtc::peer_connection::RTCPeerConnection;
 
 use std::{net::SocketAddr, sync::Arc, time::Duration};
 
-use crate::tokio::{error::Error, fingerprint::Fingerprint, sdp, substream::Substream, Connection};
+use crate::tokio::{
+    error::Error,
+    fingerprint::Fingerprint,
+    sdp,
+    substream::{self, Substream, MAX_MSG_LEN},
+    Connection,
+};
 
 /// Creates a new outbound WebRTC connection.
 pub(crate) async fn outbound(
@@ -48,7 +54,8 @@ pub(crate) async fn outbound(
     config: RTCConfiguration,
     udp_mux: Arc<dyn UDPMux + Send + Sync>,
     client_fingerprint: Fingerprint,
-    server_fingerprint: Fingerprint,
+    server_fingerprints: Vec<Fingerprint>,
+    max_message_size: usize,
     id_keys: identity::Keypair,
 ) -> Result<(PeerId, Connection), Error> {
     log::debug!("new outbound connection to {addr})");
@@ -59,14 +66,18 @@ pub(crate) async fn outbound(
     log::debug!("created SDP offer for outbound connection: {:?}", offer.sdp);
     peer_connection.set_local_description(offer).await?;
 
-    let answer = sdp::answer(addr, &server_fingerprint, &ufrag);
+    let answer = sdp::answer(addr, &server_fingerprints, &ufrag, max_message_size);
     log::debug!(
         "calculated SDP answer for outbound connection: {:?}",
         answer
     );
     peer_connection.set_remote_description(answer).await?; // This will start the gathering of ICE candidates.
+    let max_message_size = negotiate_max_message_size(&peer_connection, max_message_size).await;
 
     let data_channel = create_substream_for_noise_handshake(&peer_connection).await?;
+    // The DTLS handshake verified that the server presented one of the expected certificates,
+    // find out which one.
+    let server_fingerprint = get_remote_fingerprint(&peer_connection).await;
     let peer_id = noise::outbound(
         id_keys,
         data_channel,
@@ -75,7 +86,11 @@ pub(crate) async fn outbound(
     )
     .await?;
 
-    Ok((peer_id, Connection::new(peer_connection).await))
+    let connection = Connection::new(peer_connection)
+        .await
+        .with_max_message_size(max_message_size);
+
+    Ok((peer_id, connection))
 }
 
 /// Creates a new inbound WebRTC connection.
@@ -85,15 +100,17 @@ pub(crate) async fn inbound(
     udp_mux: Arc<dyn UDPMux + Send + Sync>,
     server_fingerprint: Fingerprint,
     remote_ufrag: String,
+    max_message_size: usize,
     id_keys: identity::Keypair,
 ) -> Result<(PeerId, Connection), Error> {
     log::debug!("new inbound connection from {addr} (ufrag: {remote_ufrag})");
 
     let peer_connection = new_inbound_connection(addr, config, udp_mux, &remote_ufrag).await?;
 
-    let offer = sdp::offer(addr, &remote_ufrag);
+    let offer = sdp::offer(addr, &remote_ufrag, max_message_size);
     log::debug!("calculated SDP offer for inbound connection: {:?}", offer);
     peer_connection.set_remote_description(offer).await?;
+    let max_message_size = negotiate_max_message_size(&peer_connection, max_message_size).await;
 
     let answer = peer_connection.create_answer(None).await?;
     log::debug!("created SDP answer for inbound connection: {:?}", answer);
@@ -109,7 +126,30 @@ pub(crate) async fn inbound(
     )
     .await?;
 
-    Ok((peer_id, Connection::new(peer_connection).await))
+    let connection = Connection::new(peer_connection)
+        .await
+        .with_max_message_size(max_message_size);
+
+    Ok((peer_id, connection))
+}
+
+/// Returns the maximum message size of a connection, i.e. the smaller of the local one and the one
+/// announced as `a=max-message-size` in the remote description.
+async fn negotiate_max_message_size(peer_connection: &RTCPeerConnection, local: usize) -> usize {
+    let remote = peer_connection
+        .remote_description()
+        .await
+        .and_then(|description| sdp::max_message_size(&description.sdp));
+
+    match remote {
+        // A value of 0 announces that the size of messages is not limited.
+        None | Some(0) => local,
+        Some(remote) if remote < local && !substream::is_valid_max_msg_len(remote) => {
+            log::debug!("ignoring remote max message size {remote} leaving no room for data");
+            local
+        }
+        Some(remote) => local.min(remote),
+    }
 }
 
 async fn new_outbound_connection(
@@ -234,7 +274,7 @@ async fn create_substream_for_noise_handshake(
         }
     };
 
-    let (substream, drop_listener) = Substream::new(channel);
+    let (substream, drop_listener) = Substream::new(channel, MAX_MSG_LEN);
     drop(drop_listener); // Don't care about cancelled substreams during initial handshake.
 
     Ok(substream)
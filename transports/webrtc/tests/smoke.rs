@@ -27,6 +27,7 @@ use libp2p_core::transport::{Boxed, ListenerId, TransportEvent};
 use libp2p_core::{Multiaddr, Transport};
 use libp2p_identity::PeerId;
 use libp2p_webrtc as webrtc;
+use libp2p_webrtc::tokio::Reliability;
 use rand::{thread_rng, RngCore};
 use std::future::Future;
 use std::num::NonZeroU8;
@@ -62,25 +63,160 @@ fn concurrent_connections_and_streams_tokio() {
         .quickcheck(prop as fn(_, _) -> _);
 }
 
+#[tokio::test]
+async fn unreliable_channels() {
+    let _ = env_logger::try_init();
+
+    let (_, mut a_transport) = create_connection_transport(|t| t);
+    let (_, mut b_transport) = create_connection_transport(|t| t);
+
+    let addr = start_listening(&mut a_transport, "/ip4/127.0.0.1/udp/0/webrtc-direct").await;
+    start_listening(&mut b_transport, "/ip4/127.0.0.1/udp/0/webrtc-direct").await;
+    let ((_, _, mut a_connection), (_, mut b_connection)) =
+        connect(&mut a_transport, &mut b_transport, addr).await;
+
+    // The listeners drive the UDP sockets of their connections.
+    tokio::spawn(a_transport.for_each(|_| future::ready(())));
+    tokio::spawn(b_transport.for_each(|_| future::ready(())));
+
+    // Reliable and ordered delivery is left to substreams.
+    assert!(b_connection
+        .open_channel(Reliability::Reliable { ordered: true })
+        .await
+        .is_err());
+    // A limit of 0 means no limit to the WebRTC implementation.
+    assert!(b_connection
+        .open_channel(Reliability::MaxRetransmits {
+            ordered: false,
+            max_retransmits: 0,
+        })
+        .await
+        .is_err());
+
+    let reliability = Reliability::MaxRetransmits {
+        ordered: false,
+        max_retransmits: 1,
+    };
+    let (outbound, inbound) = future::join(
+        b_connection.open_channel(reliability),
+        future::poll_fn(|cx| a_connection.poll_inbound_channel(cx)),
+    )
+    .await;
+    let outbound = outbound.unwrap();
+    assert_eq!(outbound.reliability(), reliability);
+    assert_eq!(inbound.reliability(), reliability);
+
+    outbound.send(b"hello").await.unwrap();
+    assert_eq!(inbound.recv().await.unwrap(), Some(b"hello".to_vec()));
+
+    // Messages are not split, thus must fit into a single data channel message.
+    assert!(outbound.send(&[]).await.is_err());
+    assert!(outbound.send(&[0; 16385]).await.is_err());
+}
+
+#[tokio::test]
+async fn streams_past_the_limit_are_rejected() {
+    let _ = env_logger::try_init();
+
+    let (_, mut a_transport) =
+        create_connection_transport(|t| t.with_max_inbound_streams(1).with_max_outbound_streams(1));
+    let (_, mut b_transport) = create_connection_transport(|t| t);
+
+    let addr = start_listening(&mut a_transport, "/ip4/127.0.0.1/udp/0/webrtc-direct").await;
+    start_listening(&mut b_transport, "/ip4/127.0.0.1/udp/0/webrtc-direct").await;
+    let ((_, _, mut a_connection), (_, mut b_connection)) =
+        connect(&mut a_transport, &mut b_transport, addr).await;
+
+    // The listeners drive the UDP sockets of their connections.
+    tokio::spawn(a_transport.for_each(|_| future::ready(())));
+    tokio::spawn(b_transport.for_each(|_| future::ready(())));
+
+    let reliability = Reliability::MaxRetransmits {
+        ordered: false,
+        max_retransmits: 1,
+    };
+
+    // Inbound: the channel past the limit is closed right away.
+    let (_first_outbound, _first_inbound) = future::join(
+        b_connection.open_channel(reliability),
+        future::poll_fn(|cx| a_connection.poll_inbound_channel(cx)),
+    )
+    .await;
+    let second = b_connection.open_channel(reliability).await.unwrap();
+
+    let accept = future::poll_fn(|cx| {
+        let _ = a_connection.poll_unpin(cx);
+        if let Poll::Ready(channel) = a_connection.poll_inbound_channel(cx) {
+            panic!("Unexpected channel {:?}", channel.reliability());
+        }
+
+        Poll::<()>::Pending
+    });
+    match future::select(Box::pin(second.recv()), accept).await {
+        Either::Left((result, _)) => assert!(!matches!(result, Ok(Some(_)))),
+        Either::Right(((), _)) => unreachable!(),
+    }
+
+    // Outbound: opening a channel past the limit fails, while substreams wait for a free slot.
+    let first = a_connection.open_channel(reliability).await.unwrap();
+    assert!(matches!(
+        a_connection.open_channel(reliability).await,
+        Err(webrtc::tokio::Error::StreamLimitReached)
+    ));
+    assert!(a_connection
+        .poll_outbound_unpin(&mut noop_context())
+        .is_pending());
+
+    drop(first);
+    future::poll_fn(|cx| {
+        let _ = a_connection.poll_unpin(cx);
+
+        a_connection.poll_outbound_unpin(cx)
+    })
+    .await
+    .unwrap();
+}
+
+fn noop_context() -> Context<'static> {
+    Context::from_waker(futures::task::noop_waker_ref())
+}
+
 fn generate_tls_keypair() -> libp2p_identity::Keypair {
     libp2p_identity::Keypair::generate_ed25519()
 }
 
 fn create_transport() -> (PeerId, Boxed<(PeerId, StreamMuxerBox)>) {
+    let (peer_id, transport) = create_webrtc_transport();
+
+    (
+        peer_id,
+        transport
+            .map(|(p, c), _| (p, StreamMuxerBox::new(c)))
+            .boxed(),
+    )
+}
+
+fn create_connection_transport(
+    configure: impl FnOnce(webrtc::tokio::Transport) -> webrtc::tokio::Transport,
+) -> (PeerId, Boxed<(PeerId, webrtc::tokio::Connection)>) {
+    let (peer_id, transport) = create_webrtc_transport();
+
+    (peer_id, configure(transport).boxed())
+}
+
+fn create_webrtc_transport() -> (PeerId, webrtc::tokio::Transport) {
     let keypair = generate_tls_keypair();
     let peer_id = keypair.public().to_peer_id();
 
     let transport = webrtc::tokio::Transport::new(
         keypair,
         webrtc::tokio::Certificate::generate(&mut thread_rng()).unwrap(),
-    )
-    .map(|(p, c), _| (p, StreamMuxerBox::new(c)))
-    .boxed();
+    );
 
     (peer_id, transport)
 }
 
-async fn start_listening(transport: &mut Boxed<(PeerId, StreamMuxerBox)>, addr: &str) -> Multiaddr {
+async fn start_listening<C>(transport: &mut Boxed<(PeerId, C)>, addr: &str) -> Multiaddr {
     transport
         .listen_on(ListenerId::next(), addr.parse().unwrap())
         .unwrap();
@@ -252,14 +388,11 @@ async fn open_outbound_streams<const BUFFER_SIZE: usize>(
     {}
 }
 
-async fn connect(
-    a_transport: &mut Boxed<(PeerId, StreamMuxerBox)>,
-    b_transport: &mut Boxed<(PeerId, StreamMuxerBox)>,
+async fn connect<C: Send + 'static>(
+    a_transport: &mut Boxed<(PeerId, C)>,
+    b_transport: &mut Boxed<(PeerId, C)>,
     addr: Multiaddr,
-) -> (
-    (PeerId, Multiaddr, StreamMuxerBox),
-    (PeerId, StreamMuxerBox),
-) {
+) -> ((PeerId, Multiaddr, C), (PeerId, C)) {
     match futures::future::select(
         ListenUpgrade::new(a_transport),
         Dial::new(b_transport, addr),
@@ -293,13 +426,13 @@ async fn connect(
     }
 }
 
-struct ListenUpgrade<'a> {
-    listener: &'a mut Boxed<(PeerId, StreamMuxerBox)>,
-    listener_upgrade_task: Option<BoxFuture<'static, (PeerId, Multiaddr, StreamMuxerBox)>>,
+struct ListenUpgrade<'a, C> {
+    listener: &'a mut Boxed<(PeerId, C)>,
+    listener_upgrade_task: Option<BoxFuture<'static, (PeerId, Multiaddr, C)>>,
 }
 
-impl<'a> ListenUpgrade<'a> {
-    pub(crate) fn new(listener: &'a mut Boxed<(PeerId, StreamMuxerBox)>) -> Self {
+impl<'a, C> ListenUpgrade<'a, C> {
+    pub(crate) fn new(listener: &'a mut Boxed<(PeerId, C)>) -> Self {
         Self {
             listener,
             listener_upgrade_task: None,
@@ -307,13 +440,13 @@ impl<'a> ListenUpgrade<'a> {
     }
 }
 
-struct Dial<'a> {
-    dialer: &'a mut Boxed<(PeerId, StreamMuxerBox)>,
-    dial_task: BoxFuture<'static, (PeerId, StreamMuxerBox)>,
+struct Dial<'a, C> {
+    dialer: &'a mut Boxed<(PeerId, C)>,
+    dial_task: BoxFuture<'static, (PeerId, C)>,
 }
 
-impl<'a> Dial<'a> {
-    fn new(dialer: &'a mut Boxed<(PeerId, StreamMuxerBox)>, addr: Multiaddr) -> Self {
+impl<'a, C: Send + 'static> Dial<'a, C> {
+    fn new(dialer: &'a mut Boxed<(PeerId, C)>, addr: Multiaddr) -> Self {
         Self {
             dial_task: dialer.dial(addr).unwrap().map(|r| r.unwrap()).boxed(),
             dialer,
@@ -321,8 +454,8 @@ impl<'a> Dial<'a> {
     }
 }
 
-impl Future for Dial<'_> {
-    type Output = (PeerId, StreamMuxerBox);
+impl<C> Future for Dial<'_, C> {
+    type Output = (PeerId, C);
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         loop {
@@ -339,8 +472,8 @@ impl Future for Dial<'_> {
     }
 }
 
-impl Future for ListenUpgrade<'_> {
-    type Output = (PeerId, Multiaddr, StreamMuxerBox);
+impl<C: Send + 'static> Future for ListenUpgrade<'_, C> {
+    type Output = (PeerId, Multiaddr, C);
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         loop {
@@ -0,0 +1,151 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Permessage-deflate compression ([RFC 7692](https://datatracker.ietf.org/doc/html/rfc7692)).
+
+use soketto::{
+    base::Header,
+    connection::Mode,
+    extension::{deflate::Deflate, Extension, Param},
+    BoxedError, Storage,
+};
+
+/// Default minimum payload size to compress, in bytes.
+const DEFAULT_THRESHOLD: usize = 256;
+
+/// Permessage-deflate configuration.
+///
+/// When dialing, the extension is offered to the server with the configured window bits. When
+/// listening, it is accepted if offered by the client, using the window bits the client asked for.
+#[derive(Debug, Clone)]
+pub struct Config {
+    client_max_window_bits: u8,
+    server_max_window_bits: u8,
+    threshold: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            client_max_window_bits: 15,
+            server_max_window_bits: 15,
+            threshold: DEFAULT_THRESHOLD,
+        }
+    }
+}
+
+impl Config {
+    /// Create a new configuration with the maximum window size and the default threshold.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the max. window bits the client compresses with.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` is not within `9 ..= 15`.
+    pub fn set_client_max_window_bits(&mut self, bits: u8) -> &mut Self {
+        assert!(
+            (9..=15).contains(&bits),
+            "window bits must be within 9 ..= 15"
+        );
+        self.client_max_window_bits = bits;
+        self
+    }
+
+    /// Set the max. window bits the server is asked to compress with.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` is not within `9 ..= 15`.
+    pub fn set_server_max_window_bits(&mut self, bits: u8) -> &mut Self {
+        assert!(
+            (9..=15).contains(&bits),
+            "window bits must be within 9 ..= 15"
+        );
+        self.server_max_window_bits = bits;
+        self
+    }
+
+    /// Set the min. payload size, in bytes, for a frame to be compressed.
+    ///
+    /// Smaller frames are sent uncompressed as the deflate overhead outweighs the savings.
+    pub fn set_threshold(&mut self, threshold: usize) -> &mut Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Create the extension to negotiate on a new connection.
+    pub(crate) fn extension(&self, mode: Mode) -> Box<dyn Extension + Send> {
+        let mut inner = Deflate::new(mode);
+        if mode == Mode::Client {
+            inner.set_max_client_window_bits(self.client_max_window_bits);
+            inner.set_max_server_window_bits(self.server_max_window_bits);
+        }
+
+        Box::new(Threshold {
+            inner,
+            threshold: self.threshold,
+        })
+    }
+}
+
+/// Skips compression of frames smaller than the threshold, which RFC 7692 permits on a
+/// per-message basis.
+#[derive(Debug)]
+struct Threshold {
+    inner: Deflate,
+    threshold: usize,
+}
+
+impl Extension for Threshold {
+    fn is_enabled(&self) -> bool {
+        self.inner.is_enabled()
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn params(&self) -> &[Param<'_>] {
+        self.inner.params()
+    }
+
+    fn configure(&mut self, params: &[Param<'_>]) -> Result<(), BoxedError> {
+        self.inner.configure(params)
+    }
+
+    fn encode(&mut self, header: &mut Header, data: &mut Storage) -> Result<(), BoxedError> {
+        if data.as_ref().len() < self.threshold {
+            return Ok(());
+        }
+
+        self.inner.encode(header, data)
+    }
+
+    fn decode(&mut self, header: &mut Header, data: &mut Vec<u8>) -> Result<(), BoxedError> {
+        self.inner.decode(header, data)
+    }
+
+    fn reserved_bits(&self) -> (bool, bool, bool) {
+        self.inner.reserved_bits()
+    }
+}
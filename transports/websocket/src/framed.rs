@@ -18,7 +18,9 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use crate::{error::Error, tls};
+#[cfg(feature = "http2")]
+use crate::http2;
+use crate::{error::Error, proxy::Proxy, tls};
 use either::Either;
 use futures::{future::BoxFuture, prelude::*, ready, stream::BoxStream};
 use futures_rustls::{client, rustls, server};
@@ -32,6 +34,7 @@ use log::{debug, trace};
 use parking_lot::Mutex;
 use soketto::{
     connection::{self, CloseReason},
+    extension::Extension,
     handshake,
 };
 use std::{collections::HashMap, ops::DerefMut, sync::Arc};
@@ -41,6 +44,9 @@ use url::Url;
 /// Max. number of payload bytes of a single frame.
 const MAX_DATA_SIZE: usize = 256 * 1024 * 1024;
 
+/// Max. number of headers of an upgrade request, matching the limit of the handshake itself.
+const MAX_NUM_HEADERS: usize = 32;
+
 /// A Websocket transport whose output type is a [`Stream`] and [`Sink`] of
 /// frame payloads which does not implement [`AsyncRead`] or
 /// [`AsyncWrite`]. See [`crate::WsConfig`] if you require the latter.
@@ -50,6 +56,13 @@ pub struct WsConfig<T> {
     max_data_size: usize,
     tls_config: tls::Config,
     max_redirects: u8,
+    extensions: Extensions,
+    /// Extra HTTP headers sent with outbound upgrade requests.
+    headers: Vec<(String, String)>,
+    request_filter: Option<RequestFilter>,
+    proxy: Option<Proxy>,
+    #[cfg(feature = "http2")]
+    http2: Option<http2::Sessions>,
     /// Websocket protocol of the inner listener.
     ///
     /// This is the suffix of the address provided in `listen_on`.
@@ -68,6 +81,12 @@ where
             max_data_size: MAX_DATA_SIZE,
             tls_config: tls::Config::client(),
             max_redirects: 0,
+            extensions: Extensions::default(),
+            headers: Vec::new(),
+            request_filter: None,
+            proxy: None,
+            #[cfg(feature = "http2")]
+            http2: None,
             listener_protos: HashMap::new(),
         }
     }
@@ -99,6 +118,147 @@ where
         self.tls_config = c;
         self
     }
+
+    /// Add an HTTP header to send with outbound upgrade requests, e.g. `Authorization` or
+    /// `Origin`.
+    pub fn add_header(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Set a callback deciding whether to accept an inbound upgrade request.
+    ///
+    /// Requests for which the callback returns `Err(status_code)` are rejected with that HTTP
+    /// status code, e.g. 401 for a missing token or 403 for a disallowed origin.
+    pub fn set_request_filter<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&UpgradeRequest<'_>) -> Result<(), u16> + Send + Sync + 'static,
+    {
+        self.request_filter = Some(RequestFilter(Arc::new(f)));
+        self
+    }
+
+    /// Establish outbound connections through the given proxy.
+    ///
+    /// Failures to set up the tunnel are reported as [`Error::Handshake`].
+    pub fn set_proxy(&mut self, proxy: Proxy) -> &mut Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Offer HTTP/2 when dialing `/wss` addresses, running websockets over HTTP/2 streams
+    /// ([RFC 8441](https://datatracker.ietf.org/doc/html/rfc8441)) if the server supports it.
+    ///
+    /// Dials to the same host and port share a single TLS session. Listeners only accept
+    /// HTTP/1.1 upgrades.
+    #[cfg(feature = "http2")]
+    pub fn set_http2_config(&mut self, c: crate::http2::Config) -> &mut Self {
+        self.http2 = Some(http2::Sessions::new(c));
+        self
+    }
+
+    /// Enable permessage-deflate compression with the given configuration.
+    ///
+    /// Dialers offer the extension to the server, listeners accept it if offered.
+    #[cfg(feature = "deflate")]
+    pub fn set_deflate_config(&mut self, c: crate::deflate::Config) -> &mut Self {
+        self.extensions.deflate = Some(c);
+        self
+    }
+}
+
+/// The HTTP request of an inbound websocket upgrade.
+#[derive(Debug)]
+pub struct UpgradeRequest<'a> {
+    path: &'a str,
+    headers: &'a [httparse::Header<'a>],
+}
+
+impl<'a> UpgradeRequest<'a> {
+    /// The requested resource.
+    pub fn path(&self) -> &str {
+        self.path
+    }
+
+    /// The value of the first header with the given name, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&[u8]> {
+        self.headers
+            .iter()
+            .find(|h| h.name.eq_ignore_ascii_case(name))
+            .map(|h| h.value)
+    }
+
+    /// All headers of the request, in order.
+    pub fn headers(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.headers.iter().map(|h| (h.name, h.value))
+    }
+}
+
+type FilterFn = dyn Fn(&UpgradeRequest<'_>) -> Result<(), u16> + Send + Sync;
+
+#[derive(Clone)]
+struct RequestFilter(Arc<FilterFn>);
+
+impl fmt::Debug for RequestFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RequestFilter")
+    }
+}
+
+impl RequestFilter {
+    /// Parses the raw upgrade request and applies the filter to it.
+    fn check(&self, request: &[u8]) -> Result<Result<(), u16>, httparse::Error> {
+        let mut headers = [httparse::EMPTY_HEADER; MAX_NUM_HEADERS];
+        let mut parsed = httparse::Request::new(&mut headers);
+        parsed.parse(request)?;
+
+        Ok((self.0)(&UpgradeRequest {
+            path: parsed.path.unwrap_or("/"),
+            headers: parsed.headers,
+        }))
+    }
+}
+
+/// The websocket extensions to negotiate on new connections.
+#[derive(Debug, Clone, Default)]
+struct Extensions {
+    #[cfg(feature = "deflate")]
+    deflate: Option<crate::deflate::Config>,
+}
+
+impl Extensions {
+    #[cfg_attr(not(feature = "deflate"), allow(unused_variables))]
+    fn build(&self, mode: connection::Mode) -> Vec<Box<dyn Extension + Send>> {
+        #[cfg(feature = "deflate")]
+        if let Some(config) = &self.deflate {
+            return vec![config.extension(mode)];
+        }
+        Vec::new()
+    }
+}
+
+/// The configuration of a single dial, detached from the [`WsConfig`].
+struct DialConfig {
+    tls_config: tls::Config,
+    extensions: Extensions,
+    headers: Vec<(String, String)>,
+    proxy: Option<Proxy>,
+    #[cfg(feature = "http2")]
+    http2: Option<http2::Sessions>,
+}
+
+impl DialConfig {
+    /// Returns the TLS connector for `host_port`, offering HTTP/2 via ALPN if it may be used.
+    #[cfg_attr(not(feature = "http2"), allow(unused_variables))]
+    fn tls_connector(&self, host_port: &str) -> futures_rustls::TlsConnector {
+        #[cfg(feature = "http2")]
+        if matches!(&self.http2, Some(sessions) if sessions.offer_http2(host_port)) {
+            return self
+                .tls_config
+                .connector_with_alpn(&[http2::ALPN_H2, http2::ALPN_HTTP1]);
+        }
+        self.tls_config.connector()
+    }
 }
 
 type TlsOrPlain<T> = future::Either<future::Either<client::TlsStream<T>, server::TlsStream<T>>, T>;
@@ -233,14 +393,14 @@ where
                     .listener_protos
                     .get(&listener_id)
                     .expect("Protocol was inserted in Transport::listen_on.");
-                let use_tls = match proto {
-                    Protocol::Wss(_) => true,
-                    Protocol::Ws(_) => false,
+                let (use_tls, path) = match proto {
+                    Protocol::Wss(path) => (true, path.to_string()),
+                    Protocol::Ws(path) => (false, path.to_string()),
                     _ => unreachable!("Map contains only ws and wss protocols."),
                 };
                 local_addr.push(proto.clone());
                 send_back_addr.push(proto.clone());
-                let upgrade = self.map_upgrade(upgrade, send_back_addr.clone(), use_tls);
+                let upgrade = self.map_upgrade(upgrade, send_back_addr.clone(), use_tls, path);
                 TransportEvent::Incoming {
                     listener_id,
                     upgrade,
@@ -266,7 +426,7 @@ where
         addr: Multiaddr,
         role_override: Endpoint,
     ) -> Result<<Self as Transport>::Dial, TransportError<<Self as Transport>::Error>> {
-        let mut addr = match parse_ws_dial_addr(addr) {
+        let mut addr = match parse_ws_dial_addr(addr, self.tls_config.server_name.as_ref()) {
             Ok(addr) => addr,
             Err(Error::InvalidMultiaddr(a)) => {
                 return Err(TransportError::MultiaddrNotSupported(a))
@@ -278,21 +438,27 @@ where
         let mut remaining_redirects = self.max_redirects;
 
         let transport = self.transport.clone();
-        let tls_config = self.tls_config.clone();
         let max_redirects = self.max_redirects;
+        let config = DialConfig {
+            tls_config: self.tls_config.clone(),
+            extensions: self.extensions.clone(),
+            headers: self.headers.clone(),
+            proxy: self.proxy.clone(),
+            #[cfg(feature = "http2")]
+            http2: self.http2.clone(),
+        };
 
         let future = async move {
             loop {
-                match Self::dial_once(transport.clone(), addr, tls_config.clone(), role_override)
-                    .await
-                {
+                match Self::dial_once(transport.clone(), addr, &config, role_override).await {
                     Ok(Either::Left(redirect)) => {
                         if remaining_redirects == 0 {
                             debug!("Too many redirects (> {})", max_redirects);
                             return Err(Error::TooManyRedirects);
                         }
                         remaining_redirects -= 1;
-                        addr = parse_ws_dial_addr(location_to_multiaddr(&redirect)?)?
+                        // The server name override only applies to the dialed host.
+                        addr = parse_ws_dial_addr(location_to_multiaddr(&redirect)?, None)?
                     }
                     Ok(Either::Right(conn)) => return Ok(conn),
                     Err(e) => return Err(e),
@@ -307,48 +473,85 @@ where
     async fn dial_once(
         transport: Arc<Mutex<T>>,
         addr: WsAddress,
-        tls_config: tls::Config,
+        config: &DialConfig,
         role_override: Endpoint,
     ) -> Result<Either<String, Connection<T::Output>>, Error<T::Error>> {
         trace!("Dialing websocket address: {:?}", addr);
 
-        let dial = match role_override {
-            Endpoint::Dialer => transport.lock().dial(addr.tcp_addr),
-            Endpoint::Listener => transport.lock().dial_as_listener(addr.tcp_addr),
+        #[cfg(feature = "http2")]
+        if let (true, Some(sessions)) = (addr.use_tls, &config.http2) {
+            if let Some(sender) = sessions.get(&addr.host_port) {
+                trace!(
+                    "Opening websocket on HTTP/2 session with {}",
+                    addr.host_port
+                );
+                match http2::open(sender, &addr.host_port, &addr.path, &config.headers).await {
+                    Ok(stream) => return Ok(Either::Right(Self::http2_connection(stream))),
+                    // The session may have ended in the meantime.
+                    Err(http2::Error::Http(e)) => {
+                        debug!("HTTP/2 session with {} failed: {}", addr.host_port, e)
+                    }
+                    Err(http2::Error::Rejected(status_code)) => {
+                        let msg = format!("server rejected handshake; status code = {status_code}");
+                        return Err(Error::Handshake(msg.into()));
+                    }
+                }
+            }
         }
-        .map_err(|e| match e {
-            TransportError::MultiaddrNotSupported(a) => Error::InvalidMultiaddr(a),
-            TransportError::Other(e) => Error::Transport(e),
-        })?;
 
-        let stream = dial.map_err(Error::Transport).await?;
-        trace!("TCP connection to {} established.", addr.host_port);
+        let stream = Self::connect(&transport, &addr, config, role_override).await?;
 
-        let stream = if addr.use_tls {
-            // begin TLS session
-            let dns_name = addr
-                .dns_name
-                .expect("for use_tls we have checked that dns_name is some");
-            trace!("Starting TLS handshake with {:?}", dns_name);
-            let stream = tls_config
-                .client
-                .connect(dns_name.clone(), stream)
-                .map_err(|e| {
-                    debug!("TLS handshake with {:?} failed: {}", dns_name, e);
-                    Error::Tls(tls::Error::from(e))
-                })
-                .await?;
-
-            let stream: TlsOrPlain<_> = future::Either::Left(future::Either::Left(stream));
-            stream
-        } else {
-            // continue with plain stream
-            future::Either::Right(stream)
+        #[cfg(feature = "http2")]
+        let stream = match (stream, &config.http2) {
+            (future::Either::Left(future::Either::Left(stream)), Some(sessions))
+                if stream.get_ref().1.alpn_protocol() == Some(http2::ALPN_H2) =>
+            {
+                trace!(
+                    "Opening websocket on new HTTP/2 session with {}",
+                    addr.host_port
+                );
+                match sessions.handshake(&addr.host_port, stream).await {
+                    Ok(sender) => {
+                        let stream =
+                            http2::open(sender, &addr.host_port, &addr.path, &config.headers)
+                                .await
+                                .map_err(|e| match e {
+                                    http2::Error::Http(e) => Error::Handshake(Box::new(e)),
+                                    http2::Error::Rejected(status_code) => Error::Handshake(
+                                        format!(
+                                            "server rejected handshake; status code = {status_code}"
+                                        )
+                                        .into(),
+                                    ),
+                                })?;
+                        return Ok(Either::Right(Self::http2_connection(stream)));
+                    }
+                    // HTTP/2 is no longer offered to the server, thus it picks HTTP/1.1.
+                    Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+                        debug!("Falling back to HTTP/1.1 for {}: {}", addr.host_port, e);
+                        Self::connect(&transport, &addr, config, role_override).await?
+                    }
+                    Err(e) => return Err(Error::Handshake(Box::new(e))),
+                }
+            }
+            (stream, _) => stream,
         };
 
         trace!("Sending websocket handshake to {}", addr.host_port);
 
         let mut client = handshake::Client::new(stream, &addr.host_port, addr.path.as_ref());
+        for extension in config.extensions.build(connection::Mode::Client) {
+            client.add_extension(extension);
+        }
+        let headers = config
+            .headers
+            .iter()
+            .map(|(name, value)| handshake::client::Header {
+                name,
+                value: value.as_bytes(),
+            })
+            .collect::<Vec<_>>();
+        client.set_headers(&headers);
 
         match client
             .handshake()
@@ -376,15 +579,88 @@ where
         }
     }
 
+    /// Establishes the TCP connection to `addr`, via the proxy if any, and the TLS session on top
+    /// if `addr` requires it.
+    async fn connect(
+        transport: &Arc<Mutex<T>>,
+        addr: &WsAddress,
+        config: &DialConfig,
+        role_override: Endpoint,
+    ) -> Result<TlsOrPlain<T::Output>, Error<T::Error>> {
+        let tcp_addr = match &config.proxy {
+            Some(proxy) => proxy.addr().clone(),
+            None => addr.tcp_addr.clone(),
+        };
+        let dial = match role_override {
+            Endpoint::Dialer => transport.lock().dial(tcp_addr),
+            Endpoint::Listener => transport.lock().dial_as_listener(tcp_addr),
+        }
+        .map_err(|e| match e {
+            TransportError::MultiaddrNotSupported(a) => Error::InvalidMultiaddr(a),
+            TransportError::Other(e) => Error::Transport(e),
+        })?;
+
+        let mut stream = dial.map_err(Error::Transport).await?;
+
+        if let Some(proxy) = &config.proxy {
+            trace!(
+                "Connecting to {} via proxy {}",
+                addr.host_port,
+                proxy.addr()
+            );
+            proxy
+                .connect(&mut stream, &addr.host, addr.port)
+                .map_err(|e| {
+                    debug!("Proxy connection to {} failed: {}", addr.host_port, e);
+                    Error::Handshake(Box::new(e))
+                })
+                .await?;
+        }
+        trace!("TCP connection to {} established.", addr.host_port);
+
+        if !addr.use_tls {
+            // continue with plain stream
+            return Ok(future::Either::Right(stream));
+        }
+
+        // begin TLS session
+        let dns_name = addr
+            .dns_name
+            .clone()
+            .expect("for use_tls we have checked that dns_name is some");
+        trace!("Starting TLS handshake with {:?}", dns_name);
+        let stream = config
+            .tls_connector(&addr.host_port)
+            .connect(dns_name.clone(), stream)
+            .map_err(|e| {
+                debug!("TLS handshake with {:?} failed: {}", dns_name, e);
+                Error::Tls(tls::Error::from(e))
+            })
+            .await?;
+
+        Ok(future::Either::Left(future::Either::Left(stream)))
+    }
+
+    /// Wraps a websocket established on an HTTP/2 stream.
+    ///
+    /// Extensions are not negotiated on HTTP/2 streams.
+    #[cfg(feature = "http2")]
+    fn http2_connection(stream: http2::Stream) -> Connection<T::Output> {
+        Connection::new(connection::Builder::new(stream, connection::Mode::Client))
+    }
+
     fn map_upgrade(
         &self,
         upgrade: T::ListenerUpgrade,
         remote_addr: Multiaddr,
         use_tls: bool,
+        listen_path: String,
     ) -> <Self as Transport>::ListenerUpgrade {
         let remote_addr2 = remote_addr.clone(); // used for logging
         let tls_config = self.tls_config.clone();
         let max_size = self.max_data_size;
+        let extensions = self.extensions.build(connection::Mode::Server);
+        let request_filter = self.request_filter.clone();
 
         async move {
             let stream = upgrade.map_err(Error::Transport).await?;
@@ -420,15 +696,46 @@ where
             );
 
             let mut server = handshake::Server::new(stream);
+            for extension in extensions {
+                server.add_extension(extension);
+            }
 
-            let ws_key = {
+            let (ws_key, path_matches) = {
                 let request = server
                     .receive_request()
                     .map_err(|e| Error::Handshake(Box::new(e)))
                     .await?;
-                request.key()
+                (request.key(), serves_path(&listen_path, request.path()))
             };
 
+            let verdict = if !path_matches {
+                Err(404)
+            } else if let Some(filter) = request_filter {
+                let request = server.take_buffer();
+                let verdict = filter
+                    .check(&request)
+                    .map_err(|e| Error::Handshake(Box::new(e)))?;
+                server.set_buffer(request);
+                verdict
+            } else {
+                Ok(())
+            };
+
+            if let Err(status_code) = verdict {
+                debug!(
+                    "rejecting websocket handshake request from {} with status code {}",
+                    remote_addr2, status_code
+                );
+                let response = handshake::server::Response::Reject { status_code };
+                server
+                    .send_response(&response)
+                    .map_err(|e| Error::Handshake(Box::new(e)))
+                    .await?;
+
+                let msg = format!("rejected handshake request; status code = {status_code}");
+                return Err(Error::Handshake(msg.into()));
+            }
+
             trace!(
                 "accepting websocket handshake request from {}",
                 remote_addr2
@@ -457,9 +764,19 @@ where
     }
 }
 
+/// Returns whether a listener on `listen_path` serves upgrade requests for `request_path`.
+///
+/// Listeners on the root path serve all paths. Others only serve their exact path, regardless
+/// of any query string, so that a reverse proxy can route other paths on the same port elsewhere.
+fn serves_path(listen_path: &str, request_path: &str) -> bool {
+    listen_path == "/" || request_path.split('?').next() == Some(listen_path)
+}
+
 #[derive(Debug)]
 struct WsAddress {
     host_port: String,
+    host: String,
+    port: u16,
     path: String,
     dns_name: Option<rustls::ServerName>,
     use_tls: bool,
@@ -469,28 +786,35 @@ struct WsAddress {
 /// Tries to parse the given `Multiaddr` into a `WsAddress` used
 /// for dialing.
 ///
+/// The `server_name`, if any, is used for TLS in place of the DNS name
+/// of the address.
+///
 /// Fails if the given `Multiaddr` does not represent a TCP/IP-based
 /// websocket protocol stack.
-fn parse_ws_dial_addr<T>(addr: Multiaddr) -> Result<WsAddress, Error<T>> {
+fn parse_ws_dial_addr<T>(
+    addr: Multiaddr,
+    server_name: Option<&rustls::ServerName>,
+) -> Result<WsAddress, Error<T>> {
     // The encapsulating protocol must be based on TCP/IP, possibly via DNS.
     // We peek at it in order to learn the hostname and port to use for
     // the websocket handshake.
     let mut protocols = addr.iter();
     let mut ip = protocols.next();
     let mut tcp = protocols.next();
-    let (host_port, dns_name) = loop {
+    let (host, port, dns_name) = loop {
         match (ip, tcp) {
             (Some(Protocol::Ip4(ip)), Some(Protocol::Tcp(port))) => {
-                break (format!("{ip}:{port}"), None)
+                break (ip.to_string(), port, None)
             }
             (Some(Protocol::Ip6(ip)), Some(Protocol::Tcp(port))) => {
-                break (format!("{ip}:{port}"), None)
+                break (ip.to_string(), port, None)
             }
             (Some(Protocol::Dns(h)), Some(Protocol::Tcp(port)))
             | (Some(Protocol::Dns4(h)), Some(Protocol::Tcp(port)))
             | (Some(Protocol::Dns6(h)), Some(Protocol::Tcp(port)))
             | (Some(Protocol::Dnsaddr(h)), Some(Protocol::Tcp(port))) => {
-                break (format!("{}:{}", &h, port), Some(tls::dns_name_ref(&h)?))
+                let dns_name = tls::dns_name_ref(&h)?;
+                break (h.into_owned(), port, Some(dns_name));
             }
             (Some(_), Some(p)) => {
                 ip = Some(p);
@@ -499,6 +823,7 @@ fn parse_ws_dial_addr<T>(addr: Multiaddr) -> Result<WsAddress, Error<T>> {
             _ => return Err(Error::InvalidMultiaddr(addr)),
         }
     };
+    let dns_name = server_name.cloned().or(dns_name);
 
     // Now consume the `Ws` / `Wss` protocol from the end of the address,
     // preserving the trailing `P2p` protocol that identifies the remote,
@@ -528,7 +853,9 @@ fn parse_ws_dial_addr<T>(addr: Multiaddr) -> Result<WsAddress, Error<T>> {
     };
 
     Ok(WsAddress {
-        host_port,
+        host_port: format!("{host}:{port}"),
+        host,
+        port,
         dns_name,
         path,
         use_tls,
@@ -657,7 +984,10 @@ impl<T> Connection<T>
 where
     T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
 {
-    fn new(builder: connection::Builder<TlsOrPlain<T>>) -> Self {
+    fn new<S>(builder: connection::Builder<S>) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
         let (sender, receiver) = builder.finish();
         let sink = quicksink::make_sink(sender, |mut sender, action| async move {
             match action {
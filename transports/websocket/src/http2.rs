@@ -0,0 +1,322 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Websockets over HTTP/2 ([RFC 8441](https://datatracker.ietf.org/doc/html/rfc8441)).
+//!
+//! When dialing a `/wss` address, HTTP/2 is offered via ALPN. If the server picks it and supports
+//! the extended `CONNECT` method, the websocket runs on an HTTP/2 stream and later dials to the
+//! same host and port open further streams on the same TLS session. If the server does not pick
+//! HTTP/2, the websocket is upgraded from HTTP/1.1 on the same TLS session. If the server picks
+//! HTTP/2 without supporting the extended `CONNECT` method, the dial reconnects offering only
+//! HTTP/1.1, which is also done for all later dials to the same host and port.
+
+use bytes::Bytes;
+use futures::{future::BoxFuture, prelude::*, ready};
+use h2::{
+    client::{self, SendRequest},
+    RecvStream, SendStream,
+};
+use parking_lot::Mutex;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+use tokio_util::compat::FuturesAsyncReadCompatExt;
+
+/// The ALPN protocol identifier of HTTP/2.
+pub(crate) const ALPN_H2: &[u8] = b"h2";
+/// The ALPN protocol identifier of HTTP/1.1.
+pub(crate) const ALPN_HTTP1: &[u8] = b"http/1.1";
+
+type ExecutorFn = dyn Fn(BoxFuture<'static, ()>) + Send + Sync;
+
+/// HTTP/2 configuration.
+#[derive(Clone)]
+pub struct Config {
+    executor: Arc<ExecutorFn>,
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Config")
+    }
+}
+
+impl Config {
+    /// Create a new configuration.
+    ///
+    /// Each HTTP/2 session is driven by a background task, which is passed to `executor` to be
+    /// spawned on the runtime of choice.
+    pub fn new<F>(executor: F) -> Self
+    where
+        F: Fn(BoxFuture<'static, ()>) + Send + Sync + 'static,
+    {
+        Config {
+            executor: Arc::new(executor),
+        }
+    }
+}
+
+/// The established HTTP/2 sessions, by `host:port` of the remote.
+#[derive(Debug, Clone)]
+pub(crate) struct Sessions {
+    config: Config,
+    sessions: Arc<Mutex<HashMap<String, Session>>>,
+    /// The `host:port` of remotes which negotiated HTTP/2 without supporting websockets over it.
+    http1_only: Arc<Mutex<HashSet<String>>>,
+}
+
+#[derive(Debug)]
+struct Session {
+    id: u64,
+    sender: SendRequest<Bytes>,
+}
+
+impl Sessions {
+    pub(crate) fn new(config: Config) -> Self {
+        Sessions {
+            config,
+            sessions: Default::default(),
+            http1_only: Default::default(),
+        }
+    }
+
+    /// Returns whether HTTP/2 is offered when connecting to `host_port`.
+    pub(crate) fn offer_http2(&self, host_port: &str) -> bool {
+        !self.http1_only.lock().contains(host_port)
+    }
+
+    /// Returns the session established with `host_port`, if any.
+    pub(crate) fn get(&self, host_port: &str) -> Option<SendRequest<Bytes>> {
+        self.sessions
+            .lock()
+            .get(host_port)
+            .map(|session| session.sender.clone())
+    }
+
+    /// Performs the HTTP/2 handshake on a TLS stream which negotiated `h2`, and keeps the
+    /// session for later dials to `host_port`.
+    ///
+    /// Fails with [`io::ErrorKind::Unsupported`] if the server does not support websockets over
+    /// HTTP/2, in which case HTTP/2 is no longer offered to `host_port`.
+    pub(crate) async fn handshake<S>(
+        &self,
+        host_port: &str,
+        stream: S,
+    ) -> Result<SendRequest<Bytes>, io::Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (sender, mut connection) = client::handshake(stream.compat())
+            .await
+            .map_err(into_io_error)?;
+
+        // The server announces support for extended `CONNECT` in its initial settings, which
+        // precede the answer to a ping.
+        let mut ping_pong = connection.ping_pong().expect("called once");
+
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let sessions = self.sessions.clone();
+        let key = host_port.to_owned();
+        (self.config.executor)(
+            async move {
+                if let Err(e) = connection.await {
+                    log::debug!("HTTP/2 session with {} failed: {}", key, e);
+                }
+                let mut sessions = sessions.lock();
+                if sessions.get(&key).map(|s| s.id) == Some(id) {
+                    sessions.remove(&key);
+                }
+            }
+            .boxed(),
+        );
+
+        ping_pong
+            .ping(h2::Ping::opaque())
+            .await
+            .map_err(into_io_error)?;
+        let sender = sender.ready().await.map_err(into_io_error)?;
+        if !sender.is_extended_connect_protocol_enabled() {
+            self.http1_only.lock().insert(host_port.to_owned());
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "server does not support websockets over HTTP/2",
+            ));
+        }
+
+        self.sessions.lock().insert(
+            host_port.to_owned(),
+            Session {
+                id,
+                sender: sender.clone(),
+            },
+        );
+
+        Ok(sender)
+    }
+}
+
+/// Opens a websocket stream on an HTTP/2 session via an extended `CONNECT` request.
+pub(crate) async fn open(
+    sender: SendRequest<Bytes>,
+    host_port: &str,
+    path: &str,
+    headers: &[(String, String)],
+) -> Result<Stream, Error> {
+    let mut request = http::Request::connect(format!("https://{host_port}{path}"))
+        .header("sec-websocket-version", "13");
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    let mut request = request
+        .body(())
+        .map_err(|e| Error::Http(io::Error::new(io::ErrorKind::InvalidInput, e)))?;
+    request
+        .extensions_mut()
+        .insert(h2::ext::Protocol::from("websocket"));
+
+    let mut sender = sender
+        .ready()
+        .await
+        .map_err(|e| Error::Http(into_io_error(e)))?;
+    let (response, send) = sender
+        .send_request(request, false)
+        .map_err(|e| Error::Http(into_io_error(e)))?;
+    let response = response.await.map_err(|e| Error::Http(into_io_error(e)))?;
+
+    if !response.status().is_success() {
+        return Err(Error::Rejected(response.status().as_u16()));
+    }
+
+    Ok(Stream::new(send, response.into_body()))
+}
+
+/// Failure to open a websocket stream on an HTTP/2 session.
+#[derive(Debug)]
+pub(crate) enum Error {
+    Http(io::Error),
+    Rejected(u16),
+}
+
+fn into_io_error(e: h2::Error) -> io::Error {
+    if e.is_io() {
+        e.into_io().expect("is_io")
+    } else {
+        io::Error::new(io::ErrorKind::Other, e)
+    }
+}
+
+/// An HTTP/2 stream carrying a websocket.
+pub(crate) struct Stream {
+    send: SendStream<Bytes>,
+    recv: RecvStream,
+    read_buffer: Bytes,
+    closed: bool,
+}
+
+impl Stream {
+    pub(crate) fn new(send: SendStream<Bytes>, recv: RecvStream) -> Self {
+        Stream {
+            send,
+            recv,
+            read_buffer: Bytes::new(),
+            closed: false,
+        }
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        while self.read_buffer.is_empty() {
+            match ready!(self.recv.poll_data(cx)) {
+                Some(Ok(data)) => {
+                    self.recv
+                        .flow_control()
+                        .release_capacity(data.len())
+                        .map_err(into_io_error)?;
+                    self.read_buffer = data;
+                }
+                Some(Err(e)) => return Poll::Ready(Err(into_io_error(e))),
+                None => return Poll::Ready(Ok(0)),
+            }
+        }
+
+        let n = buf.len().min(self.read_buffer.len());
+        buf[..n].copy_from_slice(&self.read_buffer.split_to(n));
+
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        self.send.reserve_capacity(buf.len());
+        let capacity = loop {
+            match ready!(self.send.poll_capacity(cx)) {
+                Some(Ok(0)) => continue,
+                Some(Ok(capacity)) => break capacity,
+                Some(Err(e)) => return Poll::Ready(Err(into_io_error(e))),
+                None => return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+            }
+        };
+
+        let n = capacity.min(buf.len());
+        self.send
+            .send_data(Bytes::copy_from_slice(&buf[..n]), false)
+            .map_err(into_io_error)?;
+
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // Data is written out by the session's background task.
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if !self.closed {
+            self.closed = true;
+            self.send
+                .send_data(Bytes::new(), true)
+                .map_err(into_io_error)?;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
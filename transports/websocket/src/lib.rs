@@ -22,8 +22,13 @@
 
 #![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
 
+#[cfg(feature = "deflate")]
+pub mod deflate;
 pub mod error;
 pub mod framed;
+#[cfg(feature = "http2")]
+pub mod http2;
+pub mod proxy;
 pub mod tls;
 
 use error::Error;
@@ -54,7 +59,8 @@ use std::{
 ///
 /// # Dependencies
 ///
-/// This transport requires the `zlib` shared library to be installed on the system.
+/// With the `deflate` feature enabled, this transport requires the `zlib` shared library to be
+/// installed on the system.
 ///
 /// Future releases might lift this requirement, see <https://github.com/paritytech/soketto/issues/72>.
 ///
@@ -174,6 +180,53 @@ where
         self.transport.inner_mut().set_tls_config(c);
         self
     }
+
+    /// Add an HTTP header to send with outbound upgrade requests, e.g. `Authorization` or
+    /// `Origin`.
+    pub fn add_header(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.transport.inner_mut().add_header(name, value);
+        self
+    }
+
+    /// Set a callback deciding whether to accept an inbound upgrade request.
+    ///
+    /// Requests for which the callback returns `Err(status_code)` are rejected with that HTTP
+    /// status code, e.g. 401 for a missing token or 403 for a disallowed origin.
+    pub fn set_request_filter<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&framed::UpgradeRequest<'_>) -> Result<(), u16> + Send + Sync + 'static,
+    {
+        self.transport.inner_mut().set_request_filter(f);
+        self
+    }
+
+    /// Establish outbound connections through the given proxy.
+    ///
+    /// Failures to set up the tunnel are reported as [`Error::Handshake`].
+    pub fn set_proxy(&mut self, proxy: proxy::Proxy) -> &mut Self {
+        self.transport.inner_mut().set_proxy(proxy);
+        self
+    }
+
+    /// Offer HTTP/2 when dialing `/wss` addresses, running websockets over HTTP/2 streams
+    /// ([RFC 8441](https://datatracker.ietf.org/doc/html/rfc8441)) if the server supports it.
+    ///
+    /// Dials to the same host and port share a single TLS session. Listeners only accept
+    /// HTTP/1.1 upgrades.
+    #[cfg(feature = "http2")]
+    pub fn set_http2_config(&mut self, c: http2::Config) -> &mut Self {
+        self.transport.inner_mut().set_http2_config(c);
+        self
+    }
+
+    /// Enable permessage-deflate compression with the given configuration.
+    ///
+    /// Dialers offer the extension to the server, listeners accept it if offered.
+    #[cfg(feature = "deflate")]
+    pub fn set_deflate_config(&mut self, c: deflate::Config) -> &mut Self {
+        self.transport.inner_mut().set_deflate_config(c);
+        self
+    }
 }
 
 impl<T> Transport for WsConfig<T>
@@ -289,7 +342,7 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::WsConfig;
+    use super::{tls::rustls, WsConfig};
     use futures::prelude::*;
     use libp2p_core::{multiaddr::Protocol, transport::ListenerId, Multiaddr, Transport};
     use libp2p_identity::PeerId;
@@ -307,6 +360,387 @@ mod tests {
         futures::executor::block_on(connect(a))
     }
 
+    #[cfg(feature = "deflate")]
+    #[test]
+    fn dialer_connects_to_listener_with_deflate() {
+        let a = "/ip4/127.0.0.1/tcp/0/ws".parse().unwrap();
+        futures::executor::block_on(transfer_with(a, || {
+            let mut ws_config = new_ws_config();
+            ws_config.set_deflate_config(super::deflate::Config::new());
+            ws_config
+        }))
+    }
+
+    #[test]
+    fn listener_filters_requests() {
+        futures::executor::block_on(async {
+            let mut listener = new_ws_config();
+            listener.set_request_filter(|request| match request.header("authorization") {
+                Some(b"Bearer secret") => Ok(()),
+                _ => Err(401),
+            });
+            let mut listener = listener.boxed();
+            listener
+                .listen_on(
+                    ListenerId::next(),
+                    "/ip4/127.0.0.1/tcp/0/ws".parse().unwrap(),
+                )
+                .unwrap();
+            let addr = listener.next().await.unwrap().into_new_address().unwrap();
+
+            for (token, accepted) in [("Bearer secret", true), ("Bearer wrong", false)] {
+                let mut dialer = new_ws_config();
+                dialer.add_header("Authorization", token);
+                let outbound = dialer.boxed().dial(addr.clone()).unwrap();
+
+                let inbound = async {
+                    let (upgrade, _) = listener
+                        .select_next_some()
+                        .map(|ev| ev.into_incoming())
+                        .await
+                        .unwrap();
+                    upgrade.await
+                };
+
+                let (inbound, outbound) = futures::join!(inbound, outbound);
+                assert_eq!(inbound.is_ok(), accepted);
+                assert_eq!(outbound.is_ok(), accepted);
+            }
+        })
+    }
+
+    #[test]
+    fn dialer_overrides_server_name() {
+        futures::executor::block_on(async {
+            let cert = rcgen::generate_simple_self_signed(vec!["example.com".into()]).unwrap();
+            let key = super::tls::PrivateKey::new(cert.serialize_private_key_der());
+            let cert = cert.serialize_der().unwrap();
+
+            let mut listener = new_ws_config();
+            listener.set_tls_config(
+                super::tls::Config::new(key, vec![super::tls::Certificate::new(cert.clone())])
+                    .unwrap(),
+            );
+            let mut listener = listener.boxed();
+            listener
+                .listen_on(
+                    ListenerId::next(),
+                    "/ip4/127.0.0.1/tcp/0/wss".parse().unwrap(),
+                )
+                .unwrap();
+            let addr = listener.next().await.unwrap().into_new_address().unwrap();
+
+            let mut roots = rustls::RootCertStore::empty();
+            roots.add(&rustls::Certificate(cert)).unwrap();
+            let client = rustls::ClientConfig::builder()
+                .with_safe_defaults()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            let mut tls_config = super::tls::Config::from_rustls(client, None);
+
+            // Without a DNS name in the address, there is nothing to verify the certificate with.
+            assert!(new_ws_config().boxed().dial(addr.clone()).is_err());
+
+            tls_config.set_server_name("example.com").unwrap();
+            let mut dialer = new_ws_config();
+            dialer.set_tls_config(tls_config);
+            let outbound = dialer.boxed().dial(addr).unwrap();
+
+            let inbound = async {
+                let (upgrade, _) = listener
+                    .select_next_some()
+                    .map(|ev| ev.into_incoming())
+                    .await
+                    .unwrap();
+                upgrade.await
+            };
+
+            let (inbound, outbound) = futures::join!(inbound, outbound);
+            assert!(inbound.is_ok());
+            assert!(outbound.is_ok());
+        })
+    }
+
+    #[test]
+    fn dialer_connects_through_http_proxy() {
+        futures::executor::block_on(async {
+            let proxy = async_std::net::TcpListener::bind("127.0.0.1:0")
+                .await
+                .unwrap();
+            let proxy_addr = proxy.local_addr().unwrap();
+
+            // A minimal HTTP proxy, tunneling a single connection.
+            async_std::task::spawn(async move {
+                let (mut client, _) = proxy.accept().await.unwrap();
+                let mut request = Vec::new();
+                while !request.ends_with(b"\r\n\r\n") {
+                    let mut byte = [0];
+                    client.read_exact(&mut byte).await.unwrap();
+                    request.push(byte[0]);
+                }
+                let request = String::from_utf8(request).unwrap();
+                let target = request
+                    .strip_prefix("CONNECT ")
+                    .and_then(|r| r.split(' ').next())
+                    .unwrap();
+                let server = async_std::net::TcpStream::connect(target).await.unwrap();
+                client.write_all(b"HTTP/1.1 200 OK\r\n\r\n").await.unwrap();
+
+                let (client_read, client_write) = (&client, &client);
+                let (server_read, server_write) = (&server, &server);
+                let _ = futures::future::select(
+                    futures::io::copy(client_read, &mut &*server_write),
+                    futures::io::copy(server_read, &mut &*client_write),
+                )
+                .await;
+            });
+
+            let a = "/ip4/127.0.0.1/tcp/0/ws".parse().unwrap();
+            transfer_with(a, || {
+                let mut ws_config = new_ws_config();
+                ws_config.set_proxy(super::proxy::Proxy::http(
+                    Multiaddr::empty()
+                        .with(proxy_addr.ip().into())
+                        .with(Protocol::Tcp(proxy_addr.port())),
+                ));
+                ws_config
+            })
+            .await
+        })
+    }
+
+    #[test]
+    fn listener_serves_only_its_path() {
+        futures::executor::block_on(async {
+            let mut listener = new_ws_config().boxed();
+            listener
+                .listen_on(
+                    ListenerId::next(),
+                    "/ip4/127.0.0.1/tcp/0/x-parity-ws/%2Fp2p%2Fws"
+                        .parse()
+                        .unwrap(),
+                )
+                .unwrap();
+            let addr = listener.next().await.unwrap().into_new_address().unwrap();
+            assert_eq!(addr.iter().last(), Some(Protocol::Ws("/p2p/ws".into())));
+
+            let mut other_path = addr.clone();
+            other_path.pop();
+            other_path.push(Protocol::Ws("/other".into()));
+
+            for (addr, accepted) in [(addr, true), (other_path, false)] {
+                let outbound = new_ws_config().boxed().dial(addr).unwrap();
+
+                let inbound = async {
+                    let (upgrade, _) = listener
+                        .select_next_some()
+                        .map(|ev| ev.into_incoming())
+                        .await
+                        .unwrap();
+                    upgrade.await
+                };
+
+                let (inbound, outbound) = futures::join!(inbound, outbound);
+                assert_eq!(inbound.is_ok(), accepted);
+                assert_eq!(outbound.is_ok(), accepted);
+            }
+        })
+    }
+
+    #[cfg(feature = "http2")]
+    #[test]
+    fn dialer_shares_http2_session() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+        use tokio_util::compat::FuturesAsyncReadCompatExt;
+
+        futures::executor::block_on(async {
+            let (acceptor, tls_config) = self_signed_tls(vec![b"h2".to_vec()]);
+
+            let listener = async_std::net::TcpListener::bind("127.0.0.1:0")
+                .await
+                .unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let num_connections = Arc::new(AtomicUsize::new(0));
+
+            // An HTTP/2 server echoing everything received on websockets.
+            let counter = num_connections.clone();
+            async_std::task::spawn(async move {
+                loop {
+                    let (tcp, _) = listener.accept().await.unwrap();
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    let tls = acceptor.accept(tcp).await.unwrap();
+                    let mut connection = h2::server::Builder::new()
+                        .enable_connect_protocol()
+                        .handshake::<_, bytes::Bytes>(tls.compat())
+                        .await
+                        .unwrap();
+                    async_std::task::spawn(async move {
+                        while let Some(request) = connection.accept().await {
+                            let (request, mut respond) = request.unwrap();
+                            assert_eq!(request.method(), http::Method::CONNECT);
+                            assert_eq!(
+                                request
+                                    .extensions()
+                                    .get::<h2::ext::Protocol>()
+                                    .map(|p| p.as_str()),
+                                Some("websocket")
+                            );
+                            let send = respond
+                                .send_response(http::Response::new(()), false)
+                                .unwrap();
+                            let stream = super::http2::Stream::new(send, request.into_body());
+                            async_std::task::spawn(async move {
+                                let (mut sender, mut receiver) = soketto::connection::Builder::new(
+                                    stream,
+                                    soketto::connection::Mode::Server,
+                                )
+                                .finish();
+                                let mut data = Vec::new();
+                                while receiver.receive_data(&mut data).await.is_ok() {
+                                    sender.send_binary_mut(&mut data).await.unwrap();
+                                    sender.flush().await.unwrap();
+                                    data.clear();
+                                }
+                            });
+                        }
+                    });
+                }
+            });
+
+            let mut dialer = new_ws_config();
+            dialer.set_tls_config(tls_config);
+            dialer.set_http2_config(super::http2::Config::new(|future| {
+                async_std::task::spawn(future);
+            }));
+            let mut dialer = dialer.boxed();
+
+            let addr: Multiaddr = format!("/ip4/127.0.0.1/tcp/{port}/wss").parse().unwrap();
+            let mut conns = Vec::new();
+            for i in 0..3u8 {
+                let mut conn = dialer.dial(addr.clone()).unwrap().await.unwrap();
+                conn.write_all(&[i; 4096]).await.unwrap();
+                conn.flush().await.unwrap();
+                conns.push(conn);
+            }
+            for (i, conn) in conns.iter_mut().enumerate() {
+                let mut buf = vec![0; 4096];
+                conn.read_exact(&mut buf).await.unwrap();
+                assert_eq!(buf, vec![i as u8; 4096]);
+            }
+
+            assert_eq!(num_connections.load(Ordering::SeqCst), 1);
+        })
+    }
+
+    #[cfg(feature = "http2")]
+    #[test]
+    fn dialer_falls_back_to_http1_without_extended_connect() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+        use tokio_util::compat::FuturesAsyncReadCompatExt;
+
+        futures::executor::block_on(async {
+            let (acceptor, tls_config) =
+                self_signed_tls(vec![b"h2".to_vec(), b"http/1.1".to_vec()]);
+
+            let listener = async_std::net::TcpListener::bind("127.0.0.1:0")
+                .await
+                .unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let num_connections = Arc::new(AtomicUsize::new(0));
+
+            // A server preferring HTTP/2 but only supporting websockets over HTTP/1.1, echoing
+            // everything received on them.
+            let counter = num_connections.clone();
+            async_std::task::spawn(async move {
+                loop {
+                    let (tcp, _) = listener.accept().await.unwrap();
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    let tls = acceptor.accept(tcp).await.unwrap();
+                    if tls.get_ref().1.alpn_protocol() == Some(b"h2") {
+                        let mut connection = h2::server::handshake(tls.compat()).await.unwrap();
+                        async_std::task::spawn(async move {
+                            while let Some(Ok(_)) = connection.accept().await {}
+                        });
+                        continue;
+                    }
+                    async_std::task::spawn(async move {
+                        let mut server = soketto::handshake::Server::new(tls);
+                        let key = server.receive_request().await.unwrap().key();
+                        server
+                            .send_response(&soketto::handshake::server::Response::Accept {
+                                key,
+                                protocol: None,
+                            })
+                            .await
+                            .unwrap();
+                        let (mut sender, mut receiver) = server.into_builder().finish();
+                        let mut data = Vec::new();
+                        while receiver.receive_data(&mut data).await.is_ok() {
+                            sender.send_binary_mut(&mut data).await.unwrap();
+                            sender.flush().await.unwrap();
+                            data.clear();
+                        }
+                    });
+                }
+            });
+
+            let mut dialer = new_ws_config();
+            dialer.set_tls_config(tls_config);
+            dialer.set_http2_config(super::http2::Config::new(|future| {
+                async_std::task::spawn(future);
+            }));
+            let mut dialer = dialer.boxed();
+
+            let addr: Multiaddr = format!("/ip4/127.0.0.1/tcp/{port}/wss").parse().unwrap();
+            for (i, expected_connections) in [(0u8, 2), (1, 3)] {
+                let mut conn = dialer.dial(addr.clone()).unwrap().await.unwrap();
+                conn.write_all(&[i; 4096]).await.unwrap();
+                conn.flush().await.unwrap();
+                let mut buf = vec![0; 4096];
+                conn.read_exact(&mut buf).await.unwrap();
+                assert_eq!(buf, vec![i; 4096]);
+
+                // Only the first dial tries HTTP/2 before reconnecting.
+                assert_eq!(num_connections.load(Ordering::SeqCst), expected_connections);
+            }
+        })
+    }
+
+    /// Returns an acceptor with a self-signed certificate for `example.com` offering the given
+    /// ALPN protocols, and a client configuration trusting it.
+    #[cfg(feature = "http2")]
+    fn self_signed_tls(
+        alpn_protocols: Vec<Vec<u8>>,
+    ) -> (futures_rustls::TlsAcceptor, super::tls::Config) {
+        let cert = rcgen::generate_simple_self_signed(vec!["example.com".into()]).unwrap();
+        let key = rustls::PrivateKey(cert.serialize_private_key_der());
+        let cert = rustls::Certificate(cert.serialize_der().unwrap());
+        let mut server_config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.clone()], key)
+            .unwrap();
+        server_config.alpn_protocols = alpn_protocols;
+        let acceptor = futures_rustls::TlsAcceptor::from(std::sync::Arc::new(server_config));
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(&cert).unwrap();
+        let client = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let mut tls_config = super::tls::Config::from_rustls(client, None);
+        tls_config.set_server_name("example.com").unwrap();
+
+        (acceptor, tls_config)
+    }
+
     fn new_ws_config() -> WsConfig<tcp::async_io::Transport> {
         WsConfig::new(tcp::async_io::Transport::new(tcp::Config::default()))
     }
@@ -344,4 +778,55 @@ mod tests {
         let (a, b) = futures::join!(inbound, outbound);
         a.and(b).unwrap();
     }
+
+    /// Connects a dialer to a listener, both built with `new_ws_config`, and sends data
+    /// from the dialer to the listener.
+    async fn transfer_with(
+        listen_addr: Multiaddr,
+        new_ws_config: impl Fn() -> WsConfig<tcp::async_io::Transport>,
+    ) {
+        let mut ws_config = new_ws_config().boxed();
+        ws_config
+            .listen_on(ListenerId::next(), listen_addr)
+            .expect("listener");
+
+        let addr = ws_config
+            .next()
+            .await
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+
+        assert_eq!(Some(Protocol::Ws("/".into())), addr.iter().nth(2));
+        assert_ne!(Some(Protocol::Tcp(0)), addr.iter().nth(1));
+
+        let inbound = async move {
+            let (conn, _addr) = ws_config
+                .select_next_some()
+                .map(|ev| ev.into_incoming())
+                .await
+                .unwrap();
+            let mut conn = conn.await.unwrap();
+
+            let mut buf = vec![0; 4096];
+            conn.read_exact(&mut buf).await.unwrap();
+            buf
+        };
+
+        let outbound = async {
+            let mut conn = new_ws_config()
+                .boxed()
+                .dial(addr.with(Protocol::P2p(PeerId::random())))
+                .unwrap()
+                .await
+                .unwrap();
+
+            conn.write_all(&[42; 4096]).await.unwrap();
+            conn.flush().await.unwrap();
+            conn
+        };
+
+        let (received, _conn) = futures::join!(inbound, outbound);
+        assert_eq!(received, vec![42; 4096]);
+    }
 }
@@ -0,0 +1,182 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Dialing through HTTP (`CONNECT`) and SOCKS5 ([RFC 1928](https://datatracker.ietf.org/doc/html/rfc1928)) proxies.
+
+use futures::prelude::*;
+use libp2p_core::Multiaddr;
+use std::io;
+
+/// A proxy to establish outbound connections through.
+///
+/// The proxy address is dialed with the inner transport. The remote host is resolved by the
+/// proxy, so DNS names of dialed addresses are never resolved locally.
+#[derive(Debug, Clone)]
+pub struct Proxy {
+    kind: Kind,
+    addr: Multiaddr,
+    credentials: Option<(String, String)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Http,
+    Socks5,
+}
+
+impl Proxy {
+    /// An HTTP proxy, tunneling connections via `CONNECT` requests.
+    pub fn http(addr: Multiaddr) -> Self {
+        Proxy {
+            kind: Kind::Http,
+            addr,
+            credentials: None,
+        }
+    }
+
+    /// A SOCKS5 proxy.
+    pub fn socks5(addr: Multiaddr) -> Self {
+        Proxy {
+            kind: Kind::Socks5,
+            addr,
+            credentials: None,
+        }
+    }
+
+    /// Authenticate with the given username and password.
+    ///
+    /// HTTP proxies receive them via basic authentication, SOCKS5 proxies via username/password
+    /// authentication ([RFC 1929](https://datatracker.ietf.org/doc/html/rfc1929)).
+    pub fn with_credentials(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.credentials = Some((username.into(), password.into()));
+        self
+    }
+
+    /// The address of the proxy.
+    pub fn addr(&self) -> &Multiaddr {
+        &self.addr
+    }
+
+    /// Asks the proxy, connected to via `stream`, to tunnel the stream to `host:port`.
+    pub(crate) async fn connect<S>(&self, stream: &mut S, host: &str, port: u16) -> io::Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let credentials = self
+            .credentials
+            .as_ref()
+            .map(|(username, password)| (username.as_str(), password.as_str()));
+        match self.kind {
+            Kind::Http => libp2p_proxy::http::connect(stream, host, port, credentials).await,
+            Kind::Socks5 => libp2p_proxy::socks5::connect(stream, host, port, credentials).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::net::{TcpListener, TcpStream};
+
+    /// Runs `proxy` against a fake proxy server, which answers `reply` and expects `request`.
+    async fn run(
+        proxy: Proxy,
+        host: &str,
+        port: u16,
+        request: &[u8],
+        reply: &[u8],
+    ) -> io::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = async {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream.write_all(reply).await.unwrap();
+            let mut received = vec![0; request.len()];
+            stream.read_exact(&mut received).await.unwrap();
+            assert_eq!(received, request);
+            stream
+        };
+        let client = async {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            proxy.connect(&mut stream, host, port).await
+        };
+
+        futures::join!(server, client).1
+    }
+
+    fn proxy_addr() -> Multiaddr {
+        "/ip4/127.0.0.1/tcp/1080".parse().unwrap()
+    }
+
+    #[async_std::test]
+    async fn http_connect() {
+        let proxy = Proxy::http(proxy_addr()).with_credentials("user", "pass");
+        let request = b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\
+            Proxy-Authorization: Basic dXNlcjpwYXNz\r\n\r\n";
+
+        run(
+            proxy.clone(),
+            "example.com",
+            443,
+            request,
+            b"HTTP/1.1 200 OK\r\n\r\n",
+        )
+        .await
+        .unwrap();
+
+        let err = run(
+            proxy,
+            "example.com",
+            443,
+            request,
+            b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n",
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+    }
+
+    #[async_std::test]
+    async fn socks5_connect_with_credentials() {
+        let request = [
+            &[5, 1, 2][..],
+            &[1, 4][..],
+            b"user",
+            &[4][..],
+            b"pass",
+            &[5, 1, 0, 1, 127, 0, 0, 1, 0, 80][..],
+        ]
+        .concat();
+        let reply = [
+            &[5, 2][..],
+            &[1, 0][..],
+            &[5, 0, 0, 1, 0, 0, 0, 0, 0, 0][..],
+        ]
+        .concat();
+        let proxy = Proxy::socks5(proxy_addr()).with_credentials("user", "pass");
+
+        run(proxy, "127.0.0.1", 80, &request, &reply).await.unwrap();
+    }
+}
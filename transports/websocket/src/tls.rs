@@ -18,15 +18,20 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use futures_rustls::{rustls, TlsAcceptor, TlsConnector};
+use futures_rustls::{TlsAcceptor, TlsConnector};
 use std::convert::TryFrom;
 use std::{fmt, io, sync::Arc};
 
+pub use futures_rustls::rustls;
+
 /// TLS configuration.
 #[derive(Clone)]
 pub struct Config {
-    pub(crate) client: TlsConnector,
+    client: Arc<rustls::ClientConfig>,
     pub(crate) server: Option<TlsAcceptor>,
+    /// Name sent via SNI and verified against the server certificate, if it is not to be
+    /// taken from the dialed address.
+    pub(crate) server_name: Option<rustls::ServerName>,
 }
 
 impl fmt::Debug for Config {
@@ -75,11 +80,47 @@ impl Config {
             .with_root_certificates(client_root_store())
             .with_no_client_auth();
         Config {
-            client: Arc::new(client).into(),
+            client: Arc::new(client),
             server: None,
+            server_name: None,
+        }
+    }
+
+    /// Create a configuration from existing rustls client and server configurations.
+    ///
+    /// This allows for custom root stores, client certificates or certificate verifiers.
+    pub fn from_rustls(client: rustls::ClientConfig, server: Option<rustls::ServerConfig>) -> Self {
+        Config {
+            client: Arc::new(client),
+            server: server.map(|s| Arc::new(s).into()),
+            server_name: None,
         }
     }
 
+    /// The connector for outbound TLS sessions.
+    pub(crate) fn connector(&self) -> TlsConnector {
+        self.client.clone().into()
+    }
+
+    /// The connector for outbound TLS sessions offering the given ALPN protocols.
+    #[cfg(feature = "http2")]
+    pub(crate) fn connector_with_alpn(&self, protocols: &[&[u8]]) -> TlsConnector {
+        let mut client = (*self.client).clone();
+        client.alpn_protocols = protocols.iter().map(|p| p.to_vec()).collect();
+        Arc::new(client).into()
+    }
+
+    /// Override the server name used for SNI and certificate verification when dialing.
+    ///
+    /// By default the DNS name of the dialed address is used. Setting a name also allows
+    /// dialing `/wss` addresses without a DNS name, e.g. a TLS-terminating proxy by IP.
+    /// The name only applies to the dialed address, redirects to other locations are verified
+    /// against their own DNS names.
+    pub fn set_server_name(&mut self, name: &str) -> Result<&mut Self, Error> {
+        self.server_name = Some(dns_name_ref(name)?);
+        Ok(self)
+    }
+
     /// Create a new TLS configuration builder.
     pub fn builder() -> Builder {
         Builder {
@@ -140,8 +181,9 @@ impl Builder {
             .with_no_client_auth();
 
         Config {
-            client: Arc::new(client).into(),
+            client: Arc::new(client),
             server: self.server.map(|s| Arc::new(s).into()),
+            server_name: None,
         }
     }
 }
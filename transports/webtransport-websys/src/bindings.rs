@@ -38,6 +38,29 @@ extern "C" {
 
     #[wasm_bindgen (method, structural, js_class = "WebTransport", js_name = createBidirectionalStream)]
     pub fn create_bidirectional_stream(this: &WebTransport) -> Promise;
+
+    #[wasm_bindgen(method, structural, js_class = "WebTransport", js_name = getStats)]
+    pub fn get_stats(this: &WebTransport) -> Promise;
+
+    #[wasm_bindgen(structural, method, getter, js_class = "WebTransport", js_name = datagrams)]
+    pub fn datagrams(this: &WebTransport) -> WebTransportDatagramDuplexStream;
+}
+
+// WebTransportDatagramDuplexStream bindings
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(extends = Object, js_name = WebTransportDatagramDuplexStream, typescript_type = "WebTransportDatagramDuplexStream")]
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub type WebTransportDatagramDuplexStream;
+
+    #[wasm_bindgen(structural, method, getter, js_class = "WebTransportDatagramDuplexStream", js_name = readable)]
+    pub fn readable(this: &WebTransportDatagramDuplexStream) -> ReadableStream;
+
+    #[wasm_bindgen(structural, method, getter, js_class = "WebTransportDatagramDuplexStream", js_name = writable)]
+    pub fn writable(this: &WebTransportDatagramDuplexStream) -> WritableStream;
+
+    #[wasm_bindgen(structural, method, getter, js_class = "WebTransportDatagramDuplexStream", js_name = maxDatagramSize)]
+    pub fn max_datagram_size(this: &WebTransportDatagramDuplexStream) -> u32;
 }
 
 // WebTransportBidirectionalStream bindings
@@ -7,6 +7,7 @@ use send_wrapper::SendWrapper;
 use std::collections::HashSet;
 use std::future::poll_fn;
 use std::pin::Pin;
+use std::rc::Rc;
 use std::task::{ready, Context, Poll};
 use wasm_bindgen_futures::JsFuture;
 use web_sys::ReadableStreamDefaultReader;
@@ -14,8 +15,9 @@ use web_sys::ReadableStreamDefaultReader;
 use crate::bindings::{WebTransport, WebTransportBidirectionalStream};
 use crate::endpoint::Endpoint;
 use crate::fused_js_promise::FusedJsPromise;
+use crate::stream::OpenStreams;
 use crate::utils::{detach_promise, parse_reader_response, to_js_type};
-use crate::{Error, Stream};
+use crate::{Datagrams, Error, StatsHandle, Stream};
 
 /// An opened WebTransport connection.
 #[derive(Debug)]
@@ -25,17 +27,27 @@ pub struct Connection {
     inner: SendWrapper<ConnectionInner>,
 }
 
+/// Limits applied to the streams of a connection.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct StreamLimits {
+    pub(crate) max_concurrent_streams: Option<usize>,
+    pub(crate) send_buffer_size: usize,
+}
+
 #[derive(Debug)]
 struct ConnectionInner {
     session: WebTransport,
     create_stream_promise: FusedJsPromise,
     incoming_stream_promise: FusedJsPromise,
     incoming_streams_reader: ReadableStreamDefaultReader,
+    limits: StreamLimits,
+    open_streams: Rc<OpenStreams>,
+    datagrams_taken: bool,
     closed: bool,
 }
 
 impl Connection {
-    pub(crate) fn new(endpoint: &Endpoint) -> Result<Self, Error> {
+    pub(crate) fn new(endpoint: &Endpoint, limits: StreamLimits) -> Result<Self, Error> {
         let url = endpoint.url();
 
         let session = if endpoint.certhashes.is_empty() {
@@ -57,11 +69,33 @@ impl Connection {
                 create_stream_promise: FusedJsPromise::new(),
                 incoming_stream_promise: FusedJsPromise::new(),
                 incoming_streams_reader,
+                limits,
+                open_streams: Default::default(),
+                datagrams_taken: false,
                 closed: false,
             }),
         })
     }
 
+    /// Takes the [`Datagrams`] of the session, for unreliable messaging alongside streams.
+    ///
+    /// Datagrams can only be taken once per connection, e.g. when mapping the output of the
+    /// [`Transport`](crate::Transport) before handing the connection to the swarm.
+    pub fn datagrams(&mut self) -> Result<Datagrams, Error> {
+        if self.inner.datagrams_taken {
+            return Err(Error::JsError("datagrams already taken".to_string()));
+        }
+        let datagrams = Datagrams::new(self.inner.session.datagrams())?;
+        self.inner.datagrams_taken = true;
+
+        Ok(datagrams)
+    }
+
+    /// Returns a handle to query the [`ConnectionStats`](crate::ConnectionStats) of the session.
+    pub fn stats_handle(&self) -> StatsHandle {
+        StatsHandle::new(self.inner.session.clone())
+    }
+
     pub(crate) async fn authenticate(
         &mut self,
         keypair: &Keypair,
@@ -113,11 +147,28 @@ impl ConnectionInner {
         Ok(peer_id)
     }
 
+    /// Whether the connection has as many open streams as allowed.
+    fn at_stream_limit(&self) -> bool {
+        self.limits
+            .max_concurrent_streams
+            .is_some_and(|max| self.open_streams.count() >= max)
+    }
+
     /// Initiates and polls a promise from `create_bidirectional_stream`.
+    ///
+    /// Waits for a stream to be closed if the connection is at its stream limit.
     fn poll_create_bidirectional_stream(
         &mut self,
         cx: &mut Context,
     ) -> Poll<Result<Stream, Error>> {
+        if !self.create_stream_promise.is_active() && self.at_stream_limit() {
+            self.open_streams.register(cx.waker());
+            // A stream may have been closed before the waker was registered.
+            if self.at_stream_limit() {
+                return Poll::Pending;
+            }
+        }
+
         // Create bidirectional stream
         let val = ready!(self
             .create_stream_promise
@@ -126,31 +177,56 @@ impl ConnectionInner {
         .map_err(Error::from_js_value)?;
 
         let bidi_stream = to_js_type::<WebTransportBidirectionalStream>(val)?;
-        let stream = Stream::new(bidi_stream)?;
+        let stream = self.new_stream(bidi_stream)?;
 
         Poll::Ready(Ok(stream))
     }
 
     /// Polls for incoming stream from `incoming_bidirectional_streams` reader.
+    ///
+    /// Streams exceeding the stream limit are rejected.
     fn poll_incoming_bidirectional_streams(
         &mut self,
         cx: &mut Context,
     ) -> Poll<Result<Stream, Error>> {
-        // Read the next incoming stream from the JS channel
-        let val = ready!(self
-            .incoming_stream_promise
-            .maybe_init(|| self.incoming_streams_reader.read())
-            .poll_unpin(cx))
-        .map_err(Error::from_js_value)?;
+        loop {
+            // Read the next incoming stream from the JS channel
+            let val = ready!(self
+                .incoming_stream_promise
+                .maybe_init(|| self.incoming_streams_reader.read())
+                .poll_unpin(cx))
+            .map_err(Error::from_js_value)?;
 
-        let val = parse_reader_response(&val)
-            .map_err(Error::from_js_value)?
-            .ok_or_else(|| Error::JsError("incoming_bidirectional_streams closed".to_string()))?;
+            let val = parse_reader_response(&val)
+                .map_err(Error::from_js_value)?
+                .ok_or_else(|| {
+                    Error::JsError("incoming_bidirectional_streams closed".to_string())
+                })?;
 
-        let bidi_stream = to_js_type::<WebTransportBidirectionalStream>(val)?;
-        let stream = Stream::new(bidi_stream)?;
+            let bidi_stream = to_js_type::<WebTransportBidirectionalStream>(val)?;
 
-        Poll::Ready(Ok(stream))
+            if self.at_stream_limit() {
+                log::debug!(
+                    "Rejecting inbound stream, {} streams are open",
+                    self.open_streams.count()
+                );
+                detach_promise(bidi_stream.readable().cancel());
+                detach_promise(bidi_stream.writable().abort());
+                continue;
+            }
+
+            let stream = self.new_stream(bidi_stream)?;
+
+            return Poll::Ready(Ok(stream));
+        }
+    }
+
+    fn new_stream(&self, bidi_stream: WebTransportBidirectionalStream) -> Result<Stream, Error> {
+        Stream::new(
+            bidi_stream,
+            self.limits.send_buffer_size,
+            self.open_streams.clone(),
+        )
     }
 
     /// Closes the session.
@@ -0,0 +1,118 @@
+use futures::{FutureExt, Stream};
+use js_sys::Uint8Array;
+use send_wrapper::SendWrapper;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use web_sys::{ReadableStreamDefaultReader, WritableStreamDefaultWriter};
+
+use crate::bindings::WebTransportDatagramDuplexStream;
+use crate::fused_js_promise::FusedJsPromise;
+use crate::utils::{detach_promise, parse_reader_response, to_js_type};
+use crate::Error;
+
+/// Unreliable, unordered datagrams sent and received on a WebTransport session.
+///
+/// Datagrams are delivered at most once. They may be dropped when the network or the
+/// browser's queues are congested, so applications must tolerate loss.
+///
+/// Received datagrams are yielded by the [`Stream`] implementation, which ends when the
+/// session is closed. Dropping [`Datagrams`] stops receiving datagrams on the session.
+#[derive(Debug)]
+pub struct Datagrams {
+    // Swarm needs all types to be Send. WASM is single-threaded
+    // and it is safe to use SendWrapper.
+    inner: SendWrapper<DatagramsInner>,
+}
+
+#[derive(Debug)]
+struct DatagramsInner {
+    duplex: WebTransportDatagramDuplexStream,
+    reader: ReadableStreamDefaultReader,
+    reader_read_promise: FusedJsPromise,
+    writer: WritableStreamDefaultWriter,
+}
+
+impl Datagrams {
+    pub(crate) fn new(duplex: WebTransportDatagramDuplexStream) -> Result<Self, Error> {
+        let reader = to_js_type::<ReadableStreamDefaultReader>(duplex.readable().get_reader())?;
+        let writer = duplex
+            .writable()
+            .get_writer()
+            .map_err(Error::from_js_value)?;
+
+        Ok(Datagrams {
+            inner: SendWrapper::new(DatagramsInner {
+                duplex,
+                reader,
+                reader_read_promise: FusedJsPromise::new(),
+                writer,
+            }),
+        })
+    }
+
+    /// The maximum size of a datagram that can be sent, which depends on the path MTU.
+    pub fn max_datagram_size(&self) -> usize {
+        self.inner.duplex.max_datagram_size() as usize
+    }
+
+    /// Queues a datagram for sending.
+    ///
+    /// Fails if the datagram exceeds [`Datagrams::max_datagram_size`] or the session is closed.
+    /// A queued datagram may still be dropped before it is sent.
+    pub fn send(&mut self, data: &[u8]) -> Result<(), Error> {
+        let max_size = self.max_datagram_size();
+        if data.len() > max_size {
+            return Err(Error::DatagramTooLarge {
+                size: data.len(),
+                max_size,
+            });
+        }
+
+        let writer = &self.inner.writer;
+        // The desired size is null once the stream errored, e.g. because the session was closed.
+        if writer
+            .desired_size()
+            .map_err(Error::from_js_value)?
+            .is_none()
+        {
+            return Err(Error::JsError("datagrams closed".to_string()));
+        }
+
+        let chunk = Uint8Array::new_with_length(data.len() as u32);
+        chunk.copy_from(data);
+        detach_promise(writer.write_with_chunk(&chunk));
+
+        Ok(())
+    }
+}
+
+impl Stream for Datagrams {
+    type Item = Result<Vec<u8>, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let inner = &mut *self.inner;
+        let val = match ready!(inner
+            .reader_read_promise
+            .maybe_init(|| inner.reader.read())
+            .poll_unpin(cx))
+        {
+            Ok(val) => val,
+            Err(e) => return Poll::Ready(Some(Err(Error::from_js_value(e)))),
+        };
+
+        let datagram = parse_reader_response(&val)
+            .map_err(Error::from_js_value)
+            .map(|val| val.map(|val| Uint8Array::from(val).to_vec()))
+            .transpose();
+
+        Poll::Ready(datagram)
+    }
+}
+
+impl Drop for DatagramsInner {
+    fn drop(&mut self) {
+        // Cancel any ongoing reads.
+        detach_promise(self.reader.cancel());
+        self.writer.release_lock();
+    }
+}
@@ -18,6 +18,12 @@ pub enum Error {
 
     #[error("Unknown remote peer ID")]
     UnknownRemotePeerId,
+
+    #[error("Session handshake timed out")]
+    HandshakeTimedOut,
+
+    #[error("Datagram of {size} bytes exceeds the maximum of {max_size} bytes")]
+    DatagramTooLarge { size: usize, max_size: usize },
 }
 
 impl Error {
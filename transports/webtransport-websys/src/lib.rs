@@ -2,14 +2,22 @@
 
 mod bindings;
 mod connection;
+mod datagrams;
 mod endpoint;
 mod error;
 mod fused_js_promise;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod stats;
 mod stream;
 mod transport;
 mod utils;
 
 pub use self::connection::Connection;
+pub use self::datagrams::Datagrams;
 pub use self::error::Error;
+#[cfg(feature = "metrics")]
+pub use self::metrics::{Metrics, Recorder};
+pub use self::stats::{ConnectionStats, StatsHandle};
 pub use self::stream::Stream;
 pub use self::transport::{Config, Transport};
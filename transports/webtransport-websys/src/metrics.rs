@@ -0,0 +1,195 @@
+use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
+use prometheus_client::registry::{Registry, Unit};
+
+use crate::ConnectionStats;
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct DirectionLabels {
+    direction: Direction,
+}
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelValue, Debug)]
+enum Direction {
+    Inbound,
+    Outbound,
+}
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct DroppedLabels {
+    reason: Reason,
+}
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelValue, Debug)]
+enum Reason {
+    DroppedIncoming,
+    ExpiredOutgoing,
+    LostOutgoing,
+}
+
+/// Prometheus metrics fed from [`ConnectionStats`] of sessions.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    rtt: Histogram,
+    bytes: Family<DirectionLabels, Counter>,
+    packets_lost: Counter,
+    datagrams_dropped: Family<DroppedLabels, Counter>,
+}
+
+impl Metrics {
+    /// Registers the metrics under the `webtransport` prefix.
+    pub fn new(registry: &mut Registry) -> Self {
+        let sub_registry = registry.sub_registry_with_prefix("webtransport");
+
+        let rtt = Histogram::new(exponential_buckets(0.001, 2.0, 12));
+        sub_registry.register_with_unit(
+            "rtt",
+            "Smoothed round-trip time of sessions",
+            Unit::Seconds,
+            rtt.clone(),
+        );
+
+        let bytes = Family::default();
+        sub_registry.register_with_unit(
+            "transferred",
+            "Bytes transferred on sessions",
+            Unit::Bytes,
+            bytes.clone(),
+        );
+
+        let packets_lost = Counter::default();
+        sub_registry.register(
+            "packets_lost",
+            "Packets declared lost on sessions",
+            packets_lost.clone(),
+        );
+
+        let datagrams_dropped = Family::default();
+        sub_registry.register(
+            "datagrams_dropped",
+            "Datagrams dropped on sessions",
+            datagrams_dropped.clone(),
+        );
+
+        Self {
+            rtt,
+            bytes,
+            packets_lost,
+            datagrams_dropped,
+        }
+    }
+
+    /// Creates a recorder for the statistics of a single session.
+    pub fn recorder(&self) -> Recorder {
+        Recorder {
+            metrics: self.clone(),
+            last: ConnectionStats::default(),
+        }
+    }
+}
+
+/// Records the [`ConnectionStats`] of a single session into [`Metrics`].
+///
+/// Statistics are cumulative per session, so the recorder only adds what changed since the
+/// previously recorded statistics.
+#[derive(Debug)]
+pub struct Recorder {
+    metrics: Metrics,
+    last: ConnectionStats,
+}
+
+impl Recorder {
+    /// Records freshly queried statistics of the session.
+    pub fn record(&mut self, stats: &ConnectionStats) {
+        let metrics = &self.metrics;
+        let last = &self.last;
+
+        if let Some(rtt) = stats.smoothed_rtt {
+            metrics.rtt.observe(rtt.as_secs_f64());
+        }
+        metrics
+            .bytes
+            .get_or_create(&DirectionLabels {
+                direction: Direction::Outbound,
+            })
+            .inc_by(delta(last.bytes_sent, stats.bytes_sent));
+        metrics
+            .bytes
+            .get_or_create(&DirectionLabels {
+                direction: Direction::Inbound,
+            })
+            .inc_by(delta(last.bytes_received, stats.bytes_received));
+        metrics
+            .packets_lost
+            .inc_by(delta(last.packets_lost, stats.packets_lost));
+        for (reason, last, current) in [
+            (
+                Reason::DroppedIncoming,
+                last.datagrams_dropped_incoming,
+                stats.datagrams_dropped_incoming,
+            ),
+            (
+                Reason::ExpiredOutgoing,
+                last.datagrams_expired_outgoing,
+                stats.datagrams_expired_outgoing,
+            ),
+            (
+                Reason::LostOutgoing,
+                last.datagrams_lost_outgoing,
+                stats.datagrams_lost_outgoing,
+            ),
+        ] {
+            metrics
+                .datagrams_dropped
+                .get_or_create(&DroppedLabels { reason })
+                .inc_by(delta(last, current));
+        }
+
+        self.last = stats.clone();
+    }
+}
+
+/// The increase of a cumulative statistic.
+fn delta(last: Option<u64>, current: Option<u64>) -> u64 {
+    current
+        .unwrap_or_default()
+        .saturating_sub(last.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn records_increase_of_cumulative_stats() {
+        let mut registry = Registry::default();
+        let metrics = Metrics::new(&mut registry);
+        let mut recorder = metrics.recorder();
+
+        let mut stats = ConnectionStats {
+            bytes_sent: Some(100),
+            smoothed_rtt: Some(Duration::from_millis(20)),
+            ..Default::default()
+        };
+        recorder.record(&stats);
+        stats.bytes_sent = Some(150);
+        stats.datagrams_lost_outgoing = Some(2);
+        recorder.record(&stats);
+
+        let outbound = DirectionLabels {
+            direction: Direction::Outbound,
+        };
+        assert_eq!(metrics.bytes.get_or_create(&outbound).get(), 150);
+        let lost = DroppedLabels {
+            reason: Reason::LostOutgoing,
+        };
+        assert_eq!(metrics.datagrams_dropped.get_or_create(&lost).get(), 2);
+
+        // Statistics of another session are added up.
+        metrics.recorder().record(&stats);
+        assert_eq!(metrics.bytes.get_or_create(&outbound).get(), 300);
+    }
+}
@@ -0,0 +1,99 @@
+use js_sys::Reflect;
+use send_wrapper::SendWrapper;
+use std::time::Duration;
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::JsFuture;
+
+use crate::bindings::WebTransport;
+use crate::Error;
+
+/// Statistics of a WebTransport session, as reported by `WebTransport.getStats()`.
+///
+/// Browsers do not report all statistics, missing ones are `None`. Counters are cumulative
+/// over the lifetime of the session.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[non_exhaustive]
+pub struct ConnectionStats {
+    pub bytes_sent: Option<u64>,
+    pub bytes_received: Option<u64>,
+    pub packets_sent: Option<u64>,
+    pub packets_received: Option<u64>,
+    pub packets_lost: Option<u64>,
+    /// Smoothed round-trip time estimate.
+    pub smoothed_rtt: Option<Duration>,
+    /// Variation of the round-trip time samples.
+    pub rtt_variation: Option<Duration>,
+    /// Minimum round-trip time observed.
+    pub min_rtt: Option<Duration>,
+    /// Incoming datagrams dropped because the application did not read them in time.
+    pub datagrams_dropped_incoming: Option<u64>,
+    /// Outgoing datagrams dropped because they were queued for too long.
+    pub datagrams_expired_outgoing: Option<u64>,
+    /// Outgoing datagrams declared lost.
+    pub datagrams_lost_outgoing: Option<u64>,
+}
+
+impl ConnectionStats {
+    fn from_js_value(stats: &JsValue) -> Self {
+        let datagrams = Reflect::get(stats, &JsValue::from_str("datagrams")).ok();
+        let datagram_count = |name| datagrams.as_ref().and_then(|d| count(d, name));
+
+        ConnectionStats {
+            bytes_sent: count(stats, "bytesSent"),
+            bytes_received: count(stats, "bytesReceived"),
+            packets_sent: count(stats, "packetsSent"),
+            packets_received: count(stats, "packetsReceived"),
+            packets_lost: count(stats, "packetsLost"),
+            smoothed_rtt: duration(stats, "smoothedRtt"),
+            rtt_variation: duration(stats, "rttVariation"),
+            min_rtt: duration(stats, "minRtt"),
+            datagrams_dropped_incoming: datagram_count("droppedIncoming"),
+            datagrams_expired_outgoing: datagram_count("expiredOutgoing"),
+            datagrams_lost_outgoing: datagram_count("lostOutgoing"),
+        }
+    }
+}
+
+/// Handle to query the [`ConnectionStats`] of a session.
+///
+/// It can be kept after handing the [`Connection`](crate::Connection) to the swarm.
+#[derive(Debug, Clone)]
+pub struct StatsHandle {
+    // Swarm needs all types to be Send. WASM is single-threaded
+    // and it is safe to use SendWrapper.
+    session: SendWrapper<WebTransport>,
+}
+
+impl StatsHandle {
+    pub(crate) fn new(session: WebTransport) -> Self {
+        StatsHandle {
+            session: SendWrapper::new(session),
+        }
+    }
+
+    /// Queries the current statistics of the session.
+    pub async fn stats(&self) -> Result<ConnectionStats, Error> {
+        let stats = SendWrapper::new(JsFuture::from(self.session.get_stats()))
+            .await
+            .map_err(Error::from_js_value)?;
+
+        Ok(ConnectionStats::from_js_value(&stats))
+    }
+}
+
+fn count(object: &JsValue, name: &str) -> Option<u64> {
+    Reflect::get(object, &JsValue::from_str(name))
+        .ok()?
+        .as_f64()
+        .filter(|n| *n >= 0.0)
+        .map(|n| n as u64)
+}
+
+/// Reads a duration, given in milliseconds.
+fn duration(object: &JsValue, name: &str) -> Option<Duration> {
+    Reflect::get(object, &JsValue::from_str(name))
+        .ok()?
+        .as_f64()
+        .filter(|ms| ms.is_finite() && *ms >= 0.0)
+        .map(|ms| Duration::from_secs_f64(ms / 1000.0))
+}
@@ -1,8 +1,11 @@
+use futures::task::AtomicWaker;
 use futures::{AsyncRead, AsyncWrite, FutureExt};
 use js_sys::Uint8Array;
 use send_wrapper::SendWrapper;
+use std::cell::Cell;
 use std::io;
 use std::pin::Pin;
+use std::rc::Rc;
 use std::task::ready;
 use std::task::{Context, Poll};
 use web_sys::{ReadableStreamDefaultReader, WritableStreamDefaultWriter};
@@ -29,6 +32,27 @@ struct StreamInner {
     writer_state: StreamState,
     writer_ready_promise: FusedJsPromise,
     writer_closed_promise: FusedJsPromise,
+    /// Maximum number of bytes passed to the writer at once.
+    send_buffer_size: usize,
+    open_streams: Rc<OpenStreams>,
+}
+
+/// The number of open streams of a connection.
+#[derive(Debug, Default)]
+pub(crate) struct OpenStreams {
+    count: Cell<usize>,
+    /// Woken when a stream is closed.
+    waker: AtomicWaker,
+}
+
+impl OpenStreams {
+    pub(crate) fn count(&self) -> usize {
+        self.count.get()
+    }
+
+    pub(crate) fn register(&self, waker: &std::task::Waker) {
+        self.waker.register(waker);
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -39,12 +63,17 @@ enum StreamState {
 }
 
 impl Stream {
-    pub(crate) fn new(bidi_stream: WebTransportBidirectionalStream) -> Result<Self, Error> {
+    pub(crate) fn new(
+        bidi_stream: WebTransportBidirectionalStream,
+        send_buffer_size: usize,
+        open_streams: Rc<OpenStreams>,
+    ) -> Result<Self, Error> {
         let recv_stream = bidi_stream.readable();
         let send_stream = bidi_stream.writable();
 
         let reader = to_js_type::<ReadableStreamDefaultReader>(recv_stream.get_reader())?;
         let writer = send_stream.get_writer().map_err(Error::from_js_value)?;
+        open_streams.count.set(open_streams.count() + 1);
 
         Ok(Stream {
             inner: SendWrapper::new(StreamInner {
@@ -55,6 +84,8 @@ impl Stream {
                 writer_state: StreamState::Open,
                 writer_ready_promise: FusedJsPromise::new(),
                 writer_closed_promise: FusedJsPromise::new(),
+                send_buffer_size,
+                open_streams,
             }),
         })
     }
@@ -134,6 +165,9 @@ impl StreamInner {
     fn poll_write(&mut self, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
         ready!(self.poll_writer_ready(cx))?;
 
+        // The writer applies backpressure once a write is queued, so limiting the size of
+        // writes bounds the data buffered by the stream.
+        let buf = &buf[..buf.len().min(self.send_buffer_size)];
         let len = buf.len() as u32;
         let data = Uint8Array::new_with_length(len);
         data.copy_from(buf);
@@ -196,6 +230,11 @@ impl Drop for StreamInner {
 
         // Cancel any ongoing reads.
         detach_promise(self.reader.cancel());
+
+        self.open_streams
+            .count
+            .set(self.open_streams.count().saturating_sub(1));
+        self.open_streams.waker.wake();
     }
 }
 
@@ -1,24 +1,48 @@
-use futures::future::FutureExt;
+use futures::future::{self, Either, FutureExt, LocalBoxFuture};
+use futures_timer::Delay;
 use libp2p_core::muxing::StreamMuxerBox;
 use libp2p_core::transport::{Boxed, ListenerId, Transport as _, TransportError, TransportEvent};
 use libp2p_identity::{Keypair, PeerId};
 use multiaddr::Multiaddr;
+use multihash::Multihash;
+use send_wrapper::SendWrapper;
+use std::collections::{HashMap, HashSet};
 use std::future::Future;
+use std::num::NonZeroUsize;
 use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
+use std::time::Duration;
 
+use crate::connection::StreamLimits;
 use crate::endpoint::Endpoint;
 use crate::Connection;
 use crate::Error;
 
+type RefreshFn = dyn Fn(&Multiaddr) -> LocalBoxFuture<'static, Option<Vec<Multihash<64>>>>;
+
 /// Config for the [`Transport`].
 pub struct Config {
     keypair: Keypair,
+    handshake_timeout: Duration,
+    limits: StreamLimits,
+    // Swarm needs all types to be Send. WASM is single-threaded
+    // and it is safe to use SendWrapper.
+    certhash_refresh: Option<SendWrapper<Rc<RefreshFn>>>,
 }
 
 /// A WebTransport [`Transport`](libp2p_core::Transport) that works with `web-sys`.
 pub struct Transport {
     config: Config,
+    /// Certhashes learned through the refresh callback, by host and port of the server.
+    refreshed_certhashes: Arc<Mutex<HashMap<(String, u16), RefreshedCerthashes>>>,
+}
+
+/// The current certhashes of a server, replacing the stale ones of its dialed address.
+struct RefreshedCerthashes {
+    stale: HashSet<Multihash<64>>,
+    current: HashSet<Multihash<64>>,
 }
 
 impl Config {
@@ -26,14 +50,63 @@ impl Config {
     pub fn new(keypair: &Keypair) -> Self {
         Config {
             keypair: keypair.to_owned(),
+            handshake_timeout: Duration::from_secs(10),
+            limits: StreamLimits {
+                max_concurrent_streams: None,
+                send_buffer_size: 64 * 1024,
+            },
+            certhash_refresh: None,
         }
     }
+
+    /// Sets the timeout for establishing and authenticating a session.
+    ///
+    /// Dials exceeding it fail with [`Error::HandshakeTimedOut`]. Defaults to 10 seconds.
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    /// Limits the number of concurrently open streams per connection. Unlimited by default.
+    ///
+    /// At the limit, opening an outbound stream waits until another stream is closed, and
+    /// inbound streams are rejected.
+    pub fn with_max_concurrent_streams(mut self, max: NonZeroUsize) -> Self {
+        self.limits.max_concurrent_streams = Some(max.get());
+        self
+    }
+
+    /// Sets the maximum number of bytes a stream passes to the browser with a single write.
+    ///
+    /// Since a stream has at most one write queued before applying backpressure, this bounds
+    /// the data buffered per stream. Defaults to 64 KiB.
+    pub fn with_stream_send_buffer_size(mut self, size: NonZeroUsize) -> Self {
+        self.limits.send_buffer_size = size.get();
+        self
+    }
+
+    /// Sets a callback fetching the current certhashes of a server, e.g. from an HTTP endpoint
+    /// of the application.
+    ///
+    /// The callback is invoked with the dialed address when dialing an address with certhashes
+    /// fails. If it returns different certhashes, the dial is retried with them. Later dials of
+    /// the same stale address use them right away.
+    pub fn with_certhash_refresh<F>(mut self, refresh: F) -> Self
+    where
+        F: Fn(&Multiaddr) -> LocalBoxFuture<'static, Option<Vec<Multihash<64>>>> + 'static,
+    {
+        self.certhash_refresh = Some(SendWrapper::new(Rc::new(refresh)));
+        self
+    }
 }
 
 impl Transport {
     /// Constructs a new `Transport` with the given [`Config`].
     pub fn new(config: Config) -> Transport {
-        Transport { config }
+        Transport {
+            config,
+            refreshed_certhashes: Default::default(),
+        }
     }
 
     /// Wraps `Transport` in [`Boxed`] and makes it ready to be consumed by
@@ -63,22 +136,58 @@ impl libp2p_core::Transport for Transport {
     }
 
     fn dial(&mut self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
-        let endpoint = Endpoint::from_multiaddr(&addr).map_err(|e| match e {
-            e @ Error::InvalidMultiaddr(_) => {
+        let mut endpoint = match Endpoint::from_multiaddr(&addr) {
+            Ok(endpoint) => endpoint,
+            Err(e @ Error::InvalidMultiaddr(_)) => {
                 log::warn!("{}", e);
-                TransportError::MultiaddrNotSupported(addr)
+                return Err(TransportError::MultiaddrNotSupported(addr));
+            }
+            Err(e) => return Err(TransportError::Other(e)),
+        };
+
+        let key = (endpoint.host.clone(), endpoint.port);
+        let dialed_certhashes = endpoint.certhashes.clone();
+        if let Some(refreshed) = self.refreshed_certhashes.lock().unwrap().get(&key) {
+            if refreshed.stale == endpoint.certhashes {
+                endpoint.certhashes = refreshed.current.clone();
             }
-            e => TransportError::Other(e),
-        })?;
+        }
 
-        let mut session = Connection::new(&endpoint).map_err(TransportError::Other)?;
         let keypair = self.config.keypair.clone();
+        let limits = self.config.limits;
+        let handshake_timeout = self.config.handshake_timeout;
+        let refresh = self.config.certhash_refresh.clone();
+        let refreshed_certhashes = self.refreshed_certhashes.clone();
 
         Ok(async move {
-            let peer_id = session
-                .authenticate(&keypair, endpoint.remote_peer, endpoint.certhashes)
-                .await?;
-            Ok((peer_id, session))
+            let error = match connect(&endpoint, &keypair, limits, handshake_timeout).await {
+                Ok(output) => return Ok(output),
+                Err(e) => e,
+            };
+
+            let refresh = match refresh {
+                Some(refresh) if !endpoint.certhashes.is_empty() => refresh,
+                _ => return Err(error),
+            };
+            let current = match SendWrapper::new(refresh(&addr)).await {
+                Some(current) => current.into_iter().collect::<HashSet<_>>(),
+                None => return Err(error),
+            };
+            if current.is_empty() || current == endpoint.certhashes {
+                return Err(error);
+            }
+
+            log::debug!("Retrying dial to {addr} with refreshed certhashes after: {error}");
+            refreshed_certhashes.lock().unwrap().insert(
+                key,
+                RefreshedCerthashes {
+                    stale: dialed_certhashes,
+                    current: current.clone(),
+                },
+            );
+            endpoint.certhashes = current;
+
+            connect(&endpoint, &keypair, limits, handshake_timeout).await
         }
         .boxed())
     }
@@ -101,3 +210,21 @@ impl libp2p_core::Transport for Transport {
         None
     }
 }
+
+/// Establishes a session with the endpoint and authenticates the server.
+async fn connect(
+    endpoint: &Endpoint,
+    keypair: &Keypair,
+    limits: StreamLimits,
+    timeout: Duration,
+) -> Result<(PeerId, Connection), Error> {
+    let mut session = Connection::new(endpoint, limits)?;
+    let authenticate =
+        session.authenticate(keypair, endpoint.remote_peer, endpoint.certhashes.clone());
+    let peer_id = match future::select(Box::pin(authenticate), Delay::new(timeout)).await {
+        Either::Left((result, _)) => result?,
+        Either::Right(_) => return Err(Error::HandshakeTimedOut),
+    };
+
+    Ok((peer_id, session))
+}
@@ -0,0 +1,203 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Self-signed certificates, verified by browsers via their hashes.
+//!
+//! Browsers only accept certificates by hash if they are valid for at most 14 days. The listener
+//! therefore serves a new certificate every 7 days. Certificate `i` is valid from
+//! `epoch + i * 7 days` for 14 days and is served during the second half of the validity of
+//! certificate `i - 1`. Listen addresses advertise the hashes of the served certificate and its
+//! successor, so dialers keep working across one rotation.
+
+use crate::Error;
+use multihash::Multihash;
+use parking_lot::Mutex;
+use rustls::{
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+};
+use std::{
+    collections::HashSet,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+/// The multihash code of SHA-256.
+const MULTIHASH_SHA256_CODE: u64 = 0x12;
+
+/// Validity of a certificate, the maximum accepted by browsers.
+const CERT_VALIDITY: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+/// Interval in which a new certificate is served.
+const ROTATION_INTERVAL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// Allowance for clocks of dialers running behind.
+const CLOCK_SKEW: Duration = Duration::from_secs(60 * 60);
+
+/// The ALPN protocol identifier of HTTP/3.
+const ALPN_H3: &[u8] = b"h3";
+
+/// The certificates of a listener.
+pub(crate) struct Certificates {
+    /// Start of the validity of the first certificate.
+    epoch: SystemTime,
+    /// Index of the served certificate.
+    index: u32,
+    previous: Option<Multihash<64>>,
+    current: Generated,
+    next: Generated,
+    resolver: Arc<Resolver>,
+}
+
+struct Generated {
+    key: Arc<CertifiedKey>,
+    hash: Multihash<64>,
+}
+
+impl Certificates {
+    pub(crate) fn new() -> Result<Self, Error> {
+        let epoch = SystemTime::now() - CLOCK_SKEW;
+        let current = generate(epoch)?;
+        let next = generate(epoch + ROTATION_INTERVAL)?;
+        let resolver = Arc::new(Resolver(Mutex::new(current.key.clone())));
+
+        Ok(Certificates {
+            epoch,
+            index: 0,
+            previous: None,
+            current,
+            next,
+            resolver,
+        })
+    }
+
+    /// The TLS configuration serving the current certificate.
+    pub(crate) fn server_config(&self) -> Arc<rustls::ServerConfig> {
+        let mut config = rustls::ServerConfig::builder()
+            .with_safe_default_cipher_suites()
+            .with_safe_default_kx_groups()
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .expect("TLS 1.3 is supported")
+            .with_no_client_auth()
+            .with_cert_resolver(self.resolver.clone());
+        config.alpn_protocols = vec![ALPN_H3.to_vec()];
+
+        Arc::new(config)
+    }
+
+    /// The hashes to advertise in listen addresses.
+    pub(crate) fn advertised(&self) -> [Multihash<64>; 2] {
+        [self.current.hash, self.next.hash]
+    }
+
+    /// The hashes dialers may expect, i.e. those advertised now or before the last rotation.
+    pub(crate) fn accepted(&self) -> HashSet<Multihash<64>> {
+        self.previous.into_iter().chain(self.advertised()).collect()
+    }
+
+    /// The time at which [`Certificates::rotate`] is due.
+    pub(crate) fn next_rotation(&self) -> SystemTime {
+        self.epoch + ROTATION_INTERVAL * (self.index + 1) + CLOCK_SKEW
+    }
+
+    /// Serves the next certificate and generates its successor.
+    pub(crate) fn rotate(&mut self) -> Result<(), Error> {
+        let next = generate(self.epoch + ROTATION_INTERVAL * (self.index + 2))?;
+        let current = std::mem::replace(&mut self.next, next);
+        let previous = std::mem::replace(&mut self.current, current);
+
+        self.index += 1;
+        self.previous = Some(previous.hash);
+        *self.resolver.0.lock() = self.current.key.clone();
+
+        Ok(())
+    }
+}
+
+/// Generates a self-signed ECDSA certificate, valid for [`CERT_VALIDITY`] from `not_before`.
+fn generate(not_before: SystemTime) -> Result<Generated, Error> {
+    let mut params = rcgen::CertificateParams::new(Vec::new());
+    params.alg = &rcgen::PKCS_ECDSA_P256_SHA256;
+    params.key_pair = Some(rcgen::KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256)?);
+    params.not_before = not_before.into();
+    params.not_after = (not_before + CERT_VALIDITY).into();
+
+    let certificate = rcgen::Certificate::from_params(params)?;
+    // Every serialization is signed anew, so the certificate must only be serialized once.
+    let der = certificate.serialize_der()?;
+    let private_key = rustls::PrivateKey(certificate.serialize_private_key_der());
+    let signing_key =
+        rustls::sign::any_ecdsa_type(&private_key).expect("rcgen generates valid ECDSA keys");
+
+    Ok(Generated {
+        hash: certhash(&der),
+        key: Arc::new(CertifiedKey::new(
+            vec![rustls::Certificate(der)],
+            signing_key,
+        )),
+    })
+}
+
+/// The hash of a DER-encoded certificate, as found in `/certhash` components of addresses.
+pub(crate) fn certhash(der: &[u8]) -> Multihash<64> {
+    let digest = ring::digest::digest(&ring::digest::SHA256, der);
+    Multihash::wrap(MULTIHASH_SHA256_CODE, digest.as_ref()).expect("SHA-256 fits into 64 bytes")
+}
+
+/// Resolves to the currently served certificate, regardless of the client hello.
+struct Resolver(Mutex<Arc<CertifiedKey>>);
+
+impl ResolvesServerCert for Resolver {
+    fn resolve(&self, _: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.0.lock().clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation_keeps_previous_hashes_accepted() {
+        let mut certificates = Certificates::new().unwrap();
+        let [first, second] = certificates.advertised();
+        assert_eq!(certificates.accepted(), HashSet::from([first, second]));
+
+        let rotation = certificates.next_rotation();
+        certificates.rotate().unwrap();
+        let [current, third] = certificates.advertised();
+
+        assert_eq!(current, second);
+        assert_ne!(third, second);
+        assert_eq!(
+            certificates.accepted(),
+            HashSet::from([first, second, third])
+        );
+        assert_eq!(certificates.next_rotation(), rotation + ROTATION_INTERVAL);
+    }
+
+    #[test]
+    fn served_certificate_is_valid_until_rotation() {
+        let certificates = Certificates::new().unwrap();
+        let served = certificates.resolver.0.lock().clone();
+
+        assert_eq!(certhash(&served.cert[0].0), certificates.advertised()[0]);
+        assert!(certificates.next_rotation() < certificates.epoch + CERT_VALIDITY);
+        assert!(certificates.epoch < SystemTime::now());
+    }
+}
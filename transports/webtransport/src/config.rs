@@ -0,0 +1,65 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use libp2p_identity::Keypair;
+use std::{sync::Arc, time::Duration};
+
+/// Config for the transport.
+#[derive(Clone)]
+pub struct Config {
+    /// Timeout for setting up and authenticating a session, starting with the QUIC handshake.
+    pub handshake_timeout: Duration,
+    /// Maximum duration of inactivity in ms to accept before timing out the connection.
+    pub max_idle_timeout: u32,
+    /// Period of inactivity before sending a keep-alive packet.
+    /// Must be set lower than the idle_timeout of both
+    /// peers to be effective.
+    ///
+    /// See [`quinn::TransportConfig::keep_alive_interval`] for more
+    /// info.
+    pub keep_alive_interval: Duration,
+
+    /// Keypair used to authenticate sessions via Noise.
+    pub(crate) keypair: Keypair,
+}
+
+impl Config {
+    /// Creates a new configuration object with default values.
+    pub fn new(keypair: &Keypair) -> Self {
+        Self {
+            handshake_timeout: Duration::from_secs(10),
+            max_idle_timeout: 30 * 1000,
+            keep_alive_interval: Duration::from_secs(15),
+            keypair: keypair.clone(),
+        }
+    }
+
+    /// The transport configuration shared by all connections.
+    pub(crate) fn quinn_transport_config(&self) -> Arc<quinn::TransportConfig> {
+        let mut transport = quinn::TransportConfig::default();
+        // HTTP/3 needs a control stream and two QPACK streams from the remote.
+        // Unidirectional WebTransport streams are not used by libp2p.
+        transport.max_concurrent_uni_streams(8u32.into());
+        transport.keep_alive_interval(Some(self.keep_alive_interval));
+        transport.max_idle_timeout(Some(quinn::VarInt::from_u32(self.max_idle_timeout).into()));
+        transport.allow_spin(false);
+        Arc::new(transport)
+    }
+}
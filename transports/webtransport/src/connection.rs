@@ -0,0 +1,249 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+mod stream;
+
+pub use stream::Stream;
+
+use crate::{h3, Error};
+use futures::{
+    future::BoxFuture,
+    stream::{FuturesUnordered, StreamExt},
+    FutureExt,
+};
+use libp2p_core::muxing::{StreamMuxer, StreamMuxerEvent};
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Error code to close a connection with (`H3_NO_ERROR`).
+const NO_ERROR: u32 = 0x0100;
+
+/// State of a WebTransport session, which spans a whole QUIC connection.
+pub struct Connection {
+    /// Underlying connection.
+    connection: quinn::Connection,
+    /// ID of the WebTransport session, sent at the start of each stream.
+    session_id: u64,
+    /// Our HTTP/3 control stream, which must not be closed.
+    _control: quinn::SendStream,
+    /// Sending side of the stream carrying the session request.
+    _session: quinn::SendStream,
+    /// Future for accepting a new incoming bidirectional stream.
+    incoming: Option<
+        BoxFuture<'static, Result<(quinn::SendStream, quinn::RecvStream), quinn::ConnectionError>>,
+    >,
+    /// Incoming bidirectional streams whose header is being read.
+    pending_incoming: FuturesUnordered<BoxFuture<'static, Option<Stream>>>,
+    /// Future for opening a new outgoing bidirectional stream.
+    outgoing: Option<BoxFuture<'static, Result<Stream, Error>>>,
+    /// Future for accepting a new incoming unidirectional stream.
+    incoming_uni: Option<BoxFuture<'static, Result<quinn::RecvStream, quinn::ConnectionError>>>,
+    /// Streams of the remote which are read until they end, i.e. its unidirectional streams and
+    /// the receiving side of the session request. Each resolves to whether the session ended.
+    remote_streams: FuturesUnordered<BoxFuture<'static, bool>>,
+    /// Future to wait for the connection to be closed.
+    closing: Option<BoxFuture<'static, quinn::ConnectionError>>,
+}
+
+impl Connection {
+    /// Build a [`Connection`] from an accepted session.
+    pub(crate) fn new(connection: quinn::Connection, session: h3::Session) -> Self {
+        let h3::Session {
+            id,
+            control,
+            send,
+            recv,
+        } = session;
+        let remote_streams = FuturesUnordered::new();
+        remote_streams.push(
+            async move {
+                drain(recv).await;
+                true
+            }
+            .boxed(),
+        );
+
+        Self {
+            connection,
+            session_id: id,
+            _control: control,
+            _session: send,
+            incoming: None,
+            pending_incoming: FuturesUnordered::new(),
+            outgoing: None,
+            incoming_uni: None,
+            remote_streams,
+            closing: None,
+        }
+    }
+}
+
+impl StreamMuxer for Connection {
+    type Substream = Stream;
+    type Error = Error;
+
+    fn poll_inbound(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::Substream, Self::Error>> {
+        let this = self.get_mut();
+
+        loop {
+            match this.pending_incoming.poll_next_unpin(cx) {
+                Poll::Ready(Some(Some(stream))) => return Poll::Ready(Ok(stream)),
+                Poll::Ready(Some(None)) => continue,
+                Poll::Ready(None) | Poll::Pending => {}
+            }
+
+            let incoming = this.incoming.get_or_insert_with(|| {
+                let connection = this.connection.clone();
+                async move { connection.accept_bi().await }.boxed()
+            });
+
+            let (send, recv) = futures::ready!(incoming.poll_unpin(cx))?;
+            this.incoming.take();
+            this.pending_incoming
+                .push(accept_stream(this.session_id, send, recv).boxed());
+        }
+    }
+
+    fn poll_outbound(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::Substream, Self::Error>> {
+        let this = self.get_mut();
+
+        let outgoing = this.outgoing.get_or_insert_with(|| {
+            let connection = this.connection.clone();
+            let mut header = Vec::new();
+            h3::encode_varint(&mut header, h3::WEBTRANSPORT_STREAM);
+            h3::encode_varint(&mut header, this.session_id);
+            async move {
+                let (mut send, recv) = connection.open_bi().await?;
+                send.write_all(&header).await?;
+                Ok(Stream::new(send, recv))
+            }
+            .boxed()
+        });
+
+        let stream = futures::ready!(outgoing.poll_unpin(cx));
+        this.outgoing.take();
+        Poll::Ready(stream)
+    }
+
+    fn poll(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<StreamMuxerEvent, Self::Error>> {
+        let this = self.get_mut();
+
+        loop {
+            match this.remote_streams.poll_next_unpin(cx) {
+                Poll::Ready(Some(true)) => return Poll::Ready(Err(Error::SessionClosed)),
+                Poll::Ready(Some(false)) => continue,
+                Poll::Ready(None) | Poll::Pending => {}
+            }
+
+            let incoming_uni = this.incoming_uni.get_or_insert_with(|| {
+                let connection = this.connection.clone();
+                async move { connection.accept_uni().await }.boxed()
+            });
+
+            let recv = futures::ready!(incoming_uni.poll_unpin(cx))?;
+            this.incoming_uni.take();
+            this.remote_streams.push(read_uni_stream(recv).boxed());
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+
+        let closing = this.closing.get_or_insert_with(|| {
+            this.connection.close(NO_ERROR.into(), &[]);
+            let connection = this.connection.clone();
+            async move { connection.closed().await }.boxed()
+        });
+
+        match futures::ready!(closing.poll_unpin(cx)) {
+            // Expected error given that `connection.close` was called above.
+            quinn::ConnectionError::LocallyClosed => {}
+            error => return Poll::Ready(Err(error.into())),
+        };
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Reads the header of an incoming bidirectional stream, rejecting streams which do not belong
+/// to the session.
+async fn accept_stream(
+    session_id: u64,
+    mut send: quinn::SendStream,
+    mut recv: quinn::RecvStream,
+) -> Option<Stream> {
+    let header = async {
+        let signal = h3::read_varint(&mut recv).await?;
+        let id = h3::read_varint(&mut recv).await?;
+        Ok::<_, io::Error>((signal, id))
+    }
+    .await;
+
+    match header {
+        Ok((h3::WEBTRANSPORT_STREAM, id)) if id == session_id => Some(Stream::new(send, recv)),
+        Ok(_) => {
+            log::debug!("Rejecting stream {} outside of the session", recv.id());
+            let _ = send.reset(h3::REQUEST_REJECTED.into());
+            let _ = recv.stop(h3::REQUEST_REJECTED.into());
+            None
+        }
+        Err(e) => {
+            log::debug!("Failed to read header of stream {}: {e}", recv.id());
+            None
+        }
+    }
+}
+
+/// Reads an incoming unidirectional stream, returning whether its end ends the session.
+///
+/// The streams critical to HTTP/3 are read until they end, any others are stopped.
+async fn read_uni_stream(mut recv: quinn::RecvStream) -> bool {
+    match h3::read_varint(&mut recv).await {
+        Ok(
+            h3::STREAM_TYPE_CONTROL | h3::STREAM_TYPE_QPACK_ENCODER | h3::STREAM_TYPE_QPACK_DECODER,
+        ) => {
+            drain(recv).await;
+            true
+        }
+        Ok(_) => {
+            let _ = recv.stop(h3::STREAM_CREATION_ERROR.into());
+            false
+        }
+        Err(_) => false,
+    }
+}
+
+/// Reads and discards all data of a stream.
+async fn drain(mut recv: quinn::RecvStream) {
+    let mut buf = [0; 1024];
+    while let Ok(Some(_)) = recv.read(&mut buf).await {}
+}
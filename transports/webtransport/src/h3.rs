@@ -0,0 +1,302 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The subset of HTTP/3 ([RFC 9114](https://datatracker.ietf.org/doc/html/rfc9114)) needed to
+//! accept a WebTransport session ([draft-ietf-webtrans-http3-02](https://datatracker.ietf.org/doc/html/draft-ietf-webtrans-http3-02)).
+
+mod huffman;
+mod qpack;
+
+use crate::Error;
+use futures::{AsyncRead, AsyncReadExt};
+use std::io;
+
+/// Type of a unidirectional HTTP/3 control stream.
+pub(crate) const STREAM_TYPE_CONTROL: u64 = 0x00;
+/// Type of a unidirectional QPACK encoder stream.
+pub(crate) const STREAM_TYPE_QPACK_ENCODER: u64 = 0x02;
+/// Type of a unidirectional QPACK decoder stream.
+pub(crate) const STREAM_TYPE_QPACK_DECODER: u64 = 0x03;
+/// Signal at the start of a bidirectional WebTransport stream, followed by the session ID.
+pub(crate) const WEBTRANSPORT_STREAM: u64 = 0x41;
+
+/// Error code for streams of unknown or unwanted type (`H3_STREAM_CREATION_ERROR`).
+pub(crate) const STREAM_CREATION_ERROR: u32 = 0x0103;
+/// Error code for requests which were not processed (`H3_REQUEST_REJECTED`).
+pub(crate) const REQUEST_REJECTED: u32 = 0x010b;
+
+const FRAME_DATA: u64 = 0x00;
+const FRAME_HEADERS: u64 = 0x01;
+const FRAME_SETTINGS: u64 = 0x04;
+
+/// Maximum size of a `HEADERS` frame accepted from the remote.
+const MAX_HEADERS_LEN: u64 = 16 * 1024;
+
+/// The settings sent on our control stream.
+const SETTINGS: [(u64, u64); 6] = [
+    // `SETTINGS_QPACK_MAX_TABLE_CAPACITY`, disabling the dynamic table.
+    (0x01, 0),
+    // `SETTINGS_QPACK_BLOCKED_STREAMS`
+    (0x07, 0),
+    // `SETTINGS_ENABLE_CONNECT_PROTOCOL`
+    (0x08, 1),
+    // `SETTINGS_H3_DATAGRAM`, and its value from draft-ietf-masque-h3-datagram-04.
+    (0x33, 1),
+    (0xffd277, 1),
+    // `SETTINGS_ENABLE_WEBTRANSPORT`
+    (0x2b603742, 1),
+];
+
+/// The path at which libp2p WebTransport sessions are requested.
+const LIBP2P_PATH: &[u8] = b"/.well-known/libp2p-webtransport?type=noise";
+
+/// An accepted WebTransport session.
+pub(crate) struct Session {
+    /// The session ID, i.e. the ID of the stream carrying the `CONNECT` request.
+    pub(crate) id: u64,
+    /// Our control stream, to be kept open for the lifetime of the connection.
+    pub(crate) control: quinn::SendStream,
+    /// The stream carrying the `CONNECT` request, which is closed to close the session.
+    pub(crate) send: quinn::SendStream,
+    pub(crate) recv: quinn::RecvStream,
+}
+
+/// Announces our settings and accepts the WebTransport session request of a browser.
+///
+/// The unidirectional streams of the remote are left to the [`crate::Connection`].
+pub(crate) async fn accept_session(connection: &quinn::Connection) -> Result<Session, Error> {
+    let mut control = connection.open_uni().await?;
+    let mut preamble = Vec::new();
+    encode_varint(&mut preamble, STREAM_TYPE_CONTROL);
+    let mut settings = Vec::new();
+    for (id, value) in SETTINGS {
+        encode_varint(&mut settings, id);
+        encode_varint(&mut settings, value);
+    }
+    encode_frame(&mut preamble, FRAME_SETTINGS, &settings);
+    control.write_all(&preamble).await?;
+
+    let (mut send, mut recv) = connection.accept_bi().await?;
+    let request = read_headers(&mut recv).await?;
+    let status = check_request(&request);
+
+    let mut response = Vec::new();
+    let status_text = status.to_string();
+    encode_frame(
+        &mut response,
+        FRAME_HEADERS,
+        &qpack::encode(&[
+            (b":status", status_text.as_bytes()),
+            (b"sec-webtransport-http3-draft", b"draft02"),
+        ]),
+    );
+    send.write_all(&response).await?;
+
+    if status != 200 {
+        let _ = send.finish().await;
+        return Err(Error::RequestRejected(status));
+    }
+
+    Ok(Session {
+        id: quinn::VarInt::from(recv.id()).into_inner(),
+        control,
+        send,
+        recv,
+    })
+}
+
+/// Returns the status with which to answer a request.
+fn check_request(fields: &qpack::Fields) -> u16 {
+    let field = |name: &[u8]| {
+        fields
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_slice())
+    };
+
+    if field(b":method") != Some(b"CONNECT") || field(b":protocol") != Some(b"webtransport") {
+        return 400;
+    }
+    if field(b":path") != Some(LIBP2P_PATH) {
+        return 404;
+    }
+
+    200
+}
+
+/// Reads the request headers from a request stream, skipping other frames before them.
+async fn read_headers<R>(recv: &mut R) -> io::Result<qpack::Fields>
+where
+    R: AsyncRead + Unpin,
+{
+    loop {
+        let ty = read_varint(recv).await?;
+        let len = read_varint(recv).await?;
+        match ty {
+            FRAME_HEADERS if len <= MAX_HEADERS_LEN => {
+                let mut payload = vec![0; len as usize];
+                recv.read_exact(&mut payload).await?;
+                return qpack::decode(&payload);
+            }
+            FRAME_HEADERS | FRAME_DATA => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "unexpected frame on request stream",
+                ))
+            }
+            _ => {
+                // Reserved or unknown frame types must be ignored.
+                futures::io::copy((&mut *recv).take(len), &mut futures::io::sink()).await?;
+            }
+        }
+    }
+}
+
+/// Reads a variable-length integer ([RFC 9000, Section 16](https://datatracker.ietf.org/doc/html/rfc9000#section-16)).
+pub(crate) async fn read_varint<R>(recv: &mut R) -> io::Result<u64>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut buf = [0; 8];
+    recv.read_exact(&mut buf[..1]).await?;
+    let len = 1 << (buf[0] >> 6);
+    buf[0] &= 0x3f;
+    recv.read_exact(&mut buf[1..len]).await?;
+
+    Ok(buf[..len]
+        .iter()
+        .fold(0, |value, byte| (value << 8) | u64::from(*byte)))
+}
+
+/// Encodes a variable-length integer.
+///
+/// Panics if `value` exceeds `2^62 - 1`.
+pub(crate) fn encode_varint(dst: &mut Vec<u8>, value: u64) {
+    if value < 1 << 6 {
+        dst.push(value as u8);
+    } else if value < 1 << 14 {
+        dst.extend_from_slice(&(value as u16 | 0x4000).to_be_bytes());
+    } else if value < 1 << 30 {
+        dst.extend_from_slice(&(value as u32 | 0x8000_0000).to_be_bytes());
+    } else {
+        assert!(value < 1 << 62, "varint out of range");
+        dst.extend_from_slice(&(value | 0xc000_0000_0000_0000).to_be_bytes());
+    }
+}
+
+fn encode_frame(dst: &mut Vec<u8>, ty: u64, payload: &[u8]) {
+    encode_varint(dst, ty);
+    encode_varint(dst, payload.len() as u64);
+    dst.extend_from_slice(payload);
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// The control stream preamble of a client.
+    pub(crate) fn client_control_stream() -> Vec<u8> {
+        let mut dst = Vec::new();
+        encode_varint(&mut dst, STREAM_TYPE_CONTROL);
+        encode_frame(&mut dst, FRAME_SETTINGS, &[0x2b, 0x60, 0x37, 0x42, 0x01]);
+        dst
+    }
+
+    /// A libp2p WebTransport session request.
+    pub(crate) fn session_request(authority: &str) -> Vec<u8> {
+        let mut dst = Vec::new();
+        encode_frame(
+            &mut dst,
+            FRAME_HEADERS,
+            &qpack::encode(&[
+                (b":method", b"CONNECT"),
+                (b":protocol", b"webtransport"),
+                (b":scheme", b"https"),
+                (b":authority", authority.as_bytes()),
+                (b":path", LIBP2P_PATH),
+            ]),
+        );
+        dst
+    }
+
+    /// Reads the status of a response.
+    pub(crate) async fn read_status<R>(recv: &mut R) -> io::Result<Vec<u8>>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let fields = read_headers(recv).await?;
+        Ok(fields
+            .into_iter()
+            .find(|(name, _)| name == b":status")
+            .map(|(_, value)| value)
+            .unwrap_or_default())
+    }
+
+    #[test]
+    fn varint_roundtrip() {
+        for value in [
+            0,
+            63,
+            64,
+            16383,
+            16384,
+            0x2b603742,
+            (1 << 30) + 1,
+            (1 << 62) - 1,
+        ] {
+            let mut encoded = Vec::new();
+            encode_varint(&mut encoded, value);
+            let decoded = futures::executor::block_on(read_varint(&mut encoded.as_slice()));
+            assert_eq!(decoded.unwrap(), value);
+        }
+
+        // Example from RFC 9000, Appendix A.1.
+        let mut encoded = Vec::new();
+        encode_varint(&mut encoded, 151_288_809_941_952_652);
+        assert_eq!(encoded, [0xc2, 0x19, 0x7c, 0x5e, 0xff, 0x14, 0xe8, 0x8c]);
+    }
+
+    #[test]
+    fn checks_session_requests() {
+        let request = |method: &[u8], path: &[u8]| {
+            vec![
+                (b":method".to_vec(), method.to_vec()),
+                (b":protocol".to_vec(), b"webtransport".to_vec()),
+                (b":path".to_vec(), path.to_vec()),
+            ]
+        };
+
+        assert_eq!(check_request(&request(b"CONNECT", LIBP2P_PATH)), 200);
+        assert_eq!(check_request(&request(b"GET", LIBP2P_PATH)), 400);
+        assert_eq!(check_request(&request(b"CONNECT", b"/")), 404);
+    }
+
+    #[test]
+    fn skips_unknown_frames_before_headers() {
+        let mut src = Vec::new();
+        // A reserved frame type, used for greasing.
+        encode_frame(&mut src, 0x21, b"grease");
+        src.extend(session_request("localhost"));
+
+        let fields = futures::executor::block_on(read_headers(&mut src.as_slice())).unwrap();
+
+        assert_eq!(check_request(&fields), 200);
+    }
+}
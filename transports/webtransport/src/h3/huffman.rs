@@ -0,0 +1,371 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Decoding of Huffman encoded string literals ([RFC 7541, Appendix B](https://datatracker.ietf.org/doc/html/rfc7541#appendix-B)),
+//! as used by QPACK.
+
+/// The end-of-string symbol.
+const EOS: usize = 256;
+
+/// Decodes a Huffman encoded string.
+///
+/// Returns `None` if the input is not a valid encoding.
+pub(crate) fn decode(src: &[u8]) -> Option<Vec<u8>> {
+    // The code is canonical: codes of the same length are consecutive and ordered by symbol.
+    let mut first_code = [0u64; 31];
+    let mut first_index = [0usize; 31];
+    let mut count = [0usize; 31];
+    let mut symbols = (0..ENCODE_TABLE.len()).collect::<Vec<_>>();
+    symbols.sort_by_key(|&symbol| ENCODE_TABLE[symbol]);
+    for (index, &symbol) in symbols.iter().enumerate().rev() {
+        let (len, code) = ENCODE_TABLE[symbol];
+        first_code[len] = code;
+        first_index[len] = index;
+        count[len] += 1;
+    }
+
+    let mut dst = Vec::with_capacity(src.len() * 8 / 5);
+    let mut code = 0u64;
+    let mut len = 0;
+    for byte in src {
+        for shift in (0..8).rev() {
+            code = (code << 1) | u64::from((byte >> shift) & 1);
+            len += 1;
+            if len >= first_code.len() {
+                return None;
+            }
+            if count[len] > 0
+                && code >= first_code[len]
+                && code - first_code[len] < count[len] as u64
+            {
+                let symbol = symbols[first_index[len] + (code - first_code[len]) as usize];
+                if symbol == EOS {
+                    return None;
+                }
+                dst.push(symbol as u8);
+                code = 0;
+                len = 0;
+            }
+        }
+    }
+
+    // Padding must be a prefix of the EOS code, i.e. all ones, and shorter than a byte.
+    if len > 7 || code != (1 << len) - 1 {
+        return None;
+    }
+
+    Some(dst)
+}
+
+// Taken from the `h2` crate.
+// (num-bits, bits)
+const ENCODE_TABLE: [(usize, u64); 257] = [
+    (13, 0x1ff8),
+    (23, 0x007f_ffd8),
+    (28, 0x0fff_ffe2),
+    (28, 0x0fff_ffe3),
+    (28, 0x0fff_ffe4),
+    (28, 0x0fff_ffe5),
+    (28, 0x0fff_ffe6),
+    (28, 0x0fff_ffe7),
+    (28, 0x0fff_ffe8),
+    (24, 0x00ff_ffea),
+    (30, 0x3fff_fffc),
+    (28, 0x0fff_ffe9),
+    (28, 0x0fff_ffea),
+    (30, 0x3fff_fffd),
+    (28, 0x0fff_ffeb),
+    (28, 0x0fff_ffec),
+    (28, 0x0fff_ffed),
+    (28, 0x0fff_ffee),
+    (28, 0x0fff_ffef),
+    (28, 0x0fff_fff0),
+    (28, 0x0fff_fff1),
+    (28, 0x0fff_fff2),
+    (30, 0x3fff_fffe),
+    (28, 0x0fff_fff3),
+    (28, 0x0fff_fff4),
+    (28, 0x0fff_fff5),
+    (28, 0x0fff_fff6),
+    (28, 0x0fff_fff7),
+    (28, 0x0fff_fff8),
+    (28, 0x0fff_fff9),
+    (28, 0x0fff_fffa),
+    (28, 0x0fff_fffb),
+    (6, 0x14),
+    (10, 0x3f8),
+    (10, 0x3f9),
+    (12, 0xffa),
+    (13, 0x1ff9),
+    (6, 0x15),
+    (8, 0xf8),
+    (11, 0x7fa),
+    (10, 0x3fa),
+    (10, 0x3fb),
+    (8, 0xf9),
+    (11, 0x7fb),
+    (8, 0xfa),
+    (6, 0x16),
+    (6, 0x17),
+    (6, 0x18),
+    (5, 0x0),
+    (5, 0x1),
+    (5, 0x2),
+    (6, 0x19),
+    (6, 0x1a),
+    (6, 0x1b),
+    (6, 0x1c),
+    (6, 0x1d),
+    (6, 0x1e),
+    (6, 0x1f),
+    (7, 0x5c),
+    (8, 0xfb),
+    (15, 0x7ffc),
+    (6, 0x20),
+    (12, 0xffb),
+    (10, 0x3fc),
+    (13, 0x1ffa),
+    (6, 0x21),
+    (7, 0x5d),
+    (7, 0x5e),
+    (7, 0x5f),
+    (7, 0x60),
+    (7, 0x61),
+    (7, 0x62),
+    (7, 0x63),
+    (7, 0x64),
+    (7, 0x65),
+    (7, 0x66),
+    (7, 0x67),
+    (7, 0x68),
+    (7, 0x69),
+    (7, 0x6a),
+    (7, 0x6b),
+    (7, 0x6c),
+    (7, 0x6d),
+    (7, 0x6e),
+    (7, 0x6f),
+    (7, 0x70),
+    (7, 0x71),
+    (7, 0x72),
+    (8, 0xfc),
+    (7, 0x73),
+    (8, 0xfd),
+    (13, 0x1ffb),
+    (19, 0x7fff0),
+    (13, 0x1ffc),
+    (14, 0x3ffc),
+    (6, 0x22),
+    (15, 0x7ffd),
+    (5, 0x3),
+    (6, 0x23),
+    (5, 0x4),
+    (6, 0x24),
+    (5, 0x5),
+    (6, 0x25),
+    (6, 0x26),
+    (6, 0x27),
+    (5, 0x6),
+    (7, 0x74),
+    (7, 0x75),
+    (6, 0x28),
+    (6, 0x29),
+    (6, 0x2a),
+    (5, 0x7),
+    (6, 0x2b),
+    (7, 0x76),
+    (6, 0x2c),
+    (5, 0x8),
+    (5, 0x9),
+    (6, 0x2d),
+    (7, 0x77),
+    (7, 0x78),
+    (7, 0x79),
+    (7, 0x7a),
+    (7, 0x7b),
+    (15, 0x7ffe),
+    (11, 0x7fc),
+    (14, 0x3ffd),
+    (13, 0x1ffd),
+    (28, 0x0fff_fffc),
+    (20, 0xfffe6),
+    (22, 0x003f_ffd2),
+    (20, 0xfffe7),
+    (20, 0xfffe8),
+    (22, 0x003f_ffd3),
+    (22, 0x003f_ffd4),
+    (22, 0x003f_ffd5),
+    (23, 0x007f_ffd9),
+    (22, 0x003f_ffd6),
+    (23, 0x007f_ffda),
+    (23, 0x007f_ffdb),
+    (23, 0x007f_ffdc),
+    (23, 0x007f_ffdd),
+    (23, 0x007f_ffde),
+    (24, 0x00ff_ffeb),
+    (23, 0x007f_ffdf),
+    (24, 0x00ff_ffec),
+    (24, 0x00ff_ffed),
+    (22, 0x003f_ffd7),
+    (23, 0x007f_ffe0),
+    (24, 0x00ff_ffee),
+    (23, 0x007f_ffe1),
+    (23, 0x007f_ffe2),
+    (23, 0x007f_ffe3),
+    (23, 0x007f_ffe4),
+    (21, 0x001f_ffdc),
+    (22, 0x003f_ffd8),
+    (23, 0x007f_ffe5),
+    (22, 0x003f_ffd9),
+    (23, 0x007f_ffe6),
+    (23, 0x007f_ffe7),
+    (24, 0x00ff_ffef),
+    (22, 0x003f_ffda),
+    (21, 0x001f_ffdd),
+    (20, 0xfffe9),
+    (22, 0x003f_ffdb),
+    (22, 0x003f_ffdc),
+    (23, 0x007f_ffe8),
+    (23, 0x007f_ffe9),
+    (21, 0x001f_ffde),
+    (23, 0x007f_ffea),
+    (22, 0x003f_ffdd),
+    (22, 0x003f_ffde),
+    (24, 0x00ff_fff0),
+    (21, 0x001f_ffdf),
+    (22, 0x003f_ffdf),
+    (23, 0x007f_ffeb),
+    (23, 0x007f_ffec),
+    (21, 0x001f_ffe0),
+    (21, 0x001f_ffe1),
+    (22, 0x003f_ffe0),
+    (21, 0x001f_ffe2),
+    (23, 0x007f_ffed),
+    (22, 0x003f_ffe1),
+    (23, 0x007f_ffee),
+    (23, 0x007f_ffef),
+    (20, 0xfffea),
+    (22, 0x003f_ffe2),
+    (22, 0x003f_ffe3),
+    (22, 0x003f_ffe4),
+    (23, 0x007f_fff0),
+    (22, 0x003f_ffe5),
+    (22, 0x003f_ffe6),
+    (23, 0x007f_fff1),
+    (26, 0x03ff_ffe0),
+    (26, 0x03ff_ffe1),
+    (20, 0xfffeb),
+    (19, 0x7fff1),
+    (22, 0x003f_ffe7),
+    (23, 0x007f_fff2),
+    (22, 0x003f_ffe8),
+    (25, 0x01ff_ffec),
+    (26, 0x03ff_ffe2),
+    (26, 0x03ff_ffe3),
+    (26, 0x03ff_ffe4),
+    (27, 0x07ff_ffde),
+    (27, 0x07ff_ffdf),
+    (26, 0x03ff_ffe5),
+    (24, 0x00ff_fff1),
+    (25, 0x01ff_ffed),
+    (19, 0x7fff2),
+    (21, 0x001f_ffe3),
+    (26, 0x03ff_ffe6),
+    (27, 0x07ff_ffe0),
+    (27, 0x07ff_ffe1),
+    (26, 0x03ff_ffe7),
+    (27, 0x07ff_ffe2),
+    (24, 0x00ff_fff2),
+    (21, 0x001f_ffe4),
+    (21, 0x001f_ffe5),
+    (26, 0x03ff_ffe8),
+    (26, 0x03ff_ffe9),
+    (28, 0x0fff_fffd),
+    (27, 0x07ff_ffe3),
+    (27, 0x07ff_ffe4),
+    (27, 0x07ff_ffe5),
+    (20, 0xfffec),
+    (24, 0x00ff_fff3),
+    (20, 0xfffed),
+    (21, 0x001f_ffe6),
+    (22, 0x003f_ffe9),
+    (21, 0x001f_ffe7),
+    (21, 0x001f_ffe8),
+    (23, 0x007f_fff3),
+    (22, 0x003f_ffea),
+    (22, 0x003f_ffeb),
+    (25, 0x01ff_ffee),
+    (25, 0x01ff_ffef),
+    (24, 0x00ff_fff4),
+    (24, 0x00ff_fff5),
+    (26, 0x03ff_ffea),
+    (23, 0x007f_fff4),
+    (26, 0x03ff_ffeb),
+    (27, 0x07ff_ffe6),
+    (26, 0x03ff_ffec),
+    (26, 0x03ff_ffed),
+    (27, 0x07ff_ffe7),
+    (27, 0x07ff_ffe8),
+    (27, 0x07ff_ffe9),
+    (27, 0x07ff_ffea),
+    (27, 0x07ff_ffeb),
+    (28, 0x0fff_fffe),
+    (27, 0x07ff_ffec),
+    (27, 0x07ff_ffed),
+    (27, 0x07ff_ffee),
+    (27, 0x07ff_ffef),
+    (27, 0x07ff_fff0),
+    (26, 0x03ff_ffee),
+    (30, 0x3fff_ffff),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_rfc_7541_examples() {
+        let examples: [(&[u8], &[u8]); 3] = [
+            (
+                &[
+                    0xf1, 0xe3, 0xc2, 0xe5, 0xf2, 0x3a, 0x6b, 0xa0, 0xab, 0x90, 0xf4, 0xff,
+                ],
+                b"www.example.com",
+            ),
+            (&[0xa8, 0xeb, 0x10, 0x64, 0x9c, 0xbf], b"no-cache"),
+            (
+                &[0x25, 0xa8, 0x49, 0xe9, 0x5b, 0xb8, 0xe8, 0xb4, 0xbf],
+                b"custom-value",
+            ),
+        ];
+
+        for (encoded, decoded) in examples {
+            assert_eq!(decode(encoded).as_deref(), Some(decoded));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_padding() {
+        // "no-cache" with the padding bits cleared.
+        assert_eq!(decode(&[0xa8, 0xeb, 0x10, 0x64, 0x9c, 0xbe]), None);
+        // A full byte of padding.
+        assert_eq!(decode(&[0xa8, 0xeb, 0x10, 0x64, 0x9c, 0xbf, 0xff]), None);
+    }
+}
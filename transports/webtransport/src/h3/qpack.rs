@@ -0,0 +1,310 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Field section encoding ([RFC 9204](https://datatracker.ietf.org/doc/html/rfc9204)), without
+//! the dynamic table.
+//!
+//! The dynamic table is disabled via the `QPACK_MAX_TABLE_CAPACITY` setting, so a remote may only
+//! reference the static table.
+
+use super::huffman;
+use std::io;
+
+/// A list of field lines, by name and value.
+pub(crate) type Fields = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// Decodes an encoded field section.
+pub(crate) fn decode(mut src: &[u8]) -> io::Result<Fields> {
+    let required_insert_count = decode_int(&mut src, 8)?;
+    if required_insert_count != 0 {
+        return Err(invalid("reference to the dynamic table"));
+    }
+    // Delta base, which is meaningless without the dynamic table.
+    decode_int(&mut src, 7)?;
+
+    let mut fields = Vec::new();
+    while let Some(&first) = src.first() {
+        let field = if first & 0x80 != 0 {
+            // Indexed field line.
+            if first & 0x40 == 0 {
+                return Err(invalid("reference to the dynamic table"));
+            }
+            let (name, value) = static_entry(decode_int(&mut src, 6)?)?;
+            (name.to_vec(), value.to_vec())
+        } else if first & 0x40 != 0 {
+            // Literal field line with name reference.
+            if first & 0x10 == 0 {
+                return Err(invalid("reference to the dynamic table"));
+            }
+            let (name, _) = static_entry(decode_int(&mut src, 4)?)?;
+            (name.to_vec(), decode_str(&mut src, 7)?)
+        } else if first & 0x20 != 0 {
+            // Literal field line with literal name.
+            let name = decode_str(&mut src, 3)?;
+            (name, decode_str(&mut src, 7)?)
+        } else {
+            return Err(invalid("reference to the dynamic table"));
+        };
+        fields.push(field);
+    }
+
+    Ok(fields)
+}
+
+/// Encodes a field section with literal field lines only.
+pub(crate) fn encode(fields: &[(&[u8], &[u8])]) -> Vec<u8> {
+    // Required insert count and delta base.
+    let mut dst = vec![0, 0];
+    for (name, value) in fields {
+        encode_int(&mut dst, 0x20, 3, name.len() as u64);
+        dst.extend_from_slice(name);
+        encode_int(&mut dst, 0, 7, value.len() as u64);
+        dst.extend_from_slice(value);
+    }
+    dst
+}
+
+/// Decodes an integer with an `n`-bit prefix ([RFC 7541, Section 5.1](https://datatracker.ietf.org/doc/html/rfc7541#section-5.1)).
+/// Flags in the first byte are ignored.
+fn decode_int(src: &mut &[u8], n: u32) -> io::Result<u64> {
+    let mask = (1u16 << n) as u64 - 1;
+    let (&first, rest) = src.split_first().ok_or_else(truncated)?;
+    *src = rest;
+
+    let mut value = u64::from(first) & mask;
+    if value < mask {
+        return Ok(value);
+    }
+    for shift in (0..63).step_by(7) {
+        let (&byte, rest) = src.split_first().ok_or_else(truncated)?;
+        *src = rest;
+        value = value
+            .checked_add(u64::from(byte & 0x7f) << shift)
+            .ok_or_else(|| invalid("integer overflow"))?;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(invalid("integer overflow"))
+}
+
+/// Encodes an integer with an `n`-bit prefix, combining the first byte with `flags`.
+fn encode_int(dst: &mut Vec<u8>, flags: u8, n: u32, mut value: u64) {
+    let mask = (1u16 << n) as u64 - 1;
+    if value < mask {
+        dst.push(flags | value as u8);
+        return;
+    }
+    dst.push(flags | mask as u8);
+    value -= mask;
+    while value >= 0x80 {
+        dst.push(0x80 | (value & 0x7f) as u8);
+        value >>= 7;
+    }
+    dst.push(value as u8);
+}
+
+/// Decodes a string literal, whose length has an `n`-bit prefix preceded by the Huffman flag.
+fn decode_str(src: &mut &[u8], n: u32) -> io::Result<Vec<u8>> {
+    let huffman = src.first().ok_or_else(truncated)? & (1 << n) != 0;
+    let len = decode_int(src, n)?;
+    if len > src.len() as u64 {
+        return Err(truncated());
+    }
+    let (value, rest) = src.split_at(len as usize);
+    *src = rest;
+
+    if huffman {
+        huffman::decode(value).ok_or_else(|| invalid("invalid Huffman encoding"))
+    } else {
+        Ok(value.to_vec())
+    }
+}
+
+fn static_entry(index: u64) -> io::Result<(&'static [u8], &'static [u8])> {
+    usize::try_from(index)
+        .ok()
+        .and_then(|index| STATIC_TABLE.get(index))
+        .map(|(name, value)| (name.as_bytes(), value.as_bytes()))
+        .ok_or_else(|| invalid("invalid static table index"))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("QPACK: {message}"))
+}
+
+fn truncated() -> io::Error {
+    invalid("truncated field section")
+}
+
+/// The static table ([RFC 9204, Appendix A](https://datatracker.ietf.org/doc/html/rfc9204#appendix-A)).
+const STATIC_TABLE: [(&str, &str); 99] = [
+    (":authority", ""),
+    (":path", "/"),
+    ("age", "0"),
+    ("content-disposition", ""),
+    ("content-length", "0"),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("referer", ""),
+    ("set-cookie", ""),
+    (":method", "CONNECT"),
+    (":method", "DELETE"),
+    (":method", "GET"),
+    (":method", "HEAD"),
+    (":method", "OPTIONS"),
+    (":method", "POST"),
+    (":method", "PUT"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "103"),
+    (":status", "200"),
+    (":status", "304"),
+    (":status", "404"),
+    (":status", "503"),
+    ("accept", "*/*"),
+    ("accept", "application/dns-message"),
+    ("accept-encoding", "gzip, deflate, br"),
+    ("accept-ranges", "bytes"),
+    ("access-control-allow-headers", "cache-control"),
+    ("access-control-allow-headers", "content-type"),
+    ("access-control-allow-origin", "*"),
+    ("cache-control", "max-age=0"),
+    ("cache-control", "max-age=2592000"),
+    ("cache-control", "max-age=604800"),
+    ("cache-control", "no-cache"),
+    ("cache-control", "no-store"),
+    ("cache-control", "public, max-age=31536000"),
+    ("content-encoding", "br"),
+    ("content-encoding", "gzip"),
+    ("content-type", "application/dns-message"),
+    ("content-type", "application/javascript"),
+    ("content-type", "application/json"),
+    ("content-type", "application/x-www-form-urlencoded"),
+    ("content-type", "image/gif"),
+    ("content-type", "image/jpeg"),
+    ("content-type", "image/png"),
+    ("content-type", "text/css"),
+    ("content-type", "text/html; charset=utf-8"),
+    ("content-type", "text/plain"),
+    ("content-type", "text/plain;charset=utf-8"),
+    ("range", "bytes=0-"),
+    ("strict-transport-security", "max-age=31536000"),
+    (
+        "strict-transport-security",
+        "max-age=31536000; includesubdomains",
+    ),
+    (
+        "strict-transport-security",
+        "max-age=31536000; includesubdomains; preload",
+    ),
+    ("vary", "accept-encoding"),
+    ("vary", "origin"),
+    ("x-content-type-options", "nosniff"),
+    ("x-xss-protection", "1; mode=block"),
+    (":status", "100"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "302"),
+    (":status", "400"),
+    (":status", "403"),
+    (":status", "421"),
+    (":status", "425"),
+    (":status", "500"),
+    ("accept-language", ""),
+    ("access-control-allow-credentials", "FALSE"),
+    ("access-control-allow-credentials", "TRUE"),
+    ("access-control-allow-headers", "*"),
+    ("access-control-allow-methods", "get"),
+    ("access-control-allow-methods", "get, post, options"),
+    ("access-control-allow-methods", "options"),
+    ("access-control-expose-headers", "content-length"),
+    ("access-control-request-headers", "content-type"),
+    ("access-control-request-method", "get"),
+    ("access-control-request-method", "post"),
+    ("alt-svc", "clear"),
+    ("authorization", ""),
+    (
+        "content-security-policy",
+        "script-src 'none'; object-src 'none'; base-uri 'none'",
+    ),
+    ("early-data", "1"),
+    ("expect-ct", ""),
+    ("forwarded", ""),
+    ("if-range", ""),
+    ("origin", ""),
+    ("purpose", "prefetch"),
+    ("server", ""),
+    ("timing-allow-origin", "*"),
+    ("upgrade-insecure-requests", "1"),
+    ("user-agent", ""),
+    ("x-forwarded-for", ""),
+    ("x-frame-options", "deny"),
+    ("x-frame-options", "sameorigin"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_static_references_and_huffman_literals() {
+        let src = [
+            0x00, 0x00, // Required insert count and delta base.
+            0xcf, // Indexed, static 15: `:method CONNECT`.
+            0x5f, 0x1d, 0x8c, 0xf1, 0xe3, 0xc2, 0xe5, 0xf2, 0x3a, 0x6b, 0xa0, 0xab, 0x90, 0xf4,
+            0xff, // Literal with static name 44 (`content-type`), Huffman "www.example.com".
+            0x24, b'f', b'o', b'o', b'o', 0x03, b'b', b'a', b'r', // Literal `fooo: bar`.
+        ];
+
+        assert_eq!(
+            decode(&src).unwrap(),
+            vec![
+                (b":method".to_vec(), b"CONNECT".to_vec()),
+                (b"content-type".to_vec(), b"www.example.com".to_vec()),
+                (b"fooo".to_vec(), b"bar".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_dynamic_table_references() {
+        assert!(decode(&[0x01, 0x00]).is_err());
+        assert!(decode(&[0x00, 0x00, 0x80]).is_err());
+        assert!(decode(&[0x00, 0x00, 0x10]).is_err());
+    }
+
+    #[test]
+    fn roundtrips_long_literals() {
+        let name = b"sec-webtransport-http3-draft".as_slice();
+        let value = vec![b'x'; 300];
+
+        let encoded = encode(&[(name, &value)]);
+
+        assert_eq!(decode(&encoded).unwrap(), vec![(name.to_vec(), value)]);
+    }
+}
@@ -0,0 +1,114 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Implementation of the [WebTransport] server side of the libp2p WebTransport transport.
+//!
+//! Browsers dial it through `libp2p-webtransport-websys`. Since browsers cannot verify
+//! self-signed certificates, the listener generates short-lived certificates itself and
+//! advertises their hashes in its listen addresses, e.g.
+//! `/ip4/127.0.0.1/udp/4001/quic-v1/webtransport/certhash/<hash>/certhash/<hash>`.
+//! Certificates are rotated on a schedule, see [`Transport`] for details.
+//!
+//! A session is authenticated via Noise on its first stream, as described in the [spec].
+//! Listening is the only supported operation; dialing is not.
+//!
+//! # Usage
+//!
+//! ```no_run
+//! # #[tokio::main]
+//! # async fn main() {
+//! use libp2p_core::{transport::ListenerId, Transport as _};
+//! use libp2p_webtransport as webtransport;
+//!
+//! let keypair = libp2p_identity::Keypair::generate_ed25519();
+//! let mut transport = webtransport::Transport::new(webtransport::Config::new(&keypair));
+//!
+//! let addr = "/ip4/0.0.0.0/udp/0/quic-v1/webtransport".parse().unwrap();
+//! transport.listen_on(ListenerId::next(), addr).unwrap();
+//! # }
+//! ```
+//!
+//! [WebTransport]: https://www.w3.org/TR/webtransport/
+//! [spec]: https://github.com/libp2p/specs/tree/master/webtransport
+
+#![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
+
+mod certificate;
+mod config;
+mod connection;
+mod h3;
+mod transport;
+
+pub use config::Config;
+pub use connection::{Connection, Stream};
+pub use transport::Transport;
+
+/// Errors that may happen on the [`Transport`] or a single [`Connection`].
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Error on the QUIC connection.
+    #[error(transparent)]
+    Connection(#[from] ConnectionError),
+
+    /// I/O Error on a socket or stream.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// Generating a certificate failed.
+    #[error("Failed to generate certificate")]
+    Certificate(#[from] rcgen::RcgenError),
+
+    /// The Noise handshake on the session failed.
+    #[error(transparent)]
+    Noise(#[from] libp2p_noise::Error),
+
+    /// The task to drive a quic endpoint has crashed.
+    #[error("Endpoint driver crashed")]
+    EndpointDriverCrashed,
+
+    /// The session setup and authentication timed out.
+    #[error("Handshake with the remote timed out.")]
+    HandshakeTimedOut,
+
+    /// The remote sent a request other than a libp2p WebTransport session request.
+    #[error("Rejected session request with status {0}")]
+    RequestRejected(u16),
+
+    /// The remote closed the WebTransport session.
+    #[error("WebTransport session closed by the remote")]
+    SessionClosed,
+}
+
+/// Error on an established [`Connection`].
+#[derive(Debug, thiserror::Error)]
+#[error(transparent)]
+pub struct ConnectionError(quinn::ConnectionError);
+
+impl From<quinn::ConnectionError> for Error {
+    fn from(e: quinn::ConnectionError) -> Self {
+        Error::Connection(ConnectionError(e))
+    }
+}
+
+impl From<quinn::WriteError> for Error {
+    fn from(e: quinn::WriteError) -> Self {
+        Error::Io(e.into())
+    }
+}
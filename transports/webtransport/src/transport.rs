@@ -0,0 +1,644 @@
+// Copyright 2023 Protocol Labs.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use crate::certificate::Certificates;
+use crate::{h3, Config, Connection, Error};
+
+use futures::future::{self, BoxFuture, Either};
+use futures::stream::SelectAll;
+use futures::{prelude::*, ready};
+use futures_timer::Delay;
+use if_watch::{tokio::IfWatcher, IfEvent};
+use libp2p_core::{
+    multiaddr::{Multiaddr, Protocol},
+    muxing::StreamMuxerExt,
+    transport::{ListenerId, TransportError, TransportEvent},
+    InboundUpgrade, UpgradeInfo,
+};
+use libp2p_identity::{Keypair, PeerId};
+use multihash::Multihash;
+use std::collections::{HashSet, VecDeque};
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::time::{Duration, SystemTime};
+use std::{
+    fmt,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+};
+
+/// Implementation of the [`Transport`](libp2p_core::Transport) trait for WebTransport servers.
+///
+/// Listen addresses have the form `/ip4/<ip>/udp/<port>/quic-v1/webtransport`. The reported
+/// listen addresses additionally carry the hashes of the listener's certificates.
+///
+/// Each listener serves a new self-signed certificate every 7 days. Its addresses are then
+/// reported as expired and reported anew with the hashes of the new certificates, which have to
+/// be re-advertised to browsers. Dialers knowing the addresses from before the last rotation
+/// can still connect.
+///
+/// Dialing is not supported.
+pub struct Transport {
+    config: Config,
+    /// Streams of active [`Listener`]s.
+    listeners: SelectAll<Listener>,
+    /// Waker to poll the transport again when a new listener is added.
+    waker: Option<Waker>,
+}
+
+impl Transport {
+    /// Create a new [`Transport`] with the given [`Config`].
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            listeners: SelectAll::new(),
+            waker: None,
+        }
+    }
+
+    /// Create a new [`quinn::Endpoint`] serving the given certificates.
+    fn new_endpoint(
+        &self,
+        certificates: &Certificates,
+        socket: UdpSocket,
+    ) -> Result<quinn::Endpoint, Error> {
+        let mut server_config = quinn::ServerConfig::with_crypto(certificates.server_config());
+        server_config.transport = self.config.quinn_transport_config();
+        // Disables connection migration, as browsers do not migrate WebTransport connections.
+        server_config.migration(false);
+
+        let mut endpoint_config = quinn::EndpointConfig::default();
+        endpoint_config.supported_versions(vec![1]);
+
+        let endpoint = quinn::Endpoint::new(
+            endpoint_config,
+            Some(server_config),
+            socket,
+            Arc::new(quinn::TokioRuntime),
+        )?;
+        Ok(endpoint)
+    }
+}
+
+impl libp2p_core::Transport for Transport {
+    type Output = (PeerId, Connection);
+    type Error = Error;
+    type ListenerUpgrade = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+    type Dial = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn listen_on(
+        &mut self,
+        listener_id: ListenerId,
+        addr: Multiaddr,
+    ) -> Result<(), TransportError<Self::Error>> {
+        let socket_addr = multiaddr_to_socketaddr(&addr)
+            .ok_or_else(|| TransportError::MultiaddrNotSupported(addr))?;
+        let certificates = Certificates::new()?;
+        let socket = UdpSocket::bind(socket_addr).map_err(Error::from)?;
+        let endpoint = self.new_endpoint(&certificates, socket)?;
+        let listener = Listener::new(listener_id, endpoint, certificates, self.config.clone())?;
+        self.listeners.push(listener);
+
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+
+        Ok(())
+    }
+
+    fn remove_listener(&mut self, id: ListenerId) -> bool {
+        if let Some(listener) = self.listeners.iter_mut().find(|l| l.listener_id == id) {
+            // Close the listener, which will eventually finish its stream.
+            // `SelectAll` removes streams once they are finished.
+            listener.close(Ok(()));
+            true
+        } else {
+            false
+        }
+    }
+
+    fn address_translation(&self, _listen: &Multiaddr, _observed: &Multiaddr) -> Option<Multiaddr> {
+        None
+    }
+
+    fn dial(&mut self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        Err(TransportError::MultiaddrNotSupported(addr))
+    }
+
+    fn dial_as_listener(
+        &mut self,
+        addr: Multiaddr,
+    ) -> Result<Self::Dial, TransportError<Self::Error>> {
+        Err(TransportError::MultiaddrNotSupported(addr))
+    }
+
+    fn poll(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<TransportEvent<Self::ListenerUpgrade, Self::Error>> {
+        if let Poll::Ready(Some(ev)) = self.listeners.poll_next_unpin(cx) {
+            return Poll::Ready(ev);
+        }
+
+        self.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl From<Error> for TransportError<Error> {
+    fn from(err: Error) -> Self {
+        TransportError::Other(err)
+    }
+}
+
+impl fmt::Debug for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transport")
+            .field("listeners", &self.listeners.len())
+            .finish()
+    }
+}
+
+/// Accepts a WebTransport session on a QUIC connection and authenticates the remote.
+async fn upgrade(
+    connecting: quinn::Connecting,
+    keypair: Keypair,
+    certhashes: HashSet<Multihash<64>>,
+) -> Result<(PeerId, Connection), Error> {
+    let connection = connecting.await?;
+    let session = h3::accept_session(&connection).await?;
+    let mut muxer = Connection::new(connection, session);
+
+    // The remote authenticates on the first stream it opens.
+    let stream = future::poll_fn(|cx| muxer.poll_inbound_unpin(cx)).await?;
+    let noise = libp2p_noise::Config::new(&keypair)?.with_webtransport_certhashes(certhashes);
+    // We do not use `upgrade::apply_inbound` function because it uses
+    // `multistream_select` protocol, which is not used by WebTransport spec.
+    let info = noise.protocol_info().next().unwrap_or_default();
+    let (peer_id, _io) = noise.upgrade_inbound(stream, info).await?;
+
+    Ok((peer_id, muxer))
+}
+
+/// Listener for incoming sessions.
+struct Listener {
+    /// Id of the listener.
+    listener_id: ListenerId,
+
+    /// Endpoint
+    endpoint: quinn::Endpoint,
+
+    /// The address the endpoint is bound to.
+    socket_addr: SocketAddr,
+
+    /// A future to poll new incoming connections.
+    accept: BoxFuture<'static, Option<quinn::Connecting>>,
+
+    config: Config,
+
+    /// The served certificates.
+    certificates: Certificates,
+
+    /// Timer for the next certificate rotation.
+    rotation: Delay,
+
+    /// Watcher for network interface changes.
+    ///
+    /// None if we are only listening on a single interface.
+    if_watcher: Option<IfWatcher>,
+
+    /// Whether the listener was closed and the stream should terminate.
+    is_closed: bool,
+
+    /// Pending events to be reported.
+    pending_events: VecDeque<<Self as Stream>::Item>,
+
+    /// The stream must be awaken after it has been closed to deliver the last event.
+    close_listener_waker: Option<Waker>,
+
+    listening_addresses: HashSet<IpAddr>,
+}
+
+impl Listener {
+    fn new(
+        listener_id: ListenerId,
+        endpoint: quinn::Endpoint,
+        certificates: Certificates,
+        config: Config,
+    ) -> Result<Self, Error> {
+        let mut if_watcher = None;
+        let mut pending_events = VecDeque::new();
+        let mut listening_addresses = HashSet::new();
+        let socket_addr = endpoint.local_addr()?;
+        if socket_addr.ip().is_unspecified() {
+            if_watcher = Some(IfWatcher::new()?);
+        } else {
+            listening_addresses.insert(socket_addr.ip());
+            pending_events.push_back(TransportEvent::NewAddress {
+                listener_id,
+                listen_addr: socketaddr_to_multiaddr(&socket_addr, &certificates.advertised()),
+            });
+        }
+
+        let endpoint_c = endpoint.clone();
+        let accept = async move { endpoint_c.accept().await }.boxed();
+        let rotation = Delay::new(until(certificates.next_rotation()));
+
+        Ok(Listener {
+            listener_id,
+            endpoint,
+            socket_addr,
+            accept,
+            config,
+            certificates,
+            rotation,
+            if_watcher,
+            is_closed: false,
+            pending_events,
+            close_listener_waker: None,
+            listening_addresses,
+        })
+    }
+
+    /// Report the listener as closed in a [`TransportEvent::ListenerClosed`] and
+    /// terminate the stream.
+    fn close(&mut self, reason: Result<(), Error>) {
+        if self.is_closed {
+            return;
+        }
+        self.endpoint.close(From::from(0u32), &[]);
+        self.pending_events
+            .push_back(TransportEvent::ListenerClosed {
+                listener_id: self.listener_id,
+                reason,
+            });
+        self.is_closed = true;
+
+        // Wake the stream to deliver the last event.
+        if let Some(waker) = self.close_listener_waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// The listen address for the given IP.
+    fn listen_addr(&self, ip: IpAddr) -> Multiaddr {
+        socketaddr_to_multiaddr(
+            &SocketAddr::new(ip, self.socket_addr.port()),
+            &self.certificates.advertised(),
+        )
+    }
+
+    /// Poll for a next If Event.
+    fn poll_if_addr(&mut self, cx: &mut Context<'_>) -> Poll<<Self as Stream>::Item> {
+        let if_watcher = match self.if_watcher.as_mut() {
+            Some(iw) => iw,
+            None => return Poll::Pending,
+        };
+        loop {
+            match ready!(if_watcher.poll_if_event(cx)) {
+                Ok(IfEvent::Up(inet)) => {
+                    let ip = inet.addr();
+                    if self.socket_addr.is_ipv4() == ip.is_ipv4() {
+                        let listen_addr = self.listen_addr(ip);
+                        log::debug!("New listen address: {listen_addr}");
+                        self.listening_addresses.insert(ip);
+                        return Poll::Ready(TransportEvent::NewAddress {
+                            listener_id: self.listener_id,
+                            listen_addr,
+                        });
+                    }
+                }
+                Ok(IfEvent::Down(inet)) => {
+                    let ip = inet.addr();
+                    if self.socket_addr.is_ipv4() == ip.is_ipv4() {
+                        let listen_addr = self.listen_addr(ip);
+                        log::debug!("Expired listen address: {listen_addr}");
+                        self.listening_addresses.remove(&ip);
+                        return Poll::Ready(TransportEvent::AddressExpired {
+                            listener_id: self.listener_id,
+                            listen_addr,
+                        });
+                    }
+                }
+                Err(err) => {
+                    return Poll::Ready(TransportEvent::ListenerError {
+                        listener_id: self.listener_id,
+                        error: err.into(),
+                    })
+                }
+            }
+        }
+    }
+
+    /// Rotate the certificates once due, replacing all listen addresses.
+    fn poll_rotation(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        ready!(self.rotation.poll_unpin(cx));
+
+        let expired = self
+            .listening_addresses
+            .iter()
+            .map(|ip| self.listen_addr(*ip))
+            .collect::<Vec<_>>();
+        if let Err(error) = self.certificates.rotate() {
+            // Retry later, the current certificates are still valid for a while.
+            self.rotation.reset(Duration::from_secs(60));
+            self.pending_events
+                .push_back(TransportEvent::ListenerError {
+                    listener_id: self.listener_id,
+                    error,
+                });
+            return Poll::Ready(());
+        }
+        self.rotation
+            .reset(until(self.certificates.next_rotation()));
+
+        for listen_addr in expired {
+            log::debug!("Expired listen address: {listen_addr}");
+            self.pending_events
+                .push_back(TransportEvent::AddressExpired {
+                    listener_id: self.listener_id,
+                    listen_addr,
+                });
+        }
+        for ip in self.listening_addresses.iter() {
+            let listen_addr = self.listen_addr(*ip);
+            log::debug!("New listen address: {listen_addr}");
+            self.pending_events.push_back(TransportEvent::NewAddress {
+                listener_id: self.listener_id,
+                listen_addr,
+            });
+        }
+
+        Poll::Ready(())
+    }
+}
+
+impl Stream for Listener {
+    type Item = TransportEvent<<Transport as libp2p_core::Transport>::ListenerUpgrade, Error>;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(event) = self.pending_events.pop_front() {
+                return Poll::Ready(Some(event));
+            }
+            if self.is_closed {
+                return Poll::Ready(None);
+            }
+            if let Poll::Ready(event) = self.poll_if_addr(cx) {
+                return Poll::Ready(Some(event));
+            }
+            if self.poll_rotation(cx).is_ready() {
+                continue;
+            }
+
+            match self.accept.poll_unpin(cx) {
+                Poll::Ready(Some(connecting)) => {
+                    let endpoint = self.endpoint.clone();
+                    self.accept = async move { endpoint.accept().await }.boxed();
+
+                    let local_addr = socketaddr_to_multiaddr(&self.socket_addr, &[]);
+                    let send_back_addr = socketaddr_to_multiaddr(&connecting.remote_address(), &[]);
+                    let keypair = self.config.keypair.clone();
+                    let certhashes = self.certificates.accepted();
+                    let timeout = Delay::new(self.config.handshake_timeout);
+                    let upgrade = async move {
+                        let upgrade = upgrade(connecting, keypair, certhashes).boxed();
+                        match future::select(upgrade, timeout).await {
+                            Either::Left((result, _)) => result,
+                            Either::Right(_) => Err(Error::HandshakeTimedOut),
+                        }
+                    };
+
+                    let event = TransportEvent::Incoming {
+                        upgrade: upgrade.boxed(),
+                        local_addr,
+                        send_back_addr,
+                        listener_id: self.listener_id,
+                    };
+                    return Poll::Ready(Some(event));
+                }
+                Poll::Ready(None) => {
+                    self.close(Err(Error::EndpointDriverCrashed));
+                    continue;
+                }
+                Poll::Pending => {}
+            };
+
+            self.close_listener_waker = Some(cx.waker().clone());
+
+            return Poll::Pending;
+        }
+    }
+}
+
+/// The duration until `time`, or zero if it has passed.
+fn until(time: SystemTime) -> Duration {
+    time.duration_since(SystemTime::now()).unwrap_or_default()
+}
+
+/// Tries to turn a WebTransport multiaddress into a UDP [`SocketAddr`]. Returns None if the
+/// format of the multiaddr is wrong.
+fn multiaddr_to_socketaddr(addr: &Multiaddr) -> Option<SocketAddr> {
+    let mut iter = addr.iter();
+    let ip: IpAddr = match iter.next()? {
+        Protocol::Ip4(ip) => ip.into(),
+        Protocol::Ip6(ip) => ip.into(),
+        _ => return None,
+    };
+    let port = match iter.next()? {
+        Protocol::Udp(port) => port,
+        _ => return None,
+    };
+    match (iter.next()?, iter.next()?, iter.next()) {
+        (Protocol::QuicV1, Protocol::WebTransport, None) => Some(SocketAddr::new(ip, port)),
+        _ => None,
+    }
+}
+
+/// Turns an IP address, port and certificate hashes into a WebTransport multiaddr.
+fn socketaddr_to_multiaddr(socket_addr: &SocketAddr, certhashes: &[Multihash<64>]) -> Multiaddr {
+    let mut addr = Multiaddr::empty()
+        .with(socket_addr.ip().into())
+        .with(Protocol::Udp(socket_addr.port()))
+        .with(Protocol::QuicV1)
+        .with(Protocol::WebTransport);
+    for hash in certhashes {
+        addr.push(Protocol::Certhash(*hash));
+    }
+    addr
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{certificate::certhash, h3::tests::*, Stream};
+    use libp2p_core::{OutboundUpgrade, Transport as _};
+    use rustls::client::{ServerCertVerified, ServerCertVerifier};
+
+    #[test]
+    fn parses_listen_addresses() {
+        let parse = |addr: &str| multiaddr_to_socketaddr(&addr.parse().unwrap());
+
+        assert_eq!(
+            parse("/ip4/127.0.0.1/udp/1234/quic-v1/webtransport"),
+            Some("127.0.0.1:1234".parse().unwrap())
+        );
+        assert_eq!(
+            parse("/ip6/::1/udp/1234/quic-v1/webtransport"),
+            Some("[::1]:1234".parse().unwrap())
+        );
+        assert_eq!(parse("/ip4/127.0.0.1/udp/1234/quic-v1"), None);
+        assert_eq!(parse("/ip4/127.0.0.1/tcp/1234/quic-v1/webtransport"), None);
+        assert_eq!(
+            parse("/ip4/127.0.0.1/udp/1234/quic-v1/webtransport/certhash/uEiCaDd1Ca1A8IVJ3hsIxIyi11cwxaDKqzVrBkGJbKZU5ng"),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn browser_session_is_authenticated() {
+        let server_keypair = Keypair::generate_ed25519();
+        let client_keypair = Keypair::generate_ed25519();
+        let mut transport = Transport::new(Config::new(&server_keypair));
+        transport
+            .listen_on(
+                ListenerId::next(),
+                "/ip4/127.0.0.1/udp/0/quic-v1/webtransport".parse().unwrap(),
+            )
+            .unwrap();
+
+        let listen_addr = match poll_transport(&mut transport).await {
+            TransportEvent::NewAddress { listen_addr, .. } => listen_addr,
+            e => panic!("Unexpected event {e:?}"),
+        };
+        let certhashes = listen_addr
+            .iter()
+            .filter_map(|p| match p {
+                Protocol::Certhash(hash) => Some(hash),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+        assert_eq!(certhashes.len(), 2);
+        let mut addr = listen_addr.clone();
+        addr.pop();
+        addr.pop();
+        let server_addr = multiaddr_to_socketaddr(&addr).unwrap();
+
+        let client = tokio::spawn(dial(server_addr, client_keypair.clone(), certhashes));
+
+        let upgrade = match poll_transport(&mut transport).await {
+            TransportEvent::Incoming { upgrade, .. } => upgrade,
+            e => panic!("Unexpected event {e:?}"),
+        };
+        let (peer_id, mut connection) = upgrade.await.unwrap();
+        assert_eq!(peer_id, client_keypair.public().to_peer_id());
+
+        let mut stream = future::poll_fn(|cx| connection.poll_outbound_unpin(cx))
+            .await
+            .unwrap();
+        stream.write_all(b"hello").await.unwrap();
+        stream.close().await.unwrap();
+
+        let (remote_peer_id, data) = client.await.unwrap();
+        assert_eq!(remote_peer_id, server_keypair.public().to_peer_id());
+        assert_eq!(data, b"hello");
+    }
+
+    async fn poll_transport(
+        transport: &mut Transport,
+    ) -> TransportEvent<<Transport as libp2p_core::Transport>::ListenerUpgrade, Error> {
+        future::poll_fn(|cx| Pin::new(&mut *transport).poll(cx)).await
+    }
+
+    /// Dials like a browser, returning the authenticated server and the data of the first stream
+    /// it opens.
+    async fn dial(
+        server_addr: SocketAddr,
+        keypair: Keypair,
+        certhashes: HashSet<Multihash<64>>,
+    ) -> (PeerId, Vec<u8>) {
+        let mut tls = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(CerthashVerifier(certhashes.clone())))
+            .with_no_client_auth();
+        tls.alpn_protocols = vec![b"h3".to_vec()];
+        let endpoint = quinn::Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+        let connection = endpoint
+            .connect_with(
+                quinn::ClientConfig::new(Arc::new(tls)),
+                server_addr,
+                "localhost",
+            )
+            .unwrap()
+            .await
+            .unwrap();
+
+        let mut control = connection.open_uni().await.unwrap();
+        control.write_all(&client_control_stream()).await.unwrap();
+        let (mut send, mut recv) = connection.open_bi().await.unwrap();
+        send.write_all(&session_request("localhost")).await.unwrap();
+        assert_eq!(read_status(&mut recv).await.unwrap(), b"200");
+
+        let mut header = Vec::new();
+        h3::encode_varint(&mut header, h3::WEBTRANSPORT_STREAM);
+        h3::encode_varint(&mut header, quinn::VarInt::from(send.id()).into_inner());
+        let (mut noise_send, noise_recv) = connection.open_bi().await.unwrap();
+        noise_send.write_all(&header).await.unwrap();
+        let (peer_id, _) = libp2p_noise::Config::new(&keypair)
+            .unwrap()
+            .with_webtransport_certhashes(certhashes)
+            .upgrade_outbound(Stream::new(noise_send, noise_recv), "/noise")
+            .await
+            .unwrap();
+
+        let (_, mut recv) = connection.accept_bi().await.unwrap();
+        assert_eq!(
+            h3::read_varint(&mut recv).await.unwrap(),
+            h3::WEBTRANSPORT_STREAM
+        );
+        assert_eq!(
+            h3::read_varint(&mut recv).await.unwrap(),
+            quinn::VarInt::from(send.id()).into_inner()
+        );
+        let data = recv.read_to_end(1024).await.unwrap();
+
+        (peer_id, data)
+    }
+
+    /// Verifies the server certificate by its hash, like browsers do.
+    struct CerthashVerifier(HashSet<Multihash<64>>);
+
+    impl ServerCertVerifier for CerthashVerifier {
+        fn verify_server_cert(
+            &self,
+            end_entity: &rustls::Certificate,
+            _: &[rustls::Certificate],
+            _: &rustls::ServerName,
+            _: &mut dyn Iterator<Item = &[u8]>,
+            _: &[u8],
+            _: SystemTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            if self.0.contains(&certhash(&end_entity.0)) {
+                Ok(ServerCertVerified::assertion())
+            } else {
+                Err(rustls::Error::General("unknown certificate".into()))
+            }
+        }
+    }
+}
@@ -8,7 +8,10 @@ use libp2p_webtransport_websys::{Config, Connection, Error, Stream, Transport};
 use multiaddr::{Multiaddr, Protocol};
 use multihash::Multihash;
 use std::future::poll_fn;
+use std::num::NonZeroUsize;
 use std::pin::Pin;
+use std::task::Context;
+use std::time::Duration;
 use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::{spawn_local, JsFuture};
 use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
@@ -304,6 +307,38 @@ async fn error_on_unknown_certhash() {
     ));
 }
 
+#[wasm_bindgen_test]
+async fn error_on_handshake_timeout() {
+    let addr = fetch_server_addr().await;
+    let keypair = Keypair::generate_ed25519();
+
+    let config = Config::new(&keypair).with_handshake_timeout(Duration::ZERO);
+    let mut transport = Transport::new(config);
+    let e = transport.dial(addr).unwrap().await.unwrap_err();
+    assert!(matches!(e, Error::HandshakeTimedOut));
+}
+
+#[wasm_bindgen_test]
+async fn outbound_stream_waits_at_stream_limit() {
+    let addr = fetch_server_addr().await;
+    let keypair = Keypair::generate_ed25519();
+
+    let config = Config::new(&keypair).with_max_concurrent_streams(NonZeroUsize::new(1).unwrap());
+    let mut transport = Transport::new(config);
+    let (_peer_id, mut conn) = transport.dial(addr).unwrap().await.unwrap();
+
+    let mut stream = create_stream(&mut conn).await;
+    send_recv(&mut stream).await;
+
+    let waker = futures::task::noop_waker();
+    let pending = Pin::new(&mut conn).poll_outbound(&mut Context::from_waker(&waker));
+    assert!(pending.is_pending());
+
+    drop(stream);
+    let mut stream = create_stream(&mut conn).await;
+    send_recv(&mut stream).await;
+}
+
 async fn new_connection_to_echo_server() -> Connection {
     let addr = fetch_server_addr().await;
     let keypair = Keypair::generate_ed25519();